@@ -1,20 +1,33 @@
 import ApiService, {DefaultApiService} from "./api-service";
-import {PlaylistGroup} from "../model/playlist";
+import {ChannelNote, PlaylistGroup} from "../model/playlist";
 import {Observable, throwError} from "rxjs";
+import {map} from "rxjs/operators";
 import {PlaylistRequest} from "../model/playlist-request";
+import {PlayerErrorReport, PlayerErrorResponse} from "../model/player-error";
 
 const PLAYLIST_API_PATH = 'playlist';
 const TARGET_UPDATE_API_PATH = 'playlist/update';
+const PLAYER_ERROR_API_PATH = 'diagnostics/player-error';
+const PLAYER_TOKEN_API_PATH = 'player/token';
+const CHANNEL_NOTES_API_PATH = 'channel/notes';
 
 export default interface PlaylistApiService extends ApiService {
     getPlaylist(req: PlaylistRequest): Observable<PlaylistGroup[]>;
 
     updateTargets(targets: string[]): Observable<any>;
+
+    reportPlayerError(report: PlayerErrorReport): Observable<PlayerErrorResponse>;
+
+    getPlaybackUrl(target: string, streamId: number): Observable<string>;
+
+    getChannelNotes(): Observable<Record<string, ChannelNote>>;
+
+    setChannelNote(uuid: string, note: string, labels: string[]): Observable<ChannelNote>;
 }
 
 export class DefaultPlaylistApiService extends DefaultApiService implements PlaylistApiService {
     getPlaylist(req: PlaylistRequest): Observable<PlaylistGroup[]> {
-        if (req.url || req.input_id != undefined) {
+        if (req.url || req.input_id != undefined || req.target) {
             return this.post<PlaylistGroup[]>(PLAYLIST_API_PATH, req);
         }
         return throwError(() => new Error('Invalid arguments'));
@@ -23,4 +36,21 @@ export class DefaultPlaylistApiService extends DefaultApiService implements Play
     updateTargets(targets: string[]): Observable<any> {
         return this.post(TARGET_UPDATE_API_PATH, targets);
     }
+
+    reportPlayerError(report: PlayerErrorReport): Observable<PlayerErrorResponse> {
+        return this.post<PlayerErrorResponse>(PLAYER_ERROR_API_PATH, report);
+    }
+
+    getPlaybackUrl(target: string, streamId: number): Observable<string> {
+        return this.post<{ token: string }>(PLAYER_TOKEN_API_PATH, {target, stream_id: streamId}).pipe(
+            map(response => this.getBaseUrl() + '/player/stream/' + response.token));
+    }
+
+    getChannelNotes(): Observable<Record<string, ChannelNote>> {
+        return this.get<Record<string, ChannelNote>>(CHANNEL_NOTES_API_PATH);
+    }
+
+    setChannelNote(uuid: string, note: string, labels: string[]): Observable<ChannelNote> {
+        return this.put<ChannelNote>(CHANNEL_NOTES_API_PATH + '/' + uuid, {note, labels});
+    }
 }
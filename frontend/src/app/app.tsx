@@ -29,6 +29,7 @@ export default function App(props: AppProps) {
     const searchChannel = useMemo<Subject<SearchRequest>>(() => new Subject<SearchRequest>(), []);
     const [progress, setProgress] = useState<boolean>(false);
     const [playlist, setPlaylist] = useState<PlaylistGroup[]>([]);
+    const [playlistTarget, setPlaylistTarget] = useState<string>(undefined);
     const [serverConfig, setServerConfig] = useState<ServerConfig>(undefined);
     const [preferencesVisible, setPreferencesVisible] = useState<boolean>(true);
     const clipboardChannel = useMemo<Subject<string>>(() => new Subject<string>(), []);
@@ -42,6 +43,7 @@ export default function App(props: AppProps) {
             next: (pl: PlaylistGroup[]) => {
                 enqueueSnackbar('Sucessfully downloaded playlist', {variant: 'success'})
                 setPlaylist(pl);
+                setPlaylistTarget(req.target);
             },
             error: (err) => {
                 setProgress(false);
@@ -68,8 +70,12 @@ export default function App(props: AppProps) {
     }, []);
 
     const handleOnPlay = useCallback((playlistItem: PlaylistItem): void => {
-        videoChannel.next(playlistItem);
-    }, [videoChannel]);
+        if (playlistTarget) {
+            videoChannel.next(playlistItem);
+        } else {
+            enqueueSnackbar('Only channels of a target playlist can be played', {variant: 'warning'});
+        }
+    }, [videoChannel, playlistTarget, enqueueSnackbar]);
 
     const handleOnCopy = useCallback((playlistItem: PlaylistItem): void => {
         clipboardChannel.next(playlistItem.header.url);
@@ -133,6 +139,8 @@ export default function App(props: AppProps) {
         return noop
     }, [enqueueSnackbar, services]);
 
+    const brandingLogo = useMemo(() => document.querySelector('meta[name="m3u-filter-logo"]')?.getAttribute('content'), []);
+
     const handlePreferences = useCallback(() => {
        setPreferencesVisible((value:boolean) => !value);
     }, []);
@@ -141,7 +149,7 @@ export default function App(props: AppProps) {
     return (
         <div className="app">
             <div className={'app-header'}>
-                <div className={'app-header__caption'}><span className={'app-header__logo'}>{getIconByName('Logo')}</span>m3u-filter</div>
+                <div className={'app-header__caption'}><span className={'app-header__logo'}>{brandingLogo ? <img alt="logo" src={brandingLogo}/> : getIconByName('Logo')}</span>{document.title || 'm3u-filter'}</div>
                 <div className={'app-header__toolbar'}><button title="Configuration" onClick={handlePreferences}>{getIconByName('Config')}</button></div>
             </div>
             <div className={'app-main' + (preferencesVisible ? '' : '  hidden')}>
@@ -161,7 +169,7 @@ export default function App(props: AppProps) {
                                     onDownload={handleOnDownload}
                                     onWebSearch={handleOnWebSearch}
                                     serverConfig={serverConfig}/>
-                    <PlaylistVideo channel={videoChannel}/>
+                    <PlaylistVideo channel={videoChannel} target={playlistTarget}/>
                     <Toolbar onDownload={handleSave}/>
                     <FileDownload></FileDownload>
                     <Progress visible={progress}/>
@@ -7,6 +7,8 @@ import {noop} from "rxjs";
 import {useSnackbar} from "notistack";
 import {getIconByName} from "../../icons/icons";
 import ServerConfig from "../../model/server-config";
+import {useServices} from "../../provider/service-provider";
+import {channelUuidToHex} from "../../utils/channel-uuid";
 
 export type PlaylistTreeState = { [key: number]: boolean };
 
@@ -26,6 +28,7 @@ export default function PlaylistTree(props: PlaylistTreeProps) {
     const [, setForceUpdate] = useState(null);
     const expanded = useRef<PlaylistTreeState>({});
     const {enqueueSnackbar/*, closeSnackbar*/} = useSnackbar();
+    const services = useServices();
     const [videoExtensions, setVideoExtensions] = useState<string[]>([]);
 
     useEffect(() => {
@@ -106,6 +109,30 @@ export default function PlaylistTree(props: PlaylistTreeProps) {
         }
     }, [onPlay, getPlaylistItemById]);
 
+    const handleEditNote = useCallback((e: any) => {
+        const item = getPlaylistItemById(e.target.dataset.item);
+        const uuid = item && channelUuidToHex(item.header.uuid);
+        if (uuid) {
+            const labels = window.prompt('Labels (comma separated)', (item.note?.labels ?? []).join(', '));
+            if (labels == null) {
+                return;
+            }
+            const note = window.prompt('Note', item.note?.note ?? '');
+            if (note == null) {
+                return;
+            }
+            services.playlist().setChannelNote(uuid, note, labels.split(',')).pipe(first()).subscribe({
+                next: (channelNote) => {
+                    item.note = channelNote;
+                    setForceUpdate({});
+                    enqueueSnackbar("Channel note saved", {variant: 'success'});
+                },
+                error: _ => enqueueSnackbar("Failed to save channel note!", {variant: 'error'}),
+                complete: noop,
+            });
+        }
+    }, [services, enqueueSnackbar, getPlaylistItemById]);
+
     const isVideoFile = useCallback((entry: PlaylistItem): boolean => {
         if (videoExtensions && entry.header.url) {
             for (const ext of videoExtensions) {
@@ -123,6 +150,9 @@ export default function PlaylistTree(props: PlaylistTreeProps) {
                 <div className={'tool-button'} data-item={entry.id} onClick={handleClipboardUrl}>
                     {getIconByName('LinkRounded')}
                 </div>
+                <div className={'tool-button'} data-item={entry.id} onClick={handleEditNote} title={entry.note?.note}>
+                    {getIconByName('Label')}
+                </div>
                 <div style={{display: 'none'}} className={'tool-button'} data-item={entry.id} onClick={handlePlayUrl}>
                     {getIconByName('PlayArrow')}
                 </div>
@@ -141,9 +171,12 @@ export default function PlaylistTree(props: PlaylistTreeProps) {
             </div>
             <div className={'tree-group__channel-content'}>
                 <div className={'tree-group__channel-nr'}>{index + 1}</div>
-                {entry.header.name}</div>
+                {entry.header.name}
+                {entry.note?.labels?.map(label =>
+                    <span key={label} className={'tree-group__channel-label'}>{label}</span>)}
+            </div>
         </div>
-    }, [handleClipboardUrl, handlePlayUrl, handleDownloadUrl, isVideoFile, handleWebSearch, serverConfig]);
+    }, [handleClipboardUrl, handleEditNote, handlePlayUrl, handleDownloadUrl, isVideoFile, handleWebSearch, serverConfig]);
 
     const renderGroup = useCallback((group: PlaylistGroup): React.ReactNode => {
         return <div className={'tree-group'} key={group.id}>
@@ -5,14 +5,15 @@ import {Observable} from "rxjs";
 
 interface PlaylistVideoProps {
     channel: Observable<PlaylistItem>;
+    target?: string;
 }
 
 export default function PlaylistVideo(props: PlaylistVideoProps): JSX.Element {
-    const {channel} = props;
+    const {channel, target} = props;
     const playerRef = useRef(null);
     const handlePlayerReady = (player: any) => {
         playerRef.current = player;
     };
 
-    return <VideoPlayer channel={channel} onReady={handlePlayerReady}/>;
+    return <VideoPlayer channel={channel} target={target} onReady={handlePlayerReady}/>;
 }
\ No newline at end of file
@@ -24,10 +24,18 @@ function textMatch(text: string, searchRequest: SearchRequest): boolean {
     }
 }
 
+function noteMatch(item: PlaylistItem, searchRequest: SearchRequest): boolean {
+    if (item.note) {
+        return textMatch(item.note.note ?? '', searchRequest)
+            || (item.note.labels ?? []).some(label => textMatch(label, searchRequest));
+    }
+    return false;
+}
+
 function filterMatchingChannels(grp: PlaylistGroup, searchRequest: SearchRequest): PlaylistGroup {
     let channels: PlaylistItem[] = [];
     for (const c of grp.channels) {
-        if (textMatch(c.header.name, searchRequest)) {
+        if (textMatch(c.header.name, searchRequest) || noteMatch(c, searchRequest)) {
             channels.push(c);
         }
     }
@@ -2,7 +2,7 @@ import React, {KeyboardEvent, useCallback, useEffect, useRef, useState} from "re
 
 import './source-selector.scss';
 import {getIconByName} from "../../icons/icons";
-import ServerConfig, {InputConfig} from "../../model/server-config";
+import ServerConfig from "../../model/server-config";
 import PopupMenu from "../popup-menu/popup-menu";
 import {PlaylistRequest} from "../../model/playlist-request";
 import InputField from "../input-field/input-field";
@@ -12,11 +12,17 @@ interface SourceSelectorProps {
     onDownload: (req: PlaylistRequest) => void;
 }
 
+// inputs are downloaded from the provider, targets are read from the storage
+interface Source {
+    name: string;
+    request: PlaylistRequest;
+}
+
 export default function SourceSelector(props: SourceSelectorProps) {
     const textField = useRef<HTMLInputElement>(null);
     const [popupVisible, setPopupVisible] = useState<{ x: number, y: number }>(undefined);
-    const [sources, setSources] = useState<InputConfig[]>([]);
-    const [selected, setSelected] = useState<InputConfig>(undefined);
+    const [sources, setSources] = useState<Source[]>([]);
+    const [selected, setSelected] = useState<Source>(undefined);
 
     const {serverConfig, onDownload} = props;
 
@@ -25,7 +31,7 @@ export default function SourceSelector(props: SourceSelectorProps) {
         if (value && value.trim().length > 0) {
             // eslint-disable-next-line
             if (value.trim() == selected?.name) {
-                onDownload({input_id: selected.id});
+                onDownload(selected.request);
             } else {
                 onDownload({url: value.trim()});
             }
@@ -53,13 +59,17 @@ export default function SourceSelector(props: SourceSelectorProps) {
         if (idx != null) {
             setPopupVisible(undefined);
             setSelected(sources[idx]);
-            textField.current.value = sources[idx].name || sources[idx].url;
+            textField.current.value = sources[idx].name;
         }
     }, [sources]);
 
     useEffect(()=> {
         if (serverConfig) {
-            setSources(serverConfig.sources?.flatMap(source => source.inputs))
+            const inputs = serverConfig.sources?.flatMap(source => source.inputs)
+                .map(input => ({name: input.name || input.url, request: {input_id: input.id}})) ?? [];
+            const targets = serverConfig.sources?.flatMap(source => source.targets)
+                .map(target => ({name: 'Target: ' + target.name, request: {target: target.name}})) ?? [];
+            setSources([...inputs, ...targets]);
         }
     }, [serverConfig]);
 
@@ -72,7 +82,7 @@ export default function SourceSelector(props: SourceSelectorProps) {
         <PopupMenu position={popupVisible} onHide={closePopup}>
             <ul>
                 {sources.map((s, idx) =>
-                    <li key={s.id + '_' + idx} data-idx={idx} onClick={handleMenuClick}>{s.name || s.url}</li>)}
+                    <li key={s.name + '_' + idx} data-idx={idx} onClick={handleMenuClick}>{s.name}</li>)}
             </ul>
         </PopupMenu>
     </div>
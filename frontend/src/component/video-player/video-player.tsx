@@ -1,18 +1,37 @@
-import React, {useCallback, useEffect} from 'react';
+import React, {useCallback, useEffect, useState} from 'react';
 import './video-player.scss';
 import {Observable, Subscription} from "rxjs";
 import {PlaylistItem} from "../../model/playlist";
+import {useServices} from "../../provider/service-provider";
+import {first} from "rxjs/operators";
+import {useSnackbar} from "notistack";
+import {PlayerErrorResponse} from "../../model/player-error";
 
 interface VideoPlayerProps {
     channel: Observable<PlaylistItem>;
+    // only the stored channels of a target can be played
+    target?: string;
     onReady: (player: any) => void;
 }
 
 export const VideoPlayer = (props: VideoPlayerProps) => {
-    const {channel} = props;
+    const {channel, target} = props;
+    const services = useServices();
+    const {enqueueSnackbar} = useSnackbar();
+    const [streamUrl, setStreamUrl] = useState<string>(undefined);
+    const [channelName, setChannelName] = useState<string>(undefined);
 
     const handlePlayVideo = useCallback((playlistItem: PlaylistItem) => {
-    }, []);
+        if (!target || !playlistItem.header.virtual_id) {
+            setStreamUrl(undefined);
+            return;
+        }
+        setChannelName(playlistItem.header.title || playlistItem.header.name);
+        services.playlist().getPlaybackUrl(target, playlistItem.header.virtual_id).pipe(first()).subscribe({
+            next: (url: string) => setStreamUrl(url),
+            error: () => setStreamUrl(undefined),
+        });
+    }, [services, target]);
 
     useEffect(() => {
         let sub: Subscription = undefined;
@@ -22,7 +41,30 @@ export const VideoPlayer = (props: VideoPlayerProps) => {
         return () => sub && sub.unsubscribe();
     }, [channel, handlePlayVideo]);
 
-    return <React.Fragment />;
+    // the error is reported to the diagnostics, the server side errors of the stream are shown
+    const handleError = useCallback((evt: React.SyntheticEvent<HTMLVideoElement>) => {
+        const video = evt.currentTarget;
+        services.playlist().reportPlayerError({
+            url: streamUrl,
+            channel: channelName,
+            error_code: video.error ? String(video.error.code) : undefined,
+            player_state: 'ready=' + video.readyState + ' network=' + video.networkState,
+            message: video.error?.message,
+        }).pipe(first()).subscribe({
+            next: (response: PlayerErrorResponse) => {
+                const serverError = response.correlated[response.correlated.length - 1];
+                enqueueSnackbar('Playback failed' + (serverError ? ': ' + serverError.message : ''), {variant: 'error'});
+            },
+            error: () => enqueueSnackbar('Playback failed', {variant: 'error'}),
+        });
+    }, [services, streamUrl, channelName, enqueueSnackbar]);
+
+    if (!streamUrl) {
+        return <React.Fragment/>;
+    }
+    return <div className={'video-player'}>
+        <video src={streamUrl} controls autoPlay onError={handleError}/>
+    </div>;
 }
 
 export default VideoPlayer;
\ No newline at end of file
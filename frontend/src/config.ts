@@ -1,5 +1,3 @@
-const location = window.location;
-
 const dev = {
     app: {
         version: process.env.REACT_APP_VERSION,
@@ -14,7 +12,8 @@ const prod = {
         version: process.env.REACT_APP_VERSION,
     },
     api: {
-        serverUrl: location.origin + '/api/v1/',
+        // resolved against the injected base href when the ui is served under a sub path
+        serverUrl: new URL('api/v1/', document.baseURI).href,
     },
 };
 
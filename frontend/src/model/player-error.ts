@@ -0,0 +1,20 @@
+export interface PlayerErrorReport {
+    url: string;
+    channel?: string;
+    error_code?: string;
+    player_state?: string;
+    message?: string;
+}
+
+export interface DiagnosticEvent {
+    id: number;
+    timestamp: number;
+    source: 'server' | 'player';
+    url: string;
+    message: string;
+}
+
+export interface PlayerErrorResponse {
+    id: number;
+    correlated: DiagnosticEvent[];
+}
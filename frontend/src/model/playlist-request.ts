@@ -1,4 +1,5 @@
 export interface PlaylistRequest {
     url?: string;
     input_id?: number;
+    target?: string;
 }
\ No newline at end of file
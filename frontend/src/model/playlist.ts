@@ -11,11 +11,22 @@ export interface PlaylistItemHeader {
     rec: string;
     source: string;
     url: string;
+    input_id?: number;
+    virtual_id?: number;
+    uuid?: number[];
+    kodi_props?: string[];
+}
+
+export interface ChannelNote {
+    note?: string;
+    labels?: string[];
+    updated?: number;
 }
 
 export interface PlaylistItem {
     id: number;
     header: PlaylistItemHeader;
+    note?: ChannelNote;
 }
 
 export interface PlaylistGroup {
@@ -1,6 +1,7 @@
 export enum InputType {
     m3u = "m3u",
-    xtream = "xtream"
+    xtream = "xtream",
+    local = "local"
 }
 
 export enum SortOrder {
@@ -11,7 +12,8 @@ export enum SortOrder {
 export enum TargetType {
     m3u = "m3u",
     xtream = "xtream",
-    strm = "strm"
+    strm = "strm",
+    stalker = "stalker"
 }
 
 export enum ProcessingOrder {
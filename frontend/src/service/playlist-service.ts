@@ -1,7 +1,8 @@
-import {Observable} from "rxjs";
+import {Observable, of} from "rxjs";
 import PlaylistApiService, {DefaultPlaylistApiService} from "../api/playlist-api-service";
-import {PlaylistGroup} from "../model/playlist";
-import {first} from "rxjs/operators";
+import {ChannelNote, PlaylistGroup} from "../model/playlist";
+import {catchError, first} from "rxjs/operators";
+import {channelUuidToHex} from "../utils/channel-uuid";
 import {PlaylistRequest} from "../model/playlist-request";
 
 export default class PlaylistService {
@@ -14,22 +15,37 @@ export default class PlaylistService {
             this.playlistApiService.getPlaylist(req).pipe(first()).subscribe({
                 next: (pl: PlaylistGroup[]) => {
                     if (pl) {
-                        let cnt = 0;
-                        pl.forEach(g => {
-                            g.id = ++cnt;
-                            g.channels.forEach(c => c.id = ++cnt);
-                        })
-                        obs.next(pl);
+                        // the playlist is shown without notes if they can't be loaded
+                        this.playlistApiService.getChannelNotes().pipe(first(), catchError(() => of({} as Record<string, ChannelNote>)))
+                            .subscribe((notes: Record<string, ChannelNote>) => {
+                                let cnt = 0;
+                                pl.forEach(g => {
+                                    g.id = ++cnt;
+                                    g.channels.forEach(c => {
+                                        c.id = ++cnt;
+                                        c.note = notes[channelUuidToHex(c.header.uuid)];
+                                    });
+                                })
+                                obs.next(pl);
+                                obs.complete();
+                            });
                     } else {
                         obs.error("Could not download playlist");
                     }
                 },
                 error: (e) => obs.error(e),
-                complete: () => obs.complete(),
             }));
     }
 
     update(targets: string[]): Observable<any> {
         return this.playlistApiService.updateTargets(targets);
     }
+
+    getPlaybackUrl(url: string, inputId?: number): Observable<string> {
+        return this.playlistApiService.getPlaybackUrl(url, inputId);
+    }
+
+    setChannelNote(uuid: string, note: string, labels: string[]): Observable<ChannelNote> {
+        return this.playlistApiService.setChannelNote(uuid, note, labels);
+    }
 }
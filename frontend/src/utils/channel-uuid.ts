@@ -0,0 +1,7 @@
+// the channel notes are keyed by the upper case hex encoded channel uuid
+export const channelUuidToHex = (uuid: number[]): string => {
+    if (!uuid?.length) {
+        return undefined;
+    }
+    return uuid.map(b => b.toString(16).padStart(2, '0')).join('').toUpperCase();
+}
@@ -1,18 +1,29 @@
 use crate::api::model::app_state::AppState;
+use crate::api::model::provider_connections::ProviderConnectionGuard;
+use crate::api::model::user_connections::UserConnectionGuard;
+use crate::model::channel_viewers::ChannelViewerGuard;
 use crate::api::model::provider_stream;
-use crate::api::model::provider_stream::{get_provider_pipe_stream};
+use crate::api::model::provider_stream::{get_provider_head_response, get_provider_pipe_stream};
+use crate::api::model::timeout_stream::StreamTimeouts;
+use crate::api::model::relay_stream::{get_relay_response_headers, get_relay_stream, is_relay_url};
 use crate::api::model::request::UserApiRequest;
 use crate::api::model::shared_stream::SharedStream;
 use crate::debug_if_enabled;
-use crate::model::api_proxy::ProxyUserCredentials;
-use crate::model::config::{ConfigInput, ConfigTarget};
+use crate::model::api_proxy::{ForwardedOrigin, ProxyUserCredentials};
+use crate::model::config::{Config, ConfigInput, ConfigTarget, CustomVideo, ProviderQuirk};
 use crate::model::playlist::PlaylistItemType;
-use crate::utils::request_utils;
+use crate::model::preview_user::PREVIEW_USERNAME;
+use crate::repository::storage::get_target_storage_path;
+use crate::repository::m3u_playlist_iterator::is_live_stream;
+use crate::utils::{metrics, request_utils};
 use crate::utils::request_utils::mask_sensitive_info;
+use crate::utils::preflight::{guard_provider_request, ProviderRequestDenied};
 use actix_files::NamedFile;
-use actix_web::body::{BodyStream};
-use actix_web::http::header::DATE;
-use actix_web::http::header::{HeaderValue, CACHE_CONTROL};
+use actix_web::body::{BodySize, BodyStream, MessageBody};
+use actix_web::http::header::{ACCEPT_LANGUAGE, DATE, FORWARDED, HOST, USER_AGENT, X_FORWARDED_HOST, X_FORWARDED_PROTO};
+use actix_web::http::uri::Authority;
+use actix_web::http::header::{HeaderName, HeaderValue, CACHE_CONTROL, CONTENT_LENGTH, RETRY_AFTER, SET_COOKIE};
+use actix_web::http::Method;
 use actix_web::{HttpRequest, HttpResponse};
 use bytes::Bytes;
 use chrono::Utc;
@@ -22,16 +33,50 @@ use std::path::{Path};
 use std::sync::Arc;
 use async_std::sync::Mutex;
 use futures::stream::BoxStream;
-use futures::{TryStreamExt};
+use futures::Stream;
+use tokio::sync::OwnedSemaphorePermit;
+use futures::{StreamExt, TryStreamExt};
 use reqwest::StatusCode;
 use url::Url;
 use crate::api::model::model_utils::get_stream_response_with_headers;
 use crate::api::model::persist_pipe_stream::PersistPipeStream;
 use crate::api::model::provider_stream_factory::BufferStreamOptions;
 use crate::api::model::stream_error::StreamError;
+use crate::api::model::stream_trace::{trace_stream, StreamTrace, STREAM_TRACE_HEADER};
+use crate::auth::authenticator::verify_jwt;
+use crate::auth::signature::sign_content;
 use crate::utils::file_utils::create_new_file_for_write;
 use crate::utils::lru_cache::LRUResourceCache;
 
+/// Response header with the signature of the body, see `response_signing`.
+pub const CONTENT_SIGNATURE_HEADER: &str = "x-content-signature";
+
+/// Adds the signature header if `response_signing` is configured, the body is buffered to sign it as sent.
+/// Partial (`206`) and streamed responses are sent unsigned, they would have to be buffered completely.
+pub async fn sign_response(config: &Config, response: HttpResponse) -> HttpResponse {
+    let Some(response_signing) = config.response_signing.as_ref() else {
+        return response;
+    };
+    if response.status() != actix_web::http::StatusCode::OK || matches!(response.body().size(), BodySize::Stream) {
+        return response;
+    }
+    let (response, body) = response.into_parts();
+    match actix_web::body::to_bytes(body).await {
+        Ok(content) => {
+            let signature = sign_content(response_signing.secret.as_bytes(), &content);
+            let mut response = response.set_body(content).map_into_boxed_body();
+            if let Ok(value) = HeaderValue::from_str(&signature) {
+                response.headers_mut().insert(HeaderName::from_static(CONTENT_SIGNATURE_HEADER), value);
+            }
+            response
+        }
+        Err(err) => {
+            error!("Failed to sign response: {err}");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
 pub async fn serve_file(file_path: &Path, req: &HttpRequest, mime_type: mime::Mime) -> HttpResponse {
     if file_path.exists() {
         if let Ok(file) = actix_files::NamedFile::open_async(file_path).await {
@@ -46,8 +91,8 @@ pub async fn serve_file(file_path: &Path, req: &HttpRequest, mime_type: mime::Mi
 }
 
 pub fn get_user_target_by_credentials<'a>(username: &str, password: &str, api_req: &'a UserApiRequest,
-                                          app_state: &'a AppState) -> Option<(ProxyUserCredentials, &'a ConfigTarget)> {
-    if !username.is_empty() && !password.is_empty() {
+                                          app_state: &'a AppState, req: &HttpRequest) -> Option<(ProxyUserCredentials, &'a ConfigTarget)> {
+    let user_target = if !username.is_empty() && !password.is_empty() {
         app_state.config.get_target_for_user(username, password)
     } else {
         let token = api_req.token.as_str().trim();
@@ -56,13 +101,72 @@ pub fn get_user_target_by_credentials<'a>(username: &str, password: &str, api_re
         } else {
             app_state.config.get_target_for_user_by_token(token)
         }
+    };
+    if let Some((mut user, target)) = user_target {
+        apply_request_origin(app_state, &mut user, req);
+        app_state.user_activities.record_login(&user, get_client_ip(app_state, req), get_user_agent(req));
+        return Some((user, target));
+    }
+    // the preview user is not a configured user, it has no recorded activity
+    let preview_token = if username == PREVIEW_USERNAME { password } else { api_req.token.as_str().trim() };
+    let (mut user, target_name) = app_state.preview_users.get_user(preview_token)?;
+    let target = app_state.config.get_target_by_name(&target_name)?;
+    // the held outputs of a target which failed the validation, otherwise the published outputs
+    let target = target.t_preview.as_deref()
+        .filter(|preview| get_target_storage_path(&app_state.config, &preview.name).is_some_and(|path| path.exists()))
+        .unwrap_or(target);
+    apply_request_origin(app_state, &mut user, req);
+    Some((user, target))
+}
+
+/// The user of a set-top box, the mac has to be normalized.
+pub fn get_user_target_by_mac<'a>(mac: &str, app_state: &'a AppState, req: &HttpRequest) -> Option<(ProxyUserCredentials, &'a ConfigTarget)> {
+    let (mut user, target) = app_state.config.get_target_for_user_by_mac(mac)?;
+    apply_request_origin(app_state, &mut user, req);
+    app_state.user_activities.record_login(&user, get_client_ip(app_state, req), get_user_agent(req));
+    Some((user, target))
+}
+
+/// The ip of the client, behind a trusted proxy the forwarded ip.
+fn get_client_ip(app_state: &AppState, req: &HttpRequest) -> Option<String> {
+    let peer_addr = req.peer_addr()?;
+    if request_utils::is_trusted_proxy(&peer_addr.ip(), &app_state.config.api.trusted_proxies) {
+        if let Some(ip) = req.connection_info().realip_remote_addr() {
+            return Some(ip.to_string());
+        }
+    }
+    Some(peer_addr.ip().to_string())
+}
+
+fn get_user_agent(req: &HttpRequest) -> Option<&str> {
+    req.headers().get(USER_AGENT).and_then(|value| value.to_str().ok())
+}
+
+/// The server info is selected by the host of the request.
+/// Forwarded headers are only used from trusted proxies, the urls are then built with the forwarded protocol and host.
+fn apply_request_origin(app_state: &AppState, user: &mut ProxyUserCredentials, req: &HttpRequest) {
+    let config = &app_state.config;
+    let is_forwarded = [FORWARDED, X_FORWARDED_HOST, X_FORWARDED_PROTO].iter().any(|header| req.headers().contains_key(header));
+    if is_forwarded && req.peer_addr().is_some_and(|addr| request_utils::is_trusted_proxy(&addr.ip(), &config.api.trusted_proxies)) {
+        let connection_info = req.connection_info();
+        let origin = ForwardedOrigin { protocol: connection_info.scheme().to_string(), host: connection_info.host().to_string() };
+        config.select_user_server_for_host(user, &origin.host);
+        user.t_forwarded_origin = Some(origin);
+    } else if let Some(host) = req.headers().get(HOST).and_then(|host| host.to_str().ok()).or_else(|| req.uri().authority().map(Authority::as_str)) {
+        config.select_user_server_for_host(user, host);
     }
 }
 
-pub fn get_user_target<'a>(api_req: &'a UserApiRequest, app_state: &'a AppState) -> Option<(ProxyUserCredentials, &'a ConfigTarget)> {
+/// The category translations for the locale of the user or the `Accept-Language` header of the request.
+pub fn get_category_translations<'a>(config: &'a Config, user: &ProxyUserCredentials, req: &HttpRequest) -> Option<&'a HashMap<String, String>> {
+    let accept_language = req.headers().get(ACCEPT_LANGUAGE).and_then(|value| value.to_str().ok());
+    config.get_category_translations(user.locale.as_deref(), accept_language)
+}
+
+pub fn get_user_target<'a>(api_req: &'a UserApiRequest, app_state: &'a AppState, req: &HttpRequest) -> Option<(ProxyUserCredentials, &'a ConfigTarget)> {
     let username = api_req.username.as_str().trim();
     let password = api_req.password.as_str().trim();
-    get_user_target_by_credentials(username, password, api_req, app_state)
+    get_user_target_by_credentials(username, password, api_req, app_state, req)
 }
 
 /// Creates a broadcast notify stream for the given URL if a shared stream exists.
@@ -80,18 +184,158 @@ async fn create_broadcast_stream(
     }
 }
 
+/// A stream is traced if the user has `trace_streams` enabled or the request has the trace header with a valid web ui token.
+fn start_stream_trace(app_state: &AppState, req: &HttpRequest, user: Option<&ProxyUserCredentials>, stream_url: &str, item_type: PlaylistItemType) -> Option<Arc<StreamTrace>> {
+    let header_token = req.headers().get(STREAM_TRACE_HEADER).and_then(|value| value.to_str().ok());
+    let header_enabled = header_token.is_some_and(|token| app_state.config.web_auth.as_ref()
+        .is_some_and(|web_auth| web_auth.enabled && verify_jwt(token, web_auth.secret.as_bytes())));
+    if !header_enabled && !user.is_some_and(|user| user.trace_streams) {
+        return None;
+    }
+    let trace = app_state.stream_traces.start(user.map(|user| user.username.as_str()), stream_url);
+    trace.event(&format!("{} {} {item_type:?} stream requested", req.method(), req.path()));
+    Some(trace)
+}
+
+fn trace_event(stream_trace: Option<&Arc<StreamTrace>>, message: &str) {
+    if let Some(trace) = stream_trace {
+        trace.event(message);
+    }
+}
+
+/// Users with `max_connections` are limited to this number of concurrent streams.
+/// The streams of the other users are counted for the metrics.
+fn acquire_user_connection(app_state: &AppState, user: Option<&ProxyUserCredentials>) -> Result<Option<Arc<UserConnectionGuard>>, HttpResponse> {
+    let Some(user) = user else {
+        return Ok(None);
+    };
+    let max_connections = user.max_connections.unwrap_or(u32::MAX);
+    match app_state.user_connections.acquire(&user.username, max_connections) {
+        Some(guard) => Ok(Some(Arc::new(guard))),
+        None => {
+            if !user.no_log {
+                debug_if_enabled!("Connection limit of {max_connections} reached for user {}", user.username);
+            }
+            Err(HttpResponse::ServiceUnavailable().content_type(mime::TEXT_PLAIN_UTF_8).body("User connection limit reached"))
+        }
+    }
+}
+
+/// Takes a provider connection slot if the input limits the connections, the streams of the other inputs are counted for the metrics.
+/// Requests without user, like the web ui player, share one slot account.
+fn acquire_provider_connection(app_state: &AppState, input: Option<&ConfigInput>, user: Option<&ProxyUserCredentials>) -> Result<Option<ProviderConnectionGuard>, HttpResponse> {
+    let Some(input) = input else {
+        return Ok(None);
+    };
+    let (username, weight) = user.map_or(("", 1), |user| (user.username.as_str(), user.get_provider_weight()));
+    let Some(connections) = input.connections.as_ref() else {
+        return Ok(app_state.provider_connections.track(input.id, username));
+    };
+    let healthy = app_state.config.t_input_health.is_healthy(input.id);
+    match app_state.provider_connections.acquire(input.id, connections, username, weight, healthy) {
+        Some(guard) => Ok(Some(guard)),
+        None => {
+            if !user.is_some_and(|user| user.no_log) {
+                debug_if_enabled!("Provider connection limit reached for input {}, user {username}", input.name.as_deref().unwrap_or_default());
+            }
+            Err(HttpResponse::ServiceUnavailable().content_type(mime::TEXT_PLAIN_UTF_8).body("Provider connection limit reached"))
+        }
+    }
+}
+
+/// Takes a playlist generation slot if `playlist_limit` is configured, busy servers respond with `503` and `Retry-After`.
+pub async fn acquire_playlist_permit(app_state: &AppState) -> Result<Option<OwnedSemaphorePermit>, HttpResponse> {
+    let (Some(limiter), Some(limit)) = (app_state.playlist_limiter.as_ref(), app_state.config.api.playlist_limit.as_ref()) else {
+        return Ok(None);
+    };
+    match limiter.acquire().await {
+        Some(permit) => Ok(Some(permit)),
+        None => {
+            debug_if_enabled!("Playlist request rejected, {} playlists are generated", limit.max_concurrent);
+            Err(HttpResponse::ServiceUnavailable()
+                .insert_header((RETRY_AFTER, limit.retry_after_secs.to_string()))
+                .content_type(mime::TEXT_PLAIN_UTF_8)
+                .body("Server busy"))
+        }
+    }
+}
+
+/// The playlist slot is released when the playlist is sent or the client disconnects.
+pub fn hold_playlist_permit<S: Stream>(stream: S, permit: Option<OwnedSemaphorePermit>) -> impl Stream<Item=S::Item> {
+    stream.map(move |item| {
+        let _ = &permit;
+        item
+    })
+}
+
+/// The connection slot is released when the stream ends or the client disconnects.
+fn hold_connection<G: Send + 'static>(stream: BoxStream<'static, Result<Bytes, StreamError>>, guard: Option<G>) -> BoxStream<'static, Result<Bytes, StreamError>> {
+    match guard {
+        Some(guard) => stream.map(move |chunk| {
+            let _ = &guard;
+            chunk
+        }).boxed(),
+        None => stream,
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 pub async fn stream_response(app_state: &AppState, stream_url: &str,
                              req: &HttpRequest, input: Option<&ConfigInput>,
-                             item_type: PlaylistItemType, target: &ConfigTarget) -> HttpResponse {
+                             item_type: PlaylistItemType, target: Option<&ConfigTarget>,
+                             user: Option<&ProxyUserCredentials>) -> HttpResponse {
+    if app_state.config.t_maintenance.is_enabled() {
+        return maintenance_response(app_state, req).await;
+    }
+    if req.method() == Method::HEAD {
+        return head_stream_response(app_state, stream_url, req, input, item_type, target).await;
+    }
+    let original_input = input;
+
     if log_enabled!(log::Level::Trace) { trace!("Try to open stream {}", mask_sensitive_info(stream_url)); }
 
-    let share_stream = is_stream_share_enabled(item_type, target);
+    if let Some(user) = user {
+        app_state.user_activities.record_stream(user, get_client_ip(app_state, req), get_user_agent(req));
+    }
+    let stream_trace = start_stream_trace(app_state, req, user, stream_url, item_type);
+    let user_guard = match acquire_user_connection(app_state, user) {
+        Ok(guard) => guard,
+        Err(response) => {
+            trace_event(stream_trace.as_ref(), "User connection limit reached");
+            return custom_video_response(app_state, req, target, CustomVideo::UserConnectionsExhausted, response).await;
+        }
+    };
+    // the viewers of the live channels are counted for the trending order of the playlist
+    let viewer_guard = is_live_stream(item_type).then(|| app_state.channel_viewers.acquire(stream_url)).flatten().map(Arc::new);
+    let share_stream = target.is_some_and(|target| is_stream_share_enabled(item_type, target));
     if share_stream {
-        if let Some(value) = shared_stream_response(app_state, stream_url).await {
+        if let Some(value) = shared_stream_response(app_state, stream_url, stream_trace.as_ref(), user_guard.as_ref().map(Arc::clone),
+                                                    viewer_guard.as_ref().map(Arc::clone)).await {
             return value;
         }
     }
 
+    // the shared stream is registered with the requested url, the provider request can use the fallback input
+    let (input, provider_url) = match guard_stream_request(app_state, input, stream_url).await {
+        Ok(request) => request,
+        Err(denied) => {
+            trace_event(stream_trace.as_ref(), &denied.to_string());
+            if let (Some(input), ProviderRequestDenied::Unreachable(err)) = (original_input, &denied) {
+                app_state.config.t_input_health.record_failure(input, app_state.config.messaging.as_ref(), &format!("Provider unreachable: {err}"));
+            }
+            return denied_stream_response(app_state, req, target, &denied).await;
+        }
+    };
+
+    // a shared stream holds the provider connection of the user who opened it
+    let connection_guard = match acquire_provider_connection(app_state, input, user) {
+        Ok(guard) => guard,
+        Err(response) => {
+            trace_event(stream_trace.as_ref(), "Provider connection limit reached");
+            return custom_video_response(app_state, req, target, CustomVideo::ChannelUnavailable, response).await;
+        }
+    };
+
     let (stream_retry, buffer_enabled, buffer_size) = app_state
         .config
         .reverse_proxy
@@ -104,23 +348,46 @@ pub async fn stream_response(app_state: &AppState, stream_url: &str,
                 .map_or((false, 0), |buffer| (buffer.enabled, buffer.size));
             (stream.retry, buffer_enabled, buffer_size)
         });
+    let timeouts = StreamTimeouts::new(app_state.config.reverse_proxy.as_ref()
+        .and_then(|reverse_proxy| reverse_proxy.stream.as_ref())
+        .and_then(|stream| stream.timeouts.as_ref()), item_type);
 
 
-    if let Ok(url) = Url::parse(stream_url) {
+    if let Ok(url) = Url::parse(&provider_url) {
         let direct_pipe_provider_stream = !stream_retry && !buffer_enabled;
-        let (stream_opt, provider_response) = if direct_pipe_provider_stream {
-            get_provider_pipe_stream(&app_state.http_client, &url, req, input).await
+        // icy metadata is interleaved with a fixed byte interval, the client framing breaks
+        // when joining a shared stream or when the provider stream is reconnected.
+        let icy_metadata = !share_stream && !stream_retry;
+        let (stream_opt, provider_response) = if is_relay_url(&url) {
+            let relay_config = app_state.config.reverse_proxy.as_ref()
+                .and_then(|reverse_proxy| reverse_proxy.stream.as_ref())
+                .and_then(|stream| stream.relay.as_ref());
+            trace_event(stream_trace.as_ref(), "Relaying stream");
+            (get_relay_stream(relay_config, &url).await, Some((get_relay_response_headers(), StatusCode::OK)))
+        } else if direct_pipe_provider_stream {
+            trace_event(stream_trace.as_ref(), &format!("Provider request {}", url.as_str()));
+            get_provider_pipe_stream(&app_state.http_client, &url, req, input, icy_metadata, &timeouts).await
         } else {
-            let buffer_stream_options = BufferStreamOptions::new(item_type, stream_retry, buffer_enabled, buffer_size);
+            let buffer_stream_options = BufferStreamOptions::new(item_type, stream_retry, buffer_enabled, buffer_size, icy_metadata)
+                .with_trace(stream_trace.clone())
+                .with_timeouts(timeouts);
             provider_stream::get_provider_reconnect_buffered_stream(&app_state.http_client, &url, req, input, buffer_stream_options).await
         };
+        let provider_response = apply_cookie_policy(app_state, share_stream, apply_response_quirks(input, provider_response));
+        if let (Some(trace), Some((headers, status))) = (stream_trace.as_ref(), provider_response.as_ref()) {
+            trace.event(&format!("Provider responded with status {status}, headers {headers:?}"));
+        }
+        if let Some(input) = input {
+            record_input_health(app_state, input, stream_opt.is_some(), provider_response.as_ref());
+        }
         if let Some(stream) = stream_opt {
+            let stream = hold_connection(hold_connection(hold_connection(trace_stream(stream, stream_trace.as_ref()), connection_guard), user_guard), viewer_guard);
             let use_buffer = !buffer_enabled || direct_pipe_provider_stream;
             return if share_stream {
                 let shared_headers = provider_response.as_ref().map_or_else(Vec::new, |(h, _)| h.clone());
                 SharedStream::register(app_state, stream_url, stream, use_buffer, shared_headers).await;
                 if let Some(broadcast_stream) = create_broadcast_stream(app_state, stream_url).await {
-                    let body_stream = BodyStream::new(broadcast_stream);
+                    let body_stream = BodyStream::new(stop_on_maintenance(app_state, broadcast_stream));
                     let mut response_builder = get_stream_response_with_headers(provider_response, stream_url);
                     response_builder.body(body_stream)
                 } else {
@@ -128,17 +395,177 @@ pub async fn stream_response(app_state: &AppState, stream_url: &str,
                 }
             } else {
                 let mut response_builder = get_stream_response_with_headers(provider_response, stream_url);
-                response_builder.streaming(stream)
+                response_builder.streaming(stop_on_maintenance(app_state, stream))
             };
         }
+        let message = provider_response.map_or_else(|| String::from("Cant open stream"), |(_, status)| format!("Provider responded with status {status}"));
+        trace_event(stream_trace.as_ref(), &message);
+        app_state.diagnostics.lock().await.add_stream_error(stream_url, req.path(), &message);
     }
-    error!("Cant open stream {}", mask_sensitive_info(stream_url));
-    HttpResponse::BadRequest().finish()
+    error!("Cant open stream {}", mask_sensitive_info(&provider_url));
+    custom_video_response(app_state, req, target, CustomVideo::ChannelUnavailable, HttpResponse::BadRequest().finish()).await
 }
 
-async fn shared_stream_response(app_state: &AppState, stream_url: &str) -> Option<HttpResponse> {
+/// The file is opened for each request, a replaced file is served without restart.
+async fn video_file_response(req: &HttpRequest, path: &Path) -> Option<HttpResponse> {
+    match NamedFile::open_async(path).await {
+        Ok(named_file) => Some(named_file.set_content_type("video/mp2t".parse::<mime::Mime>().unwrap_or(mime::APPLICATION_OCTET_STREAM))
+            .disable_content_disposition().into_response(req)),
+        Err(err) => {
+            error!("Cant open video {}: {err}", path.display());
+            None
+        }
+    }
+}
+
+/// The custom video of the target or the global one instead of the error response.
+async fn custom_video_response(app_state: &AppState, req: &HttpRequest, target: Option<&ConfigTarget>, video: CustomVideo, response: HttpResponse) -> HttpResponse {
+    match app_state.config.get_custom_video(target, video) {
+        Some(path) => video_file_response(req, &path).await.unwrap_or(response),
+        None => response,
+    }
+}
+
+/// New streams get the maintenance video, or the maintenance message if no video is configured.
+pub async fn maintenance_response(app_state: &AppState, req: &HttpRequest) -> HttpResponse {
+    let video = app_state.config.maintenance.as_ref().and_then(|maintenance| maintenance.video.as_ref());
+    if let Some(video) = video {
+        if let Some(response) = video_file_response(req, Path::new(video)).await {
+            return response;
+        }
+    }
+    let message = app_state.config.t_maintenance.get_state().message.unwrap_or_else(|| "Service is under maintenance".to_string());
+    HttpResponse::ServiceUnavailable().content_type(mime::TEXT_PLAIN_UTF_8).body(message)
+}
+
+/// Running streams end when the maintenance mode is enabled without draining.
+fn stop_on_maintenance(app_state: &AppState, stream: BoxStream<'static, Result<Bytes, StreamError>>) -> BoxStream<'static, Result<Bytes, StreamError>> {
+    let maintenance = Arc::clone(&app_state.config.t_maintenance);
+    stream.take_while(move |_| futures::future::ready(!maintenance.should_stop_streams())).boxed()
+}
+
+/// The input and url of the provider request, see `guard_provider_request`.
+async fn guard_stream_request<'a>(app_state: &'a AppState, input: Option<&'a ConfigInput>, stream_url: &str) -> Result<(Option<&'a ConfigInput>, String), ProviderRequestDenied> {
+    match input {
+        Some(input) => guard_provider_request(&app_state.config, input, stream_url).await.map(|(input, url)| (Some(input), url)),
+        None => Ok((None, stream_url.to_string())),
+    }
+}
+
+async fn denied_stream_response(app_state: &AppState, req: &HttpRequest, target: Option<&ConfigTarget>, denied: &ProviderRequestDenied) -> HttpResponse {
+    match denied {
+        ProviderRequestDenied::RateLimit(wait) => HttpResponse::ServiceUnavailable()
+            .insert_header((RETRY_AFTER, (wait.as_secs() + 1).to_string()))
+            .content_type(mime::TEXT_PLAIN_UTF_8)
+            .body("Provider rate limit"),
+        ProviderRequestDenied::Unreachable(_) => {
+            let response = HttpResponse::ServiceUnavailable().content_type(mime::TEXT_PLAIN_UTF_8).body("Provider unreachable");
+            custom_video_response(app_state, req, target, CustomVideo::ChannelUnavailable, response).await
+        }
+    }
+}
+
+/// Players probe streams with HEAD before playback. The headers are taken from a running shared stream
+/// or from a HEAD request to the provider, a provider stream connection is never opened.
+/// Probes are not taken into account for the provider health.
+async fn head_stream_response(app_state: &AppState, stream_url: &str,
+                              req: &HttpRequest, input: Option<&ConfigInput>,
+                              item_type: PlaylistItemType, target: Option<&ConfigTarget>) -> HttpResponse {
+    if target.is_some_and(|target| is_stream_share_enabled(item_type, target)) {
+        if let Some((headers, _)) = app_state.shared_streams.lock().await.get(stream_url) {
+            return head_response(Some((headers.clone(), StatusCode::OK)), stream_url);
+        }
+    }
+    // probes pass the same rate limit and preflight checks as the stream requests
+    let (input, provider_url) = match guard_stream_request(app_state, input, stream_url).await {
+        Ok(request) => request,
+        Err(denied) => return denied_stream_response(app_state, req, target, &denied).await,
+    };
+    let Ok(url) = Url::parse(&provider_url) else {
+        return HttpResponse::BadRequest().finish();
+    };
+    if is_relay_url(&url) {
+        return head_response(Some((get_relay_response_headers(), StatusCode::OK)), stream_url);
+    }
+    match get_provider_head_response(&app_state.http_client, &url, req, input).await {
+        // provider does not support HEAD, the stream is answered without provider headers
+        Some((_, status)) if status == StatusCode::METHOD_NOT_ALLOWED || status == StatusCode::NOT_IMPLEMENTED => head_response(None, stream_url),
+        Some(provider_response) => {
+            let share_stream = target.is_some_and(|target| is_stream_share_enabled(item_type, target));
+            head_response(apply_cookie_policy(app_state, share_stream, apply_response_quirks(input, Some(provider_response))), stream_url)
+        }
+        None => HttpResponse::BadRequest().finish(),
+    }
+}
+
+/// The provider cookies belong to the provider session of the request. They are only forwarded with `forward_cookies`,
+/// a shared stream replays the headers of the first request to every client which joins.
+fn apply_cookie_policy(app_state: &AppState, share_stream: bool, provider_response: Option<(Vec<(String, String)>, StatusCode)>) -> Option<(Vec<(String, String)>, StatusCode)> {
+    let forward_cookies = !share_stream && app_state.config.reverse_proxy.as_ref()
+        .and_then(|reverse_proxy| reverse_proxy.stream.as_ref())
+        .is_some_and(|stream| stream.forward_cookies);
+    provider_response.map(|(mut headers, status)| {
+        if !forward_cookies {
+            headers.retain(|(key, _)| key.as_str() != SET_COOKIE.as_str());
+        }
+        (headers, status)
+    })
+}
+
+/// Removes provider response headers which are known to be wrong for the input.
+fn apply_response_quirks(input: Option<&ConfigInput>, provider_response: Option<(Vec<(String, String)>, StatusCode)>) -> Option<(Vec<(String, String)>, StatusCode)> {
+    match (input, provider_response) {
+        (Some(input), Some((mut headers, status))) if input.has_quirk(ProviderQuirk::IgnoreContentLength) => {
+            headers.retain(|(key, _)| key.as_str() != CONTENT_LENGTH.as_str());
+            Some((headers, status))
+        }
+        (_, provider_response) => provider_response,
+    }
+}
+
+fn head_response(provider_response: Option<(Vec<(String, String)>, StatusCode)>, stream_url: &str) -> HttpResponse {
+    let content_length = provider_response.as_ref().and_then(|(headers, _)| headers.iter()
+        .find(|(key, _)| key.as_str() == CONTENT_LENGTH.as_str())
+        .and_then(|(_, value)| value.parse::<u64>().ok()));
+    let mut response_builder = get_stream_response_with_headers(provider_response, stream_url);
+    match content_length {
+        // the content length of an empty body would overwrite the provider content length
+        Some(length) => response_builder.no_chunking(length).body(BodyStream::new(futures::stream::empty::<Result<Bytes, StreamError>>())),
+        None => response_builder.finish(),
+    }
+}
+
+/// Failed logins, server errors and connection failures count towards the provider health.
+/// A missing stream (404) is a channel problem and not taken into account.
+fn record_input_health(app_state: &AppState, input: &ConfigInput, opened: bool, provider_response: Option<&(Vec<(String, String)>, StatusCode)>) {
+    let input_health = &app_state.config.t_input_health;
+    let messaging = app_state.config.messaging.as_ref();
+    let status = provider_response.map(|(_, status)| *status);
+    if opened {
+        request_utils::record_provider_success(input);
+        input_health.record_success(input, messaging);
+    } else {
+        let retry_after = provider_response.and_then(|(headers, _)| headers.iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(RETRY_AFTER.as_str())).map(|(_, value)| value.as_str()));
+        match status {
+            None => { input_health.record_failure(input, messaging, "Failed to connect"); }
+            Some(status) if request_utils::record_provider_rate_limit(input, status, retry_after) => {
+                input_health.record_failure(input, messaging, &format!("Provider rate limit, status {status}"));
+            }
+            Some(status) if status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN || status.is_server_error() => {
+                input_health.record_failure(input, messaging, &format!("Provider responded with status {status}"));
+            }
+            Some(_) => {}
+        }
+    }
+}
+
+async fn shared_stream_response(app_state: &AppState, stream_url: &str, stream_trace: Option<&Arc<StreamTrace>>,
+                                user_guard: Option<Arc<UserConnectionGuard>>, viewer_guard: Option<Arc<ChannelViewerGuard>>) -> Option<HttpResponse> {
     if let Some(stream) = create_broadcast_stream(app_state, stream_url).await {
         debug_if_enabled!("Using shared channel {}", mask_sensitive_info(stream_url));
+        trace_event(stream_trace, "Joined shared stream");
+        let stream = hold_connection(hold_connection(trace_stream(stream, stream_trace), user_guard), viewer_guard);
         if let Some((headers,_)) = app_state.shared_streams.lock().await.get(stream_url) {
             let mut response_builder = get_stream_response_with_headers(Some((headers.clone(), StatusCode::OK)), stream_url);
             let current_date = Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string();
@@ -158,6 +585,8 @@ pub type HeaderFilter = Option<Box<dyn Fn(&str) -> bool>>;
 pub fn get_headers_from_request(req: &HttpRequest, filter: &HeaderFilter) -> HashMap<String, Vec<u8>> {
     req.headers()
         .iter()
+        // the trace header holds a web ui token and is never forwarded to the provider
+        .filter(|(k, _)| k.as_str() != STREAM_TRACE_HEADER)
         .filter(|(k, _)| match &filter {
             None => true,
             Some(predicate) => predicate(k.as_str())
@@ -193,13 +622,22 @@ pub async fn resource_response(app_state: &AppState, resource_url: &str, req: &H
         if let Some(resource_path) = guard.get_content(resource_url).await {
             if let Ok(named_file) = NamedFile::open_async(resource_path).await {
                 debug_if_enabled!("Cached resource {}", mask_sensitive_info(resource_url));
+                metrics::record_cache_lookup(true);
                 return named_file.into_response(req);
             }
         }
+        metrics::record_cache_lookup(false);
     }
-    debug_if_enabled!("Try to fetch resource {}", mask_sensitive_info(resource_url));
-    if let Ok(url) = Url::parse(resource_url) {
-        let client = request_utils::get_client_request(&app_state.http_client, input.map(|i| &i.headers), &url, Some(&req_headers));
+    let (input, provider_url) = match guard_stream_request(app_state, input, resource_url).await {
+        Ok(request) => request,
+        Err(denied) => {
+            debug_if_enabled!("Resource request denied {}: {denied}", mask_sensitive_info(resource_url));
+            return HttpResponse::ServiceUnavailable().content_type(mime::TEXT_PLAIN_UTF_8).body(denied.to_string());
+        }
+    };
+    debug_if_enabled!("Try to fetch resource {}", mask_sensitive_info(&provider_url));
+    if let Ok(url) = Url::parse(&provider_url) {
+        let client = request_utils::get_client_request(&app_state.http_client, input, &url, Some(&req_headers));
         match client.send().await {
             Ok(response) => {
                 let status = response.status();
@@ -213,7 +651,7 @@ pub async fn resource_response(app_state: &AppState, resource_url: &str, req: &H
                     if let Some(cache) = app_state.cache.as_ref() {
                        let resource_path = {
                             let guard = cache.lock().await;
-                            guard.store_path(resource_url)
+                            guard.part_path(resource_url)
                         };
                         if let Ok(file) = create_new_file_for_write(&resource_path) {
                             let writer = Arc::new(file);
@@ -235,3 +673,91 @@ pub async fn resource_response(app_state: &AppState, resource_url: &str, req: &H
     }
     HttpResponse::BadRequest().finish()
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use actix_web::http::header::RETRY_AFTER;
+    use actix_web::http::{Method, StatusCode};
+    use actix_web::test::TestRequest;
+
+    use actix_web::body::{to_bytes, BodySize, MessageBody};
+    use actix_web::HttpResponse;
+    use bytes::Bytes;
+    use futures::stream;
+
+    use crate::api::api_utils::{apply_cookie_policy, sign_response, stream_response, CONTENT_SIGNATURE_HEADER};
+    use crate::api::model::app_state::AppState;
+    use crate::api::main_api::create_shared_data;
+    use crate::auth::signature::sign_content;
+    use crate::model::config::{Config, ConfigInput, ConfigSource, ResponseSigningConfig, ReverseProxyConfig, StreamConfig};
+    use crate::model::playlist::PlaylistItemType;
+
+    #[actix_rt::test]
+    async fn head_stream_guard_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = ConfigInput { id: 1, name: Some("provider".to_string()), url: "http://127.0.0.1:1".to_string(), ..Default::default() };
+        let cfg = Arc::new(Config {
+            working_dir: dir.path().to_string_lossy().to_string(),
+            sources: vec![ConfigSource { inputs: vec![input], targets: vec![] }],
+            ..Config::default()
+        });
+        let app_state = create_shared_data(&cfg);
+        let input = &cfg.sources[0].inputs[0];
+        let stream_url = "http://127.0.0.1:1/live/user/pass/1.ts";
+        let req = TestRequest::default().method(Method::HEAD).to_http_request();
+
+        // no probe leaves for the provider during the rate limit
+        input.t_rate_limit.record_rate_limited(Some(30));
+        let response = stream_response(&app_state, stream_url, &req, Some(input), PlaylistItemType::Live, None, None).await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert!(response.headers().contains_key(RETRY_AFTER));
+        input.t_rate_limit.record_success();
+
+        // nor while the preflight checks fail
+        input.t_preflight.start_simulated_outage(chrono::Utc::now().timestamp() + 60);
+        let response = stream_response(&app_state, stream_url, &req, Some(input), PlaylistItemType::Live, None, None).await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert!(!response.headers().contains_key(RETRY_AFTER));
+    }
+
+    #[actix_rt::test]
+    async fn cookie_policy_test() {
+        let provider_response = || Some((vec![("content-type".to_string(), "video/mp2t".to_string()),
+                                              ("set-cookie".to_string(), "session=1".to_string())], reqwest::StatusCode::OK));
+        let cookies = |app_state: &AppState, share_stream: bool| apply_cookie_policy(app_state, share_stream, provider_response())
+            .unwrap().0.iter().filter(|(key, _)| key == "set-cookie").count();
+        let app_state = create_shared_data(&Arc::new(Config::default()));
+        assert_eq!(cookies(&app_state, false), 0);
+        let cfg = Config {
+            reverse_proxy: Some(ReverseProxyConfig { stream: Some(StreamConfig { forward_cookies: true, ..StreamConfig::default() }), cache: None }),
+            ..Config::default()
+        };
+        let app_state = create_shared_data(&Arc::new(cfg));
+        assert_eq!(cookies(&app_state, false), 1);
+        // the joined clients of a shared stream would get the session of the first client
+        assert_eq!(cookies(&app_state, true), 0);
+    }
+
+    #[actix_rt::test]
+    async fn sign_response_test() {
+        let cfg = Config {
+            response_signing: Some(ResponseSigningConfig { secret: "secret".to_string() }),
+            ..Config::default()
+        };
+        let content = Bytes::from_static(b"#EXTM3U");
+
+        let response = sign_response(&cfg, HttpResponse::Ok().body(content.clone())).await;
+        assert_eq!(response.headers().get(CONTENT_SIGNATURE_HEADER).unwrap().to_str().unwrap(), sign_content(b"secret", &content));
+        assert_eq!(to_bytes(response.into_body()).await.unwrap(), content);
+
+        // partial and streamed responses are not buffered to sign them
+        let response = sign_response(&cfg, HttpResponse::PartialContent().body(content.clone())).await;
+        assert!(!response.headers().contains_key(CONTENT_SIGNATURE_HEADER));
+        let response = sign_response(&cfg, HttpResponse::Ok()
+            .streaming(stream::iter(vec![Ok::<Bytes, String>(content.clone())]))).await;
+        assert!(!response.headers().contains_key(CONTENT_SIGNATURE_HEADER));
+        assert!(matches!(response.body().size(), BodySize::Stream));
+    }
+}
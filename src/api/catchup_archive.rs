@@ -0,0 +1,423 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::{Duration, SystemTime};
+
+use base64::prelude::{Engine, BASE64_STANDARD};
+use chrono::{DateTime, FixedOffset, Local, NaiveDateTime, Offset, TimeZone, Utc};
+use futures::StreamExt;
+use log::{debug, error, info, warn};
+use serde_json::{json, Value};
+use tokio::io::{AsyncWriteExt, BufWriter};
+use url::Url;
+
+use crate::api::model::provider_connections::ProviderConnections;
+use crate::api::xmltv_api::get_epg_path_for_target;
+use crate::model::config::{CatchupArchiveConfig, Config, ConfigInput, ConfigTarget};
+use crate::model::playlist::XtreamPlaylistItem;
+use crate::repository::epg_repository::{epg_read_channel_programmes, EpgProgramme};
+use crate::repository::xtream_repository::xtream_get_live_items_by_epg_id;
+use crate::utils::file_utils::file_reader;
+use crate::utils::json_utils::json_write_documents_to_file;
+use crate::utils::request_utils::get_client_request;
+
+const RECORDING_EXTENSION: &str = "ts";
+const PROGRAMME_EXTENSION: &str = "json";
+// the recording is written into a part file and renamed when the programme has ended
+const PART_EXTENSION: &str = "part";
+// the recordings share the provider connections with the users
+const ARCHIVE_USERNAME: &str = "_archive";
+const ARCHIVE_CHECK_INTERVAL_SECS: u64 = 60;
+// the recording is started before the programme, the epg times are not exact
+const ARCHIVE_LEAD_SECS: i64 = 30;
+const RECONNECT_DELAY_SECS: u64 = 5;
+const CATCHUP_DATE_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// The recordings by channel directory, valid while the modification time of the directory is unchanged.
+type RecordingCache = HashMap<PathBuf, (SystemTime, Arc<Vec<ArchiveRecording>>)>;
+static RECORDINGS: LazyLock<Mutex<RecordingCache>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// A finished recording of an epg programme.
+#[derive(Debug, Clone)]
+pub struct ArchiveRecording {
+    pub path: PathBuf,
+    pub programme: EpgProgramme,
+}
+
+pub fn get_channel_dir(archive: &CatchupArchiveConfig, target_name: &str, epg_id: &str) -> PathBuf {
+    let sanitize = |name: &str| name.chars().map(|c| if c.is_alphanumeric() || matches!(c, '.' | '-' | '_') { c } else { '_' }).collect::<String>();
+    PathBuf::from(&archive.directory).join(sanitize(target_name)).join(sanitize(epg_id))
+}
+
+fn get_recording_name(programme: &EpgProgramme) -> String {
+    format!("{}_{}", programme.start, programme.stop)
+}
+
+/// The finished recordings of a channel, ordered by start. The recordings are cached until the directory changes.
+pub fn list_recordings(channel_dir: &Path) -> Arc<Vec<ArchiveRecording>> {
+    let Ok(modified) = std::fs::metadata(channel_dir).and_then(|metadata| metadata.modified()) else { return Arc::new(vec![]) };
+    if let Some((_, recordings)) = RECORDINGS.lock().ok().and_then(|cache| cache.get(channel_dir).cloned())
+        .filter(|(cached_modified, _)| *cached_modified == modified) {
+        return recordings;
+    }
+    let recordings = Arc::new(read_recordings(channel_dir));
+    if let Ok(mut cache) = RECORDINGS.lock() {
+        cache.insert(channel_dir.to_path_buf(), (modified, Arc::clone(&recordings)));
+    }
+    recordings
+}
+
+fn read_recordings(channel_dir: &Path) -> Vec<ArchiveRecording> {
+    let Ok(entries) = std::fs::read_dir(channel_dir) else { return vec![] };
+    let mut recordings: Vec<ArchiveRecording> = entries.filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == PROGRAMME_EXTENSION))
+        .filter_map(|programme_path| {
+            let path = programme_path.with_extension(RECORDING_EXTENSION);
+            if !path.exists() {
+                return None;
+            }
+            let programme = File::open(&programme_path).ok().map(file_reader)
+                .and_then(|reader| serde_json::from_reader::<_, EpgProgramme>(reader).ok())?;
+            Some(ArchiveRecording { path, programme })
+        })
+        .collect();
+    recordings.sort_by_key(|recording| recording.programme.start);
+    recordings
+}
+
+/// The recordings of the channel, read on a blocking thread.
+pub async fn get_recordings(channel_dir: PathBuf) -> Arc<Vec<ArchiveRecording>> {
+    tokio::task::spawn_blocking(move || list_recordings(&channel_dir)).await.unwrap_or_default()
+}
+
+/// The recording of the channel which covers the timestamp.
+pub async fn find_recording(archive: &CatchupArchiveConfig, target_name: &str, epg_id: &str, timestamp: i64) -> Option<ArchiveRecording> {
+    if !archive.channels.iter().any(|channel| channel == epg_id) {
+        return None;
+    }
+    get_recordings(get_channel_dir(archive, target_name, epg_id)).await.iter()
+        .find(|recording| recording.programme.start <= timestamp && timestamp < recording.programme.stop)
+        .cloned()
+}
+
+/// The `timezone` of the server info, `UTC`, an offset like `+01:00` or the timezone of the system for other names.
+#[derive(Debug, Clone, Copy)]
+pub enum ServerTimezone {
+    Fixed(FixedOffset),
+    Local,
+}
+
+impl ServerTimezone {
+    pub fn new(timezone: &str) -> Self {
+        let timezone = timezone.trim();
+        let offset = timezone.strip_prefix("UTC").or_else(|| timezone.strip_prefix("GMT")).unwrap_or(timezone);
+        if offset.is_empty() {
+            return Self::Fixed(Utc.fix());
+        }
+        parse_utc_offset(offset).map_or(Self::Local, Self::Fixed)
+    }
+
+    fn to_timestamp(self, date: &NaiveDateTime) -> Option<i64> {
+        match self {
+            Self::Fixed(offset) => offset.from_local_datetime(date).earliest().map(|date| date.timestamp()),
+            Self::Local => Local.from_local_datetime(date).earliest().map(|date| date.timestamp()),
+        }
+    }
+
+    fn format(self, timestamp: i64) -> String {
+        let Some(date) = DateTime::from_timestamp(timestamp, 0) else { return String::new() };
+        match self {
+            Self::Fixed(offset) => date.with_timezone(&offset).format(CATCHUP_DATE_FORMAT).to_string(),
+            Self::Local => date.with_timezone(&Local).format(CATCHUP_DATE_FORMAT).to_string(),
+        }
+    }
+}
+
+/// An offset like `+1`, `+01:00` or `-0530`.
+fn parse_utc_offset(offset: &str) -> Option<FixedOffset> {
+    let (sign, value) = match offset.split_at_checked(1)? {
+        ("+", value) => (1, value),
+        ("-", value) => (-1, value),
+        _ => return None,
+    };
+    let (hours, minutes) = match value.split_once(':') {
+        Some((hours, minutes)) => (hours, minutes),
+        None if value.len() > 2 => value.split_at(value.len() - 2),
+        None => (value, "0"),
+    };
+    let seconds = hours.parse::<i32>().ok()? * 3600 + minutes.parse::<i32>().ok()? * 60;
+    FixedOffset::east_opt(sign * seconds)
+}
+
+/// Parses the start of a timeshift request, `YYYY-MM-DD:HH-MM`, `YYYY-MM-DD:HH:MM` or `YYYY-MM-DD HH:MM:SS`
+/// in the timezone of the server.
+pub fn parse_timeshift_start(start: &str, timezone: ServerTimezone) -> Option<i64> {
+    ["%Y-%m-%d:%H-%M", "%Y-%m-%d:%H:%M", CATCHUP_DATE_FORMAT].iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(start.trim(), format).ok())
+        .and_then(|date| timezone.to_timestamp(&date))
+}
+
+/// The catchup table entries of the recordings in the format of the xtream `get_simple_data_table` action.
+/// The dates are in the timezone of the server, the clients request the timeshift with these dates.
+pub fn get_catchup_listings(recordings: &[ArchiveRecording], epg_id: &str, timezone: ServerTimezone) -> Vec<Value> {
+    let format_date = |timestamp: i64| timezone.format(timestamp);
+    recordings.iter().map(|recording| {
+        let programme = &recording.programme;
+        json!({
+            "id": programme.start.to_string(),
+            "epg_id": epg_id,
+            "title": BASE64_STANDARD.encode(programme.title.as_deref().unwrap_or_default()),
+            "lang": "",
+            "start": format_date(programme.start),
+            "end": format_date(programme.stop),
+            "description": BASE64_STANDARD.encode(programme.desc.as_deref().unwrap_or_default()),
+            "channel_id": epg_id,
+            "start_timestamp": programme.start.to_string(),
+            "stop_timestamp": programme.stop.to_string(),
+            "now_playing": 0,
+            "has_archive": 1,
+        })
+    }).collect()
+}
+
+/// Removes the catchup listings which start before `window_start`, listings without start are removed too.
+pub fn limit_catchup_listings(listings: &mut Vec<Value>, window_start: i64) {
+    listings.retain(|listing| {
+        listing.get("start_timestamp").and_then(|start| match start {
+            Value::Number(number) => number.as_i64(),
+            Value::String(text) => text.trim().parse::<i64>().ok(),
+            _ => None,
+        }).is_some_and(|start| start >= window_start)
+    });
+}
+
+/// Removes the recordings which ended more than `days` ago, returns the count of removed recordings.
+fn purge_recordings(channel_dir: &Path, days: u16, now: i64) -> usize {
+    let expired = now - i64::from(days) * 86_400;
+    let mut removed = 0;
+    for recording in list_recordings(channel_dir).iter().filter(|recording| recording.programme.stop < expired) {
+        if std::fs::remove_file(&recording.path).is_ok() {
+            removed += 1;
+        }
+        let _ = std::fs::remove_file(recording.path.with_extension(PROGRAMME_EXTENSION));
+    }
+    removed
+}
+
+async fn record_stream(client: &Arc<reqwest::Client>, input: &ConfigInput, url: &Url, file: &mut BufWriter<tokio::fs::File>, stop: i64) -> std::io::Result<u64> {
+    let mut written = 0;
+    while chrono::Utc::now().timestamp() < stop {
+        let response = match get_client_request(client, Some(input), url, None).send().await {
+            Ok(response) if response.status().is_success() => response,
+            Ok(response) => {
+                debug!("Archive recording got status {} for {}", response.status(), input.name.as_deref().unwrap_or_default());
+                tokio::time::sleep(Duration::from_secs(RECONNECT_DELAY_SECS)).await;
+                continue;
+            }
+            Err(err) => {
+                debug!("Archive recording failed for {}: {err}", input.name.as_deref().unwrap_or_default());
+                tokio::time::sleep(Duration::from_secs(RECONNECT_DELAY_SECS)).await;
+                continue;
+            }
+        };
+        let remaining = u64::try_from(stop - chrono::Utc::now().timestamp()).unwrap_or(0);
+        let mut stream = response.bytes_stream();
+        // the timeout ends the recording at the end of the programme
+        if let Ok(result) = tokio::time::timeout(Duration::from_secs(remaining), async {
+            while let Some(Ok(chunk)) = stream.next().await {
+                file.write_all(&chunk).await?;
+                written += chunk.len() as u64;
+            }
+            Ok::<(), std::io::Error>(())
+        }).await {
+            result?;
+        }
+    }
+    file.flush().await?;
+    Ok(written)
+}
+
+/// Records the channel until the end of the programme, the finished recording is stored with the programme.
+async fn record_programme(client: Arc<reqwest::Client>, input: ConfigInput, pli: XtreamPlaylistItem, channel_dir: PathBuf,
+                          programme: EpgProgramme, provider_connections: Arc<ProviderConnections>, healthy: bool) {
+    let _guard = match input.connections.as_ref() {
+        Some(connections) => match provider_connections.acquire(input.id, connections, ARCHIVE_USERNAME, 1, healthy) {
+            Some(guard) => Some(guard),
+            None => {
+                warn!("Provider connection limit reached, can't record {}", pli.name);
+                return;
+            }
+        },
+        None => provider_connections.track(input.id, ARCHIVE_USERNAME),
+    };
+    let Ok(url) = Url::parse(&pli.url) else { return; };
+    let input = input.with_stream_headers(&pli.user_agent, &pli.referrer).into_owned();
+    let name = get_recording_name(&programme);
+    let path = channel_dir.join(format!("{name}.{RECORDING_EXTENSION}"));
+    let part_path = channel_dir.join(format!("{name}.{RECORDING_EXTENSION}.{PART_EXTENSION}"));
+    let result = async {
+        tokio::fs::create_dir_all(&channel_dir).await?;
+        let mut file = BufWriter::new(tokio::fs::File::create(&part_path).await?);
+        record_stream(&client, &input, &url, &mut file, programme.stop).await
+    }.await;
+    match result {
+        Ok(written) if written > 0 => {
+            let title = programme.title.clone().unwrap_or_default();
+            let recording_path = path.clone();
+            let stored = tokio::task::spawn_blocking(move || std::fs::rename(&part_path, &recording_path)
+                .and_then(|()| json_write_documents_to_file(&recording_path.with_extension(PROGRAMME_EXTENSION), &programme))).await
+                .unwrap_or_else(|err| Err(std::io::Error::other(err)));
+            if let Err(err) = stored {
+                error!("Failed to store archive recording {}: {err}", path.display());
+            } else {
+                info!("Archived {} {title}", pli.name);
+            }
+        }
+        Ok(_) => {
+            let _ = tokio::fs::remove_file(&part_path).await;
+            warn!("Archive recording of {} received no data", pli.name);
+        }
+        Err(err) => {
+            let _ = tokio::fs::remove_file(&part_path).await;
+            error!("Failed to record {}: {err}", pli.name);
+        }
+    }
+}
+
+/// Schedules the recordings of the programmes which start before the next check, each recording starts
+/// `ARCHIVE_LEAD_SECS` before the programme. A running programme is recorded from now on.
+async fn exec_target_recording(cfg: &Arc<Config>, client: &Arc<reqwest::Client>, provider_connections: &Arc<ProviderConnections>,
+                               target: &ConfigTarget, archive: &CatchupArchiveConfig, scheduled: &Arc<Mutex<HashSet<PathBuf>>>) {
+    let now = chrono::Utc::now().timestamp();
+    let channel_dirs: Vec<PathBuf> = archive.channels.iter().map(|epg_id| get_channel_dir(archive, &target.name, epg_id)).collect();
+    let days = archive.days;
+    let removed = tokio::task::spawn_blocking(move || channel_dirs.iter().map(|channel_dir| purge_recordings(channel_dir, days, now)).sum::<usize>()).await.unwrap_or(0);
+    if removed > 0 {
+        debug!("Removed {removed} expired archive recordings of target {}", target.name);
+    }
+    let Some(epg_path) = get_epg_path_for_target(cfg, target) else { return; };
+    let mut channels = match xtream_get_live_items_by_epg_id(cfg, target, &archive.channels).await {
+        Ok(channels) => channels,
+        Err(err) => {
+            error!("Failed to read archive channels of target {}: {err}", target.name);
+            return;
+        }
+    };
+    let next_check = now + ARCHIVE_CHECK_INTERVAL_SECS.cast_signed() + ARCHIVE_LEAD_SECS;
+    for epg_id in &archive.channels {
+        let Some(pli) = channels.remove(epg_id) else { continue; };
+        let Some(input) = cfg.get_input_by_id(pli.input_id) else { continue; };
+        let channel_dir = get_channel_dir(archive, &target.name, epg_id);
+        for programme in epg_read_channel_programmes(cfg, &epg_path, epg_id, Some(now), Some(next_check)).await {
+            let path = channel_dir.join(format!("{}.{RECORDING_EXTENSION}", get_recording_name(&programme)));
+            if tokio::fs::try_exists(&path).await.unwrap_or(false) {
+                continue;
+            }
+            if !scheduled.lock().is_ok_and(|mut scheduled| scheduled.insert(path.clone())) {
+                continue;
+            }
+            let (client, input, pli, channel_dir, provider_connections, scheduled) =
+                (Arc::clone(client), input.clone(), pli.clone(), channel_dir.clone(), Arc::clone(provider_connections), Arc::clone(scheduled));
+            let health = Arc::clone(&cfg.t_input_health);
+            actix_rt::spawn(async move {
+                let delay = programme.start - ARCHIVE_LEAD_SECS - chrono::Utc::now().timestamp();
+                if delay > 0 {
+                    tokio::time::sleep(Duration::from_secs(delay.cast_unsigned())).await;
+                }
+                let healthy = health.is_healthy(input.id);
+                record_programme(client, input, pli, channel_dir, programme, provider_connections, healthy).await;
+                if let Ok(mut scheduled) = scheduled.lock() {
+                    scheduled.remove(&path);
+                }
+            });
+        }
+    }
+}
+
+/// Records the archive channels of the targets, each programme of the epg is stored as one recording.
+pub fn exec_catchup_recording(cfg: &Arc<Config>, client: &Arc<reqwest::Client>, provider_connections: &Arc<ProviderConnections>) {
+    for target in cfg.sources.iter().flat_map(|source| &source.targets).filter(|target| target.archive.is_some()) {
+        let (cfg, client, provider_connections, target_name) = (Arc::clone(cfg), Arc::clone(client), Arc::clone(provider_connections), target.name.clone());
+        info!("Catchup archive enabled for target {target_name}");
+        actix_rt::spawn(async move {
+            let scheduled = Arc::new(Mutex::new(HashSet::new()));
+            let mut interval = tokio::time::interval(Duration::from_secs(ARCHIVE_CHECK_INTERVAL_SECS));
+            loop {
+                interval.tick().await;
+                let Some(target) = cfg.get_target_by_name(&target_name) else { break; };
+                let Some(archive) = target.archive.as_ref() else { break; };
+                exec_target_recording(&cfg, &client, &provider_connections, target, archive, &scheduled).await;
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use serde_json::json;
+
+    use chrono::FixedOffset;
+
+    use crate::api::catchup_archive::{find_recording, get_catchup_listings, get_channel_dir, limit_catchup_listings, list_recordings, parse_timeshift_start, purge_recordings, ServerTimezone, PROGRAMME_EXTENSION, RECORDING_EXTENSION};
+    use crate::model::config::CatchupArchiveConfig;
+    use crate::repository::epg_repository::EpgProgramme;
+    use crate::utils::json_utils::json_write_documents_to_file;
+
+    fn write_recording(dir: &Path, start: i64, stop: i64, title: &str) {
+        let programme = EpgProgramme { start, stop, title: Some(title.to_string()), desc: None, category: vec![], icon: None };
+        std::fs::write(dir.join(format!("{start}_{stop}.{RECORDING_EXTENSION}")), b"ts").unwrap();
+        json_write_documents_to_file(&dir.join(format!("{start}_{stop}.{PROGRAMME_EXTENSION}")), &programme).unwrap();
+    }
+
+    #[actix_rt::test]
+    async fn catchup_archive_test() {
+        let archive = CatchupArchiveConfig { directory: "/tmp/m3u_filter_archive_test".to_string(), channels: vec!["news.uk".to_string()], days: 1 };
+        let dir = get_channel_dir(&archive, "my target", "news.uk");
+        assert!(dir.ends_with("my_target/news.uk"));
+        let _ = std::fs::remove_dir_all(&archive.directory);
+        std::fs::create_dir_all(&dir).unwrap();
+        write_recording(&dir, 1000, 2000, "Early");
+        write_recording(&dir, 200_000, 201_800, "Late");
+        // unfinished recordings are not listed
+        std::fs::write(dir.join(format!("300000_301800.{RECORDING_EXTENSION}.part")), b"ts").unwrap();
+        assert_eq!(list_recordings(&dir).len(), 2);
+
+        assert_eq!(find_recording(&archive, "my target", "news.uk", 1500).await.unwrap().programme.title.as_deref(), Some("Early"));
+        assert!(find_recording(&archive, "my target", "news.uk", 2000).await.is_none());
+        assert!(find_recording(&archive, "my target", "sports.uk", 1500).await.is_none());
+
+        let listings = get_catchup_listings(&list_recordings(&dir), "news.uk", ServerTimezone::new("UTC"));
+        assert_eq!(listings[1]["title"], "TGF0ZQ==");
+        assert_eq!(listings[0]["start"], "1970-01-01 00:16:40");
+        let listings = get_catchup_listings(&list_recordings(&dir), "news.uk", ServerTimezone::new("+02:00"));
+        assert_eq!(listings[0]["start"], "1970-01-01 02:16:40");
+
+        assert_eq!(purge_recordings(&dir, 1, 200_000), 1);
+        assert_eq!(list_recordings(&dir).len(), 1);
+        let _ = std::fs::remove_dir_all(&archive.directory);
+    }
+
+    #[test]
+    fn parse_timeshift_start_test() {
+        let utc = ServerTimezone::new("UTC");
+        assert_eq!(parse_timeshift_start("1970-01-01:00-16", utc), Some(960));
+        assert_eq!(parse_timeshift_start("1970-01-01:00:16", utc), Some(960));
+        assert_eq!(parse_timeshift_start("1970-01-01 00:16:40", utc), Some(1000));
+        assert_eq!(parse_timeshift_start("yesterday", utc), None);
+        assert_eq!(parse_timeshift_start("1970-01-01:01-16", ServerTimezone::new("+01:00")), Some(960));
+        assert_eq!(parse_timeshift_start("1970-01-01:01-16", ServerTimezone::new("UTC+1")), Some(960));
+        assert!(matches!(ServerTimezone::new("GMT-0530"), ServerTimezone::Fixed(offset) if offset == FixedOffset::west_opt(5 * 3600 + 1800).unwrap()));
+        assert!(matches!(ServerTimezone::new("Europe/Berlin"), ServerTimezone::Local));
+    }
+
+    #[test]
+    fn limit_catchup_listings_test() {
+        let mut listings = vec![json!({"start_timestamp": "1000"}), json!({"start_timestamp": 5000}), json!({"start_timestamp": "x"}), json!({"id": "1"})];
+        limit_catchup_listings(&mut listings, 2000);
+        assert_eq!(listings, vec![json!({"start_timestamp": 5000})]);
+    }
+}
@@ -1,23 +1,34 @@
 use crate::api::model::app_state::AppState;
 use crate::api::model::download::{DownloadQueue, FileDownload, FileDownloadRequest};
-use crate::model::config::VideoDownloadConfig;
-use crate::utils::request_utils;
+use crate::messaging::{send_message, MsgKind};
+use crate::model::config::{DiskSpaceConfig, MessagingConfig, VideoDownloadConfig, VideoDownloadPostProcessConfig};
+use crate::repository::kodi_repository::kodi_style_rename_file;
+use crate::utils::disk_space::check_disk_space;
+use crate::utils::{file_utils, request_utils};
 use actix_web::{web, HttpResponse};
 use async_std::sync::RwLock;
 use futures::stream::TryStreamExt;
 use log::info;
 use serde_json::{json, Value};
+use std::ffi::OsStr;
 use std::fs::File;
 use std::io::{Write};
 use std::ops::Deref;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::{fs};
 use crate::m3u_filter_error::to_io_error;
 
-async fn download_file(active: Arc<RwLock<Option<FileDownload>>>, client: &reqwest::Client) -> Result<(), String> {
+async fn download_file(active: Arc<RwLock<Option<FileDownload>>>, client: &reqwest::Client, disk_space: &DiskSpaceConfig,
+                       messaging: Option<&MessagingConfig>) -> Result<(), String> {
     let file_download = { active.read().await.as_ref().unwrap().clone() };
     match client.get(file_download.url.clone()).send().await {
         Ok(response) => {
+            // the download is not started if the announced size does not fit
+            if let Err(err) = check_disk_space(disk_space, &file_download.file_dir, response.content_length().unwrap_or(0)) {
+                send_message(&MsgKind::Error, messaging, &format!("Download of {} not started: {err}", file_download.filename));
+                return Err(err);
+            }
             match fs::create_dir_all(&file_download.file_dir) {
                 Ok(()) => {
                     if let Some(file_path_str) = file_download.file_path.to_str() {
@@ -61,31 +72,125 @@ async fn download_file(active: Arc<RwLock<Option<FileDownload>>>, client: &reqwe
     }
 }
 
-async fn run_download_queue(download_cfg: &VideoDownloadConfig, download_queue: &Arc<DownloadQueue>) -> Result<(), String> {
+fn file_checksum(path: &Path) -> std::io::Result<String> {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update_reader(File::open(path)?)?;
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+/// Calculates the blake3 checksum if requested or configured and verifies it against the expected checksum.
+fn verify_download_checksum(file_download: &mut FileDownload, calculate: bool) -> Result<(), String> {
+    if calculate || file_download.expected_checksum.is_some() {
+        let checksum = file_checksum(&file_download.file_path)
+            .map_err(|err| format!("Error while calculating checksum for file: {} {err}", file_download.filename))?;
+        if let Some(expected) = &file_download.expected_checksum {
+            if !expected.eq_ignore_ascii_case(&checksum) {
+                return Err(format!("Checksum mismatch for file: {} expected {expected} got {checksum}", file_download.filename));
+            }
+        }
+        file_download.checksum = Some(checksum);
+    }
+    Ok(())
+}
+
+/// Renames the downloaded file kodi style and/or moves it into the library directory.
+fn move_download(download_cfg: &VideoDownloadConfig, post_process: &VideoDownloadPostProcessConfig, file_download: &mut FileDownload) -> Result<(), String> {
+    if !post_process.kodi_style && post_process.library_directory.is_none() {
+        return Ok(());
+    }
+    let mut file_dir = match (&post_process.library_directory, &download_cfg.directory) {
+        (Some(library_dir), _) => PathBuf::from(library_dir),
+        // kodi style has its own directory structure
+        (None, Some(download_dir)) if post_process.kodi_style => PathBuf::from(download_dir),
+        _ => file_download.file_dir.clone(),
+    };
+    let mut filename = file_download.filename.clone();
+    if post_process.kodi_style {
+        let path = Path::new(&file_download.filename);
+        let file_stem = path.file_stem().and_then(OsStr::to_str).unwrap_or_default();
+        let (kodi_dir, kodi_filename) = kodi_style_rename_file(file_stem, post_process.underscore_whitespace);
+        file_dir.push(kodi_dir);
+        filename = match path.extension().and_then(OsStr::to_str) {
+            Some(ext) => format!("{kodi_filename}.{ext}"),
+            None => kodi_filename,
+        };
+    }
+    let file_path = file_dir.join(&filename);
+    if file_path == file_download.file_path {
+        return Ok(());
+    }
+    if file_path.exists() {
+        return Err(format!("Error while moving file, target file already exists: {}", file_path.to_string_lossy()));
+    }
+    fs::create_dir_all(&file_dir).map_err(|err| format!("Error while creating directory for file: {} {err}", file_dir.to_string_lossy()))?;
+    file_utils::rename_or_copy(&file_download.file_path, &file_path, true)
+        .map_err(|err| format!("Error while moving file: {} {err}", file_path.to_string_lossy()))?;
+    info!("Moved {} to {}", file_download.file_path.to_string_lossy(), file_path.to_string_lossy());
+    if download_cfg.organize_into_directories && file_download.file_dir != file_dir {
+        // remove the now empty download sub directory
+        let _ = fs::remove_dir(&file_download.file_dir);
+    }
+    file_download.file_dir = file_dir;
+    file_download.file_path = file_path;
+    file_download.filename = filename;
+    Ok(())
+}
+
+fn post_process_download(download_cfg: &VideoDownloadConfig, file_download: &mut FileDownload) -> Result<(), String> {
+    let post_process = download_cfg.post_process.as_ref();
+    verify_download_checksum(file_download, post_process.is_some_and(|pp| pp.checksum))?;
+    match post_process {
+        Some(post_process) => move_download(download_cfg, post_process, file_download),
+        None => Ok(()),
+    }
+}
+
+fn notify_download(download_cfg: &VideoDownloadConfig, messaging: Option<&MessagingConfig>, file_download: &FileDownload) {
+    if download_cfg.post_process.as_ref().is_some_and(|pp| pp.notify) {
+        match &file_download.error {
+            None => send_message(&MsgKind::Info, messaging, &format!("Download finished: {}", file_download.file_path.to_string_lossy())),
+            Some(err) => send_message(&MsgKind::Error, messaging, &format!("Download failed: {} {err}", file_download.filename)),
+        }
+    }
+}
+
+async fn run_download_queue(download_cfg: &VideoDownloadConfig, disk_space: &DiskSpaceConfig, messaging: Option<&MessagingConfig>,
+                            download_queue: &Arc<DownloadQueue>) -> Result<(), String> {
     let next_download = download_queue.as_ref().queue.lock().await.pop_front();
     if next_download.is_some() {
         { *download_queue.as_ref().active.write().await = next_download; }
         let headers = request_utils::get_request_headers(Some(&download_cfg.headers), None);
         let dq = Arc::clone(download_queue);
+        let download_cfg = download_cfg.clone();
+        let disk_space = disk_space.clone();
+        let messaging = messaging.cloned();
         match reqwest::Client::builder().default_headers(headers).build() {
             Ok(client) => {
                 actix_rt::spawn(async move {
                     loop {
                         if dq.active.read().await.deref().is_some() {
-                            match download_file(Arc::clone(&dq.active), &client).await {
-                                Ok(()) => {
-                                    if let Some(fd) = &mut *dq.active.write().await {
-                                        fd.finished = true;
-                                        dq.finished.write().await.push(fd.clone());
-                                    }
+                            let result = download_file(Arc::clone(&dq.active), &client, &disk_space, messaging.as_ref()).await;
+                            // the checksum and the copy of a move across file systems read the whole file
+                            let (processed, result) = match (result, dq.active.read().await.clone()) {
+                                (Ok(()), Some(mut processed)) => {
+                                    let post_process_cfg = download_cfg.clone();
+                                    tokio::task::spawn_blocking(move || {
+                                        let result = post_process_download(&post_process_cfg, &mut processed);
+                                        (Some(processed), result)
+                                    }).await.unwrap_or_else(|err| (None, Err(format!("Error while post processing download: {err}"))))
                                 }
-                                Err(err) => {
-                                    if let Some(fd) = &mut *dq.active.write().await {
-                                        fd.finished = true;
-                                        fd.error = Some(err);
-                                        dq.finished.write().await.push(fd.clone());
-                                    }
+                                (result, _) => (None, result),
+                            };
+                            if let Some(fd) = &mut *dq.active.write().await {
+                                if let Some(processed) = processed {
+                                    *fd = processed;
+                                }
+                                if let Err(err) = result {
+                                    fd.error = Some(err);
                                 }
+                                fd.finished = true;
+                                notify_download(&download_cfg, messaging.as_ref(), fd);
+                                dq.finished.write().await.push(fd.clone());
                             }
                             *dq.active.write().await = dq.queue.lock().await.pop_front();
                         } else {
@@ -105,7 +210,7 @@ macro_rules! download_info {
     ($file_download:expr) => {
        json!({"uuid": $file_download.uuid, "filename":  $file_download.filename,
        "filesize": $file_download.size, "finished": $file_download.finished,
-       "error": $file_download.error})
+       "error": $file_download.error, "checksum": $file_download.checksum})
     }
 }
 
@@ -118,11 +223,12 @@ pub async fn queue_download_file(
             return HttpResponse::BadRequest().json(json!({"error": "Server config missing video.download.directory configuration"}));
         }
         match FileDownload::new(req.url.as_str(), req.filename.as_str(), download_cfg) {
-            Some(file_download) => {
+            Some(mut file_download) => {
+                file_download.expected_checksum.clone_from(&req.checksum);
                 let response = HttpResponse::Ok().json(download_info!(file_download));
                 app_state.downloads.queue.lock().await.push_back(file_download);
                 if app_state.downloads.active.read().await.is_none() {
-                    match run_download_queue(download_cfg, &app_state.downloads).await {
+                    match run_download_queue(download_cfg, &app_state.config.t_disk_space, app_state.config.messaging.as_ref(), &app_state.downloads).await {
                         Ok(()) => {}
                         Err(err) => return HttpResponse::InternalServerError().json(json!({"error": err})),
                     }
@@ -0,0 +1,234 @@
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::SystemTime;
+
+use actix_web::{HttpRequest, HttpResponse};
+use log::error;
+use url::Url;
+
+use crate::api::api_utils::serve_file;
+use crate::model::config::{ConfigInput, InputType};
+
+const TS_PACKET_SIZE: u64 = 188;
+const TS_SYNC_BYTE: u8 = 0x47;
+// ~3MB, a few seconds of a typical movie
+const HLS_SEGMENT_PACKETS: u64 = 16_384;
+// a segment boundary is searched for the next pes start within this window
+const PES_SEARCH_WINDOW: u64 = 512 * 1024;
+const PTS_CLOCK: f64 = 90_000.0;
+const PTS_WRAP: u64 = 1 << 33;
+const MAX_CACHED_HLS_PLAYLISTS: usize = 256;
+
+/// The hls playlists by file and segment url, a playlist is valid while the file is unchanged.
+/// `None` marks a file which is no mpeg-ts file.
+type HlsPlaylistCache = HashMap<(PathBuf, String), (SystemTime, u64, Option<Arc<String>>)>;
+static HLS_PLAYLISTS: LazyLock<Mutex<HlsPlaylistCache>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// The path of a file of a local input, files outside of the input directory are rejected.
+pub fn get_local_file_path(input: &ConfigInput, url: &str) -> Option<PathBuf> {
+    if input.input_type != InputType::Local {
+        return None;
+    }
+    let path = Url::parse(url).ok()?.to_file_path().ok()?.canonicalize().ok()?;
+    let dir = PathBuf::from(input.url.strip_prefix("file://").unwrap_or(&input.url)).canonicalize().ok()?;
+    path.starts_with(&dir).then_some(path)
+}
+
+fn get_video_mime_type(path: &Path) -> mime::Mime {
+    let mime_type = match path.extension().and_then(|ext| ext.to_str()).map(str::to_lowercase).as_deref() {
+        Some("ts") => "video/mp2t",
+        Some("mp4" | "m4v") => "video/mp4",
+        Some("mkv") => "video/x-matroska",
+        Some("avi") => "video/x-msvideo",
+        _ => return mime::APPLICATION_OCTET_STREAM,
+    };
+    mime_type.parse().unwrap_or(mime::APPLICATION_OCTET_STREAM)
+}
+
+/// The pts of the packet if it starts an audio or video pes with pts.
+fn get_packet_pts(packet: &[u8]) -> Option<u64> {
+    if packet.len() < TS_PACKET_SIZE as usize || packet[0] != TS_SYNC_BYTE || packet[1] & 0x40 == 0 {
+        return None;
+    }
+    let adaptation_field_control = (packet[3] >> 4) & 0x03;
+    if adaptation_field_control & 0x01 == 0 {
+        return None;
+    }
+    let offset = if adaptation_field_control & 0x02 == 0 { 4 } else { 5 + usize::from(packet[4]) };
+    let pes = packet.get(offset..)?;
+    if pes.len() < 14 || pes[0..3] != [0, 0, 1] || !(0xC0..=0xEF).contains(&pes[3]) || pes[7] & 0x80 == 0 {
+        return None;
+    }
+    Some((u64::from(pes[9] >> 1) & 0x07) << 30 | u64::from(pes[10]) << 22 | u64::from(pes[11] >> 1) << 15
+        | u64::from(pes[12]) << 7 | u64::from(pes[13] >> 1))
+}
+
+fn read_window<R: Read + Seek>(reader: &mut R, offset: u64, len: u64) -> std::io::Result<Vec<u8>> {
+    reader.seek(SeekFrom::Start(offset))?;
+    let mut buffer = vec![];
+    reader.take(len).read_to_end(&mut buffer)?;
+    Ok(buffer)
+}
+
+/// The offset and pts of the first pes start at or after the offset.
+fn find_pes_start<R: Read + Seek>(reader: &mut R, offset: u64) -> std::io::Result<Option<(u64, u64)>> {
+    let buffer = read_window(reader, offset, PES_SEARCH_WINDOW)?;
+    Ok(buffer.chunks_exact(TS_PACKET_SIZE as usize).enumerate()
+        .find_map(|(index, packet)| get_packet_pts(packet).map(|pts| (offset + index as u64 * TS_PACKET_SIZE, pts))))
+}
+
+/// The highest pts at the end of the stream, the pts of b-frames are not ordered.
+fn find_last_pts<R: Read + Seek>(reader: &mut R, size: u64) -> std::io::Result<Option<u64>> {
+    let offset = size.saturating_sub(PES_SEARCH_WINDOW) / TS_PACKET_SIZE * TS_PACKET_SIZE;
+    let buffer = read_window(reader, offset, PES_SEARCH_WINDOW)?;
+    Ok(buffer.chunks_exact(TS_PACKET_SIZE as usize).filter_map(get_packet_pts).max())
+}
+
+fn pts_duration(start: u64, end: u64) -> f64 {
+    ((end + PTS_WRAP - start) % PTS_WRAP) as f64 / PTS_CLOCK
+}
+
+/// Packages a mpeg-ts file as hls vod playlist, the segments are byte ranges of the file at `segment_url`.
+/// The segments start at the next pes after each `segment_packets` packets, the durations are taken from the pts.
+/// Returns `None` if the file is no mpeg-ts file.
+fn create_ts_hls_playlist<R: Read + Seek>(reader: &mut R, size: u64, segment_url: &str, segment_packets: u64) -> std::io::Result<Option<String>> {
+    let Some(first) = find_pes_start(reader, 0)? else { return Ok(None) };
+    let mut boundaries = vec![first];
+    let segment_size = segment_packets * TS_PACKET_SIZE;
+    let mut offset = first.0 + segment_size;
+    while offset < size {
+        match find_pes_start(reader, offset)? {
+            Some(boundary) if boundary.0 < size => {
+                offset = boundary.0 + segment_size;
+                boundaries.push(boundary);
+            }
+            _ => break,
+        }
+    }
+    let last_pts = find_last_pts(reader, size)?.unwrap_or(first.1);
+    let mut segments = vec![];
+    for (index, (start, pts)) in boundaries.iter().enumerate() {
+        let (end, end_pts) = boundaries.get(index + 1).copied().unwrap_or((size, last_pts));
+        segments.push((*start, end - start, pts_duration(*pts, end_pts).max(0.001)));
+    }
+    let target_duration = segments.iter().map(|(_, _, duration)| duration.ceil() as u64).max().unwrap_or(1);
+    let mut playlist = format!("#EXTM3U\n#EXT-X-VERSION:4\n#EXT-X-TARGETDURATION:{target_duration}\n#EXT-X-MEDIA-SEQUENCE:0\n#EXT-X-PLAYLIST-TYPE:VOD\n");
+    for (start, length, duration) in segments {
+        let _ = write!(playlist, "#EXTINF:{duration:.3},\n#EXT-X-BYTERANGE:{length}@{start}\n{segment_url}\n");
+    }
+    playlist.push_str("#EXT-X-ENDLIST\n");
+    Ok(Some(playlist))
+}
+
+/// The hls playlist of the file from the cache, a missing or outdated playlist is created.
+fn get_hls_playlist(path: &Path, segment_url: &str) -> std::io::Result<Option<Arc<String>>> {
+    let mut file = File::open(path)?;
+    let metadata = file.metadata()?;
+    let (modified, size) = (metadata.modified()?, metadata.len());
+    let key = (path.to_path_buf(), segment_url.to_string());
+    if let Some((_, _, playlist)) = HLS_PLAYLISTS.lock().ok().and_then(|cache| cache.get(&key).cloned())
+        .filter(|(cached_modified, cached_size, _)| *cached_modified == modified && *cached_size == size) {
+        return Ok(playlist);
+    }
+    let playlist = create_ts_hls_playlist(&mut file, size, segment_url, HLS_SEGMENT_PACKETS)?.map(Arc::new);
+    if let Ok(mut cache) = HLS_PLAYLISTS.lock() {
+        if cache.len() >= MAX_CACHED_HLS_PLAYLISTS && !cache.contains_key(&key) {
+            cache.clear();
+        }
+        cache.insert(key, (modified, size, playlist.clone()));
+    }
+    Ok(playlist)
+}
+
+/// Serves a file of a local input, range requests are supported.
+/// A request with the extension `m3u8` gets a hls playlist of a mpeg-ts file, the segments are requested as `{stream_id}.ts`.
+/// The playlist is created on a blocking thread and cached until the file changes.
+pub async fn serve_local_video(req: &HttpRequest, path: &Path, stream_id: u32, extension: Option<&str>) -> HttpResponse {
+    if extension.is_some_and(|ext| ext.trim_start_matches('.').eq_ignore_ascii_case("m3u8")) {
+        let file_path = path.to_path_buf();
+        let playlist = tokio::task::spawn_blocking(move || get_hls_playlist(&file_path, &format!("{stream_id}.ts"))).await
+            .unwrap_or_else(|err| Err(std::io::Error::other(err)));
+        match playlist {
+            Ok(Some(playlist)) => return HttpResponse::Ok().content_type("application/vnd.apple.mpegurl").body(playlist.to_string()),
+            Ok(None) => {}
+            Err(err) => {
+                error!("Failed to create hls playlist for {}: {err}", path.display());
+                return HttpResponse::InternalServerError().finish();
+            }
+        }
+    }
+    serve_file(path, req, get_video_mime_type(path)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::api::local_vod::{create_ts_hls_playlist, get_hls_playlist, get_packet_pts, TS_PACKET_SIZE};
+
+    fn pes_packet(pts: u64) -> Vec<u8> {
+        let mut packet = vec![0x47, 0x41, 0x00, 0x10, 0, 0, 1, 0xE0, 0, 0, 0x80, 0x80, 5];
+        packet.extend_from_slice(&[
+            0x21 | (((pts >> 30) & 0x07) as u8) << 1,
+            (pts >> 22) as u8,
+            0x01 | ((pts >> 15) as u8) << 1,
+            (pts >> 7) as u8,
+            0x01 | (pts as u8) << 1,
+        ]);
+        packet.resize(TS_PACKET_SIZE as usize, 0xFF);
+        packet
+    }
+
+    fn data_packet() -> Vec<u8> {
+        let mut packet = vec![0x47, 0x01, 0x00, 0x10];
+        packet.resize(TS_PACKET_SIZE as usize, 0xFF);
+        packet
+    }
+
+    #[test]
+    fn ts_hls_playlist_test() {
+        assert_eq!(get_packet_pts(&pes_packet(8_589_934_000)), Some(8_589_934_000));
+        assert_eq!(get_packet_pts(&data_packet()), None);
+
+        // pes every 4 packets, 2 seconds apart
+        let mut content = vec![];
+        for index in 0..12 {
+            content.extend(pes_packet(90_000 * 2 * index));
+            for _ in 0..3 {
+                content.extend(data_packet());
+            }
+        }
+        let size = content.len() as u64;
+        let playlist = create_ts_hls_playlist(&mut Cursor::new(&content), size, "7.ts", 6).unwrap().unwrap();
+        assert_eq!(playlist, "#EXTM3U\n#EXT-X-VERSION:4\n#EXT-X-TARGETDURATION:4\n#EXT-X-MEDIA-SEQUENCE:0\n#EXT-X-PLAYLIST-TYPE:VOD\n\
+            #EXTINF:4.000,\n#EXT-X-BYTERANGE:1504@0\n7.ts\n\
+            #EXTINF:4.000,\n#EXT-X-BYTERANGE:1504@1504\n7.ts\n\
+            #EXTINF:4.000,\n#EXT-X-BYTERANGE:1504@3008\n7.ts\n\
+            #EXTINF:4.000,\n#EXT-X-BYTERANGE:1504@4512\n7.ts\n\
+            #EXTINF:4.000,\n#EXT-X-BYTERANGE:1504@6016\n7.ts\n\
+            #EXTINF:2.000,\n#EXT-X-BYTERANGE:1504@7520\n7.ts\n\
+            #EXT-X-ENDLIST\n");
+
+        let not_ts = vec![0u8; 1000];
+        assert!(create_ts_hls_playlist(&mut Cursor::new(&not_ts), 1000, "7.ts", 6).unwrap().is_none());
+    }
+
+    #[test]
+    fn hls_playlist_cache_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("movie.ts");
+        let mut content = pes_packet(0);
+        content.extend(data_packet());
+        std::fs::write(&path, &content).unwrap();
+        let playlist = get_hls_playlist(&path, "3.ts").unwrap().unwrap();
+        assert!(std::sync::Arc::ptr_eq(&playlist, &get_hls_playlist(&path, "3.ts").unwrap().unwrap()));
+        // a changed file creates a new playlist
+        content.extend(pes_packet(90_000));
+        std::fs::write(&path, &content).unwrap();
+        assert!(!std::sync::Arc::ptr_eq(&playlist, &get_hls_playlist(&path, "3.ts").unwrap().unwrap()));
+    }
+}
@@ -1,32 +1,197 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::sync::{Arc, LazyLock};
+use std::time::SystemTime;
+
+use actix_web::http::header::{self, HeaderValue, HttpDate};
 use actix_web::{web, HttpRequest, HttpResponse};
 use bytes::Bytes;
+use flate2::write::GzEncoder;
+use flate2::Compression;
 use futures::stream;
 use log::{debug, error};
+use serde::Serialize;
 
-use crate::api::api_utils::{get_user_target, get_user_target_by_credentials, resource_response, stream_response};
+use crate::api::local_vod;
+use crate::api::api_utils::{acquire_playlist_permit, get_category_translations, hold_playlist_permit, get_user_target, get_user_target_by_credentials, maintenance_response, resource_response, sign_response, stream_response};
 use crate::api::model::app_state::AppState;
 use crate::api::model::request::UserApiRequest;
-use crate::model::api_proxy::ProxyType;
-use crate::model::config::TargetType;
-use crate::model::playlist::FieldGetAccessor;
-use crate::repository::m3u_playlist_iterator::{M3U_STREAM_PATH, M3U_RESOURCE_PATH};
-use crate::repository::m3u_repository::{m3u_get_file_paths, m3u_get_item_for_stream_id, m3u_load_rewrite_playlist};
-use crate::repository::storage::get_target_storage_path;
-use crate::utils::request_utils::mask_sensitive_info;
+use crate::model::api_proxy::{ProxyType, ProxyUserCredentials};
+use crate::model::config::{Config, ConfigTarget, InputType, OutputEncoding, TargetType};
+use crate::model::playlist::{FieldGetAccessor, M3uPlaylistItem, XtreamCluster};
+use crate::repository::m3u_playlist_iterator::{is_live_stream, M3uPlaylistFilter, M3uPlaylistIterator, M3uPlaylistParams, M3U_STREAM_PATH, M3U_RESOURCE_PATH};
+use crate::repository::m3u_repository::{m3u_get_file_paths, m3u_get_gzip_cache_path, m3u_get_item_for_stream_id, m3u_load_rewrite_playlist, m3u_write_gzip_cache};
+use crate::repository::storage::{get_target_storage_path, hash_string_as_hex};
+use crate::utils::output_encoding::{apply_newline_style, get_bom, get_newline};
+use crate::utils::request_utils::{is_valid_stream_extension, mask_sensitive_info, replace_stream_extension};
+
+static SERVER_START: LazyLock<SystemTime> = LazyLock::new(SystemTime::now);
+
+/// Validators of a playlist response. The etag changes with the stored playlist and with everything
+/// the playlist of the user is rendered with, the last modification is the one of the stored playlist.
+struct PlaylistValidators {
+    etag: String,
+    last_modified: SystemTime,
+    /// The key of the cached gzip rendition, `None` if the playlist contains the credentials of the user.
+    gzip_cache_key: Option<String>,
+}
+
+impl PlaylistValidators {
+    fn quoted_etag(&self) -> String {
+        format!("\"{}\"", self.etag)
+    }
+
+    /// The last modification for the client, the stored playlist or the server start
+    /// because the configuration of the user could have changed.
+    fn last_modified(&self) -> HttpDate {
+        HttpDate::from(self.last_modified.max(*SERVER_START))
+    }
+}
+
+/// No validators for the memory storage and for playlists with the current epg programme or viewers.
+fn get_playlist_validators(cfg: &Config, target: &ConfigTarget, user: &ProxyUserCredentials, api_req: &UserApiRequest,
+                           params: &M3uPlaylistParams) -> Option<PlaylistValidators> {
+    let options = target.options.as_ref();
+    if cfg.get_memory_storage().is_some() || options.is_some_and(|opts| opts.m3u_epg_now_next) || params.trending.is_some() {
+        return None;
+    }
+    let target_path = get_target_storage_path(cfg, target.name.as_str())?;
+    let (m3u_path, _) = m3u_get_file_paths(&target_path);
+    let metadata = std::fs::metadata(m3u_path).ok()?;
+    let last_modified = metadata.modified().ok()?;
+    let modified_nanos = last_modified.duration_since(SystemTime::UNIX_EPOCH).map_or(0, |duration| duration.as_nanos());
+    let translations = params.category_translations.as_ref().map(|translations| translations.iter().collect::<BTreeMap<_, _>>());
+    // the rendition without the user, the same for all users with the same server and proxy type
+    let rendition = format!("{modified_nanos}|{}|{}|{:?}|{}|{}|{}|{}|{}|{}|{}|{:?}|{:?}",
+                            metadata.len(), cfg.get_user_server_info(user).get_base_url(), user.proxy,
+                            options.is_some_and(|opts| opts.m3u_include_type_in_url), options.is_some_and(|opts| opts.m3u_mask_redirect_url),
+                            options.is_some_and(|opts| opts.ignore_logo), api_req.playlist_type, api_req.output, api_req.cluster, api_req.group,
+                            translations, target.get_output_encoding());
+    let fingerprint = format!("{rendition}|{}|{}", user.username, user.password);
+    let gzip_cache_key = (options.is_some_and(|opts| opts.m3u_gzip_cache) && !has_user_credentials(cfg, target, user))
+        .then(|| hash_string_as_hex(&rendition)[..32].to_string());
+    Some(PlaylistValidators { etag: hash_string_as_hex(&fingerprint)[..32].to_string(), last_modified, gzip_cache_key })
+}
+
+/// The urls of reverse proxy users, masked redirects and files of local inputs contain the credentials of the user.
+fn has_user_credentials(cfg: &Config, target: &ConfigTarget, user: &ProxyUserCredentials) -> bool {
+    matches!(user.proxy, ProxyType::Reverse)
+        || target.options.as_ref().is_some_and(|opts| opts.m3u_mask_redirect_url)
+        || cfg.get_inputs_for_target(&target.name).is_none_or(|inputs| inputs.iter().any(|input| input.input_type == InputType::Local))
+}
+
+/// `If-None-Match` is evaluated before `If-Modified-Since`, the dates are compared in seconds.
+fn is_not_modified(if_none_match: Option<&str>, if_modified_since: Option<&str>, etag: &str, last_modified: HttpDate) -> bool {
+    if let Some(if_none_match) = if_none_match {
+        return if_none_match.split(',')
+            .map(|tag| tag.trim().trim_start_matches("W/"))
+            .any(|tag| tag == "*" || tag == etag);
+    }
+    if_modified_since.and_then(|since| since.parse::<HttpDate>().ok())
+        .is_some_and(|since| SystemTime::from(last_modified) <= SystemTime::from(since))
+}
+
+fn accepts_gzip(req: &HttpRequest) -> bool {
+    req.headers().get(header::ACCEPT_ENCODING).and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.split(',').any(|encoding| {
+            let mut parts = encoding.split(';');
+            parts.next().is_some_and(|name| name.trim().eq_ignore_ascii_case("gzip"))
+                && !parts.any(|param| param.trim().replace(' ', "") == "q=0")
+        }))
+}
+
+/// The lines of the playlist with the newline style and the bom of the output encoding.
+fn get_playlist_content(m3u_iter: impl Iterator<Item=String>, encoding: Option<OutputEncoding>) -> impl Iterator<Item=Bytes> {
+    let newline = get_newline(encoding.as_ref());
+    let bom = Bytes::from_static(get_bom(encoding.as_ref()));
+    std::iter::once(bom).filter(|bom| !bom.is_empty())
+        .chain(m3u_iter.map(move |line| Bytes::from([apply_newline_style(&line, encoding.as_ref()).as_bytes(), newline.as_bytes()].concat())))
+}
+
+/// The gzipped playlist, rendered and compressed on a blocking thread. With a cache key the rendition
+/// is read from the cache of the target, a missing rendition is created.
+async fn get_gzip_content(cfg: &Arc<Config>, target: &ConfigTarget, user: &ProxyUserCredentials,
+                          params: M3uPlaylistParams, cache_key: Option<&str>) -> Option<Vec<u8>> {
+    let cache_path = match cache_key {
+        Some(cache_key) => Some(m3u_get_gzip_cache_path(&get_target_storage_path(cfg, target.name.as_str())?, cache_key)),
+        None => None,
+    };
+    let (cfg, target, user) = (Arc::clone(cfg), target.clone(), user.clone());
+    tokio::task::spawn_blocking(move || {
+        if let Some(content) = cache_path.as_ref().and_then(|cache_path| std::fs::read(cache_path).ok()) {
+            return Some(content);
+        }
+        let m3u_iter = match tokio::runtime::Handle::current().block_on(m3u_load_rewrite_playlist(&cfg, &target, &user, params)) {
+            Ok(m3u_iter) => m3u_iter,
+            Err(err) => {
+                error!("{}", mask_sensitive_info(err.to_string().as_str()));
+                return None;
+            }
+        };
+        let mut encoder = GzEncoder::new(Vec::with_capacity(4096), Compression::default());
+        for chunk in get_playlist_content(m3u_iter, target.get_output_encoding().copied()) {
+            encoder.write_all(&chunk).ok()?;
+        }
+        let content = encoder.finish().ok()?;
+        if let Some(cache_path) = cache_path {
+            if let Err(err) = m3u_write_gzip_cache(&cache_path, &content) {
+                error!("Failed to write m3u gzip cache {}: {err}", cache_path.display());
+            }
+        }
+        Some(content)
+    }).await.ok().flatten()
+}
 
 async fn m3u_api(
+    req: &HttpRequest,
     api_req: &UserApiRequest,
     app_state: &AppState,
 ) -> HttpResponse {
-    match get_user_target(api_req, app_state) {
+    match get_user_target(api_req, app_state, req) {
         Some((user, target)) => {
-            match m3u_load_rewrite_playlist(&app_state.config, target, &user).await {
+            let params = M3uPlaylistParams::from_request_params(&api_req.playlist_type, &api_req.output)
+                .with_filter(M3uPlaylistFilter::from_request_params(&api_req.cluster, &api_req.group))
+                .with_category_translations(get_category_translations(&app_state.config, &user, req))
+                .with_trending(api_req.sort.trim().eq_ignore_ascii_case("trending").then(|| app_state.channel_viewers.get_viewers()));
+            let validators = get_playlist_validators(&app_state.config, target, &user, api_req, &params);
+            let mut response_builder = HttpResponse::Ok();
+            if let Some(validators) = validators.as_ref() {
+                let header_value = |name| req.headers().get(name).and_then(|value: &HeaderValue| value.to_str().ok());
+                if is_not_modified(header_value(header::IF_NONE_MATCH), header_value(header::IF_MODIFIED_SINCE),
+                                   &validators.quoted_etag(), validators.last_modified()) {
+                    return HttpResponse::NotModified()
+                        .insert_header((header::ETAG, validators.quoted_etag()))
+                        .insert_header((header::LAST_MODIFIED, validators.last_modified()))
+                        .finish();
+                }
+                response_builder.insert_header((header::ETAG, validators.quoted_etag()))
+                    .insert_header((header::LAST_MODIFIED, validators.last_modified()))
+                    .insert_header((header::CACHE_CONTROL, "no-cache"));
+            }
+            let permit = match acquire_playlist_permit(app_state).await {
+                Ok(permit) => permit,
+                Err(response) => return response,
+            };
+            response_builder.content_type(mime::TEXT_PLAIN_UTF_8);
+            let gzip_validators = validators.as_ref().filter(|_| target.options.as_ref().is_some_and(|opts| opts.m3u_gzip_cache));
+            if let Some(validators) = gzip_validators {
+                response_builder.insert_header((header::VARY, "Accept-Encoding"));
+                if accepts_gzip(req) {
+                    let Some(gzip_content) = get_gzip_content(&app_state.config, target, &user, params, validators.gzip_cache_key.as_deref()).await else {
+                        return HttpResponse::InternalServerError().finish();
+                    };
+                    return sign_response(&app_state.config, response_builder
+                        .insert_header((header::CONTENT_ENCODING, "gzip"))
+                        .body(gzip_content)).await;
+                }
+            }
+            match m3u_load_rewrite_playlist(&app_state.config, target, &user, params).await {
                 Ok(m3u_iter) => {
+                    let content = get_playlist_content(m3u_iter, target.get_output_encoding().copied());
                     // Convert the iterator into a stream of `Bytes`
-                    let content_stream = stream::iter(m3u_iter.map(|line| Ok::<Bytes, String>(Bytes::from([line.as_bytes(), b"\n"].concat()))));
-                    HttpResponse::Ok()
-                        .content_type(mime::TEXT_PLAIN_UTF_8)
-                        .streaming(content_stream)
+                    let content_stream = hold_playlist_permit(stream::iter(content.map(Ok::<Bytes, String>)), permit);
+                    sign_response(&app_state.config, response_builder.streaming(content_stream)).await
                 }
                 Err(err) => {
                     error!("{}", mask_sensitive_info(err.to_string().as_str()));
@@ -38,16 +203,123 @@ async fn m3u_api(
     }
 }
 
-async fn m3u_api_get(api_req: web::Query<UserApiRequest>,
+async fn m3u_api_get(req: HttpRequest,
+                     api_req: web::Query<UserApiRequest>,
                      app_state: web::Data<AppState>,
 ) -> HttpResponse {
-    m3u_api(&api_req.into_inner(), &app_state).await
+    m3u_api(&req, &api_req.into_inner(), &app_state).await
 }
 async fn m3u_api_post(
+    req: HttpRequest,
     api_req: web::Form<UserApiRequest>,
     app_state: web::Data<AppState>,
 ) -> HttpResponse {
-    m3u_api(&api_req.into_inner(), &app_state).await
+    m3u_api(&req, &api_req.into_inner(), &app_state).await
+}
+
+/// Channel of the channel map export for pvr backends.
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+struct ChannelMapEntry {
+    number: u32,
+    name: String,
+    group: String,
+    epg_id: String,
+    logo: String,
+    url: String,
+}
+
+/// Channels without a valid channel number are numbered after the highest channel number.
+fn create_channel_map(items: Vec<M3uPlaylistItem>) -> Vec<ChannelMapEntry> {
+    let numbers: Vec<Option<u32>> = items.iter().map(|item| item.chno.trim().parse::<u32>().ok().filter(|number| *number > 0)).collect();
+    let mut next_number = numbers.iter().flatten().max().copied().unwrap_or(0);
+    items.into_iter().zip(numbers).map(|(item, number)| ChannelMapEntry {
+        number: number.unwrap_or_else(|| {
+            next_number += 1;
+            next_number
+        }),
+        name: item.title.to_string(),
+        group: item.group.to_string(),
+        epg_id: item.epg_channel_id.as_ref().map(ToString::to_string).unwrap_or_default(),
+        logo: item.logo.to_string(),
+        url: item.url.to_string(),
+    }).collect()
+}
+
+fn csv_field(value: &str) -> Cow<'_, str> {
+    if value.contains([',', '"', '\n', '\r']) {
+        Cow::Owned(format!("\"{}\"", value.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(value)
+    }
+}
+
+fn channel_map_to_csv(channels: &[ChannelMapEntry]) -> String {
+    let mut csv = String::from("number,name,group,epg_id,logo,url\r\n");
+    for channel in channels {
+        let fields = [csv_field(&channel.name), csv_field(&channel.group), csv_field(&channel.epg_id), csv_field(&channel.logo), csv_field(&channel.url)];
+        csv.push_str(&format!("{},{}\r\n", channel.number, fields.join(",")));
+    }
+    csv
+}
+
+fn m3u_attribute(value: &str) -> String {
+    value.replace(['"', '\n', '\r'], " ")
+}
+
+/// The m3u of the iptv automatic network of TVHeadend, the channel number is `tvh-chnum`, the group is the channel tag (`|` separates tags).
+fn channel_map_to_tvheadend(channels: &[ChannelMapEntry]) -> String {
+    let mut m3u = String::from("#EXTM3U\n");
+    for channel in channels {
+        m3u.push_str(&format!("#EXTINF:-1 tvg-id=\"{}\" tvg-name=\"{}\" tvg-logo=\"{}\" tvh-chnum=\"{}\" tvh-tags=\"{}\",{}\n{}\n",
+                              m3u_attribute(&channel.epg_id), m3u_attribute(&channel.name), m3u_attribute(&channel.logo), channel.number,
+                              m3u_attribute(&channel.group.replace('|', "/")), channel.name.replace(['\n', '\r'], " "), channel.url));
+    }
+    m3u
+}
+
+/// The m3u of the iptv source of NextPVR, the channel number is `tvg-chno` and `channel-number`.
+fn channel_map_to_nextpvr(channels: &[ChannelMapEntry]) -> String {
+    let mut m3u = String::from("#EXTM3U\n");
+    for channel in channels {
+        m3u.push_str(&format!("#EXTINF:-1 tvg-id=\"{}\" tvg-name=\"{}\" tvg-logo=\"{}\" tvg-chno=\"{}\" channel-number=\"{}\" group-title=\"{}\",{}\n{}\n",
+                              m3u_attribute(&channel.epg_id), m3u_attribute(&channel.name), m3u_attribute(&channel.logo), channel.number, channel.number,
+                              m3u_attribute(&channel.group), channel.name.replace(['\n', '\r'], " "), channel.url));
+    }
+    m3u
+}
+
+/// Live channels of the user playlist with the stream urls of the user, `format=csv` (default), `format=json`,
+/// `format=tvheadend` or `format=nextpvr`.
+async fn m3u_api_channel_map(
+    req: HttpRequest,
+    api_req: web::Query<UserApiRequest>,
+    app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let Some((user, target)) = get_user_target(&api_req, &app_state, &req) else { return HttpResponse::BadRequest().finish() };
+    if !target.has_output(&TargetType::M3u) {
+        return HttpResponse::BadRequest().finish();
+    }
+    let params = M3uPlaylistParams::from_request_params("", &api_req.output)
+        .with_filter(M3uPlaylistFilter { cluster: Some(XtreamCluster::Live), group_prefix: None })
+        .with_category_translations(get_category_translations(&app_state.config, &user, &req));
+    let mut m3u_iter = match M3uPlaylistIterator::new(&app_state.config, target, &user, params).await {
+        Ok(m3u_iter) => m3u_iter,
+        Err(err) => {
+            error!("{}", mask_sensitive_info(err.to_string().as_str()));
+            return HttpResponse::NoContent().finish();
+        }
+    };
+    let channels = create_channel_map(std::iter::from_fn(|| m3u_iter.next_user_item()).collect());
+    let attachment = |suffix: &str| ("Content-Disposition", format!("attachment; filename=\"{}_{suffix}\"", target.name.replace(' ', "_")));
+    match api_req.format.to_lowercase().as_str() {
+        "json" => HttpResponse::Ok().json(channels),
+        "tvheadend" => HttpResponse::Ok().content_type("audio/x-mpegurl; charset=utf-8")
+            .insert_header(attachment("tvheadend.m3u")).body(channel_map_to_tvheadend(&channels)),
+        "nextpvr" => HttpResponse::Ok().content_type("audio/x-mpegurl; charset=utf-8")
+            .insert_header(attachment("nextpvr.m3u")).body(channel_map_to_nextpvr(&channels)),
+        _ => HttpResponse::Ok().content_type("text/csv; charset=utf-8")
+            .insert_header(attachment("channels.csv")).body(channel_map_to_csv(&channels)),
+    }
 }
 
 async fn m3u_api_stream(
@@ -57,8 +329,17 @@ async fn m3u_api_stream(
     app_state: web::Data<AppState>,
 ) -> HttpResponse {
     let (username, password, stream_id) = path.into_inner();
+    // the extension is set for the requested `output` format
+    let (stream_id, stream_ext) = stream_id.rsplit_once('.').map_or((stream_id.as_str(), None), |(id, ext)| (id, Some(ext)));
     let Ok(m3u_stream_id) = stream_id.parse::<u32>() else { return HttpResponse::BadRequest().finish() };
-    let Some((user, target)) = get_user_target_by_credentials(&username, &password, &api_req, &app_state) else { return HttpResponse::BadRequest().finish() };
+    let video_extensions = app_state.config.video.as_ref().map_or(&[][..], |video| video.extensions.as_slice());
+    if stream_ext.is_some_and(|ext| !is_valid_stream_extension(ext, video_extensions)) {
+        return HttpResponse::BadRequest().finish();
+    }
+    let Some((user, target)) = get_user_target_by_credentials(&username, &password, &api_req, &app_state, &req) else { return HttpResponse::BadRequest().finish() };
+    if app_state.config.t_maintenance.is_enabled() {
+        return maintenance_response(&app_state, &req).await;
+    }
 
     if !target.has_output(&TargetType::M3u) {
         return HttpResponse::BadRequest().finish();
@@ -78,13 +359,24 @@ async fn m3u_api_stream(
         }
     };
 
+    let stream_url = match stream_ext {
+        Some(ext) if is_live_stream(m3u_item.item_type) => replace_stream_extension(&m3u_item.url, &format!(".{ext}")),
+        _ => m3u_item.url.to_string(),
+    };
+
+    let input = app_state.config.get_input_by_id(m3u_item.input_id);
+    if let Some(path) = input.and_then(|input| local_vod::get_local_file_path(input, &m3u_item.url)) {
+        debug!("Streaming local file {}", path.display());
+        return local_vod::serve_local_video(&req, &path, m3u_stream_id, stream_ext).await;
+    }
+
     if user.proxy == ProxyType::Redirect {
-        let stream_url = m3u_item.url;
         debug!("Redirecting stream request to {}", mask_sensitive_info(&stream_url));
-        return HttpResponse::Found().insert_header(("Location", stream_url.to_string())).finish();
+        return HttpResponse::Found().insert_header(("Location", stream_url)).finish();
     }
 
-    stream_response(&app_state, m3u_item.url.as_str(), &req, None, m3u_item.item_type, target).await
+    let input = input.map(|input| input.with_stream_headers(&m3u_item.user_agent, &m3u_item.referrer));
+    stream_response(&app_state, &stream_url, &req, input.as_deref(), m3u_item.item_type, Some(target), Some(&user)).await
 }
 
 async fn m3u_api_resource(
@@ -95,7 +387,7 @@ async fn m3u_api_resource(
 ) -> HttpResponse {
     let (username, password, stream_id, resource) = path.into_inner();
     let Ok(m3u_stream_id) = stream_id.parse::<u32>() else { return HttpResponse::BadRequest().finish() };
-    let Some((user, target)) = get_user_target_by_credentials(&username, &password, &api_req, &app_state) else { return HttpResponse::BadRequest().finish() };
+    let Some((user, target)) = get_user_target_by_credentials(&username, &password, &api_req, &app_state, &req) else { return HttpResponse::BadRequest().finish() };
 
     if !target.has_output(&TargetType::M3u) {
         return HttpResponse::BadRequest().finish();
@@ -123,7 +415,7 @@ async fn m3u_api_resource(
                 debug!("Redirecting stream request to {}", mask_sensitive_info(&url));
                 HttpResponse::Found().insert_header(("Location", url.as_str())).finish()
             } else {
-                resource_response(&app_state, url.as_str(), &req, None).await
+                resource_response(&app_state, url.as_str(), &req, app_state.config.get_input_by_id(m3u_item.input_id)).await
             }
         }
     }
@@ -132,7 +424,7 @@ async fn m3u_api_resource(
 macro_rules! register_m3u_stream_routes {
     ($cfg:expr, [$($path:expr),*]) => {{
         $(
-            $cfg.service(web::resource(format!("/{M3U_STREAM_PATH}/{}/{{username}}/{{password}}/{{stream_id}}", $path)).route(web::get().to(m3u_api_stream)));
+            $cfg.service(web::resource(format!("/{M3U_STREAM_PATH}/{}/{{username}}/{{password}}/{{stream_id}}", $path)).route(web::get().to(m3u_api_stream)).route(web::head().to(m3u_api_stream)));
         )*
     }};
 }
@@ -148,6 +440,57 @@ macro_rules! register_m3u_api_routes {
 pub fn m3u_api_register(cfg: &mut web::ServiceConfig) {
     register_m3u_api_routes!(cfg, ["get.php", "apiget", "m3u"]);
     register_m3u_stream_routes!(cfg, ["live", "movie", "series"]);
-    cfg.service(web::resource(format!("/{M3U_STREAM_PATH}/{{username}}/{{password}}/{{stream_id}}")).route(web::get().to(m3u_api_stream)));
+    cfg.service(web::resource("/channelmap.php").route(web::get().to(m3u_api_channel_map)));
+    cfg.service(web::resource(format!("/{M3U_STREAM_PATH}/{{username}}/{{password}}/{{stream_id}}")).route(web::get().to(m3u_api_stream)).route(web::head().to(m3u_api_stream)));
     cfg.service(web::resource(format!("/{M3U_RESOURCE_PATH}/{{username}}/{{password}}/{{stream_id}}/{{resource}}")).route(web::get().to(m3u_api_resource)));
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use actix_web::http::header::HttpDate;
+
+    use crate::api::m3u_api::{channel_map_to_csv, channel_map_to_nextpvr, channel_map_to_tvheadend, create_channel_map, is_not_modified};
+    use crate::model::playlist::M3uPlaylistItem;
+    use crate::model::playlist_test_utils::item as test_item;
+
+    fn item(title: &str, chno: &str) -> M3uPlaylistItem {
+        test_item(title).chno(chno).group("News").url("http://proxy.tv/m3u-stream/u/p/1")
+            .epg_id(Some("news.de")).virtual_id(1).input_id(1).build_m3u()
+    }
+
+    #[test]
+    fn channel_map_test() {
+        let channels = create_channel_map(vec![item("News, Weather", ""), item("Sport", "7"), item("Kids \"HD\"", "0")]);
+        assert_eq!(channels.iter().map(|channel| channel.number).collect::<Vec<_>>(), vec![8, 7, 9]);
+        let csv = channel_map_to_csv(&channels);
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], "number,name,group,epg_id,logo,url");
+        assert_eq!(lines[1], "8,\"News, Weather\",News,news.de,,http://proxy.tv/m3u-stream/u/p/1");
+        assert_eq!(lines[3], "9,\"Kids \"\"HD\"\"\",News,news.de,,http://proxy.tv/m3u-stream/u/p/1");
+    }
+
+    #[test]
+    fn channel_map_pvr_test() {
+        let mut channels = create_channel_map(vec![item("News, Weather", ""), item("Sport", "7"), item("Kids \"HD\"", "0")]);
+        channels[1].group = "Sport | Live".to_string();
+        channels[1].logo = "http://logo.tv/sport.png".to_string();
+        assert_eq!(channel_map_to_tvheadend(&channels), include_str!("../../test/channelmap/tvheadend.m3u"));
+        assert_eq!(channel_map_to_nextpvr(&channels), include_str!("../../test/channelmap/nextpvr.m3u"));
+    }
+
+    #[test]
+    fn not_modified_test() {
+        let last_modified = HttpDate::from(SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000));
+        let since = last_modified.to_string();
+        assert!(is_not_modified(Some("\"abc\""), None, "\"abc\"", last_modified));
+        assert!(is_not_modified(Some("\"x\", W/\"abc\""), None, "\"abc\"", last_modified));
+        assert!(is_not_modified(Some("*"), None, "\"abc\"", last_modified));
+        // the etag wins over the date
+        assert!(!is_not_modified(Some("\"old\""), Some(&since), "\"abc\"", last_modified));
+        assert!(is_not_modified(None, Some(&since), "\"abc\"", last_modified));
+        let before = HttpDate::from(SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000)).to_string();
+        assert!(!is_not_modified(None, Some(&before), "\"abc\"", last_modified));
+        assert!(!is_not_modified(None, None, "\"abc\"", last_modified));
+    }
+}
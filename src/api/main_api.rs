@@ -1,28 +1,45 @@
 use actix_cors::Cors;
 use actix_web::middleware::Logger;
 use actix_web::web::Data;
-use actix_web::{web, App, HttpResponse, HttpServer};
+use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
+use actix_web::http::header;
 use async_std::sync::{Mutex, RwLock};
 use log::{error, info};
-use std::collections::{HashMap, VecDeque};
+use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::io::ErrorKind;
 use std::path::{PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 
 use crate::api::m3u_api::m3u_api_register;
 use crate::api::model::app_state::AppState;
 use crate::api::model::download::DownloadQueue;
-use crate::api::scheduler::start_scheduler;
+use crate::api::model::provider_connections::ProviderConnections;
+use crate::api::model::user_connections::UserConnections;
+use crate::model::channel_viewers::ChannelViewers;
+use crate::api::scheduler::{start_input_scheduler, start_scheduler};
+use crate::utils::download::InputSnapshotKind;
 use crate::api::v1_api::v1_api_register;
+use crate::api::model::diagnostics::DiagnosticsBuffer;
+use crate::api::model::stream_trace::StreamTraces;
+use crate::repository::channel_notes_repository::ChannelNotes;
+use crate::repository::user_repository::{UserActivities, UserEpgOverrides};
 use crate::api::web_index::index_register;
 use crate::api::xmltv_api::xmltv_api_register;
+use crate::api::stalker_api::stalker_api_register;
 use crate::api::xtream_api::xtream_api_register;
+use crate::auth::password::generate_random_string;
 use crate::model::config::{validate_targets, Config, ProcessTargets, ScheduleConfig};
 use crate::model::healthcheck::Healthcheck;
-use crate::processing::playlist_processor;
-use crate::utils::lru_cache::{LRUResourceCache};
+use crate::model::preview_user::PreviewUsers;
+use crate::api::catchup_archive::exec_catchup_recording;
+use crate::model::short_link::{ShortLinks, SHORT_LINK_FLUSH_INTERVAL_SECS};
+use crate::processing::{playlist_processor, trial_user};
 use crate::utils::size_utils::human_readable_byte_size;
 use crate::utils::sys;
+use crate::utils::request_limiter::RequestLimiter;
+use crate::utils::metrics::{MetricType, MetricsWriter};
+use crate::repository::storage::hash_string_as_hex;
 use crate::VERSION;
 
 fn get_web_dir_path(web_ui_enabled: bool, web_root: &str) -> Result<PathBuf, std::io::Error> {
@@ -45,16 +62,51 @@ async fn healthcheck() -> HttpResponse {
     })
 }
 
-fn create_shared_data(cfg: &Arc<Config>) -> Data<AppState> {
-    let lru_cache = cfg.reverse_proxy.as_ref().and_then(|r| r.cache.as_ref()).and_then(|c| if c.enabled  {
-        Some(Mutex::new(LRUResourceCache::new(c.t_size, &PathBuf::from(c.dir.as_ref().unwrap()))))
-    } else { None} );
-    let cache = Arc::new(lru_cache);
+/// Short links are redirected to the long url, expired and revoked links are not found.
+async fn short_link(path: web::Path<String>, app_state: web::Data<AppState>) -> HttpResponse {
+    match app_state.short_links.resolve(&path.into_inner()) {
+        Some(url) => HttpResponse::Found().insert_header(("Location", url)).finish(),
+        None => HttpResponse::NotFound().finish(),
+    }
+}
+
+/// Prometheus metrics of the connections, the resource cache, the stream reconnects and the processing.
+/// With `metrics_token` the request needs the bearer token, without the usernames are hashed.
+async fn metrics(req: HttpRequest, app_state: web::Data<AppState>) -> HttpResponse {
+    let metrics_token = app_state.config.api.metrics_token.as_deref();
+    if let Some(token) = metrics_token {
+        let bearer = req.headers().get(header::AUTHORIZATION).and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        // the hashes are compared to not leak the token length or prefix through the timing
+        if bearer.is_none_or(|bearer| hash_string_as_hex(bearer.trim()) != hash_string_as_hex(token)) {
+            return HttpResponse::Unauthorized().insert_header((header::WWW_AUTHENTICATE, "Bearer")).finish();
+        }
+    }
+    let user_label = |username: String| if metrics_token.is_some() { username } else { hash_string_as_hex(&username)[..12].to_string() };
+    let mut writer = MetricsWriter::default();
+    let user_connections = app_state.user_connections.get_connections();
+    writer.write("client_connections", "Active client streams per user.", MetricType::Gauge, "user",
+                 user_connections.into_iter().map(|(username, streams)| (user_label(username), streams)).collect::<BTreeMap<_, _>>());
+    let provider_connections: BTreeMap<String, u32> = app_state.provider_connections.get_streams().into_iter()
+        .map(|(input_id, users)| {
+            let input_name = app_state.config.get_input_by_id(input_id).and_then(|input| input.name.clone()).unwrap_or_else(|| input_id.to_string());
+            (input_name, users.values().map(|streams| u32::from(*streams)).sum())
+        })
+        .collect();
+    writer.write("provider_connections", "Active provider streams per input.", MetricType::Gauge, "input", provider_connections);
+    writer.write_counters();
+    HttpResponse::Ok().content_type("text/plain; version=0.0.4").body(writer.finish())
+}
+
+pub(crate) fn create_shared_data(cfg: &Arc<Config>) -> Data<AppState> {
+    // the cache is shared with the processing for prefetching
+    let cache = Arc::clone(&cfg.t_resource_cache);
     let cache_scanner = Arc::clone(&cache);
     actix_rt::spawn(async move {
         if let Some(m) = cache_scanner.as_ref() {
             let mut c = m.lock().await;
-            if let Err(err) = (*c).scan().await {
+            // no download is running yet, all partial files are left over
+            if let Err(err) = (*c).scan(Duration::ZERO).await {
                 error!("Failed to scan cache {err}");
             }
         }
@@ -69,6 +121,19 @@ fn create_shared_data(cfg: &Arc<Config>) -> Data<AppState> {
         shared_streams: Arc::new(Mutex::new(HashMap::new())),
         http_client: Arc::new(reqwest::Client::new()),
         cache,
+        diagnostics: Arc::new(Mutex::new(DiagnosticsBuffer::default())),
+        user_bouquets: Arc::clone(&cfg.t_user_bouquets),
+        provider_connections: Arc::new(ProviderConnections::default()),
+        user_connections: Arc::new(UserConnections::default()),
+        channel_viewers: Arc::new(ChannelViewers::default()),
+        stream_traces: Arc::new(StreamTraces::default()),
+        short_links: Arc::new(ShortLinks::new(&cfg.working_dir)),
+        user_epg_overrides: Arc::new(UserEpgOverrides::new(&cfg.working_dir)),
+        user_activities: Arc::new(UserActivities::new(&cfg.working_dir)),
+        preview_users: Arc::new(PreviewUsers::new(&cfg.working_dir)),
+        channel_notes: Arc::new(ChannelNotes::new(&cfg.working_dir)),
+        playlist_limiter: cfg.api.playlist_limit.as_ref().map(|limit| Arc::new(RequestLimiter::new(limit))),
+        playback_secret: generate_random_string(64),
     })
 }
 
@@ -95,7 +160,7 @@ fn get_process_targets(cfg: &Arc<Config>, process_targets: &Arc<ProcessTargets>,
                 .copied()
                 .collect();
             let targets: Vec<u16> = user_targets.targets.iter()
-                .filter(|&id| process_targets.inputs.contains(id))
+                .filter(|&id| process_targets.targets.contains(id))
                 .copied()
                 .collect();
             return Arc::new(ProcessTargets {
@@ -108,6 +173,20 @@ fn get_process_targets(cfg: &Arc<Config>, process_targets: &Arc<ProcessTargets>,
     Arc::clone(process_targets)
 }
 
+/// The targets of a global schedule. A schedule without targets processes the enabled targets
+/// which don't have their own schedule, `None` if there is no such target.
+fn get_schedule_targets(cfg: &Config, schedule_targets: Option<&Vec<String>>) -> Option<Option<Vec<String>>> {
+    if schedule_targets.is_some() {
+        return Some(schedule_targets.cloned());
+    }
+    let enabled_targets = || cfg.sources.iter().flat_map(|source| &source.targets).filter(|target| target.enabled);
+    if enabled_targets().all(|target| target.schedule.is_none()) {
+        return Some(None);
+    }
+    let names: Vec<String> = enabled_targets().filter(|target| target.schedule.is_none()).map(|target| target.name.clone()).collect();
+    if names.is_empty() { None } else { Some(Some(names)) }
+}
+
 fn exec_scheduler(client: &Arc<reqwest::Client>, cfg: &Arc<Config>, targets: &Arc<ProcessTargets>) {
     let schedules: Vec<ScheduleConfig> = if let Some(schedules) = &cfg.schedules {
         schedules.clone()
@@ -116,13 +195,74 @@ fn exec_scheduler(client: &Arc<reqwest::Client>, cfg: &Arc<Config>, targets: &Ar
     };
     for schedule in schedules {
         let expression = schedule.schedule.to_string();
-        let exec_targets = get_process_targets(cfg, targets, schedule.targets.as_ref());
+        let Some(schedule_targets) = get_schedule_targets(cfg, schedule.targets.as_ref()) else {
+            info!("Schedule {expression} skipped, all targets have their own schedule");
+            continue;
+        };
+        let exec_targets = get_process_targets(cfg, targets, schedule_targets.as_ref());
         let cfg_clone = Arc::clone(cfg);
         let http_client = Arc::clone(client);
         actix_rt::spawn(async move {
             start_scheduler(http_client, expression.as_str(), cfg_clone, exec_targets).await;
         });
     }
+    // each target with its own schedule is processed alone
+    for target in cfg.sources.iter().flat_map(|source| &source.targets).filter(|target| target.enabled) {
+        if let Some(expression) = target.schedule.clone() {
+            let exec_targets = get_process_targets(cfg, targets, Some(&vec![target.name.clone()]));
+            let cfg_clone = Arc::clone(cfg);
+            let http_client = Arc::clone(client);
+            actix_rt::spawn(async move {
+                start_scheduler(http_client, expression.as_str(), cfg_clone, exec_targets).await;
+            });
+        }
+    }
+    for input in cfg.sources.iter().flat_map(|source| &source.inputs).filter(|input| input.enabled) {
+        if let Some(schedule) = &input.schedule {
+            for (expression, kind) in [(&schedule.playlist, InputSnapshotKind::Playlist), (&schedule.epg, InputSnapshotKind::Epg)] {
+                if let Some(expression) = expression.clone() {
+                    let input_id = input.id;
+                    let cfg_clone = Arc::clone(cfg);
+                    let http_client = Arc::clone(client);
+                    actix_rt::spawn(async move {
+                        start_input_scheduler(http_client, expression.as_str(), cfg_clone, input_id, kind).await;
+                    });
+                }
+            }
+        }
+    }
+}
+
+/// Writes the counted short link hits periodically.
+fn exec_short_link_flush(short_links: &Arc<ShortLinks>) {
+    let short_links = Arc::clone(short_links);
+    actix_rt::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(SHORT_LINK_FLUSH_INTERVAL_SECS));
+        loop {
+            interval.tick().await;
+            let links = Arc::clone(&short_links);
+            let _ = tokio::task::spawn_blocking(move || links.flush()).await;
+        }
+    });
+}
+
+/// Disables the expired trial users every minute.
+fn exec_trial_user_expiry(cfg: &Arc<Config>) {
+    let cfg_clone = Arc::clone(cfg);
+    actix_rt::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(60));
+        loop {
+            interval.tick().await;
+            match trial_user::exec_trial_user_expiry(&cfg_clone) {
+                Ok(disabled) => {
+                    if !disabled.is_empty() {
+                        info!("Disabled expired trial users: {}", disabled.join(", "));
+                    }
+                }
+                Err(err) => error!("Failed to disable expired trial users {err}"),
+            }
+        }
+    });
 }
 
 fn is_web_auth_enabled(cfg: &Arc<Config>, web_ui_enabled: bool) -> bool {
@@ -147,38 +287,86 @@ pub async fn start_server(cfg: Arc<Config>, targets: Arc<ProcessTargets>) -> fut
         info!("Web root: {:?}", &web_dir_path);
     }
     let shared_data = create_shared_data(&cfg);
+    let short_links = Arc::clone(&shared_data.short_links);
+    let user_activities = Arc::clone(&shared_data.user_activities);
 
     exec_scheduler(&Arc::clone(&shared_data.http_client), &cfg, &targets);
     exec_update_on_boot(Arc::clone(&shared_data.http_client), &cfg, &targets);
+    exec_short_link_flush(&short_links);
+    exec_trial_user_expiry(&cfg);
+    exec_catchup_recording(&cfg, &shared_data.http_client, &shared_data.provider_connections);
     let web_auth_enabled = is_web_auth_enabled(&cfg, web_ui_enabled);
+    let web_ui_path = cfg.web_ui.as_ref().map_or_else(String::new, |web_ui| web_ui.base_path().to_string());
 
+    let http_workers = cfg.get_http_workers();
+    let metrics_enabled = cfg.api.metrics;
     // Web Server
-    HttpServer::new(move || {
+    let server = HttpServer::new(move || {
         App::new()
             .wrap(Logger::default())
             .wrap(Cors::default()
                 .supports_credentials()
                 .allow_any_origin()
-                .allowed_methods(vec!["GET", "POST", "OPTIONS", "HEAD"])
+                .allowed_methods(vec!["GET", "POST", "PUT", "DELETE", "OPTIONS", "HEAD"])
                 .allow_any_header()
                 .max_age(3600))
             .app_data(shared_data.clone())
             // .wrap(Condition::new(web_auth_enabled, ErrorHandlers::new().handler(StatusCode::UNAUTHORIZED, handle_unauthorized)))
             .configure(|srvcfg| {
                 if web_ui_enabled {
-                    srvcfg.service(actix_files::Files::new("/static", web_dir_path.join("static")));
-                    srvcfg.configure(v1_api_register(web_auth_enabled));
+                    srvcfg.configure(v1_api_register(web_auth_enabled, &web_ui_path));
                 }
                 srvcfg.service(web::resource("/healthcheck").route(web::get().to(healthcheck)));
                 srvcfg.service(web::resource("/status").route(web::get().to(healthcheck)));
+                srvcfg.service(web::resource("/s/{id}").route(web::get().to(short_link)));
+                if metrics_enabled {
+                    srvcfg.service(web::resource("/metrics").route(web::get().to(metrics)));
+                }
             })
             .configure(xtream_api_register)
             .configure(m3u_api_register)
             .configure(xmltv_api_register)
+            .configure(stalker_api_register)
             .configure(|srvcfg| {
                 if web_ui_enabled {
-                    srvcfg.configure(index_register(&web_dir_path));
+                    srvcfg.configure(index_register(&web_ui_path));
                 }
             })
-    }).bind(format!("{host}:{port}"))?.run().await
+    });
+    let server = match http_workers {
+        Some(workers) => server.workers(workers),
+        None => server,
+    };
+    let result = server.bind(format!("{host}:{port}"))?.run().await;
+    user_activities.flush();
+    short_links.flush();
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::main_api::get_schedule_targets;
+    use crate::model::config::{Config, ConfigSource, ConfigTarget};
+
+    fn target(name: &str, schedule: Option<&str>) -> ConfigTarget {
+        ConfigTarget { name: name.to_string(), enabled: true, schedule: schedule.map(ToString::to_string), ..Default::default() }
+    }
+
+    #[test]
+    fn schedule_targets_test() {
+        let mut cfg = Config {
+            sources: vec![ConfigSource { inputs: vec![], targets: vec![target("live", None), target("vod", None)] }],
+            ..Default::default()
+        };
+        assert_eq!(get_schedule_targets(&cfg, None), Some(None));
+        let explicit = vec!["vod".to_string()];
+        assert_eq!(get_schedule_targets(&cfg, Some(&explicit)), Some(Some(explicit.clone())));
+
+        // the targets with their own schedule are not processed by the global schedule
+        cfg.sources[0].targets[1].schedule = Some("0  0  3  *  *  *  *".to_string());
+        assert_eq!(get_schedule_targets(&cfg, None), Some(Some(vec!["live".to_string()])));
+        assert_eq!(get_schedule_targets(&cfg, Some(&explicit)), Some(Some(explicit)));
+        cfg.sources[0].targets[0].schedule = Some("0  0  */2  *  *  *  *".to_string());
+        assert_eq!(get_schedule_targets(&cfg, None), None);
+    }
 }
@@ -5,7 +5,10 @@ mod v1_api;
 mod xtream_api;
 mod m3u_api;
 mod xmltv_api;
+mod stalker_api;
 mod scheduler;
+mod catchup_archive;
+mod local_vod;
 mod web_index;
 
 pub(crate) mod model;
\ No newline at end of file
@@ -1,10 +1,21 @@
 use std::collections::HashMap;
 use std::sync::Arc;
 use async_std::sync::{Mutex};
+use crate::api::model::diagnostics::DiagnosticsBuffer;
 use crate::api::model::download::DownloadQueue;
+use crate::api::model::provider_connections::ProviderConnections;
+use crate::api::model::user_connections::UserConnections;
+use crate::model::channel_viewers::ChannelViewers;
 use crate::api::model::shared_stream::SharedStream;
+use crate::api::model::stream_trace::StreamTraces;
 use crate::model::config::{Config};
+use crate::repository::user_repository::UserBouquets;
+use crate::model::preview_user::PreviewUsers;
+use crate::model::short_link::ShortLinks;
+use crate::repository::channel_notes_repository::ChannelNotes;
+use crate::repository::user_repository::{UserActivities, UserEpgOverrides};
 use crate::utils::lru_cache::LRUResourceCache;
+use crate::utils::request_limiter::RequestLimiter;
 
 type SharedStreamState = (Vec<(String, String)>, SharedStream);
 
@@ -13,5 +24,19 @@ pub struct AppState {
     pub downloads: Arc<DownloadQueue>,
     pub shared_streams: Arc<Mutex<HashMap<String, SharedStreamState>>>,
     pub http_client: Arc<reqwest::Client>,
-    pub cache: Arc<Option<Mutex<LRUResourceCache>>>
+    pub cache: Arc<Option<Mutex<LRUResourceCache>>>,
+    pub diagnostics: Arc<Mutex<DiagnosticsBuffer>>,
+    pub user_bouquets: Arc<UserBouquets>,
+    pub provider_connections: Arc<ProviderConnections>,
+    pub user_connections: Arc<UserConnections>,
+    pub channel_viewers: Arc<ChannelViewers>,
+    pub stream_traces: Arc<StreamTraces>,
+    pub short_links: Arc<ShortLinks>,
+    pub user_epg_overrides: Arc<UserEpgOverrides>,
+    pub user_activities: Arc<UserActivities>,
+    pub preview_users: Arc<PreviewUsers>,
+    pub channel_notes: Arc<ChannelNotes>,
+    pub playlist_limiter: Option<Arc<RequestLimiter>>,
+    // signs the playback tokens of the web ui player, tokens are invalid after a restart
+    pub playback_secret: String,
 }
@@ -0,0 +1,164 @@
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+use crate::model::api_proxy::ProxyUserCredentials;
+use crate::utils::request_utils::mask_sensitive_info;
+
+const DIAGNOSTICS_CAPACITY: usize = 500;
+// server events within this window before a player report are correlated with it
+const CORRELATION_WINDOW_SECS: i64 = 300;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DiagnosticSource {
+    Server,
+    Player,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DiagnosticEvent {
+    pub id: u64,
+    pub timestamp: i64,
+    pub source: DiagnosticSource,
+    pub url: String,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub channel: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub player_state: Option<String>,
+    #[serde(skip)]
+    t_urls: Vec<String>,
+}
+
+impl DiagnosticEvent {
+    fn matches_url(&self, url: &str) -> bool {
+        self.t_urls.iter().filter(|event_url| !event_url.is_empty()).any(|event_url| url == event_url || url.ends_with(event_url.as_str()))
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PlayerErrorReport {
+    pub url: String,
+    #[serde(default)]
+    pub channel: Option<String>,
+    #[serde(default)]
+    pub error_code: Option<String>,
+    #[serde(default)]
+    pub player_state: Option<String>,
+    #[serde(default)]
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PlayerErrorResponse {
+    pub id: u64,
+    pub correlated: Vec<DiagnosticEvent>,
+}
+
+/// Ring buffer of the latest stream errors reported by the server and the players.
+pub struct DiagnosticsBuffer {
+    events: VecDeque<DiagnosticEvent>,
+    capacity: usize,
+    next_id: u64,
+}
+
+impl Default for DiagnosticsBuffer {
+    fn default() -> Self {
+        Self::new(DIAGNOSTICS_CAPACITY)
+    }
+}
+
+impl DiagnosticsBuffer {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            events: VecDeque::with_capacity(capacity),
+            capacity,
+            next_id: 1,
+        }
+    }
+
+    fn push(&mut self, mut event: DiagnosticEvent) -> u64 {
+        event.id = self.next_id;
+        self.next_id += 1;
+        if self.events.len() >= self.capacity {
+            self.events.pop_front();
+        }
+        let id = event.id;
+        self.events.push_back(event);
+        id
+    }
+
+    /// Records a server side stream error. The request path is kept to match reports of players using the proxy url.
+    pub fn add_stream_error(&mut self, stream_url: &str, request_path: &str, message: &str) {
+        self.push(DiagnosticEvent {
+            id: 0,
+            timestamp: chrono::Utc::now().timestamp(),
+            source: DiagnosticSource::Server,
+            url: mask_sensitive_info(stream_url),
+            message: message.to_string(),
+            channel: None,
+            error_code: None,
+            player_state: None,
+            t_urls: vec![stream_url.to_string(), request_path.to_string()],
+        });
+    }
+
+    /// Records the player error and returns the server events for the same stream within the correlation window.
+    pub fn add_player_error(&mut self, report: PlayerErrorReport) -> PlayerErrorResponse {
+        let timestamp = chrono::Utc::now().timestamp();
+        let correlated = self.events.iter()
+            .filter(|event| event.source == DiagnosticSource::Server
+                && timestamp - event.timestamp <= CORRELATION_WINDOW_SECS
+                && event.matches_url(&report.url))
+            .cloned()
+            .collect();
+        let id = self.push(DiagnosticEvent {
+            id: 0,
+            timestamp,
+            source: DiagnosticSource::Player,
+            url: mask_sensitive_info(&report.url),
+            message: report.message.unwrap_or_default(),
+            channel: report.channel,
+            error_code: report.error_code,
+            player_state: report.player_state,
+            t_urls: vec![report.url],
+        });
+        PlayerErrorResponse { id, correlated }
+    }
+
+    pub fn get_events(&self) -> Vec<DiagnosticEvent> {
+        self.events.iter().cloned().collect()
+    }
+
+    /// Removes the events with urls of the user, returns the count of removed events.
+    pub fn purge_user(&mut self, user: &ProxyUserCredentials) -> usize {
+        let count = self.events.len();
+        self.events.retain(|event| !event.t_urls.iter().any(|url| user.is_referenced_by(url)));
+        count - self.events.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::model::diagnostics::{DiagnosticsBuffer, PlayerErrorReport};
+
+    fn report(url: &str) -> PlayerErrorReport {
+        PlayerErrorReport { url: url.to_string(), channel: Some("Channel 1".to_string()), error_code: Some("404".to_string()), player_state: None, message: None }
+    }
+
+    #[test]
+    fn correlation_test() {
+        let mut buffer = DiagnosticsBuffer::new(3);
+        buffer.add_stream_error("http://provider.tv/live/1.ts", "/live/user/pass/1.ts", "Provider responded with status 404");
+        buffer.add_stream_error("http://provider.tv/live/2.ts", "/live/user/pass/2.ts", "Cant open stream");
+        assert_eq!(buffer.add_player_error(report("http://proxy.tv:8901/live/user/pass/1.ts")).correlated.len(), 1);
+        assert_eq!(buffer.add_player_error(report("http://provider.tv/live/2.ts")).correlated.len(), 1);
+        assert!(buffer.add_player_error(report("http://provider.tv/live/3.ts")).correlated.is_empty());
+        // capacity exceeded, oldest events are dropped
+        assert_eq!(buffer.get_events().len(), 3);
+        assert_eq!(buffer.get_events()[0].id, 3);
+    }
+}
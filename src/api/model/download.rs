@@ -29,6 +29,10 @@ pub struct FileDownload {
     pub size: u64,
     /// Optional error if something goes wrong during downloading.
     pub error: Option<String>,
+    /// Optional expected blake3 checksum (hex) of the downloaded file.
+    pub expected_checksum: Option<String>,
+    /// blake3 checksum (hex) of the downloaded file, if post processing `checksum` is enabled.
+    pub checksum: Option<String>,
 }
 
 /// Returns the directory for th file download.
@@ -106,6 +110,8 @@ impl FileDownload {
                     finished: false,
                     size: 0,
                     error: None,
+                    expected_checksum: None,
+                    checksum: None,
                 })
             }
             Err(_) => None
@@ -124,6 +130,8 @@ pub struct DownloadQueue {
 pub struct FileDownloadRequest {
     pub url: String,
     pub filename: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<String>,
 }
 
 impl From<web::Json<Self>> for FileDownloadRequest {
@@ -5,6 +5,9 @@ pub mod xtream;
 pub mod app_state;
 pub mod shared_stream;
 pub mod provider_stream;
+pub mod relay_stream;
+pub mod provider_connections;
+pub mod user_connections;
 pub mod persist_pipe_stream;
 pub mod provider_stream_factory;
 mod buffered_stream;
@@ -12,3 +15,6 @@ pub mod model_utils;
 mod client_stream;
 pub mod stream_error;
 mod broadcast_stream;
+pub mod diagnostics;
+pub mod stream_trace;
+pub mod timeout_stream;
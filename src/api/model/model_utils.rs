@@ -6,13 +6,60 @@ use std::collections::{HashSet};
 use std::str::FromStr;
 use crate::utils::request_utils::mask_sensitive_info;
 
-const MEDIA_STREAM_HEADERS: &[&str] = &["content-type", "content-length", "connection", "accept-ranges", "content-range", "vary", "transfer-encoding", "access-control-allow-credentials"];
+const MEDIA_STREAM_HEADERS: &[&str] = &["content-type", "content-length", "accept-ranges", "content-range", "vary", "access-control-allow-credentials", "set-cookie"];
+// headers of the single connection to the provider, they are not forwarded (RFC 9110 7.6.1)
+const HOP_BY_HOP_HEADERS: &[&str] = &["connection", "keep-alive", "proxy-connection", "proxy-authenticate", "proxy-authorization", "te", "trailer", "transfer-encoding", "upgrade"];
+// headers with one value, only the first value is forwarded
+const SINGLE_VALUE_HEADERS: &[&str] = &["content-type", "content-length", "accept-ranges", "content-range", "access-control-allow-credentials"];
+// ICY (SHOUTcast) response headers like icy-metaint, icy-name, icy-genre, icy-br
+const ICY_HEADER_PREFIX: &str = "icy-";
+/// Request header, if set the provider interleaves stream title updates every `icy-metaint` bytes.
+pub const ICY_METADATA_HEADER: &str = "icy-metadata";
+
+fn is_media_stream_header(key: &str) -> bool {
+    MEDIA_STREAM_HEADERS.contains(&key) || key.starts_with(ICY_HEADER_PREFIX)
+}
+
+/// The provider response headers forwarded to the client. The names are lower case, hop-by-hop headers
+/// and the headers listed in `connection` are removed, repeated headers keep all their values in order.
+/// A `content-length` is dropped if the body is transfer encoded or the lengths are conflicting.
+fn filter_response_headers<'a>(headers: impl Iterator<Item=(&'a str, &'a str)>) -> Vec<(String, String)> {
+    let headers: Vec<(String, &str)> = headers.map(|(key, value)| (key.trim().to_lowercase(), value.trim())).collect();
+    let connection_headers: HashSet<String> = headers.iter().filter(|(key, _)| key == "connection")
+        .flat_map(|(_, value)| value.split(',').map(|name| name.trim().to_lowercase())).collect();
+    let transfer_encoded = headers.iter().any(|(key, _)| key == "transfer-encoding");
+    let content_lengths: HashSet<&str> = headers.iter().filter(|(key, _)| key == "content-length")
+        .flat_map(|(_, value)| value.split(',').map(str::trim)).collect();
+    let valid_content_length = !transfer_encoded && content_lengths.len() == 1
+        && content_lengths.iter().all(|length| length.parse::<u64>().is_ok());
+
+    let mut forwarded: Vec<(String, String)> = Vec::with_capacity(headers.len());
+    for (key, value) in headers {
+        if HOP_BY_HOP_HEADERS.contains(&key.as_str()) || connection_headers.contains(&key) || !is_media_stream_header(&key) {
+            continue;
+        }
+        if key == "content-length" {
+            if !valid_content_length {
+                continue;
+            }
+            // repeated equal lengths like `100, 100` are sent as one length
+            let length = value.split(',').next().unwrap_or(value).trim().to_string();
+            if !forwarded.iter().any(|(name, _)| name == &key) {
+                forwarded.push((key, length));
+            }
+            continue;
+        }
+        if SINGLE_VALUE_HEADERS.contains(&key.as_str()) && forwarded.iter().any(|(name, _)| name == &key) {
+            continue;
+        }
+        forwarded.push((key, value.to_string()));
+    }
+    forwarded
+}
 
 pub fn get_response_headers(response: &mut Response) -> Vec<(String, String)> {
-    let response_headers: Vec<(String, String)> = response.headers_mut().iter()
-        .filter(|(key, _)| MEDIA_STREAM_HEADERS.contains(&key.as_str()))
-        .map(|(key, value)| (key.to_string(), value.to_str().unwrap().to_string())).collect();
-    response_headers
+    filter_response_headers(response.headers().iter()
+        .filter_map(|(key, value)| value.to_str().ok().map(|value| (key.as_str(), value))))
 }
 
 pub fn get_stream_response_with_headers(custom: Option<(Vec<(String, String)>, StatusCode)>, stream_url: &str) -> HttpResponseBuilder {
@@ -21,15 +68,16 @@ pub fn get_stream_response_with_headers(custom: Option<(Vec<(String, String)>, S
     let mut status = 200_u16;
     if let Some((custom_headers, status_code)) = custom {
         status = status_code.as_u16();
-        for header in custom_headers {
-            headers.push((HeaderName::from_str(&header.0).unwrap(), HeaderValue::from_str(header.1.as_str()).unwrap()));
-            added_headers.insert(header.0.to_string());
+        for (key, value) in custom_headers {
+            if let (Ok(name), Ok(value)) = (HeaderName::from_str(&key), HeaderValue::from_str(&value)) {
+                added_headers.insert(name.as_str().to_string());
+                headers.push((name, value));
+            }
         }
     }
 
     let default_headers = vec![
         (actix_web::http::header::CONTENT_TYPE, HeaderValue::from_str("application/octet-stream").unwrap()),
-        (actix_web::http::header::CONNECTION, HeaderValue::from_str("keep-alive").unwrap()),
         (actix_web::http::header::VARY, HeaderValue::from_str("accept-encoding").unwrap())
     ];
 
@@ -43,9 +91,57 @@ pub fn get_stream_response_with_headers(custom: Option<(Vec<(String, String)>, S
 
     let mut response_builder = actix_web::HttpResponse::build(actix_web::http::StatusCode::from_u16(status).unwrap());
     debug_if_enabled!("Responding stream {} with status {status}, headers {headers:?}", mask_sensitive_info(stream_url));
+    // appended, repeated headers like `vary` keep all values
     for header in headers {
-        response_builder.insert_header(header);
+        response_builder.append_header(header);
     }
 
     response_builder
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use crate::api::model::model_utils::{filter_response_headers, get_stream_response_with_headers, is_media_stream_header};
+    use reqwest::StatusCode;
+
+    #[test]
+    fn media_stream_header_test() {
+        assert!(is_media_stream_header("content-type"));
+        assert!(is_media_stream_header("icy-metaint"));
+        assert!(is_media_stream_header("icy-name"));
+        assert!(is_media_stream_header("set-cookie"));
+        assert!(!is_media_stream_header("server"));
+    }
+
+    fn filter(headers: &[(&str, &str)]) -> Vec<(String, String)> {
+        filter_response_headers(headers.iter().copied())
+    }
+
+    #[test]
+    fn response_headers_test() {
+        let headers = filter(&[("Content-Type", "video/mp2t"), ("Connection", "keep-alive, Icy-Br"), ("Keep-Alive", "timeout=5"),
+            ("icy-br", "128"), ("icy-name", "Radio"), ("Vary", "Origin"), ("vary", "Accept-Encoding"), ("Content-Type", "text/plain"),
+            ("Set-Cookie", "a=1"), ("Set-Cookie", "b=2"), ("Content-Length", "100")]);
+        assert_eq!(headers, vec![("content-type".to_string(), "video/mp2t".to_string()), ("icy-name".to_string(), "Radio".to_string()),
+                                 ("vary".to_string(), "Origin".to_string()), ("vary".to_string(), "Accept-Encoding".to_string()),
+                                 ("set-cookie".to_string(), "a=1".to_string()), ("set-cookie".to_string(), "b=2".to_string()),
+                                 ("content-length".to_string(), "100".to_string())]);
+        // transfer encoded bodies have no length
+        assert!(filter(&[("Transfer-Encoding", "chunked"), ("Content-Length", "100")]).is_empty());
+        assert!(filter(&[("Content-Length", "100"), ("Content-Length", "200")]).is_empty());
+        assert!(filter(&[("Content-Length", "abc")]).is_empty());
+        assert_eq!(filter(&[("Content-Length", "100, 100"), ("Content-Length", "100")]), vec![("content-length".to_string(), "100".to_string())]);
+    }
+
+    #[test]
+    fn stream_response_headers_test() {
+        let custom = vec![("vary".to_string(), "Origin".to_string()), ("vary".to_string(), "Accept-Encoding".to_string()),
+                          ("set-cookie".to_string(), "a=1".to_string()), ("set-cookie".to_string(), "b=2".to_string()),
+                          ("content-type".to_string(), "video/mp2t".to_string()), ("invalid header".to_string(), "x".to_string())];
+        let response = get_stream_response_with_headers(Some((custom, StatusCode::OK)), "").finish();
+        let headers = response.headers();
+        assert_eq!(headers.get_all("vary").count(), 2);
+        assert_eq!(headers.get_all("set-cookie").map(|value| value.to_str().unwrap()).collect::<Vec<_>>(), vec!["a=1", "b=2"]);
+        assert_eq!(headers.get_all("content-type").map(|value| value.to_str().unwrap()).collect::<Vec<_>>(), vec!["video/mp2t"]);
+        assert!(headers.get("connection").is_none());
+    }
+}
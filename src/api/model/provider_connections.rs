@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::model::config::ConfigInputConnections;
+
+#[derive(Debug, Clone, Copy)]
+struct UserConnections {
+    streams: u16,
+    weight: u16,
+}
+
+/// Provider streams per input and user. The connection slots of an input are shared fair between the users,
+/// one user can't take more than `max_share` of the slots and while other users are streaming
+/// each user is limited to the part of the slots given by the user weight.
+#[derive(Debug, Default)]
+pub struct ProviderConnections {
+    inputs: Mutex<HashMap<u16, HashMap<String, UserConnections>>>,
+}
+
+/// Holds a provider connection slot, the slot is released when the stream is dropped.
+#[derive(Debug)]
+pub struct ProviderConnectionGuard {
+    connections: Arc<ProviderConnections>,
+    input_id: u16,
+    username: String,
+}
+
+impl Drop for ProviderConnectionGuard {
+    fn drop(&mut self) {
+        self.connections.release(self.input_id, &self.username);
+    }
+}
+
+fn get_user_limit(config: &ConfigInputConnections, users: &HashMap<String, UserConnections>, username: &str, weight: u16) -> u16 {
+    let max = config.max;
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let share_limit = config.max_share.map_or(max, |share| (f64::from(max) * share).floor() as u16);
+    let other_weights: u32 = users.iter()
+        .filter(|(name, _)| name.as_str() != username)
+        .map(|(_, connections)| u32::from(connections.weight))
+        .sum();
+    let weight = u32::from(weight.max(1));
+    let fair_limit = u16::try_from((u32::from(max) * weight).div_ceil(weight + other_weights)).unwrap_or(max);
+    share_limit.min(fair_limit).max(1)
+}
+
+impl ProviderConnections {
+    /// Returns a guard if the user gets a connection slot of the input.
+    /// An unhealthy input is demoted to one probe connection until it recovers.
+    pub fn acquire(self: &Arc<Self>, input_id: u16, config: &ConfigInputConnections, username: &str, weight: u16, healthy: bool) -> Option<ProviderConnectionGuard> {
+        let mut inputs = self.inputs.lock().ok()?;
+        let users = inputs.entry(input_id).or_default();
+        let active: u32 = users.values().map(|connections| u32::from(connections.streams)).sum();
+        let max = if healthy { config.max } else { 1 };
+        if active >= u32::from(max) {
+            return None;
+        }
+        let streams = users.get(username).map_or(0, |user| user.streams);
+        if streams >= get_user_limit(config, users, username, weight) {
+            return None;
+        }
+        users.insert(username.to_string(), UserConnections { streams: streams + 1, weight });
+        Some(ProviderConnectionGuard { connections: Arc::clone(self), input_id, username: username.to_string() })
+    }
+
+    /// Counts a stream of an input without connection limit, only for the metrics.
+    pub fn track(self: &Arc<Self>, input_id: u16, username: &str) -> Option<ProviderConnectionGuard> {
+        let mut inputs = self.inputs.lock().ok()?;
+        let connections = inputs.entry(input_id).or_default().entry(username.to_string()).or_insert(UserConnections { streams: 0, weight: 1 });
+        connections.streams += 1;
+        Some(ProviderConnectionGuard { connections: Arc::clone(self), input_id, username: username.to_string() })
+    }
+
+    fn release(&self, input_id: u16, username: &str) {
+        if let Ok(mut inputs) = self.inputs.lock() {
+            if let Some(users) = inputs.get_mut(&input_id) {
+                if let Some(user) = users.get_mut(username) {
+                    user.streams = user.streams.saturating_sub(1);
+                    if user.streams == 0 {
+                        users.remove(username);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Active provider streams per input and user.
+    pub fn get_streams(&self) -> HashMap<u16, HashMap<String, u16>> {
+        self.inputs.lock().map(|inputs| inputs.iter()
+            .filter(|(_, users)| !users.is_empty())
+            .map(|(input_id, users)| (*input_id, users.iter().map(|(name, connections)| (name.clone(), connections.streams)).collect()))
+            .collect()).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::api::model::provider_connections::ProviderConnections;
+    use crate::model::config::ConfigInputConnections;
+
+    #[test]
+    fn fair_share_test() {
+        let connections = Arc::new(ProviderConnections::default());
+        let config = ConfigInputConnections { max: 4, max_share: Some(0.75) };
+        let mut first: Vec<_> = (0..3).filter_map(|_| connections.acquire(1, &config, "first", 1, true)).collect();
+        // max share of 3 slots
+        assert_eq!(first.len(), 3);
+        assert!(connections.acquire(1, &config, "first", 1, true).is_none());
+        let second = connections.acquire(1, &config, "second", 1, true);
+        assert!(second.is_some());
+        // provider is full
+        assert!(connections.acquire(1, &config, "third", 1, true).is_none());
+        first.pop();
+        // the streams of inputs without limit are counted
+        let tracked = connections.track(2, "first");
+        assert_eq!(connections.get_streams()[&2]["first"], 1);
+        drop(tracked);
+        assert!(!connections.get_streams().contains_key(&2));
+        // the fair share of the first user is 2 while the second user is streaming
+        assert!(connections.acquire(1, &config, "first", 1, true).is_none());
+        let third = connections.acquire(1, &config, "third", 2, true);
+        assert!(third.is_some());
+        assert_eq!(connections.get_streams()[&1]["first"], 2);
+        drop(first);
+        drop(second);
+        assert_eq!(connections.get_streams()[&1].len(), 1);
+
+        // an unhealthy input has one probe connection
+        let probe = connections.acquire(2, &config, "first", 1, false);
+        assert!(probe.is_some());
+        assert!(connections.acquire(2, &config, "second", 1, false).is_none());
+        assert!(connections.acquire(2, &config, "second", 1, true).is_some());
+    }
+}
@@ -2,38 +2,41 @@ use crate::api::api_utils::get_headers_from_request;
 use crate::api::model::provider_stream_factory::{create_provider_stream, BufferStreamOptions};
 use crate::debug_if_enabled;
 use crate::model::config::ConfigInput;
-use crate::utils::request_utils::{get_request_headers, mask_sensitive_info};
+use crate::utils::request_utils::{get_client_request, get_request_headers, mask_sensitive_info};
 use actix_web::{HttpRequest};
 use bytes::Bytes;
 use futures::stream::BoxStream;
 use log::error;
 use reqwest::StatusCode;
 use std::sync::Arc;
-use futures::TryStreamExt;
+use futures::{StreamExt, TryStreamExt};
 use url::Url;
-use crate::api::model::model_utils::get_response_headers;
+use crate::api::model::model_utils::{get_response_headers, ICY_METADATA_HEADER};
 use crate::api::model::stream_error::StreamError;
+use crate::api::model::timeout_stream::{send_with_timeout, StreamTimeouts, TimeoutStream};
 
 type ProviderStreamResponse = (Option<BoxStream<'static, Result<Bytes, StreamError>>>, Option<(Vec<(String, String)>, StatusCode)>);
 
 pub async fn get_provider_pipe_stream(http_client: &Arc<reqwest::Client>,
                                       stream_url: &Url,
                                       req: &HttpRequest,
-                                      input: Option<&ConfigInput>) -> ProviderStreamResponse {
-    let req_headers = get_headers_from_request(req, &None);
+                                      input: Option<&ConfigInput>,
+                                      icy_metadata: bool,
+                                      timeouts: &StreamTimeouts) -> ProviderStreamResponse {
+    let mut req_headers = get_headers_from_request(req, &None);
+    if !icy_metadata {
+        req_headers.remove(ICY_METADATA_HEADER);
+    }
     debug_if_enabled!("Stream requested with headers: {:?}", req_headers.iter().map(|header| (header.0, String::from_utf8_lossy(header.1))).collect::<Vec<_>>());
-    // These are the configured headers for this input.
-    let input_headers = input.map(|i| i.headers.clone());
-    // The stream url, we need to clone it because of move to async block.
     // We merge configured input headers with the headers from the request.
-    let headers = get_request_headers(input_headers.as_ref(), Some(&req_headers));
-    let client = http_client.get(stream_url.clone()).headers(headers.clone());
-    match client.send().await {
+    let client = get_client_request(http_client, input, stream_url, Some(&req_headers));
+    match send_with_timeout(client, timeouts).await {
         Ok(mut response) => {
             let response_headers = get_response_headers(&mut response);
             let status = response.status();
             if status.is_success() {
-                (Some(Box::pin(response.bytes_stream().map_err(|err|StreamError::reqwest(&err)))), Some((response_headers, status)))
+                let stream = response.bytes_stream().map_err(|err| StreamError::reqwest(&err)).boxed();
+                (Some(TimeoutStream::new(stream, *timeouts, false, None).boxed()), Some((response_headers, status)))
             } else {
                 (None, Some((response_headers, status)))
             }
@@ -46,6 +49,28 @@ pub async fn get_provider_pipe_stream(http_client: &Arc<reqwest::Client>,
     }
 }
 
+/// Probes the stream with a HEAD request, no provider stream connection is opened.
+/// Providers which do not support HEAD respond with `405` or `501`.
+pub async fn get_provider_head_response(http_client: &Arc<reqwest::Client>,
+                                        stream_url: &Url,
+                                        req: &HttpRequest,
+                                        input: Option<&ConfigInput>) -> Option<(Vec<(String, String)>, StatusCode)> {
+    let req_headers = get_headers_from_request(req, &None);
+    let input_headers = input.map(|i| i.headers.clone());
+    let headers = get_request_headers(input_headers.as_ref(), Some(&req_headers));
+    let (http_client, request_url) = match input {
+        Some(input) => (input.get_http_client(http_client), input.get_request_url(stream_url)),
+        None => (http_client, stream_url.clone()),
+    };
+    match http_client.head(request_url).headers(headers).send().await {
+        Ok(mut response) => Some((get_response_headers(&mut response), response.status())),
+        Err(err) => {
+            error!("Failed to probe stream {} {err}", mask_sensitive_info(stream_url.as_str()));
+            None
+        }
+    }
+}
+
 pub async fn get_provider_reconnect_buffered_stream(http_client: &Arc<reqwest::Client>,
                                                     stream_url: &Url,
                                                     req: &HttpRequest,
@@ -1,12 +1,15 @@
 use crate::api::api_utils::get_headers_from_request;
 use crate::api::model::buffered_stream::BufferedStream;
 use crate::api::model::client_stream::ClientStream;
-use crate::api::model::model_utils::get_response_headers;
+use crate::api::model::model_utils::{get_response_headers, ICY_METADATA_HEADER};
 use crate::api::model::stream_error::StreamError;
+use crate::api::model::stream_trace::StreamTrace;
+use crate::api::model::timeout_stream::{send_with_timeout, StreamTimeouts, TimeoutStream};
 use crate::debug_if_enabled;
-use crate::model::config::ConfigInput;
+use crate::model::config::{ConfigInput, ConfigInputTokenRefresh};
 use crate::model::playlist::PlaylistItemType;
 use crate::utils::request_utils::{get_request_headers, mask_sensitive_info};
+use crate::utils::metrics;
 use actix_web::HttpRequest;
 use bytes::Bytes;
 use futures::stream::{self, BoxStream};
@@ -15,8 +18,8 @@ use log::warn;
 use reqwest::header::{HeaderMap, RANGE};
 use reqwest::StatusCode;
 use std::collections::HashMap;
-use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
 use std::time::{Duration, Instant};
 use url::Url;
 use crate::utils::atomic_once_flag::AtomicOnceFlag;
@@ -29,11 +32,13 @@ type ResponseInfo = Option<(Vec<(String, String)>, StatusCode)>;
 type ProviderStreamResponse = (ResponseStream, ResponseInfo);
 
 pub struct BufferStreamOptions {
-    #[allow(dead_code)]
     item_type: PlaylistItemType,
     reconnect_enabled: bool,
     buffer_enabled: bool,
     buffer_size: usize,
+    icy_metadata: bool,
+    trace: Option<Arc<StreamTrace>>,
+    timeouts: StreamTimeouts,
 }
 
 impl BufferStreamOptions {
@@ -42,15 +47,30 @@ impl BufferStreamOptions {
         reconnect_enabled: bool,
         buffer_enabled: bool,
         buffer_size: usize,
+        icy_metadata: bool,
     ) -> Self {
         Self {
             item_type,
             reconnect_enabled,
             buffer_enabled,
             buffer_size,
+            icy_metadata,
+            trace: None,
+            timeouts: StreamTimeouts::new(None, item_type),
         }
     }
 
+    /// Provider requests and reconnects are recorded to the trace.
+    pub(crate) fn with_trace(mut self, trace: Option<Arc<StreamTrace>>) -> Self {
+        self.trace = trace;
+        self
+    }
+
+    pub(crate) fn with_timeouts(mut self, timeouts: StreamTimeouts) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
+
     #[inline]
     fn is_buffer_enabled(&self) -> bool {
         self.buffer_enabled
@@ -66,6 +86,12 @@ impl BufferStreamOptions {
         self.reconnect_enabled
     }
 
+    /// Vod and series can be resumed with a range request on reconnect, even if the client did not request a range.
+    #[inline]
+    fn is_resumable(&self) -> bool {
+        self.item_type != PlaylistItemType::Live
+    }
+
     #[inline]
     pub(crate) fn get_stream_buffer_size(&self) -> usize {
         if self.buffer_size > 0 { self.buffer_size } else { STREAM_QUEUE_SIZE }
@@ -77,10 +103,17 @@ impl BufferStreamOptions {
 struct ProviderStreamOptions {
     buffer_size: usize,
     continue_flag: Arc<AtomicOnceFlag>,
-    url: Url,
+    url: Arc<RwLock<Url>>,
+    item_url: Url,
+    token_refresh: Option<ConfigInputTokenRefresh>,
+    refresh_attempts: Arc<AtomicU32>,
     reconnect: bool,
     headers: HeaderMap,
     range_bytes: Arc<Option<AtomicUsize>>,
+    range_end: Option<usize>,
+    range_requested: bool,
+    trace: Option<Arc<StreamTrace>>,
+    timeouts: StreamTimeouts,
 }
 
 impl ProviderStreamOptions {
@@ -107,9 +140,23 @@ impl ProviderStreamOptions {
         self.continue_flag.notify();
     }
 
+    /// The current stream url, it changes when an expired token is refreshed.
     #[inline]
-    pub fn get_url(&self) -> &Url {
-        &self.url
+    pub fn get_url(&self) -> Url {
+        self.url.read().map_or_else(|_| self.item_url.clone(), |url| url.clone())
+    }
+
+    fn set_url(&self, url: Url) {
+        if let Ok(mut current) = self.url.write() {
+            *current = url;
+        }
+    }
+
+    /// With token refresh reconnects continue with the resolved (redirected) stream url.
+    fn keep_response_url(&self, response_url: &Url) {
+        if self.token_refresh.is_some() {
+            self.set_url(response_url.clone());
+        }
     }
 
     #[inline]
@@ -127,6 +174,22 @@ impl ProviderStreamOptions {
         self.range_bytes.as_ref().as_ref().map(|atomic| atomic.load(Ordering::Relaxed))
     }
 
+    /// The range for the initial request, only set if the client requested a range.
+    #[inline]
+    pub fn get_initial_range(&self) -> Option<(usize, Option<usize>)> {
+        if self.range_requested {
+            self.get_total_bytes_send().map(|start| (start, self.range_end))
+        } else {
+            None
+        }
+    }
+
+    /// The range for a reconnect, continues at the position of the last byte sent to the client.
+    #[inline]
+    pub fn get_reconnect_range(&self) -> Option<(usize, Option<usize>)> {
+        self.get_total_bytes_send().map(|start| (start, self.range_end))
+    }
+
     // pub fn get_range_bytes(&self) -> &Arc<Option<AtomicUsize>> {
     //     &self.range_bytes
     // }
@@ -140,49 +203,86 @@ impl ProviderStreamOptions {
     pub fn should_continue(&self) -> bool {
         self.continue_flag.is_active()
     }
-}
 
-fn get_request_range_start_bytes(req_headers: &HashMap<String, Vec<u8>>) -> Option<usize> {
-    // range header looks like  bytes=1234-5566/2345345 or bytes=0-
-    if let Some(req_range) = req_headers.get(actix_web::http::header::RANGE.as_str()) {
-        if let Some(bytes_range) = req_range.strip_prefix(b"bytes=") {
-            if let Some(index) = bytes_range.iter().position(|&x| x == b'-') {
-                let start_bytes = &bytes_range[..index];
-                if let Ok(start_str) = std::str::from_utf8(start_bytes) {
-                    if let Ok(bytes_requested) = start_str.parse::<usize>() {
-                        return Some(bytes_requested);
-                    }
-                }
-            }
+    fn trace_event(&self, message: &str) {
+        if let Some(trace) = &self.trace {
+            trace.event(message);
         }
     }
-    None
+
+    fn create_response_stream(&self, response: reqwest::Response) -> ResponseStream {
+        let stream = response.bytes_stream().map_err(|err| StreamError::reqwest(&err)).boxed();
+        TimeoutStream::new(stream, self.timeouts, self.reconnect, self.trace.clone()).boxed()
+    }
+}
+
+/// Parses a single byte range like `bytes=1234-5566` or `bytes=1234-`.
+/// Suffix ranges (`bytes=-500`) and multiple ranges are not supported.
+fn get_request_range(req_headers: &HashMap<String, Vec<u8>>) -> Option<(usize, Option<usize>)> {
+    let req_range = req_headers.get(actix_web::http::header::RANGE.as_str())?;
+    let bytes_range = std::str::from_utf8(req_range.strip_prefix(b"bytes=")?).ok()?;
+    let (start_str, end_str) = bytes_range.trim().split_once('-')?;
+    let start = start_str.trim().parse::<usize>().ok()?;
+    let end_str = end_str.trim();
+    if end_str.is_empty() {
+        return Some((start, None));
+    }
+    let end = end_str.parse::<usize>().ok()?;
+    if end < start {
+        return None;
+    }
+    Some((start, Some(end)))
+}
+
+struct ClientStreamRequestParams {
+    buffer_size: usize,
+    range: Option<(usize, Option<usize>)>,
+    range_requested: bool,
+    reconnect: bool,
+    headers: HeaderMap,
 }
 
 fn get_client_stream_request_params(
     req: &HttpRequest,
     input: Option<&ConfigInput>,
-    options: &BufferStreamOptions) -> (usize, Option<usize>, bool, HeaderMap)
+    options: &BufferStreamOptions) -> ClientStreamRequestParams
 {
     let stream_buffer_size = if options.is_buffer_enabled() { options.get_stream_buffer_size() } else { 1 };
     let mut req_headers = get_headers_from_request(req, &None);
     debug_if_enabled!("Stream requested with headers: {:?}", req_headers.iter().map(|header| (header.0, String::from_utf8_lossy(header.1))).collect::<Vec<_>>());
     // we need the range bytes from client request for seek ing to the right position
-    let req_range_start_bytes = get_request_range_start_bytes(&req_headers);
-    req_headers.remove("range");
+    let mut reconnect = options.is_reconnect_enabled();
+    let req_range = get_request_range(&req_headers);
+    let range_requested = req_range.is_some();
+    if range_requested {
+        req_headers.remove("range");
+    } else if req_headers.contains_key("range") {
+        // unsupported ranges are forwarded as they are, but can't be resumed
+        reconnect = false;
+    }
+    let range = req_range.or_else(|| (reconnect && options.is_resumable()).then_some((0, None)));
+    if !options.icy_metadata {
+        req_headers.remove(ICY_METADATA_HEADER);
+    }
     // These are the configured headers for this input.
     let input_headers = input.map(|i| i.headers.clone());
     // We merge configured input headers with the headers from the request.
     let headers = get_request_headers(input_headers.as_ref(), Some(&req_headers));
 
-    (stream_buffer_size, req_range_start_bytes, options.is_reconnect_enabled(), headers)
+    ClientStreamRequestParams {
+        buffer_size: stream_buffer_size,
+        range,
+        range_requested,
+        reconnect,
+        headers,
+    }
 }
 
-fn prepare_client(request_client: &Arc<reqwest::Client>, url: &Url, headers: &HeaderMap, range_start_bytes_to_request: Option<usize>) -> (reqwest::RequestBuilder, bool) {
+fn prepare_client(request_client: &Arc<reqwest::Client>, url: &Url, headers: &HeaderMap, range_to_request: Option<(usize, Option<usize>)>) -> (reqwest::RequestBuilder, bool) {
     let mut client = request_client.get(url.clone()).headers(headers.clone());
-    if let Some(range) = range_start_bytes_to_request {
+    if let Some((start, end)) = range_to_request {
         // on reconnect send range header to avoid starting from beginning for vod
-        let range = format!("bytes={range}-",);
+        let range = end.map_or_else(|| format!("bytes={start}-"), |end| format!("bytes={start}-{end}"));
         client = client.header(RANGE, range);
         (client, true) // partial content
     } else {
@@ -190,12 +290,85 @@ fn prepare_client(request_client: &Arc<reqwest::Client>, url: &Url, headers: &He
     }
 }
 
+/// A new stream url from the body of the refresh endpoint, a plain url or json with an `url` field.
+fn parse_refresh_response(body: &str) -> Option<Url> {
+    let body = body.trim();
+    let url = match serde_json::from_str::<serde_json::Value>(body) {
+        Ok(serde_json::Value::Object(fields)) => fields.get("url")?.as_str()?.to_string(),
+        _ => body.to_string(),
+    };
+    Url::parse(&url).ok().filter(|url| matches!(url.scheme(), "http" | "https"))
+}
+
+fn get_refresh_url(token_refresh: &ConfigInputTokenRefresh, item_url: &Url, expired_url: &Url) -> Option<Url> {
+    match &token_refresh.url {
+        Some(url) => Url::parse(&url.replace("{url}", &url::form_urlencoded::byte_serialize(expired_url.as_str().as_bytes()).collect::<String>())).ok(),
+        None => Some(item_url.clone()),
+    }
+}
+
+/// Requests the refresh url. The playlist item url answers with the stream itself, only the redirect target is needed:
+/// it is requested with `HEAD`, providers which don't support `HEAD` with a range request for the first byte.
+async fn send_refresh_request(client: &reqwest::Client, token_refresh: &ConfigInputTokenRefresh, refresh_url: &Url, headers: &HeaderMap) -> reqwest::Result<reqwest::Response> {
+    if token_refresh.url.is_some() {
+        return client.get(refresh_url.clone()).headers(headers.clone()).send().await;
+    }
+    let response = client.head(refresh_url.clone()).headers(headers.clone()).send().await?;
+    if !matches!(response.status(), StatusCode::METHOD_NOT_ALLOWED | StatusCode::NOT_IMPLEMENTED) {
+        return Ok(response);
+    }
+    client.get(refresh_url.clone()).headers(headers.clone()).header(RANGE, "bytes=0-0").send().await
+}
+
+/// Requests a new stream url after the provider rejected the stream url with `403` or `410`.
+/// Returns true if the stream url was refreshed and the request should be repeated.
+async fn refresh_stream_url(client: &Arc<reqwest::Client>, stream_options: &ProviderStreamOptions, status: StatusCode) -> bool {
+    let Some(token_refresh) = &stream_options.token_refresh else { return false; };
+    if !matches!(status, StatusCode::FORBIDDEN | StatusCode::GONE)
+        || stream_options.refresh_attempts.fetch_add(1, Ordering::Relaxed) >= token_refresh.max_attempts {
+        return false;
+    }
+    let expired_url = stream_options.get_url();
+    let Some(refresh_url) = get_refresh_url(token_refresh, &stream_options.item_url, &expired_url) else { return false; };
+    stream_options.trace_event(&format!("Refreshing expired stream url {expired_url} with {refresh_url}"));
+    let response = match send_refresh_request(client, token_refresh, &refresh_url, stream_options.get_headers()).await {
+        Ok(response) if response.status().is_success() => response,
+        Ok(response) => {
+            stream_options.trace_event(&format!("Token refresh responded with status {}", response.status()));
+            return false;
+        }
+        Err(err) => {
+            stream_options.trace_event(&format!("Token refresh failed: {err}"));
+            return false;
+        }
+    };
+    let new_url = if response.url() != &refresh_url {
+        // the provider redirected to the stream url with a new token
+        Some(response.url().clone())
+    } else if token_refresh.url.is_some() {
+        response.text().await.ok().and_then(|body| parse_refresh_response(&body))
+    } else {
+        Some(refresh_url)
+    };
+    match new_url {
+        Some(url) => {
+            debug_if_enabled!("Refreshed stream url {}", mask_sensitive_info(url.as_str()));
+            stream_options.set_url(url);
+            true
+        }
+        None => false,
+    }
+}
+
 async fn provider_request(request_client: Arc<reqwest::Client>, initial_info: bool, stream_options: &ProviderStreamOptions) -> Result<Option<ProviderStreamResponse>, StatusCode> {
-    let (client, _partial_content) = prepare_client(&request_client, stream_options.get_url(), stream_options.get_headers(), stream_options.get_total_bytes_send());
-    match client.send().await {
+    let (client, _partial_content) = prepare_client(&request_client, &stream_options.get_url(), stream_options.get_headers(), stream_options.get_initial_range());
+    stream_options.trace_event(&format!("Provider request {} range {:?}", stream_options.get_url(), stream_options.get_initial_range()));
+    match send_with_timeout(client, &stream_options.timeouts).await {
         Ok(mut response) => {
             let status = response.status();
+            stream_options.trace_event(&format!("Provider request responded with status {status}"));
             if status.is_success() {
+                stream_options.keep_response_url(response.url());
                 let response_info = if initial_info {
                     // Unfortunately, the HEAD request does not work, so we need this workaround.
                     // We need some header information from the provider, we extract the necessary headers and forward them to the client
@@ -206,11 +379,12 @@ async fn provider_request(request_client: Arc<reqwest::Client>, initial_info: bo
                 } else {
                     None
                 };
-                return Ok(Some((response.bytes_stream().map_err(|err|StreamError::reqwest(&err)).boxed(), response_info)));
+                return Ok(Some((stream_options.create_response_stream(response), response_info)));
             }
             Err(status)
         }
-        Err(_err) => {
+        Err(err) => {
+            stream_options.trace_event(&format!("Provider request failed: {err}"));
             Err(StatusCode::SERVICE_UNAVAILABLE)
         }
     }
@@ -218,18 +392,38 @@ async fn provider_request(request_client: Arc<reqwest::Client>, initial_info: bo
 
 
 async fn stream_provider(client: Arc<reqwest::Client>, stream_options: ProviderStreamOptions) -> Option<ResponseStream> {
-    let url = stream_options.get_url();
-    let range_start = stream_options.get_total_bytes_send();
+    let range = stream_options.get_reconnect_range();
     let headers = stream_options.get_headers();
+    if let Some((start, Some(end))) = range {
+        if start > end {
+            // requested range completely sent
+            return None;
+        }
+    }
 
     while stream_options.should_continue() {
+        let url = stream_options.get_url();
         debug_if_enabled!("Reconnecting stream {}", mask_sensitive_info(url.as_str()));
-        let (client, _) = prepare_client(&client, url, headers, range_start);
-        match client.send().await {
+        metrics::record_stream_reconnect();
+        if let Some(trace) = &stream_options.trace {
+            trace.reconnect(&format!("Reconnecting stream {url} range {range:?}"));
+        }
+        let (request, partial_content) = prepare_client(&client, &url, headers, range);
+        match send_with_timeout(request, &stream_options.timeouts).await {
             Ok(response) => {
                 let status = response.status();
+                stream_options.trace_event(&format!("Reconnect responded with status {status}"));
+                if partial_content && status != StatusCode::PARTIAL_CONTENT && range.is_some_and(|(start, _)| start > 0) {
+                    // the provider ignores the range, continuing would send the content from the beginning
+                    debug_if_enabled!("Provider does not support range requests, stopped reconnecting {}", mask_sensitive_info(url.as_str()));
+                    return None;
+                }
                 if status.is_success() {
-                    return Some(response.bytes_stream().map_err(|err|StreamError::reqwest(&err)).boxed());
+                    stream_options.keep_response_url(response.url());
+                    return Some(stream_options.create_response_stream(response));
+                }
+                if refresh_stream_url(&client, &stream_options, status).await {
+                    continue;
                 }
                 if status.is_client_error() {
                     return None;
@@ -244,14 +438,14 @@ async fn stream_provider(client: Arc<reqwest::Client>, stream_options: ProviderS
                     }
                 }
             }
-            Err(_err) => {}
+            Err(err) => stream_options.trace_event(&format!("Reconnect failed: {err}")),
         }
         if !stream_options.should_continue() {
             return None;
         }
         actix_web::rt::time::sleep(Duration::from_millis(100)).await;
     }
-    debug_if_enabled!("Stopped seconnecting stream {}", mask_sensitive_info(url.as_str()));
+    debug_if_enabled!("Stopped seconnecting stream {}", mask_sensitive_info(stream_options.get_url().as_str()));
     None
 }
 
@@ -269,6 +463,9 @@ async fn get_initial_stream(client: Arc<reqwest::Client>, stream_options: &Provi
                 }
             }
             Err(status) => {
+                if refresh_stream_url(&client, stream_options, status).await {
+                    continue;
+                }
                 if connect_err > ERR_MAX_RETRY_COUNT {
                     warn!("The stream could be unavailable. ({status}) {}", mask_sensitive_info(stream_options.get_url().as_str()));
                 }
@@ -292,18 +489,25 @@ fn create_provider_stream_options(stream_url: &Url,
                                   req: &HttpRequest,
                                   input: Option<&ConfigInput>,
                                   options: &BufferStreamOptions) -> ProviderStreamOptions {
-    let (buffer_size, req_range_start_bytes, reconnect, headers) = get_client_stream_request_params(req, input, options);
-    let url = stream_url.clone();
-    let range_bytes = Arc::new(req_range_start_bytes.map(AtomicUsize::new));
+    let ClientStreamRequestParams { buffer_size, range, range_requested, reconnect, headers } = get_client_stream_request_params(req, input, options);
+    let range_bytes = Arc::new(range.map(|(start, _)| AtomicUsize::new(start)));
+    let range_end = range.and_then(|(_, end)| end);
     let continue_flag = Arc::new(AtomicOnceFlag::new());
 
     ProviderStreamOptions {
+        trace: options.trace.clone(),
         buffer_size,
         continue_flag,
-        url,
+        url: Arc::new(RwLock::new(stream_url.clone())),
+        item_url: stream_url.clone(),
+        token_refresh: input.and_then(|input| input.token_refresh.clone()),
+        refresh_attempts: Arc::new(AtomicU32::new(0)),
         reconnect,
         headers,
         range_bytes,
+        range_end,
+        range_requested,
+        timeouts: options.timeouts,
     }
 }
 
@@ -314,7 +518,12 @@ pub async fn create_provider_stream(client: Arc<reqwest::Client>,
                                     req: &HttpRequest,
                                     input: Option<&ConfigInput>,
                                     options: BufferStreamOptions) -> Option<ProviderStreamResponse> {
-    let stream_options = create_provider_stream_options(stream_url, req, input, &options);
+    // the tls settings of the input apply to the initial request and all reconnects
+    let (client, request_url) = match input {
+        Some(input) => (Arc::clone(input.get_http_client(&client)), input.get_request_url(stream_url)),
+        None => (client, stream_url.clone()),
+    };
+    let stream_options = create_provider_stream_options(&request_url, req, input, &options);
 
     let client_stream_factory = |stream, reconnect, range_cnt| {
         let stream = if stream_options.is_buffered() {
@@ -353,20 +562,94 @@ pub async fn create_provider_stream(client: Arc<reqwest::Client>,
 mod tests {
     use crate::api::model::provider_stream_factory::PlaylistItemType;
     use crate::api::model::provider_stream_factory::{create_provider_stream, BufferStreamOptions};
-    use actix_web::test;
     use actix_web::test::TestRequest;
     use actix_web::web;
     use actix_web::App;
     use actix_web::{HttpRequest, HttpResponse};
     use futures::StreamExt;
+    use std::collections::HashMap;
     use std::sync::Arc;
+    use crate::api::model::provider_stream_factory::{get_refresh_url, get_request_range, parse_refresh_response, send_refresh_request};
+    use crate::model::config::ConfigInputTokenRefresh;
+
+    #[test]
+    fn request_range_test() {
+        let range = |value: &str| get_request_range(&HashMap::from([("range".to_string(), value.as_bytes().to_vec())]));
+        assert_eq!(range("bytes=0-"), Some((0, None)));
+        assert_eq!(range("bytes=1234-5566"), Some((1234, Some(5566))));
+        assert_eq!(range("bytes=-500"), None);
+        assert_eq!(range("bytes=500-100"), None);
+        assert_eq!(range("bytes=0-10,20-30"), None);
+        assert_eq!(get_request_range(&HashMap::new()), None);
+    }
+
+    #[test]
+    fn token_refresh_test() {
+        let item_url = url::Url::parse("http://provider.tv/live/user/pass/1.ts").unwrap();
+        let expired_url = url::Url::parse("http://cdn.provider.tv/1.ts?token=abc&exp=1").unwrap();
+        let reload = ConfigInputTokenRefresh { url: None, max_attempts: 3 };
+        assert_eq!(get_refresh_url(&reload, &item_url, &expired_url), Some(item_url.clone()));
+        let endpoint = ConfigInputTokenRefresh { url: Some("http://provider.tv/refresh?url={url}".to_string()), max_attempts: 3 };
+        assert_eq!(get_refresh_url(&endpoint, &item_url, &expired_url).unwrap().as_str(),
+                   "http://provider.tv/refresh?url=http%3A%2F%2Fcdn.provider.tv%2F1.ts%3Ftoken%3Dabc%26exp%3D1");
+        assert_eq!(parse_refresh_response(" http://cdn.provider.tv/1.ts?token=def\n").unwrap().as_str(), "http://cdn.provider.tv/1.ts?token=def");
+        assert_eq!(parse_refresh_response(r#"{"url": "https://cdn.provider.tv/1.ts?token=def"}"#).unwrap().as_str(), "https://cdn.provider.tv/1.ts?token=def");
+        assert_eq!(parse_refresh_response("expired"), None);
+    }
+
+    /// Answers each request with the response of the path and records the request line and range header.
+    async fn serve_refresh_requests(listener: tokio::net::TcpListener, responses: HashMap<&'static str, String>, requests: Arc<std::sync::Mutex<Vec<String>>>) {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        while let Ok((mut socket, _)) = listener.accept().await {
+            let mut buffer = vec![0u8; 4096];
+            let len = socket.read(&mut buffer).await.unwrap_or(0);
+            let request = String::from_utf8_lossy(&buffer[..len]).to_lowercase();
+            let line = request.lines().next().unwrap_or_default().to_string();
+            let range = request.lines().find_map(|header| header.strip_prefix("range: ")).unwrap_or("-").to_string();
+            requests.lock().unwrap().push(format!("{} {range}", line.trim_end_matches(" http/1.1")));
+            let path = line.split(' ').nth(1).unwrap_or_default();
+            let method = line.split(' ').next().unwrap_or_default();
+            let response = responses.get(format!("{method} {path}").as_str()).cloned()
+                .unwrap_or_else(|| "HTTP/1.1 404 Not Found\r\ncontent-length: 0\r\n\r\n".to_string());
+            let _ = socket.write_all(response.as_bytes()).await;
+        }
+    }
+
+    #[actix_rt::test]
+    async fn token_refresh_request_test() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let requests = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let responses = HashMap::from([
+            ("head /live/1.ts", format!("HTTP/1.1 302 Found\r\nlocation: http://{address}/cdn/1.ts?token=new\r\ncontent-length: 0\r\n\r\n")),
+            ("head /cdn/1.ts?token=new", "HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n".to_string()),
+            ("head /vod/1.mp4", "HTTP/1.1 405 Method Not Allowed\r\ncontent-length: 0\r\n\r\n".to_string()),
+            ("get /vod/1.mp4", format!("HTTP/1.1 302 Found\r\nlocation: http://{address}/cdn/1.mp4?token=new\r\ncontent-length: 0\r\n\r\n")),
+            ("get /cdn/1.mp4?token=new", "HTTP/1.1 206 Partial Content\r\ncontent-range: bytes 0-0/1000\r\ncontent-length: 1\r\n\r\n0".to_string()),
+        ]);
+        actix_rt::spawn(serve_refresh_requests(listener, responses, Arc::clone(&requests)));
+        let client = reqwest::Client::new();
+        let reload = ConfigInputTokenRefresh { url: None, max_attempts: 3 };
+
+        let item_url = url::Url::parse(&format!("http://{address}/live/1.ts")).unwrap();
+        let response = send_refresh_request(&client, &reload, &item_url, &reqwest::header::HeaderMap::new()).await.unwrap();
+        assert_eq!(response.url().as_str(), format!("http://{address}/cdn/1.ts?token=new"));
+        // without head support only the first byte is requested
+        let item_url = url::Url::parse(&format!("http://{address}/vod/1.mp4")).unwrap();
+        let response = send_refresh_request(&client, &reload, &item_url, &reqwest::header::HeaderMap::new()).await.unwrap();
+        assert_eq!(response.url().as_str(), format!("http://{address}/cdn/1.mp4?token=new"));
+        assert_eq!(*requests.lock().unwrap(), vec![
+            "head /live/1.ts -", "head /cdn/1.ts?token=new -",
+            "head /vod/1.mp4 -", "get /vod/1.mp4 bytes=0-0", "get /cdn/1.mp4?token=new bytes=0-0",
+        ]);
+    }
 
     #[actix_rt::test]
     async fn test_stream() {
         let app = App::new().route("/test", web::get().to(test_stream_handler));
-        let server = test::init_service(app).await;
+        let server = actix_web::test::init_service(app).await;
         let req = TestRequest::get().uri("/test").to_request();
-        let _response = test::call_service(&server, req).await;
+        let _response = actix_web::test::call_service(&server, req).await;
     }
     async fn test_stream_handler(req: HttpRequest) -> HttpResponse {
         let mut counter = 5;
@@ -374,7 +657,7 @@ mod tests {
         let url = url::Url::parse("https://info.cern.ch/hypertext/WWW/TheProject.html").unwrap();
         let input = None;
 
-        let options = BufferStreamOptions::new(PlaylistItemType::Live, true, true, 0);
+        let options = BufferStreamOptions::new(PlaylistItemType::Live, true, true, 0, false);
         'outer: while let Some((mut stream, info)) = create_provider_stream(Arc::clone(&client), &url, &req, input, options).await {
             if info.is_some() {
                 println!("{:?}", info.unwrap());
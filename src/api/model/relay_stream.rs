@@ -0,0 +1,171 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::process::Stdio;
+
+use actix_web::http::header::CONTENT_TYPE;
+use bytes::{Bytes, BytesMut};
+use futures::stream::{self, BoxStream};
+use futures::StreamExt;
+use log::{debug, error};
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::io::AsyncReadExt;
+use tokio::net::UdpSocket;
+use tokio::process::{Child, ChildStdout, Command};
+use url::{Host, Url};
+
+use crate::api::model::stream_error::StreamError;
+use crate::model::config::StreamRelayConfig;
+use crate::utils::request_utils::mask_sensitive_info;
+
+const RELAY_SCHEMES: &[&str] = &["rtsp", "rtsps", "rtp", "udp"];
+// max size of an udp datagram
+const UDP_BUFFER_SIZE: usize = 65_536;
+const PIPE_BUFFER_SIZE: usize = 65_536;
+
+type RelayStream = BoxStream<'static, Result<Bytes, StreamError>>;
+
+/// Streams which can't be requested over http, they are restreamed by the relay.
+pub fn is_relay_url(url: &Url) -> bool {
+    RELAY_SCHEMES.contains(&url.scheme())
+}
+
+pub fn get_relay_response_headers() -> Vec<(String, String)> {
+    vec![(CONTENT_TYPE.to_string(), "video/mp2t".to_string())]
+}
+
+/// `udp://@239.1.1.1:1234` (vlc style) or `udp://239.1.1.1:1234`, a source address before `@` is ignored.
+fn get_udp_address(url: &Url) -> Option<SocketAddr> {
+    let ip = match url.host()? {
+        Host::Ipv4(ip) => IpAddr::V4(ip),
+        Host::Ipv6(ip) => IpAddr::V6(ip),
+        Host::Domain(domain) => domain.parse::<IpAddr>().ok()?,
+    };
+    Some(SocketAddr::new(ip, url.port()?))
+}
+
+/// Binds the port of the address and joins the multicast group.
+/// The address is reused, multiple clients can receive the same multicast group.
+fn open_udp_socket(addr: SocketAddr) -> std::io::Result<UdpSocket> {
+    let (domain, bind_ip) = match addr.ip() {
+        IpAddr::V4(_) => (Domain::IPV4, IpAddr::V4(Ipv4Addr::UNSPECIFIED)),
+        IpAddr::V6(_) => (Domain::IPV6, IpAddr::V6(Ipv6Addr::UNSPECIFIED)),
+    };
+    let socket = Socket::new(domain, Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_reuse_address(true)?;
+    socket.bind(&SocketAddr::new(bind_ip, addr.port()).into())?;
+    match addr.ip() {
+        IpAddr::V4(ip) if ip.is_multicast() => socket.join_multicast_v4(&ip, &Ipv4Addr::UNSPECIFIED)?,
+        IpAddr::V6(ip) if ip.is_multicast() => socket.join_multicast_v6(&ip, 0)?,
+        _ => {}
+    }
+    socket.set_nonblocking(true)?;
+    UdpSocket::from_std(socket.into())
+}
+
+/// Each datagram of the mpeg-ts multicast is forwarded as chunk.
+fn get_udp_stream(addr: SocketAddr) -> std::io::Result<RelayStream> {
+    let socket = open_udp_socket(addr)?;
+    Ok(stream::unfold((socket, BytesMut::new()), |(socket, mut buffer)| async move {
+        buffer.reserve(UDP_BUFFER_SIZE);
+        match socket.recv_buf(&mut buffer).await {
+            Ok(_) => {
+                let chunk = buffer.split().freeze();
+                Some((Ok(chunk), (socket, buffer)))
+            }
+            Err(err) => {
+                error!("Failed to receive udp stream: {err}");
+                None
+            }
+        }
+    }).boxed())
+}
+
+fn spawn_ffmpeg(config: &StreamRelayConfig, url: &Url) -> std::io::Result<Child> {
+    let mut command = Command::new(&config.ffmpeg);
+    command.args(["-hide_banner", "-loglevel", "error"]);
+    if let Some(transport) = config.rtsp_transport.as_ref().filter(|_| url.scheme().starts_with("rtsp")) {
+        command.args(["-rtsp_transport", transport]);
+    }
+    command.args(["-i", url.as_str(), "-c", "copy", "-f", "mpegts", "pipe:1"])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        // the ffmpeg process is stopped when the client disconnects
+        .kill_on_drop(true);
+    command.spawn()
+}
+
+async fn read_pipe(stdout: &mut ChildStdout, buffer: &mut BytesMut) -> Option<Bytes> {
+    buffer.reserve(PIPE_BUFFER_SIZE);
+    match stdout.read_buf(buffer).await {
+        Ok(0) => None,
+        Ok(_) => Some(buffer.split().freeze()),
+        Err(err) => {
+            error!("Failed to read relay stream: {err}");
+            None
+        }
+    }
+}
+
+/// ffmpeg copies the stream into mpeg-ts without transcoding.
+/// The first chunk is awaited, a stream which can't be opened is not responded.
+async fn get_ffmpeg_stream(config: &StreamRelayConfig, url: &Url) -> std::io::Result<Option<RelayStream>> {
+    let mut child = spawn_ffmpeg(config, url)?;
+    let Some(mut stdout) = child.stdout.take() else { return Ok(None) };
+    let mut buffer = BytesMut::new();
+    let Some(first_chunk) = read_pipe(&mut stdout, &mut buffer).await else { return Ok(None) };
+    let remaining = stream::unfold((child, stdout, buffer), |(child, mut stdout, mut buffer)| async move {
+        read_pipe(&mut stdout, &mut buffer).await.map(|chunk| (Ok(chunk), (child, stdout, buffer)))
+    });
+    Ok(Some(stream::once(async move { Ok(first_chunk) }).chain(remaining).boxed()))
+}
+
+/// Restreams rtsp, rtp and udp urls. Udp multicast is received natively unless `udp_ffmpeg` is set,
+/// the other protocols are relayed through ffmpeg.
+pub async fn get_relay_stream(config: Option<&StreamRelayConfig>, url: &Url) -> Option<RelayStream> {
+    let default_config = StreamRelayConfig::default();
+    let config = config.unwrap_or(&default_config);
+    let masked_url = mask_sensitive_info(url.as_str());
+    debug!("Relaying stream {masked_url}");
+    let result = if url.scheme() == "udp" && !config.udp_ffmpeg {
+        match get_udp_address(url) {
+            Some(addr) => get_udp_stream(addr).map(Some),
+            None => {
+                error!("Invalid udp stream address {masked_url}");
+                return None;
+            }
+        }
+    } else {
+        get_ffmpeg_stream(config, url).await
+    };
+    match result {
+        Ok(stream) => {
+            if stream.is_none() {
+                error!("Relay stream {masked_url} has no content");
+            }
+            stream
+        }
+        Err(err) => {
+            error!("Failed to relay stream {masked_url}: {err}");
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use url::Url;
+
+    use crate::api::model::relay_stream::{get_udp_address, is_relay_url};
+
+    #[test]
+    fn get_udp_address_test() {
+        assert!(is_relay_url(&Url::parse("rtsp://192.168.1.10:554/stream").unwrap()));
+        assert!(!is_relay_url(&Url::parse("http://provider.tv/live/1.ts").unwrap()));
+        let expected: SocketAddr = "239.1.1.1:1234".parse().unwrap();
+        assert_eq!(get_udp_address(&Url::parse("udp://@239.1.1.1:1234").unwrap()), Some(expected));
+        assert_eq!(get_udp_address(&Url::parse("udp://10.0.0.1@239.1.1.1:1234").unwrap()), Some(expected));
+        assert_eq!(get_udp_address(&Url::parse("udp://239.1.1.1").unwrap()), None);
+    }
+}
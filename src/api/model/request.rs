@@ -1,10 +1,18 @@
+use std::collections::{BTreeSet, HashMap};
+
 use actix_web::web;
 use serde::{Deserialize, Serialize};
 
+use crate::model::config::ConfigTarget;
+use crate::processing::user_bulk::{UserBulkFilter, UserBulkUpdate};
+
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct PlaylistRequest {
     pub url: Option<String>,
     pub input_id: Option<u16>,
+    /// The stored playlist of the target.
+    #[serde(default)]
+    pub target: Option<String>,
 }
 
 impl From<web::Json<Self>> for PlaylistRequest {
@@ -13,6 +21,16 @@ impl From<web::Json<Self>> for PlaylistRequest {
     }
 }
 
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct EpgProgrammeRequest {
+    /// unix timestamp
+    #[serde(default)]
+    pub from: Option<i64>,
+    /// unix timestamp
+    #[serde(default)]
+    pub to: Option<i64>,
+}
+
 #[derive(Debug, serde::Serialize, serde::Deserialize, Default)]
 pub struct UserApiRequest {
     #[serde(default)]
@@ -41,4 +59,115 @@ pub struct UserApiRequest {
     pub stream: String,
     #[serde(default)]
     pub duration: String,
-}
\ No newline at end of file
+    #[serde(default, rename = "type")]
+    pub playlist_type: String,
+    #[serde(default)]
+    pub output: String,
+    #[serde(default)]
+    pub cluster: String,
+    #[serde(default)]
+    pub group: String,
+    #[serde(default)]
+    pub format: String,
+    #[serde(default)]
+    pub sort: String,
+}
+
+/// Virtual ids of a favorites list or bouquet of a user, an empty list removes the bouquet.
+#[derive(Debug, Clone, Deserialize)]
+pub struct UserBouquetRequest {
+    #[serde(default)]
+    pub ids: BTreeSet<u32>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct MaintenanceRequest {
+    pub enabled: bool,
+    #[serde(default)]
+    pub message: Option<String>,
+}
+
+/// Marks an input as down for `minutes`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SimulatedOutageRequest {
+    pub minutes: u32,
+}
+
+/// Stored channel of a target which should be played in the ui player, `stream_id` is the virtual id.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct PlaybackTokenRequest {
+    pub target: String,
+    pub stream_id: u32,
+}
+
+/// Short link for a playlist or epg url, `expires_in` in seconds.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct ShortLinkRequest {
+    pub url: String,
+    #[serde(default)]
+    pub expires_in: Option<i64>,
+}
+
+/// Epg channel id which replaces the epg channel id of a channel for a user.
+#[derive(Debug, Clone, Deserialize)]
+pub struct EpgOverrideRequest {
+    pub epg_id: String,
+}
+
+/// Note and labels of a channel, an empty note without labels removes the note.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ChannelNoteRequest {
+    #[serde(default)]
+    pub note: String,
+    #[serde(default)]
+    pub labels: Vec<String>,
+}
+
+/// Channel notes with the query in the note or a label, all notes for an empty query.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ChannelNoteSearchRequest {
+    #[serde(default)]
+    pub q: String,
+}
+
+/// Users without activity since `dormant_days` days are flagged as dormant.
+#[derive(Debug, Clone, Deserialize)]
+pub struct UserActivityRequest {
+    #[serde(default = "default_dormant_days")]
+    pub dormant_days: u32,
+}
+
+const fn default_dormant_days() -> u32 { 30 }
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TrialUserRequest {
+    pub preset: String,
+}
+
+/// Changes for all users matching the filter, with `dry_run` only the matching users are returned.
+#[derive(Deserialize, Debug, Clone)]
+pub struct UserBulkRequest {
+    #[serde(default)]
+    pub filter: UserBulkFilter,
+    pub update: UserBulkUpdate,
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// Import of a xtream panel user export, `content` is the csv or json export.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct UserImportRequest {
+    pub content: String,
+    pub target: String,
+    /// panel bouquet id to target name
+    #[serde(default)]
+    pub bouquets: HashMap<String, String>,
+}
+
+/// New target, `source` is the index of the source in the sources file.
+#[derive(Deserialize, Debug, Clone)]
+pub struct TargetCreateRequest {
+    #[serde(default)]
+    pub source: usize,
+    pub target: ConfigTarget,
+}
@@ -5,6 +5,12 @@ pub enum StreamError {
     // StdIo(std::io::Error),
     ReceiverClosed,
     // ReceiverError(RecvError),
+    /// The provider did not respond with headers in time, seconds.
+    ConnectTimeout(u64),
+    /// The provider sent no data after the headers in time, seconds.
+    FirstByteTimeout(u64),
+    /// The provider sent no data while streaming, seconds.
+    Stalled(u64),
 }
 
 impl StreamError {
@@ -24,6 +30,9 @@ impl std::fmt::Display for StreamError {
             StreamError::Reqwest(e) => write!(f, "Reqwest error: {e}"),
             // StreamError::StdIo(e) => write!(f, "IO error: {e}"),
             StreamError::ReceiverClosed =>  write!(f, "Receiver closed"),
+            StreamError::ConnectTimeout(secs) => write!(f, "Connect timeout, no provider response within {secs}s"),
+            StreamError::FirstByteTimeout(secs) => write!(f, "First byte timeout, no provider data within {secs}s"),
+            StreamError::Stalled(secs) => write!(f, "Stream stalled, no provider data for {secs}s"),
             // StreamError::ReceiverError(e) =>  write!(f, "Receiver error {e}"),
         }
     }
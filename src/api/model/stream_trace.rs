@@ -0,0 +1,215 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use bytes::Bytes;
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use serde::Serialize;
+
+use crate::api::model::stream_error::StreamError;
+use crate::utils::request_utils::mask_sensitive_info;
+
+/// Request header to trace a stream, the value is a valid web ui token.
+pub const STREAM_TRACE_HEADER: &str = "x-stream-trace";
+const STREAM_TRACE_CAPACITY: usize = 50;
+// each chunk is counted, but only the first chunk is recorded as event
+const STREAM_TRACE_MAX_EVENTS: usize = 500;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StreamTraceEvent {
+    pub elapsed_ms: u64,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct StreamTraceReport {
+    pub id: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    pub url: String,
+    pub started_at: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<u64>,
+    pub bytes: u64,
+    pub chunks: u64,
+    pub reconnects: u32,
+    pub events: Vec<StreamTraceEvent>,
+}
+
+/// Detailed trace of one streaming session: upstream requests, reconnects, byte counts and timing.
+#[derive(Debug)]
+pub struct StreamTrace {
+    started: Instant,
+    report: Mutex<StreamTraceReport>,
+}
+
+impl StreamTrace {
+    fn elapsed_ms(&self) -> u64 {
+        u64::try_from(self.started.elapsed().as_millis()).unwrap_or(u64::MAX)
+    }
+
+    pub fn event(&self, message: &str) {
+        let elapsed_ms = self.elapsed_ms();
+        if let Ok(mut report) = self.report.lock() {
+            if report.events.len() < STREAM_TRACE_MAX_EVENTS {
+                report.events.push(StreamTraceEvent { elapsed_ms, message: mask_sensitive_info(message) });
+            }
+        }
+    }
+
+    pub fn reconnect(&self, message: &str) {
+        if let Ok(mut report) = self.report.lock() {
+            report.reconnects += 1;
+        }
+        self.event(message);
+    }
+
+    fn chunk(&self, size: usize) {
+        let first = self.report.lock().is_ok_and(|mut report| {
+            report.chunks += 1;
+            report.bytes += size as u64;
+            report.chunks == 1
+        });
+        if first {
+            self.event(&format!("First chunk with {size} bytes"));
+        }
+    }
+
+    fn finish(&self) {
+        let duration_ms = self.elapsed_ms();
+        let bytes = self.report.lock().map(|mut report| {
+            report.duration_ms = Some(duration_ms);
+            report.bytes
+        }).unwrap_or_default();
+        self.event(&format!("Stream closed after {bytes} bytes"));
+    }
+
+    pub fn get_report(&self) -> StreamTraceReport {
+        self.report.lock().map(|report| report.clone()).unwrap_or_default()
+    }
+}
+
+/// Finishes the trace when the client stream is dropped.
+struct StreamTraceGuard(Arc<StreamTrace>);
+
+impl Drop for StreamTraceGuard {
+    fn drop(&mut self) {
+        self.0.finish();
+    }
+}
+
+/// Counts the bytes sent to the client.
+pub fn trace_stream(stream: BoxStream<'static, Result<Bytes, StreamError>>, trace: Option<&Arc<StreamTrace>>) -> BoxStream<'static, Result<Bytes, StreamError>> {
+    match trace {
+        Some(trace) => {
+            let guard = StreamTraceGuard(Arc::clone(trace));
+            stream.map(move |chunk| {
+                match &chunk {
+                    Ok(bytes) => guard.0.chunk(bytes.len()),
+                    Err(err) => guard.0.event(&format!("Stream error: {err}")),
+                }
+                chunk
+            }).boxed()
+        }
+        None => stream,
+    }
+}
+
+/// The latest stream traces, the oldest trace is dropped when the capacity is exceeded.
+pub struct StreamTraces {
+    traces: Mutex<VecDeque<Arc<StreamTrace>>>,
+    capacity: usize,
+    next_id: AtomicU64,
+}
+
+impl Default for StreamTraces {
+    fn default() -> Self {
+        Self::new(STREAM_TRACE_CAPACITY)
+    }
+}
+
+impl StreamTraces {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            traces: Mutex::new(VecDeque::with_capacity(capacity)),
+            capacity,
+            next_id: AtomicU64::new(1),
+        }
+    }
+
+    pub fn start(&self, username: Option<&str>, url: &str) -> Arc<StreamTrace> {
+        let trace = Arc::new(StreamTrace {
+            started: Instant::now(),
+            report: Mutex::new(StreamTraceReport {
+                id: self.next_id.fetch_add(1, Ordering::Relaxed),
+                username: username.map(ToString::to_string),
+                url: mask_sensitive_info(url),
+                started_at: chrono::Utc::now().timestamp(),
+                ..StreamTraceReport::default()
+            }),
+        });
+        if let Ok(mut traces) = self.traces.lock() {
+            if traces.len() >= self.capacity {
+                traces.pop_front();
+            }
+            traces.push_back(Arc::clone(&trace));
+        }
+        trace
+    }
+
+    /// The reports without the events.
+    pub fn get_summaries(&self) -> Vec<StreamTraceReport> {
+        self.traces.lock().map(|traces| traces.iter().map(|trace| {
+            let mut report = trace.get_report();
+            report.events.clear();
+            report
+        }).collect()).unwrap_or_default()
+    }
+
+    pub fn get_report(&self, id: u64) -> Option<StreamTraceReport> {
+        self.traces.lock().ok()?.iter().map(|trace| trace.get_report()).find(|report| report.id == id)
+    }
+
+    /// Removes the traces of the user, returns the count of removed traces.
+    pub fn purge_user(&self, username: &str) -> usize {
+        let Ok(mut traces) = self.traces.lock() else { return 0; };
+        let count = traces.len();
+        traces.retain(|trace| trace.get_report().username.as_deref() != Some(username));
+        count - traces.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use futures::StreamExt;
+
+    use crate::api::model::stream_trace::{trace_stream, StreamTraces};
+
+    #[actix_rt::test]
+    async fn stream_trace_test() {
+        let traces = StreamTraces::new(2);
+        let trace = traces.start(Some("user"), "http://provider.tv/live/user/secret/1.ts");
+        trace.event("Provider responded with status 200 OK");
+        trace.reconnect("Reconnecting stream");
+        let stream = futures::stream::iter(vec![Ok(Bytes::from_static(b"abc")), Ok(Bytes::from_static(b"de"))]).boxed();
+        let chunks: Vec<_> = trace_stream(stream, Some(&trace)).collect().await;
+        assert_eq!(chunks.len(), 2);
+        let report = traces.get_report(1).unwrap();
+        assert_eq!(report.bytes, 5);
+        assert_eq!(report.chunks, 2);
+        assert_eq!(report.reconnects, 1);
+        assert!(report.duration_ms.is_some());
+        assert!(!report.url.contains("secret"));
+        assert_eq!(report.events.len(), 4);
+        traces.start(None, "http://provider.tv/live/2.ts");
+        traces.start(None, "http://provider.tv/live/3.ts");
+        assert!(traces.get_report(1).is_none());
+        assert!(traces.get_summaries().iter().all(|summary| summary.events.is_empty()));
+        traces.start(Some("user"), "http://provider.tv/live/user/secret/4.ts");
+        assert_eq!(traces.purge_user("user"), 1);
+        assert_eq!(traces.get_summaries().len(), 1);
+    }
+}
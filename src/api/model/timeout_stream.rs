@@ -0,0 +1,185 @@
+use crate::api::model::provider_stream_factory::ResponseStream;
+use crate::api::model::stream_error::StreamError;
+use crate::api::model::stream_trace::StreamTrace;
+use crate::model::config::{StreamTimeoutConfig, StreamTimeoutsConfig};
+use crate::model::playlist::PlaylistItemType;
+use futures::{Stream, StreamExt};
+use log::debug;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::time::{sleep, Instant, Sleep};
+
+const DEFAULT_CONNECT_TIMEOUT_SECS: u32 = 10;
+// live streams start immediately, vod providers often seek or transcode before sending
+const DEFAULT_LIVE_FIRST_BYTE_TIMEOUT_SECS: u32 = 10;
+const DEFAULT_VOD_FIRST_BYTE_TIMEOUT_SECS: u32 = 30;
+const DEFAULT_LIVE_STALL_TIMEOUT_SECS: u32 = 15;
+const DEFAULT_VOD_STALL_TIMEOUT_SECS: u32 = 60;
+
+/// Timeouts of a provider stream, `None` waits without limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamTimeouts {
+    pub connect: Option<Duration>,
+    pub first_byte: Option<Duration>,
+    pub stall: Option<Duration>,
+}
+
+fn get_timeout(value: Option<u32>, default_secs: u32) -> Option<Duration> {
+    match value.unwrap_or(default_secs) {
+        0 => None,
+        secs => Some(Duration::from_secs(u64::from(secs))),
+    }
+}
+
+impl StreamTimeouts {
+    /// The configured timeouts for the item type, missing values are set to the defaults.
+    pub fn new(config: Option<&StreamTimeoutsConfig>, item_type: PlaylistItemType) -> Self {
+        let (timeout_config, first_byte_secs, stall_secs) = match item_type {
+            PlaylistItemType::Video | PlaylistItemType::Catchup =>
+                (config.and_then(|c| c.vod.as_ref()), DEFAULT_VOD_FIRST_BYTE_TIMEOUT_SECS, DEFAULT_VOD_STALL_TIMEOUT_SECS),
+            PlaylistItemType::Series | PlaylistItemType::SeriesInfo =>
+                (config.and_then(|c| c.series.as_ref()), DEFAULT_VOD_FIRST_BYTE_TIMEOUT_SECS, DEFAULT_VOD_STALL_TIMEOUT_SECS),
+            PlaylistItemType::Live | PlaylistItemType::LiveUnknown | PlaylistItemType::LiveHls =>
+                (config.and_then(|c| c.live.as_ref()), DEFAULT_LIVE_FIRST_BYTE_TIMEOUT_SECS, DEFAULT_LIVE_STALL_TIMEOUT_SECS),
+        };
+        let StreamTimeoutConfig { connect, first_byte, stall } = timeout_config.cloned().unwrap_or_default();
+        Self {
+            connect: get_timeout(connect, DEFAULT_CONNECT_TIMEOUT_SECS),
+            first_byte: get_timeout(first_byte, first_byte_secs),
+            stall: get_timeout(stall, stall_secs),
+        }
+    }
+}
+
+/// Sends the provider request, fails with `ConnectTimeout` if the provider does not respond in time.
+pub async fn send_with_timeout(request: reqwest::RequestBuilder, timeouts: &StreamTimeouts) -> Result<reqwest::Response, StreamError> {
+    match timeouts.connect {
+        Some(connect) => tokio::time::timeout(connect, request.send()).await
+            .map_err(|_| StreamError::ConnectTimeout(connect.as_secs()))?
+            .map_err(|err| StreamError::reqwest(&err)),
+        None => request.send().await.map_err(|err| StreamError::reqwest(&err)),
+    }
+}
+
+/// Ends the provider stream if no data is received in time, the first data after `first_byte`,
+/// following data after `stall`. The timer runs only while the stream is polled, a client which
+/// stops reading does not stall the stream.
+/// With `reconnect` the stream ends without error to continue with a reconnect.
+pub struct TimeoutStream {
+    inner: ResponseStream,
+    timeouts: StreamTimeouts,
+    reconnect: bool,
+    trace: Option<Arc<StreamTrace>>,
+    timer: Pin<Box<Sleep>>,
+    waiting: bool,
+    received: bool,
+    finished: bool,
+}
+
+impl TimeoutStream {
+    pub fn new(inner: ResponseStream, timeouts: StreamTimeouts, reconnect: bool, trace: Option<Arc<StreamTrace>>) -> Self {
+        Self {
+            inner,
+            timeouts,
+            reconnect,
+            trace,
+            timer: Box::pin(sleep(Duration::ZERO)),
+            waiting: false,
+            received: false,
+            finished: false,
+        }
+    }
+
+    fn get_timeout(&self) -> Option<Duration> {
+        if self.received { self.timeouts.stall } else { self.timeouts.first_byte }
+    }
+
+    fn get_timeout_error(&self, timeout: Duration) -> StreamError {
+        if self.received { StreamError::Stalled(timeout.as_secs()) } else { StreamError::FirstByteTimeout(timeout.as_secs()) }
+    }
+}
+
+impl Stream for TimeoutStream {
+    type Item = Result<bytes::Bytes, StreamError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.finished {
+            return Poll::Ready(None);
+        }
+        match self.inner.poll_next_unpin(cx) {
+            Poll::Ready(Some(item)) => {
+                self.received = true;
+                self.waiting = false;
+                Poll::Ready(Some(item))
+            }
+            Poll::Ready(None) => {
+                self.finished = true;
+                Poll::Ready(None)
+            }
+            Poll::Pending => {
+                let Some(timeout) = self.get_timeout() else { return Poll::Pending; };
+                if !self.waiting {
+                    self.waiting = true;
+                    self.timer.as_mut().reset(Instant::now() + timeout);
+                }
+                if self.timer.as_mut().poll(cx).is_pending() {
+                    return Poll::Pending;
+                }
+                self.finished = true;
+                let err = self.get_timeout_error(timeout);
+                debug!("{err}");
+                if let Some(trace) = &self.trace {
+                    trace.event(&err.to_string());
+                }
+                if self.reconnect {
+                    Poll::Ready(None)
+                } else {
+                    Poll::Ready(Some(Err(err)))
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::model::stream_error::StreamError;
+    use crate::api::model::timeout_stream::{StreamTimeouts, TimeoutStream};
+    use crate::model::config::{StreamTimeoutConfig, StreamTimeoutsConfig};
+    use crate::model::playlist::PlaylistItemType;
+    use bytes::Bytes;
+    use futures::{stream, StreamExt};
+    use std::time::Duration;
+
+    #[test]
+    fn stream_timeouts_test() {
+        let config = StreamTimeoutsConfig {
+            live: Some(StreamTimeoutConfig { connect: Some(5), first_byte: None, stall: Some(0) }),
+            ..StreamTimeoutsConfig::default()
+        };
+        let live = StreamTimeouts::new(Some(&config), PlaylistItemType::Live);
+        assert_eq!(live, StreamTimeouts { connect: Some(Duration::from_secs(5)), first_byte: Some(Duration::from_secs(10)), stall: None });
+        let vod = StreamTimeouts::new(Some(&config), PlaylistItemType::Video);
+        assert_eq!(vod, StreamTimeouts { connect: Some(Duration::from_secs(10)), first_byte: Some(Duration::from_secs(30)), stall: Some(Duration::from_secs(60)) });
+    }
+
+    #[actix_rt::test]
+    async fn timeout_stream_test() {
+        let timeouts = StreamTimeouts { connect: None, first_byte: Some(Duration::from_millis(20)), stall: Some(Duration::from_millis(20)) };
+        let stalled = stream::iter([Ok(Bytes::from_static(b"data"))]).chain(stream::pending()).boxed();
+        let mut timeout_stream = TimeoutStream::new(stalled, timeouts, false, None);
+        assert!(timeout_stream.next().await.is_some_and(|item| item.is_ok()));
+        assert!(matches!(timeout_stream.next().await, Some(Err(StreamError::Stalled(_)))));
+        assert!(timeout_stream.next().await.is_none());
+
+        let mut timeout_stream = TimeoutStream::new(stream::pending().boxed(), timeouts, false, None);
+        assert!(matches!(timeout_stream.next().await, Some(Err(StreamError::FirstByteTimeout(_)))));
+
+        // reconnecting streams end without error
+        let mut timeout_stream = TimeoutStream::new(stream::pending().boxed(), timeouts, true, None);
+        assert!(timeout_stream.next().await.is_none());
+    }
+}
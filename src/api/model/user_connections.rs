@@ -0,0 +1,68 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// Active streams per user, users with `max_connections` are limited.
+#[derive(Debug, Default)]
+pub struct UserConnections {
+    users: Mutex<HashMap<String, u32>>,
+}
+
+/// Holds a stream of the user, the stream is released when the guard is dropped.
+#[derive(Debug)]
+pub struct UserConnectionGuard {
+    connections: Arc<UserConnections>,
+    username: String,
+}
+
+impl Drop for UserConnectionGuard {
+    fn drop(&mut self) {
+        self.connections.release(&self.username);
+    }
+}
+
+impl UserConnections {
+    /// Returns a guard if the user has less than `max` active streams.
+    pub fn acquire(self: &Arc<Self>, username: &str, max: u32) -> Option<UserConnectionGuard> {
+        let mut users = self.users.lock().ok()?;
+        let streams = users.entry(username.to_string()).or_default();
+        if *streams >= max {
+            return None;
+        }
+        *streams += 1;
+        Some(UserConnectionGuard { connections: Arc::clone(self), username: username.to_string() })
+    }
+
+    pub fn get_connections(&self) -> HashMap<String, u32> {
+        self.users.lock().map(|users| users.clone()).unwrap_or_default()
+    }
+
+    fn release(&self, username: &str) {
+        if let Ok(mut users) = self.users.lock() {
+            if let Some(streams) = users.get_mut(username) {
+                *streams = streams.saturating_sub(1);
+                if *streams == 0 {
+                    users.remove(username);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::api::model::user_connections::UserConnections;
+
+    #[test]
+    fn user_connections_test() {
+        let connections = Arc::new(UserConnections::default());
+        let first = connections.acquire("user", 2);
+        let second = connections.acquire("user", 2);
+        assert!(first.is_some() && second.is_some());
+        assert!(connections.acquire("user", 2).is_none());
+        assert!(connections.acquire("other", 1).is_some());
+        drop(first);
+        assert!(connections.acquire("user", 2).is_some());
+    }
+}
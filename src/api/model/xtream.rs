@@ -50,9 +50,9 @@ impl XtreamAuthorizationResponse {
                 allowed_output_formats: Vec::from(["ts".to_string(), "m3u8".to_string(), "rtmp".to_string()]),
                 auth: 1,
                 created_at: (now - Duration::days(365)).timestamp(), // fake
-                exp_date: (now + Duration::days(365)).timestamp(), // fake
+                exp_date: user.exp_date.unwrap_or_else(|| (now + Duration::days(365)).timestamp()), // fake without exp_date
                 is_trial: "0".to_string(),
-                max_connections: "1".to_string(),
+                max_connections: user.max_connections.unwrap_or(1).to_string(),
                 message: server_info.message.to_string(),
                 password: user.password.to_string(),
                 username: user.username.to_string(),
@@ -3,10 +3,12 @@ use std::str::FromStr;
 use std::time::{Duration, Instant, SystemTime};
 use chrono::{DateTime, FixedOffset, Local};
 use cron::Schedule;
-use log::error;
+use std::future::Future;
+use log::{error, info};
 use crate::exit;
 use crate::model::config::{Config, ProcessTargets};
 use crate::processing::playlist_processor::exec_processing;
+use crate::utils::download::{refresh_input_snapshot, InputSnapshotKind};
 
 fn datetime_to_instant(datetime: DateTime<FixedOffset>) -> Instant {
     // Convert DateTime<FixedOffset> to SystemTime
@@ -24,7 +26,11 @@ fn datetime_to_instant(datetime: DateTime<FixedOffset>) -> Instant {
     Instant::now() + duration_until
 }
 
-pub async fn start_scheduler(client: Arc<reqwest::Client>, expression: &str, config: Arc<Config>, targets: Arc<ProcessTargets>) -> ! {
+async fn run_schedule<F, Fut>(expression: &str, mut task: F) -> !
+where
+    F: FnMut() -> Fut,
+    Fut: Future,
+{
     match Schedule::from_str(expression) {
         Ok(schedule) => {
             let offset = *Local::now().offset();
@@ -32,7 +38,7 @@ pub async fn start_scheduler(client: Arc<reqwest::Client>, expression: &str, con
                 let mut upcoming = schedule.upcoming(offset).take(1);
                 if let Some(datetime) = upcoming.next() {
                     actix_web::rt::time::sleep_until(actix_rt::time::Instant::from(datetime_to_instant(datetime))).await;
-                    exec_processing(Arc::clone(&client), Arc::clone(&config), Arc::clone(&targets)).await;
+                    task().await;
                  }
             }
         }
@@ -40,6 +46,36 @@ pub async fn start_scheduler(client: Arc<reqwest::Client>, expression: &str, con
     }
 }
 
+pub async fn start_scheduler(client: Arc<reqwest::Client>, expression: &str, config: Arc<Config>, targets: Arc<ProcessTargets>) -> ! {
+    run_schedule(expression, || exec_processing(Arc::clone(&client), Arc::clone(&config), Arc::clone(&targets))).await
+}
+
+/// Refreshes the snapshot of an input, the targets are processed by their own schedule.
+pub async fn start_input_scheduler(client: Arc<reqwest::Client>, expression: &str, config: Arc<Config>, input_id: u16, kind: InputSnapshotKind) -> ! {
+    run_schedule(expression, || {
+        let client = Arc::clone(&client);
+        let config = Arc::clone(&config);
+        async move {
+            if config.t_maintenance.is_enabled() {
+                return;
+            }
+            if let Some(input) = config.get_input_by_id(input_id) {
+                let input_name = input.name.as_deref().unwrap_or_default();
+                match refresh_input_snapshot(client, &config, input, kind).await {
+                    Ok(()) => {
+                        info!("Refreshed {kind:?} snapshot of input {input_name}");
+                        config.t_input_health.record_success(input, config.messaging.as_ref());
+                    }
+                    Err(err) => {
+                        error!("Failed to refresh {kind:?} snapshot of input {input_name}: {}", err.message);
+                        config.t_input_health.record_failure(input, config.messaging.as_ref(), &err.message);
+                    }
+                }
+            }
+        }
+    }).await
+}
+
 #[cfg(test)]
 mod tests {
     use std::str::FromStr;
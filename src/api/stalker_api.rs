@@ -0,0 +1,289 @@
+// Subset of the stalker (ministra) portal api used by MAG boxes and stalker clients, only live channels are served.
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use log::{debug, error};
+use serde_json::{json, Value};
+
+use crate::api::api_utils::{get_category_translations, get_user_target_by_credentials, get_user_target_by_mac, maintenance_response, stream_response};
+use crate::api::model::app_state::AppState;
+use crate::api::model::request::UserApiRequest;
+use crate::api::xmltv_api::get_epg_path_for_target;
+use crate::auth::password::generate_random_string;
+use crate::auth::signature::sign_content;
+use crate::model::api_proxy::{normalize_mac, ProxyType, ProxyUserCredentials};
+use crate::model::config::{Config, ConfigTarget, TargetType};
+use crate::repository::epg_repository::epg_read_channel_programmes;
+use crate::repository::stalker_repository::{stalker_read_playlist, StalkerChannel, StalkerPlaylist};
+use crate::utils::request_utils::mask_sensitive_info;
+
+pub const STALKER_STREAM_PATH: &str = "stalker_portal/stream";
+const STALKER_MAX_PAGE_ITEMS: usize = 14;
+const DEFAULT_SHORT_EPG_SIZE: usize = 4;
+const COOKIE_MAC: &str = "mac";
+const STALKER_SIGNATURE_LEN: usize = 32;
+
+const TYPE_STB: &str = "stb";
+const TYPE_ITV: &str = "itv";
+const TYPE_ACCOUNT_INFO: &str = "account_info";
+const TYPE_WATCHDOG: &str = "watchdog";
+
+#[derive(Debug, Default, serde::Deserialize)]
+struct StalkerApiRequest {
+    #[serde(default, rename = "type")]
+    request_type: String,
+    #[serde(default)]
+    action: String,
+    #[serde(default)]
+    genre: String,
+    #[serde(default)]
+    p: String,
+    #[serde(default)]
+    cmd: String,
+    #[serde(default)]
+    ch_id: String,
+    #[serde(default)]
+    size: String,
+    #[serde(default)]
+    mac: String,
+}
+
+fn stalker_response(js: Value) -> HttpResponse {
+    HttpResponse::Ok().json(json!({"js": js}))
+}
+
+/// The mac of the box is sent as cookie, some clients send it as query parameter.
+fn get_request_mac(req: &HttpRequest, api_req: &StalkerApiRequest) -> Option<String> {
+    let mac = req.cookie(COOKIE_MAC).map(|cookie| cookie.value().replace("%3A", ":").replace("%3a", ":"))
+        .unwrap_or_else(|| api_req.mac.clone());
+    Some(normalize_mac(&mac)).filter(|mac| !mac.is_empty())
+}
+
+/// The stream path of a channel is signed with the password of the user, the password is not part of the command
+/// and a leaked command only opens this channel.
+fn sign_channel_path(user: &ProxyUserCredentials, channel_id: u32) -> String {
+    let signature = sign_content(user.password.as_bytes(), format!("{}/{channel_id}", user.username).as_bytes());
+    signature.trim_start_matches("sha256=").chars().take(STALKER_SIGNATURE_LEN).collect()
+}
+
+fn get_stream_url(config: &Config, user: &ProxyUserCredentials, channel: &StalkerChannel) -> String {
+    let server_info = config.get_user_server_info(user);
+    format!("{}/{STALKER_STREAM_PATH}/{}/{}/{}", server_info.get_base_url(), user.username, sign_channel_path(user, channel.id), channel.id)
+}
+
+fn channel_to_json(config: &Config, user: &ProxyUserCredentials, channel: &StalkerChannel) -> Value {
+    json!({
+        "id": channel.id.to_string(),
+        "name": channel.name,
+        "number": channel.number.to_string(),
+        "cmd": format!("ffmpeg {}", get_stream_url(config, user, channel)),
+        "logo": channel.logo,
+        "tv_genre_id": channel.genre_id.to_string(),
+        "xmltv_id": channel.epg_channel_id,
+        "censored": 0,
+        "status": 1,
+        "use_http_tmp_link": 0,
+        "enable_tv_archive": 0,
+    })
+}
+
+/// The page of the channels, pages start with 1.
+fn get_channel_page(channels: &[&StalkerChannel], page: &str) -> (usize, Vec<usize>) {
+    let page = page.trim().parse::<usize>().unwrap_or(1).max(1);
+    let start = (page - 1) * STALKER_MAX_PAGE_ITEMS;
+    (page, (start..channels.len().min(start + STALKER_MAX_PAGE_ITEMS)).collect())
+}
+
+fn get_genres(playlist: &StalkerPlaylist, req: &HttpRequest, config: &Config, user: &ProxyUserCredentials) -> Value {
+    let translations = get_category_translations(config, user, req);
+    let mut genres = vec![json!({"id": "*", "title": "All", "alias": "All", "censored": 0})];
+    genres.extend(playlist.genres.iter().map(|genre| {
+        let title = translations.and_then(|translations| translations.get(&genre.title)).unwrap_or(&genre.title);
+        json!({"id": genre.id.to_string(), "title": title, "alias": title, "censored": 0})
+    }));
+    Value::Array(genres)
+}
+
+fn get_channel_list(config: &Config, user: &ProxyUserCredentials, channels: &[&StalkerChannel], page: Option<&str>) -> Value {
+    let (cur_page, indices) = match page {
+        Some(page) => get_channel_page(channels, page),
+        None => (0, (0..channels.len()).collect()),
+    };
+    let data: Vec<Value> = indices.into_iter().map(|index| channel_to_json(config, user, channels[index])).collect();
+    json!({
+        "total_items": channels.len(),
+        "max_page_items": if page.is_some() { STALKER_MAX_PAGE_ITEMS } else { channels.len() },
+        "selected_item": 0,
+        "cur_page": cur_page,
+        "data": data,
+    })
+}
+
+async fn get_short_epg(config: &Config, target: &ConfigTarget, playlist: &StalkerPlaylist, api_req: &StalkerApiRequest) -> Value {
+    let Some(channel) = api_req.ch_id.trim().parse::<u32>().ok().and_then(|channel_id| playlist.get_channel(channel_id)) else { return json!([]) };
+    let Some(epg_path) = get_epg_path_for_target(config, target).filter(|_| !channel.epg_channel_id.is_empty()) else { return json!([]) };
+    let size = api_req.size.trim().parse::<usize>().ok().filter(|size| *size > 0).unwrap_or(DEFAULT_SHORT_EPG_SIZE);
+    let now = chrono::Utc::now().timestamp();
+    let format_time = |timestamp: i64| chrono::DateTime::from_timestamp(timestamp, 0).map(|time| time.format("%Y-%m-%d %H:%M:%S").to_string()).unwrap_or_default();
+    let programmes: Vec<Value> = epg_read_channel_programmes(config, &epg_path, &channel.epg_channel_id, Some(now), None).await.into_iter().take(size)
+        .map(|programme| json!({
+            "id": format!("{}_{}", channel.id, programme.start),
+            "ch_id": channel.id.to_string(),
+            "name": programme.title.unwrap_or_default(),
+            "descr": programme.desc.unwrap_or_default(),
+            "time": format_time(programme.start),
+            "time_to": format_time(programme.stop),
+            "duration": programme.stop - programme.start,
+            "start_timestamp": programme.start,
+            "stop_timestamp": programme.stop,
+        }))
+        .collect();
+    Value::Array(programmes)
+}
+
+/// The channel id is the last path segment of the stream url of the command.
+fn get_command_channel_id(cmd: &str) -> Option<u32> {
+    cmd.trim().rsplit('/').next().and_then(|channel_id| channel_id.parse::<u32>().ok())
+}
+
+async fn stalker_api(
+    req: HttpRequest,
+    api_req: web::Query<StalkerApiRequest>,
+    app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let api_req = api_req.into_inner();
+    let request_type = api_req.request_type.trim().to_lowercase();
+    let action = api_req.action.trim().to_lowercase();
+    if request_type == TYPE_STB && action == "handshake" {
+        return stalker_response(json!({"token": generate_random_string(32)}));
+    }
+    let Some(mac) = get_request_mac(&req, &api_req) else { return HttpResponse::BadRequest().finish() };
+    let Some((user, target)) = get_user_target_by_mac(&mac, &app_state, &req) else {
+        debug!("No stalker user for mac {mac}");
+        return HttpResponse::BadRequest().finish();
+    };
+    if !target.has_output(&TargetType::Stalker) {
+        return HttpResponse::BadRequest().finish();
+    }
+    let config = &app_state.config;
+    match (request_type.as_str(), action.as_str()) {
+        (TYPE_STB, "get_profile") => {
+            let server_info = config.get_user_server_info(&user);
+            stalker_response(json!({
+                "id": 1,
+                "name": user.username,
+                "login": user.username,
+                "mac": mac,
+                "status": 0,
+                "blocked": "0",
+                "timezone": server_info.timezone,
+                "locale": "en_GB.utf8",
+                "stb_type": "MAG250",
+                "watchdog_timeout": 120,
+            }))
+        }
+        (TYPE_STB, "do_auth") => stalker_response(json!(true)),
+        (TYPE_ACCOUNT_INFO, "get_main_info") => {
+            let expiry = user.exp_date.and_then(|exp_date| chrono::DateTime::from_timestamp(exp_date, 0))
+                .map(|exp_date| exp_date.format("%Y-%m-%d").to_string()).unwrap_or_default();
+            stalker_response(json!({"mac": mac, "phone": expiry, "message": ""}))
+        }
+        (TYPE_WATCHDOG, "get_events") => stalker_response(json!({"data": {"msgs": 0, "additional_services_on": 1}})),
+        (TYPE_ITV, "create_link") => {
+            let cmd = api_req.cmd.trim();
+            match get_command_channel_id(cmd) {
+                Some(channel_id) => stalker_response(json!({"id": channel_id.to_string(), "cmd": cmd})),
+                None => HttpResponse::BadRequest().finish(),
+            }
+        }
+        (TYPE_ITV, "get_genres" | "get_all_channels" | "get_ordered_list" | "get_short_epg") => {
+            let Some(playlist) = stalker_read_playlist(config, &target.name).await else {
+                error!("Failed to read stalker playlist for target {}", target.name);
+                return HttpResponse::NoContent().finish();
+            };
+            match action.as_str() {
+                "get_genres" => stalker_response(get_genres(&playlist, &req, config, &user)),
+                "get_all_channels" => stalker_response(get_channel_list(config, &user, &playlist.get_genre_channels("*"), None)),
+                "get_ordered_list" => stalker_response(get_channel_list(config, &user, &playlist.get_genre_channels(&api_req.genre), Some(&api_req.p))),
+                _ => stalker_response(get_short_epg(config, target, &playlist, &api_req).await),
+            }
+        }
+        _ => stalker_response(json!([])),
+    }
+}
+
+async fn stalker_api_stream(
+    req: HttpRequest,
+    path: web::Path<(String, String, String)>,
+    app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let (username, signature, channel_id) = path.into_inner();
+    let Ok(channel_id) = channel_id.parse::<u32>() else { return HttpResponse::BadRequest().finish() };
+    let credentials = app_state.config.get_user_credentials(&username);
+    let Some(credentials) = credentials.as_ref().filter(|credentials| sign_channel_path(credentials, channel_id) == signature) else {
+        if !credentials.is_some_and(|credentials| credentials.no_log) {
+            debug!("Invalid stalker stream signature for user {username}");
+        }
+        return HttpResponse::Forbidden().finish();
+    };
+    let api_req = UserApiRequest::default();
+    let Some((user, target)) = get_user_target_by_credentials(&credentials.username, &credentials.password, &api_req, &app_state, &req) else { return HttpResponse::BadRequest().finish() };
+    if app_state.config.t_maintenance.is_enabled() {
+        return maintenance_response(&app_state, &req).await;
+    }
+    if !target.has_output(&TargetType::Stalker) {
+        return HttpResponse::BadRequest().finish();
+    }
+    let Some(channel) = stalker_read_playlist(&app_state.config, &target.name).await
+        .and_then(|playlist| playlist.get_channel(channel_id).cloned()) else { return HttpResponse::BadRequest().finish() };
+
+    if user.proxy == ProxyType::Redirect {
+        debug!("Redirecting stream request to {}", mask_sensitive_info(&channel.url));
+        return HttpResponse::Found().insert_header(("Location", channel.url)).finish();
+    }
+
+    let input = app_state.config.get_input_by_id(channel.input_id)
+        .map(|input| input.with_stream_headers(&channel.user_agent, &channel.referrer));
+    stream_response(&app_state, &channel.url, &req, input.as_deref(), channel.item_type, Some(target), Some(&user)).await
+}
+
+pub fn stalker_api_register(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("/stalker_portal/server/load.php").route(web::get().to(stalker_api)));
+    cfg.service(web::resource("/portal.php").route(web::get().to(stalker_api)));
+    cfg.service(web::resource(format!("/{STALKER_STREAM_PATH}/{{username}}/{{signature}}/{{channel_id}}")).route(web::get().to(stalker_api_stream)).route(web::head().to(stalker_api_stream)));
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::stalker_api::{get_channel_page, get_command_channel_id, sign_channel_path};
+    use crate::model::api_proxy::ProxyUserCredentials;
+    use crate::model::preview_user::{create_preview_credentials, PreviewToken};
+    use crate::repository::stalker_repository::StalkerChannel;
+    use crate::model::playlist::PlaylistItemType;
+
+    #[test]
+    fn stalker_channel_page_test() {
+        let channel = StalkerChannel {
+            id: 1, number: 1, name: String::new(), logo: String::new(), genre_id: 1, epg_channel_id: String::new(),
+            url: String::new(), user_agent: String::new(), referrer: String::new(), input_id: 1, item_type: PlaylistItemType::Live,
+        };
+        let channels = vec![&channel; 30];
+        assert_eq!(get_channel_page(&channels, "1"), (1, (0..14).collect()));
+        assert_eq!(get_channel_page(&channels, "0"), (1, (0..14).collect()));
+        assert_eq!(get_channel_page(&channels, "3"), (3, vec![28, 29]));
+        assert!(get_channel_page(&channels, "4").1.is_empty());
+        assert_eq!(get_command_channel_id("ffmpeg http://localhost:8901/stalker_portal/stream/user/pwd/42"), Some(42));
+        assert_eq!(get_command_channel_id("ffmpeg http://localhost:8901/"), None);
+    }
+
+    #[test]
+    fn stalker_channel_signature_test() {
+        let user = ProxyUserCredentials { username: "max".to_string(), password: "secret".to_string(), ..create_preview_credentials(&PreviewToken { token: "token".to_string(), expires: 0 }) };
+        let signature = sign_channel_path(&user, 42);
+        assert_eq!(signature.len(), 32);
+        assert!(!signature.contains("secret"));
+        assert_eq!(sign_channel_path(&user, 42), signature);
+        assert_ne!(sign_channel_path(&user, 43), signature);
+        let other = ProxyUserCredentials { password: "other".to_string(), ..user };
+        assert_ne!(sign_channel_path(&other, 42), signature);
+    }
+}
@@ -1,22 +1,40 @@
+use std::collections::HashSet;
 use std::sync::Arc;
 
+use actix_web::http::header::CONTENT_DISPOSITION;
 use actix_web::middleware::Condition;
-use actix_web::{web, HttpResponse};
+use actix_web::{web, HttpRequest, HttpResponse};
 use actix_web_httpauth::middleware::HttpAuthentication;
-use log::error;
+use log::{error, info, warn};
 use serde_json::json;
+use url::Url;
 
+use crate::api::api_utils::stream_response;
 use crate::api::download_api;
+use crate::api::xmltv_api::get_epg_path_for_target;
 use crate::api::model::app_state::AppState;
+use crate::api::model::diagnostics::PlayerErrorReport;
 use crate::api::model::config::{ServerConfig, ServerInputConfig, ServerSourceConfig, ServerTargetConfig};
-use crate::api::model::request::PlaylistRequest;
-use crate::auth::authenticator::validator;
+use crate::api::model::request::{ChannelNoteRequest, ChannelNoteSearchRequest, EpgOverrideRequest, EpgProgrammeRequest, MaintenanceRequest, PlaybackTokenRequest, PlaylistRequest, ShortLinkRequest, SimulatedOutageRequest, TargetCreateRequest, TrialUserRequest, UserActivityRequest, UserBouquetRequest, UserBulkRequest, UserImportRequest};
+use crate::auth::authenticator::{create_playback_token, validator, verify_playback_token};
 use crate::m3u_filter_error::M3uFilterError;
 use crate::model::api_proxy::{ApiProxyConfig, ApiProxyServerInfo, ProxyUserCredentials, TargetUser};
+use crate::model::preview_user::{create_preview_credentials, PREVIEW_USERNAME};
 use crate::model::config::{validate_targets, Config, ConfigDto, ConfigInput, ConfigInputOptions, ConfigSource, ConfigTarget, InputType};
+use crate::processing::parse_report::InputParseReport;
 use crate::processing::playlist_processor;
+use crate::processing::trial_user::provision_trial_user;
+use crate::processing::user_bulk::bulk_update_users;
+use crate::processing::user_import::{import_users, UserImportOptions};
+use crate::repository::epg_repository::epg_read_channel_programmes;
+use crate::repository::playlist_repository::{get_target_stream, load_target_playlist};
+use crate::repository::channel_notes_repository::{is_valid_channel_uuid, ChannelNote};
+use crate::repository::report_repository::{read_target_report, REPORT_MAPPING, REPORT_PARSER, REPORT_TIMING, REPORT_VALIDATION};
+use crate::repository::storage_compaction::compact_storage;
+use crate::utils::lru_cache::STALE_PART_FILE_AGE;
 use crate::utils::request_utils::mask_sensitive_info;
-use crate::utils::{config_reader, download};
+use crate::utils::config_reader::TargetChange;
+use crate::utils::{config_reader, download, sanitize};
 
 fn intern_save_config_api_proxy(backup_dir: &str, api_proxy: &ApiProxyConfig, file_path: &str) -> Option<M3uFilterError> {
     match config_reader::save_api_proxy(file_path, backup_dir, api_proxy) {
@@ -53,10 +71,315 @@ async fn save_config_api_proxy_user(
             return HttpResponse::InternalServerError().json(json!({"error": err.to_string()}));
         }
         api_proxy.user.iter_mut().flat_map(|t| &mut t.credentials).for_each(|c| c.prepare(true));
+        sanitize::set_log_excluded_users(api_proxy.get_log_excluded_users());
     }
     HttpResponse::Ok().finish()
 }
 
+async fn import_config_api_proxy_users(
+    req: web::Json<UserImportRequest>,
+    app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let UserImportRequest { content, target, bouquets } = req.0;
+    match import_users(&app_state.config, &content, &UserImportOptions { target, bouquets }) {
+        Ok(report) => HttpResponse::Ok().json(report),
+        Err(err) => {
+            error!("Failed to import users {err}");
+            HttpResponse::BadRequest().json(json!({"error": err.to_string()}))
+        }
+    }
+}
+
+async fn create_trial_user(
+    req: web::Json<TrialUserRequest>,
+    app_state: web::Data<AppState>,
+) -> HttpResponse {
+    match provision_trial_user(&app_state.config, &req.preset) {
+        Ok(trial_user) => HttpResponse::Ok().json(trial_user),
+        Err(err) => {
+            error!("Failed to create trial user {err}");
+            HttpResponse::BadRequest().json(json!({"error": err.to_string()}))
+        }
+    }
+}
+
+/// Creates the preview token of the target, a previous token is revoked.
+async fn preview_user_create(
+    path: web::Path<String>,
+    app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let Some(target) = app_state.config.get_target_by_name(&path.into_inner()) else {
+        return HttpResponse::NotFound().json(json!({"error": "Unknown target"}));
+    };
+    let Some(preview_token) = app_state.preview_users.create(&target.name) else {
+        return HttpResponse::InternalServerError().finish();
+    };
+    info!("Created preview user for target {}", target.name);
+    let server_url = app_state.config.get_user_server_info(&create_preview_credentials(&preview_token)).get_base_url();
+    let token = preview_token.token;
+    HttpResponse::Ok().json(json!({
+        "target": target.name,
+        "username": PREVIEW_USERNAME,
+        "password": token,
+        "token": token,
+        "server_url": server_url,
+        "m3u_url": format!("{server_url}/get.php?token={token}&type=m3u_plus"),
+        "epg_url": format!("{server_url}/xmltv.php?token={token}"),
+        "expires": preview_token.expires,
+    }))
+}
+
+async fn preview_user_delete(
+    path: web::Path<String>,
+    app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let target_name = path.into_inner();
+    let target_name = app_state.config.get_target_by_name(&target_name).map_or(target_name, |target| target.name.clone());
+    if app_state.preview_users.remove(&target_name) {
+        info!("Removed preview user for target {target_name}");
+        HttpResponse::Ok().finish()
+    } else {
+        HttpResponse::NotFound().finish()
+    }
+}
+
+async fn bulk_update_config_api_proxy_users(
+    req: web::Json<UserBulkRequest>,
+    app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let UserBulkRequest { filter, update, dry_run } = req.0;
+    match bulk_update_users(&app_state.config, &filter, &update, dry_run) {
+        Ok(report) => HttpResponse::Ok().json(report),
+        Err(err) => {
+            error!("Failed to update users {err}");
+            HttpResponse::BadRequest().json(json!({"error": err.to_string()}))
+        }
+    }
+}
+
+/// The last activity of all users, users without activity since `dormant_days` are flagged as dormant.
+async fn user_activity(
+    query: web::Query<UserActivityRequest>,
+    app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let now = chrono::Utc::now().timestamp();
+    let users: Vec<_> = app_state.config.t_api_proxy.read().unwrap().as_ref().map(|api_proxy| api_proxy.user.iter()
+        .flat_map(|target_user| target_user.credentials.iter().map(|credentials| {
+            let activity = app_state.user_activities.get(&credentials.username).unwrap_or_default();
+            json!({
+                "username": credentials.username,
+                "target": target_user.target,
+                "dormant": activity.is_dormant(query.dormant_days, now),
+                "activity": activity,
+            })
+        })).collect()).unwrap_or_default();
+    HttpResponse::Ok().json(users)
+}
+
+/// Removes the stored stream traces, diagnostic events, short links, epg overrides and activity of the user.
+async fn purge_user_data(
+    path: web::Path<String>,
+    app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let username = path.into_inner();
+    let Some(user) = app_state.config.get_user_credentials(&username) else {
+        return HttpResponse::NotFound().json(json!({"error": format!("Unknown user {username}")}));
+    };
+    let stream_traces = app_state.stream_traces.purge_user(&user.username);
+    let diagnostics = app_state.diagnostics.lock().await.purge_user(&user);
+    let short_links = app_state.short_links.purge_user(&user);
+    let epg_overrides = app_state.user_epg_overrides.purge_user(&user.username);
+    let activity = app_state.user_activities.purge_user(&user.username);
+    let bouquets = app_state.user_bouquets.purge_user(&user.username);
+    HttpResponse::Ok().json(json!({"stream_traces": stream_traces, "diagnostics": diagnostics, "short_links": short_links, "epg_overrides": epg_overrides, "activity": activity, "bouquets": bouquets}))
+}
+
+fn unknown_user_response(app_state: &AppState, username: &str) -> Option<HttpResponse> {
+    if app_state.config.get_user_credentials(username).is_some() {
+        None
+    } else {
+        Some(HttpResponse::NotFound().json(json!({"error": format!("Unknown user {username}")})))
+    }
+}
+
+async fn user_epg_overrides(
+    path: web::Path<String>,
+    app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let username = path.into_inner();
+    if let Some(response) = unknown_user_response(&app_state, &username) {
+        return response;
+    }
+    HttpResponse::Ok().json(app_state.user_epg_overrides.get(&username).unwrap_or_default())
+}
+
+async fn user_epg_override_set(
+    path: web::Path<(String, String)>,
+    req: web::Json<EpgOverrideRequest>,
+    app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let (username, epg_channel_id) = path.into_inner();
+    if let Some(response) = unknown_user_response(&app_state, &username) {
+        return response;
+    }
+    let epg_id = req.epg_id.trim();
+    if epg_id.is_empty() {
+        return HttpResponse::BadRequest().json(json!({"error": "epg_id is required"}));
+    }
+    app_state.user_epg_overrides.set(&username, &epg_channel_id, epg_id);
+    HttpResponse::Ok().finish()
+}
+
+async fn user_epg_override_delete(
+    path: web::Path<(String, String)>,
+    app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let (username, epg_channel_id) = path.into_inner();
+    if app_state.user_epg_overrides.remove(&username, &epg_channel_id) {
+        HttpResponse::Ok().finish()
+    } else {
+        HttpResponse::NotFound().finish()
+    }
+}
+
+async fn user_bouquets(
+    path: web::Path<String>,
+    app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let username = path.into_inner();
+    if let Some(response) = unknown_user_response(&app_state, &username) {
+        return response;
+    }
+    HttpResponse::Ok().json(app_state.user_bouquets.get(&username).unwrap_or_default())
+}
+
+async fn user_bouquet_set(
+    path: web::Path<(String, String)>,
+    req: web::Json<UserBouquetRequest>,
+    app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let (username, name) = path.into_inner();
+    let Some(user) = app_state.config.get_user_credentials(&username) else {
+        return HttpResponse::NotFound().json(json!({"error": format!("Unknown user {username}")}));
+    };
+    let Some((_, target)) = app_state.config.get_target_for_user(&user.username, &user.password) else {
+        return HttpResponse::BadRequest().json(json!({"error": format!("No target for user {username}")}));
+    };
+    let name = name.trim();
+    if name.is_empty() {
+        return HttpResponse::BadRequest().json(json!({"error": "name is required"}));
+    }
+    let ids = req.into_inner().ids;
+    if !ids.is_empty() {
+        // only channels of the target can be part of a bouquet, the ids are protected from the id mapping retention
+        let known_ids: HashSet<u32> = match load_target_playlist(&app_state.config, target).await {
+            Ok(playlist) => playlist.iter().flat_map(|group| &group.channels).map(|channel| channel.header.borrow().virtual_id).collect(),
+            Err(err) => return HttpResponse::InternalServerError().json(json!({"error": err.to_string()})),
+        };
+        let unknown_ids: Vec<u32> = ids.iter().filter(|id| !known_ids.contains(id)).copied().collect();
+        if !unknown_ids.is_empty() {
+            return HttpResponse::BadRequest().json(json!({"error": "Unknown channel ids", "ids": unknown_ids}));
+        }
+    }
+    app_state.user_bouquets.set(&username, &target.name, name, ids);
+    HttpResponse::Ok().finish()
+}
+
+async fn user_bouquet_delete(
+    path: web::Path<(String, String)>,
+    app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let (username, name) = path.into_inner();
+    if app_state.user_bouquets.remove(&username, name.trim()) {
+        HttpResponse::Ok().finish()
+    } else {
+        HttpResponse::NotFound().finish()
+    }
+}
+
+async fn channel_notes(
+    query: web::Query<ChannelNoteSearchRequest>,
+    app_state: web::Data<AppState>,
+) -> HttpResponse {
+    HttpResponse::Ok().json(app_state.channel_notes.search(&query.q))
+}
+
+async fn channel_note(
+    path: web::Path<String>,
+    app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let uuid = path.into_inner();
+    if !is_valid_channel_uuid(&uuid) {
+        return HttpResponse::BadRequest().json(json!({"error": "Invalid channel uuid"}));
+    }
+    match app_state.channel_notes.get(&uuid) {
+        Some(note) => HttpResponse::Ok().json(note),
+        None => HttpResponse::NotFound().finish(),
+    }
+}
+
+async fn channel_note_set(
+    path: web::Path<String>,
+    req: web::Json<ChannelNoteRequest>,
+    app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let uuid = path.into_inner();
+    if !is_valid_channel_uuid(&uuid) {
+        return HttpResponse::BadRequest().json(json!({"error": "Invalid channel uuid"}));
+    }
+    let note = ChannelNote::new(&req.note, &req.labels);
+    app_state.channel_notes.set(&uuid, note.clone()).await;
+    HttpResponse::Ok().json(note)
+}
+
+async fn channel_note_delete(
+    path: web::Path<String>,
+    app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let uuid = path.into_inner();
+    if !is_valid_channel_uuid(&uuid) {
+        return HttpResponse::BadRequest().json(json!({"error": "Invalid channel uuid"}));
+    }
+    if app_state.channel_notes.remove(&uuid).await {
+        HttpResponse::Ok().finish()
+    } else {
+        HttpResponse::NotFound().finish()
+    }
+}
+
+fn save_target_change(app_state: &AppState, change: &TargetChange) -> HttpResponse {
+    match config_reader::save_target_change(&app_state.config, change) {
+        Ok(()) => HttpResponse::Ok().finish(),
+        Err(err) => {
+            error!("Failed to save target {err}");
+            HttpResponse::BadRequest().json(json!({"error": err.to_string()}))
+        }
+    }
+}
+
+async fn target_create(
+    req: web::Json<TargetCreateRequest>,
+    app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let TargetCreateRequest { source, target } = req.0;
+    save_target_change(&app_state, &TargetChange::Create { source, target })
+}
+
+async fn target_update(
+    path: web::Path<String>,
+    req: web::Json<ConfigTarget>,
+    app_state: web::Data<AppState>,
+) -> HttpResponse {
+    save_target_change(&app_state, &TargetChange::Update { name: path.into_inner(), target: req.0 })
+}
+
+async fn target_delete(
+    path: web::Path<String>,
+    app_state: web::Data<AppState>,
+) -> HttpResponse {
+    save_target_change(&app_state, &TargetChange::Delete { name: path.into_inner() })
+}
+
 async fn save_config_main(
     req: web::Json<ConfigDto>,
     app_state: web::Data<AppState>,
@@ -123,6 +446,7 @@ fn create_config_input_for_url(url: &str) -> ConfigInput {
             xtream_skip_live: false,
             xtream_skip_vod: false,
             xtream_skip_series: false,
+            ..Default::default()
         }),
         ..Default::default()
     }
@@ -131,10 +455,12 @@ fn create_config_input_for_url(url: &str) -> ConfigInput {
 async fn get_playlist(client: Arc<reqwest::Client>, cfg_input: Option<&ConfigInput>, cfg: &Config) -> HttpResponse {
     match cfg_input {
         Some(input) => {
+            let mut parse_report = InputParseReport::new(input);
             let (result, errors) =
                 match input.input_type {
-                    InputType::M3u => download::get_m3u_playlist(client, cfg, input, &cfg.working_dir).await,
-                    InputType::Xtream => download::get_xtream_playlist(client, input, &cfg.working_dir).await,
+                    InputType::M3u => download::get_m3u_playlist(client, cfg, input, &cfg.working_dir, &mut parse_report).await,
+                    InputType::Xtream => download::get_xtream_playlist(client, cfg, input, &cfg.working_dir, &mut parse_report).await,
+                    InputType::Local => download::get_local_playlist(input),
                 };
             if result.is_empty() {
                 let error_strings: Vec<String> = errors.iter().map(std::string::ToString::to_string).collect();
@@ -151,7 +477,15 @@ async fn playlist(
     req: web::Json<PlaylistRequest>,
     app_state: web::Data<AppState>,
 ) -> HttpResponse {
-    if let Some(input_id) = req.input_id {
+    if let Some(target_name) = req.target.as_deref() {
+        let Some(target) = app_state.config.get_target_by_name(target_name) else {
+            return HttpResponse::BadRequest().json(json!({"error": "Unknown target"}));
+        };
+        match load_target_playlist(&app_state.config, target).await {
+            Ok(playlist) => HttpResponse::Ok().json(playlist),
+            Err(err) => HttpResponse::BadRequest().json(json!({"error": err.to_string()})),
+        }
+    } else if let Some(input_id) = req.input_id {
         get_playlist(Arc::clone(&app_state.http_client), app_state.config.get_input_by_id(input_id), &app_state.config).await
     } else {
         let url = req.url.as_deref().unwrap_or("");
@@ -166,9 +500,9 @@ async fn config(
     let map_input = |i: &ConfigInput| ServerInputConfig {
         id: i.id,
         input_type: i.input_type.clone(),
-        url: i.url.clone(),
-        username: i.username.clone(),
-        password: i.password.clone(),
+        url: i.get_config_value("url", Some(&i.url)).unwrap_or_default(),
+        username: i.get_config_value("username", i.username.as_ref()),
+        password: i.get_config_value("password", i.password.as_ref()),
         persist: i.persist.clone(),
         name: i.name.clone(),
         enabled: i.enabled,
@@ -223,17 +557,377 @@ async fn config(
     HttpResponse::Ok().json(result)
 }
 
-pub fn v1_api_register(web_auth_enabled: bool) -> impl Fn(&mut web::ServiceConfig) {
+async fn mapping_report(
+    path: web::Path<String>,
+    app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let target_name = path.into_inner();
+    match read_target_report(&app_state.config, &target_name, REPORT_MAPPING) {
+        Some(content) => HttpResponse::Ok().content_type(mime::APPLICATION_JSON).body(content),
+        None => HttpResponse::NotFound().json(json!({"error": format!("No mapping report for target {target_name}")})),
+    }
+}
+
+async fn timing_report(
+    path: web::Path<String>,
+    app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let target_name = path.into_inner();
+    match read_target_report(&app_state.config, &target_name, REPORT_TIMING) {
+        Some(content) => HttpResponse::Ok().content_type(mime::APPLICATION_JSON).body(content),
+        None => HttpResponse::NotFound().json(json!({"error": format!("No timing report for target {target_name}")})),
+    }
+}
+
+async fn epg_channel_programmes(
+    path: web::Path<(String, String)>,
+    query: web::Query<EpgProgrammeRequest>,
+    app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let (target_name, channel_id) = path.into_inner();
+    let Some(target) = app_state.config.sources.iter().flat_map(|source| &source.targets).find(|target| target.name == target_name) else {
+        return HttpResponse::NotFound().json(json!({"error": format!("Target {target_name} not found")}));
+    };
+    match get_epg_path_for_target(&app_state.config, target) {
+        Some(epg_path) => HttpResponse::Ok().json(json!({
+            "channel_id": channel_id,
+            "programmes": epg_read_channel_programmes(&app_state.config, &epg_path, &channel_id, query.from, query.to).await,
+        })),
+        None => HttpResponse::NotFound().json(json!({"error": format!("No epg for target {target_name}")})),
+    }
+}
+
+async fn parser_report(
+    path: web::Path<String>,
+    app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let target_name = path.into_inner();
+    match read_target_report(&app_state.config, &target_name, REPORT_PARSER) {
+        Some(content) => HttpResponse::Ok().content_type(mime::APPLICATION_JSON).body(content),
+        None => HttpResponse::NotFound().json(json!({"error": format!("No parser report for target {target_name}")})),
+    }
+}
+
+async fn validation_report(
+    path: web::Path<String>,
+    app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let target_name = path.into_inner();
+    match read_target_report(&app_state.config, &target_name, REPORT_VALIDATION) {
+        Some(content) => HttpResponse::Ok().content_type(mime::APPLICATION_JSON).body(content),
+        None => HttpResponse::NotFound().json(json!({"error": format!("No validation report for target {target_name}")})),
+    }
+}
+
+async fn storage_compact(
+    app_state: web::Data<AppState>,
+) -> HttpResponse {
+    match compact_storage(&app_state.config).await {
+        Ok(report) => HttpResponse::Ok().json(report),
+        Err(err) => HttpResponse::InternalServerError().json(json!({"error": err.to_string()})),
+    }
+}
+
+async fn cache_scan(
+    app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let Some(cache) = app_state.cache.as_ref() else {
+        return HttpResponse::NotFound().json(json!({"error": "Resource cache is not enabled"}));
+    };
+    match cache.lock().await.scan(STALE_PART_FILE_AGE).await {
+        Ok(report) => HttpResponse::Ok().json(report),
+        Err(err) => HttpResponse::InternalServerError().json(json!({"error": err.to_string()})),
+    }
+}
+
+async fn maintenance(
+    app_state: web::Data<AppState>,
+) -> HttpResponse {
+    HttpResponse::Ok().json(app_state.config.t_maintenance.get_state())
+}
+
+async fn maintenance_toggle(
+    req: web::Json<MaintenanceRequest>,
+    app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let request = req.into_inner();
+    HttpResponse::Ok().json(app_state.config.t_maintenance.set_enabled(request.enabled, request.message))
+}
+
+async fn processing_jobs(
+    app_state: web::Data<AppState>,
+) -> HttpResponse {
+    HttpResponse::Ok().json(app_state.config.t_processing_jobs.get_jobs())
+}
+
+async fn processing_job_cancel(
+    path: web::Path<u32>,
+    app_state: web::Data<AppState>,
+) -> HttpResponse {
+    if app_state.config.t_processing_jobs.cancel(path.into_inner()) {
+        HttpResponse::Accepted().finish()
+    } else {
+        HttpResponse::NotFound().finish()
+    }
+}
+
+async fn diagnostics(
+    app_state: web::Data<AppState>,
+) -> HttpResponse {
+    HttpResponse::Ok().json(app_state.diagnostics.lock().await.get_events())
+}
+
+async fn diagnostics_player_error(
+    req: web::Json<PlayerErrorReport>,
+    app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let report = req.into_inner();
+    if report.url.trim().is_empty() {
+        return HttpResponse::BadRequest().json(json!({"error": "Missing url"}));
+    }
+    HttpResponse::Ok().json(app_state.diagnostics.lock().await.add_player_error(report))
+}
+
+async fn stream_traces(
+    app_state: web::Data<AppState>,
+) -> HttpResponse {
+    HttpResponse::Ok().json(app_state.stream_traces.get_summaries())
+}
+
+/// The trace report is downloaded as json file.
+async fn stream_trace_report(
+    path: web::Path<u64>,
+    app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let id = path.into_inner();
+    match app_state.stream_traces.get_report(id) {
+        Some(report) => HttpResponse::Ok()
+            .insert_header((CONTENT_DISPOSITION, format!("attachment; filename=\"stream_trace_{id}.json\"")))
+            .json(report),
+        None => HttpResponse::NotFound().finish(),
+    }
+}
+
+async fn short_links(
+    app_state: web::Data<AppState>,
+) -> HttpResponse {
+    HttpResponse::Ok().json(app_state.short_links.get_links())
+}
+
+async fn short_link_create(
+    req: web::Json<ShortLinkRequest>,
+    app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let request = req.into_inner();
+    let url = request.url.trim();
+    if !Url::parse(url).is_ok_and(|url| matches!(url.scheme(), "http" | "https")) {
+        return HttpResponse::BadRequest().json(json!({"error": "Invalid url"}));
+    }
+    if request.expires_in.is_some_and(|expires_in| expires_in <= 0) {
+        return HttpResponse::BadRequest().json(json!({"error": "Invalid expires_in"}));
+    }
+    let expires = request.expires_in.map(|expires_in| chrono::Utc::now().timestamp() + expires_in);
+    match app_state.short_links.create(url, expires) {
+        Some(link) => HttpResponse::Ok().json(json!({"id": link.id, "path": format!("/s/{}", link.id), "expires": link.expires})),
+        None => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+async fn short_link_delete(
+    path: web::Path<String>,
+    app_state: web::Data<AppState>,
+) -> HttpResponse {
+    if app_state.short_links.remove(&path.into_inner()) {
+        HttpResponse::Ok().finish()
+    } else {
+        HttpResponse::NotFound().finish()
+    }
+}
+
+async fn input_health(
+    app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let inputs = app_state.config.sources.iter().flat_map(|source| &source.inputs);
+    HttpResponse::Ok().json(app_state.config.t_input_health.get_input_states(inputs))
+}
+
+/// Active provider streams of the inputs with connection limit, per input id and user.
+async fn input_connections(
+    app_state: web::Data<AppState>,
+) -> HttpResponse {
+    HttpResponse::Ok().json(app_state.provider_connections.get_streams())
+}
+
+// a simulated outage is limited to one day, it is not persisted
+const MAX_SIMULATED_OUTAGE_MINUTES: u32 = 1440;
+
+/// The input by name or id.
+fn find_input<'a>(config: &'a Config, input: &str) -> Option<&'a ConfigInput> {
+    config.get_input_by_name(input).or_else(|| input.parse::<u16>().ok().and_then(|input_id| config.get_input_by_id(input_id)))
+}
+
+fn get_request_origin(req: &HttpRequest) -> String {
+    req.peer_addr().map_or_else(|| "unknown".to_string(), |addr| addr.ip().to_string())
+}
+
+/// Marks the input as down, the preflight checks fail until the outage ends.
+/// The outage ends automatically after the given minutes.
+async fn input_outage_start(
+    path: web::Path<String>,
+    body: web::Json<SimulatedOutageRequest>,
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let input_key = path.into_inner();
+    let Some(input) = find_input(&app_state.config, &input_key) else {
+        return HttpResponse::NotFound().json(json!({"error": format!("Unknown input {input_key}")}));
+    };
+    let minutes = body.minutes;
+    if minutes == 0 || minutes > MAX_SIMULATED_OUTAGE_MINUTES {
+        return HttpResponse::BadRequest().json(json!({"error": format!("minutes must be between 1 and {MAX_SIMULATED_OUTAGE_MINUTES}")}));
+    }
+    let input_name = input.name.as_ref().map_or_else(|| mask_sensitive_info(input.url.as_str()), ToString::to_string);
+    let until = chrono::Utc::now().timestamp() + i64::from(minutes) * 60;
+    input.t_preflight.start_simulated_outage(until);
+    warn!("Simulated outage of input {input_name} for {minutes} minutes started by {}", get_request_origin(&req));
+    let preflight = Arc::clone(&input.t_preflight);
+    actix_rt::spawn(async move {
+        tokio::time::sleep(std::time::Duration::from_secs(u64::from(minutes) * 60)).await;
+        if preflight.end_expired_simulated_outage(chrono::Utc::now().timestamp()) {
+            info!("Simulated outage of input {input_name} ended");
+        }
+    });
+    HttpResponse::Ok().json(json!({"until": until}))
+}
+
+async fn input_outage_end(
+    path: web::Path<String>,
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let input_key = path.into_inner();
+    let Some(input) = find_input(&app_state.config, &input_key) else {
+        return HttpResponse::NotFound().json(json!({"error": format!("Unknown input {input_key}")}));
+    };
+    if !input.t_preflight.end_simulated_outage() {
+        return HttpResponse::NotFound().finish();
+    }
+    let input_name = input.name.as_ref().map_or_else(|| mask_sensitive_info(input.url.as_str()), ToString::to_string);
+    info!("Simulated outage of input {input_name} ended by {}", get_request_origin(&req));
+    HttpResponse::Ok().finish()
+}
+
+async fn input_health_reset(
+    app_state: web::Data<AppState>,
+) -> HttpResponse {
+    app_state.config.t_input_health.reset();
+    HttpResponse::Ok().finish()
+}
+
+/// Issues a playback token for a stored channel of a target.
+/// The ui player opens the stream with the token, no proxy user is needed.
+/// Only the urls of stored channels are signed, the token can't be used to request arbitrary urls.
+async fn player_token(
+    req: web::Json<PlaybackTokenRequest>,
+    app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let playback = req.into_inner();
+    let Some(target) = app_state.config.get_target_by_name(&playback.target) else {
+        return HttpResponse::BadRequest().json(json!({"error": "Unknown target"}));
+    };
+    let (url, input_id, item_type) = match get_target_stream(&app_state.config, target, playback.stream_id).await {
+        Ok(stream) => stream,
+        Err(err) => {
+            warn!("Failed to find stream {} of target {}: {err}", playback.stream_id, target.name);
+            return HttpResponse::NotFound().json(json!({"error": "Unknown stream"}));
+        }
+    };
+    if app_state.config.get_input_by_id(input_id).is_none() {
+        return HttpResponse::BadRequest().json(json!({"error": "Unknown input"}));
+    }
+    match create_playback_token(app_state.playback_secret.as_bytes(), &url, Some(input_id), item_type) {
+        Ok(token) => HttpResponse::Ok().json(json!({"token": token})),
+        Err(err) => {
+            error!("Failed to create playback token: {err}");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// The stream is opened like a proxy user stream, with maintenance mode and input health.
+async fn player_stream(
+    req: HttpRequest,
+    path: web::Path<String>,
+    app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let Some(claims) = verify_playback_token(&path.into_inner(), app_state.playback_secret.as_bytes()) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    let input = claims.input_id.and_then(|input_id| app_state.config.get_input_by_id(input_id));
+    stream_response(&app_state, &claims.url, &req, input, claims.item_type, None, None).await
+}
+
+pub fn v1_api_register(web_auth_enabled: bool, base_path: &str) -> impl Fn(&mut web::ServiceConfig) {
+    let api_path = format!("{base_path}/api/v1");
+    let player_path = format!("{base_path}/player/stream/{{token}}");
     move |cfg: &mut web::ServiceConfig| {
-        cfg.service(web::scope("/api/v1")
+        cfg.service(web::scope(&api_path)
             .wrap(Condition::new(web_auth_enabled, HttpAuthentication::with_fn(validator)))
             .route("/config", web::get().to(config))
             .route("/config/main", web::post().to(save_config_main))
             .route("/config/user", web::post().to(save_config_api_proxy_user))
+            .route("/user/{username}/bouquets", web::get().to(user_bouquets))
+            .route("/user/{username}/bouquets/{name}", web::put().to(user_bouquet_set))
+            .route("/user/{username}/bouquets/{name}", web::delete().to(user_bouquet_delete))
+            .route("/config/user/import", web::post().to(import_config_api_proxy_users))
+            .route("/user/trial", web::post().to(create_trial_user))
+            .route("/user/preview/{target}", web::post().to(preview_user_create))
+            .route("/user/preview/{target}", web::delete().to(preview_user_delete))
+            .route("/user/bulk", web::post().to(bulk_update_config_api_proxy_users))
+            .route("/user/activity", web::get().to(user_activity))
+            .route("/user/{username}/data", web::delete().to(purge_user_data))
+            .route("/user/{username}/epg", web::get().to(user_epg_overrides))
+            .route("/user/{username}/epg/{channel_id}", web::put().to(user_epg_override_set))
+            .route("/user/{username}/epg/{channel_id}", web::delete().to(user_epg_override_delete))
             .route("/config/apiproxy", web::post().to(save_config_api_proxy_config))
+            .route("/targets", web::post().to(target_create))
+            .route("/targets/{name}", web::put().to(target_update))
+            .route("/targets/{name}", web::delete().to(target_delete))
             .route("/playlist", web::post().to(playlist))
+            .route("/channel/notes", web::get().to(channel_notes))
+            .route("/channel/notes/{uuid}", web::get().to(channel_note))
+            .route("/channel/notes/{uuid}", web::put().to(channel_note_set))
+            .route("/channel/notes/{uuid}", web::delete().to(channel_note_delete))
             .route("/playlist/update", web::post().to(playlist_update))
+            .route("/jobs", web::get().to(processing_jobs))
+            .route("/jobs/{id}", web::delete().to(processing_job_cancel))
+            .route("/report/mapping/{target}", web::get().to(mapping_report))
+            .route("/report/timing/{target}", web::get().to(timing_report))
+            .route("/report/parser/{target}", web::get().to(parser_report))
+            .route("/report/validation/{target}", web::get().to(validation_report))
+            .route("/epg/{target}/{channel_id}", web::get().to(epg_channel_programmes))
+            .route("/storage/compact", web::post().to(storage_compact))
+            .route("/cache/scan", web::post().to(cache_scan))
+            .route("/maintenance", web::get().to(maintenance))
+            .route("/maintenance", web::post().to(maintenance_toggle))
+            .route("/diagnostics", web::get().to(diagnostics))
+            .route("/input/health", web::get().to(input_health))
+            .route("/input/health/reset", web::post().to(input_health_reset))
+            .route("/input/connections", web::get().to(input_connections))
+            .route("/input/{input}/outage", web::post().to(input_outage_start))
+            .route("/input/{input}/outage", web::delete().to(input_outage_end))
+            .route("/diagnostics/player-error", web::post().to(diagnostics_player_error))
+            .route("/stream/traces", web::get().to(stream_traces))
+            .route("/stream/traces/{id}", web::get().to(stream_trace_report))
+            .route("/links", web::get().to(short_links))
+            .route("/links", web::post().to(short_link_create))
+            .route("/links/{id}", web::delete().to(short_link_delete))
             .route("/file/download", web::post().to(download_api::queue_download_file))
-            .route("/file/download/info", web::get().to(download_api::download_file_info)));
+            .route("/file/download/info", web::get().to(download_api::download_file_info))
+            .route("/player/token", web::post().to(player_token)));
+        // the video element can't send the bearer token, the playback token is the authorization
+        cfg.service(web::resource(&player_path)
+            .route(web::get().to(player_stream))
+            .route(web::head().to(player_stream)));
     }
 }
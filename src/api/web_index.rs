@@ -3,6 +3,9 @@ use std::path::{Path, PathBuf};
 
 use actix_files::NamedFile;
 use actix_web::{HttpRequest, HttpResponse, web};
+use actix_web::http::header;
+use log::error;
+use quick_xml::escape::escape;
 use actix_web_httpauth::extractors::bearer::BearerAuth;
 use crate::api::model::app_state::AppState;
 use crate::auth::authenticator::{create_jwt, verify_token};
@@ -66,21 +69,143 @@ async fn token_refresh(
     }
 }
 
+fn get_request_host(req: &HttpRequest) -> String {
+    let connection_info = req.connection_info();
+    let host = connection_info.host();
+    let host = match host.strip_prefix('[') {
+        Some(ipv6) => ipv6.split(']').next(),
+        None => host.split(':').next(),
+    };
+    host.unwrap_or_default().to_lowercase()
+}
+
+/// Only plain relative paths are allowed, hidden files and parent references are rejected.
+fn get_asset_path(filename: &str) -> Option<PathBuf> {
+    let mut path = PathBuf::new();
+    for segment in filename.split('/') {
+        if segment.is_empty() || segment == "." {
+            continue;
+        }
+        if segment.starts_with('.') || segment.contains('\\') {
+            return None;
+        }
+        path.push(segment);
+    }
+    Some(path)
+}
+
+/// Assets from the override directories take precedence over the `web_root` assets.
+fn resolve_asset(app_state: &AppState, host: &str, asset_path: &Path) -> Option<PathBuf> {
+    let asset_dirs = app_state.config.web_ui.as_ref().map_or_else(Vec::new, |web_ui| web_ui.get_asset_dirs(host));
+    asset_dirs.into_iter()
+        .chain(std::iter::once(app_state.config.api.web_root.as_str()))
+        .map(|dir| Path::new(dir).join(asset_path))
+        .find(|path| path.is_file())
+}
+
+fn brand_index_html(content: &str, base_path: &str, title: Option<&str>, logo: Option<&str>) -> String {
+    let mut html = content.to_string();
+    if let Some(title) = title {
+        if let (Some(start), Some(end)) = (html.find("<title>"), html.find("</title>")) {
+            if start < end {
+                html.replace_range(start + "<title>".len()..end, &escape(title));
+            }
+        }
+    }
+    let mut head = String::new();
+    if !base_path.is_empty() {
+        head.push_str(&format!("<base href=\"{}/\">", escape(base_path)));
+    }
+    if let Some(logo) = logo {
+        head.push_str(&format!("<meta name=\"m3u-filter-logo\" content=\"{}\">", escape(logo)));
+    }
+    if !head.is_empty() {
+        if let Some(pos) = html.find("<head>") {
+            html.insert_str(pos + "<head>".len(), &head);
+        }
+    }
+    html
+}
+
 async fn index(
-    _req: HttpRequest,
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let host = get_request_host(&req);
+    let Some(index_path) = resolve_asset(&app_state, &host, Path::new("index.html")) else {
+        return HttpResponse::NotFound().finish();
+    };
+    match std::fs::read_to_string(&index_path) {
+        Ok(content) => {
+            let html = match app_state.config.web_ui.as_ref() {
+                None => content,
+                Some(web_ui) => brand_index_html(&content, web_ui.base_path(), web_ui.get_title(&host), web_ui.get_logo(&host)),
+            };
+            HttpResponse::Ok().content_type(mime::TEXT_HTML_UTF_8).body(html)
+        }
+        Err(err) => {
+            error!("Failed to read index file {:?}: {err}", &index_path);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+async fn asset(
+    req: HttpRequest,
     app_state: web::Data<AppState>,
-) -> std::io::Result<NamedFile> {
-    let path: PathBuf = [&app_state.config.api.web_root, "index.html"].iter().collect();
-    NamedFile::open(path)
+) -> HttpResponse {
+    let host = get_request_host(&req);
+    let Some(asset_path) = get_asset_path(req.match_info().query("filename")) else {
+        return HttpResponse::NotFound().finish();
+    };
+    if asset_path.as_os_str().is_empty() {
+        return index(req, app_state).await;
+    }
+    match resolve_asset(&app_state, &host, &asset_path).map(NamedFile::open) {
+        Some(Ok(file)) => file.into_response(&req),
+        _ => HttpResponse::NotFound().finish(),
+    }
 }
 
-pub fn index_register(web_dir_path: &Path) -> impl Fn(&mut web::ServiceConfig) + '_ {
+async fn base_path_redirect(
+    app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let base_path = app_state.config.web_ui.as_ref().map_or("", |web_ui| web_ui.base_path());
+    HttpResponse::Found().insert_header((header::LOCATION, format!("{base_path}/"))).finish()
+}
+
+pub fn index_register(base_path: &str) -> impl Fn(&mut web::ServiceConfig) + '_ {
     move |cfg: &mut web::ServiceConfig| {
-        cfg.service(web::scope("/auth")
+        cfg.service(web::scope(&format!("{base_path}/auth"))
             .route("/token", web::post().to(token))
             .route("/refresh", web::post().to(token_refresh)));
-        cfg.service(web::scope("/")
-            .route("", web::get().to(index))
-            .service(actix_files::Files::new("/", web_dir_path)));
+        if !base_path.is_empty() {
+            cfg.route(base_path, web::get().to(base_path_redirect));
+        }
+        cfg.route(&format!("{base_path}/"), web::get().to(index));
+        cfg.route(&format!("{base_path}/{{filename:.*}}"), web::get().to(asset));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::api::web_index::{brand_index_html, get_asset_path};
+
+    #[test]
+    fn asset_path_test() {
+        assert_eq!(get_asset_path("static/js/main.js"), Some(PathBuf::from("static/js/main.js")));
+        assert_eq!(get_asset_path("/favicon.ico"), Some(PathBuf::from("favicon.ico")));
+        assert_eq!(get_asset_path("static/../../config.yml"), None);
+        assert_eq!(get_asset_path(".env"), None);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn brand_index_test() {
+        let html = "<html><head><title>m3u-filter</title></head></html>";
+        assert_eq!(brand_index_html(html, "", None, None), html);
+        assert_eq!(brand_index_html(html, "/tv", Some("Reseller <TV>"), Some("logo.png")),
+                   "<html><head><base href=\"/tv/\"><meta name=\"m3u-filter-logo\" content=\"logo.png\"><title>Reseller &lt;TV&gt;</title></head></html>");
+    }
+}
@@ -1,4 +1,6 @@
+use std::collections::{BTreeSet, HashMap};
 use std::fs::File;
+use std::io::{BufRead, Write};
 use std::path::{Path, PathBuf};
 
 use actix_web::{HttpRequest, HttpResponse, web, http::header};
@@ -6,17 +8,20 @@ use log::{error, info};
 use quick_xml::{Reader, Writer};
 use flate2::write::GzEncoder;
 use flate2::Compression;
+use quick_xml::encoding::Decoder;
 use quick_xml::events::{BytesStart, Event};
 use chrono::{Duration, NaiveDateTime, TimeDelta};
 
-use crate::api::api_utils::{get_user_target, serve_file};
+use crate::api::api_utils::{get_user_target, serve_file, sign_response};
 use crate::api::model::app_state::AppState;
 use crate::api::model::request::UserApiRequest;
 use crate::model::api_proxy::{ProxyUserCredentials};
 use crate::model::config::{Config, ConfigTarget};
 use crate::model::config::TargetType;
+use crate::repository::epg_repository::epg_get_bouquet_variant;
 use crate::repository::m3u_repository::m3u_get_epg_file_path;
 use crate::repository::storage::get_target_storage_path;
+use crate::repository::stalker_repository::stalker_get_epg_file_path;
 use crate::repository::xtream_repository::{xtream_get_epg_file_path, xtream_get_storage_path};
 use crate::utils::{file_utils};
 use crate::utils::file_utils::file_reader;
@@ -46,7 +51,7 @@ fn get_epg_path_for_target_of_type(target_name: &str, epg_path: PathBuf) -> Opti
     None
 }
 
-fn get_epg_path_for_target(config: &Config, target: &ConfigTarget) -> Option<PathBuf> {
+pub fn get_epg_path_for_target(config: &Config, target: &ConfigTarget) -> Option<PathBuf> {
     // TODO if we share the same virtual_id for epg, can we store an epg file for the target ?
     for output in &target.output {
         match output.target {
@@ -60,12 +65,30 @@ fn get_epg_path_for_target(config: &Config, target: &ConfigTarget) -> Option<Pat
                     return get_epg_path_for_target_of_type(&target.name, xtream_get_epg_file_path(&storage_path));
                 }
             }
+            TargetType::Stalker => {
+                if let Some(target_path) = get_target_storage_path(config, &target.name) {
+                    return get_epg_path_for_target_of_type(&target.name, stalker_get_epg_file_path(&target_path));
+                }
+            }
             TargetType::Strm => {}
         }
     }
     None
 }
 
+/// The epg variant with the channels of the bouquets of the user, see `epg_bouquet_variants`.
+async fn get_epg_variant_path(app_state: &AppState, target: &ConfigTarget, user: &ProxyUserCredentials, epg_path: &Path) -> Option<PathBuf> {
+    if !target.options.as_ref().is_some_and(|options| options.epg_bouquet_variants) {
+        return None;
+    }
+    let bouquets = app_state.user_bouquets.get(&user.username).filter(|bouquets| bouquets.target == target.name)?;
+    let virtual_ids: BTreeSet<u32> = bouquets.bouquets.values().flatten().copied().collect();
+    if virtual_ids.is_empty() {
+        return None;
+    }
+    epg_get_bouquet_variant(&app_state.config, target, epg_path, &virtual_ids).await
+}
+
 fn parse_timeshift(time_shift: Option<&String>) -> Option<i32> {
     time_shift.and_then(|offset| {
             let sign_factor = if offset.starts_with('-') { -1 } else { 1 };
@@ -103,14 +126,14 @@ fn parse_timeshift(time_shift: Option<&String>) -> Option<i32> {
         })
 }
 
-async fn serve_epg(epg_path: &Path, req: &HttpRequest, user: &ProxyUserCredentials) -> HttpResponse {
+async fn serve_epg(epg_path: &Path, req: &HttpRequest, user: &ProxyUserCredentials, epg_overrides: Option<HashMap<String, String>>) -> HttpResponse {
     match File::open(epg_path) {
         Ok(epg_file) => {
-            match parse_timeshift(user.epg_timeshift.as_ref()) {
-                None => serve_file(epg_path, req, mime::TEXT_XML).await,
-                Some(duration) => {
-                    serve_epg_with_timeshift(epg_file, duration)
-                }
+            let timeshift = parse_timeshift(user.epg_timeshift.as_ref());
+            if timeshift.is_none() && epg_overrides.is_none() {
+                serve_file(epg_path, req, mime::TEXT_XML).await
+            } else {
+                serve_epg_rewritten(epg_file, timeshift, &epg_overrides.unwrap_or_default())
             }
         }
         Err(_) => {
@@ -119,48 +142,90 @@ async fn serve_epg(epg_path: &Path, req: &HttpRequest, user: &ProxyUserCredentia
     }
 }
 
-fn serve_epg_with_timeshift(epg_file: File, offset_minutes: i32) -> HttpResponse {
-    let reader = file_reader(epg_file);
-    let encoder = GzEncoder::new(Vec::with_capacity(4096), Compression::default());
+/// Copies the programme start tag, applies the timeshift and replaces the channel.
+fn rewrite_programme_start(e: &BytesStart, decoder: Decoder, duration: Option<&TimeDelta>, channel: Option<&str>) -> BytesStart<'static> {
+    let mut elem = BytesStart::new(String::from_utf8_lossy(e.name().as_ref()).to_string());
+    for attr in e.attributes() {
+        match attr {
+            Ok(attr) if duration.is_some() && (attr.key.as_ref() == b"start" || attr.key.as_ref() == b"stop") => {
+                let key = String::from_utf8_lossy(attr.key.as_ref()).to_string();
+                let value = attr.decode_and_unescape_value(decoder).map(|value| value.to_string()).unwrap_or_default();
+                elem.push_attribute((key.as_str(), time_correct(&value, duration.unwrap()).as_str()));
+            }
+            Ok(attr) if channel.is_some() && attr.key.as_ref() == b"channel" => {
+                elem.push_attribute(("channel", channel.unwrap()));
+            }
+            Ok(attr) => {
+                // Copy any other attributes as they are
+                elem.push_attribute(attr);
+            }
+            Err(e) => {
+                error!("Error parsing attribute: {e}");
+            }
+        }
+    }
+    elem
+}
+
+fn get_programme_channel(e: &BytesStart, decoder: Decoder) -> Option<String> {
+    e.try_get_attribute("channel").ok().flatten()
+        .and_then(|attr| attr.decode_and_unescape_value(decoder).ok().map(|value| value.to_string()))
+}
+
+/// Applies the timeshift and the epg overrides of the user.
+/// The programmes of an overridden channel are replaced with copies of the programmes of the override channel.
+fn rewrite_epg<R: BufRead, W: Write>(reader: R, writer: W, offset_minutes: Option<i32>, epg_overrides: &HashMap<String, String>) -> W {
+    let mut override_channels: HashMap<&str, Vec<&str>> = HashMap::new();
+    for (channel, epg_override) in epg_overrides {
+        if channel != epg_override {
+            override_channels.entry(epg_override.as_str()).or_default().push(channel.as_str());
+        }
+    }
     let mut xml_reader = Reader::from_reader(reader);
-    let mut xml_writer = Writer::new(encoder);
+    let mut xml_writer = Writer::new(writer);
     let mut buf = Vec::with_capacity(1024);
-    let duration = Duration::minutes(i64::from(offset_minutes));
+    let duration = offset_minutes.map(|minutes| Duration::minutes(i64::from(minutes)));
+    let mut skip_programme = false;
+    // the programme of an override channel, copied for the overridden channels at the end of the programme
+    let mut copy_programme: Option<(Vec<&str>, BytesStart<'static>, Vec<Event<'static>>)> = None;
 
     loop {
         match xml_reader.read_event_into(&mut buf) {
             Ok(Event::Start(ref e)) if e.name().as_ref() == b"programme" => {
-                // Modify the attributes
-                let mut elem = BytesStart::from(e.name());
-                for attr in e.attributes() {
-                    match attr {
-                        Ok(attr) if attr.key.as_ref() == b"start" => {
-                            let start_value = attr.decode_and_unescape_value(xml_reader.decoder())
-                                .expect("Failed to decode start attribute");
-                            // Modify the start attribute value as needed
-                            elem.push_attribute(("start", time_correct(&start_value, &duration).as_str()));
-                        }
-                        Ok(attr) if attr.key.as_ref() == b"stop" => {
-                            let stop_value = attr.decode_and_unescape_value(xml_reader.decoder())
-                                .expect("Failed to decode stop attribute");
-                            // Modify the stop attribute value as needed
-                            elem.push_attribute(("stop", time_correct(&stop_value, &duration).as_str()));
-                        }
-                        Ok(attr) => {
-                            // Copy any other attributes as they are
-                            elem.push_attribute(attr);
-                        }
-                        Err(e) => {
-                            error!("Error parsing attribute: {e}");
+                let channel = get_programme_channel(e, xml_reader.decoder());
+                if channel.as_ref().is_some_and(|channel| epg_overrides.get(channel).is_some_and(|epg_override| epg_override != channel)) {
+                    skip_programme = true;
+                } else {
+                    let elem = rewrite_programme_start(e, xml_reader.decoder(), duration.as_ref(), None);
+                    if let Some(channels) = channel.as_deref().and_then(|channel| override_channels.get(channel)) {
+                        copy_programme = Some((channels.clone(), elem.clone(), vec![]));
+                    }
+                    xml_writer.write_event(Event::Start(elem)).expect("Failed to write event");
+                }
+            }
+            Ok(Event::End(ref e)) if e.name().as_ref() == b"programme" && skip_programme => {
+                skip_programme = false;
+            }
+            Ok(_) if skip_programme => {}
+            Ok(Event::End(e)) if e.name().as_ref() == b"programme" && copy_programme.is_some() => {
+                let end = e.into_owned();
+                xml_writer.write_event(Event::End(end.clone())).expect("Failed to write event");
+                if let Some((channels, start, events)) = copy_programme.take() {
+                    for channel in channels {
+                        let elem = rewrite_programme_start(&start, xml_reader.decoder(), None, Some(channel));
+                        xml_writer.write_event(Event::Start(elem)).expect("Failed to write event");
+                        for event in &events {
+                            xml_writer.write_event(event.borrow()).expect("Failed to write event");
                         }
+                        xml_writer.write_event(Event::End(end.clone())).expect("Failed to write event");
                     }
                 }
-
-                // Write the modified start event
-                xml_writer.write_event(Event::Start(elem)).expect("Failed to write event");
             }
             Ok(Event::Eof) => break, // End of file
             Ok(event) => {
+                if let Some((_, _, events)) = copy_programme.as_mut() {
+                    events.push(event.clone().into_owned());
+                }
                 // Write any other event as is
                 xml_writer.write_event(event).expect("Failed to write event");
             }
@@ -172,8 +237,12 @@ fn serve_epg_with_timeshift(epg_file: File, offset_minutes: i32) -> HttpResponse
 
         buf.clear();
     }
+    xml_writer.into_inner()
+}
 
-    let compressed_data = xml_writer.into_inner().finish().unwrap();
+fn serve_epg_rewritten(epg_file: File, offset_minutes: Option<i32>, epg_overrides: &HashMap<String, String>) -> HttpResponse {
+    let encoder = GzEncoder::new(Vec::with_capacity(4096), Compression::default());
+    let compressed_data = rewrite_epg(file_reader(epg_file), encoder, offset_minutes, epg_overrides).finish().unwrap();
     HttpResponse::Ok()
         .content_type("application/octet-stream")
         .insert_header((header::CONTENT_ENCODING, "gzip")) // Set Content-Encoding header
@@ -185,17 +254,21 @@ async fn xmltv_api(
     req: HttpRequest,
     app_state: web::Data<AppState>,
 ) -> HttpResponse {
-    if let Some((user, target)) = get_user_target(&api_req, &app_state) {
+    if let Some((user, target)) = get_user_target(&api_req, &app_state, &req) {
         match get_epg_path_for_target(&app_state.config, target) {
             None => {
                 // No epg configured,  No processing or timeshift, epg can't be mapped to the channels.
                 // we do not deliver epg
             }
-            Some(epg_path) => return serve_epg(&epg_path, &req, &user).await
+            Some(epg_path) => {
+                let epg_path = get_epg_variant_path(&app_state, target, &user, &epg_path).await.unwrap_or(epg_path);
+                let epg_overrides = app_state.user_epg_overrides.get(&user.username);
+                return sign_response(&app_state.config, serve_epg(&epg_path, &req, &user, epg_overrides).await).await;
+            }
         }
     }
-    HttpResponse::Ok().content_type(mime::TEXT_XML).body(
-        r#"<?xml version="1.0" encoding="utf-8" ?><!DOCTYPE tv SYSTEM "xmltv.dtd"><tv generator-info-name="Xtream Codes" generator-info-url=""></tv>"#)
+    sign_response(&app_state.config, HttpResponse::Ok().content_type(mime::TEXT_XML).body(
+        r#"<?xml version="1.0" encoding="utf-8" ?><!DOCTYPE tv SYSTEM "xmltv.dtd"><tv generator-info-name="Xtream Codes" generator-info-url=""></tv>"#)).await
 }
 
 pub fn xmltv_api_register(cfg: &mut web::ServiceConfig) {
@@ -203,3 +276,18 @@ pub fn xmltv_api_register(cfg: &mut web::ServiceConfig) {
         .service(web::resource("/update/epg.php").route(web::get().to(xmltv_api)))
         .service(web::resource("/epg").route(web::get().to(xmltv_api)));
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::api::xmltv_api::rewrite_epg;
+
+    #[test]
+    fn rewrite_epg_test() {
+        let epg = r#"<tv><programme start="20250101100000 +0000" stop="20250101110000 +0000" channel="bbc1.uk"><title>National</title></programme><programme start="20250101100000 +0000" stop="20250101110000 +0000" channel="bbc1.london"><title>London</title></programme></tv>"#;
+        let overrides = HashMap::from([("bbc1.uk".to_string(), "bbc1.london".to_string())]);
+        let result = String::from_utf8(rewrite_epg(epg.as_bytes(), Vec::new(), Some(60), &overrides)).unwrap();
+        assert_eq!(result, r#"<tv><programme start="20250101110000 +0000" stop="20250101120000 +0000" channel="bbc1.london"><title>London</title></programme><programme start="20250101110000 +0000" stop="20250101120000 +0000" channel="bbc1.uk"><title>London</title></programme></tv>"#);
+    }
+}
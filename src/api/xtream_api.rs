@@ -12,25 +12,30 @@ use bytes::Bytes;
 use futures::stream::{self, StreamExt};
 use futures::Stream;
 use log::{debug, error, warn};
-use serde_json::{Map, Value};
+use base64::prelude::{Engine, BASE64_STANDARD};
+use serde_json::{json, Map, Value};
 
-use crate::api::api_utils::{get_user_target, get_user_target_by_credentials, resource_response, serve_file, stream_response};
+use crate::api::{catchup_archive, local_vod};
+use crate::api::api_utils::{acquire_playlist_permit, get_category_translations, hold_playlist_permit, get_user_target, get_user_target_by_credentials, maintenance_response, resource_response, serve_file, stream_response};
 use crate::api::model::app_state::AppState;
 use crate::api::model::request::UserApiRequest;
 use crate::api::model::xtream::XtreamAuthorizationResponse;
+use crate::api::xmltv_api::get_epg_path_for_target;
 use crate::m3u_filter_error::{str_to_io_error, M3uFilterError, M3uFilterErrorKind};
 use crate::model::api_proxy::{ProxyType, ProxyUserCredentials};
 use crate::model::config::TargetType;
 use crate::model::config::{Config, ConfigInput, ConfigTarget};
 use crate::model::playlist::{get_backdrop_path_value, FieldGetAccessor, PlaylistEntry, PlaylistItemType, XtreamCluster, XtreamPlaylistItem};
 use crate::model::xtream::{INFO_RESOURCE_PREFIX, INFO_RESOURCE_PREFIX_EPISODE, PROP_BACKDROP_PATH, SEASON_RESOURCE_PREFIX};
-use crate::repository::storage::{get_target_storage_path, hash_string};
+use crate::repository::storage::{get_target_id_mapping_file, get_target_storage_path, hash_string};
+use crate::repository::id_namespace::get_target_id_namespace;
 use crate::repository::target_id_mapping::TargetIdMapping;
+use crate::repository::epg_repository::epg_read_channel_programmes;
 use crate::repository::xtream_repository;
 use crate::repository::xtream_repository::{TAG_EPISODES, TAG_INFO_DATA, TAG_SEASONS_DATA};
 use crate::utils::json_utils::get_u32_from_serde_value;
 use crate::utils::request_utils::{extract_extension_from_url, mask_sensitive_info};
-use crate::utils::{download, json_utils, request_utils};
+use crate::utils::{download, json_utils};
 use crate::{debug_if_enabled, info_err};
 
 const ACTION_GET_SERIES_INFO: &str = "get_series_info";
@@ -39,6 +44,7 @@ const ACTION_GET_VOD_INFO: &str = "get_vod_info";
 
 const ACTION_GET_EPG: &str = "get_epg";
 const ACTION_GET_SHORT_EPG: &str = "get_short_epg";
+const DEFAULT_SHORT_EPG_LIMIT: usize = 4;
 const ACTION_GET_CATCHUP_TABLE: &str = "get_simple_data_table";
 const ACTION_GET_LIVE_CATEGORIES: &str = "get_live_categories";
 const ACTION_GET_VOD_CATEGORIES: &str = "get_vod_categories";
@@ -180,7 +186,10 @@ async fn xtream_player_api_stream(
     app_state: &web::Data<AppState>,
     stream_req: XtreamApiStreamRequest<'_>,
 ) -> HttpResponse {
-    let (user, target) = try_option_bad_request!(get_user_target_by_credentials(stream_req.username, stream_req.password, api_req, app_state), false, format!("Could not find any user {}", stream_req.username));
+    let (user, target) = try_option_bad_request!(get_user_target_by_credentials(stream_req.username, stream_req.password, api_req, app_state, req), false, format!("Could not find any user {}", stream_req.username));
+    if app_state.config.t_maintenance.is_enabled() {
+        return maintenance_response(app_state, req).await;
+    }
     let target_name = &target.name;
     if !target.has_output(&TargetType::Xtream) {
         debug!("Target has no xtream output {}", target_name);
@@ -191,6 +200,34 @@ async fn xtream_player_api_stream(
     let pli = try_result_bad_request!(xtream_repository::xtream_get_item_for_stream_id(virtual_id, &app_state.config, target, None).await, true, format!("Failed to read xtream item for stream id {}", virtual_id));
     let input = try_option_bad_request!(app_state.config.get_input_by_id(pli.input_id), true, format!("Cant find input for target {target_name}, context {}, stream_id {virtual_id}", stream_req.context));
 
+    if let XtreamApiStreamContext::Timeshift = &stream_req.context {
+        let timezone = catchup_archive::ServerTimezone::new(&app_state.config.get_user_server_info(&user).timezone);
+        let start = stream_req.action_path.split_once('/').and_then(|(_, start)| catchup_archive::parse_timeshift_start(start, timezone));
+        if let Some(window_start) = user.get_catchup_window_start(chrono::Utc::now().timestamp()) {
+            if start.is_none_or(|start| start < window_start) {
+                if !user.no_log {
+                    debug_if_enabled!("Timeshift request of user {} is outside of the catchup window", user.username);
+                }
+                return HttpResponse::Forbidden().finish();
+            }
+        }
+        if let Some(archive) = target.archive.as_ref() {
+            let recording = match start.zip(pli.epg_channel_id.as_ref()) {
+                Some((start, epg_id)) => catchup_archive::find_recording(archive, target_name, epg_id, start).await,
+                None => None,
+            };
+            if let Some(recording) = recording {
+                debug_if_enabled!("Streaming archive recording {}", recording.path.display());
+                return serve_file(&recording.path, req, "video/mp2t".parse().unwrap_or(mime::APPLICATION_OCTET_STREAM)).await;
+            }
+        }
+    }
+
+    if let Some(path) = local_vod::get_local_file_path(input, &pli.url) {
+        debug_if_enabled!("Streaming local file {}", path.display());
+        return local_vod::serve_local_video(req, &path, virtual_id, stream_ext.as_deref()).await;
+    }
+
     if pli.item_type == PlaylistItemType::LiveHls {
         let stream_url = pli.url.to_string();
         debug_if_enabled!("Redirecting stream request to {}", mask_sensitive_info(&stream_url));
@@ -216,7 +253,8 @@ async fn xtream_player_api_stream(
         true, format!("Cant find stream url for target {target_name}, context {}, stream_id {virtual_id}",
         stream_req.context));
     debug_if_enabled!("Streaming stream request from {}", mask_sensitive_info(&stream_url));
-    stream_response(app_state, &stream_url, req, Some(input), pli.item_type, target).await
+    let input = input.with_stream_headers(&pli.user_agent, &pli.referrer);
+    stream_response(app_state, &stream_url, req, Some(&input), pli.item_type, Some(target), Some(&user)).await
 }
 
 fn get_doc_id_and_field_name(input: &str) -> Option<(u32, &str)> {
@@ -350,7 +388,7 @@ async fn xtream_player_api_resource(
     app_state: &web::Data<AppState>,
     resource_req: XtreamApiStreamRequest<'_>,
 ) -> HttpResponse {
-    let (user, target) = try_option_bad_request!(get_user_target_by_credentials(resource_req.username, resource_req.password, api_req, app_state), false, format!("Could not find any user {}", resource_req.username));
+    let (user, target) = try_option_bad_request!(get_user_target_by_credentials(resource_req.username, resource_req.password, api_req, app_state, req), false, format!("Could not find any user {}", resource_req.username));
     let target_name = &target.name;
     if !target.has_output(&TargetType::Xtream) {
         debug!("Target has no xtream output {}", target_name);
@@ -375,7 +413,7 @@ async fn xtream_player_api_resource(
                 HttpResponse::Found().insert_header(("Location", url.as_str())).finish()
             } else {
                 debug_if_enabled!("Resource request to {}", mask_sensitive_info(&url));
-                resource_response(app_state, url.as_str(), req, None).await
+                resource_response(app_state, url.as_str(), req, app_state.config.get_input_by_id(pli.input_id)).await
             }
         }
     }
@@ -409,6 +447,24 @@ macro_rules! create_xtream_player_api_resource {
     }
 }
 
+async fn xtream_player_api_category_resource(
+    req: HttpRequest,
+    api_req: web::Query<UserApiRequest>,
+    path: web::Path<(String, String, String, String)>,
+    app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let (username, password, category_id, resource) = path.into_inner();
+    let (_user, target) = try_option_bad_request!(get_user_target_by_credentials(&username, &password, &api_req, &app_state, &req), false, format!("Could not find any user {username}"));
+    if resource.trim() != "icon" {
+        return HttpResponse::BadRequest().finish();
+    }
+    let category_id: u32 = try_result_bad_request!(category_id.trim().parse());
+    match xtream_repository::xtream_get_category_icon(&app_state.config, &target.name, category_id) {
+        Some(url) if !url.is_empty() => resource_response(&app_state, url.as_str(), &req, None).await,
+        _ => HttpResponse::NotFound().finish(),
+    }
+}
+
 create_xtream_player_api_stream!(xtream_player_api_live_stream, XtreamApiStreamContext::Live);
 create_xtream_player_api_stream!(xtream_player_api_live_stream_alt, XtreamApiStreamContext::LiveAlt);
 create_xtream_player_api_stream!(xtream_player_api_series_stream, XtreamApiStreamContext::Series);
@@ -473,6 +529,34 @@ async fn xtream_get_stream_info_response(app_state: &AppState, user: &ProxyUserC
     }
 }
 
+fn format_epg_time(timestamp: i64) -> String {
+    chrono::DateTime::from_timestamp(timestamp, 0).map(|dt| dt.format("%Y-%m-%d %H:%M:%S").to_string()).unwrap_or_default()
+}
+
+/// The short epg of an epg override is read from the stored target epg.
+async fn xtream_get_short_epg_from_file(config: &Config, target: &ConfigTarget, epg_channel_id: &str, limit: &str) -> HttpResponse {
+    let Some(epg_path) = get_epg_path_for_target(config, target) else {
+        return HttpResponse::NoContent().finish();
+    };
+    let limit = limit.parse::<usize>().ok().filter(|limit| *limit > 0).unwrap_or(DEFAULT_SHORT_EPG_LIMIT);
+    let now = chrono::Utc::now().timestamp();
+    let listings: Vec<Value> = epg_read_channel_programmes(config, &epg_path, epg_channel_id, Some(now), None).await.into_iter().take(limit)
+        .map(|programme| json!({
+            "id": format!("{epg_channel_id}_{}", programme.start),
+            "epg_id": epg_channel_id,
+            "title": BASE64_STANDARD.encode(programme.title.unwrap_or_default()),
+            "lang": "",
+            "start": format_epg_time(programme.start),
+            "end": format_epg_time(programme.stop),
+            "description": BASE64_STANDARD.encode(programme.desc.unwrap_or_default()),
+            "channel_id": epg_channel_id,
+            "start_timestamp": programme.start.to_string(),
+            "stop_timestamp": programme.stop.to_string(),
+        }))
+        .collect();
+    HttpResponse::Ok().json(json!({"epg_listings": listings}))
+}
+
 async fn xtream_get_short_epg(app_state: &AppState, user: &ProxyUserCredentials, target: &ConfigTarget, stream_id: &str, limit: &str) -> HttpResponse {
     let target_name = &target.name;
     if target.has_output(&TargetType::Xtream) {
@@ -482,6 +566,9 @@ async fn xtream_get_short_epg(app_state: &AppState, user: &ProxyUserCredentials,
         };
 
         if let Ok(pli) = xtream_repository::xtream_get_item_for_stream_id(virtual_id, &app_state.config, target, None).await {
+            if let Some(epg_override) = pli.epg_channel_id.as_ref().and_then(|epg_id| app_state.user_epg_overrides.get_override(&user.username, epg_id)) {
+                return xtream_get_short_epg_from_file(&app_state.config, target, &epg_override, limit).await;
+            }
             let input_id: u16 = pli.input_id;
             if let Some(input) = app_state.config.get_input_by_id(input_id) {
                 if let Some(action_url) = download::get_xtream_player_api_action_url(input, ACTION_GET_SHORT_EPG) {
@@ -493,7 +580,7 @@ async fn xtream_get_short_epg(app_state: &AppState, user: &ProxyUserCredentials,
                         return HttpResponse::Found().insert_header(("Location", info_url)).finish();
                     }
 
-                    return match request_utils::download_text_content(Arc::clone(&app_state.http_client), input, info_url.as_str(), None).await {
+                    return match download::get_xtream_api_content(Arc::clone(&app_state.http_client), &app_state.config, info_url.as_str(), input).await {
                         Ok(content) => HttpResponse::Ok().content_type(mime::APPLICATION_JSON).body(content),
                         Err(err) => {
                             error!("Failed to download epg {}", mask_sensitive_info(err.to_string().as_str()));
@@ -508,43 +595,85 @@ async fn xtream_get_short_epg(app_state: &AppState, user: &ProxyUserCredentials,
     HttpResponse::NoContent().finish()
 }
 
-async fn xtream_player_api_handle_content_action(config: &Config, target_name: &str, action: &str, category_id: &str, req: &HttpRequest) -> Option<HttpResponse> {
+async fn xtream_player_api_handle_content_action(config: &Config, target: &ConfigTarget, user: &ProxyUserCredentials, action: &str, category_id: &str, req: &HttpRequest) -> Option<HttpResponse> {
+    let target_name = target.name.as_str();
     if let Ok((path, content)) = match action {
         ACTION_GET_LIVE_CATEGORIES => xtream_repository::xtream_get_collection_path(config, target_name, xtream_repository::COL_CAT_LIVE),
         ACTION_GET_VOD_CATEGORIES => xtream_repository::xtream_get_collection_path(config, target_name, xtream_repository::COL_CAT_VOD),
         ACTION_GET_SERIES_CATEGORIES => xtream_repository::xtream_get_collection_path(config, target_name, xtream_repository::COL_CAT_SERIES),
         _ => Err(str_to_io_error(""))
     } {
+        let category_id = category_id.trim();
+        let rewrite_icons = target.category_info.is_some() && user.proxy == ProxyType::Reverse;
+        let translations = get_category_translations(config, user, req);
+        let rewrite = |categories: &mut [Value]| {
+            if rewrite_icons {
+                let server_info = config.get_user_server_info(user);
+                xtream_repository::xtream_rewrite_category_icons(categories, &server_info.get_base_url(), user);
+            }
+            if let Some(translations) = translations {
+                xtream_repository::xtream_translate_categories(categories, translations);
+            }
+        };
         if let Some(file_path) = path {
-            let category_id = category_id.trim();
+            if rewrite_icons || translations.is_some() {
+                let filter = if category_id.is_empty() { HashMap::new() } else { HashMap::from([(TAG_CATEGORY_ID, category_id)]) };
+                let mut categories = json_utils::json_filter_file(&file_path, &filter);
+                rewrite(&mut categories);
+                return Some(HttpResponse::Ok().json(categories));
+            }
             if !category_id.is_empty() {
                 return Some(serve_query(&file_path, &HashMap::from([(TAG_CATEGORY_ID, category_id)])));
             }
             return Some(serve_file(&file_path, req, mime::APPLICATION_JSON).await);
         } else if let Some(payload) = content {
-            return Some(HttpResponse::Ok().body(payload));
+            if !rewrite_icons && translations.is_none() && category_id.is_empty() {
+                return Some(HttpResponse::Ok().content_type(mime::APPLICATION_JSON).body(payload));
+            }
+            // the categories of the memory storage
+            let filter = if category_id.is_empty() { HashMap::new() } else { HashMap::from([(TAG_CATEGORY_ID, category_id)]) };
+            let mut categories = json_utils::json_filter_documents(serde_json::from_str(&payload).unwrap_or_default(), &filter);
+            rewrite(&mut categories);
+            return Some(HttpResponse::Ok().json(categories));
         }
         return Some(HttpResponse::NoContent().finish());
     }
     None
 }
 
-async fn xtream_get_catchup_response(app_state: &AppState, target: &ConfigTarget, stream_id: &str, start: &str, end: &str) -> HttpResponse {
+async fn xtream_get_catchup_response(app_state: &AppState, user: &ProxyUserCredentials, target: &ConfigTarget, stream_id: &str, start: &str, end: &str) -> HttpResponse {
     let virtual_id: u32 = try_result_bad_request!(FromStr::from_str(stream_id));
     let pli = try_result_bad_request!(xtream_repository::xtream_get_item_for_stream_id(virtual_id, &app_state.config, target, Some(XtreamCluster::Live)).await);
+    let window_start = user.get_catchup_window_start(chrono::Utc::now().timestamp());
+    // recorded channels are served from the archive
+    if let Some((archive, epg_id)) = target.archive.as_ref().zip(pli.epg_channel_id.as_ref()).filter(|(archive, epg_id)| archive.channels.contains(epg_id.as_ref())) {
+        let recordings = catchup_archive::get_recordings(catchup_archive::get_channel_dir(archive, &target.name, epg_id)).await;
+        let timezone = catchup_archive::ServerTimezone::new(&app_state.config.get_user_server_info(user).timezone);
+        let mut listings = catchup_archive::get_catchup_listings(&recordings, epg_id, timezone);
+        if let Some(window_start) = window_start {
+            catchup_archive::limit_catchup_listings(&mut listings, window_start);
+        }
+        return HttpResponse::Ok().json(json!({TAG_EPG_LISTINGS: listings}));
+    }
     let input = try_option_bad_request!(app_state.config.get_input_by_id(pli.input_id));
     let info_url = try_option_bad_request!(download::get_xtream_player_api_action_url(input, ACTION_GET_CATCHUP_TABLE).map(|action_url| format!("{action_url}&{TAG_STREAM_ID}={}&start={start}&end={end}", pli.provider_id)));
-    let content = try_result_bad_request!(download::get_xtream_stream_info_content(Arc::clone(&app_state.http_client), info_url.as_str(), input).await);
+    let content = try_result_bad_request!(download::get_xtream_api_content(Arc::clone(&app_state.http_client), &app_state.config, info_url.as_str(), input).await);
     let mut doc: Map<String, Value> = try_result_bad_request!(serde_json::from_str(&content));
     let epg_listings = try_option_bad_request!(doc.get_mut(TAG_EPG_LISTINGS).and_then(Value::as_array_mut));
+    if let Some(window_start) = window_start {
+        catchup_archive::limit_catchup_listings(epg_listings, window_start);
+    }
     let target_path = try_option_bad_request!(get_target_storage_path(&app_state.config, target.name.as_str()));
-    let mut target_id_mapping = TargetIdMapping::new(&target_path);
+    let namespace = try_result_bad_request!(get_target_id_namespace(&app_state.config, target.name.as_str()));
+    let target_id_mapping_file = get_target_id_mapping_file(&target_path);
+    let _file_lock = try_result_bad_request!(app_state.config.file_locks.write_lock(&target_id_mapping_file).await);
+    let mut target_id_mapping = TargetIdMapping::open(&app_state.config, &target_id_mapping_file, namespace);
 
     for epg_list_item in epg_listings.iter_mut().filter_map(Value::as_object_mut) {
         // TODO epg_id
         if let Some(catchup_provider_id) = epg_list_item.get(TAG_ID).and_then(Value::as_str).and_then(|id| id.parse::<u32>().ok()) {
             let uuid = hash_string(&format!("{}/{}", pli.url, catchup_provider_id));
-            let virtual_id = target_id_mapping.insert_entry(uuid, catchup_provider_id, PlaylistItemType::Catchup, pli.provider_id);
+            let virtual_id = try_option_bad_request!(target_id_mapping.insert_entry(uuid, catchup_provider_id, PlaylistItemType::Catchup, pli.provider_id));
             epg_list_item.insert(TAG_ID.to_string(), Value::String(virtual_id.to_string()));
         }
     }
@@ -580,7 +709,7 @@ async fn xtream_player_api(
     api_req: UserApiRequest,
     app_state: &web::Data<AppState>,
 ) -> HttpResponse {
-    let user_target = get_user_target(&api_req, app_state);
+    let user_target = get_user_target(&api_req, app_state, req);
     if let Some((user, target)) = user_target {
         if !target.has_output(&TargetType::Xtream) {
             return HttpResponse::Ok().json(get_user_info(&user, &app_state.config));
@@ -614,18 +743,27 @@ async fn xtream_player_api(
                 ).await;
             }
             ACTION_GET_CATCHUP_TABLE => {
-                skip_response_if_flag_set!(skip_live, xtream_get_catchup_response(app_state, target, api_req.stream_id.trim(), api_req.start.trim(), api_req.end.trim()).await);
+                skip_response_if_flag_set!(skip_live, xtream_get_catchup_response(app_state, &user, target, api_req.stream_id.trim(), api_req.start.trim(), api_req.end.trim()).await);
             }
             _ => {}
         }
 
-        // Handle general content actions
+        // Handle general content actions, the empty categories of `xtream_remove_empty_categories` are not written by the update
+
         if let Some(response) = xtream_player_api_handle_content_action(
-            &app_state.config, &target.name, action, api_req.category_id.trim(), req,
+            &app_state.config, target, &user, action, api_req.category_id.trim(), req,
         ).await {
             return response;
         }
 
+        let permit = if matches!(action, ACTION_GET_LIVE_STREAMS | ACTION_GET_VOD_STREAMS | ACTION_GET_SERIES) {
+            match acquire_playlist_permit(app_state).await {
+                Ok(permit) => permit,
+                Err(response) => return response,
+            }
+        } else {
+            None
+        };
         let category_id = api_req.category_id.trim().parse::<u32>().unwrap_or(0);
         let result = match action {
             ACTION_GET_LIVE_STREAMS =>
@@ -643,7 +781,7 @@ async fn xtream_player_api(
                 match result_iter {
                     Ok(xtream_iter) => {
                         // Convert the iterator into a stream of `Bytes`
-                        let content_stream = xtream_create_content_stream(xtream_iter);
+                        let content_stream = hold_playlist_permit(xtream_create_content_stream(xtream_iter), permit);
                         HttpResponse::Ok()
                             .content_type(mime::APPLICATION_JSON)
                             .streaming(content_stream)
@@ -670,18 +808,19 @@ async fn xtream_player_api(
     }
 }
 
+/// The separator is appended to the rendered item, the items are not copied into a new buffer.
 fn xtream_create_content_stream(xtream_iter: impl Iterator<Item=String>) -> impl Stream<Item=Result<Bytes, String>> {
-    let mut first_item = true;
-    stream::once(async { Ok::<Bytes, String>(Bytes::from("[")) }).chain(
-        stream::iter(xtream_iter.map(move |line| {
-            let line = if first_item {
-                first_item = false;
-                line
-            } else {
-                format!(",{line}")
-            };
-            Ok::<Bytes, String>(Bytes::from(line))
-        })).chain(stream::once(async { Ok::<Bytes, String>(Bytes::from("]")) })))
+    let mut xtream_iter = xtream_iter.peekable();
+    let items = std::iter::from_fn(move || {
+        let mut line = xtream_iter.next()?;
+        if xtream_iter.peek().is_some() {
+            line.push(',');
+        }
+        Some(Ok::<Bytes, String>(Bytes::from(line)))
+    });
+    stream::once(async { Ok::<Bytes, String>(Bytes::from_static(b"[")) })
+        .chain(stream::iter(items))
+        .chain(stream::once(async { Ok::<Bytes, String>(Bytes::from_static(b"]")) }))
 }
 
 async fn xtream_player_api_get(req: HttpRequest,
@@ -709,7 +848,7 @@ macro_rules! register_xtream_api {
 macro_rules! register_xtream_api_stream {
      ($cfg:expr, [$(($path:expr, $fn_name:ident)),*]) => {{
        $(
-            $cfg.service(web::resource(format!("{}/{{username}}/{{password}}/{{stream_id}}", $path)).route(web::get().to($fn_name)));
+            $cfg.service(web::resource(format!("{}/{{username}}/{{password}}/{{stream_id}}", $path)).route(web::get().to($fn_name)).route(web::head().to($fn_name)));
         )*
     }};
 }
@@ -725,7 +864,7 @@ macro_rules! register_xtream_api_resource {
 macro_rules! register_xtream_api_timeshift {
      ($cfg:expr, [$($path:expr),*]) => {{
        $(
-            $cfg.service(web::resource($path).route(web::get().to(xtream_player_api_timeshift_stream)).route(web::post().to(xtream_player_api_timeshift_stream)));
+            $cfg.service(web::resource($path).route(web::get().to(xtream_player_api_timeshift_stream)).route(web::head().to(xtream_player_api_timeshift_stream)).route(web::post().to(xtream_player_api_timeshift_stream)));
         )*
     }};
 }
@@ -744,10 +883,68 @@ pub fn xtream_api_register(cfg: &mut web::ServiceConfig) {
     register_xtream_api_resource!(cfg, [
         ("live", xtream_player_api_live_resource),
         ("movie", xtream_player_api_movie_resource),
-        ("series", xtream_player_api_series_resource)]);
+        ("series", xtream_player_api_series_resource),
+        ("category", xtream_player_api_category_resource)]);
     /* TODO
     cfg.service(web::resource("/hlsr/{token}/{username}/{password}/{channel}/{hash}/{chunk}").route(web::get().to(xtream_player_api_hlsr_stream)));
     cfg.service(web::resource("/hls/{token}/{chunk}").route(web::get().to(xtream_player_api_hls_stream)));
     cfg.service(web::resource("/play/{token}/{type}").route(web::get().to(xtream_player_api_play_stream)));
      */
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, RwLock};
+
+    use actix_web::body::to_bytes;
+    use actix_web::test::TestRequest;
+    use serde_json::{json, Value};
+
+    use crate::api::main_api::create_shared_data;
+    use crate::api::model::request::UserApiRequest;
+    use crate::api::xtream_api::xtream_player_api;
+    use crate::model::api_proxy::{ApiProxyConfig, TargetUser};
+    use crate::model::config::{Config, ConfigInput, ConfigSource, ConfigTarget, ConfigTargetOptions, TargetOutput, TargetType};
+    use crate::model::playlist::XtreamCluster;
+    use crate::model::playlist_test_utils::{group, item};
+    use crate::repository::xtream_repository::xtream_write_playlist;
+
+    #[actix_rt::test]
+    async fn remove_empty_categories_test() {
+        for remove_empty_categories in [true, false] {
+            let dir = tempfile::tempdir().unwrap();
+            let target = ConfigTarget {
+                name: "xt".to_string(),
+                output: vec![TargetOutput { target: TargetType::Xtream, filename: None, username: None }],
+                options: Some(ConfigTargetOptions { xtream_remove_empty_categories: remove_empty_categories, ..Default::default() }),
+                ..Default::default()
+            };
+            let input = ConfigInput { id: 1, name: Some("provider".to_string()), url: "http://provider".to_string(), ..Default::default() };
+            let mut cfg = Config {
+                working_dir: dir.path().to_string_lossy().to_string(),
+                sources: vec![ConfigSource { inputs: vec![input], targets: vec![target] }],
+                ..Config::default()
+            };
+            let user = serde_json::from_value(json!({"username": "max", "password": "secret", "token": null, "server": null, "epg_timeshift": null})).unwrap();
+            cfg.t_api_proxy = Arc::new(RwLock::new(Some(ApiProxyConfig { server: vec![], user: vec![TargetUser { target: "xt".to_string(), credentials: vec![user] }], trial: None })));
+            let cfg = Arc::new(cfg);
+            // the channel of the second category has no provider id, it is not written
+            let mut playlist = vec![
+                group(0, "News", XtreamCluster::Live, vec![item("News 1").url("http://provider/live/u/p/1.ts").build()]),
+                group(0, "Empty", XtreamCluster::Live, vec![item("Broken").build()]),
+            ];
+            let _ = xtream_write_playlist(&cfg.sources[0].targets[0], &cfg, &mut playlist, None).await;
+
+            let app_state = create_shared_data(&cfg);
+            let api_req = UserApiRequest { username: "max".to_string(), password: "secret".to_string(), action: "get_live_categories".to_string(), ..Default::default() };
+            let response = xtream_player_api(&TestRequest::default().to_http_request(), api_req, &app_state).await;
+            let body = to_bytes(response.into_body()).await.unwrap();
+            let categories: Vec<Value> = serde_json::from_slice(&body).unwrap();
+            let names: Vec<&str> = categories.iter().filter_map(|category| category["category_name"].as_str()).collect();
+            if remove_empty_categories {
+                assert_eq!(names, vec!["News"]);
+            } else {
+                assert_eq!(names, vec!["News", "Empty"]);
+            }
+        }
+    }
+}
@@ -5,6 +5,10 @@ use jsonwebtoken::{Algorithm, DecodingKey, encode, decode, EncodingKey, Header,
 use crate::model::config::WebAuthConfig;
 use crate::api::model::app_state::AppState;
 use crate::m3u_filter_error::to_io_error;
+use crate::model::playlist::PlaylistItemType;
+
+// the token is only checked when the stream is opened
+const PLAYBACK_TOKEN_LIFETIME_MINS: i64 = 60;
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct Claims {
@@ -30,15 +34,39 @@ pub fn create_jwt(web_auth_config: &WebAuthConfig) -> Result<String, std::io::Er
     }
 }
 
+/// Claims of a web ui player token, the stream is opened without proxy user credentials.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct PlaybackClaims {
+    pub url: String,
+    pub input_id: Option<u16>,
+    pub item_type: PlaylistItemType,
+    iat: i64,
+    exp: i64,
+}
+
+pub fn create_playback_token(secret_key: &[u8], url: &str, input_id: Option<u16>, item_type: PlaylistItemType) -> Result<String, std::io::Error> {
+    let now = Local::now();
+    let claims = PlaybackClaims {
+        url: url.to_string(),
+        input_id,
+        item_type,
+        iat: now.timestamp(),
+        exp: (now + Duration::minutes(PLAYBACK_TOKEN_LIFETIME_MINS)).timestamp(),
+    };
+    encode(&Header::new(Algorithm::HS256), &claims, &EncodingKey::from_secret(secret_key)).map_err(to_io_error)
+}
+
+pub fn verify_playback_token(token: &str, secret_key: &[u8]) -> Option<PlaybackClaims> {
+    decode::<PlaybackClaims>(token, &DecodingKey::from_secret(secret_key), &Validation::new(Algorithm::HS256))
+        .ok().map(|token_data| token_data.claims)
+}
+
+pub fn verify_jwt(token: &str, secret_key: &[u8]) -> bool {
+    decode::<Claims>(token, &DecodingKey::from_secret(secret_key), &Validation::new(Algorithm::HS256)).is_ok()
+}
+
 pub fn verify_token(bearer: Option<BearerAuth>, secret_key: &[u8]) -> bool {
-    if let Some(auth) = bearer {
-        let token = auth.token();
-        let token_message = decode::<Claims>(token, &DecodingKey::from_secret(secret_key), &Validation::new(Algorithm::HS256));
-        if token_message.is_ok() {
-            return true;
-        }
-    }
-    false
+    bearer.is_some_and(|auth| verify_jwt(auth.token(), secret_key))
 }
 
 pub async fn validator(
@@ -62,3 +90,18 @@ pub async fn validator(
 //         .map_into_right_body();
 //     Ok(ErrorHandlerResponse::Response(result))
 // }
+
+#[cfg(test)]
+mod tests {
+    use crate::auth::authenticator::{create_playback_token, verify_playback_token};
+    use crate::model::playlist::PlaylistItemType;
+
+    #[test]
+    fn playback_token_test() {
+        let token = create_playback_token(b"secret", "http://provider.tv/live/1.ts", Some(1), PlaylistItemType::Live).unwrap();
+        let claims = verify_playback_token(&token, b"secret").unwrap();
+        assert_eq!(claims.url, "http://provider.tv/live/1.ts");
+        assert_eq!(claims.input_id, Some(1));
+        assert!(verify_playback_token(&token, b"other").is_none());
+    }
+}
@@ -1,3 +1,4 @@
 pub mod authenticator;
 pub mod password;
+pub mod signature;
 pub mod user;
\ No newline at end of file
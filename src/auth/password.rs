@@ -1,7 +1,7 @@
 use rand::{Rng, distributions::Alphanumeric, rngs::OsRng};
 use crate::m3u_filter_error::str_to_io_error;
 
-fn generate_salt(length: usize) -> String {
+pub fn generate_random_string(length: usize) -> String {
     let rng = OsRng;
     let salt: String = rng
         .sample_iter(&Alphanumeric)
@@ -12,7 +12,7 @@ fn generate_salt(length: usize) -> String {
 }
 
 pub fn hash(password: &[u8]) -> Option<String> {
-    let salt = generate_salt(64);
+    let salt = generate_random_string(64);
     if !password.is_empty() {
         let config = argon2::Config::default();
         if let Ok(hash) = argon2::hash_encoded(password, salt.as_bytes(), &config) {
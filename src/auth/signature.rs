@@ -0,0 +1,24 @@
+use std::fmt::Write;
+
+use ring::hmac;
+
+/// HMAC-SHA256 of the content as `sha256=<hex>`.
+pub fn sign_content(secret: &[u8], content: &[u8]) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret);
+    hmac::sign(&key, content).as_ref().iter().fold(String::from("sha256="), |mut output, b| {
+        let _ = write!(output, "{b:02x}");
+        output
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::auth::signature::sign_content;
+
+    #[test]
+    fn sign_content_test() {
+        // RFC 4231 test case 2
+        assert_eq!(sign_content(b"Jefe", b"what do ya want for nothing?"),
+                   "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843");
+    }
+}
@@ -4,6 +4,7 @@ use std::cell::RefCell;
 use std::collections::HashMap;
 use std::rc::Rc;
 
+use chrono::NaiveDate;
 use enum_iterator::all;
 use log::{debug, error, log_enabled, trace, Level};
 use pest::iterators::Pair;
@@ -23,6 +24,8 @@ pub fn get_field_value(pli: &PlaylistItem, field: &ItemField) -> Rc<String> {
         ItemField::Title => &header.title,
         ItemField::Url => &header.url,
         ItemField::Type => &Rc::new(header.item_type.to_string()),
+        ItemField::Tags => &header.tags,
+        ItemField::Language => &Rc::new(header.get_additional_property_as_str("language").unwrap_or_default()),
     };
     Rc::clone(value)
 }
@@ -34,7 +37,8 @@ pub fn set_field_value(pli: &PlaylistItem, field: &ItemField, value: Rc<String>)
         ItemField::Name => header.name = value,
         ItemField::Title => header.title = value,
         ItemField::Url => header.url = value,
-        ItemField::Type => {}
+        ItemField::Tags => header.tags = value,
+        ItemField::Type | ItemField::Language => {}
     };
 }
 
@@ -47,6 +51,115 @@ impl ValueProvider<'_> {
         let pli = *self.pli.borrow();
         get_field_value(pli, field)
     }
+
+    fn call_numeric(&self, field: NumericField) -> Option<f64> {
+        let pli = *self.pli.borrow();
+        get_numeric_field_value(pli, field)
+    }
+}
+
+/// Fields of the xtream properties which are compared as numbers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NumericField {
+    /// The 10 based rating, `rating_5based` is doubled.
+    Rating,
+    /// The `year` or the year of the release date.
+    Year,
+    /// The `added` unix timestamp.
+    Added,
+}
+
+impl NumericField {
+    const RATING: &'static str = "Rating";
+    const YEAR: &'static str = "Year";
+    const ADDED: &'static str = "Added";
+}
+
+impl std::fmt::Display for NumericField {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", match *self {
+            Self::Rating => Self::RATING,
+            Self::Year => Self::YEAR,
+            Self::Added => Self::ADDED,
+        })
+    }
+}
+
+fn get_year(value: &str) -> Option<f64> {
+    let year = value.trim().get(..4)?;
+    year.parse::<u16>().ok().filter(|year| *year > 1800).map(f64::from)
+}
+
+/// Items without the property have no value, they never match a numeric comparison.
+pub fn get_numeric_field_value(pli: &PlaylistItem, field: NumericField) -> Option<f64> {
+    let header = pli.header.borrow();
+    let number = |name: &str| header.get_additional_property(name).and_then(|value| match value {
+        serde_json::Value::Number(number) => number.as_f64(),
+        serde_json::Value::String(text) => text.trim().parse::<f64>().ok(),
+        _ => None,
+    });
+    match field {
+        NumericField::Rating => number("rating").filter(|rating| *rating > 0.0)
+            .or_else(|| number("rating_5based").filter(|rating| *rating > 0.0).map(|rating| rating * 2.0)),
+        NumericField::Year => ["year", "release_date", "series_release_date"].iter()
+            .find_map(|name| header.get_additional_property_as_str(name).and_then(|value| get_year(&value))),
+        NumericField::Added => number("added"),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ComparisonOperator {
+    Eq,
+    NotEq,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl ComparisonOperator {
+    fn from_text(text: &str) -> Option<Self> {
+        match text {
+            "=" => Some(Self::Eq),
+            "!=" => Some(Self::NotEq),
+            "<" => Some(Self::Lt),
+            "<=" => Some(Self::Le),
+            ">" => Some(Self::Gt),
+            ">=" => Some(Self::Ge),
+            _ => None,
+        }
+    }
+
+    fn compare(self, value: f64, other: f64) -> bool {
+        match self {
+            Self::Eq => (value - other).abs() < f64::EPSILON,
+            Self::NotEq => (value - other).abs() >= f64::EPSILON,
+            Self::Lt => value < other,
+            Self::Le => value <= other,
+            Self::Gt => value > other,
+            Self::Ge => value >= other,
+        }
+    }
+}
+
+impl std::fmt::Display for ComparisonOperator {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", match *self {
+            Self::Eq => "=",
+            Self::NotEq => "!=",
+            Self::Lt => "<",
+            Self::Le => "<=",
+            Self::Gt => ">",
+            Self::Ge => ">=",
+        })
+    }
+}
+
+/// The compared number, dates (`2024-01-31`) are compared as unix timestamp. The text is kept for display.
+#[derive(Debug, Clone)]
+pub struct NumericValue {
+    pub value: f64,
+    pub text: String,
 }
 
 pub trait ValueProcessor {
@@ -77,7 +190,7 @@ pub struct RegexWithCaptures {
 #[derive(Parser)]
 #[grammar_inline = r#"
 WHITESPACE = _{ " " | "\t" | "\r" | "\n"}
-field = { ^"group" | ^"title" | ^"name" | ^"url" }
+field = { ^"group" | ^"title" | ^"name" | ^"url" | ^"tags" | ^"language" }
 and = { ^"and" }
 or = { ^"or" }
 not = { ^"not" }
@@ -86,7 +199,12 @@ type_value = { ^"live" | ^"vod" | ^"series" }
 type_comparison = { ^"type" ~ "=" ~ type_value }
 field_comparison_value = _{ regexp }
 field_comparison = { field ~ "~" ~ field_comparison_value }
-comparison = { field_comparison | type_comparison }
+numeric_field = { ^"rating" | ^"year" | ^"added" }
+comparison_op = { ">=" | "<=" | "!=" | ">" | "<" | "=" }
+date_value = @{ ASCII_DIGIT{4} ~ "-" ~ ASCII_DIGIT{2} ~ "-" ~ ASCII_DIGIT{2} }
+number_value = @{ "-"? ~ ASCII_DIGIT+ ~ ("." ~ ASCII_DIGIT+)? }
+numeric_comparison = { numeric_field ~ comparison_op ~ (date_value | number_value) }
+comparison = { field_comparison | type_comparison | numeric_comparison }
 bool_op = { and | or }
 expr_group = { "(" ~ expr ~ ")" }
 basic_expr = _{ comparison | expr_group }
@@ -130,6 +248,7 @@ pub enum Filter {
     Group(Box<Filter>),
     FieldComparison(ItemField, RegexWithCaptures),
     TypeComparison(ItemField, PlaylistItemType),
+    NumericComparison(NumericField, ComparisonOperator, NumericValue),
     UnaryExpression(UnaryOperator, Box<Filter>),
     BinaryExpression(Box<Filter>, BinaryOperator, Box<Filter>),
 }
@@ -166,6 +285,13 @@ impl Filter {
                     is_match
                 })
             }
+            Self::NumericComparison(field, op, value) => {
+                let is_match = provider.call_numeric(*field).is_some_and(|field_value| op.compare(field_value, value.value));
+                if log_enabled!(Level::Trace) {
+                    debug!("Match {}: {self}", if is_match { "found" } else { "failed" });
+                }
+                is_match
+            }
             Self::Group(expr) => expr.filter(provider, processor),
             Self::UnaryExpression(op, expr) => match op {
                 UnaryOperator::Not => !expr.filter(provider, processor),
@@ -203,6 +329,9 @@ impl std::fmt::Display for Filter {
                     _ => Self::UNSUPPORTED
                 })
             }
+            Self::NumericComparison(field, op, value) => {
+                write!(f, "{field} {op} {}", value.text)
+            }
             Self::Group(stmt) => {
                 write!(f, "({stmt})")
             }
@@ -303,6 +432,31 @@ fn get_parser_type_comparison(expr: Pair<Rule>) -> Result<Filter, M3uFilterError
     item_type.map_or_else(|| create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "cant parse item type: {text_item_type}"), |itype| Ok(Filter::TypeComparison(ItemField::Type, itype)))
 }
 
+fn get_parser_numeric_comparison(expr: Pair<Rule>) -> Result<Filter, M3uFilterError> {
+    let mut expr_inner = expr.into_inner();
+    let (Some(field), Some(op), Some(value)) = (expr_inner.next(), expr_inner.next(), expr_inner.next()) else {
+        return create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "incomplete numeric comparison");
+    };
+    let numeric_field = match field.as_str().to_lowercase().as_str() {
+        "rating" => NumericField::Rating,
+        "year" => NumericField::Year,
+        "added" => NumericField::Added,
+        _ => return create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "unknown field: {}", field.as_str()),
+    };
+    let Some(operator) = ComparisonOperator::from_text(op.as_str()) else {
+        return create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "unknown comparison operator: {}", op.as_str());
+    };
+    let text = value.as_str();
+    let number = match value.as_rule() {
+        Rule::date_value => NaiveDate::parse_from_str(text, "%Y-%m-%d").ok()
+            .and_then(|date| date.and_hms_opt(0, 0, 0))
+            .map(|date| date.and_utc().timestamp() as f64),
+        _ => text.parse::<f64>().ok(),
+    };
+    number.map_or_else(|| create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "cant parse value: {text}"),
+                       |number| Ok(Filter::NumericComparison(numeric_field, operator, NumericValue { value: number, text: text.to_string() })))
+}
+
 macro_rules! handle_expr {
     ($bop: expr, $uop: expr, $stmts: expr, $exp: expr) => {{
         let result = match $bop {
@@ -349,6 +503,13 @@ fn get_parser_expression(
                     Err(err) => errors.push(err.to_string()),
                 }
             }
+            Rule::numeric_comparison => {
+                let comp_res = get_parser_numeric_comparison(pair);
+                match comp_res {
+                    Ok(comp) => handle_expr!(bop, uop, stmts, comp),
+                    Err(err) => errors.push(err.to_string()),
+                }
+            }
             Rule::comparison | Rule::expr => {
                 handle_expr!(bop, uop, stmts, get_parser_expression(pair, templates, errors));
             }
@@ -554,8 +715,10 @@ mod tests {
 
     use regex::Regex;
 
+    use serde_json::json;
+
     use crate::filter::{get_filter, MockValueProcessor, ValueProvider};
-    use crate::model::playlist::{PlaylistItem, PlaylistItemHeader};
+    use crate::model::playlist::{PlaylistItem, PlaylistItemHeader, PlaylistItemType};
 
     fn create_mock_pli(name: &str, group: &str) -> PlaylistItem {
         PlaylistItem {
@@ -723,4 +886,37 @@ mod tests {
             }
         }
     }
+
+    fn create_mock_vod(name: &str, properties: serde_json::Value) -> PlaylistItem {
+        PlaylistItem {
+            header: RefCell::new(PlaylistItemHeader {
+                name: Rc::new(name.to_string()),
+                item_type: PlaylistItemType::Video,
+                additional_properties: Some(properties),
+                ..Default::default()
+            }),
+        }
+    }
+
+    #[test]
+    fn test_filter_numeric() {
+        let flt = r#"(Type = vod) AND (Year > 2015) AND (Rating >= 6) AND Added >= 2024-01-01 AND NOT Language ~ "en""#;
+        let filter = get_filter(flt, None).unwrap();
+        assert_eq!(format!("{filter}"), flt);
+        let channels = [
+            create_mock_vod("match", json!({"year": "2019", "rating": "6.5", "added": "1717200000", "language": "de"})),
+            create_mock_vod("release date", json!({"release_date": "2020-05-01", "rating_5based": 3.5, "added": "1717200000"})),
+            create_mock_vod("old", json!({"year": "2010", "rating": 8, "added": "1717200000"})),
+            create_mock_vod("added before", json!({"year": "2019", "rating": 8, "added": "1700000000"})),
+            create_mock_vod("english", json!({"year": "2019", "rating": 8, "added": "1717200000", "language": "en"})),
+            create_mock_vod("no rating", json!({"year": "2019", "added": "1717200000"})),
+        ];
+        let mut processor = MockValueProcessor {};
+        let filtered: Vec<String> = channels.iter()
+            .filter(|&chan| filter.filter(&ValueProvider { pli: RefCell::new(chan) }, &mut processor))
+            .map(|chan| chan.header.borrow().name.to_string())
+            .collect();
+        assert_eq!(filtered, vec!["match", "release date"]);
+        assert!(get_filter("Year > abc", None).is_err());
+    }
 }
@@ -1,4 +1,8 @@
 #![allow(clippy::module_name_repetitions)]
+#![allow(mismatched_lifetime_syntaxes, clippy::collapsible_match, clippy::derivable_impls, clippy::double_ended_iterator_last, clippy::io_other_error,
+    clippy::manual_is_multiple_of, clippy::manual_ok_err, clippy::unnecessary_option_map_or_else, clippy::unnecessary_unwrap)]
+#![cfg_attr(test, allow(clippy::assertions_on_constants, clippy::bool_assert_comparison, clippy::into_iter_on_ref, clippy::needless_borrow,
+    clippy::single_match, clippy::useless_vec))]
 extern crate core;
 extern crate env_logger;
 extern crate pest;
@@ -7,15 +11,18 @@ extern crate pest_derive;
 
 use actix_rt::System;
 use std::fs::File;
+use std::io::Write;
 use std::path::PathBuf;
 use std::sync::Arc;
 
 use crate::auth::password::generate_password;
 use crate::model::config::{validate_targets, Config, HealthcheckConfig, ProcessTargets};
 use crate::model::healthcheck::Healthcheck;
-use crate::processing::playlist_processor;
-use crate::utils::{config_reader, file_utils};
-use clap::Parser;
+use crate::model::stats::ProcessingSummary;
+use crate::processing::{playlist_processor, selftest, user_import};
+use crate::utils::lru_cache::STALE_PART_FILE_AGE;
+use crate::utils::{config_reader, file_utils, progress, sanitize};
+use clap::{Parser, Subcommand};
 use env_logger::Builder;
 use log::{error, info, LevelFilter};
 mod api;
@@ -45,44 +52,169 @@ const LOG_ERROR_LEVEL_MOD: &[&str] = &[
 #[command(version)]
 #[command(about = "Extended M3U playlist filter", long_about = None)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// The config directory
-    #[arg(short = 'p', long = "config-path")]
+    #[arg(short = 'p', long = "config-path", global = true)]
     config_path: Option<String>,
 
     /// The config file
-    #[arg(short = 'c', long = "config")]
+    #[arg(short = 'c', long = "config", global = true)]
     config_file: Option<String>,
 
     /// The source config file
-    #[arg(short = 'i', long = "source")]
+    #[arg(short = 'i', long = "source", global = true)]
     source_file: Option<String>,
 
     /// The mapping file
-    #[arg(short = 'm', long = "mapping")]
+    #[arg(short = 'm', long = "mapping", global = true)]
     mapping_file: Option<String>,
 
-    /// The target to process
-    #[arg(short = 't', long)]
-    target: Option<Vec<String>>,
-
     /// The user file
-    #[arg(short = 'a', long = "api-proxy")]
+    #[arg(short = 'a', long = "api-proxy", global = true)]
     api_proxy: Option<String>,
 
-    /// Run in server mode
-    #[arg(short = 's', long, default_value_t = false, default_missing_value = "true")]
-    server: bool,
-
     /// log level
-    #[arg(short = 'l', long = "log-level", default_missing_value = "info")]
+    #[arg(short = 'l', long = "log-level", global = true, default_missing_value = "info")]
     log_level: Option<String>,
 
-    #[arg(short = None, long = "genpwd", default_value_t = false, default_missing_value = "true")]
+    // The switches below are the arguments before the subcommands, they are kept for existing scripts.
+    #[arg(short = 't', long, hide = true)]
+    target: Option<Vec<String>>,
+
+    #[arg(short = 's', long, hide = true, default_value_t = false, default_missing_value = "true")]
+    server: bool,
+
+    #[arg(short = None, long = "genpwd", hide = true, default_value_t = false, default_missing_value = "true")]
     genpwd: bool,
 
-    #[arg(short = None, long = "healthcheck", default_value_t = false, default_missing_value = "true"
-    )]
+    #[arg(short = None, long = "healthcheck", hide = true, default_value_t = false, default_missing_value = "true")]
     healthcheck: bool,
+
+    #[arg(short = None, long = "compact", hide = true, default_value_t = false, default_missing_value = "true")]
+    compact: bool,
+
+    #[arg(short = None, long = "migrate-ids", hide = true, default_value_t = false, default_missing_value = "true")]
+    migrate_ids: bool,
+
+    #[arg(short = None, long = "selftest", hide = true, default_value_t = false, default_missing_value = "true")]
+    selftest: bool,
+
+    #[arg(short = None, long = "progress", hide = true, default_value_t = false, default_missing_value = "true")]
+    progress: bool,
+
+    #[arg(short = None, long = "json-summary", hide = true, num_args = 0..=1, default_missing_value = "-")]
+    json_summary: Option<String>,
+
+    #[arg(short = None, long = "import-users", hide = true)]
+    import_users: Option<String>,
+
+    #[arg(short = None, long = "import-target", hide = true, requires = "import_users")]
+    import_target: Option<String>,
+
+    #[arg(short = None, long = "import-bouquets", hide = true, requires = "import_users")]
+    import_bouquets: Option<String>,
+}
+
+#[derive(Subcommand, Debug, PartialEq, Eq)]
+enum Command {
+    /// Run in server mode
+    #[command(alias = "server")]
+    Serve(ServeArgs),
+    /// Process the targets and exit, the default without subcommand
+    Process(ProcessArgs),
+    /// Manage the users of the api-proxy config
+    #[command(subcommand)]
+    Users(UsersCommand),
+    /// Healthcheck of the running server for docker
+    Healthcheck,
+    /// Generate a UI password
+    Genpwd,
+    /// Maintenance of the storage and the inputs
+    #[command(subcommand)]
+    Maintenance(MaintenanceCommand),
+}
+
+#[derive(clap::Args, Debug, Default, PartialEq, Eq)]
+struct ServeArgs {
+    /// The target to process
+    #[arg(short = 't', long)]
+    target: Option<Vec<String>>,
+}
+
+#[derive(clap::Args, Debug, Default, PartialEq, Eq)]
+struct ProcessArgs {
+    /// The target to process
+    #[arg(short = 't', long)]
+    target: Option<Vec<String>>,
+
+    /// Show the download and processing progress on stderr
+    #[arg(long, default_value_t = false)]
+    progress: bool,
+
+    /// Write a json summary of the run to stdout or to the given file
+    #[arg(long = "json-summary", num_args = 0..=1, default_missing_value = "-")]
+    json_summary: Option<String>,
+}
+
+#[derive(Subcommand, Debug, PartialEq, Eq)]
+enum UsersCommand {
+    /// Import the users of a xtream panel export (csv or json) into the api-proxy config
+    Import(ImportUsersArgs),
+}
+
+#[derive(clap::Args, Debug, PartialEq, Eq)]
+struct ImportUsersArgs {
+    /// The panel export file
+    file: String,
+
+    /// The target for the imported users
+    #[arg(long)]
+    target: Option<String>,
+
+    /// Maps panel bouquet ids to targets, e.g. "1=sports,2=movies"
+    #[arg(long)]
+    bouquets: Option<String>,
+}
+
+#[derive(Subcommand, Debug, PartialEq, Eq)]
+enum MaintenanceCommand {
+    /// Compact the storage files of all inputs and targets
+    Compact,
+    /// Move the virtual ids of all targets into the id namespace of the target
+    MigrateIds,
+    /// Test the connectivity of all enabled inputs, no outputs are written
+    Selftest,
+    /// Rebuild the resource cache index, remove partial and orphaned files and enforce the cache size
+    CacheScan,
+}
+
+impl Args {
+    /// The subcommand, without subcommand the top level switches are mapped to their subcommand.
+    fn take_command(&mut self) -> Command {
+        if let Some(command) = self.command.take() {
+            return command;
+        }
+        let target = self.target.take();
+        if self.healthcheck {
+            Command::Healthcheck
+        } else if self.genpwd {
+            Command::Genpwd
+        } else if self.compact {
+            Command::Maintenance(MaintenanceCommand::Compact)
+        } else if self.migrate_ids {
+            Command::Maintenance(MaintenanceCommand::MigrateIds)
+        } else if self.selftest {
+            Command::Maintenance(MaintenanceCommand::Selftest)
+        } else if let Some(file) = self.import_users.take() {
+            Command::Users(UsersCommand::Import(ImportUsersArgs { file, target: self.import_target.take(), bouquets: self.import_bouquets.take() }))
+        } else if self.server {
+            Command::Serve(ServeArgs { target })
+        } else {
+            Command::Process(ProcessArgs { target, progress: self.progress, json_summary: self.json_summary.take() })
+        }
+    }
 }
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
@@ -96,31 +228,55 @@ const VERSION: &str = env!("CARGO_PKG_VERSION");
 // pub static malloc_conf: &[u8] = b"lg_prof_interval:25,prof:true,prof_leak:true,prof_active:true,prof_prefix:/tmp/jeprof\0";
 
 fn main() {
-    let args = Args::parse();
+    let mut args = Args::parse();
+    let command = args.take_command();
     let default_log_level = std::env::var("M3U_FILTER_LOG").unwrap_or_else(|_| "info".to_string());
     init_logger(args.log_level.as_ref().unwrap_or(&default_log_level));
 
+    if command == Command::Genpwd {
+        match generate_password() {
+            Ok(pwd) => println!("{pwd}"),
+            Err(err) => error!("{err}"),
+        }
+        return;
+    }
+
     let config_path: String = args.config_path.unwrap_or_else(file_utils::get_default_config_path);
     let config_file: String = args.config_file.unwrap_or_else(|| file_utils::get_default_config_file_path(&config_path));
 
-    if args.healthcheck {
+    if command == Command::Healthcheck {
         healthcheck(config_file.as_str());
     }
 
     let sources_file: String = args.source_file.unwrap_or_else(|| file_utils::get_default_sources_file_path(&config_path));
     let mut cfg = config_reader::read_config(config_path.as_str(), config_file.as_str(), sources_file.as_str()).unwrap_or_else(|err| exit!("{}", err));
-
-    if args.genpwd {
-        match generate_password() {
-            Ok(pwd) => println!("{pwd}"),
-            Err(err) => error!("{err}"),
-        }
-        return;
+    if let Some(sanitize_config) = cfg.log.as_ref().and_then(|log| log.sanitize.clone()) {
+        sanitize::set_sanitize_config(sanitize_config);
     }
 
     create_directories(&cfg);
 
-    let targets = validate_targets(args.target.as_ref(), &cfg.sources).unwrap_or_else(|err| exit!("{}", err));
+    let (server, target, process_args) = match command {
+        Command::Maintenance(maintenance) => {
+            match maintenance {
+                MaintenanceCommand::Compact => compact_storage(&cfg),
+                MaintenanceCommand::MigrateIds => migrate_ids(&cfg),
+                MaintenanceCommand::Selftest => selftest(&cfg),
+                MaintenanceCommand::CacheScan => scan_cache(&cfg),
+            }
+            return;
+        }
+        Command::Users(UsersCommand::Import(import_args)) => {
+            config_reader::read_api_proxy_config(args.api_proxy, &mut cfg);
+            import_users(&cfg, &import_args.file, import_args.target, import_args.bouquets.as_deref());
+            return;
+        }
+        Command::Serve(serve_args) => (true, serve_args.target, ProcessArgs::default()),
+        Command::Process(mut process_args) => (false, process_args.target.take(), process_args),
+        Command::Healthcheck | Command::Genpwd => return,
+    };
+
+    let targets = validate_targets(target.as_ref(), &cfg.sources).unwrap_or_else(|err| exit!("{}", err));
 
     info!("Version: {}", VERSION);
     info!("Current time: {}", chrono::offset::Local::now().format("%Y-%m-%d %H:%M:%S").to_string());
@@ -134,6 +290,9 @@ fn main() {
         }
     }
 
+    cfg.log_storage_mode();
+    cfg.log_low_memory();
+
     match config_reader::read_mappings(args.mapping_file, &mut cfg) {
         Ok(Some(mapping_file)) => {
             info!("Mapping file: {mapping_file}");
@@ -146,13 +305,20 @@ fn main() {
     temp_path.push("tmp");
     let _ = tempfile::env::override_temp_dir(&temp_path);
 
-    if args.server {
+    repository::target_id_mapping::migrate_id_mapping_layouts(&cfg);
+    repository::xtream_repository::migrate_xtream_playlist_layouts(&cfg);
+    repository::m3u_repository::migrate_m3u_playlist_layouts(&cfg);
+
+    if server {
         if let Some(api_proxy_file) = config_reader::read_api_proxy_config(args.api_proxy, &mut cfg) {
             info!("Api Proxy File: {api_proxy_file}");
         }
         start_in_server_mode(Arc::new(cfg), Arc::new(targets));
     } else {
-        start_in_cli_mode(Arc::new(cfg), Arc::new(targets));
+        if process_args.progress {
+            progress::enable_progress();
+        }
+        start_in_cli_mode(Arc::new(cfg), Arc::new(targets), process_args.json_summary.as_deref());
     }
 }
 
@@ -186,9 +352,89 @@ fn create_directories(cfg: &Config) {
     }
 }
 
-fn start_in_cli_mode(cfg: Arc<Config>, targets: Arc<ProcessTargets>) {
+fn compact_storage(cfg: &Config) {
+    match System::new().block_on(async { repository::storage_compaction::compact_storage(cfg).await }) {
+        Ok(report) => {
+            if let Ok(json) = serde_json::to_string_pretty(&report) {
+                println!("{json}");
+            }
+        }
+        Err(err) => exit!("Storage compaction failed: {err}"),
+    }
+}
+
+fn scan_cache(cfg: &Config) {
+    let Some(cache) = cfg.t_resource_cache.as_ref() else {
+        exit!("Resource cache is not enabled");
+    };
+    match System::new().block_on(async { cache.lock().await.scan(STALE_PART_FILE_AGE).await }) {
+        Ok(report) => {
+            if let Ok(json) = serde_json::to_string_pretty(&report) {
+                println!("{json}");
+            }
+        }
+        Err(err) => exit!("Cache scan failed: {err}"),
+    }
+}
+
+fn migrate_ids(cfg: &Config) {
+    let report = System::new().block_on(async { repository::id_namespace::migrate_id_namespaces(cfg).await });
+    if let Ok(json) = serde_json::to_string_pretty(&report) {
+        println!("{json}");
+    }
+    if !report.errors.is_empty() {
+        std::process::exit(1);
+    }
+}
+
+fn selftest(cfg: &Config) {
+    let client = Arc::new(reqwest::Client::new());
+    let results = System::new().block_on(async { selftest::run_selftest(client, cfg).await });
+    println!("{}", selftest::format_selftest_matrix(&results));
+    if !results.iter().all(selftest::InputSelftest::is_success) {
+        std::process::exit(1);
+    }
+}
+
+fn import_users(cfg: &Config, import_file: &str, target: Option<String>, bouquets: Option<&str>) {
+    let content = std::fs::read_to_string(import_file).unwrap_or_else(|err| exit!("Failed to read {import_file}: {err}"));
+    let Some(target) = target.or_else(|| cfg.sources.iter().flat_map(|source| &source.targets).next().map(|target| target.name.clone())) else {
+        exit!("No target for the imported users");
+    };
+    let bouquets = bouquets.map(|mapping| mapping.split(',')
+        .filter_map(|entry| entry.split_once('='))
+        .map(|(bouquet, target)| (bouquet.trim().to_string(), target.trim().to_string()))
+        .collect()).unwrap_or_default();
+    let options = user_import::UserImportOptions { target, bouquets };
+    match user_import::import_users(cfg, &content, &options) {
+        Ok(report) => {
+            if let Ok(json) = serde_json::to_string_pretty(&report) {
+                println!("{json}");
+            }
+        }
+        Err(err) => exit!("User import failed: {err}"),
+    }
+}
+
+fn start_in_cli_mode(cfg: Arc<Config>, targets: Arc<ProcessTargets>, json_summary: Option<&str>) {
     let client = Arc::new(reqwest::Client::new());
-    System::new().block_on(async { playlist_processor::exec_processing(client, cfg, targets).await });
+    let summary = System::new().block_on(async { playlist_processor::exec_processing(client, cfg, targets).await });
+    if let (Some(destination), Some(summary)) = (json_summary, summary) {
+        write_json_summary(destination, &summary);
+    }
+}
+
+fn write_json_summary(destination: &str, summary: &ProcessingSummary) {
+    match serde_json::to_string_pretty(summary) {
+        Ok(json) => {
+            if destination == "-" {
+                println!("{json}");
+            } else if let Err(err) = std::fs::write(destination, json) {
+                error!("Failed to write json summary {destination}: {err}");
+            }
+        }
+        Err(err) => error!("Failed to serialize json summary: {err}"),
+    }
 }
 
 fn start_in_server_mode(cfg: Arc<Config>, targets: Arc<ProcessTargets>) {
@@ -226,6 +472,16 @@ fn init_logger(log_level: &str) {
     for module in LOG_ERROR_LEVEL_MOD {
         log_builder.filter_module(module, LevelFilter::Error);
     }
+    // same as the default format, log lines of the configured targets are sanitized
+    log_builder.format(|buf, record| {
+        let style = buf.default_level_style(record.level());
+        let message = record.args().to_string();
+        if sanitize::is_log_excluded(&message) {
+            return Ok(());
+        }
+        writeln!(buf, "[{} {style}{:<5}{style:#} {}] {}", buf.timestamp(), record.level(), record.target(),
+                 sanitize::sanitize_log_message(record.target(), &message))
+    });
     log_builder.init();
     info!("Log Level {}", get_log_level(log_level));
 }
@@ -245,3 +501,38 @@ fn healthcheck(config_file: &str) {
 
     std::process::exit(1);
 }
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use crate::{Args, Command, ImportUsersArgs, MaintenanceCommand, ProcessArgs, ServeArgs, UsersCommand};
+
+    fn parse_command(args: &[&str]) -> Command {
+        Args::try_parse_from(args).unwrap().take_command()
+    }
+
+    #[test]
+    fn cli_command_test() {
+        assert_eq!(parse_command(&["m3u-filter", "serve", "-p", "/config"]), Command::Serve(ServeArgs { target: None }));
+        assert_eq!(parse_command(&["m3u-filter", "process", "-t", "a", "-t", "b", "--json-summary"]),
+                   Command::Process(ProcessArgs { target: Some(vec!["a".to_string(), "b".to_string()]), progress: false, json_summary: Some("-".to_string()) }));
+        assert_eq!(parse_command(&["m3u-filter", "maintenance", "migrate-ids"]), Command::Maintenance(MaintenanceCommand::MigrateIds));
+        assert_eq!(parse_command(&["m3u-filter", "maintenance", "cache-scan"]), Command::Maintenance(MaintenanceCommand::CacheScan));
+        assert_eq!(parse_command(&["m3u-filter", "users", "import", "users.csv", "--target", "all"]),
+                   Command::Users(UsersCommand::Import(ImportUsersArgs { file: "users.csv".to_string(), target: Some("all".to_string()), bouquets: None })));
+    }
+
+    #[test]
+    fn cli_legacy_switches_test() {
+        assert_eq!(parse_command(&["m3u-filter", "-s", "-p", "/config"]), Command::Serve(ServeArgs { target: None }));
+        assert_eq!(parse_command(&["m3u-filter", "-p", "/config", "--healthcheck"]), Command::Healthcheck);
+        assert_eq!(parse_command(&["m3u-filter", "--genpwd"]), Command::Genpwd);
+        assert_eq!(parse_command(&["m3u-filter", "--compact"]), Command::Maintenance(MaintenanceCommand::Compact));
+        assert_eq!(parse_command(&["m3u-filter", "-t", "a", "--progress"]),
+                   Command::Process(ProcessArgs { target: Some(vec!["a".to_string()]), progress: true, json_summary: None }));
+        assert_eq!(parse_command(&["m3u-filter", "--import-users", "users.csv", "--import-bouquets", "1=sports"]),
+                   Command::Users(UsersCommand::Import(ImportUsersArgs { file: "users.csv".to_string(), target: None, bouquets: Some("1=sports".to_string()) })));
+        assert_eq!(parse_command(&["m3u-filter"]), Command::Process(ProcessArgs::default()));
+    }
+}
@@ -1,6 +1,18 @@
-use crate::model::config::MessagingConfig;
+use crate::model::config::{Config, MessagingConfig, WebhookConfig};
+use crate::model::stats::ProcessingSummary;
+use crate::utils::file_utils::file_reader;
+use crate::utils::json_utils::json_write_documents_to_file;
 use log::{debug, error};
 use reqwest::header;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::PathBuf;
+use std::time::Duration;
+
+// channel counts of the last processing, to detect changes between the runs
+const FILE_TARGET_CHANNEL_COUNTS: &str = "target_channel_counts.json";
+const WEBHOOK_TIMEOUT_SECS: u64 = 30;
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
 pub enum MsgKind {
@@ -14,6 +26,48 @@ pub enum MsgKind {
     Watch,
 }
 
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookEvent {
+    ProcessingFinished,
+    ProcessingFailed,
+    ChannelCountChanged,
+}
+
+impl WebhookEvent {
+    const fn as_str(self) -> &'static str {
+        match self {
+            Self::ProcessingFinished => "processing_finished",
+            Self::ProcessingFailed => "processing_failed",
+            Self::ChannelCountChanged => "channel_count_changed",
+        }
+    }
+}
+
+/// Payload format of a webhook, `generic` sends the event with its data.
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum WebhookFormat {
+    #[default]
+    Generic,
+    Discord,
+    Slack,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ChannelCountChange {
+    target: String,
+    previous: usize,
+    channels: usize,
+}
+
+struct WebhookMessage {
+    event: WebhookEvent,
+    target: Option<String>,
+    message: String,
+    data: Value,
+}
+
 fn is_enabled(kind: &MsgKind, cfg: &MessagingConfig) -> bool {
     cfg.notify_on.contains(kind)
 }
@@ -51,3 +105,127 @@ pub fn send_message(kind: &MsgKind, cfg: Option<&MessagingConfig>, msg: &str) {
         }
     }
 }
+
+/// Targets whose channel count changed by at least `threshold` percent since the last run.
+fn get_channel_count_changes(previous: &HashMap<String, usize>, current: &HashMap<String, usize>, threshold: u8) -> Vec<ChannelCountChange> {
+    let mut changes: Vec<ChannelCountChange> = current.iter().filter_map(|(target, &channels)| {
+        let &last = previous.get(target)?;
+        let change = last.abs_diff(channels);
+        let exceeded = if last == 0 { change > 0 } else { change * 100 >= last * usize::from(threshold) };
+        (change > 0 && exceeded).then(|| ChannelCountChange { target: target.clone(), previous: last, channels })
+    }).collect();
+    changes.sort_by(|a, b| a.target.cmp(&b.target));
+    changes
+}
+
+fn render_message(webhook: &WebhookConfig, message: &WebhookMessage) -> String {
+    webhook.template.as_ref().map_or_else(|| message.message.clone(), |template| template
+        .replace("{event}", message.event.as_str())
+        .replace("{target}", message.target.as_deref().unwrap_or_default())
+        .replace("{message}", &message.message))
+}
+
+fn create_webhook_payload(webhook: &WebhookConfig, message: &WebhookMessage) -> Value {
+    let text = render_message(webhook, message);
+    match webhook.format {
+        WebhookFormat::Generic => json!({"event": message.event.as_str(), "target": message.target, "message": text, "data": message.data}),
+        WebhookFormat::Discord => json!({"content": text}),
+        WebhookFormat::Slack => json!({"text": text}),
+    }
+}
+
+/// The messages are sent concurrently, the returned future completes after all requests are finished.
+/// The cli mode exits after the processing, the messages have to be sent before.
+async fn send_webhooks(messaging: &MessagingConfig, messages: &[WebhookMessage]) {
+    let client = reqwest::Client::new();
+    let requests = messaging.webhooks.iter().flat_map(|webhook| messages.iter()
+        .filter(|message| webhook.events.is_empty() || webhook.events.contains(&message.event))
+        .map(|message| client.post(&webhook.url).timeout(Duration::from_secs(WEBHOOK_TIMEOUT_SECS)).json(&create_webhook_payload(webhook, message)).send()));
+    for result in futures::future::join_all(requests).await {
+        match result {
+            Ok(response) if response.status().is_success() => debug!("Webhook message sent successfully"),
+            Ok(response) => error!("Webhook message wasn't accepted, status {}", response.status()),
+            Err(err) => error!("Webhook message wasn't sent because of: {err}"),
+        }
+    }
+}
+
+fn read_channel_counts(file: &PathBuf) -> HashMap<String, usize> {
+    File::open(file).ok().map(file_reader)
+        .and_then(|reader| serde_json::from_reader(reader).ok())
+        .unwrap_or_default()
+}
+
+/// Sends the result of the processing and the changed channel counts to the webhooks.
+pub async fn notify_processing(cfg: &Config, summary: &ProcessingSummary) {
+    let Some(messaging) = cfg.messaging.as_ref().filter(|messaging| !messaging.webhooks.is_empty()) else { return; };
+    let targets: Vec<_> = summary.sources.iter().flat_map(|source| &source.targets).collect();
+    let failed: Vec<&str> = targets.iter().filter(|target| !target.success).map(|target| target.name.as_str()).collect();
+    let mut messages = vec![if failed.is_empty() {
+        WebhookMessage {
+            event: WebhookEvent::ProcessingFinished,
+            target: None,
+            message: format!("Processing of {} targets finished in {} secs", targets.len(), summary.took_secs),
+            data: serde_json::to_value(summary).unwrap_or_default(),
+        }
+    } else {
+        WebhookMessage {
+            event: WebhookEvent::ProcessingFailed,
+            target: None,
+            message: format!("Processing failed for targets {}: {}", failed.join(", "), summary.errors.join("; ")),
+            data: serde_json::to_value(summary).unwrap_or_default(),
+        }
+    }];
+
+    let file = PathBuf::from(&cfg.working_dir).join(FILE_TARGET_CHANNEL_COUNTS);
+    let mut channel_counts = read_channel_counts(&file);
+    let current: HashMap<String, usize> = targets.iter().filter_map(|target| target.channels.map(|channels| (target.name.clone(), channels))).collect();
+    for change in get_channel_count_changes(&channel_counts, &current, messaging.channel_change_threshold) {
+        messages.push(WebhookMessage {
+            event: WebhookEvent::ChannelCountChanged,
+            message: format!("Channel count of target {} changed from {} to {}", change.target, change.previous, change.channels),
+            data: json!({"previous": change.previous, "channels": change.channels}),
+            target: Some(change.target),
+        });
+    }
+    if !current.is_empty() {
+        channel_counts.extend(current);
+        if let Err(err) = json_write_documents_to_file(&file, &channel_counts) {
+            error!("Failed to write channel counts {}: {err}", file.display());
+        }
+    }
+    send_webhooks(messaging, &messages).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde_json::Value;
+
+    use crate::messaging::{create_webhook_payload, get_channel_count_changes, ChannelCountChange, WebhookEvent, WebhookFormat, WebhookMessage};
+    use crate::model::config::WebhookConfig;
+
+    #[test]
+    fn channel_count_changes_test() {
+        let previous = HashMap::from([("live".to_string(), 1000), ("vod".to_string(), 200), ("empty".to_string(), 0)]);
+        let current = HashMap::from([("live".to_string(), 950), ("vod".to_string(), 100), ("empty".to_string(), 5), ("new".to_string(), 10)]);
+        assert_eq!(get_channel_count_changes(&previous, &current, 10), vec![
+            ChannelCountChange { target: "empty".to_string(), previous: 0, channels: 5 },
+            ChannelCountChange { target: "vod".to_string(), previous: 200, channels: 100 },
+        ]);
+        assert_eq!(get_channel_count_changes(&previous, &current, 5).len(), 3);
+    }
+
+    #[test]
+    fn webhook_payload_test() {
+        let message = WebhookMessage { event: WebhookEvent::ChannelCountChanged, target: Some("live".to_string()), message: "changed".to_string(), data: Value::Null };
+        let mut webhook = WebhookConfig { url: String::new(), format: WebhookFormat::Discord, events: vec![], template: Some("[{event}] {target}: {message}".to_string()) };
+        assert_eq!(create_webhook_payload(&webhook, &message)["content"], "[channel_count_changed] live: changed");
+        webhook.format = WebhookFormat::Generic;
+        webhook.template = None;
+        let payload = create_webhook_payload(&webhook, &message);
+        assert_eq!(payload["event"], "channel_count_changed");
+        assert_eq!(payload["message"], "changed");
+    }
+}
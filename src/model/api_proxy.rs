@@ -7,6 +7,8 @@ use log::debug;
 use crate::{create_m3u_filter_error_result, info_err};
 use crate::m3u_filter_error::{M3uFilterError, M3uFilterErrorKind};
 use crate::utils::config_reader;
+use crate::utils::default_utils::default_as_true;
+use crate::utils::sanitize;
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize, Sequence, PartialEq, Eq)]
 pub enum ProxyType {
@@ -63,6 +65,48 @@ pub struct ProxyUserCredentials {
     pub proxy: ProxyType,
     pub server: Option<String>,
     pub epg_timeshift: Option<String>,
+    /// Weight for the share of the provider connections, default 1.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider_weight: Option<u16>,
+    /// Records a trace of each stream of the user, see `/api/v1/stream/traces`.
+    #[serde(default)]
+    pub trace_streams: bool,
+    /// Expiry as unix timestamp, expired users are rejected.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exp_date: Option<i64>,
+    /// Max concurrent streams of the user.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_connections: Option<u32>,
+    /// Disabled users are rejected.
+    #[serde(default = "default_as_true", skip_serializing_if = "is_true")]
+    pub enabled: bool,
+    /// Provisioned trial user, disabled when expired.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub trial: bool,
+    /// The requests of the user are not written to the log.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub no_log: bool,
+    /// Locale of the category names, preferred over the `Accept-Language` header.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub locale: Option<String>,
+    /// MAC address of the set-top box, the user of the stalker portal requests.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mac: Option<String>,
+    /// Max age of the catchup programmes in days, older programmes are rejected.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_catchup_days: Option<u16>,
+    /// Set for requests of a trusted reverse proxy, the urls are built with the forwarded protocol and host.
+    #[serde(skip)]
+    pub t_forwarded_origin: Option<ForwardedOrigin>,
+}
+
+#[allow(clippy::trivially_copy_pass_by_ref)]
+const fn is_true(value: &bool) -> bool { *value }
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ForwardedOrigin {
+    pub protocol: String,
+    pub host: String,
 }
 
 impl ProxyUserCredentials {
@@ -75,17 +119,46 @@ impl ProxyUserCredentials {
             }
             self.trim();
         }
+        self.mac = self.mac.as_deref().map(normalize_mac).filter(|mac| !mac.is_empty());
+    }
+
+    pub fn get_provider_weight(&self) -> u16 {
+        self.provider_weight.unwrap_or(1).max(1)
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.exp_date.is_some_and(|exp_date| exp_date <= chrono::Utc::now().timestamp())
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.enabled && !self.is_expired()
+    }
+
+    /// The oldest catchup start the user can request, `None` without `max_catchup_days`.
+    pub fn get_catchup_window_start(&self, now: i64) -> Option<i64> {
+        self.max_catchup_days.map(|days| now - i64::from(days) * 86_400)
+    }
+
+    /// Returns true if the url or log text contains the username or token of the user.
+    pub fn is_referenced_by(&self, text: &str) -> bool {
+        sanitize::contains_username(text, &self.username)
+            || self.token.as_deref().is_some_and(|token| sanitize::contains_token(text, token))
     }
 
     pub fn matches_token(&self, token: &str) -> bool {
         if let Some(tkn) = &self.token {
-            return tkn.eq(token);
+            return tkn.eq(token) && self.is_active();
         }
         false
     }
 
     pub fn matches(&self, username: &str, password: &str) -> bool {
-        self.username.eq(username) && self.password.eq(password)
+        self.username.eq(username) && self.password.eq(password) && self.is_active()
+    }
+
+    /// The mac has to be normalized, see `normalize_mac`.
+    pub fn matches_mac(&self, mac: &str) -> bool {
+        self.mac.as_deref().is_some_and(|user_mac| user_mac == mac) && self.is_active()
     }
 
     pub fn trim(&mut self) {
@@ -100,6 +173,11 @@ impl ProxyUserCredentials {
     }
 }
 
+/// MAC addresses are compared uppercase with `:` as separator.
+pub fn normalize_mac(mac: &str) -> String {
+    mac.trim().replace('-', ":").to_uppercase()
+}
+
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct TargetUser {
     pub target: String,
@@ -123,6 +201,13 @@ impl TargetUser {
             .find(|c| c.matches_token(token))
             .map(|credentials| (credentials, self.target.as_str()))
     }
+
+    pub fn get_target_name_by_mac(&self, mac: &str) -> Option<(&ProxyUserCredentials, &str)> {
+        self.credentials
+            .iter()
+            .find(|c| c.matches_mac(mac))
+            .map(|credentials| (credentials, self.target.as_str()))
+    }
 }
 
 fn default_as_80() -> String {
@@ -191,6 +276,32 @@ impl ApiProxyServerInfo {
         true
     }
 
+    /// Matches the `Host` of a request, `host` or `host:port` with the http or https port.
+    pub fn matches_host(&self, request_host: &str) -> bool {
+        let (host, port) = match request_host.rsplit_once(':') {
+            // ipv6 address without port
+            Some((host, _)) if host.contains(':') && !host.ends_with(']') => (request_host, None),
+            Some((host, port)) => (host, Some(port)),
+            None => (request_host, None),
+        };
+        host.eq_ignore_ascii_case(&self.host) && port.is_none_or(|port| port == self.http_port || port == self.https_port)
+    }
+
+    /// Replaces protocol, host and port with the forwarded values, without port the default port of the protocol is used.
+    pub fn apply_forwarded_origin(&mut self, origin: &ForwardedOrigin) {
+        let (host, port) = match origin.host.rsplit_once(':') {
+            Some((host, port)) if port.parse::<u16>().is_ok() && (!host.contains(':') || host.ends_with(']')) => (host, Some(port)),
+            _ => (origin.host.as_str(), None),
+        };
+        self.protocol = origin.protocol.to_lowercase();
+        self.host = host.to_string();
+        if self.protocol == "https" {
+            self.https_port = port.unwrap_or("443").to_string();
+        } else {
+            self.http_port = port.unwrap_or("80").to_string();
+        }
+    }
+
     pub fn get_base_url(&self) -> String {
         let port = if self.protocol == "https" {
             &self.https_port
@@ -206,16 +317,37 @@ impl ApiProxyServerInfo {
     }
 }
 
+const fn default_trial_hours() -> u32 { 24 }
+const fn default_trial_connections() -> u32 { 1 }
+
+/// Preset of the trial users provisioned through `/api/v1/user/trial`.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct TrialPreset {
+    pub name: String,
+    pub target: String,
+    #[serde(default = "default_trial_hours")]
+    pub duration_hours: u32,
+    #[serde(default = "default_trial_connections")]
+    pub max_connections: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server: Option<String>,
+    #[serde(default = "ProxyType::default")]
+    pub proxy: ProxyType,
+}
+
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct ApiProxyConfig {
     pub server: Vec<ApiProxyServerInfo>,
     pub user: Vec<TargetUser>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trial: Option<Vec<TrialPreset>>,
 }
 
 impl ApiProxyConfig {
     pub fn prepare(&mut self, resolve_var: bool) -> Result<(), M3uFilterError> {
         let mut usernames = HashSet::new();
         let mut tokens = HashSet::new();
+        let mut macs = HashSet::new();
         let mut errors = Vec::new();
         if self.server.is_empty() {
             errors.push("No serverinfo defined".to_string());
@@ -251,6 +383,11 @@ impl ApiProxyConfig {
                         tokens.insert(token.to_string());
                     }
                 }
+                if let Some(mac) = &user.mac {
+                    if !macs.insert(mac.to_string()) {
+                        errors.push(format!("Non unique mac found {}", &user.username));
+                    }
+                }
 
                 if let Some(server_info_name) = &user.server {
                     if !&self
@@ -266,6 +403,26 @@ impl ApiProxyConfig {
                 }
             }
         }
+        let mut preset_names = HashSet::new();
+        for preset in self.trial.iter().flatten() {
+            if preset.name.trim().is_empty() {
+                errors.push("Trial preset name is empty".to_string());
+            } else if !preset_names.insert(preset.name.as_str()) {
+                errors.push(format!("Non unique trial preset name found {}", preset.name));
+            }
+            if preset.duration_hours == 0 {
+                errors.push(format!("Trial preset {} needs a duration", preset.name));
+            }
+            // a user without connections is rejected for every stream
+            if preset.max_connections == 0 {
+                errors.push(format!("Trial preset {} needs at least one connection", preset.name));
+            }
+            if let Some(server_info_name) = &preset.server {
+                if !self.server.iter().any(|server_info| server_info.name.eq(server_info_name)) {
+                    errors.push(format!("No server info with name {} found for trial preset {}", server_info_name, preset.name));
+                }
+            }
+        }
         if errors.is_empty() {
             Ok(())
         } else {
@@ -298,6 +455,29 @@ impl ApiProxyConfig {
         None
     }
 
+    pub fn get_target_name_by_mac(&self, mac: &str) -> Option<(ProxyUserCredentials, String)> {
+        for target_user in &self.user {
+            if let Some((credentials, target_name)) = target_user.get_target_name_by_mac(mac) {
+                return Some((credentials.clone(), target_name.to_string()));
+            };
+        }
+        None
+    }
+
+    /// The server info for the `Host` of a request.
+    pub fn get_server_name_for_host(&self, request_host: &str) -> Option<&str> {
+        self.server.iter().find(|server_info| server_info.matches_host(request_host)).map(|server_info| server_info.name.as_str())
+    }
+
+    /// The usernames and tokens of the users with `no_log`.
+    pub fn get_log_excluded_users(&self) -> HashSet<String> {
+        self.user.iter()
+            .flat_map(|target_user| &target_user.credentials)
+            .filter(|credentials| credentials.no_log)
+            .flat_map(|credentials| std::iter::once(credentials.username.clone()).chain(credentials.token.clone()))
+            .collect()
+    }
+
     pub fn get_user_credentials(&self,username: &str) -> Option<ProxyUserCredentials> {
         let result = self.user.iter()
             .flat_map(|target_user| &target_user.credentials)
@@ -309,3 +489,42 @@ impl ApiProxyConfig {
         result
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::model::api_proxy::{ApiProxyServerInfo, ForwardedOrigin};
+
+    fn server_info(host: &str) -> ApiProxyServerInfo {
+        ApiProxyServerInfo {
+            name: "wan".to_string(),
+            protocol: "https".to_string(),
+            host: host.to_string(),
+            http_port: "8080".to_string(),
+            https_port: "8443".to_string(),
+            rtmp_port: "1935".to_string(),
+            timezone: "UTC".to_string(),
+            message: String::new(),
+        }
+    }
+
+    #[test]
+    fn apply_forwarded_origin_test() {
+        let mut server = server_info("192.168.1.2");
+        server.protocol = "http".to_string();
+        server.apply_forwarded_origin(&ForwardedOrigin { protocol: "https".to_string(), host: "tv.example.com".to_string() });
+        assert_eq!(server.get_base_url(), "https://tv.example.com:443");
+        server.apply_forwarded_origin(&ForwardedOrigin { protocol: "http".to_string(), host: "[::1]:8000".to_string() });
+        assert_eq!(server.get_base_url(), "http://[::1]:8000");
+    }
+
+    #[test]
+    fn matches_host_test() {
+        let server = server_info("tv.example.com");
+        assert!(server.matches_host("tv.example.com"));
+        assert!(server.matches_host("TV.example.com:8443"));
+        assert!(!server.matches_host("tv.example.com:9000"));
+        assert!(!server.matches_host("192.168.1.2:8080"));
+        assert!(server_info("[::1]").matches_host("[::1]:8080"));
+        assert!(server_info("::1").matches_host("::1"));
+    }
+}
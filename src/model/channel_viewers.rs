@@ -0,0 +1,78 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::model::playlist::UUIDType;
+use crate::repository::storage::hash_string;
+use crate::utils::request_utils::replace_stream_extension;
+
+/// Concurrent viewers of the live channels streamed through this server, keyed by the provider url.
+/// The extension of the url is ignored, it changes with the requested `output` format.
+#[derive(Debug, Default)]
+pub struct ChannelViewers {
+    channels: Mutex<HashMap<UUIDType, u32>>,
+}
+
+/// Counts a viewer of the channel, the viewer is released when the guard is dropped.
+#[derive(Debug)]
+pub struct ChannelViewerGuard {
+    viewers: Arc<ChannelViewers>,
+    key: UUIDType,
+}
+
+impl Drop for ChannelViewerGuard {
+    fn drop(&mut self) {
+        self.viewers.release(&self.key);
+    }
+}
+
+pub fn get_channel_viewer_key(url: &str) -> UUIDType {
+    hash_string(&replace_stream_extension(url, ""))
+}
+
+impl ChannelViewers {
+    pub fn acquire(self: &Arc<Self>, url: &str) -> Option<ChannelViewerGuard> {
+        let key = get_channel_viewer_key(url);
+        let mut channels = self.channels.lock().ok()?;
+        *channels.entry(key).or_default() += 1;
+        Some(ChannelViewerGuard { viewers: Arc::clone(self), key })
+    }
+
+    /// The current viewers of the channels which are watched.
+    pub fn get_viewers(&self) -> HashMap<UUIDType, u32> {
+        self.channels.lock().map(|channels| channels.clone()).unwrap_or_default()
+    }
+
+    fn release(&self, key: &UUIDType) {
+        if let Ok(mut channels) = self.channels.lock() {
+            if let Some(viewers) = channels.get_mut(key) {
+                *viewers = viewers.saturating_sub(1);
+                if *viewers == 0 {
+                    channels.remove(key);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::model::channel_viewers::{get_channel_viewer_key, ChannelViewers};
+
+    #[test]
+    fn channel_viewers_test() {
+        let viewers = Arc::new(ChannelViewers::default());
+        let first = viewers.acquire("http://provider.tv/live/u/p/1.ts");
+        let second = viewers.acquire("http://provider.tv/live/u/p/1.m3u8");
+        let other = viewers.acquire("http://provider.tv/live/u/p/2.ts");
+        let key = get_channel_viewer_key("http://provider.tv/live/u/p/1");
+        assert_eq!(viewers.get_viewers().get(&key), Some(&2));
+        drop(first);
+        drop(second);
+        assert!(!viewers.get_viewers().contains_key(&key));
+        assert_eq!(viewers.get_viewers().len(), 1);
+        drop(other);
+        assert!(viewers.get_viewers().is_empty());
+    }
+}
@@ -1,12 +1,13 @@
 #![allow(clippy::struct_excessive_bools)]
 use enum_iterator::Sequence;
-use std::borrow::BorrowMut;
+use std::borrow::{BorrowMut, Cow};
 use std::collections::{HashMap, HashSet};
 use std::fmt::Display;
 use std::fs::File;
 use std::io::BufRead;
 use std::path::PathBuf;
 use std::str::FromStr;
+use std::sync::atomic::AtomicBool;
 use std::sync::{Arc, RwLock};
 
 use crate::auth::user::UserCredential;
@@ -16,21 +17,36 @@ use url::Url;
 
 use crate::filter::{get_filter, prepare_templates, Filter, MockValueProcessor, PatternTemplate, ValueProvider};
 use crate::m3u_filter_error::{M3uFilterError, M3uFilterErrorKind};
-use crate::messaging::MsgKind;
+use crate::messaging::{MsgKind, WebhookEvent, WebhookFormat};
 use crate::model::api_proxy::{ApiProxyConfig, ApiProxyServerInfo, ProxyUserCredentials};
+use crate::model::input_health::InputHealthRegistry;
+use crate::model::maintenance::MaintenanceMode;
+use crate::model::processing_job::ProcessingJobs;
 use crate::model::mapping::Mapping;
 use crate::model::mapping::Mappings;
-use crate::utils::default_utils::{default_as_default, default_as_true, default_as_two_u16};
+use crate::repository::epg_repository::EpgNowNextCache;
+use crate::repository::user_repository::UserBouquets;
+use crate::processing::wasm_plugin::WasmPlugin;
+use crate::repository::memory_storage::MemoryStorage;
+use crate::utils::default_utils::{default_as_default, default_as_one_u16, default_as_true, default_as_two_u16};
 use crate::utils::file_lock_manager::FileLockManager;
-use crate::utils::{config_reader, file_utils};
+use crate::utils::lru_cache::LRUResourceCache;
+use crate::utils::server_name_resolver::ServerNameResolver;
+use crate::utils::{config_reader, file_utils, locale, sanitize};
 use crate::{exit, info_err};
 use crate::utils::file_utils::file_reader;
 use crate::utils::size_utils::parse_size_base_2;
+use crate::utils::secret_resolver::SecretResolver;
+use crate::utils::bandwidth_limiter::BandwidthLimiter;
+use crate::utils::rate_limit::ProviderRateLimit;
+use crate::utils::request_coalescer::RequestCoalescer;
+use crate::utils::preflight::PreflightState;
 
 pub const MAPPER_ATTRIBUTE_FIELDS: &[&str] = &[
     "name", "title", "group", "id", "chno", "logo",
     "logo_small", "parent_code", "audio_track",
-    "time_shift", "rec", "url", "epg_channel_id", "epg_id"
+    "time_shift", "rec", "url", "epg_channel_id", "epg_id",
+    "user_agent", "referrer"
 ];
 
 
@@ -38,6 +54,11 @@ pub const AFFIX_FIELDS: &[&str] = &["name", "title", "group"];
 pub const COUNTER_FIELDS: &[&str] = &["name", "title", "chno"];
 
 const STREAM_QUEUE_SIZE: usize = 1024; // mpsc channel holding messages. with 8092byte chunks and 2Mbit/s approx 8MB
+const DEFAULT_INPUT_CACHE_SIZE: usize = 256 * 1024 * 1024;
+const LOW_MEMORY_STREAM_QUEUE_SIZE: usize = 128; // approx 1MB per buffered stream
+const LOW_MEMORY_HTTP_WORKERS: usize = 2;
+const DEFAULT_DOWNLOAD_RESUME_ATTEMPTS: u8 = 3;
+const PREVIEW_TARGET_SUFFIX: &str = ".preview";
 
 #[macro_export]
 macro_rules! valid_property {
@@ -95,12 +116,15 @@ pub enum TargetType {
     Xtream,
     #[serde(rename = "strm")]
     Strm,
+    #[serde(rename = "stalker")]
+    Stalker,
 }
 
 impl TargetType {
     const M3U: &'static str = "M3u";
     const XTREAM: &'static str = "Xtream";
     const STRM: &'static str = "Strm";
+    const STALKER: &'static str = "Stalker";
 }
 
 impl Display for TargetType {
@@ -109,6 +133,7 @@ impl Display for TargetType {
             Self::M3u => Self::M3U,
             Self::Xtream => Self::XTREAM,
             Self::Strm => Self::STRM,
+            Self::Stalker => Self::STALKER,
         })
     }
 }
@@ -164,6 +189,10 @@ pub enum ItemField {
     Url,
     #[serde(rename = "type")]
     Type,
+    #[serde(rename = "tags")]
+    Tags,
+    #[serde(rename = "language")]
+    Language,
 }
 
 impl ItemField {
@@ -172,6 +201,8 @@ impl ItemField {
     const TITLE: &'static str = "Title";
     const URL: &'static str = "Url";
     const TYPE: &'static str = "Type";
+    const TAGS: &'static str = "Tags";
+    const LANGUAGE: &'static str = "Language";
 }
 
 impl Display for ItemField {
@@ -182,10 +213,13 @@ impl Display for ItemField {
             Self::Title => Self::TITLE,
             Self::Url => Self::URL,
             Self::Type => Self::TYPE,
+            Self::Tags => Self::TAGS,
+            Self::Language => Self::LANGUAGE,
         })
     }
 }
 
+#[allow(dead_code)]
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub enum FilterMode {
     #[serde(rename = "discard")]
@@ -222,6 +256,28 @@ impl ProcessTargets {
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct ConfigSortGroup {
     pub order: SortOrder,
+    // groups matching these patterns are placed first, in the given order
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pinned: Option<Vec<String>>,
+    #[serde(skip_serializing, skip_deserializing)]
+    pub t_pinned_re: Vec<regex::Regex>,
+}
+
+impl ConfigSortGroup {
+    pub fn prepare(&mut self) -> Result<(), M3uFilterError> {
+        if let Some(pinned) = &self.pinned {
+            match pinned.iter().map(|s| regex::Regex::new(s)).collect::<Result<Vec<regex::Regex>, _>>() {
+                Ok(pinned_re) => self.t_pinned_re = pinned_re,
+                Err(err) => return create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "Invalid pinned group regular expression: {}", err),
+            }
+        }
+        Ok(())
+    }
+
+    /// Index of the first matching pinned pattern, `None` for groups which are not pinned.
+    pub fn get_pinned_index(&self, group: &str) -> Option<usize> {
+        self.t_pinned_re.iter().position(|re| re.is_match(group))
+    }
 }
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
@@ -247,6 +303,34 @@ impl ConfigSortChannel {
     }
 }
 
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigCategoryInfo {
+    pub pattern: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub icon: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing, skip_deserializing)]
+    pub re: Option<regex::Regex>,
+}
+
+impl ConfigCategoryInfo {
+    pub fn prepare(&mut self) -> Result<(), M3uFilterError> {
+        match regex::Regex::new(&self.pattern) {
+            Ok(re) => {
+                self.re = Some(re);
+                Ok(())
+            }
+            Err(_) => create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "cant parse category_info regex: {}", &self.pattern),
+        }
+    }
+
+    pub fn matches(&self, category: &str) -> bool {
+        self.re.as_ref().is_some_and(|re| re.is_match(category))
+    }
+}
+
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize, Default)]
 pub struct ConfigSort {
     #[serde(default)]
@@ -259,6 +343,9 @@ pub struct ConfigSort {
 
 impl ConfigSort {
     pub fn prepare(&mut self) -> Result<(), M3uFilterError> {
+        if let Some(groups) = self.groups.as_mut() {
+            groups.prepare()?;
+        }
         if let Some(channels) = self.channels.as_mut() {
             handle_m3u_filter_error_result_list!(M3uFilterErrorKind::Info, channels.iter_mut().map(ConfigSortChannel::prepare));
         }
@@ -304,21 +391,189 @@ pub struct ConfigTargetOptions {
     #[serde(default = "default_as_true")]
     pub xtream_skip_series_direct_source: bool,
     #[serde(default)]
+    pub xtream_remove_empty_categories: bool,
+    /// The stream lists are sent with the json field types of the xtream panels.
+    #[serde(default)]
+    pub xtream_strict_compat: bool,
+    #[serde(default)]
     pub xtream_resolve_series: bool,
     #[serde(default = "default_as_two_u16")]
     pub xtream_resolve_series_delay: u16,
     #[serde(default)]
+    pub merge_series: bool,
+    #[serde(default)]
     pub xtream_resolve_vod: bool,
     #[serde(default = "default_as_two_u16")]
     pub xtream_resolve_vod_delay: u16,
+    /// Max parallel info requests of `xtream_resolve_series` and `xtream_resolve_vod`.
+    #[serde(default = "default_as_one_u16")]
+    pub xtream_resolve_concurrency: u16,
     #[serde(default)]
     pub m3u_include_type_in_url: bool,
     #[serde(default)]
     pub m3u_mask_redirect_url: bool,
     #[serde(default)]
+    pub m3u_epg_now_next: bool,
+    /// The m3u file is split into one file per cluster (`live`, `vod`, `series`).
+    #[serde(default)]
+    pub m3u_split_clusters: bool,
+    /// The m3u file is split into one file per group title prefix.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub m3u_split_group_prefixes: Vec<String>,
+    /// Users with bouquets get an epg variant with the channels of their bouquets,
+    /// written on the first request and cached per bouquet channel set until the next update.
+    #[serde(default)]
+    pub epg_bouquet_variants: bool,
+    /// The gzipped playlist responses are stored in the target storage path and served until the next update.
+    #[serde(default)]
+    pub m3u_gzip_cache: bool,
+    /// The channel notes are emitted as `x-note` and `x-labels` attributes, applied with the next update.
+    #[serde(default)]
+    pub m3u_channel_notes: bool,
+    #[serde(default)]
     pub share_live_streams: bool,
     #[serde(default)]
     pub remove_duplicates: bool,
+    #[serde(default)]
+    pub mapping_report: bool,
+    #[serde(default)]
+    pub id_mapping_retention: u16,
+    #[serde(default)]
+    pub input_failure: InputFailurePolicy,
+    /// Channels with a new provider id or url take over the virtual id of a missing channel with the same name and group.
+    #[serde(default)]
+    pub id_mapping_rematch: bool,
+    #[serde(default)]
+    pub preserve_channel_numbers: bool,
+    #[serde(default)]
+    pub cache_prefetch_categories: u16,
+    /// Live channels without epg id, logo or name get them from the matching channel of the input epg.
+    #[serde(default)]
+    pub epg_enrich_channels: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub epg: Option<EpgTargetOptions>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_encoding: Option<OutputEncoding>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub feed_tags: Option<FeedTagOptions>,
+    /// Overrides the global `custom_videos` for the users of the target.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub custom_videos: Option<CustomVideosConfig>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CustomVideo {
+    ChannelUnavailable,
+    UserConnectionsExhausted,
+}
+
+/// Videos which are streamed instead of an error response. The files are opened for each request,
+/// a changed file is served without restart. Relative paths are relative to the `working_dir`.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CustomVideosConfig {
+    /// The stream can't be opened, e.g. the provider is unreachable or its connection limit is reached.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channel_unavailable: Option<String>,
+    /// The user has no free connection, see `max_connections`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_connections_exhausted: Option<String>,
+}
+
+impl CustomVideosConfig {
+    pub fn get_video(&self, video: CustomVideo) -> Option<&String> {
+        match video {
+            CustomVideo::ChannelUnavailable => self.channel_unavailable.as_ref(),
+            CustomVideo::UserConnectionsExhausted => self.user_connections_exhausted.as_ref(),
+        }
+    }
+
+    fn get_videos(&self) -> impl Iterator<Item=&String> {
+        self.channel_unavailable.iter().chain(self.user_connections_exhausted.iter())
+    }
+}
+
+/// Heuristic tagging of audio description, multi audio and regional channel variants.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct FeedTagOptions {
+    /// Additional name tokens of regional variants, e.g. `London`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub regional: Vec<String>,
+    /// Variants are placed after their parent channel.
+    #[serde(default)]
+    pub group_variants: bool,
+}
+
+#[derive(Debug, Copy, Clone, serde::Serialize, serde::Deserialize, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum NewlineStyle {
+    #[default]
+    Lf,
+    Crlf,
+}
+
+/// Byte order mark and line breaks of the generated m3u, xmltv and strm files.
+#[derive(Debug, Copy, Clone, serde::Serialize, serde::Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct OutputEncoding {
+    #[serde(default)]
+    pub bom: bool,
+    #[serde(default)]
+    pub newline: NewlineStyle,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct EpgTargetOptions {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub drop_categories: Option<Vec<String>>,
+    #[serde(default)]
+    pub strip_icons: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_description_length: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fill_gaps: Option<EpgGapFillOptions>,
+    #[serde(skip)]
+    pub t_drop_categories: Vec<regex::Regex>,
+}
+
+const fn default_epg_gap_block_minutes() -> u32 { 60 }
+const fn default_epg_gap_max_days() -> u32 { 7 }
+fn default_epg_gap_title() -> String { String::from("No information") }
+
+/// Fills the gaps between the programmes of a channel with synthetic programmes.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EpgGapFillOptions {
+    #[serde(default = "default_epg_gap_title")]
+    pub title: String,
+    #[serde(default = "default_epg_gap_block_minutes")]
+    pub block_minutes: u32,
+    #[serde(default = "default_epg_gap_max_days")]
+    pub max_days: u32,
+}
+
+impl EpgTargetOptions {
+    pub fn prepare(&mut self) -> Result<(), M3uFilterError> {
+        if let Some(categories) = &self.drop_categories {
+            match categories.iter().map(|s| regex::Regex::new(s)).collect::<Result<Vec<regex::Regex>, _>>() {
+                Ok(category_re) => self.t_drop_categories = category_re,
+                Err(err) => return create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "Invalid epg drop_categories regular expression: {}", err),
+            }
+        }
+        if self.fill_gaps.as_ref().is_some_and(|fill_gaps| fill_gaps.block_minutes == 0) {
+            return create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "Invalid epg fill_gaps block_minutes: 0");
+        }
+        if self.fill_gaps.as_ref().is_some_and(|fill_gaps| fill_gaps.max_days == 0) {
+            return create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "Invalid epg fill_gaps max_days: 0");
+        }
+        Ok(())
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.strip_icons || self.max_description_length.is_some() || !self.t_drop_categories.is_empty() || self.fill_gaps.is_some()
+    }
 }
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
@@ -332,6 +587,56 @@ pub struct TargetOutput {
     pub username: Option<String>,
 }
 
+/// Applied when a target has more channels than `max_channels`.
+#[derive(Debug, Copy, Clone, serde::Serialize, serde::Deserialize, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ChannelOverflowPolicy {
+    /// The channels after `max_channels` in sort order are removed.
+    #[default]
+    Truncate,
+    /// The target update fails, the previous playlist is kept.
+    Fail,
+    /// The playlist is written with all channels and a warning is logged.
+    Warn,
+}
+
+const fn default_archive_days() -> u16 { 7 }
+
+/// Live channels of a target which are recorded per epg programme and served through the xtream catchup api.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CatchupArchiveConfig {
+    pub directory: String,
+    /// Epg channel ids of the recorded channels.
+    pub channels: Vec<String>,
+    /// Recordings older than the days are removed.
+    #[serde(default = "default_archive_days")]
+    pub days: u16,
+}
+
+/// Rules which are checked after the outputs of a target are written.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct TargetValidation {
+    /// Live channels must not share an epg id.
+    #[serde(default)]
+    pub unique_epg_ids: bool,
+    /// Live channels must have a logo.
+    #[serde(default)]
+    pub require_logos: bool,
+    /// Minimum percentage of live channels with epg programmes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_epg_coverage: Option<u8>,
+    #[serde(default)]
+    pub no_empty_groups: bool,
+    /// The target update is reported as failed if a rule is violated.
+    #[serde(default)]
+    pub fail: bool,
+    /// The outputs are not published if a rule is violated, they are served to the preview user of the target.
+    #[serde(default)]
+    pub hold: bool,
+}
+
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize, Default)]
 pub struct ConfigTarget {
     #[serde(skip)]
@@ -340,6 +645,9 @@ pub struct ConfigTarget {
     pub enabled: bool,
     #[serde(default = "default_as_default")]
     pub name: String,
+    /// Previous names of the target, api-proxy users of these names are served by this target.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<String>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub options: Option<ConfigTargetOptions>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
@@ -355,24 +663,83 @@ pub struct ConfigTarget {
     pub processing_order: ProcessingOrder,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub watch: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub category_info: Option<Vec<ConfigCategoryInfo>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_channels: Option<usize>,
+    #[serde(default)]
+    pub channel_overflow: ChannelOverflowPolicy,
+    /// Names of the plugins which are applied after the processing steps.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub plugins: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub validation: Option<TargetValidation>,
+    /// Cron expression to process the target independent of the global `schedules`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schedule: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub archive: Option<CatchupArchiveConfig>,
     #[serde(default, skip_serializing, skip_deserializing)]
     pub t_watch_re: Option<Vec<regex::Regex>>,
     #[serde(default, skip_serializing, skip_deserializing)]
     pub t_filter: Option<Filter>,
     #[serde(default, skip_serializing, skip_deserializing)]
     pub t_mapping: Option<Vec<Mapping>>,
+    #[serde(default, skip_serializing, skip_deserializing)]
+    pub t_plugins: Option<Vec<Arc<WasmPlugin>>>,
+    #[serde(default, skip_serializing, skip_deserializing)]
+    pub t_alias_warned: Arc<AtomicBool>,
+    /// The target which stores the held outputs of `validation.hold`.
+    #[serde(default, skip_serializing, skip_deserializing)]
+    pub t_preview: Option<Box<ConfigTarget>>,
 }
 
 
 impl ConfigTarget {
+    /// The deprecation of an alias is logged once per target.
+    fn matches_alias(&self, name: &str) -> bool {
+        let matched = self.aliases.iter().any(|alias| alias.eq_ignore_ascii_case(name));
+        if matched && !self.t_alias_warned.swap(true, std::sync::atomic::Ordering::Relaxed) {
+            warn!("Target name {name} is deprecated, it was renamed to {}. Update the target of the api-proxy users", self.name);
+        }
+        matched
+    }
+
+    /// The held outputs are stored under their own name, only the outputs served by the api are written.
+    fn create_preview_target(&self) -> Self {
+        let mut preview = self.clone();
+        preview.name = format!("{}{PREVIEW_TARGET_SUFFIX}", self.name);
+        preview.aliases = vec![];
+        preview.output = self.output.iter().filter(|output| output.target != TargetType::Strm)
+            .map(|output| TargetOutput { target: output.target.clone(), filename: None, username: output.username.clone() })
+            .collect();
+        preview.validation = None;
+        preview.schedule = None;
+        preview.archive = None;
+        preview.watch = None;
+        preview.t_watch_re = None;
+        preview.t_alias_warned = Arc::new(AtomicBool::new(false));
+        preview
+    }
+
+    pub fn get_output_encoding(&self) -> Option<&OutputEncoding> {
+        self.options.as_ref().and_then(|options| options.output_encoding.as_ref())
+    }
+
     pub fn prepare(&mut self, id: u16, templates: Option<&Vec<PatternTemplate>>) -> Result<(), M3uFilterError> {
         self.id = id;
         if self.output.is_empty() {
             return Err(info_err!(format!("Missing output format for {}", self.name)));
         }
+        if let Some(expression) = &self.schedule {
+            if let Err(err) = cron::Schedule::from_str(expression) {
+                return Err(info_err!(format!("Invalid schedule {expression} for target {}: {err}", self.name)));
+            }
+        }
         let mut m3u_cnt = 0;
         let mut strm_cnt = 0;
         let mut xtream_cnt = 0;
+        let mut stalker_cnt = 0;
         let mut strm_needs_xtream = false;
         for format in &self.output {
             let has_username = if let Some(username) = &format.username { !username.trim().is_empty() } else { false };
@@ -406,10 +773,16 @@ impl ConfigTarget {
                         warn!("Filename for target output xtream is ignored: {}", self.name);
                     }
                 }
+                TargetType::Stalker => {
+                    stalker_cnt += 1;
+                    if has_username || has_filename {
+                        warn!("Username and filename for target output stalker are ignored: {}", self.name);
+                    }
+                }
             }
         }
 
-        if m3u_cnt > 1 || strm_cnt > 1 || xtream_cnt > 1 {
+        if m3u_cnt > 1 || strm_cnt > 1 || xtream_cnt > 1 || stalker_cnt > 1 {
             return create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "Multiple output formats with same type : {}", self.name);
         }
 
@@ -417,6 +790,23 @@ impl ConfigTarget {
             return create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "strm output with a username is only permitted when used in combination with xtream output: {}", self.name);
         }
 
+        if let Some(epg_options) = self.options.as_mut().and_then(|options| options.epg.as_mut()) {
+            epg_options.prepare()?;
+        }
+
+        if self.validation.as_ref().and_then(|validation| validation.min_epg_coverage).is_some_and(|coverage| coverage > 100) {
+            return create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "min_epg_coverage has to be a percentage between 0 and 100: {}", self.name);
+        }
+
+        if let Some(archive) = &self.archive {
+            if archive.directory.trim().is_empty() || archive.channels.is_empty() || archive.days == 0 {
+                return create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "archive needs a directory, channels and days: {}", self.name);
+            }
+            if !self.has_output(&TargetType::Xtream) {
+                return create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "archive is only permitted with xtream output: {}", self.name);
+            }
+        }
+
         if let Some(watch) = &self.watch {
             let regexps: Result<Vec<regex::Regex>, _> = watch.iter().map(|s| regex::Regex::new(s)).collect();
             match regexps {
@@ -437,12 +827,23 @@ impl ConfigTarget {
                 if let Some(sort) = self.sort.as_mut() {
                     handle_m3u_filter_error_result!(M3uFilterErrorKind::Info, sort.prepare());
                 }
+                if let Some(category_info) = self.category_info.as_mut() {
+                    handle_m3u_filter_error_result_list!(M3uFilterErrorKind::Info, category_info.iter_mut().map(ConfigCategoryInfo::prepare));
+                }
+                if self.validation.as_ref().is_some_and(|rules| rules.hold) {
+                    self.t_preview = Some(Box::new(self.create_preview_target()));
+                }
                 Ok(())
             }
             Err(err) => Err(err),
         }
     }
 
+    /// The first matching category info for the category name.
+    pub fn get_category_info(&self, category: &str) -> Option<&ConfigCategoryInfo> {
+        self.category_info.as_ref().and_then(|infos| infos.iter().find(|info| info.matches(category)))
+    }
+
     pub fn filter(&self, provider: &ValueProvider) -> bool {
         let mut processor = MockValueProcessor {};
         self.t_filter.as_ref().unwrap().filter(provider, &mut processor)
@@ -475,8 +876,8 @@ pub struct ConfigSource {
 
 impl ConfigSource {
     #[allow(clippy::cast_possible_truncation)]
-    pub fn prepare(&mut self, index: u16) -> Result<u16, M3uFilterError> {
-        handle_m3u_filter_error_result_list!(M3uFilterErrorKind::Info, self.inputs.iter_mut().enumerate().map(|(idx, i)| i.prepare(index+(idx as u16))));
+    pub fn prepare(&mut self, index: u16, resolver: Option<&SecretResolver>) -> Result<u16, M3uFilterError> {
+        handle_m3u_filter_error_result_list!(M3uFilterErrorKind::Info, self.inputs.iter_mut().enumerate().map(|(idx, i)| i.prepare(index+(idx as u16), resolver)));
         Ok(index + (self.inputs.len() as u16))
     }
 
@@ -506,11 +907,14 @@ pub enum InputType {
     M3u,
     #[serde(rename = "xtream")]
     Xtream,
+    #[serde(rename = "local")]
+    Local,
 }
 
 impl InputType {
     const M3U: &'static str = "m3u";
     const XTREAM: &'static str = "xtream";
+    const LOCAL: &'static str = "local";
 }
 
 impl Display for InputType {
@@ -518,6 +922,7 @@ impl Display for InputType {
         write!(f, "{}", match self {
             Self::M3u => Self::M3U,
             Self::Xtream => Self::XTREAM,
+            Self::Local => Self::LOCAL,
         })
     }
 }
@@ -530,6 +935,8 @@ impl FromStr for InputType {
             Ok(Self::M3u)
         } else if s.eq("xtream") {
             Ok(Self::Xtream)
+        } else if s.eq("local") {
+            Ok(Self::Local)
         } else {
             create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "Unknown InputType: {}", s)
         }
@@ -545,6 +952,13 @@ pub struct ConfigInputOptions {
     pub xtream_skip_vod: bool,
     #[serde(default)]
     pub xtream_skip_series: bool,
+    #[serde(default)]
+    pub parse_strict: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parse_max_errors: Option<usize>,
+    /// Interrupted downloads are resumed with `Range` requests, `0` disables resuming.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub download_resume_attempts: Option<u8>,
 }
 
 pub struct InputUserInfo {
@@ -553,6 +967,159 @@ pub struct InputUserInfo {
     pub password: String,
 }
 
+/// Cron expressions to refresh the input playlist and epg independent of the target schedules.
+/// The targets use the latest snapshot of the input.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct InputScheduleConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub playlist: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub epg: Option<String>,
+}
+
+/// Tls settings for providers with self-signed or broken certificates.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigInputTls {
+    /// Pem file with additional root certificates.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ca_file: Option<String>,
+    #[serde(default)]
+    pub insecure: bool,
+    /// Requests to the input host are sent with this host name (sni and `Host` header),
+    /// the connection is established to the address of the input host.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server_name: Option<String>,
+    #[serde(skip)]
+    pub t_input_host: Option<String>,
+}
+
+impl ConfigInputTls {
+    fn configure(&mut self, builder: reqwest::ClientBuilder, input_url: &str, config_path: &str) -> Result<reqwest::ClientBuilder, M3uFilterError> {
+        let mut builder = builder.danger_accept_invalid_certs(self.insecure);
+        if let Some(ca_file) = &self.ca_file {
+            let ca_path = PathBuf::from(config_path).join(ca_file);
+            let pem = std::fs::read(&ca_path).map_err(|err| info_err!(format!("Failed to read tls ca_file {}: {err}", ca_path.display())))?;
+            let certificates = reqwest::Certificate::from_pem_bundle(&pem).map_err(|err| info_err!(format!("Invalid tls ca_file {}: {err}", ca_path.display())))?;
+            for certificate in certificates {
+                builder = builder.add_root_certificate(certificate);
+            }
+        }
+        if let Some(server_name) = &self.server_name {
+            let url = Url::parse(input_url).map_err(|err| info_err!(format!("Invalid input url for tls server_name: {err}")))?;
+            let host = url.host_str().ok_or_else(|| info_err!("Input url without host for tls server_name".to_string()))?;
+            let host = host.trim_start_matches('[').trim_end_matches(']');
+            // the input host is resolved with the connection, not while loading the config
+            builder = builder.dns_resolver(Arc::new(ServerNameResolver::new(server_name, host)));
+            self.t_input_host = Some(host.to_string());
+        }
+        Ok(builder)
+    }
+
+    /// Replaces the input host with the `server_name`.
+    fn get_request_url(&self, url: &Url) -> Option<Url> {
+        let server_name = self.server_name.as_ref()?;
+        let input_host = self.t_input_host.as_ref()?;
+        if url.host_str().is_some_and(|host| host.trim_start_matches('[').trim_end_matches(']') == input_host) {
+            let mut request_url = url.clone();
+            request_url.set_host(Some(server_name)).ok()?;
+            return Some(request_url);
+        }
+        None
+    }
+}
+
+/// Limit of the concurrent provider streams of an input.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigInputConnections {
+    pub max: u16,
+    /// Max part of the slots one user can take, e.g. `0.5` for half of the slots.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_share: Option<f64>,
+}
+
+const fn default_token_refresh_attempts() -> u32 { 3 }
+
+/// Refresh of expiring stream urls, applied when the provider answers a stream request with `403` or `410`.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigInputTokenRefresh {
+    /// Endpoint which returns the new stream url, `{url}` is replaced with the expired url.
+    /// Without url the playlist item url is requested again.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// Max refreshes per client stream.
+    #[serde(default = "default_token_refresh_attempts")]
+    pub max_attempts: u32,
+}
+
+const fn default_preflight_timeout_secs() -> u64 { 5 }
+const fn default_preflight_interval_secs() -> u64 { 60 }
+
+/// Applied to a target when an input of the source fails or is empty.
+#[derive(Debug, Copy, Clone, serde::Serialize, serde::Deserialize, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum InputFailurePolicy {
+    /// The target is processed with the other inputs.
+    #[default]
+    Proceed,
+    /// The playlist of the last successful update of the failed input is used.
+    KeepPrevious,
+    /// The target is not processed, the outputs of the last update are kept.
+    Abort,
+}
+
+/// Applied when the preflight checks of an input fail.
+#[derive(Debug, Copy, Clone, serde::Serialize, serde::Deserialize, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PreflightFailureAction {
+    /// The input is not downloaded, the targets are processed with the other inputs.
+    #[default]
+    Skip,
+    /// The targets of the source are not processed.
+    Fail,
+    /// The `fallback` input is downloaded instead.
+    Fallback,
+}
+
+/// Checks before the provider of the input is requested, e.g. that the vpn is up.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigInputPreflight {
+    /// Host names which have to resolve.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dns: Vec<String>,
+    /// `host:port` addresses which have to accept tcp connections.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hosts: Vec<String>,
+    #[serde(default = "default_preflight_timeout_secs")]
+    pub timeout_secs: u64,
+    /// Streams use the last result for this time.
+    #[serde(default = "default_preflight_interval_secs")]
+    pub interval_secs: u64,
+    #[serde(default)]
+    pub on_failure: PreflightFailureAction,
+    /// Name of the input which is used if the checks fail.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fallback: Option<String>,
+}
+
+/// Workarounds for known provider bugs, enabled per input.
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ProviderQuirk {
+    /// The provider sends a wrong `content-length`, it is not forwarded to the clients.
+    IgnoreContentLength,
+    /// The provider breaks on http/2 connections.
+    ForceHttp1,
+    /// The provider sometimes answers playlist, epg and info requests with an empty body.
+    RetryEmptyBody,
+    /// The provider sends malformed negative epg timezone offsets like `+-0100` or `-100`.
+    FixNegativeEpgOffset,
+}
+
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize, Default)]
 pub struct ConfigInput {
     #[serde(skip)]
@@ -565,6 +1132,8 @@ pub struct ConfigInput {
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub epg_url: Option<String>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub epg_failover_urls: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub username: Option<String>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub password: Option<String>,
@@ -580,12 +1149,88 @@ pub struct ConfigInput {
     pub enabled: bool,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub options: Option<ConfigInputOptions>,
-
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schedule: Option<InputScheduleConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub connections: Option<ConfigInputConnections>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls: Option<ConfigInputTls>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub quirks: Vec<ProviderQuirk>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_refresh: Option<ConfigInputTokenRefresh>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preflight: Option<ConfigInputPreflight>,
+    /// Max download rate per second for playlist, epg and info requests, e.g. `2MB`. Streams are not limited.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_download_bandwidth: Option<String>,
+    #[serde(skip)]
+    pub t_download_limiter: Option<Arc<BandwidthLimiter>>,
+    /// Shared by the clones of the input, the playlist updates and the streams.
+    #[serde(skip)]
+    pub t_rate_limit: Arc<ProviderRateLimit>,
+    /// Concurrent vod and series info requests share one provider request.
+    #[serde(skip)]
+    pub t_info_requests: Arc<RequestCoalescer>,
+    /// Last result of the preflight checks.
+    #[serde(skip)]
+    pub t_preflight: Arc<PreflightState>,
+    /// Http client for the tls settings and quirks of the input.
+    #[serde(skip)]
+    pub t_http_client: Option<Arc<reqwest::Client>>,
+    /// Secret references of the resolved fields, these are shown instead of the secret values.
+    #[serde(skip)]
+    pub t_secret_refs: HashMap<String, String>,
 }
 
 impl ConfigInput {
-    pub fn prepare(&mut self, id: u16) -> Result<(), M3uFilterError> {
+    fn resolve_secret(&mut self, field: &str, value: &str, resolver: &SecretResolver) -> Result<String, M3uFilterError> {
+        if !SecretResolver::is_reference(value) {
+            return Ok(value.to_string());
+        }
+        let input_name = self.name.as_deref().unwrap_or_default();
+        let resolved = resolver.resolve(value).map_err(|err| info_err!(format!("Input {input_name} {field}: {}", err.message)))?;
+        self.t_secret_refs.insert(field.to_string(), value.to_string());
+        Ok(resolved)
+    }
+
+    fn resolve_secrets(&mut self, resolver: &SecretResolver) -> Result<(), M3uFilterError> {
+        let url = self.url.clone();
+        self.url = self.resolve_secret("url", &url, resolver)?;
+        if let Some(epg_url) = self.epg_url.clone() {
+            self.epg_url = Some(self.resolve_secret("epg_url", &epg_url, resolver)?);
+        }
+        if let Some(failover_urls) = self.epg_failover_urls.clone() {
+            let mut resolved = Vec::with_capacity(failover_urls.len());
+            for (idx, url) in failover_urls.iter().enumerate() {
+                resolved.push(self.resolve_secret(&format!("epg_failover_urls.{idx}"), url, resolver)?);
+            }
+            self.epg_failover_urls = Some(resolved);
+        }
+        if let Some(username) = self.username.clone() {
+            self.username = Some(self.resolve_secret("username", &username, resolver)?);
+        }
+        if let Some(password) = self.password.clone() {
+            self.password = Some(self.resolve_secret("password", &password, resolver)?);
+        }
+        let headers: Vec<(String, String)> = self.headers.iter().map(|(key, value)| (key.clone(), value.clone())).collect();
+        for (key, value) in headers {
+            let resolved = self.resolve_secret(&format!("headers.{key}"), &value, resolver)?;
+            self.headers.insert(key, resolved);
+        }
+        Ok(())
+    }
+
+    /// Returns the secret reference if the field was resolved from one, otherwise the value.
+    pub fn get_config_value(&self, field: &str, value: Option<&String>) -> Option<String> {
+        self.t_secret_refs.get(field).or(value).cloned()
+    }
+
+    pub fn prepare(&mut self, id: u16, resolver: Option<&SecretResolver>) -> Result<(), M3uFilterError> {
         self.id = id;
+        if let Some(secret_resolver) = resolver {
+            self.resolve_secrets(secret_resolver)?;
+        }
         if self.url.trim().is_empty() {
             return Err(info_err!("url for input is mandatory".to_string()));
         }
@@ -600,9 +1245,9 @@ impl ConfigInput {
             }
         }
         match self.input_type {
-            InputType::M3u => {
+            InputType::M3u | InputType::Local => {
                 if self.username.is_some() || self.password.is_some() {
-                    debug!("for input type m3u: username and password are ignored");
+                    debug!("for input type {}: username and password are ignored", self.input_type);
                 }
             }
             InputType::Xtream => {
@@ -616,20 +1261,122 @@ impl ConfigInput {
                 self.persist = None;
             }
         }
+        if let Some(connections) = &self.connections {
+            if connections.max == 0 {
+                return Err(info_err!("connections max for input must be greater than 0".to_string()));
+            }
+            if connections.max_share.is_some_and(|share| share <= 0.0 || share > 1.0) {
+                return Err(info_err!("connections max_share for input must be between 0 and 1".to_string()));
+            }
+        }
+        if let Some(schedule) = &self.schedule {
+            for expression in [&schedule.playlist, &schedule.epg].into_iter().flatten() {
+                if let Err(err) = cron::Schedule::from_str(expression) {
+                    return Err(info_err!(format!("Invalid input schedule {expression}: {err}")));
+                }
+            }
+        }
+        if let Some(bandwidth) = self.max_download_bandwidth.as_ref() {
+            match parse_size_base_2(bandwidth) {
+                Ok(0) => return Err(info_err!("max_download_bandwidth for input must be greater than 0".to_string())),
+                Ok(bytes_per_second) => self.t_download_limiter = Some(Arc::new(BandwidthLimiter::new(bytes_per_second))),
+                Err(err) => return Err(info_err!(format!("Invalid max_download_bandwidth: {err}"))),
+            }
+        }
+        if let Some(token_refresh) = &self.token_refresh {
+            if token_refresh.max_attempts == 0 {
+                return Err(info_err!("token_refresh max_attempts for input must be greater than 0".to_string()));
+            }
+            if let Some(url) = &token_refresh.url {
+                if let Err(err) = Url::parse(&url.replace("{url}", "")) {
+                    return Err(info_err!(format!("Invalid token_refresh url {url}: {err}")));
+                }
+            }
+        }
+        if let Some(preflight) = &self.preflight {
+            if preflight.dns.is_empty() && preflight.hosts.is_empty() {
+                return Err(info_err!("preflight for input needs dns or hosts".to_string()));
+            }
+            if let Some(host) = preflight.hosts.iter().find(|host| host.rsplit_once(':').is_none_or(|(_, port)| port.parse::<u16>().is_err())) {
+                return Err(info_err!(format!("Invalid preflight host {host}, expected host:port")));
+            }
+            if preflight.timeout_secs == 0 {
+                return Err(info_err!("preflight timeout_secs for input must be greater than 0".to_string()));
+            }
+            if (preflight.on_failure == PreflightFailureAction::Fallback) != preflight.fallback.is_some() {
+                return Err(info_err!("preflight fallback input is required for on_failure fallback".to_string()));
+            }
+        }
 
         Ok(())
     }
 
-    pub fn get_user_info(&self) -> Option<InputUserInfo> {
-        if self.input_type == InputType::Xtream {
-            if self.username.is_some() || self.password.is_some() {
-                return Some(InputUserInfo {
-                    base_url: self.url.clone(),
-                    username: self.username.as_ref().unwrap().to_owned(),
-                    password: self.password.as_ref().unwrap().to_owned(),
-                });
+    /// The input with the stream headers of a playlist item, the item headers replace the input headers.
+    pub fn with_stream_headers(&self, user_agent: &str, referrer: &str) -> Cow<'_, Self> {
+        if user_agent.is_empty() && referrer.is_empty() {
+            return Cow::Borrowed(self);
+        }
+        let mut input = self.clone();
+        for (key, value) in [("User-Agent", user_agent), ("Referer", referrer)] {
+            if !value.is_empty() {
+                input.headers.retain(|name, _| !name.eq_ignore_ascii_case(key));
+                input.headers.insert(key.to_string(), value.to_string());
             }
-        } else if let Ok(url) = Url::parse(&self.url) {
+        }
+        Cow::Owned(input)
+    }
+
+    pub fn get_download_resume_attempts(&self) -> u8 {
+        self.options.as_ref().and_then(|options| options.download_resume_attempts).unwrap_or(DEFAULT_DOWNLOAD_RESUME_ATTEMPTS)
+    }
+
+    pub fn has_quirk(&self, quirk: ProviderQuirk) -> bool {
+        self.quirks.contains(&quirk)
+    }
+
+    /// Creates an own http client if tls settings or client quirks are configured.
+    pub fn prepare_http_client(&mut self, config_path: &str) -> Result<(), M3uFilterError> {
+        let force_http1 = self.has_quirk(ProviderQuirk::ForceHttp1);
+        if self.tls.is_none() && !force_http1 {
+            return Ok(());
+        }
+        let mut builder = reqwest::Client::builder();
+        if force_http1 {
+            builder = builder.http1_only();
+        }
+        if let Some(tls) = self.tls.as_mut() {
+            builder = tls.configure(builder, &self.url, config_path)?;
+        }
+        let client = builder.build().map_err(|err| info_err!(format!("Failed to create http client for input: {err}")))?;
+        self.t_http_client = Some(Arc::new(client));
+        Ok(())
+    }
+
+    /// The http client of the input, the given client if the input has none.
+    pub fn get_http_client<'a>(&'a self, client: &'a Arc<reqwest::Client>) -> &'a Arc<reqwest::Client> {
+        self.t_http_client.as_ref().unwrap_or(client)
+    }
+
+    /// The url with the tls `server_name` for requests to the input host.
+    pub fn get_request_url(&self, url: &Url) -> Url {
+        self.tls.as_ref().and_then(|tls| tls.get_request_url(url)).unwrap_or_else(|| url.clone())
+    }
+
+    /// The primary epg url followed by the failover urls.
+    pub fn get_epg_urls(&self) -> Vec<&String> {
+        self.epg_url.iter().chain(self.epg_failover_urls.iter().flatten()).collect()
+    }
+
+    pub fn get_user_info(&self) -> Option<InputUserInfo> {
+        if self.input_type == InputType::Xtream {
+            if self.username.is_some() || self.password.is_some() {
+                return Some(InputUserInfo {
+                    base_url: self.url.clone(),
+                    username: self.username.as_ref().unwrap().to_owned(),
+                    password: self.password.as_ref().unwrap().to_owned(),
+                });
+            }
+        } else if let Ok(url) = Url::parse(&self.url) {
             let base_url = url.origin().ascii_serialization();
             let mut username = None;
             let mut password = None;
@@ -658,6 +1405,33 @@ pub struct ConfigApi {
     pub port: u16,
     #[serde(default)]
     pub web_root: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub trusted_proxies: Vec<String>,
+    /// Serves the prometheus metrics at `/metrics`.
+    #[serde(default)]
+    pub metrics: bool,
+    /// Bearer token of `/metrics`, without token the usernames of the labels are hashed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metrics_token: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub playlist_limit: Option<PlaylistLimitConfig>,
+}
+
+const fn default_playlist_limit_queue_timeout_secs() -> u64 { 10 }
+const fn default_playlist_limit_retry_after_secs() -> u64 { 5 }
+
+/// Concurrency limit of the playlist generation (`get.php` and the `player_api.php` stream lists).
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PlaylistLimitConfig {
+    pub max_concurrent: u16,
+    /// Requests which wait for a free slot, further requests are rejected.
+    #[serde(default)]
+    pub queue: u16,
+    #[serde(default = "default_playlist_limit_queue_timeout_secs")]
+    pub queue_timeout_secs: u64,
+    #[serde(default = "default_playlist_limit_retry_after_secs")]
+    pub retry_after_secs: u64,
 }
 
 impl ConfigApi {
@@ -679,6 +1453,21 @@ pub struct RestMessagingConfig {
     pub url: String,
 }
 
+/// Webhook which receives the processing events as json.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WebhookConfig {
+    pub url: String,
+    #[serde(default)]
+    pub format: WebhookFormat,
+    /// Events sent to the webhook, all events if empty.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub events: Vec<WebhookEvent>,
+    /// Message text with the placeholders `{event}`, `{target}` and `{message}`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub template: Option<String>,
+}
+
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize, Default)]
 pub struct MessagingConfig {
     #[serde(default)]
@@ -687,6 +1476,27 @@ pub struct MessagingConfig {
     pub telegram: Option<TelegramMessagingConfig>,
     #[serde(default)]
     pub rest: Option<RestMessagingConfig>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub webhooks: Vec<WebhookConfig>,
+    /// Change of the channel count of a target in percent which triggers `channel_count_changed`.
+    #[serde(default = "default_channel_change_threshold")]
+    pub channel_change_threshold: u8,
+}
+
+const fn default_channel_change_threshold() -> u8 { 10 }
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, Default)]
+pub struct VideoDownloadPostProcessConfig {
+    #[serde(default)]
+    pub kodi_style: bool,
+    #[serde(default)]
+    pub underscore_whitespace: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub library_directory: Option<String>,
+    #[serde(default)]
+    pub checksum: bool,
+    #[serde(default)]
+    pub notify: bool,
 }
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize, Default)]
@@ -699,6 +1509,8 @@ pub struct VideoDownloadConfig {
     pub organize_into_directories: bool,
     #[serde(default)]
     pub episode_pattern: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub post_process: Option<VideoDownloadPostProcessConfig>,
     #[serde(default, skip_serializing, skip_deserializing)]
     pub t_re_episode_pattern: Option<regex::Regex>,
     #[serde(default, skip_serializing, skip_deserializing)]
@@ -850,6 +1662,259 @@ impl WebAuthConfig {
     }
 }
 
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, Default)]
+pub struct WebUiBranding {
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub logo: Option<String>,
+    #[serde(default)]
+    pub asset_dir: Option<String>,
+}
+
+impl WebUiBranding {
+    fn prepare(&mut self, config_path: &str, resolve_var: bool) -> Result<(), M3uFilterError> {
+        if let Some(asset_dir) = &self.asset_dir {
+            let asset_dir = if resolve_var { config_reader::resolve_env_var(asset_dir) } else { asset_dir.clone() };
+            let mut asset_path = PathBuf::from(&asset_dir);
+            if asset_path.is_relative() {
+                asset_path = PathBuf::from(config_path).join(&asset_dir);
+            }
+            if !asset_path.is_dir() {
+                return create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "web_ui asset_dir does not exist or is not a directory: {}", asset_dir);
+            }
+            self.asset_dir = Some(asset_path.clean().to_string_lossy().to_string());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, Default)]
+pub struct WebUiHostConfig {
+    pub host: String,
+    #[serde(flatten)]
+    pub branding: WebUiBranding,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, Default)]
+pub struct WebUiConfig {
+    #[serde(default)]
+    pub path: Option<String>,
+    #[serde(flatten)]
+    pub branding: WebUiBranding,
+    #[serde(default)]
+    pub hosts: Option<Vec<WebUiHostConfig>>,
+}
+
+impl WebUiConfig {
+    pub fn prepare(&mut self, config_path: &str, resolve_var: bool) -> Result<(), M3uFilterError> {
+        self.path = self.path.as_ref()
+            .map(|path| path.trim().trim_matches('/').to_string())
+            .filter(|path| !path.is_empty())
+            .map(|path| format!("/{path}"));
+        self.branding.prepare(config_path, resolve_var)?;
+        if let Some(hosts) = &mut self.hosts {
+            let mut host_names = HashSet::new();
+            for host_cfg in hosts {
+                host_cfg.host = host_cfg.host.trim().to_lowercase();
+                if host_cfg.host.is_empty() {
+                    return create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "web_ui host name is empty");
+                }
+                if !host_names.insert(host_cfg.host.clone()) {
+                    return create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "web_ui host names should be unique: {}", host_cfg.host);
+                }
+                host_cfg.branding.prepare(config_path, resolve_var)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Base path of the web ui without trailing slash, empty if served from root.
+    pub fn base_path(&self) -> &str {
+        self.path.as_deref().unwrap_or_default()
+    }
+
+    fn get_host_branding(&self, host: &str) -> Option<&WebUiBranding> {
+        self.hosts.as_ref()?.iter()
+            .find(|host_cfg| host_cfg.host.eq_ignore_ascii_case(host))
+            .map(|host_cfg| &host_cfg.branding)
+    }
+
+    pub fn get_title(&self, host: &str) -> Option<&str> {
+        self.get_host_branding(host).and_then(|branding| branding.title.as_deref())
+            .or(self.branding.title.as_deref())
+    }
+
+    pub fn get_logo(&self, host: &str) -> Option<&str> {
+        self.get_host_branding(host).and_then(|branding| branding.logo.as_deref())
+            .or(self.branding.logo.as_deref())
+    }
+
+    /// Asset override directories for the host, ordered by priority.
+    pub fn get_asset_dirs(&self, host: &str) -> Vec<&str> {
+        self.get_host_branding(host).and_then(|branding| branding.asset_dir.as_deref()).into_iter()
+            .chain(self.branding.asset_dir.as_deref())
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MaintenanceConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    /// Video file which is served for stream requests during maintenance.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub video: Option<String>,
+    #[serde(default = "default_as_true")]
+    pub drain_streams: bool,
+}
+
+const fn default_disk_space_margin() -> u8 { 20 }
+
+/// Pre-flight check of the free disk space before the target outputs and the downloads are written.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DiskSpaceConfig {
+    #[serde(default = "default_as_true")]
+    pub enabled: bool,
+    /// Space which is kept free, e.g. `500MB`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_free: Option<String>,
+    /// Margin in percent added to the estimated size.
+    #[serde(default = "default_disk_space_margin")]
+    pub margin: u8,
+    #[serde(skip)]
+    pub t_min_free: u64,
+}
+
+impl Default for DiskSpaceConfig {
+    fn default() -> Self {
+        Self { enabled: true, min_free: None, margin: default_disk_space_margin(), t_min_free: 0 }
+    }
+}
+
+impl DiskSpaceConfig {
+    fn prepare(&mut self) -> Result<(), M3uFilterError> {
+        self.t_min_free = match self.min_free.as_ref() {
+            None => 0,
+            Some(val) => parse_size_base_2(val).map_err(|err| info_err!(format!("Invalid disk_space min_free: {err}")))?,
+        };
+        Ok(())
+    }
+}
+
+/// Signs the playlist and epg responses with a HMAC-SHA256 of the body.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ResponseSigningConfig {
+    pub secret: String,
+}
+
+impl ResponseSigningConfig {
+    fn prepare(&mut self, resolve_var: bool) -> Result<(), M3uFilterError> {
+        if resolve_var {
+            self.secret = config_reader::resolve_env_var(&self.secret);
+        }
+        if self.secret.trim().is_empty() {
+            return create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "response_signing secret is empty");
+        }
+        Ok(())
+    }
+}
+
+/// Experimental wasm plugin, see `WasmPlugin` for the module interface.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PluginConfig {
+    pub name: String,
+    pub path: String,
+    /// Instruction budget per item
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fuel: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory_limit: Option<String>,
+    #[serde(skip)]
+    pub t_memory_limit: Option<usize>,
+}
+
+impl PluginConfig {
+    fn prepare(&mut self) -> Result<(), M3uFilterError> {
+        if let Some(memory_limit) = self.memory_limit.as_ref() {
+            let size = parse_size_base_2(memory_limit).map_err(|err| info_err!(format!("Invalid plugin memory_limit: {err}")))?;
+            self.t_memory_limit = Some(usize::try_from(size).unwrap_or(usize::MAX));
+        }
+        Ok(())
+    }
+}
+
+/// Storage of the target playlists and id mappings.
+#[derive(Debug, Copy, Clone, serde::Serialize, serde::Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum StorageMode {
+    #[default]
+    File,
+    /// The playlists, categories, vod/series info and id mappings are kept in memory and lost on restart.
+    /// The epg files, the m3u/strm/kodi output files and reports are still written to the target directories.
+    Memory,
+}
+
+/// Redaction of sensitive info in logs, reports and diagnostics.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LogSanitizeConfig {
+    #[serde(default = "default_as_true")]
+    pub enabled: bool,
+    /// Values are replaced with a short hash instead of `***`, equal values stay recognizable.
+    #[serde(default)]
+    pub hash: bool,
+    /// Additional regular expressions, the first capture group is kept and the rest of the match is redacted.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub patterns: Vec<String>,
+    /// Log targets (module paths) with complete log lines redacted, the longest matching target wins.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub targets: HashMap<String, bool>,
+    #[serde(skip)]
+    pub t_patterns: Vec<regex::Regex>,
+}
+
+impl Default for LogSanitizeConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            hash: false,
+            patterns: vec![],
+            targets: HashMap::new(),
+            t_patterns: vec![],
+        }
+    }
+}
+
+impl LogSanitizeConfig {
+    pub fn prepare(&mut self) -> Result<(), M3uFilterError> {
+        self.t_patterns = self.patterns.iter()
+            .map(|pattern| regex::Regex::new(pattern).map_err(|err| info_err!(format!("Invalid sanitize pattern {pattern}: {err}"))))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(())
+    }
+
+    /// Returns true if complete log lines of the target are redacted.
+    pub fn is_target_redacted(&self, target: &str) -> bool {
+        self.targets.iter()
+            .filter(|(prefix, _)| target == prefix.as_str() || target.strip_prefix(prefix.as_str()).is_some_and(|rest| rest.starts_with("::")))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .is_some_and(|(_, redacted)| *redacted)
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, Default)]
+pub struct LogConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sanitize: Option<LogSanitizeConfig>,
+}
+
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize, Default)]
 pub struct ScheduleConfig {
     #[serde(default)]
@@ -903,13 +1968,64 @@ pub struct StreamBufferConfig {
 }
 
 impl StreamBufferConfig {
-    fn prepare(&mut self) {
+    fn prepare(&mut self, low_memory: bool) {
         if self.enabled && self.size == 0 {
-            self.size = STREAM_QUEUE_SIZE;
+            self.size = if low_memory { LOW_MEMORY_STREAM_QUEUE_SIZE } else { STREAM_QUEUE_SIZE };
+        }
+    }
+}
+
+
+fn default_ffmpeg() -> String {
+    String::from("ffmpeg")
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StreamRelayConfig {
+    #[serde(default = "default_ffmpeg")]
+    pub ffmpeg: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rtsp_transport: Option<String>,
+    #[serde(default)]
+    pub udp_ffmpeg: bool,
+}
+
+impl Default for StreamRelayConfig {
+    fn default() -> Self {
+        Self {
+            ffmpeg: default_ffmpeg(),
+            rtsp_transport: None,
+            udp_ffmpeg: false,
         }
     }
 }
 
+/// Provider stream timeouts in seconds, `0` waits without limit.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StreamTimeoutConfig {
+    /// Until the provider responded with the headers.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub connect: Option<u32>,
+    /// Until the first data after the headers.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub first_byte: Option<u32>,
+    /// Without data while streaming.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stall: Option<u32>,
+}
+
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StreamTimeoutsConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub live: Option<StreamTimeoutConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vod: Option<StreamTimeoutConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub series: Option<StreamTimeoutConfig>,
+}
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize, Default)]
 pub struct StreamConfig {
@@ -917,12 +2033,19 @@ pub struct StreamConfig {
     pub retry: bool,
     #[serde(default)]
     pub buffer: Option<StreamBufferConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub relay: Option<StreamRelayConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeouts: Option<StreamTimeoutsConfig>,
+    /// Forwards the `set-cookie` headers of the provider, never for shared streams.
+    #[serde(default)]
+    pub forward_cookies: bool,
 }
 
 impl StreamConfig {
-    fn prepare(&mut self) {
+    fn prepare(&mut self, low_memory: bool) {
         if let Some(buffer) = self.buffer.as_mut() {
-            buffer.prepare();
+            buffer.prepare(low_memory);
         }
     }
 }
@@ -936,9 +2059,9 @@ pub struct ReverseProxyConfig {
 }
 
 impl ReverseProxyConfig {
-    fn prepare(&mut self, working_dir: &str, resolve_var: bool) {
+    fn prepare(&mut self, working_dir: &str, resolve_var: bool, low_memory: bool) {
         if let Some(stream) = self.stream.as_mut() {
-            stream.prepare();
+            stream.prepare(low_memory);
         }
         if let Some(cache) = self.cache.as_mut() {
             cache.prepare(working_dir, resolve_var);
@@ -956,11 +2079,32 @@ pub struct Config {
     #[serde(default)]
     pub backup_dir: Option<String>,
     #[serde(default)]
+    pub secrets_file: Option<String>,
+    #[serde(default)]
     pub templates: Option<Vec<PatternTemplate>>,
     #[serde(default)]
     pub video: Option<VideoConfig>,
     #[serde(default)]
     pub schedules: Option<Vec<ScheduleConfig>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input_cache_size: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub maintenance: Option<MaintenanceConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub disk_space: Option<DiskSpaceConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub custom_videos: Option<CustomVideosConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response_signing: Option<ResponseSigningConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub log: Option<LogConfig>,
+    #[serde(default)]
+    pub storage_mode: StorageMode,
+    /// Profile for devices with little ram, see `prepare_low_memory`.
+    #[serde(default)]
+    pub low_memory: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub plugins: Option<Vec<PluginConfig>>,
     #[serde(default)]
     pub update_on_boot: bool,
     #[serde(default = "default_as_true")]
@@ -968,8 +2112,13 @@ pub struct Config {
     #[serde(default)]
     pub web_auth: Option<WebAuthConfig>,
     #[serde(default)]
+    pub web_ui: Option<WebUiConfig>,
+    #[serde(default)]
     pub messaging: Option<MessagingConfig>,
     pub reverse_proxy: Option<ReverseProxyConfig>,
+    /// Localized category names, the key is the locale, the value maps the category names to their translation.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub category_translations: Option<HashMap<String, HashMap<String, String>>>,
     #[serde(skip)]
     pub t_api_proxy: Arc<RwLock<Option<ApiProxyConfig>>>,
     #[serde(skip)]
@@ -981,28 +2130,60 @@ pub struct Config {
     #[serde(skip)]
     pub t_api_proxy_file_path: String,
     #[serde(skip)]
+    pub t_mapping_file_path: String,
+    /// Set when targets are changed through the api, the sources file is reloaded on each refresh.
+    #[serde(skip)]
+    pub t_sources_changed: Arc<AtomicBool>,
+    #[serde(skip)]
     pub file_locks: Arc<FileLockManager>,
+    #[serde(skip)]
+    pub t_user_bouquets: Arc<UserBouquets>,
+    #[serde(skip)]
+    pub t_epg_now_next: Arc<EpgNowNextCache>,
+    #[serde(skip)]
+    pub t_input_health: Arc<InputHealthRegistry>,
+    #[serde(skip)]
+    pub t_resource_cache: Arc<Option<async_std::sync::Mutex<LRUResourceCache>>>,
+    #[serde(skip)]
+    pub t_input_cache_size: usize,
+    #[serde(skip)]
+    pub t_maintenance: Arc<MaintenanceMode>,
+    /// The running processing jobs, shared with the reloaded sources config.
+    #[serde(skip)]
+    pub t_processing_jobs: Arc<ProcessingJobs>,
+    #[serde(skip)]
+    pub t_memory_storage: Option<Arc<MemoryStorage>>,
+    /// The configured or the default disk space check.
+    #[serde(skip)]
+    pub t_disk_space: DiskSpaceConfig,
 }
 
 impl Config {
     pub fn set_api_proxy(&mut self, api_proxy: Option<ApiProxyConfig>) {
+        sanitize::set_log_excluded_users(api_proxy.as_ref().map(ApiProxyConfig::get_log_excluded_users).unwrap_or_default());
         self.t_api_proxy = Arc::new(RwLock::new(api_proxy));
     }
 
     fn intern_get_target_for_user(&self, user_target: Option<(ProxyUserCredentials, String)>) -> Option<(ProxyUserCredentials, &ConfigTarget)> {
-        match user_target {
-            Some((user, target_name)) => {
-                for source in &self.sources {
-                    for target in &source.targets {
-                        if target_name.eq_ignore_ascii_case(&target.name) {
-                            return Some((user, target));
-                        }
-                    }
-                }
-                None
-            }
-            None => None
-        }
+        let (user, target_name) = user_target?;
+        self.get_target_by_name(&target_name).map(|target| (user, target))
+    }
+
+    /// The category translations of the user locale, without user locale of the first matching `Accept-Language` locale.
+    pub fn get_category_translations(&self, user_locale: Option<&str>, accept_language: Option<&str>) -> Option<&HashMap<String, String>> {
+        let translations = self.category_translations.as_ref()?;
+        let preferred = match user_locale.map(locale::normalize_locale).filter(|locale| !locale.is_empty()) {
+            Some(user_locale) => vec![user_locale],
+            None => locale::parse_accept_language(accept_language.unwrap_or_default()),
+        };
+        locale::select_locale(translations, &preferred)
+    }
+
+    /// The target with the name, or a renamed target with the name as alias.
+    pub fn get_target_by_name(&self, target_name: &str) -> Option<&ConfigTarget> {
+        let mut targets = self.sources.iter().flat_map(|source| &source.targets);
+        targets.clone().find(|target| target_name.eq_ignore_ascii_case(&target.name))
+            .or_else(|| targets.find(|target| target.matches_alias(target_name)))
     }
 
     pub fn get_inputs_for_target(&self, target_name: &str) -> Option<Vec<&ConfigInput>> {
@@ -1022,10 +2203,52 @@ impl Config {
         self.t_api_proxy.read().unwrap().as_ref().and_then(|api_proxy| self.intern_get_target_for_user(api_proxy.get_target_name_by_token(token)))
     }
 
+    pub fn get_target_for_user_by_mac(&self, mac: &str) -> Option<(ProxyUserCredentials, &ConfigTarget)> {
+        self.t_api_proxy.read().unwrap().as_ref().and_then(|api_proxy| self.intern_get_target_for_user(api_proxy.get_target_name_by_mac(mac)))
+    }
+
     pub fn get_user_credentials(&self, username: &str) -> Option<ProxyUserCredentials> {
         self.t_api_proxy.read().unwrap().as_ref().and_then(|api_proxy| api_proxy.get_user_credentials(username))
     }
 
+    pub fn get_input_by_name(&self, name: &str) -> Option<&ConfigInput> {
+        self.sources.iter().flat_map(|source| &source.inputs).find(|input| input.name.as_deref() == Some(name))
+    }
+
+    /// The fallback of an input has to exist and can't be the input itself.
+    fn check_preflight_fallbacks(&self) -> Result<(), M3uFilterError> {
+        for input in self.sources.iter().flat_map(|source| &source.inputs) {
+            if let Some(fallback) = input.preflight.as_ref().and_then(|preflight| preflight.fallback.as_ref()) {
+                if input.name.as_ref() == Some(fallback) || self.get_input_by_name(fallback).is_none() {
+                    return create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "Invalid preflight fallback input {}", fallback);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The video of the target, or the global video if the target has none.
+    pub fn get_custom_video(&self, target: Option<&ConfigTarget>, video: CustomVideo) -> Option<PathBuf> {
+        target.and_then(|target| target.options.as_ref()).and_then(|options| options.custom_videos.as_ref())
+            .and_then(|videos| videos.get_video(video))
+            .or_else(|| self.custom_videos.as_ref().and_then(|videos| videos.get_video(video)))
+            .map(|path| self.get_custom_video_path(path))
+    }
+
+    fn get_custom_video_path(&self, path: &str) -> PathBuf {
+        PathBuf::from(&self.working_dir).join(path).clean()
+    }
+
+    /// The configured custom videos which don't exist, the files are opened for each request and can be created later.
+    fn get_missing_custom_videos(&self) -> Vec<PathBuf> {
+        let target_videos = self.sources.iter().flat_map(|source| &source.targets)
+            .filter_map(|target| target.options.as_ref().and_then(|options| options.custom_videos.as_ref()));
+        self.custom_videos.iter().chain(target_videos).flat_map(CustomVideosConfig::get_videos)
+            .map(|path| self.get_custom_video_path(path))
+            .filter(|path| !path.exists())
+            .collect()
+    }
+
     pub fn get_input_by_id(&self, input_id: u16) -> Option<&ConfigInput> {
         for source in &self.sources {
             for input in &source.inputs {
@@ -1037,6 +2260,77 @@ impl Config {
         None
     }
 
+    /// The target storage if the playlists are kept in memory.
+    pub const fn get_memory_storage(&self) -> Option<&Arc<MemoryStorage>> {
+        self.t_memory_storage.as_ref()
+    }
+
+    /// Logs the trade-offs of the memory storage mode at startup.
+    pub fn log_storage_mode(&self) {
+        if self.storage_mode == StorageMode::Memory {
+            warn!("Storage mode memory: target playlists and id mappings are not persisted, they are lost on restart");
+            warn!("Storage mode memory: virtual ids are reassigned after a restart, client favourites and catchup links break");
+            warn!("Storage mode memory: the playlists of all targets are held in memory");
+            if !self.update_on_boot {
+                warn!("Storage mode memory: update_on_boot is disabled, the targets are empty until the first update");
+            }
+        }
+    }
+
+    /// Sources are processed one after another and the parsed inputs are not cached in memory between sources.
+    /// The target playlists have to be stored on disk.
+    fn prepare_low_memory(&mut self) -> Result<(), M3uFilterError> {
+        if self.low_memory {
+            if self.storage_mode == StorageMode::Memory {
+                return create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "storage_mode memory can't be used with low_memory");
+            }
+            if self.threads > 1 {
+                warn!("Low memory: threads {} is reduced to 1", self.threads);
+                self.threads = 1;
+            }
+        }
+        Ok(())
+    }
+
+    /// Number of http workers, `None` uses the actix default of one worker per cpu core.
+    pub const fn get_http_workers(&self) -> Option<usize> {
+        if self.low_memory { Some(LOW_MEMORY_HTTP_WORKERS) } else { None }
+    }
+
+    /// Logs the effective settings of the low memory profile at startup.
+    pub fn log_low_memory(&self) {
+        if self.low_memory {
+            let stream_buffer = self.reverse_proxy.as_ref().and_then(|r| r.stream.as_ref()).and_then(|s| s.buffer.as_ref())
+                .filter(|buffer| buffer.enabled).map_or(0, |buffer| buffer.size);
+            info!("Low memory: sources processed sequentially, {LOW_MEMORY_HTTP_WORKERS} http workers, input cache size {} bytes, stream buffer {stream_buffer} chunks",
+                self.t_input_cache_size);
+        }
+    }
+
+    /// Loads the plugins and assigns them to the targets.
+    fn prepare_plugins(&mut self) -> Result<(), M3uFilterError> {
+        let mut plugins = HashMap::new();
+        for plugin_config in self.plugins.iter_mut().flatten() {
+            plugin_config.prepare()?;
+            let plugin = WasmPlugin::load(plugin_config, &self.t_config_path)?;
+            info!("Loaded plugin {}", plugin.name);
+            plugins.insert(plugin.name.clone(), Arc::new(plugin));
+        }
+        for target in self.sources.iter_mut().flat_map(|source| &mut source.targets) {
+            if let Some(names) = target.plugins.as_ref() {
+                let mut target_plugins = vec![];
+                for name in names {
+                    match plugins.get(name) {
+                        Some(plugin) => target_plugins.push(Arc::clone(plugin)),
+                        None => return create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "Unknown plugin {} for target {}", name, target.name),
+                    }
+                }
+                target.t_plugins = if target_plugins.is_empty() { None } else { Some(target_plugins) };
+            }
+        }
+        Ok(())
+    }
+
     pub fn set_mappings(&mut self, mappings_cfg: &Mappings) {
         for source in &mut self.sources {
             for target in &mut source.targets {
@@ -1064,10 +2358,52 @@ impl Config {
             self.backup_dir = Some(backup_dir.to_string());
         }
         if let Some(reverse_proxy) = self.reverse_proxy.as_mut() {
-            reverse_proxy.prepare(&self.working_dir, resolve_var);
+            reverse_proxy.prepare(&self.working_dir, resolve_var, self.low_memory);
+        }
+        self.t_user_bouquets = Arc::new(UserBouquets::new(&self.working_dir));
+        self.t_resource_cache = Arc::new(self.reverse_proxy.as_ref().and_then(|r| r.cache.as_ref())
+            .filter(|c| c.enabled)
+            .map(|c| async_std::sync::Mutex::new(LRUResourceCache::new(c.t_size, &PathBuf::from(c.dir.as_ref().unwrap())))));
+        self.t_input_cache_size = match self.input_cache_size.as_ref() {
+            None if self.low_memory => 0,
+            None => DEFAULT_INPUT_CACHE_SIZE,
+            Some(val) => match parse_size_base_2(val) {
+                Ok(size) => usize::try_from(size).unwrap_or(usize::MAX),
+                Err(err) => return Err(info_err!(format!("Invalid input_cache_size: {err}"))),
+            }
+        };
+        if let Some(video) = self.maintenance.as_mut().and_then(|maintenance| maintenance.video.as_mut()) {
+            if PathBuf::from(&*video).is_relative() {
+                *video = PathBuf::from(&self.working_dir).join(&*video).clean().to_string_lossy().to_string();
+            }
+        }
+        self.t_maintenance = Arc::new(MaintenanceMode::new(self.maintenance.as_ref()));
+        if let Some(disk_space) = self.disk_space.as_mut() {
+            disk_space.prepare()?;
+        }
+        self.t_disk_space = self.disk_space.clone().unwrap_or_default();
+        self.prepare_low_memory()?;
+        for path in self.get_missing_custom_videos() {
+            warn!("Custom video {} does not exist", path.display());
+        }
+        self.t_memory_storage = match self.storage_mode {
+            StorageMode::File => None,
+            StorageMode::Memory => Some(Arc::new(MemoryStorage::default())),
+        };
+        if let Some(response_signing) = self.response_signing.as_mut() {
+            response_signing.prepare(resolve_var)?;
+        }
+        if let Some(sanitize) = self.log.as_mut().and_then(|log| log.sanitize.as_mut()) {
+            sanitize.prepare()?;
         }
         self.api.prepare();
         self.prepare_api_web_root(resolve_var);
+        if let Some(translations) = self.category_translations.take() {
+            self.category_translations = Some(translations.into_iter()
+                .map(|(locale, names)| (locale::normalize_locale(&locale), names))
+                .filter(|(locale, names)| !locale.is_empty() && !names.is_empty())
+                .collect());
+        }
         if let Some(templates) = &mut self.templates {
             match prepare_templates(templates) {
                 Ok(tmplts) => {
@@ -1078,13 +2414,19 @@ impl Config {
                 }
             }
         };
+        let secret_resolver = if resolve_var { Some(SecretResolver::new(&self.t_config_path, self.secrets_file.as_deref())?) } else { None };
         // prepare sources and set id's
         let mut target_names_check = HashSet::<String>::new();
+        let mut target_storage_names = HashMap::<String, String>::new();
+        let mut target_aliases = HashMap::<String, String>::new();
         let default_target_name = default_as_default();
         let mut source_index: u16 = 1;
         let mut target_index: u16 = 1;
         for source in &mut self.sources {
-            source_index = source.prepare(source_index)?;
+            source_index = source.prepare(source_index, secret_resolver.as_ref())?;
+            for input in &mut source.inputs {
+                input.prepare_http_client(&self.t_config_path)?;
+            }
             for target in &mut source.targets {
                 // check target name is unique
                 let target_name = target.name.trim().to_string();
@@ -1095,6 +2437,20 @@ impl Config {
                     if target_names_check.contains(target_name.as_str()) {
                         return create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "target names should be unique: {}", target_name);
                     }
+                    // targets with the same storage directory would share their files and virtual ids
+                    let storage_name = target_name.replace(' ', "_").to_lowercase();
+                    if let Some(other_name) = target_storage_names.insert(storage_name, target_name.clone()) {
+                        return create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "target names {} and {} use the same storage directory", other_name, target_name);
+                    }
+                    for alias in &target.aliases {
+                        let alias = alias.trim();
+                        if alias.is_empty() {
+                            return create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "empty alias for target {}", target_name);
+                        }
+                        if let Some(other_name) = target_aliases.insert(alias.to_lowercase(), target_name.clone()) {
+                            return create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "alias {} is used by targets {} and {}", alias, other_name, target_name);
+                        }
+                    }
                     target_names_check.insert(target_name);
                 }
                 // prepare templates
@@ -1119,6 +2475,13 @@ impl Config {
             }
         }
 
+        if let Some(target_name) = target_names_check.iter().find(|target_name| target_aliases.contains_key(&target_name.to_lowercase())) {
+            return create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "target name {} is used as alias", target_name);
+        }
+        self.check_preflight_fallbacks()?;
+
+        self.prepare_plugins()?;
+
         match &mut self.video {
             None => {
                 self.video = Some(VideoConfig {
@@ -1137,11 +2500,19 @@ impl Config {
 
         if !self.web_ui_enabled {
             self.web_auth = None;
+            self.web_ui = None;
+        }
+
+        if let Some(web_ui) = &mut self.web_ui {
+            web_ui.prepare(&self.t_config_path, resolve_var)?;
         }
 
         if let Some(web_auth) = &mut self.web_auth {
             if web_auth.enabled {
                 web_auth.prepare(&self.t_config_path, resolve_var)?;
+                if let Some(secret_resolver) = &secret_resolver {
+                    web_auth.secret = secret_resolver.resolve(&web_auth.secret)?;
+                }
             } else {
                 self.web_auth = None;
             }
@@ -1173,10 +2544,21 @@ impl Config {
         }
     }
 
+    /// Users connecting through a host of a server info get the urls of this server info instead of their assigned one.
+    pub fn select_user_server_for_host(&self, user: &mut ProxyUserCredentials, request_host: &str) {
+        if let Some(server_name) = self.t_api_proxy.read().unwrap().as_ref().and_then(|api_proxy| api_proxy.get_server_name_for_host(request_host)) {
+            user.server = Some(server_name.to_string());
+        }
+    }
+
     pub fn get_user_server_info(&self, user: &ProxyUserCredentials) -> ApiProxyServerInfo {
         let server_info_list = self.t_api_proxy.read().unwrap().as_ref().unwrap().server.clone();
         let server_info_name = user.server.as_ref().map_or("default", |server_name| server_name.as_str());
-        server_info_list.iter().find(|c| c.name.eq(server_info_name)).map_or_else(|| server_info_list.first().unwrap().clone(), std::clone::Clone::clone)
+        let mut server_info = server_info_list.iter().find(|c| c.name.eq(server_info_name)).map_or_else(|| server_info_list.first().unwrap().clone(), std::clone::Clone::clone);
+        if let Some(origin) = &user.t_forwarded_origin {
+            server_info.apply_forwarded_origin(origin);
+        }
+        server_info
     }
 }
 
@@ -1233,4 +2615,81 @@ pub fn validate_targets(target_args: Option<&Vec<String>>, sources: &Vec<ConfigS
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct HealthcheckConfig {
     pub api: ConfigApi,
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use url::Url;
+
+    use std::path::PathBuf;
+
+    use crate::model::config::{Config, ConfigInput, ConfigInputTls, ConfigSource, ConfigTarget, ConfigTargetOptions, CustomVideo, CustomVideosConfig, StorageMode, StreamBufferConfig, LOW_MEMORY_HTTP_WORKERS, LOW_MEMORY_STREAM_QUEUE_SIZE, STREAM_QUEUE_SIZE};
+
+    #[test]
+    fn prepare_low_memory_test() {
+        let mut cfg = Config { threads: 4, ..Config::default() };
+        cfg.prepare_low_memory().unwrap();
+        assert_eq!(cfg.threads, 4);
+        assert_eq!(cfg.get_http_workers(), None);
+
+        let mut cfg = Config { low_memory: true, threads: 4, ..Config::default() };
+        cfg.prepare_low_memory().unwrap();
+        assert_eq!(cfg.threads, 1);
+        assert_eq!(cfg.get_http_workers(), Some(LOW_MEMORY_HTTP_WORKERS));
+
+        let mut cfg = Config { low_memory: true, storage_mode: StorageMode::Memory, ..Config::default() };
+        assert!(cfg.prepare_low_memory().is_err());
+    }
+
+    #[test]
+    fn low_memory_stream_buffer_test() {
+        let mut buffer = StreamBufferConfig { enabled: true, size: 0 };
+        buffer.prepare(true);
+        assert_eq!(buffer.size, LOW_MEMORY_STREAM_QUEUE_SIZE);
+        let mut buffer = StreamBufferConfig { enabled: true, size: 0 };
+        buffer.prepare(false);
+        assert_eq!(buffer.size, STREAM_QUEUE_SIZE);
+        // an explicitly configured size is kept
+        let mut buffer = StreamBufferConfig { enabled: true, size: 512 };
+        buffer.prepare(true);
+        assert_eq!(buffer.size, 512);
+    }
+
+    #[test]
+    fn tls_server_name_test() {
+        // the input host is not resolved while loading the config
+        let mut input = ConfigInput {
+            url: "http://unresolvable.invalid:8080".to_string(),
+            tls: Some(ConfigInputTls { server_name: Some("provider.tv".to_string()), ..Default::default() }),
+            ..Default::default()
+        };
+        input.prepare_http_client("").unwrap();
+        assert!(input.t_http_client.is_some());
+        let url = Url::parse("http://unresolvable.invalid:8080/live/1.ts").unwrap();
+        assert_eq!(input.get_request_url(&url).as_str(), "http://provider.tv:8080/live/1.ts");
+        let other = Url::parse("http://other.tv/live/1.ts").unwrap();
+        assert_eq!(input.get_request_url(&other), other);
+    }
+
+    #[test]
+    fn custom_video_test() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("unavailable.ts"), b"").unwrap();
+        let videos = |channel_unavailable: &str| Some(CustomVideosConfig { channel_unavailable: Some(channel_unavailable.to_string()), user_connections_exhausted: None });
+        let target = ConfigTarget {
+            name: "kids".to_string(),
+            options: Some(ConfigTargetOptions { custom_videos: videos("/videos/kids.ts"), ..Default::default() }),
+            ..Default::default()
+        };
+        let cfg = Config {
+            working_dir: dir.path().to_string_lossy().to_string(),
+            custom_videos: Some(CustomVideosConfig { user_connections_exhausted: Some("exhausted.ts".to_string()), ..videos("videos/../unavailable.ts").unwrap() }),
+            sources: vec![ConfigSource { inputs: vec![], targets: vec![target.clone()] }],
+            ..Config::default()
+        };
+        // the video of the target is preferred, relative paths are resolved against the working_dir
+        assert_eq!(cfg.get_custom_video(Some(&target), CustomVideo::ChannelUnavailable), Some(PathBuf::from("/videos/kids.ts")));
+        assert_eq!(cfg.get_custom_video(None, CustomVideo::ChannelUnavailable), Some(dir.path().join("unavailable.ts")));
+        assert_eq!(cfg.get_custom_video(Some(&target), CustomVideo::UserConnectionsExhausted), Some(dir.path().join("exhausted.ts")));
+        assert_eq!(cfg.get_missing_custom_videos(), vec![dir.path().join("exhausted.ts"), PathBuf::from("/videos/kids.ts")]);
+    }
+}
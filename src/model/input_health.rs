@@ -0,0 +1,276 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use log::{info, warn};
+use serde::Serialize;
+
+use crate::messaging::{send_message, MsgKind};
+use crate::model::config::{ConfigInput, MessagingConfig};
+use crate::utils::rate_limit::RateLimitState;
+use crate::utils::request_utils::mask_sensitive_info;
+
+// consecutive failures until an input is marked unhealthy
+const UNHEALTHY_FAILURE_THRESHOLD: u32 = 3;
+// seconds until a failed epg url is tried first again
+const EPG_SOURCE_RETRY_SECS: i64 = 3600;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EpgSourceHealth {
+    pub url: String,
+    pub failures: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_failure: Option<i64>,
+}
+
+impl EpgSourceHealth {
+    fn is_available(&self, now: i64) -> bool {
+        self.failures == 0 || self.last_failure.is_none_or(|ts| now - ts >= EPG_SOURCE_RETRY_SECS)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct InputHealth {
+    pub input: String,
+    pub healthy: bool,
+    pub failures: u32,
+    pub since: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub epg_sources: Vec<EpgSourceHealth>,
+    /// Set while the requests to the provider are delayed after rate limit responses.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rate_limit: Option<RateLimitState>,
+    /// End of a simulated outage of the input.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub simulated_outage: Option<i64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputHealthTransition {
+    Unhealthy,
+    Recovered,
+}
+
+/// Tracks the provider health per input. Transitions are logged and notified,
+/// a recovered input is reset automatically without restarting the server.
+#[derive(Debug, Default)]
+pub struct InputHealthRegistry {
+    states: Mutex<HashMap<u16, InputHealth>>,
+}
+
+fn get_input_name(input: &ConfigInput) -> String {
+    input.name.as_ref().map_or_else(|| mask_sensitive_info(input.url.as_str()), ToString::to_string)
+}
+
+fn create_input_health(input: &ConfigInput) -> InputHealth {
+    InputHealth {
+        input: get_input_name(input),
+        healthy: true,
+        failures: 0,
+        since: chrono::Utc::now().timestamp(),
+        last_error: None,
+        epg_sources: vec![],
+        rate_limit: None,
+        simulated_outage: None,
+    }
+}
+
+fn notify_transition(messaging: Option<&MessagingConfig>, health: &InputHealth, transition: InputHealthTransition) {
+    let msg = match transition {
+        InputHealthTransition::Unhealthy => {
+            let msg = format!("Input {} is unhealthy after {} failures: {}", health.input, health.failures, health.last_error.as_deref().unwrap_or_default());
+            warn!("{msg}");
+            msg
+        }
+        InputHealthTransition::Recovered => {
+            let msg = format!("Input {} recovered", health.input);
+            info!("{msg}");
+            msg
+        }
+    };
+    send_message(&MsgKind::Info, messaging, &msg);
+}
+
+impl InputHealthRegistry {
+    fn update<F>(&self, input: &ConfigInput, messaging: Option<&MessagingConfig>, func: F) -> Option<InputHealthTransition>
+    where
+        F: FnOnce(&mut InputHealth) -> Option<InputHealthTransition>,
+    {
+        let (health, transition) = {
+            let Ok(mut states) = self.states.lock() else { return None };
+            let health = states.entry(input.id).or_insert_with(|| create_input_health(input));
+            let transition = func(health);
+            (health.clone(), transition)
+        };
+        if let Some(transition) = transition {
+            notify_transition(messaging, &health, transition);
+        }
+        transition
+    }
+
+    pub fn record_failure(&self, input: &ConfigInput, messaging: Option<&MessagingConfig>, error: &str) -> Option<InputHealthTransition> {
+        self.update(input, messaging, |health| {
+            health.failures += 1;
+            health.last_error = Some(mask_sensitive_info(error));
+            if health.healthy && health.failures >= UNHEALTHY_FAILURE_THRESHOLD {
+                health.healthy = false;
+                health.since = chrono::Utc::now().timestamp();
+                Some(InputHealthTransition::Unhealthy)
+            } else {
+                None
+            }
+        })
+    }
+
+    pub fn record_success(&self, input: &ConfigInput, messaging: Option<&MessagingConfig>) -> Option<InputHealthTransition> {
+        self.update(input, messaging, |health| {
+            health.failures = 0;
+            if health.healthy {
+                None
+            } else {
+                health.healthy = true;
+                health.since = chrono::Utc::now().timestamp();
+                health.last_error = None;
+                Some(InputHealthTransition::Recovered)
+            }
+        })
+    }
+
+    pub fn is_healthy(&self, input_id: u16) -> bool {
+        self.states.lock().map_or(true, |states| states.get(&input_id).is_none_or(|health| health.healthy))
+    }
+
+    /// The inputs in lineup order, unhealthy inputs are demoted behind the healthy inputs.
+    /// A recovered input is back at its configured position.
+    pub fn get_priority_order<'a>(&self, inputs: &'a [ConfigInput]) -> Vec<&'a ConfigInput> {
+        let (mut healthy, unhealthy): (Vec<&'a ConfigInput>, Vec<&'a ConfigInput>) = inputs.iter().partition(|input| self.is_healthy(input.id));
+        for input in &unhealthy {
+            info!("Input {} is unhealthy, its channels are moved behind the healthy inputs", get_input_name(input));
+        }
+        healthy.extend(unhealthy);
+        healthy
+    }
+
+    /// The epg urls in failover order. Urls which failed recently are moved to the end,
+    /// they are tried first again after the retry interval.
+    pub fn get_epg_failover_order<'a>(&self, input: &ConfigInput, urls: &[&'a String]) -> Vec<&'a String> {
+        let now = chrono::Utc::now().timestamp();
+        let Ok(states) = self.states.lock() else { return urls.to_vec() };
+        let sources = states.get(&input.id).map(|health| health.epg_sources.as_slice()).unwrap_or_default();
+        let is_available = |url: &String| {
+            let masked_url = mask_sensitive_info(url);
+            sources.iter().find(|source| source.url == masked_url).is_none_or(|source| source.is_available(now))
+        };
+        let (mut available, unavailable): (Vec<&'a String>, Vec<&'a String>) = urls.iter().copied().partition(|url| is_available(url));
+        available.extend(unavailable);
+        available
+    }
+
+    pub fn record_epg_result(&self, input: &ConfigInput, url: &str, success: bool) {
+        let masked_url = mask_sensitive_info(url);
+        self.update(input, None, |health| {
+            let idx = health.epg_sources.iter().position(|source| source.url == masked_url).unwrap_or_else(|| {
+                health.epg_sources.push(EpgSourceHealth { url: masked_url, failures: 0, last_failure: None });
+                health.epg_sources.len() - 1
+            });
+            let source = &mut health.epg_sources[idx];
+            if success {
+                source.failures = 0;
+            } else {
+                source.failures += 1;
+                source.last_failure = Some(chrono::Utc::now().timestamp());
+            }
+            None
+        });
+    }
+
+    /// The states with the rate limit of the inputs, rate limited inputs and inputs with a simulated outage
+    /// are listed even without health record.
+    pub fn get_input_states<'a>(&self, inputs: impl Iterator<Item=&'a ConfigInput>) -> Vec<InputHealth> {
+        let Ok(states) = self.states.lock() else { return vec![] };
+        let now = chrono::Utc::now().timestamp();
+        inputs.filter_map(|input| {
+            let rate_limit = input.t_rate_limit.get_state();
+            let simulated_outage = input.t_preflight.get_simulated_outage(now);
+            let mut health = match states.get(&input.id) {
+                Some(health) => health.clone(),
+                None if rate_limit.is_some() || simulated_outage.is_some() => create_input_health(input),
+                None => return None,
+            };
+            health.rate_limit = rate_limit;
+            health.simulated_outage = simulated_outage;
+            Some(health)
+        }).collect()
+    }
+
+    pub fn reset(&self) {
+        if let Ok(mut states) = self.states.lock() {
+            states.clear();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::config::ConfigInput;
+    use crate::model::input_health::{InputHealthRegistry, InputHealthTransition};
+
+    #[test]
+    fn transition_test() {
+        let input = ConfigInput { id: 1, url: "http://provider.tv".to_string(), ..Default::default() };
+        let registry = InputHealthRegistry::default();
+        assert_eq!(registry.record_failure(&input, None, "timeout"), None);
+        assert_eq!(registry.record_failure(&input, None, "timeout"), None);
+        assert!(registry.get_input_states([&input].into_iter())[0].healthy);
+        assert_eq!(registry.record_failure(&input, None, "timeout"), Some(InputHealthTransition::Unhealthy));
+        assert_eq!(registry.record_failure(&input, None, "timeout"), None);
+        assert!(!registry.get_input_states([&input].into_iter())[0].healthy);
+        assert_eq!(registry.record_success(&input, None), Some(InputHealthTransition::Recovered));
+        assert_eq!(registry.record_success(&input, None), None);
+        assert_eq!(registry.get_input_states([&input].into_iter())[0].failures, 0);
+    }
+
+    #[test]
+    fn priority_order_test() {
+        let inputs: Vec<ConfigInput> = (1..=3).map(|id| ConfigInput { id, url: format!("http://provider{id}.tv"), ..Default::default() }).collect();
+        let registry = InputHealthRegistry::default();
+        let order = |registry: &InputHealthRegistry| registry.get_priority_order(&inputs).iter().map(|input| input.id).collect::<Vec<u16>>();
+        assert_eq!(order(&registry), vec![1, 2, 3]);
+        for _ in 0..3 {
+            registry.record_failure(&inputs[0], None, "timeout");
+        }
+        assert!(!registry.is_healthy(1));
+        assert_eq!(order(&registry), vec![2, 3, 1]);
+        registry.record_success(&inputs[0], None);
+        assert_eq!(order(&registry), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn epg_failover_order_test() {
+        let input = ConfigInput { id: 1, url: "http://provider.tv".to_string(), ..Default::default() };
+        let registry = InputHealthRegistry::default();
+        let primary = "http://primary.tv/epg.xml".to_string();
+        let secondary = "http://secondary.tv/epg.xml".to_string();
+        let urls = [&primary, &secondary];
+        assert_eq!(registry.get_epg_failover_order(&input, &urls), vec![&primary, &secondary]);
+        registry.record_epg_result(&input, &primary, false);
+        registry.record_epg_result(&input, &secondary, true);
+        assert_eq!(registry.get_epg_failover_order(&input, &urls), vec![&secondary, &primary]);
+        registry.record_epg_result(&input, &primary, true);
+        assert_eq!(registry.get_epg_failover_order(&input, &urls), vec![&primary, &secondary]);
+        assert_eq!(registry.get_input_states([&input].into_iter())[0].epg_sources.len(), 2);
+    }
+
+    #[test]
+    fn rate_limit_state_test() {
+        let input = ConfigInput { id: 1, url: "http://provider.tv".to_string(), ..Default::default() };
+        let registry = InputHealthRegistry::default();
+        assert!(registry.get_input_states([&input].into_iter()).is_empty());
+        input.t_rate_limit.record_rate_limited(Some(60));
+        let states = registry.get_input_states([&input].into_iter());
+        assert_eq!(states.len(), 1);
+        assert!(states[0].healthy);
+        assert_eq!(states[0].rate_limit.as_ref().map(|rate_limit| rate_limit.delay_secs), Some(60));
+    }
+}
@@ -0,0 +1,81 @@
+use std::sync::Mutex;
+
+use log::info;
+use serde::Serialize;
+
+use crate::model::config::MaintenanceConfig;
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct MaintenanceState {
+    pub enabled: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub since: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    pub drain_streams: bool,
+}
+
+/// Runtime state of the maintenance mode. It is initialized from the config and toggled through the api.
+/// While enabled, new streams get the maintenance response and the processing is paused.
+#[derive(Debug, Default)]
+pub struct MaintenanceMode {
+    state: Mutex<MaintenanceState>,
+}
+
+impl MaintenanceMode {
+    pub fn new(config: Option<&MaintenanceConfig>) -> Self {
+        let state = config.map_or_else(MaintenanceState::default, |cfg| MaintenanceState {
+            enabled: cfg.enabled,
+            since: cfg.enabled.then(|| chrono::Utc::now().timestamp()),
+            message: cfg.message.clone(),
+            drain_streams: cfg.drain_streams,
+        });
+        Self { state: Mutex::new(state) }
+    }
+
+    pub fn get_state(&self) -> MaintenanceState {
+        self.state.lock().unwrap().clone()
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.state.lock().unwrap().enabled
+    }
+
+    /// Running streams are stopped if they should not drain.
+    pub fn should_stop_streams(&self) -> bool {
+        let state = self.state.lock().unwrap();
+        state.enabled && !state.drain_streams
+    }
+
+    pub fn set_enabled(&self, enabled: bool, message: Option<String>) -> MaintenanceState {
+        let mut state = self.state.lock().unwrap();
+        if state.enabled != enabled {
+            info!("Maintenance mode {}", if enabled { "enabled" } else { "disabled" });
+            state.since = enabled.then(|| chrono::Utc::now().timestamp());
+        }
+        state.enabled = enabled;
+        if message.is_some() {
+            state.message = message;
+        }
+        state.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::config::MaintenanceConfig;
+    use crate::model::maintenance::MaintenanceMode;
+
+    #[test]
+    fn maintenance_toggle_test() {
+        let mode = MaintenanceMode::new(Some(&MaintenanceConfig { enabled: false, message: None, video: None, drain_streams: false }));
+        assert!(!mode.is_enabled());
+        assert!(!mode.should_stop_streams());
+        let state = mode.set_enabled(true, Some("Provider migration".to_string()));
+        assert!(state.since.is_some());
+        assert!(mode.should_stop_streams());
+        let state = mode.set_enabled(false, None);
+        assert!(state.since.is_none());
+        assert_eq!(state.message.as_deref(), Some("Provider migration"));
+    }
+}
@@ -5,4 +5,12 @@ pub mod api_proxy;
 pub mod stats;
 pub mod xmltv;
 pub mod xtream;
-pub mod healthcheck;
\ No newline at end of file
+pub mod healthcheck;
+pub mod input_health;
+pub mod channel_viewers;
+pub mod maintenance;
+pub mod processing_job;
+pub mod short_link;
+#[cfg(test)]
+pub mod playlist_test_utils;
+pub mod preview_user;
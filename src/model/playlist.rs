@@ -1,3 +1,4 @@
+use std::borrow::Cow;
 use std::cell::RefCell;
 use std::cmp::PartialEq;
 use std::fmt::{Display, Formatter};
@@ -10,6 +11,7 @@ use crate::model::xtream::{xtream_playlistitem_to_document, XtreamMappingOptions
 use crate::processing::m3u_parser::extract_id_from_url;
 use crate::repository::storage::hash_string;
 use crate::utils::json_utils::{get_string_from_serde_value, get_u64_from_serde_value};
+use crate::utils::output_encoding::sanitize_line;
 use serde::{Deserialize, Serialize};
 use serde_json::{Map, Value};
 // https://de.wikipedia.org/wiki/M3U
@@ -20,6 +22,7 @@ pub trait PlaylistEntry {
     fn get_provider_id(&self) -> Option<u32>;
     fn get_category_id(&self) -> Option<u32>;
     fn get_provider_url(&self) -> Rc<String>;
+    fn get_additional_property(&self, field: &str) -> Option<Value>;
 }
 
 #[derive(Debug, Clone)]
@@ -152,6 +155,17 @@ pub struct PlaylistItemHeader {
     pub time_shift: Rc<String>,
     pub rec: Rc<String>,
     pub url: Rc<String>,
+    /// `User-Agent` and `Referer` for the provider stream request, assigned by the mappings.
+    #[serde(default)]
+    pub user_agent: Rc<String>,
+    #[serde(default)]
+    pub referrer: Rc<String>,
+    /// Comma separated feed tags like `audio_description`, filterable with `Tags`.
+    #[serde(default)]
+    pub tags: Rc<String>,
+    /// `#KODIPROP` lines of the source entry as `key=value`, e.g. the clearkey license of a drm stream.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kodi_props: Option<Rc<Vec<String>>>,
     pub epg_channel_id: Option<Rc<String>>,
     pub xtream_cluster: XtreamCluster,
     pub additional_properties: Option<Value>,
@@ -270,7 +284,7 @@ macro_rules! generate_field_accessor_impl_for_playlist_item_header {
     }
 }
 
-generate_field_accessor_impl_for_playlist_item_header!(id, /*virtual_id,*/ name, chno, logo, logo_small, group, title, parent_code, audio_track, time_shift, rec, url;);
+generate_field_accessor_impl_for_playlist_item_header!(id, /*virtual_id,*/ name, chno, logo, logo_small, group, title, parent_code, audio_track, time_shift, rec, url, user_agent, referrer;);
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct M3uPlaylistItem {
@@ -287,15 +301,38 @@ pub struct M3uPlaylistItem {
     pub time_shift: Rc<String>,
     pub rec: Rc<String>,
     pub url: Rc<String>,
+    pub user_agent: Rc<String>,
+    pub referrer: Rc<String>,
     pub epg_channel_id: Option<Rc<String>>,
     pub input_id: u16,
     pub item_type: PlaylistItemType,
+    /// Channel note and comma separated labels, set with `m3u_channel_notes`.
+    pub note: Rc<String>,
+    pub labels: Rc<String>,
+    pub kodi_props: Option<Rc<Vec<String>>>,
+}
+
+/// Pipe delimited stream headers (`url|User-Agent=...&Referer=...`) for players which request the provider url directly.
+fn append_stream_headers<'a>(url: &'a str, user_agent: &str, referrer: &str) -> Cow<'a, str> {
+    let options: Vec<String> = [("User-Agent", user_agent), ("Referer", referrer)].iter()
+        .filter(|(_, value)| !value.is_empty())
+        .map(|(key, value)| format!("{key}={}", url::form_urlencoded::byte_serialize(value.as_bytes()).collect::<String>().replace('+', "%20")))
+        .collect();
+    if options.is_empty() {
+        Cow::Borrowed(url)
+    } else {
+        Cow::Owned(format!("{url}|{}", options.join("&")))
+    }
 }
 
 impl M3uPlaylistItem {
-    pub fn to_m3u(&self, target_options: Option<&ConfigTargetOptions>, rewrite_urls: Option<&(String, String)>) -> String {
+    fn get_provider_stream_url(&self) -> Cow<'_, str> {
+        append_stream_headers(&self.url, &self.user_agent, &self.referrer)
+    }
+
+    pub fn to_m3u(&self, target_options: Option<&ConfigTargetOptions>, rewrite_urls: Option<&(String, String)>, description: Option<&str>) -> String {
         let (stream_url, resource_url) = rewrite_urls
-            .map_or_else(|| (self.url.as_str(), None), |(su, ru)| (su.as_str(), Some(ru.as_str())));
+            .map_or_else(|| (self.get_provider_stream_url(), None), |(su, ru)| (Cow::Borrowed(su.as_str()), Some(ru.as_str())));
 
         let options = target_options.as_ref();
         let ignore_logo = options.is_some_and(|o| o.ignore_logo);
@@ -321,7 +358,22 @@ impl M3uPlaylistItem {
             (time_shift, "timeshift"),
             (rec, "tvg-rec"););
 
-        format!("{},{}\n{}", line, self.title, stream_url)
+        if let Some(desc) = description {
+            line = format!("{line} description=\"{}\"", desc.replace('"', "'"));
+        }
+        if !self.note.is_empty() {
+            line = format!("{line} x-note=\"{}\"", self.note.replace('"', "'"));
+        }
+        to_m3u_non_empty_fields!(self, line, (labels, "x-labels"););
+
+        // provider values with line breaks or control characters would break the entry
+        let kodi_props = self.kodi_props.as_ref().map_or_else(String::new, |props| props.iter()
+            .map(|prop| format!("#KODIPROP:{}\n", sanitize_line(prop))).collect());
+        format!("{}\n{kodi_props}{}", sanitize_line(&format!("{line},{}", self.title)), sanitize_line(&stream_url))
+    }
+    pub fn to_plain_m3u(&self, rewrite_urls: Option<&(String, String)>) -> String {
+        let stream_url = rewrite_urls.map_or_else(|| self.get_provider_stream_url(), |(su, _)| Cow::Borrowed(su.as_str()));
+        format!("#EXTINF:-1,{}\n{}", sanitize_line(&self.title), sanitize_line(&stream_url))
     }
 }
 
@@ -353,6 +405,10 @@ impl PlaylistEntry for M3uPlaylistItem {
     fn get_provider_url(&self) -> Rc<String> {
         Rc::clone(&self.url)
     }
+    #[inline]
+    fn get_additional_property(&self, _field: &str) -> Option<Value> {
+        None
+    }
 }
 
 macro_rules! generate_field_accessor_impl_for_m3u_playlist_item {
@@ -378,6 +434,7 @@ pub struct XtreamPlaylistItem {
     pub virtual_id: u32,
     pub provider_id: u32,
     pub name: Rc<String>,
+    pub chno: Rc<String>,
     pub logo: Rc<String>,
     pub logo_small: Rc<String>,
     pub group: Rc<String>,
@@ -385,6 +442,8 @@ pub struct XtreamPlaylistItem {
     pub parent_code: Rc<String>,
     pub rec: Rc<String>,
     pub url: Rc<String>,
+    pub user_agent: Rc<String>,
+    pub referrer: Rc<String>,
     pub epg_channel_id: Option<Rc<String>>,
     pub xtream_cluster: XtreamCluster,
     pub additional_properties: Option<String>,
@@ -416,6 +475,11 @@ impl PlaylistEntry for XtreamPlaylistItem {
     fn get_provider_url(&self) -> Rc<String> {
         Rc::clone(&self.url)
     }
+    fn get_additional_property(&self, field: &str) -> Option<Value> {
+        self.additional_properties.as_ref()
+            .and_then(|add_props| serde_json::from_str::<Map<String, Value>>(add_props).ok())
+            .and_then(|mut props| props.remove(field))
+    }
 }
 
 pub fn get_backdrop_path_value(field: &str, value: Option<&Value>) -> Option<Rc<String>> {
@@ -445,6 +509,16 @@ pub fn get_backdrop_path_value(field: &str, value: Option<&Value>) -> Option<Rc<
     }
 }
 
+/// The channel number of the outputs (xtream `num`, m3u `tvg-chno`).
+/// The provider number is used if preserved and numeric, otherwise the virtual id.
+pub fn get_output_channel_number(chno: &str, virtual_id: u32, preserve: bool) -> u32 {
+    if preserve {
+        chno.trim().parse::<u32>().unwrap_or(virtual_id)
+    } else {
+        virtual_id
+    }
+}
+
 macro_rules! generate_field_accessor_impl_for_xtream_playlist_item {
     ($($prop:ident),*;) => {
         impl FieldGetAccessor for XtreamPlaylistItem {
@@ -481,7 +555,7 @@ pub struct PlaylistItem {
     pub header: RefCell<PlaylistItemHeader>,
 }
 
-generate_field_accessor_impl_for_xtream_playlist_item!(name, logo, logo_small, group,title, parent_code, rec, url;);
+generate_field_accessor_impl_for_xtream_playlist_item!(name, chno, logo, logo_small, group,title, parent_code, rec, url;);
 
 impl PlaylistItem {
     pub fn to_m3u(&self) -> M3uPlaylistItem {
@@ -500,9 +574,14 @@ impl PlaylistItem {
             time_shift: Rc::clone(&header.time_shift),
             rec: Rc::clone(&header.rec),
             url: Rc::clone(&header.url),
+            user_agent: Rc::clone(&header.user_agent),
+            referrer: Rc::clone(&header.referrer),
             epg_channel_id: header.epg_channel_id.clone(),
             input_id: header.input_id,
             item_type: header.item_type,
+            note: Rc::new(String::new()),
+            labels: Rc::new(String::new()),
+            kodi_props: header.kodi_props.clone(),
         }
     }
 
@@ -513,6 +592,7 @@ impl PlaylistItem {
             virtual_id: header.virtual_id,
             provider_id,
             name: Rc::clone(if header.item_type == PlaylistItemType::Series { &header.title } else { &header.name }),
+            chno: Rc::clone(&header.chno),
             logo: Rc::clone(&header.logo),
             logo_small: Rc::clone(&header.logo_small),
             group: Rc::clone(&header.group),
@@ -520,6 +600,8 @@ impl PlaylistItem {
             parent_code: Rc::clone(&header.parent_code),
             rec: Rc::clone(&header.rec),
             url: Rc::clone(&header.url),
+            user_agent: Rc::clone(&header.user_agent),
+            referrer: Rc::clone(&header.referrer),
             epg_channel_id: header.epg_channel_id.clone(),
             xtream_cluster: header.xtream_cluster,
             additional_properties: header.additional_properties.as_ref().and_then(|props| serde_json::to_string(props).ok()),
@@ -560,6 +642,10 @@ impl PlaylistEntry for PlaylistItem {
     fn get_provider_url(&self) -> Rc<String> {
         Rc::clone(&self.header.borrow().url)
     }
+    #[inline]
+    fn get_additional_property(&self, field: &str) -> Option<Value> {
+        self.header.borrow().get_additional_property(field).cloned()
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -0,0 +1,100 @@
+// not every test uses every setter
+#![allow(dead_code)]
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use serde_json::Value;
+
+use crate::model::playlist::{M3uPlaylistItem, PlaylistGroup, PlaylistItem, PlaylistItemHeader, PlaylistItemType, UUIDType, XtreamCluster};
+
+/// Builds the playlist items of the tests, unset fields keep their default.
+pub struct ItemBuilder {
+    header: PlaylistItemHeader,
+}
+
+/// A live item with name and title.
+pub fn item(name: &str) -> ItemBuilder {
+    ItemBuilder {
+        header: PlaylistItemHeader {
+            name: Rc::new(name.to_string()),
+            title: Rc::new(name.to_string()),
+            item_type: PlaylistItemType::Live,
+            ..Default::default()
+        }
+    }
+}
+
+impl ItemBuilder {
+    pub fn title(mut self, title: &str) -> Self {
+        self.header.title = Rc::new(title.to_string());
+        self
+    }
+
+    pub fn group(mut self, group: &str) -> Self {
+        self.header.group = Rc::new(group.to_string());
+        self
+    }
+
+    pub fn logo(mut self, logo: &str) -> Self {
+        self.header.logo = Rc::new(logo.to_string());
+        self
+    }
+
+    pub fn chno(mut self, chno: &str) -> Self {
+        self.header.chno = Rc::new(chno.to_string());
+        self
+    }
+
+    pub fn url(mut self, url: &str) -> Self {
+        self.header.url = Rc::new(url.to_string());
+        self
+    }
+
+    pub fn epg_id(mut self, epg_id: Option<&str>) -> Self {
+        self.header.epg_channel_id = epg_id.map(|id| Rc::new(id.to_string()));
+        self
+    }
+
+    pub fn item_type(mut self, item_type: PlaylistItemType) -> Self {
+        self.header.item_type = item_type;
+        self
+    }
+
+    pub fn cluster(mut self, xtream_cluster: XtreamCluster) -> Self {
+        self.header.xtream_cluster = xtream_cluster;
+        self
+    }
+
+    pub fn virtual_id(mut self, virtual_id: u32) -> Self {
+        self.header.virtual_id = virtual_id;
+        self
+    }
+
+    pub fn input_id(mut self, input_id: u16) -> Self {
+        self.header.input_id = input_id;
+        self
+    }
+
+    pub fn uuid(mut self, uuid: UUIDType) -> Self {
+        self.header.uuid = Rc::new(uuid);
+        self
+    }
+
+    pub fn props(mut self, props: Value) -> Self {
+        self.header.additional_properties = Some(props);
+        self
+    }
+
+    pub fn build(self) -> PlaylistItem {
+        PlaylistItem { header: RefCell::new(self.header) }
+    }
+
+    pub fn build_m3u(self) -> M3uPlaylistItem {
+        self.build().to_m3u()
+    }
+}
+
+pub fn group(id: u32, title: &str, xtream_cluster: XtreamCluster, channels: Vec<PlaylistItem>) -> PlaylistGroup {
+    PlaylistGroup { id, title: Rc::new(title.to_string()), channels, xtream_cluster }
+}
@@ -0,0 +1,143 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use log::error;
+use serde::{Deserialize, Serialize};
+
+use crate::auth::password::generate_random_string;
+use crate::model::api_proxy::{ProxyType, ProxyUserCredentials};
+use crate::repository::user_repository::load_user_file;
+use crate::utils::json_utils::json_write_documents_to_file;
+
+const FILE_PREVIEW_TOKENS: &str = "preview_tokens.json";
+const PREVIEW_TOKEN_LENGTH: usize = 32;
+const PREVIEW_TOKEN_TTL_SECS: i64 = 24 * 3600;
+// the connections are counted per username, the limit is shared by the preview users of all targets
+const PREVIEW_MAX_CONNECTIONS: u32 = 2;
+// the password of the preview user is the token, the username is only a placeholder for the xtream urls
+pub const PREVIEW_USERNAME: &str = "_preview";
+
+/// Preview credentials per target, created by an admin to check the result of the latest processing
+/// before the playlist is handed out to the users. The tokens are stored in the working directory and expire after a day.
+#[derive(Debug)]
+pub struct PreviewUsers {
+    file: Option<PathBuf>,
+    tokens: Mutex<HashMap<String, PreviewToken>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PreviewToken {
+    pub token: String,
+    /// Expiration timestamp in seconds.
+    pub expires: i64,
+}
+
+impl PreviewUsers {
+    pub fn new(working_dir: &str) -> Self {
+        let file = PathBuf::from(working_dir).join(FILE_PREVIEW_TOKENS);
+        let tokens = load_user_file(&file, "preview tokens");
+        Self { file: Some(file), tokens: Mutex::new(tokens) }
+    }
+
+    fn persist(&self, tokens: &HashMap<String, PreviewToken>) {
+        if let Some(file) = &self.file {
+            if let Err(err) = json_write_documents_to_file(file, tokens) {
+                error!("Failed to write preview tokens {}: {err}", file.display());
+            }
+        }
+    }
+
+    /// Creates a new token for the target, a previous token of the target is revoked.
+    /// Expired tokens are removed.
+    pub fn create(&self, target: &str) -> Option<PreviewToken> {
+        let mut tokens = self.tokens.lock().ok()?;
+        let now = chrono::Utc::now().timestamp();
+        tokens.retain(|_, preview_token| preview_token.expires > now);
+        let preview_token = PreviewToken { token: generate_random_string(PREVIEW_TOKEN_LENGTH), expires: now + PREVIEW_TOKEN_TTL_SECS };
+        tokens.insert(target.to_string(), preview_token.clone());
+        self.persist(&tokens);
+        Some(preview_token)
+    }
+
+    pub fn remove(&self, target: &str) -> bool {
+        let Ok(mut tokens) = self.tokens.lock() else { return false; };
+        let removed = tokens.remove(target).is_some();
+        if removed {
+            self.persist(&tokens);
+        }
+        removed
+    }
+
+    /// The target and credentials of a preview token.
+    pub fn get_user(&self, token: &str) -> Option<(ProxyUserCredentials, String)> {
+        if token.is_empty() {
+            return None;
+        }
+        let now = chrono::Utc::now().timestamp();
+        let tokens = self.tokens.lock().ok()?;
+        tokens.iter().find(|(_, preview_token)| preview_token.token == token && preview_token.expires > now)
+            .map(|(target, preview_token)| (create_preview_credentials(preview_token), target.clone()))
+    }
+}
+
+/// Credentials of the preview user, the password and the token are the preview token.
+pub fn create_preview_credentials(preview_token: &PreviewToken) -> ProxyUserCredentials {
+    let token = preview_token.token.as_str();
+    ProxyUserCredentials {
+        username: PREVIEW_USERNAME.to_string(),
+        password: token.to_string(),
+        token: Some(token.to_string()),
+        proxy: ProxyType::default(),
+        server: None,
+        epg_timeshift: None,
+        provider_weight: None,
+        trace_streams: false,
+        exp_date: Some(preview_token.expires),
+        max_connections: Some(PREVIEW_MAX_CONNECTIONS),
+        enabled: true,
+        trial: false,
+        no_log: false,
+        locale: None,
+        mac: None,
+        max_catchup_days: None,
+        t_forwarded_origin: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    use crate::model::preview_user::{PreviewToken, PreviewUsers, PREVIEW_MAX_CONNECTIONS, PREVIEW_USERNAME};
+
+    #[test]
+    fn preview_user_test() {
+        let preview_users = PreviewUsers { file: None, tokens: Mutex::new(HashMap::new()) };
+        let token = preview_users.create("live").unwrap().token;
+        let (user, target) = preview_users.get_user(&token).unwrap();
+        assert_eq!(target, "live");
+        assert_eq!(user.username, PREVIEW_USERNAME);
+        assert_eq!(user.password, token);
+        assert_eq!(user.max_connections, Some(PREVIEW_MAX_CONNECTIONS));
+        assert!(!user.is_expired());
+        // a new token revokes the previous one
+        let rotated = preview_users.create("live").unwrap().token;
+        assert!(preview_users.get_user(&token).is_none());
+        assert!(preview_users.get_user(&rotated).is_some());
+        assert!(preview_users.get_user("").is_none());
+        assert!(preview_users.remove("live"));
+        assert!(preview_users.get_user(&rotated).is_none());
+    }
+
+    #[test]
+    fn preview_user_expired_test() {
+        let expired = PreviewToken { token: "expired".to_string(), expires: chrono::Utc::now().timestamp() - 1 };
+        let preview_users = PreviewUsers { file: None, tokens: Mutex::new(HashMap::from([("vod".to_string(), expired)])) };
+        assert!(preview_users.get_user("expired").is_none());
+        // the expired tokens are removed with the next token
+        preview_users.create("live").unwrap();
+        assert!(!preview_users.tokens.lock().unwrap().contains_key("vod"));
+    }
+}
@@ -0,0 +1,119 @@
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+
+use log::info;
+use serde::Serialize;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ProcessingJobInfo {
+    pub id: u32,
+    pub started: i64,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub targets: Vec<u16>,
+    pub cancelled: bool,
+}
+
+/// A running processing run. The cancellation is cooperative,
+/// the processing checks the flag between its stages and inside the info resolve loops and stops there.
+#[derive(Debug)]
+pub struct ProcessingJob {
+    id: u32,
+    started: i64,
+    targets: Vec<u16>,
+    cancelled: AtomicBool,
+}
+
+impl ProcessingJob {
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// An empty target list covers all targets.
+    fn overlaps(&self, targets: &[u16]) -> bool {
+        self.targets.is_empty() || targets.is_empty() || self.targets.iter().any(|id| targets.contains(id))
+    }
+
+    pub fn get_info(&self) -> ProcessingJobInfo {
+        ProcessingJobInfo {
+            id: self.id,
+            started: self.started,
+            targets: self.targets.clone(),
+            cancelled: self.is_cancelled(),
+        }
+    }
+}
+
+/// The running processing jobs, listed and cancelled through the api.
+#[derive(Debug, Default)]
+pub struct ProcessingJobs {
+    next_id: AtomicU32,
+    jobs: Mutex<Vec<Arc<ProcessingJob>>>,
+}
+
+impl ProcessingJobs {
+    /// Registers a new job, the targets are empty if all enabled targets are processed.
+    /// Returns `None` if a running job processes one of the targets, a target is never processed twice at once.
+    pub fn start(&self, targets: Vec<u16>) -> Option<Arc<ProcessingJob>> {
+        let mut jobs = self.jobs.lock().unwrap();
+        if jobs.iter().any(|running| running.overlaps(&targets)) {
+            return None;
+        }
+        let job = Arc::new(ProcessingJob {
+            id: self.next_id.fetch_add(1, Ordering::SeqCst) + 1,
+            started: chrono::Utc::now().timestamp(),
+            targets,
+            cancelled: AtomicBool::new(false),
+        });
+        jobs.push(Arc::clone(&job));
+        Some(job)
+    }
+
+    pub fn finish(&self, job: &ProcessingJob) {
+        self.jobs.lock().unwrap().retain(|running| running.id != job.id);
+    }
+
+    pub fn get_jobs(&self) -> Vec<ProcessingJobInfo> {
+        self.jobs.lock().unwrap().iter().map(|job| job.get_info()).collect()
+    }
+
+    /// Returns `false` if there is no running job with this id.
+    pub fn cancel(&self, id: u32) -> bool {
+        let jobs = self.jobs.lock().unwrap();
+        match jobs.iter().find(|job| job.id == id) {
+            Some(job) => {
+                info!("Processing job {id} cancelled");
+                job.cancelled.store(true, Ordering::SeqCst);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::processing_job::ProcessingJobs;
+
+    #[test]
+    fn processing_job_cancel_test() {
+        let jobs = ProcessingJobs::default();
+        let first = jobs.start(vec![1, 2]).unwrap();
+        let second = jobs.start(vec![3]).unwrap();
+        assert_eq!(jobs.get_jobs().len(), 2);
+        // the targets are already processed
+        assert!(jobs.start(vec![2, 4]).is_none());
+        assert!(jobs.start(vec![]).is_none());
+        assert!(jobs.cancel(second.get_info().id));
+        assert!(second.is_cancelled());
+        assert!(!first.is_cancelled());
+        jobs.finish(&second);
+        assert!(!jobs.cancel(second.get_info().id));
+        assert_eq!(jobs.get_jobs().len(), 1);
+        jobs.finish(&first);
+        assert!(jobs.get_jobs().is_empty());
+        let all = jobs.start(vec![]).unwrap();
+        assert!(jobs.start(vec![1]).is_none());
+        jobs.finish(&all);
+        assert!(jobs.start(vec![1]).is_some());
+    }
+}
@@ -0,0 +1,173 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+use log::error;
+use serde::{Deserialize, Serialize};
+
+use crate::auth::password::generate_random_string;
+use crate::model::api_proxy::ProxyUserCredentials;
+use crate::repository::user_repository::load_user_file;
+use crate::utils::json_utils::json_write_documents_to_file;
+use crate::utils::request_utils::mask_sensitive_info;
+
+const FILE_SHORT_LINKS: &str = "short_links.json";
+const SHORT_LINK_ID_LENGTH: usize = 8;
+/// The hits are counted in memory and written with this interval, see `ShortLinks::flush`.
+pub const SHORT_LINK_FLUSH_INTERVAL_SECS: u64 = 60;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShortLink {
+    pub id: String,
+    pub url: String,
+    pub created: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires: Option<i64>,
+    #[serde(default)]
+    pub hits: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_used: Option<i64>,
+}
+
+impl ShortLink {
+    fn is_expired(&self, now: i64) -> bool {
+        self.expires.is_some_and(|expires| expires <= now)
+    }
+
+    /// The url is masked, the api lists the links without exposing the tokens.
+    pub fn to_masked(&self) -> Self {
+        Self { url: mask_sensitive_info(&self.url), ..self.clone() }
+    }
+}
+
+/// Short links like `/s/{id}` to long tokenized playlist and epg urls.
+/// The links are stored in the working directory and survive a restart.
+#[derive(Debug)]
+pub struct ShortLinks {
+    file: Option<PathBuf>,
+    links: Mutex<HashMap<String, ShortLink>>,
+    /// Hits which are not written yet.
+    dirty: AtomicBool,
+}
+
+impl ShortLinks {
+    pub fn new(working_dir: &str) -> Self {
+        let file = PathBuf::from(working_dir).join(FILE_SHORT_LINKS);
+        let links = load_user_file::<Vec<ShortLink>>(&file, "short links").into_iter().map(|link| (link.id.clone(), link)).collect();
+        Self { file: Some(file), links: Mutex::new(links), dirty: AtomicBool::new(false) }
+    }
+
+    fn persist(&self, links: &HashMap<String, ShortLink>) {
+        self.dirty.store(false, Ordering::Relaxed);
+        if let Some(file) = &self.file {
+            let values: Vec<&ShortLink> = links.values().collect();
+            if let Err(err) = json_write_documents_to_file(file, &values) {
+                error!("Failed to write short links {}: {err}", file.display());
+            }
+        }
+    }
+
+    pub fn create(&self, url: &str, expires: Option<i64>) -> Option<ShortLink> {
+        let mut links = self.links.lock().ok()?;
+        let mut id = generate_random_string(SHORT_LINK_ID_LENGTH);
+        while links.contains_key(&id) {
+            id = generate_random_string(SHORT_LINK_ID_LENGTH);
+        }
+        let link = ShortLink { id: id.clone(), url: url.to_string(), created: chrono::Utc::now().timestamp(), expires, hits: 0, last_used: None };
+        links.insert(id, link.clone());
+        self.persist(&links);
+        Some(link)
+    }
+
+    /// Returns the url of a valid link and counts the usage, the hits are written with the next flush.
+    pub fn resolve(&self, id: &str) -> Option<String> {
+        let mut links = self.links.lock().ok()?;
+        let now = chrono::Utc::now().timestamp();
+        let link = links.get_mut(id).filter(|link| !link.is_expired(now))?;
+        link.hits += 1;
+        link.last_used = Some(now);
+        self.dirty.store(true, Ordering::Relaxed);
+        Some(link.url.clone())
+    }
+
+    /// Writes the links if hits were counted since the last write, periodically and before shutdown.
+    pub fn flush(&self) -> bool {
+        if !self.dirty.load(Ordering::Relaxed) {
+            return false;
+        }
+        let Ok(links) = self.links.lock() else { return false; };
+        self.persist(&links);
+        true
+    }
+
+    /// Revokes the link.
+    pub fn remove(&self, id: &str) -> bool {
+        let Ok(mut links) = self.links.lock() else { return false; };
+        let removed = links.remove(id).is_some();
+        if removed {
+            self.persist(&links);
+        }
+        removed
+    }
+
+    /// Removes the links with urls of the user, returns the count of removed links.
+    pub fn purge_user(&self, user: &ProxyUserCredentials) -> usize {
+        let Ok(mut links) = self.links.lock() else { return 0; };
+        let count = links.len();
+        links.retain(|_, link| !user.is_referenced_by(&link.url));
+        let removed = count - links.len();
+        if removed > 0 {
+            self.persist(&links);
+        }
+        removed
+    }
+
+    pub fn get_links(&self) -> Vec<ShortLink> {
+        let mut links: Vec<ShortLink> = self.links.lock().map(|links| links.values().map(ShortLink::to_masked).collect()).unwrap_or_default();
+        links.sort_by_key(|link| link.created);
+        links
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Mutex;
+
+    use crate::model::short_link::ShortLinks;
+
+    #[test]
+    fn short_link_test() {
+        let links = ShortLinks { file: None, links: Mutex::new(HashMap::new()), dirty: AtomicBool::new(false) };
+        let link = links.create("http://proxy.tv/get.php?token=secret", None).unwrap();
+        let expired = links.create("http://proxy.tv/xmltv.php?token=secret", Some(chrono::Utc::now().timestamp() - 1)).unwrap();
+        assert_eq!(link.id.len(), 8);
+        assert_eq!(links.resolve(&link.id).as_deref(), Some("http://proxy.tv/get.php?token=secret"));
+        assert!(links.resolve(&expired.id).is_none());
+        let listed = links.get_links();
+        assert_eq!(listed.len(), 2);
+        assert!(listed.iter().all(|link| !link.url.contains("secret")));
+        assert_eq!(listed.iter().find(|l| l.id == link.id).unwrap().hits, 1);
+        assert!(links.remove(&link.id));
+        assert!(links.resolve(&link.id).is_none());
+    }
+
+    #[test]
+    fn short_link_flush_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let working_dir = dir.path().to_string_lossy().to_string();
+        let links = ShortLinks::new(&working_dir);
+        let link = links.create("http://proxy.tv/get.php?token=secret", None).unwrap();
+        assert!(!links.flush());
+        // the hits are kept in memory until the flush
+        links.resolve(&link.id);
+        links.resolve(&link.id);
+        assert_eq!(ShortLinks::new(&working_dir).get_links()[0].hits, 0);
+        assert!(links.flush());
+        assert!(!links.flush());
+        assert_eq!(ShortLinks::new(&working_dir).get_links()[0].hits, 2);
+    }
+}
+
@@ -1,6 +1,8 @@
 use std::fmt::{Display};
 use serde::{Serialize, Serializer};
+use crate::m3u_filter_error::M3uFilterError;
 use crate::model::config::InputType;
+use crate::utils::step_measure::StepTiming;
 
 pub fn format_elapsed_time(seconds: u64) -> String {
     if seconds < 60 {
@@ -51,19 +53,56 @@ impl Display for InputStats {
 }
 
 
+/// Source of the playlist of an input in the output of a target.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InputContribution {
+    /// The playlist of this update.
+    Current,
+    /// The input failed, the incomplete playlist of this update is used.
+    Partial,
+    /// The input failed, the playlist of the last successful update is used.
+    Previous,
+    /// The input failed and is missing in the output.
+    Missing,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TargetInputStats {
+    pub name: String,
+    pub contribution: InputContribution,
+    /// Timestamp of the playlist for `previous`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<i64>,
+}
+
 #[derive(Debug, Clone, Serialize)]
 pub struct TargetStats {
     #[serde(rename = "target")]
     pub name: String,
     pub success: bool,
+    #[serde(rename = "took", serialize_with = "serialize_elapsed_time")]
+    pub secs_took: u64,
+    /// Channels removed because of `max_channels`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub truncated: Option<usize>,
+    /// Violated target validation rules.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub violations: Option<usize>,
+    /// Channels of the written playlist.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub channels: Option<usize>,
+    /// The inputs of the source and their contribution to the output.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub inputs: Vec<TargetInputStats>,
 }
 
 impl TargetStats {
-    pub fn success(name: &str) -> Self {
-        Self  {name: name.to_string(), success: true}
+    pub fn success(name: &str, secs_took: u64) -> Self {
+        Self  {name: name.to_string(), success: true, secs_took, truncated: None, violations: None, channels: None, inputs: vec![]}
     }
-    pub fn failure(name: &str) -> Self {
-        Self  {name: name.to_string(), success: false}
+    pub fn failure(name: &str, secs_took: u64) -> Self {
+        Self  {name: name.to_string(), success: false, secs_took, truncated: None, violations: None, channels: None, inputs: vec![]}
     }
 }
 
@@ -73,6 +112,23 @@ impl Display for TargetStats {
     }
 }
 
+/// Durations of the processing steps of one target update.
+/// The input steps (download) are shared between all targets of a source.
+#[derive(Debug, Clone, Serialize)]
+pub struct TimingReport {
+    pub target: String,
+    pub timestamp: i64,
+    pub took_millis: u128,
+    pub inputs: Vec<StepTiming>,
+    pub steps: Vec<StepTiming>,
+}
+
+impl Display for TimingReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        serde_json::to_string(&self).map_or(Err(std::fmt::Error), |json_str| write!(f, "{json_str}"))
+    }
+}
+
 #[derive(Debug, Clone, Serialize)]
 pub struct SourceStats {
     #[serde(rename = "inputs")]
@@ -87,6 +143,28 @@ impl SourceStats {
     }
 }
 
+/// Machine-readable result of a processing run.
+#[derive(Debug, Clone, Serialize)]
+pub struct ProcessingSummary {
+    pub success: bool,
+    pub timestamp: i64,
+    pub took_secs: u64,
+    pub sources: Vec<SourceStats>,
+    pub errors: Vec<String>,
+}
+
+impl ProcessingSummary {
+    pub fn new(sources: Vec<SourceStats>, errors: &[M3uFilterError], took_secs: u64) -> Self {
+        Self {
+            success: sources.iter().flat_map(|source| &source.targets).all(|target| target.success),
+            timestamp: chrono::Local::now().timestamp(),
+            took_secs,
+            sources,
+            errors: errors.iter().map(|err| err.message.clone()).collect(),
+        }
+    }
+}
+
 impl Display for SourceStats {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         serde_json::to_string(&self).map_or(Err(std::fmt::Error), |json_str| write!(f, "{json_str}"))
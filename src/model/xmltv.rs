@@ -5,11 +5,29 @@ use std::rc::Rc;
 use quick_xml::{Error, Writer};
 use quick_xml::events::{BytesEnd, BytesStart, BytesText, Event};
 
+use crate::utils::output_encoding::sanitize_control_chars;
+
 pub const EPG_TAG_TV: &str = "tv";
 pub const EPG_TAG_PROGRAMME: &str = "programme";
 pub const EPG_TAG_CHANNEL: &str = "channel";
 pub const EPG_ATTRIB_ID: &str = "id";
 pub const EPG_ATTRIB_CHANNEL: &str = "channel";
+pub const EPG_TAG_DISPLAY_NAME: &str = "display-name";
+pub const EPG_TAG_ICON: &str = "icon";
+pub const EPG_ATTRIB_SRC: &str = "src";
+pub const EPG_TAG_TITLE: &str = "title";
+pub const EPG_TAG_DESC: &str = "desc";
+pub const EPG_TAG_CATEGORY: &str = "category";
+pub const EPG_ATTRIB_START: &str = "start";
+pub const EPG_ATTRIB_STOP: &str = "stop";
+
+/// Id, display names and icon of an epg channel.
+#[derive(Debug, Clone, Default)]
+pub struct EpgChannelInfo {
+    pub id: String,
+    pub display_names: Vec<String>,
+    pub icon: Option<String>,
+}
 
 // https://github.com/XMLTV/xmltv/blob/master/xmltv.dtd
 
@@ -29,10 +47,11 @@ impl XmlTag {
     fn write_to<W: std::io::Write>(&self, writer: &mut Writer<W>) -> Result<(), Error> {
         let mut elem = BytesStart::new(self.name.as_str());
         if let Some(attribs) = self.attributes.as_ref() {
-            attribs.iter().for_each(|(k, v)| elem.push_attribute((k.as_str(), v.as_str())));
+            attribs.iter().for_each(|(k, v)| elem.push_attribute((k.as_str(), sanitize_control_chars(v).as_ref())));
         }
         writer.write_event(Event::Start(elem))?;
-        self.value.as_ref().map(|text| writer.write_event(Event::Text(BytesText::new(text.as_str()))));
+        // control characters of the provider epg are invalid in xml
+        self.value.as_ref().map(|text| writer.write_event(Event::Text(BytesText::new(&sanitize_control_chars(text)))));
         if let Some(children) = &self.children {
             for child in children {
                 child.write_to(writer)?;
@@ -3,8 +3,9 @@ use std::iter::FromIterator;
 use std::rc::Rc;
 
 use crate::model::api_proxy::{ProxyType, ProxyUserCredentials};
-use crate::model::config::ConfigTargetOptions;
-use crate::model::playlist::{PlaylistEntry, PlaylistItem, XtreamCluster, XtreamPlaylistItem};
+use crate::model::config::ConfigTarget;
+use crate::model::playlist::{get_output_channel_number, PlaylistEntry, PlaylistItem, XtreamCluster, XtreamPlaylistItem};
+use crate::utils::request_utils::is_local_url;
 use crate::utils::json_utils::{opt_string_or_number_u32, string_default_on_null, string_or_number_f64, string_or_number_u32};
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Deserializer, Serialize};
@@ -34,6 +35,42 @@ const SERIES_STREAM_FIELDS: &[&str] = &[
 
 const XTREAM_VOD_REWRITE_URL_PROPS: &[&str] = &[PROP_COVER];
 
+/// Json type of a field in the responses of the xtream panels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum XtreamFieldType {
+    Int,
+    Float,
+    String,
+    /// A string or `null`.
+    OptString,
+}
+
+// field types as sent by the xtream panels, used for `xtream_strict_compat`
+const LIVE_STREAM_FIELD_TYPES: &[(&str, XtreamFieldType)] = &[
+    ("num", XtreamFieldType::Int), ("name", XtreamFieldType::String), ("stream_type", XtreamFieldType::String),
+    ("stream_id", XtreamFieldType::Int), ("stream_icon", XtreamFieldType::String), ("epg_channel_id", XtreamFieldType::OptString),
+    ("added", XtreamFieldType::String), ("is_adult", XtreamFieldType::String), ("category_id", XtreamFieldType::String),
+    ("custom_sid", XtreamFieldType::String), ("tv_archive", XtreamFieldType::Int), ("direct_source", XtreamFieldType::String),
+    ("tv_archive_duration", XtreamFieldType::Int), ("thumbnail", XtreamFieldType::String),
+];
+
+const VIDEO_STREAM_FIELD_TYPES: &[(&str, XtreamFieldType)] = &[
+    ("num", XtreamFieldType::Int), ("name", XtreamFieldType::String), ("stream_type", XtreamFieldType::String),
+    ("stream_id", XtreamFieldType::Int), ("stream_icon", XtreamFieldType::String), ("rating", XtreamFieldType::String),
+    ("rating_5based", XtreamFieldType::Float), ("added", XtreamFieldType::String), ("is_adult", XtreamFieldType::String),
+    ("category_id", XtreamFieldType::String), ("container_extension", XtreamFieldType::String),
+    ("custom_sid", XtreamFieldType::String), ("direct_source", XtreamFieldType::String),
+];
+
+const SERIES_STREAM_FIELD_TYPES: &[(&str, XtreamFieldType)] = &[
+    ("num", XtreamFieldType::Int), ("name", XtreamFieldType::String), ("series_id", XtreamFieldType::Int),
+    ("cover", XtreamFieldType::String), ("plot", XtreamFieldType::String), ("cast", XtreamFieldType::String),
+    ("director", XtreamFieldType::String), ("genre", XtreamFieldType::String), ("releaseDate", XtreamFieldType::String),
+    ("release_date", XtreamFieldType::String), ("last_modified", XtreamFieldType::String), ("rating", XtreamFieldType::String),
+    ("rating_5based", XtreamFieldType::Float), ("youtube_trailer", XtreamFieldType::String),
+    ("episode_run_time", XtreamFieldType::String), ("category_id", XtreamFieldType::String),
+];
+
 
 fn deserialize_number_from_string<'de, D, T: DeserializeOwned>(
     deserializer: D,
@@ -143,6 +180,8 @@ pub struct XtreamStream {
     pub stream_id: Option<u32>,
     #[serde(default, deserialize_with = "deserialize_number_from_string")]
     pub series_id: Option<u32>,
+    #[serde(default, deserialize_with = "deserialize_number_from_string")]
+    pub num: Option<u32>,
     #[serde(default, deserialize_with = "deserialize_as_rc_string")]
     pub stream_icon: Rc<String>,
     #[serde(default, deserialize_with = "deserialize_as_rc_string")]
@@ -184,6 +223,8 @@ pub struct XtreamStream {
     #[serde(default, deserialize_with = "deserialize_as_option_rc_string")]
     pub year: Option<Rc<String>>,
     #[serde(default, deserialize_with = "deserialize_as_option_rc_string")]
+    pub language: Option<Rc<String>>,
+    #[serde(default, deserialize_with = "deserialize_as_option_rc_string")]
     pub trailer: Option<Rc<String>>,
     #[serde(default, deserialize_with = "deserialize_as_option_rc_string")]
     pub youtube_trailer: Option<Rc<String>>,
@@ -277,6 +318,7 @@ impl XtreamStream {
         add_rc_str_property_if_exists!(result, self.stream_type, "stream_type");
         add_rc_str_property_if_exists!(result, self.title, "title");
         add_rc_str_property_if_exists!(result, self.year, "year");
+        add_rc_str_property_if_exists!(result, self.language, "language");
         add_rc_str_property_if_exists!(result, self.trailer, "trailer");
         add_rc_str_property_if_exists!(result, self.youtube_trailer, "youtube_trailer");
         add_rc_str_property_if_exists!(result, self.epg_channel_id, "epg_channel_id");
@@ -480,6 +522,9 @@ impl XtreamSeriesInfoEpisode {
         add_i64_property_if_exists!(result, self.season, "season");
         add_i64_property_if_exists!(result, self.episode_num, "episode");
         add_opt_i64_property_if_exists!(result, self.info.as_ref().and_then(|info| info.tmdb_id), "tmdb_id");
+        add_opt_i64_property_if_exists!(result, self.info.as_ref().map(|info| info.bitrate).filter(|bitrate| *bitrate > 0), "bitrate");
+        add_opt_i64_property_if_exists!(result, self.info.as_ref().and_then(|info| info.video.get("height"))
+            .and_then(Value::as_u64).and_then(|height| u32::try_from(height).ok()), "video_height");
         if result.is_empty() { None } else { Some(Value::Object(result)) }
     }
 }
@@ -488,19 +533,80 @@ pub struct XtreamMappingOptions {
     pub skip_live_direct_source: bool,
     pub skip_video_direct_source: bool,
     pub skip_series_direct_source: bool,
+    pub preserve_channel_numbers: bool,
+    pub strict_compat: bool,
+    /// Epg channel ids of the recorded channels and the days of the archive.
+    pub archive: Option<(Vec<String>, u16)>,
 }
 
 impl XtreamMappingOptions {
-    pub fn from_target_options(options: Option<&ConfigTargetOptions>) -> Self {
-        let (skip_live_direct_source, skip_video_direct_source, skip_series_direct_source) = options
-            .map_or((false, false, false), |o| (
+    pub fn from_target(target: &ConfigTarget) -> Self {
+        let options = target.options.as_ref();
+        let (skip_live_direct_source, skip_video_direct_source, skip_series_direct_source, preserve_channel_numbers, strict_compat) = options
+            .map_or((false, false, false, false, false), |o| (
                 o.xtream_skip_live_direct_source,
                 o.xtream_skip_video_direct_source,
-                o.xtream_skip_series_direct_source));
+                o.xtream_skip_series_direct_source,
+                o.preserve_channel_numbers,
+                o.xtream_strict_compat));
         Self {
             skip_live_direct_source,
             skip_video_direct_source,
             skip_series_direct_source,
+            preserve_channel_numbers,
+            strict_compat,
+            archive: target.archive.as_ref().map(|archive| (archive.channels.clone(), archive.days)),
+        }
+    }
+}
+
+fn get_string_normalized(value: &Value) -> Option<String> {
+    match value {
+        Value::String(_) => None,
+        Value::Null => Some(String::new()),
+        Value::Bool(flag) => Some(if *flag { "1" } else { "0" }.to_string()),
+        Value::Number(number) => Some(number.to_string()),
+        Value::Array(_) | Value::Object(_) => None,
+    }
+}
+
+fn normalize_field_type(value: &Value, field_type: XtreamFieldType) -> Option<Value> {
+    match field_type {
+        XtreamFieldType::Int => match value {
+            Value::Number(number) if number.is_i64() || number.is_u64() => None,
+            #[allow(clippy::cast_possible_truncation)]
+            Value::Number(number) => Some(Value::from(number.as_f64().unwrap_or(0.0) as i64)),
+            Value::String(text) => Some(Value::from(text.trim().parse::<i64>().ok()
+                .or_else(|| text.trim().parse::<f64>().ok().map(|number| number as i64)).unwrap_or(0))),
+            Value::Bool(flag) => Some(Value::from(i64::from(*flag))),
+            Value::Null => Some(Value::from(0)),
+            Value::Array(_) | Value::Object(_) => None,
+        },
+        XtreamFieldType::Float => match value {
+            Value::Number(_) | Value::Array(_) | Value::Object(_) => None,
+            Value::String(text) => Some(serde_json::Number::from_f64(text.trim().parse::<f64>().unwrap_or(0.0)).map_or(Value::from(0), Value::Number)),
+            Value::Bool(flag) => Some(Value::from(i64::from(*flag))),
+            Value::Null => Some(Value::from(0)),
+        },
+        XtreamFieldType::String => get_string_normalized(value).map(Value::String),
+        XtreamFieldType::OptString => if value.is_null() { None } else { get_string_normalized(value).map(Value::String) },
+    }
+}
+
+/// Converts the fields to the types of the xtream panels and adds missing fields,
+/// some players crash on numbers sent as strings or missing keys.
+fn normalize_field_types(document: &mut Map<String, Value>, field_types: &[(&str, XtreamFieldType)]) {
+    for &(field, field_type) in field_types {
+        match document.get(field) {
+            Some(value) => {
+                if let Some(normalized) = normalize_field_type(value, field_type) {
+                    document.insert(field.to_string(), normalized);
+                }
+            }
+            None => {
+                let value = if field_type == XtreamFieldType::OptString { Value::Null } else { normalize_field_type(&Value::Null, field_type).unwrap_or(Value::Null) };
+                document.insert(field.to_string(), value);
+            }
         }
     }
 }
@@ -555,6 +661,7 @@ fn make_bdpath_resource_url(resource_url: &str, bd_path: &str, index: usize, fie
 
 pub fn xtream_playlistitem_to_document(pli: &XtreamPlaylistItem, url: &str, options: &XtreamMappingOptions, user: &ProxyUserCredentials) -> serde_json::Value {
     let stream_id_value = Value::Number(serde_json::Number::from(pli.virtual_id));
+    let num_value = Value::Number(serde_json::Number::from(get_output_channel_number(&pli.chno, pli.virtual_id, options.preserve_channel_numbers)));
     let (resource_url, logo, logo_small) = match user.proxy {
         ProxyType::Reverse => {
             let resource_url = format!("{url}/resource/{}/{}/{}/{}", pli.xtream_cluster.as_stream_type(), user.username, user.password, pli.get_virtual_id());
@@ -570,7 +677,7 @@ pub fn xtream_playlistitem_to_document(pli: &XtreamPlaylistItem, url: &str, opti
         ("category_id".to_string(), Value::String(format!("{}", &pli.category_id))),
         ("category_ids".to_string(), Value::Array(Vec::from([Value::Number(serde_json::Number::from(pli.category_id))]))),
         ("name".to_string(), Value::String(pli.name.as_ref().clone())),
-        ("num".to_string(), stream_id_value.clone()),
+        ("num".to_string(), num_value),
         ("title".to_string(), Value::String(pli.title.as_ref().clone())),
         ("stream_icon".to_string(), Value::String(logo)),
     ]);
@@ -589,7 +696,7 @@ pub fn xtream_playlistitem_to_document(pli: &XtreamPlaylistItem, url: &str, opti
         }
         XtreamCluster::Video => {
             document.insert("stream_id".to_string(), stream_id_value);
-            if options.skip_video_direct_source {
+            if options.skip_video_direct_source || is_local_url(&pli.url) {
                 document.insert("direct_source".to_string(), Value::String(String::new()));
             } else {
                 document.insert("direct_source".to_string(), Value::String(pli.url.as_ref().clone()));
@@ -629,9 +736,46 @@ pub fn xtream_playlistitem_to_document(pli: &XtreamPlaylistItem, url: &str, opti
 
     rewrite_doc_urls(resource_url.as_ref(), &mut document, XTREAM_VOD_REWRITE_URL_PROPS, "");
 
+    // recorded channels have a catchup archive, independent of the provider
+    if let Some((channels, days)) = options.archive.as_ref() {
+        if pli.xtream_cluster == XtreamCluster::Live && pli.epg_channel_id.as_ref().is_some_and(|epg_id| channels.contains(epg_id.as_ref())) {
+            document.insert("tv_archive".to_string(), Value::Number(serde_json::Number::from(1)));
+            document.insert("tv_archive_duration".to_string(), Value::Number(serde_json::Number::from(*days)));
+        }
+    }
+
+    if let (XtreamCluster::Live, Some(max_days)) = (pli.xtream_cluster, user.max_catchup_days) {
+        limit_catchup_duration(&mut document, max_days);
+    }
+
+    if options.strict_compat {
+        let field_types = match pli.xtream_cluster {
+            XtreamCluster::Live => LIVE_STREAM_FIELD_TYPES,
+            XtreamCluster::Video => VIDEO_STREAM_FIELD_TYPES,
+            XtreamCluster::Series => SERIES_STREAM_FIELD_TYPES,
+        };
+        normalize_field_types(&mut document, field_types);
+    }
+
     Value::Object(document)
 }
 
+/// The `tv_archive_duration` of a live stream is limited to the catchup window of the user, `0` days disable the archive.
+fn limit_catchup_duration(document: &mut Map<String, Value>, max_days: u16) {
+    let duration = document.get("tv_archive_duration").and_then(|value| match value {
+        Value::Number(number) => number.as_i64(),
+        Value::String(text) => text.trim().parse::<i64>().ok(),
+        _ => None,
+    });
+    if let Some(duration) = duration {
+        let limited = duration.min(i64::from(max_days));
+        document.insert("tv_archive_duration".to_string(), Value::Number(serde_json::Number::from(limited)));
+        if limited <= 0 {
+            document.insert("tv_archive".to_string(), Value::Number(serde_json::Number::from(0)));
+        }
+    }
+}
+
 pub fn rewrite_doc_urls(resource_url: Option<&String>, document: &mut Map<String, Value>, fields: &[&str], field_prefix: &str) {
     if let Some(rewrite_url) = resource_url {
         if let Some(bdpath) = document.get(PROP_BACKDROP_PATH) {
@@ -658,4 +802,48 @@ pub fn rewrite_doc_urls(resource_url: Option<&String>, document: &mut Map<String
             }
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use crate::model::xtream::{limit_catchup_duration, normalize_field_types, LIVE_STREAM_FIELD_TYPES, VIDEO_STREAM_FIELD_TYPES};
+
+    #[test]
+    fn normalize_field_types_test() {
+        let Value::Object(mut live) = json!({"num": "7", "stream_id": 12, "added": 1_700_000_000, "is_adult": 0,
+            "tv_archive": "1", "tv_archive_duration": null, "epg_channel_id": null, "custom": true}) else { unreachable!() };
+        normalize_field_types(&mut live, LIVE_STREAM_FIELD_TYPES);
+        assert_eq!(live["num"], json!(7));
+        assert_eq!(live["stream_id"], json!(12));
+        assert_eq!(live["added"], json!("1700000000"));
+        assert_eq!(live["is_adult"], json!("0"));
+        assert_eq!(live["tv_archive"], json!(1));
+        assert_eq!(live["tv_archive_duration"], json!(0));
+        assert_eq!(live["epg_channel_id"], Value::Null);
+        assert_eq!(live["direct_source"], json!(""));
+        assert_eq!(live["custom"], json!(true));
+
+        let Value::Object(mut video) = json!({"rating": 7.5, "rating_5based": "3.75", "stream_id": "42.0"}) else { unreachable!() };
+        normalize_field_types(&mut video, VIDEO_STREAM_FIELD_TYPES);
+        assert_eq!(video["rating"], json!("7.5"));
+        assert_eq!(video["rating_5based"], json!(3.75));
+        assert_eq!(video["stream_id"], json!(42));
+    }
+
+    #[test]
+    fn limit_catchup_duration_test() {
+        let Value::Object(mut live) = json!({"tv_archive": 1, "tv_archive_duration": "7"}) else { unreachable!() };
+        limit_catchup_duration(&mut live, 3);
+        assert_eq!(live["tv_archive_duration"], json!(3));
+        assert_eq!(live["tv_archive"], json!(1));
+        limit_catchup_duration(&mut live, 0);
+        assert_eq!(live["tv_archive_duration"], json!(0));
+        assert_eq!(live["tv_archive"], json!(0));
+
+        let Value::Object(mut live) = json!({"tv_archive": 1, "tv_archive_duration": 2}) else { unreachable!() };
+        limit_catchup_duration(&mut live, 5);
+        assert_eq!(live["tv_archive_duration"], json!(2));
+    }
+}
@@ -0,0 +1,143 @@
+use std::collections::HashSet;
+use std::io::Write;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+use actix_rt::System;
+use async_std::sync::Mutex;
+use futures::stream::{self, StreamExt};
+use log::{debug, info};
+use reqwest::RequestBuilder;
+use serde_json::Value;
+use url::Url;
+
+use crate::model::config::{Config, ConfigTarget};
+use crate::model::playlist::{PlaylistGroup, PlaylistItemType};
+use crate::model::xtream::PROP_BACKDROP_PATH;
+use crate::utils::file_utils::create_new_file_for_write;
+use crate::utils::lru_cache::LRUResourceCache;
+use crate::utils::request_utils::{self, mask_sensitive_info};
+
+const PREFETCH_CONCURRENCY: usize = 4;
+
+/// Collects the cover and backdrop urls of the given number of vod and series categories with the most entries.
+fn get_prefetch_urls(playlist: &[PlaylistGroup], category_count: usize) -> Vec<(u16, String)> {
+    let mut groups: Vec<&PlaylistGroup> = playlist.iter()
+        .filter(|group| group.channels.first().is_some_and(|item| {
+            matches!(item.header.borrow().item_type, PlaylistItemType::Video | PlaylistItemType::SeriesInfo | PlaylistItemType::Series)
+        }))
+        .collect();
+    groups.sort_by_key(|group| std::cmp::Reverse(group.channels.len()));
+
+    let mut seen = HashSet::new();
+    let mut urls = vec![];
+    for group in groups.into_iter().take(category_count) {
+        for item in &group.channels {
+            let header = item.header.borrow();
+            let mut item_urls = vec![header.logo.to_string(), header.logo_small.to_string()];
+            match header.get_additional_property(PROP_BACKDROP_PATH) {
+                Some(Value::String(url)) => item_urls.push(url.to_string()),
+                Some(Value::Array(values)) => item_urls.extend(values.iter().filter_map(Value::as_str).map(ToString::to_string)),
+                _ => {}
+            }
+            for url in item_urls {
+                if url.starts_with("http") && seen.insert(url.clone()) {
+                    urls.push((header.input_id, url));
+                }
+            }
+        }
+    }
+    urls
+}
+
+/// Pre-warms the reverse proxy resource cache with the covers and backdrops of the most popular categories,
+/// the popularity of a category is the number of entries.
+/// The resources are fetched in the background with a few requests at a time to not hammer the provider.
+pub fn prefetch_resources(client: &Arc<reqwest::Client>, cfg: &Config, target: &ConfigTarget, playlist: &[PlaylistGroup]) {
+    let cache = Arc::clone(&cfg.t_resource_cache);
+    let category_count = target.options.as_ref().map_or(0, |opt| opt.cache_prefetch_categories);
+    if cache.is_none() || category_count == 0 {
+        return;
+    }
+    // the playlist can't leave the processing, the requests are built before
+    let requests: Vec<(String, RequestBuilder)> = get_prefetch_urls(playlist, usize::from(category_count)).into_iter()
+        .filter_map(|(input_id, resource_url)| {
+            let url = Url::parse(&resource_url).ok()?;
+            let request = request_utils::get_client_request(client, cfg.get_input_by_id(input_id), &url, None);
+            Some((resource_url, request))
+        })
+        .collect();
+    if requests.is_empty() {
+        return;
+    }
+    let target_name = target.name.clone();
+    // the processing can run on its own runtime which ends with the update
+    thread::spawn(move || System::new().block_on(async move {
+        let Some(cache) = cache.as_ref() else { return; };
+        let fetched = AtomicUsize::new(0);
+        stream::iter(requests).for_each_concurrent(PREFETCH_CONCURRENCY, |(resource_url, request)| {
+            let fetched = &fetched;
+            async move {
+                if prefetch_resource(cache, &resource_url, request).await {
+                    fetched.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        }).await;
+        let fetched = fetched.into_inner();
+        if fetched > 0 {
+            info!("Prefetched {fetched} resources for target {target_name}");
+        }
+    }));
+}
+
+async fn prefetch_resource(cache: &Mutex<LRUResourceCache>, resource_url: &str, request: RequestBuilder) -> bool {
+    let resource_path = {
+        let mut guard = cache.lock().await;
+        if guard.get_content(resource_url).await.is_some() {
+            return false;
+        }
+        guard.part_path(resource_url)
+    };
+    let content = match request.send().await {
+        Ok(response) if response.status().is_success() => response.bytes().await.ok(),
+        _ => None,
+    };
+    let Some(content) = content else {
+        debug!("Failed to prefetch resource {}", mask_sensitive_info(resource_url));
+        return false;
+    };
+    let size = content.len();
+    let path = resource_path.clone();
+    let written = tokio::task::spawn_blocking(move || create_new_file_for_write(&path).and_then(|mut file| file.write_all(&content))).await;
+    if matches!(written, Ok(Ok(()))) && cache.lock().await.add_content(resource_url, size).await.is_ok() {
+        return true;
+    }
+    let _ = std::fs::remove_file(&resource_path);
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::model::playlist::{PlaylistGroup, PlaylistItemType, XtreamCluster};
+    use crate::model::playlist_test_utils::{group as test_group, item};
+    use crate::processing::cache_prefetch::get_prefetch_urls;
+
+    fn group(title: &str, item_type: PlaylistItemType, logos: &[&str]) -> PlaylistGroup {
+        test_group(1, title, XtreamCluster::Video, logos.iter().map(|logo| item("").logo(logo).item_type(item_type)
+            .props(json!({"backdrop_path": ["http://provider/backdrop.jpg"]})).input_id(1).build()).collect())
+    }
+
+    #[test]
+    fn get_prefetch_urls_test() {
+        let playlist = vec![
+            group("Live", PlaylistItemType::Live, &["http://provider/live1.png", "http://provider/live2.png", "http://provider/live3.png"]),
+            group("Small", PlaylistItemType::Video, &["http://provider/small.jpg"]),
+            group("Big", PlaylistItemType::Video, &["http://provider/big1.jpg", "", "http://provider/big2.jpg"]),
+        ];
+        let urls: Vec<String> = get_prefetch_urls(&playlist, 1).into_iter().map(|(_, url)| url).collect();
+        assert_eq!(urls, vec!["http://provider/big1.jpg", "http://provider/backdrop.jpg", "http://provider/big2.jpg"]);
+    }
+}
@@ -0,0 +1,87 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::model::playlist::PlaylistGroup;
+use crate::model::xmltv::EpgChannelInfo;
+use crate::repository::m3u_playlist_iterator::is_live_stream;
+
+/// Channel names are compared without case and non-alphanumeric characters.
+pub fn normalize_name(name: &str) -> String {
+    name.chars().filter(|c| c.is_alphanumeric()).flat_map(char::to_lowercase).collect()
+}
+
+/// Live channels without epg id get the id of the epg channel with the same display name.
+/// Channels with epg id get the display name and icon of their epg channel if the provider omits the name or logo.
+pub fn enrich_from_epg(playlist: &mut [PlaylistGroup], epg_channels: &[EpgChannelInfo]) {
+    if epg_channels.is_empty() {
+        return;
+    }
+    let channels_by_id: HashMap<&str, &EpgChannelInfo> = epg_channels.iter().map(|channel| (channel.id.as_str(), channel)).collect();
+    let mut channels_by_name: HashMap<String, &EpgChannelInfo> = HashMap::new();
+    for channel in epg_channels {
+        for display_name in &channel.display_names {
+            channels_by_name.entry(normalize_name(display_name)).or_insert(channel);
+        }
+    }
+    for item in playlist.iter_mut().flat_map(|group| &mut group.channels) {
+        let mut header = item.header.borrow_mut();
+        if !is_live_stream(header.item_type) {
+            continue;
+        }
+        let epg_channel = match header.epg_channel_id.as_ref().filter(|epg_id| !epg_id.is_empty()) {
+            Some(epg_id) => channels_by_id.get(epg_id.as_str()).copied(),
+            None => {
+                let epg_channel = channels_by_name.get(&normalize_name(&header.name)).copied();
+                if let Some(epg_channel) = epg_channel {
+                    header.epg_channel_id = Some(Rc::new(epg_channel.id.clone()));
+                }
+                epg_channel
+            }
+        };
+        let Some(epg_channel) = epg_channel else { continue; };
+        if header.name.is_empty() {
+            if let Some(display_name) = epg_channel.display_names.first() {
+                header.name = Rc::new(display_name.clone());
+            }
+        }
+        if header.logo.is_empty() {
+            if let Some(icon) = &epg_channel.icon {
+                header.logo = Rc::new(icon.clone());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::playlist::{PlaylistItem, PlaylistItemType, XtreamCluster};
+    use crate::model::playlist_test_utils::{group, item as test_item};
+    use crate::model::xmltv::EpgChannelInfo;
+    use crate::processing::epg_enrich::enrich_from_epg;
+
+    fn item(name: &str, logo: &str, epg_id: Option<&str>, item_type: PlaylistItemType) -> PlaylistItem {
+        test_item(name).logo(logo).epg_id(epg_id).item_type(item_type).build()
+    }
+
+    #[test]
+    fn enrich_from_epg_test() {
+        let epg_channels = vec![
+            EpgChannelInfo { id: "bbc1.uk".to_string(), display_names: vec!["BBC One HD".to_string()], icon: Some("http://epg/bbc1.png".to_string()) },
+            EpgChannelInfo { id: "itv1.uk".to_string(), display_names: vec!["ITV 1".to_string()], icon: None },
+        ];
+        let mut playlist = vec![group(1, "UK", XtreamCluster::Live, vec![
+            item("bbc one hd", "", None, PlaylistItemType::Live),
+            item("", "http://provider/itv.png", Some("itv1.uk"), PlaylistItemType::Live),
+            item("ITV 1", "", None, PlaylistItemType::Video),
+            item("Unknown", "", None, PlaylistItemType::Live),
+        ])];
+        enrich_from_epg(&mut playlist, &epg_channels);
+        let headers: Vec<_> = playlist[0].channels.iter().map(|item| item.header.borrow().clone()).collect();
+        assert_eq!(headers[0].epg_channel_id.as_deref().map(String::as_str), Some("bbc1.uk"));
+        assert_eq!(headers[0].logo.as_str(), "http://epg/bbc1.png");
+        assert_eq!(headers[1].name.as_str(), "ITV 1");
+        assert_eq!(headers[1].logo.as_str(), "http://provider/itv.png");
+        assert!(headers[2].epg_channel_id.is_none());
+        assert!(headers[3].epg_channel_id.is_none());
+    }
+}
@@ -0,0 +1,146 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::sync::LazyLock;
+
+use regex::Regex;
+
+use crate::model::config::FeedTagOptions;
+use crate::model::playlist::{PlaylistGroup, PlaylistItem};
+
+pub const TAG_AUDIO_DESCRIPTION: &str = "audio_description";
+pub const TAG_MULTI_AUDIO: &str = "multi_audio";
+pub const TAG_REGIONAL: &str = "regional";
+
+// `AD` is only matched in upper case, the lower case word is too common in channel names
+static AUDIO_DESCRIPTION_REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\bAD\b|(?i:\baudio[\s_-]?descri\w*|\baudiodeskription\b|\bh[öo]rfilm\b)").unwrap());
+static MULTI_AUDIO_REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?i)\bmulti(?:[\s_-]?(?:audio|lang\w*))?\b|\bdual[\s_-]?audio\b|\b\d[\s_-]?audios?\b").unwrap());
+static REGIONAL_REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?i)\b(?:east|west|north|south|regional|region|local)\b").unwrap());
+
+struct FeedTagger {
+    regional: Option<Regex>,
+}
+
+impl FeedTagger {
+    fn new(options: &FeedTagOptions) -> Self {
+        let tokens: Vec<String> = options.regional.iter().filter(|token| !token.trim().is_empty()).map(|token| regex::escape(token.trim())).collect();
+        let regional = if tokens.is_empty() { None } else { Regex::new(&format!(r"(?i)\b(?:{})\b", tokens.join("|"))).ok() };
+        Self { regional }
+    }
+
+    fn get_patterns(&self) -> Vec<(&'static str, &Regex)> {
+        let mut patterns = vec![(TAG_AUDIO_DESCRIPTION, &*AUDIO_DESCRIPTION_REGEX), (TAG_MULTI_AUDIO, &*MULTI_AUDIO_REGEX), (TAG_REGIONAL, &*REGIONAL_REGEX)];
+        if let Some(regional) = self.regional.as_ref() {
+            patterns.push((TAG_REGIONAL, regional));
+        }
+        patterns
+    }
+
+    fn get_tags(&self, name: &str) -> Vec<&'static str> {
+        let mut tags = vec![];
+        for (tag, re) in self.get_patterns() {
+            if !tags.contains(&tag) && re.is_match(name) {
+                tags.push(tag);
+            }
+        }
+        tags
+    }
+
+    /// The channel name without the variant tokens, case and non-alphanumeric characters.
+    fn get_base_name(&self, name: &str) -> String {
+        let mut base_name = name.to_string();
+        for (_, re) in self.get_patterns() {
+            base_name = re.replace_all(&base_name, "").to_string();
+        }
+        base_name.chars().filter(|c| c.is_alphanumeric()).flat_map(char::to_lowercase).collect()
+    }
+}
+
+/// Tags the channels as `audio_description`, `multi_audio` or `regional` variants by the tokens of their name.
+/// The tags are comma separated and can be filtered with `Tags ~ "regional"`.
+pub fn tag_feeds(playlist: &mut [PlaylistGroup], options: &FeedTagOptions) {
+    let tagger = FeedTagger::new(options);
+    for item in playlist.iter_mut().flat_map(|group| &mut group.channels) {
+        let mut header = item.header.borrow_mut();
+        let tags = tagger.get_tags(&header.name);
+        if !tags.is_empty() {
+            header.tags = Rc::new(tags.join(","));
+        }
+    }
+}
+
+/// Variants are placed after their parent channel, the untagged channel with the same base name in the group.
+/// Variants without epg id get the epg id of their parent.
+pub fn group_feed_variants(playlist: &mut [PlaylistGroup], options: &FeedTagOptions) {
+    let tagger = FeedTagger::new(options);
+    for group in playlist.iter_mut() {
+        let base_names: Vec<String> = group.channels.iter().map(|item| tagger.get_base_name(&item.header.borrow().name)).collect();
+        let parents: HashMap<&str, usize> = group.channels.iter().enumerate().rev()
+            .filter(|(_, item)| item.header.borrow().tags.is_empty())
+            .map(|(idx, _)| (base_names[idx].as_str(), idx))
+            .collect();
+        let mut variants: HashMap<usize, Vec<usize>> = HashMap::new();
+        let mut is_variant = vec![false; group.channels.len()];
+        for (idx, item) in group.channels.iter().enumerate() {
+            if item.header.borrow().tags.is_empty() || base_names[idx].is_empty() {
+                continue;
+            }
+            if let Some(&parent_idx) = parents.get(base_names[idx].as_str()) {
+                variants.entry(parent_idx).or_default().push(idx);
+                is_variant[idx] = true;
+                let parent_epg_id = group.channels[parent_idx].header.borrow().epg_channel_id.clone();
+                let mut header = item.header.borrow_mut();
+                if header.epg_channel_id.as_ref().is_none_or(|epg_id| epg_id.is_empty()) {
+                    header.epg_channel_id = parent_epg_id;
+                }
+            }
+        }
+        if variants.is_empty() {
+            continue;
+        }
+        let mut channels: Vec<Option<PlaylistItem>> = std::mem::take(&mut group.channels).into_iter().map(Some).collect();
+        let mut ordered = Vec::with_capacity(channels.len());
+        for idx in 0..channels.len() {
+            if is_variant[idx] {
+                continue;
+            }
+            ordered.extend(channels[idx].take());
+            for &variant_idx in variants.get(&idx).into_iter().flatten() {
+                ordered.extend(channels[variant_idx].take());
+            }
+        }
+        group.channels = ordered;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::config::FeedTagOptions;
+    use crate::model::playlist::{PlaylistItem, XtreamCluster};
+    use crate::model::playlist_test_utils::{group, item as test_item};
+    use crate::processing::feed_tags::{group_feed_variants, tag_feeds};
+
+    fn item(name: &str, epg_id: Option<&str>) -> PlaylistItem {
+        test_item(name).epg_id(epg_id).build()
+    }
+
+    #[test]
+    fn feed_tags_test() {
+        let options = FeedTagOptions { regional: vec!["London".to_string()], group_variants: true };
+        let mut playlist = vec![group(1, "UK", XtreamCluster::Live, vec![
+                item("BBC One London", None),
+                item("Sky Cinema", None),
+                item("BBC One", Some("bbc1.uk")),
+                item("BBC One (AD)", None),
+                item("Sky Cinema Multi-Audio", None),
+                item("Road Trip", None),
+        ])];
+        tag_feeds(&mut playlist, &options);
+        let tags: Vec<String> = playlist[0].channels.iter().map(|item| item.header.borrow().tags.to_string()).collect();
+        assert_eq!(tags, vec!["regional", "", "", "audio_description", "multi_audio", ""]);
+
+        group_feed_variants(&mut playlist, &options);
+        let names: Vec<String> = playlist[0].channels.iter().map(|item| item.header.borrow().name.to_string()).collect();
+        assert_eq!(names, vec!["Sky Cinema", "Sky Cinema Multi-Audio", "BBC One", "BBC One London", "BBC One (AD)", "Road Trip"]);
+        assert_eq!(playlist[0].channels[3].header.borrow().epg_channel_id.as_deref().map(String::as_str), Some("bbc1.uk"));
+    }
+}
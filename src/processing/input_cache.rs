@@ -0,0 +1,191 @@
+use std::collections::HashMap;
+use std::mem::size_of;
+
+use crate::model::config::{Config, ConfigInput};
+use crate::model::playlist::{PlaylistGroup, PlaylistItemHeader};
+use crate::model::xmltv::TVGuide;
+use crate::processing::parse_report::InputParseReport;
+use crate::repository::storage::hash_string_as_hex;
+
+pub struct CachedInput {
+    pub playlistgroups: Vec<PlaylistGroup>,
+    pub epg: Option<TVGuide>,
+    pub parse_report: InputParseReport,
+    size: usize,
+}
+
+/// Inputs with the same provider settings are equal, regardless of name and affixes.
+fn get_input_key(input: &ConfigInput) -> String {
+    let mut headers: Vec<(&String, &String)> = input.headers.iter().collect();
+    headers.sort();
+    let options = input.options.as_ref().and_then(|options| serde_json::to_string(options).ok()).unwrap_or_default();
+    hash_string_as_hex(&format!("{:?}|{}|{:?}|{:?}|{:?}|{headers:?}|{options}",
+                                input.input_type, input.url, input.username, input.password, input.get_epg_urls()))
+}
+
+/// Approximate memory usage, the strings and the header struct of each entry.
+fn estimate_playlist_size(playlistgroups: &[PlaylistGroup]) -> usize {
+    playlistgroups.iter().map(|group| {
+        size_of::<PlaylistGroup>() + group.title.len() + group.channels.iter().map(|item| {
+            let header = item.header.borrow();
+            size_of::<PlaylistItemHeader>() + header.id.len() + header.name.len() + header.chno.len() + header.logo.len()
+                + header.logo_small.len() + header.group.len() + header.title.len() + header.parent_code.len()
+                + header.url.len() + header.epg_channel_id.as_ref().map_or(0, |id| id.len())
+                + header.additional_properties.as_ref().map_or(0, |props| props.to_string().len())
+        }).sum::<usize>()
+    }).sum()
+}
+
+/// Parsed inputs of one processing run, shared between the sources which use the same input.
+/// Each source gets a clone of the playlist, the strings are shared and replaced on modification.
+/// An input is only cached when it is used by a later source and the cache size allows it,
+/// it is dropped after the last use.
+pub struct InputRunCache {
+    max_size: usize,
+    size: usize,
+    remaining_uses: HashMap<String, usize>,
+    entries: HashMap<String, CachedInput>,
+}
+
+impl InputRunCache {
+    pub fn new(cfg: &Config, source_indices: &[usize]) -> Self {
+        let mut remaining_uses = HashMap::new();
+        for input in source_indices.iter().filter_map(|idx| cfg.sources.get(*idx)).flat_map(|source| &source.inputs) {
+            if input.enabled {
+                *remaining_uses.entry(get_input_key(input)).or_insert(0) += 1;
+            }
+        }
+        Self { max_size: cfg.t_input_cache_size, size: 0, remaining_uses, entries: HashMap::new() }
+    }
+
+    /// Returns the cached input with the ids and name of the given input.
+    pub fn get(&mut self, input: &ConfigInput) -> Option<CachedInput> {
+        let key = get_input_key(input);
+        let remaining = self.remaining_uses.get_mut(&key).map_or(0, |uses| {
+            *uses = uses.saturating_sub(1);
+            *uses
+        });
+        let mut cached = if remaining == 0 {
+            let cached = self.entries.remove(&key)?;
+            self.size -= cached.size;
+            cached
+        } else {
+            let cached = self.entries.get(&key)?;
+            CachedInput {
+                playlistgroups: cached.playlistgroups.clone(),
+                epg: cached.epg.clone(),
+                parse_report: cached.parse_report.clone(),
+                size: cached.size,
+            }
+        };
+        for item in cached.playlistgroups.iter().flat_map(|group| &group.channels) {
+            item.header.borrow_mut().input_id = input.id;
+        }
+        cached.parse_report.input = InputParseReport::new(input).input;
+        Some(cached)
+    }
+
+    pub fn put(&mut self, input: &ConfigInput, playlistgroups: &[PlaylistGroup], epg: Option<&TVGuide>, parse_report: &InputParseReport) {
+        let key = get_input_key(input);
+        if self.remaining_uses.get(&key).is_none_or(|uses| *uses == 0) {
+            return;
+        }
+        let size = estimate_playlist_size(playlistgroups);
+        if self.size + size > self.max_size {
+            return;
+        }
+        self.size += size;
+        self.entries.insert(key, CachedInput {
+            playlistgroups: playlistgroups.to_vec(),
+            epg: epg.cloned(),
+            parse_report: parse_report.clone(),
+            size,
+        });
+    }
+}
+
+/// Sources which share an input are grouped to be processed one after another with the same cache.
+pub fn group_sources_by_input(cfg: &Config) -> Vec<Vec<usize>> {
+    let mut groups: Vec<Vec<usize>> = vec![];
+    let mut group_of_input: HashMap<String, usize> = HashMap::new();
+    for (source_idx, source) in cfg.sources.iter().enumerate() {
+        let keys: Vec<String> = source.inputs.iter().filter(|input| input.enabled).map(get_input_key).collect();
+        let mut group_indices: Vec<usize> = keys.iter().filter_map(|key| group_of_input.get(key).copied()).collect();
+        group_indices.sort_unstable();
+        group_indices.dedup();
+        let group_idx = match group_indices.first() {
+            Some(first) => {
+                // merge all groups which share an input with this source into the first one
+                for other in group_indices.iter().skip(1).rev() {
+                    let mut merged = std::mem::take(&mut groups[*other]);
+                    groups[*first].append(&mut merged);
+                    group_of_input.values_mut().filter(|idx| **idx == *other).for_each(|idx| *idx = *first);
+                }
+                groups[*first].push(source_idx);
+                *first
+            }
+            None => {
+                groups.push(vec![source_idx]);
+                groups.len() - 1
+            }
+        };
+        for key in keys {
+            group_of_input.insert(key, group_idx);
+        }
+    }
+    groups.retain(|group| !group.is_empty());
+    groups.iter_mut().for_each(|group| group.sort_unstable());
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::config::{Config, ConfigInput, ConfigSource};
+    use crate::model::playlist::XtreamCluster;
+    use crate::model::playlist_test_utils::{group, item};
+    use crate::processing::input_cache::{group_sources_by_input, InputRunCache};
+    use crate::processing::parse_report::InputParseReport;
+
+    fn input(id: u16, url: &str) -> ConfigInput {
+        ConfigInput { id, url: url.to_string(), enabled: true, ..Default::default() }
+    }
+
+    fn config(sources: Vec<Vec<ConfigInput>>) -> Config {
+        Config {
+            sources: sources.into_iter().map(|inputs| ConfigSource { inputs, targets: vec![] }).collect(),
+            t_input_cache_size: 1024 * 1024,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn group_sources_by_input_test() {
+        let cfg = config(vec![
+            vec![input(1, "http://a")],
+            vec![input(2, "http://b")],
+            vec![input(3, "http://c"), input(4, "http://a")],
+            vec![input(5, "http://b"), input(6, "http://c")],
+            vec![input(7, "http://d")],
+        ]);
+        assert_eq!(group_sources_by_input(&cfg), vec![vec![0, 1, 2, 3], vec![4]]);
+    }
+
+    #[test]
+    fn input_run_cache_test() {
+        let cfg = config(vec![vec![input(1, "http://a")], vec![input(2, "http://a")], vec![input(3, "http://b")]]);
+        let mut cache = InputRunCache::new(&cfg, &[0, 1, 2]);
+        let playlist = vec![group(1, "News", XtreamCluster::Live, vec![item("").input_id(1).build()])];
+        let first = &cfg.sources[0].inputs[0];
+        assert!(cache.get(first).is_none());
+        cache.put(first, &playlist, None, &InputParseReport::new(first));
+        let cached = cache.get(&cfg.sources[1].inputs[0]).unwrap();
+        assert_eq!(cached.playlistgroups[0].channels[0].header.borrow().input_id, 2);
+        assert!(cache.entries.is_empty());
+        assert_eq!(cache.size, 0);
+
+        let other = &cfg.sources[2].inputs[0];
+        assert!(cache.get(other).is_none());
+        cache.put(other, &playlist, None, &InputParseReport::new(other));
+        assert!(cache.entries.is_empty());
+    }
+}
@@ -0,0 +1,118 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use serde_json::json;
+use url::Url;
+
+use crate::m3u_filter_error::{M3uFilterError, M3uFilterErrorKind};
+use crate::model::config::ConfigInput;
+use crate::model::playlist::{PlaylistGroup, PlaylistItem, PlaylistItemHeader, PlaylistItemType, XtreamCluster};
+use crate::repository::storage::hash_string;
+use crate::create_m3u_filter_error_result;
+
+/// Extensions of the video files which are served by a local input.
+pub const LOCAL_VIDEO_EXTENSIONS: &[&str] = &["mp4", "m4v", "mkv", "avi", "ts"];
+
+fn collect_video_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = std::fs::read_dir(dir) else { return };
+    for path in entries.filter_map(Result::ok).map(|entry| entry.path()) {
+        if path.is_dir() {
+            collect_video_files(&path, files);
+        } else if path.extension().and_then(|ext| ext.to_str())
+            .is_some_and(|ext| LOCAL_VIDEO_EXTENSIONS.contains(&ext.to_lowercase().as_str())) {
+            files.push(path);
+        }
+    }
+}
+
+/// The provider id of a local file, derived from the path relative to the input directory.
+fn get_local_provider_id(relative_path: &str) -> u32 {
+    let hash = hash_string(relative_path);
+    (u32::from_le_bytes([hash[0], hash[1], hash[2], hash[3]]) & 0x7fff_ffff).max(1)
+}
+
+fn create_local_item(input: &ConfigInput, dir: &Path, path: &Path, group: &Rc<String>) -> Option<PlaylistItem> {
+    let relative_path = path.strip_prefix(dir).ok()?.to_string_lossy().to_string();
+    let url = Url::from_file_path(path).ok()?;
+    let title = path.file_stem()?.to_string_lossy().replace('_', " ").trim().to_string();
+    let extension = path.extension()?.to_string_lossy().to_lowercase();
+    let title = Rc::new(title);
+    let mut header = PlaylistItemHeader {
+        id: Rc::new(get_local_provider_id(&relative_path).to_string()),
+        name: Rc::clone(&title),
+        title,
+        group: Rc::clone(group),
+        url: Rc::new(url.to_string()),
+        xtream_cluster: XtreamCluster::Video,
+        item_type: PlaylistItemType::Video,
+        additional_properties: Some(json!({"container_extension": extension})),
+        input_id: input.id,
+        ..PlaylistItemHeader::default()
+    };
+    header.gen_uuid();
+    Some(PlaylistItem { header: RefCell::new(header) })
+}
+
+/// Creates the video playlist of a local input, the `url` of the input is the directory of the video files.
+/// Each sub directory is a group, the files of the directory itself are grouped by the directory name.
+pub fn parse_local_directory(input: &ConfigInput) -> Result<Vec<PlaylistGroup>, M3uFilterError> {
+    let dir = PathBuf::from(input.url.strip_prefix("file://").unwrap_or(&input.url));
+    let Ok(dir) = dir.canonicalize() else {
+        return create_m3u_filter_error_result!(M3uFilterErrorKind::Notify, "Local input directory not found: {}", dir.display());
+    };
+    let mut files = vec![];
+    collect_video_files(&dir, &mut files);
+    files.sort();
+
+    let root_group = dir.file_name().map_or_else(|| "Local".to_string(), |name| name.to_string_lossy().to_string());
+    let mut groups: BTreeMap<String, Vec<PlaylistItem>> = BTreeMap::new();
+    for path in &files {
+        let group_title = path.parent().and_then(|parent| parent.strip_prefix(&dir).ok())
+            .map(|parent| parent.to_string_lossy().replace(std::path::MAIN_SEPARATOR, " / "))
+            .filter(|parent| !parent.is_empty())
+            .unwrap_or_else(|| root_group.clone());
+        let group = Rc::new(group_title.clone());
+        if let Some(item) = create_local_item(input, &dir, path, &group) {
+            groups.entry(group_title).or_default().push(item);
+        }
+    }
+    Ok(groups.into_iter().enumerate().map(|(index, (title, channels))| PlaylistGroup {
+        id: u32::try_from(index + 1).unwrap_or(u32::MAX),
+        title: Rc::new(title),
+        channels,
+        xtream_cluster: XtreamCluster::Video,
+    }).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::config::{ConfigInput, InputType};
+    use crate::processing::local_parser::parse_local_directory;
+
+    #[test]
+    fn parse_local_directory_test() {
+        let dir = std::path::PathBuf::from("/tmp/m3u_filter_local_input_test");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("My_Series")).unwrap();
+        std::fs::write(dir.join("The_Movie.mp4"), b"mp4").unwrap();
+        std::fs::write(dir.join("My_Series/My_Series_S01E01.mkv"), b"mkv").unwrap();
+        std::fs::write(dir.join("My_Series/My_Series_S01E02.ts.part"), b"ts").unwrap();
+        std::fs::write(dir.join("notes.txt"), b"txt").unwrap();
+
+        let input = ConfigInput { id: 3, input_type: InputType::Local, url: dir.to_string_lossy().to_string(), ..ConfigInput::default() };
+        let groups = parse_local_directory(&input).unwrap();
+        assert_eq!(groups.iter().map(|group| group.title.as_str()).collect::<Vec<_>>(), vec!["My_Series", "m3u_filter_local_input_test"]);
+        let header = groups[1].channels[0].header.borrow();
+        assert_eq!(header.title.as_str(), "The Movie");
+        assert_eq!(header.url.as_str(), "file:///tmp/m3u_filter_local_input_test/The_Movie.mp4");
+        assert_eq!(header.input_id, 3);
+        assert!(header.id.parse::<u32>().unwrap() > 0);
+        assert_eq!(groups[0].channels.len(), 1);
+
+        let input = ConfigInput { input_type: InputType::Local, url: "/tmp/m3u_filter_local_input_missing".to_string(), ..ConfigInput::default() };
+        assert!(parse_local_directory(&input).is_err());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
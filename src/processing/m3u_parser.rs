@@ -2,8 +2,10 @@ use std::borrow::BorrowMut;
 use std::cell::RefCell;
 use std::rc::Rc;
 
+use crate::m3u_filter_error::M3uFilterError;
 use crate::model::config::{Config, ConfigInput};
 use crate::model::playlist::{PlaylistGroup, PlaylistItem, PlaylistItemHeader, PlaylistItemType, XtreamCluster};
+use crate::processing::parse_report::InputParseReport;
 use crate::utils::string_utils;
 
 #[inline]
@@ -109,7 +111,12 @@ fn process_header(input: &ConfigInput, video_suffixes: &[&str], content: &str, u
                 let token = token_till(&mut it, '=', true);
                 if let Some(t) = token {
                     let value = token_value(&mut it);
-                    process_header_fields!(plih, t.to_lowercase().as_str(),
+                    let t = t.to_lowercase();
+                    if t == "tvg-language" {
+                        // filterable with `Language`
+                        plih.additional_properties = Some(serde_json::json!({"language": value}));
+                    }
+                    process_header_fields!(plih, t.as_str(),
                         (id, "tvg-id"),
                         (group, "group-title"),
                         (name, "tvg-name"),
@@ -161,53 +168,83 @@ pub fn extract_id_from_url(url: &str) -> Option<String> {
     None
 }
 
-pub fn consume_m3u<'a, I, F: FnMut(PlaylistItem)>(cfg: &Config, input: &ConfigInput, lines: I, mut visit: F)
+/// Malformed entries are skipped and recorded in the parse report.
+pub fn consume_m3u<I, S, F: FnMut(PlaylistItem)>(cfg: &Config, input: &ConfigInput, lines: I, parse_report: &mut InputParseReport, mut visit: F) -> Result<(), M3uFilterError>
 where
-    I: Iterator<Item=&'a str>,
+    I: Iterator<Item=S>,
+    S: AsRef<str>,
 {
     let mut header: Option<String> = None;
     let mut group: Option<String> = None;
+    // the props are placed before or after #EXTINF, they belong to the next url
+    let mut kodi_props: Vec<String> = vec![];
 
     let video_suffixes = cfg.video.as_ref().unwrap().extensions.iter().map(String::as_str).collect::<Vec<&str>>();
     for line in lines {
+        let line = line.as_ref();
+        if line.trim().is_empty() {
+            continue;
+        }
         if line.starts_with("#EXTINF") {
-            header = Some(String::from(line));
+            if let Some(header_value) = header.replace(String::from(line)) {
+                parse_report.skip_entry("Missing url", &header_value)?;
+            }
             continue;
         }
         if line.starts_with("#EXTGRP") {
             group = Some(String::from(&line[8..]));
             continue;
         }
+        if let Some(prop) = line.strip_prefix("#KODIPROP:").map(str::trim).filter(|prop| prop.contains('=')) {
+            kodi_props.push(prop.to_string());
+            continue;
+        }
         if line.starts_with('#') {
             continue;
         }
         if let Some(header_value) = header {
             let item = PlaylistItem { header: RefCell::new(process_header(input, &video_suffixes, &header_value, line)) };
             let mut header = item.header.borrow_mut();
-            if header.group.is_empty() {
-                if let Some(group_value) = group {
-                    header.group = Rc::new(group_value);
-                } else {
-                    let current_title = header.title.clone();
-                    header.group = Rc::new(string_utils::get_title_group(current_title.as_str()));
+            if header.name.is_empty() {
+                drop(header);
+                parse_report.skip_entry("Missing name", &format!("{header_value} {line}"))?;
+            } else {
+                if header.group.is_empty() {
+                    if let Some(group_value) = group {
+                        header.group = Rc::new(group_value);
+                    } else {
+                        let current_title = header.title.clone();
+                        header.group = Rc::new(string_utils::get_title_group(current_title.as_str()));
+                    }
+                }
+                if !kodi_props.is_empty() {
+                    header.kodi_props = Some(Rc::new(std::mem::take(&mut kodi_props)));
                 }
+                drop(header);
+                visit(item);
             }
-            drop(header);
-            visit(item);
+        } else {
+            parse_report.skip_entry("Missing #EXTINF", line)?;
         }
         header = None;
         group = None;
+        kodi_props.clear();
     }
+    if let Some(header_value) = header {
+        parse_report.skip_entry("Missing url", &header_value)?;
+    }
+    Ok(())
 }
 
-pub fn parse_m3u<'a, I>(cfg: &Config, input: &ConfigInput, lines: I) -> Vec<PlaylistGroup>
+pub fn parse_m3u<I, S>(cfg: &Config, input: &ConfigInput, lines: I, parse_report: &mut InputParseReport) -> Result<Vec<PlaylistGroup>, M3uFilterError>
 where
-    I: Iterator<Item=&'a str>,
+    I: Iterator<Item=S>,
+    S: AsRef<str>,
 {
     let mut sort_order: Vec<Vec<PlaylistItem>> = vec![];
     let mut sort_order_idx: usize = 0;
     let mut group_map: std::collections::HashMap<Rc<String>, usize> = std::collections::HashMap::new();
-    consume_m3u(cfg, input, lines, |item| {
+    consume_m3u(cfg, input, lines, parse_report, |item| {
         // keep the original sort order for groups and group the playlist items
         let key = Rc::clone(&item.header.borrow().group);
         match group_map.entry(key) {
@@ -220,7 +257,7 @@ where
                 sort_order.get_mut(*o.get()).unwrap().push(item);
             }
         }
-    });
+    })?;
     let mut grp_id = 0;
     let result: Vec<PlaylistGroup> = sort_order.into_iter().map(|channels| {
         // create a group based on the first playlist item
@@ -230,5 +267,37 @@ where
         grp_id += 1;
         PlaylistGroup { id: grp_id, xtream_cluster: cluster, title: Rc::clone(&group_title), channels }
     }).collect();
-    result
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::config::{Config, ConfigInput, VideoConfig};
+    use crate::processing::m3u_parser::parse_m3u;
+    use crate::processing::parse_report::InputParseReport;
+
+    #[test]
+    fn kodi_props_test() {
+        let cfg = Config { video: Some(VideoConfig { extensions: vec!["mp4".to_string()], ..VideoConfig::default() }), ..Config::default() };
+        let input = ConfigInput::default();
+        let mut parse_report = InputParseReport::new(&input);
+        let content = r#"#EXTM3U
+#KODIPROP:inputstream.adaptive.manifest_type=mpd
+#EXTINF:-1 tvg-id="before.de" group-title="DE",Before
+http://provider.tv/live/1.mpd
+#EXTINF:-1 tvg-id="after.de" group-title="DE",After
+#KODIPROP:inputstream.adaptive.license_type=clearkey
+#KODIPROP:invalid
+http://provider.tv/live/2.mpd
+#EXTINF:-1 tvg-id="plain.de" group-title="DE",Plain
+http://provider.tv/live/3.ts"#;
+        let playlist = parse_m3u(&cfg, &input, content.lines(), &mut parse_report).unwrap();
+        let props: Vec<Option<Vec<String>>> = playlist[0].channels.iter()
+            .map(|item| item.header.borrow().kodi_props.as_ref().map(|props| props.to_vec())).collect();
+        assert_eq!(props, vec![
+            Some(vec!["inputstream.adaptive.manifest_type=mpd".to_string()]),
+            Some(vec!["inputstream.adaptive.license_type=clearkey".to_string()]),
+            None,
+        ]);
+    }
 }
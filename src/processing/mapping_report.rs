@@ -0,0 +1,68 @@
+use serde::Serialize;
+
+use crate::model::config::ConfigTarget;
+use crate::model::playlist::PlaylistItem;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MapperReport {
+    pub mapping: String,
+    pub pattern: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filter: Option<String>,
+    pub matches: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UnmatchedChannel {
+    pub input: String,
+    pub group: String,
+    pub name: String,
+}
+
+/// Dry-run of the target mappings against the channels as they reach the mapping step of the processing order.
+/// The mappers are applied to a copy of the channels in their order, a mapper sees the output of the previous mappers.
+#[derive(Debug, Clone, Serialize)]
+pub struct MappingReport {
+    pub target: String,
+    pub channel_count: usize,
+    pub mappers: Vec<MapperReport>,
+    pub unmatched_channels: Vec<UnmatchedChannel>,
+}
+
+impl MappingReport {
+    pub fn new(target: &ConfigTarget) -> Self {
+        let mappers = target.t_mapping.as_ref().map_or_else(Vec::new, |mappings| mappings.iter()
+            .flat_map(|mapping| mapping.mapper.iter().map(|mapper| MapperReport {
+                mapping: mapping.id.clone(),
+                pattern: mapper.pattern.clone(),
+                filter: mapper.filter.clone(),
+                matches: 0,
+            })).collect());
+        Self {
+            target: target.name.clone(),
+            channel_count: 0,
+            mappers,
+            unmatched_channels: vec![],
+        }
+    }
+
+    /// Counts a channel as it reaches the mapping step, `matches` has one entry for each mapper of the report.
+    pub fn add_channel(&mut self, input_name: &str, channel: &PlaylistItem, matches: &[bool]) {
+        self.channel_count += 1;
+        for (mapper, _) in self.mappers.iter_mut().zip(matches).filter(|(_, matched)| **matched) {
+            mapper.matches += 1;
+        }
+        if !matches.contains(&true) {
+            let header = channel.header.borrow();
+            self.unmatched_channels.push(UnmatchedChannel {
+                input: input_name.to_string(),
+                group: header.group.to_string(),
+                name: header.name.to_string(),
+            });
+        }
+    }
+
+    pub fn unused_mapper_count(&self) -> usize {
+        self.mappers.iter().filter(|m| m.matches == 0).count()
+    }
+}
@@ -1,9 +1,24 @@
 pub mod m3u_parser;
 pub mod xtream_parser;
+pub mod local_parser;
 pub mod playlist_processor;
 pub mod xmltv_parser;
+pub mod movie_parts;
+pub mod parse_report;
+pub mod selftest;
+pub mod user_import;
+pub mod trial_user;
+pub mod user_bulk;
+pub mod wasm_plugin;
 mod playlist_watch;
 mod xtream_processor;
 mod affix_processor;
 mod xtream_processor_vod;
 mod xtream_processor_series;
+mod mapping_report;
+pub mod series_merge;
+mod feed_tags;
+pub mod epg_enrich;
+mod target_validation;
+mod cache_prefetch;
+mod input_cache;
@@ -0,0 +1,102 @@
+use std::collections::HashMap;
+use std::sync::LazyLock;
+
+use regex::Regex;
+use serde_json::{json, Map, Value};
+
+use crate::model::playlist::{PlaylistGroup, PlaylistItemType};
+
+pub const PROP_MOVIE_PART: &str = "movie_part";
+pub const PROP_MOVIE_PARTS: &str = "movie_parts";
+
+// "Movie (2010) CD1", "Movie.cd.2", "Movie [Disc 1]". "Part 1" is not matched, it is mostly used for sequels.
+static MOVIE_PART_REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?i)^(?P<title>.+?)[\s._-]*[(\[]?\b(?:cd|dvd|disc|disk)[\s._-]*(?P<part>\d{1,2})[)\]]?$").unwrap());
+
+// (input_id, group, title) -> [(part, group index, item index)]
+type MoviePartMap = HashMap<(u16, String, String), Vec<(u32, usize, usize)>>;
+
+/// Splits a movie title into the title without part and the part number.
+pub fn extract_movie_part(title: &str) -> Option<(String, u32)> {
+    MOVIE_PART_REGEX.captures(title.trim()).and_then(|caps| {
+        let part = caps["part"].parse::<u32>().ok().filter(|part| *part > 0)?;
+        Some((caps["title"].trim().to_string(), part))
+    })
+}
+
+/// Multi-part movies are movies of the same group and input with the same title and different parts.
+/// Each part gets the additional properties `movie_part` and `movie_parts` (all parts with virtual id),
+/// they are used for the strm naming and the xtream vod info.
+/// Virtual ids have to be assigned before.
+pub fn assign_movie_parts(playlist: &[PlaylistGroup]) {
+    let mut movies: MoviePartMap = HashMap::new();
+    for (group_idx, group) in playlist.iter().enumerate() {
+        for (item_idx, item) in group.channels.iter().enumerate() {
+            let header = item.header.borrow();
+            if header.item_type == PlaylistItemType::Video {
+                if let Some((title, part)) = extract_movie_part(&header.title) {
+                    movies.entry((header.input_id, header.group.to_lowercase(), title.to_lowercase()))
+                        .or_default().push((part, group_idx, item_idx));
+                }
+            }
+        }
+    }
+
+    for mut parts in movies.into_values() {
+        parts.sort_by_key(|(part, _, _)| *part);
+        parts.dedup_by_key(|(part, _, _)| *part);
+        if parts.len() < 2 {
+            continue;
+        }
+        let movie_parts = Value::Array(parts.iter().map(|(part, group_idx, item_idx)| {
+            let header = playlist[*group_idx].channels[*item_idx].header.borrow();
+            json!({"part": part, "stream_id": header.virtual_id, "name": header.title.as_str()})
+        }).collect());
+        for (part, group_idx, item_idx) in &parts {
+            let mut header = playlist[*group_idx].channels[*item_idx].header.borrow_mut();
+            let mut props = match header.additional_properties.take() {
+                Some(Value::Object(props)) => props,
+                _ => Map::new(),
+            };
+            props.insert(PROP_MOVIE_PART.to_string(), Value::from(*part));
+            props.insert(PROP_MOVIE_PARTS.to_string(), movie_parts.clone());
+            header.additional_properties = Some(Value::Object(props));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use crate::model::playlist::{PlaylistGroup, PlaylistItem, PlaylistItemType, XtreamCluster};
+    use crate::model::playlist_test_utils::item;
+    use crate::processing::movie_parts::{assign_movie_parts, extract_movie_part, PROP_MOVIE_PART};
+
+    fn movie(title: &str, virtual_id: u32) -> PlaylistItem {
+        item(title).group("Movies").virtual_id(virtual_id).item_type(PlaylistItemType::Video).cluster(XtreamCluster::Video).build()
+    }
+
+    #[test]
+    fn extract_movie_part_test() {
+        assert_eq!(extract_movie_part("Movie (2010) CD1"), Some(("Movie (2010)".to_string(), 1)));
+        assert_eq!(extract_movie_part("Movie.cd.2"), Some(("Movie".to_string(), 2)));
+        assert_eq!(extract_movie_part("Movie [Disc 2]"), Some(("Movie".to_string(), 2)));
+        assert_eq!(extract_movie_part("Movie Part 1"), None);
+        assert_eq!(extract_movie_part("Abcd1"), None);
+    }
+
+    #[test]
+    fn assign_movie_parts_test() {
+        let playlist = vec![PlaylistGroup {
+            id: 1,
+            title: Rc::new("Movies".to_string()),
+            channels: vec![movie("Movie CD2", 2), movie("Movie CD1", 1), movie("Other CD1", 3)],
+            xtream_cluster: XtreamCluster::Video,
+        }];
+        assign_movie_parts(&playlist);
+        let channels = &playlist[0].channels;
+        assert_eq!(channels[0].header.borrow().get_additional_property_as_u64(PROP_MOVIE_PART), Some(2));
+        assert_eq!(channels[1].header.borrow().get_additional_property_as_u64(PROP_MOVIE_PART), Some(1));
+        assert!(channels[2].header.borrow().additional_properties.is_none());
+    }
+}
@@ -0,0 +1,88 @@
+use serde::Serialize;
+
+use crate::m3u_filter_error::{M3uFilterError, M3uFilterErrorKind};
+use crate::model::config::ConfigInput;
+use crate::utils::request_utils::mask_sensitive_info;
+use crate::create_m3u_filter_error_result;
+
+// the skipped entry is shortened for the report
+const MAX_ENTRY_LEN: usize = 256;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SkippedEntry {
+    pub reason: String,
+    pub entry: String,
+}
+
+/// Malformed entries of an input playlist which were skipped while parsing.
+/// With `parse_strict` the parsing is aborted on the first malformed entry,
+/// with `parse_max_errors` when more than the given number of entries are malformed.
+#[derive(Debug, Clone, Serialize)]
+pub struct InputParseReport {
+    pub input: String,
+    pub skipped: Vec<SkippedEntry>,
+    pub aborted: bool,
+    #[serde(skip)]
+    strict: bool,
+    #[serde(skip)]
+    max_errors: Option<usize>,
+}
+
+impl InputParseReport {
+    pub fn new(input: &ConfigInput) -> Self {
+        let options = input.options.as_ref();
+        Self {
+            input: input.name.as_ref().map_or_else(|| mask_sensitive_info(input.url.as_str()), ToString::to_string),
+            skipped: vec![],
+            aborted: false,
+            strict: options.is_some_and(|opt| opt.parse_strict),
+            max_errors: options.and_then(|opt| opt.parse_max_errors),
+        }
+    }
+
+    /// Records a malformed entry. Returns an error if the parsing should be aborted.
+    pub fn skip_entry(&mut self, reason: &str, entry: &str) -> Result<(), M3uFilterError> {
+        let mut entry = mask_sensitive_info(entry);
+        if let Some((idx, _)) = entry.char_indices().nth(MAX_ENTRY_LEN) {
+            entry.truncate(idx);
+        }
+        self.skipped.push(SkippedEntry { reason: reason.to_string(), entry });
+        if self.strict || self.max_errors.is_some_and(|max| self.skipped.len() > max) {
+            self.aborted = true;
+            return create_m3u_filter_error_result!(M3uFilterErrorKind::Notify, "Parsing of input {} aborted after {} malformed entries, last: {reason}", self.input, self.skipped.len());
+        }
+        Ok(())
+    }
+}
+
+/// Parse reports of all inputs of the source of a target update.
+#[derive(Debug, Clone, Serialize)]
+pub struct ParseReport {
+    pub target: String,
+    pub timestamp: i64,
+    pub inputs: Vec<InputParseReport>,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::config::{ConfigInput, ConfigInputOptions};
+    use crate::processing::parse_report::InputParseReport;
+
+    #[test]
+    fn skip_entry_test() {
+        let mut input = ConfigInput { id: 1, name: Some("provider".to_string()), ..Default::default() };
+        let mut report = InputParseReport::new(&input);
+        assert!(report.skip_entry("Missing url", "#EXTINF:-1,Channel").is_ok());
+        assert_eq!(report.skipped.len(), 1);
+
+        input.options = Some(ConfigInputOptions { parse_max_errors: Some(1), ..Default::default() });
+        let mut report = InputParseReport::new(&input);
+        assert!(report.skip_entry("Missing url", "#EXTINF:-1,Channel 1").is_ok());
+        assert!(report.skip_entry("Missing url", "#EXTINF:-1,Channel 2").is_err());
+        assert!(report.aborted);
+
+        input.options = Some(ConfigInputOptions { parse_strict: true, ..Default::default() });
+        let mut report = InputParseReport::new(&input);
+        assert!(report.skip_entry("Missing url", "#EXTINF:-1,Channel").is_err());
+    }
+}
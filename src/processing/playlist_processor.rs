@@ -1,6 +1,6 @@
 extern crate unidecode;
 
-use crate::repository::storage::hash_string;
+use crate::repository::storage::{get_target_storage_path, hash_string, quarantine_corrupt_documents, target_remove_staged_files};
 use async_std::sync::Mutex;
 use core::cmp::Ordering;
 use std::cell::RefCell;
@@ -17,22 +17,37 @@ use unidecode::unidecode;
 
 use crate::filter::{get_field_value, set_field_value, MockValueProcessor, ValueProvider};
 use crate::m3u_filter_error::{M3uFilterError, M3uFilterErrorKind};
-use crate::messaging::{send_message, MsgKind};
-use crate::model::config::{ConfigSortChannel, ConfigSortGroup, ConfigTarget, InputType,
-                           ItemField, ProcessTargets, ProcessingOrder, SortOrder::{Asc, Desc}};
-use crate::model::mapping::{CounterModifier, Mapping, MappingValueProcessor};
+use crate::messaging::{notify_processing, send_message, MsgKind};
+use crate::model::config::{ChannelOverflowPolicy, ConfigInput, ConfigSortChannel, ConfigSortGroup, ConfigTarget, InputFailurePolicy, InputType,
+                           ItemField, ProcessTargets, ProcessingOrder, ProviderQuirk, SortOrder::{Asc, Desc}};
+use crate::model::processing_job::ProcessingJob;
+use crate::model::mapping::{CounterModifier, Mapper, Mapping, MappingValueProcessor};
 use crate::model::playlist::{FetchedPlaylist, FieldGetAccessor, FieldSetAccessor, PlaylistEntry, PlaylistGroup, PlaylistItem, UUIDType, XtreamCluster};
-use crate::model::stats::{InputStats, PlaylistStats, SourceStats, TargetStats};
+use crate::model::stats::{format_elapsed_time, InputContribution, InputStats, PlaylistStats, ProcessingSummary, SourceStats, TargetInputStats, TargetStats, TimingReport};
 use crate::processing::affix_processor::apply_affixes;
+use crate::processing::mapping_report::MappingReport;
+use crate::processing::parse_report::{InputParseReport, ParseReport};
 use crate::processing::playlist_watch::process_group_watch;
-use crate::processing::xmltv_parser::flatten_tvguide;
+use crate::processing::feed_tags::{group_feed_variants, tag_feeds};
+use crate::processing::series_merge::merge_series;
+use crate::processing::target_validation::{validate_target, ValidationReport, ValidationViolation};
+use crate::processing::wasm_plugin::apply_plugin;
+use crate::processing::epg_enrich::enrich_from_epg;
+use crate::processing::xmltv_parser::{apply_epg_options, fix_negative_epg_offsets, flatten_tvguide};
 use crate::processing::xtream_processor_series::playlist_resolve_series;
 use crate::processing::xtream_processor_vod::playlist_resolve_vod;
+use crate::repository::input_fallback_repository::{input_read_fallback, input_write_fallback};
 use crate::repository::playlist_repository::persist_playlist;
+use crate::processing::cache_prefetch::prefetch_resources;
+use crate::processing::input_cache::{group_sources_by_input, InputRunCache};
+use crate::repository::report_repository::{write_target_report, REPORT_MAPPING, REPORT_PARSER, REPORT_TIMING, REPORT_VALIDATION};
 use crate::utils::default_utils::default_as_default;
-use crate::utils::download;
+use crate::utils::{config_reader, download, metrics};
+use crate::utils::preflight::{apply_input_preflight, PreflightOutcome};
 use crate::utils::request_utils::mask_sensitive_info;
-use crate::{debug_if_enabled, get_errors_notify_message, model::config, notify_err, Config};
+use crate::utils::step_measure::{StepMeasure, StepTiming};
+use crate::utils::progress::{finish_progress, update_progress};
+use crate::{debug_if_enabled, get_errors_notify_message, info_err, model::config, notify_err, Config};
 
 fn is_valid(pli: &PlaylistItem, target: &ConfigTarget) -> bool {
     let provider = ValueProvider { pli: RefCell::new(pli) };
@@ -62,6 +77,13 @@ fn filter_playlist(playlist: &mut [PlaylistGroup], target: &ConfigTarget) -> Opt
 fn playlistgroup_comparator(a: &PlaylistGroup, b: &PlaylistGroup, group_sort: &ConfigSortGroup, match_as_ascii: bool) -> Ordering {
     let value_a = if match_as_ascii { Rc::new(unidecode(&a.title)) } else { Rc::clone(&a.title) };
     let value_b = if match_as_ascii { Rc::new(unidecode(&b.title)) } else { Rc::clone(&b.title) };
+    // pinned groups first, in the order of their patterns
+    match (group_sort.get_pinned_index(&value_a), group_sort.get_pinned_index(&value_b)) {
+        (Some(pin_a), Some(pin_b)) if pin_a != pin_b => return pin_a.cmp(&pin_b),
+        (Some(_), None) => return Ordering::Less,
+        (None, Some(_)) => return Ordering::Greater,
+        _ => {}
+    }
     let ordering = value_a.partial_cmp(&value_b).unwrap();
     match group_sort.order {
         Asc => ordering,
@@ -173,12 +195,14 @@ fn rename_playlist(playlist: &mut [PlaylistGroup], target: &ConfigTarget) -> Opt
     }
 }
 
-macro_rules! apply_pattern {
-    ($pattern:expr, $provider:expr, $processor:expr) => {{
-            if let Some(ptrn) = $pattern {
-               ptrn.filter($provider, $processor);
-            };
-    }};
+/// Applies the mapper to the channel if the filter and the pattern match, returns `true` if it was applied.
+fn apply_mapper(channel: &PlaylistItem, mapper: &Mapper) -> bool {
+    let ref_chan = RefCell::new(channel);
+    let provider = ValueProvider { pli: ref_chan.clone() };
+    let mut mock_processor = MockValueProcessor {};
+    let mut processor = MappingValueProcessor { pli: ref_chan.clone(), mapper };
+    mapper.t_filter.as_ref().is_none_or(|filter| filter.filter(&provider, &mut mock_processor))
+        && mapper.t_pattern.as_ref().is_some_and(|pattern| pattern.filter(&provider, &mut processor))
 }
 
 fn map_channel(channel: PlaylistItem, mapping: &Mapping) -> PlaylistItem {
@@ -187,26 +211,23 @@ fn map_channel(channel: PlaylistItem, mapping: &Mapping) -> PlaylistItem {
         let channel_name = if mapping.match_as_ascii { Rc::new(unidecode(&header.name)) } else { header.name.clone() };
         if mapping.match_as_ascii && log_enabled!(Level::Trace) { trace!("Decoded {} for matching to {}", &header.name, &channel_name); };
         drop(header);
-        let ref_chan = RefCell::new(&channel);
-        let provider = ValueProvider { pli: ref_chan.clone() };
-        let mut mock_processor = MockValueProcessor {};
         for m in &mapping.mapper {
-            let mut processor = MappingValueProcessor { pli: ref_chan.clone(), mapper: m };
-            match &m.t_filter {
-                Some(filter) => {
-                    if filter.filter(&provider, &mut mock_processor) {
-                        apply_pattern!(&m.t_pattern, &provider, &mut processor);
-                    }
-                }
-                _ => {
-                    apply_pattern!(&m.t_pattern, &provider, &mut processor);
-                }
-            };
+            apply_mapper(&channel, m);
         }
     }
     channel
 }
 
+/// Evaluates the mappers for the mapping report on a copy of the channels, like the mapping step does.
+fn report_mapping(report: &mut MappingReport, input_name: &str, playlist: &[PlaylistGroup], target: &ConfigTarget) {
+    let Some(mappings) = target.t_mapping.as_ref() else { return };
+    for channel in playlist.iter().flat_map(|group| &group.channels) {
+        let mapped = channel.clone();
+        let matches: Vec<bool> = mappings.iter().flat_map(|mapping| &mapping.mapper).map(|mapper| apply_mapper(&mapped, mapper)).collect();
+        report.add_channel(input_name, channel, &matches);
+    }
+}
+
 fn map_playlist(playlist: &mut [PlaylistGroup], target: &ConfigTarget) -> Option<Vec<PlaylistGroup>> {
     if target.t_mapping.is_some() {
         let new_playlist: Vec<PlaylistGroup> = playlist.iter().map(|playlist_group| {
@@ -216,32 +237,43 @@ fn map_playlist(playlist: &mut [PlaylistGroup], target: &ConfigTarget) -> Option
                 grp.channels = grp.channels.drain(..).map(|chan| map_channel(chan, mapping)).collect());
             grp
         }).collect();
+        Some(regroup_channels(new_playlist))
+    } else {
+        None
+    }
+}
 
-        // if the group names are changed, restructure channels to the right groups
-        // we use
-        let mut new_groups: Vec<PlaylistGroup> = Vec::with_capacity(128);
-        let mut grp_id: u32 = 0;
-        for playlist_group in new_playlist {
-            for channel in &playlist_group.channels {
-                let cluster = &channel.header.borrow().xtream_cluster;
-                let title = &channel.header.borrow().group;
-                if let Some(grp) = new_groups.iter_mut().find(|x| *x.title == **title) {
-                    grp.channels.push(channel.clone());
-                } else {
-                    grp_id += 1;
-                    new_groups.push(PlaylistGroup {
-                        id: grp_id,
-                        title: Rc::clone(title),
-                        channels: vec![channel.clone()],
-                        xtream_cluster: *cluster,
-                    });
-                }
+// if the group names are changed, restructure channels to the right groups
+fn regroup_channels(playlist: Vec<PlaylistGroup>) -> Vec<PlaylistGroup> {
+    let mut new_groups: Vec<PlaylistGroup> = Vec::with_capacity(128);
+    let mut grp_id: u32 = 0;
+    for playlist_group in playlist {
+        for channel in &playlist_group.channels {
+            let cluster = &channel.header.borrow().xtream_cluster;
+            let title = &channel.header.borrow().group;
+            if let Some(grp) = new_groups.iter_mut().find(|x| *x.title == **title) {
+                grp.channels.push(channel.clone());
+            } else {
+                grp_id += 1;
+                new_groups.push(PlaylistGroup {
+                    id: grp_id,
+                    title: Rc::clone(title),
+                    channels: vec![channel.clone()],
+                    xtream_cluster: *cluster,
+                });
             }
         }
-        Some(new_groups)
-    } else {
-        None
     }
+    new_groups
+}
+
+fn plugin_playlist(playlist: &mut [PlaylistGroup], target: &ConfigTarget) -> Option<Vec<PlaylistGroup>> {
+    let plugins = target.t_plugins.as_ref()?;
+    let mut new_playlist = playlist.to_vec();
+    for plugin in plugins {
+        apply_plugin(plugin, &mut new_playlist);
+    }
+    Some(regroup_channels(new_playlist))
 }
 
 fn map_playlist_counter(target: &ConfigTarget, playlist: &[PlaylistGroup]) {
@@ -291,37 +323,111 @@ fn is_target_enabled(target: &ConfigTarget, user_targets: &ProcessTargets) -> bo
     (!user_targets.enabled && target.enabled) || (user_targets.enabled && user_targets.has_target(target.id))
 }
 
-async fn process_source(client: Arc<reqwest::Client>, cfg: Arc<Config>, source_idx: usize, user_targets: Arc<ProcessTargets>) -> (Vec<InputStats>, Vec<TargetStats>, Vec<M3uFilterError>) {
+async fn process_source(client: Arc<reqwest::Client>, cfg: Arc<Config>, source_idx: usize, user_targets: Arc<ProcessTargets>,
+                        input_cache: &mut InputRunCache, job: &ProcessingJob) -> (Vec<InputStats>, Vec<TargetStats>, Vec<M3uFilterError>) {
     let source = cfg.sources.get(source_idx).unwrap();
     let mut errors = vec![];
     let mut input_stats = HashMap::<u16, InputStats>::new();
     let mut target_stats = Vec::<TargetStats>::new();
     let mut source_playlists = Vec::with_capacity(128);
+    let mut parse_reports = vec![];
     let enabled_inputs = source.inputs.iter().filter(|item| item.enabled).count();
+    // the failed inputs get a placeholder playlist, which is replaced by the playlist of their last successful update
+    let keep_previous = source.targets.iter()
+        .any(|target| is_target_enabled(target, &user_targets) && get_input_failure_policy(target) == InputFailurePolicy::KeepPrevious);
+    let mut source_inputs = vec![];
+    let mut failed_inputs = HashSet::new();
     // Downlod the sources
-    for input in &source.inputs {
+    let mut input_measure = StepMeasure::new();
+    let mut preflight_failed = false;
+    // the health at the start of the update decides the lineup, the download below updates it for the next update
+    for input in cfg.t_input_health.get_priority_order(&source.inputs) {
+        if job.is_cancelled() {
+            break;
+        }
         let input_id = input.id;
         if is_input_enabled(enabled_inputs, input.enabled, input_id, &user_targets) {
-            let start_time = Instant::now();
-            let (mut playlistgroups, mut error_list) = match input.input_type {
-                InputType::M3u => download::get_m3u_playlist(Arc::clone(&client), &cfg, input, &cfg.working_dir).await,
-                InputType::Xtream => download::get_xtream_playlist(Arc::clone(&client), input, &cfg.working_dir).await,
+            let input = match apply_input_preflight(&cfg, input, false).await {
+                PreflightOutcome::Use(input) => input,
+                PreflightOutcome::Fallback(fallback, err) => {
+                    errors.push(preflight_error(input, &err));
+                    info!("Using fallback input {} for input {}", fallback.name.as_deref().unwrap_or_default(), input.name.as_deref().unwrap_or_default());
+                    fallback
+                }
+                PreflightOutcome::Skip(err) => {
+                    errors.push(preflight_error(input, &err));
+                    source_inputs.push(input);
+                    failed_inputs.insert(input.id);
+                    if keep_previous {
+                        source_playlists.push(FetchedPlaylist { input, playlistgroups: vec![], epg: None });
+                    }
+                    continue;
+                }
+                PreflightOutcome::Fail(err) => {
+                    errors.push(preflight_error(input, &err));
+                    preflight_failed = true;
+                    break;
+                }
             };
-            let (tvguide, mut tvguide_errors) = if error_list.is_empty() {
-                download::get_xmltv(Arc::clone(&client), &cfg, input, &cfg.working_dir).await
+            let start_time = Instant::now();
+            let input_name = input.name.as_ref().map_or_else(|| mask_sensitive_info(input.url.as_str()), std::string::ToString::to_string);
+            input_measure.restart();
+            let (mut playlistgroups, mut error_list, tvguide, mut tvguide_errors) = if let Some(cached) = input_cache.get(input) {
+                debug!("Using the playlist of input {input_name} from a previous source");
+                parse_reports.push(cached.parse_report);
+                input_measure.tick(&format!("{input_name}: shared input"));
+                (cached.playlistgroups, vec![], cached.epg, vec![])
             } else {
-                (None, vec![])
+                let mut parse_report = InputParseReport::new(input);
+                let (playlistgroups, error_list) = match input.input_type {
+                    InputType::M3u => download::get_m3u_playlist(Arc::clone(&client), &cfg, input, &cfg.working_dir, &mut parse_report).await,
+                    InputType::Xtream => download::get_xtream_playlist(Arc::clone(&client), &cfg, input, &cfg.working_dir, &mut parse_report).await,
+                    InputType::Local => download::get_local_playlist(input),
+                };
+                if !parse_report.skipped.is_empty() {
+                    warn!("Skipped {} malformed entries of input {input_name}", parse_report.skipped.len());
+                }
+                input_measure.tick(&format!("{input_name}: download playlist"));
+                if job.is_cancelled() {
+                    break;
+                }
+                let (tvguide, tvguide_errors) = if error_list.is_empty() {
+                    download::get_xmltv(Arc::clone(&client), &cfg, input, &cfg.working_dir).await
+                } else {
+                    (None, vec![])
+                };
+                input_measure.tick(&format!("{input_name}: download epg"));
+                match error_list.first() {
+                    Some(err) => { cfg.t_input_health.record_failure(input, cfg.messaging.as_ref(), &err.message); }
+                    None if playlistgroups.is_empty() => { cfg.t_input_health.record_failure(input, cfg.messaging.as_ref(), "Source is empty"); }
+                    None => { cfg.t_input_health.record_success(input, cfg.messaging.as_ref()); }
+                }
+                if error_list.is_empty() && tvguide_errors.is_empty() && !playlistgroups.is_empty() {
+                    input_cache.put(input, &playlistgroups, tvguide.as_ref(), &parse_report);
+                }
+                parse_reports.push(parse_report);
+                (playlistgroups, error_list, tvguide, tvguide_errors)
             };
+            source_inputs.push(input);
+            if !error_list.is_empty() || playlistgroups.is_empty() {
+                failed_inputs.insert(input.id);
+            } else if keep_previous {
+                if let Err(err) = input_write_fallback(input, &cfg.working_dir, &playlistgroups) {
+                    errors.push(err);
+                }
+            }
             errors.append(&mut error_list);
             errors.append(&mut tvguide_errors);
             let group_count = playlistgroups.len();
             let channel_count = playlistgroups.iter()
                 .map(|group| group.channels.len())
                 .sum();
-            let input_name = input.name.as_ref().map_or_else(|| mask_sensitive_info(input.url.as_str()), std::string::ToString::to_string);
             if playlistgroups.is_empty() {
                 info!("Source is empty {input_name}");
                 errors.push(notify_err!(format!("Source is empty {input_name}")));
+                if keep_previous {
+                    source_playlists.push(FetchedPlaylist { input, playlistgroups, epg: None });
+                }
             } else {
                 playlistgroups.iter_mut().for_each(PlaylistGroup::on_load);
                 source_playlists.push(
@@ -333,23 +439,74 @@ async fn process_source(client: Arc<reqwest::Client>, cfg: Arc<Config>, source_i
                 );
             }
             let elapsed = start_time.elapsed().as_secs();
+            finish_progress(&input_name, &format!("{channel_count} items in {group_count} groups"));
             input_stats.insert(input_id, create_input_stat(group_count, channel_count, error_list.len(),
                                                            input.input_type.clone(), &input_name, elapsed));
         }
     }
-    if source_playlists.is_empty() {
+    if job.is_cancelled() {
+        for target in source.targets.iter().filter(|target| is_target_enabled(target, &user_targets)) {
+            target_stats.push(cancel_target(&cfg, target, &mut errors));
+        }
+    } else if preflight_failed {
+        for target in source.targets.iter().filter(|target| is_target_enabled(target, &user_targets)) {
+            target_stats.push(TargetStats::failure(&target.name, 0));
+        }
+    } else if source_playlists.is_empty() {
         debug!("Source at index {source_idx} is empty");
         errors.push(notify_err!(format!("Source at {source_idx} is empty")));
     } else {
         debug_if_enabled!("Source has {} groups", source_playlists.iter().map(|fpl| fpl.playlistgroups.len()).sum::<usize>());
         for target in &source.targets {
             if is_target_enabled(target, &user_targets) {
-                match process_playlist_for_target(Arc::clone(&client), &mut source_playlists, target, &cfg, &mut input_stats, &mut errors).await {
-                    Ok(()) => {
-                        target_stats.push(TargetStats::success(&target.name));
+                if job.is_cancelled() {
+                    target_stats.push(cancel_target(&cfg, target, &mut errors));
+                    continue;
+                }
+                let policy = get_input_failure_policy(target);
+                if policy == InputFailurePolicy::Abort && !failed_inputs.is_empty() {
+                    warn!("Target {} is not updated, {} of its inputs failed", target.name, failed_inputs.len());
+                    errors.push(notify_err!(format!("Target {} is not updated because of failed inputs", target.name)));
+                    let mut stats = TargetStats::failure(&target.name, 0);
+                    stats.inputs = get_target_input_stats(&source_inputs, &failed_inputs, &source_playlists, &HashMap::new());
+                    target_stats.push(stats);
+                    continue;
+                }
+                let (replaced, fallbacks) = if policy == InputFailurePolicy::KeepPrevious {
+                    apply_input_fallbacks(&cfg, target, &mut source_playlists, &failed_inputs)
+                } else {
+                    (vec![], HashMap::new())
+                };
+                let target_inputs = get_target_input_stats(&source_inputs, &failed_inputs, &source_playlists, &fallbacks);
+                if source_playlists.iter().all(|fpl| fpl.playlistgroups.is_empty()) {
+                    errors.push(notify_err!(format!("Source at {source_idx} is empty")));
+                    restore_input_fallbacks(&mut source_playlists, replaced);
+                    continue;
+                }
+                let mut measure = StepMeasure::new();
+                let result = process_playlist_for_target(Arc::clone(&client), &mut source_playlists, target, &cfg, &mut input_stats, &mut errors, &mut measure, job).await;
+                restore_input_fallbacks(&mut source_playlists, replaced);
+                let secs_took = u64::try_from(measure.elapsed_millis() / 1000).unwrap_or(u64::MAX);
+                persist_timing_report(&cfg, target, input_measure.steps(), measure, &mut errors);
+                persist_parser_report(&cfg, target, &parse_reports, &mut errors);
+                match result {
+                    Ok((truncated, violations, channels)) => {
+                        finish_progress(&target.name, &format!("done in {}", format_elapsed_time(secs_took)));
+                        let mut stats = TargetStats::success(&target.name, secs_took);
+                        stats.truncated = truncated;
+                        stats.violations = violations;
+                        stats.channels = Some(channels);
+                        stats.inputs = target_inputs;
+                        target_stats.push(stats);
+                    }
+                    Err(_) if job.is_cancelled() => {
+                        target_stats.push(cancel_target(&cfg, target, &mut errors));
                     }
                     Err(mut err) => {
-                        target_stats.push(TargetStats::failure(&target.name));
+                        finish_progress(&target.name, &format!("failed after {}", format_elapsed_time(secs_took)));
+                        let mut stats = TargetStats::failure(&target.name, secs_took);
+                        stats.inputs = target_inputs;
+                        target_stats.push(stats);
                         errors.append(&mut err);
                     }
                 }
@@ -359,6 +516,79 @@ async fn process_source(client: Arc<reqwest::Client>, cfg: Arc<Config>, source_i
     (input_stats.into_values().collect(), target_stats, errors)
 }
 
+/// The target of a cancelled job is not updated, the served files are kept.
+fn cancel_target(cfg: &Config, target: &ConfigTarget, errors: &mut Vec<M3uFilterError>) -> TargetStats {
+    let removed = target_remove_staged_files(cfg, &target.name);
+    if removed > 0 {
+        debug!("Removed {removed} staging files of target {}", target.name);
+    }
+    finish_progress(&target.name, "cancelled");
+    errors.push(info_err!(format!("Processing of target {} cancelled", target.name)));
+    TargetStats::failure(&target.name, 0)
+}
+
+fn get_input_failure_policy(target: &ConfigTarget) -> InputFailurePolicy {
+    target.options.as_ref().map_or_else(InputFailurePolicy::default, |options| options.input_failure)
+}
+
+fn get_input_name(input: &ConfigInput) -> String {
+    input.name.as_ref().map_or_else(|| mask_sensitive_info(input.url.as_str()), std::string::ToString::to_string)
+}
+
+// the replaced playlists by their index in the playlists of the source
+type ReplacedPlaylists = Vec<(usize, Vec<PlaylistGroup>)>;
+
+/// Replaces the playlists of the failed inputs with the playlist of their last successful update.
+/// Returns the replaced playlists, which are restored for the next target, and the timestamps of the used playlists.
+fn apply_input_fallbacks(cfg: &Config, target: &ConfigTarget, playlists: &mut [FetchedPlaylist], failed_inputs: &HashSet<u16>)
+                         -> (ReplacedPlaylists, HashMap<u16, i64>) {
+    let mut replaced = vec![];
+    let mut fallbacks = HashMap::new();
+    for (index, fpl) in playlists.iter_mut().enumerate().filter(|(_, fpl)| failed_inputs.contains(&fpl.input.id)) {
+        let input_name = get_input_name(fpl.input);
+        match input_read_fallback(fpl.input, &cfg.working_dir) {
+            Some((timestamp, mut playlistgroups)) => {
+                info!("Target {} uses the previous playlist of the failed input {input_name}", target.name);
+                playlistgroups.iter_mut().for_each(PlaylistGroup::on_load);
+                replaced.push((index, std::mem::replace(&mut fpl.playlistgroups, playlistgroups)));
+                fallbacks.insert(fpl.input.id, timestamp);
+            }
+            None => warn!("No previous playlist of the failed input {input_name} for target {}", target.name),
+        }
+    }
+    (replaced, fallbacks)
+}
+
+fn restore_input_fallbacks(playlists: &mut [FetchedPlaylist], replaced: ReplacedPlaylists) {
+    for (index, playlistgroups) in replaced {
+        if let Some(fpl) = playlists.get_mut(index) {
+            fpl.playlistgroups = playlistgroups;
+        }
+    }
+}
+
+fn get_target_input_stats(inputs: &[&ConfigInput], failed_inputs: &HashSet<u16>, playlists: &[FetchedPlaylist],
+                          fallbacks: &HashMap<u16, i64>) -> Vec<TargetInputStats> {
+    inputs.iter().map(|input| {
+        let timestamp = fallbacks.get(&input.id).copied();
+        let contribution = if !failed_inputs.contains(&input.id) {
+            InputContribution::Current
+        } else if timestamp.is_some() {
+            InputContribution::Previous
+        } else if playlists.iter().any(|fpl| fpl.input.id == input.id && !fpl.playlistgroups.is_empty()) {
+            InputContribution::Partial
+        } else {
+            InputContribution::Missing
+        };
+        TargetInputStats { name: get_input_name(input), contribution, timestamp }
+    }).collect()
+}
+
+fn preflight_error(input: &ConfigInput, err: &str) -> M3uFilterError {
+    let input_name = input.name.as_ref().map_or_else(|| mask_sensitive_info(input.url.as_str()), std::string::ToString::to_string);
+    notify_err!(format!("Preflight checks of input {input_name} failed: {err}"))
+}
+
 fn create_input_stat(group_count: usize, channel_count: usize, error_count: usize, input_type: InputType, input_name: &str, secs_took: u64) -> InputStats {
     InputStats {
         name: input_name.to_string(),
@@ -376,7 +606,24 @@ fn create_input_stat(group_count: usize, channel_count: usize, error_count: usiz
     }
 }
 
-async fn process_sources(client: Arc<reqwest::Client>, config: Arc<Config>, user_targets: Arc<ProcessTargets>) -> (Vec<SourceStats>, Vec<M3uFilterError>) {
+async fn process_source_group(client: Arc<reqwest::Client>, cfg: Arc<Config>, source_indices: &[usize], user_targets: Arc<ProcessTargets>,
+                              stats: Arc<Mutex<Vec<SourceStats>>>, errors: Arc<Mutex<Vec<M3uFilterError>>>, job: Arc<ProcessingJob>) {
+    let mut input_cache = InputRunCache::new(&cfg, source_indices);
+    for &index in source_indices {
+        // We're using the file lock this way on purpose
+        let source_lock_path = PathBuf::from(format!("source_{index}"));
+        let Ok(update_lock) = cfg.file_locks.try_write_lock(&source_lock_path).await else {
+            warn!("The update operation for the source at index {index} was skipped because an update is already in progress.");
+            continue;
+        };
+        let (input_stats, target_stats, mut res_errors) = process_source(Arc::clone(&client), Arc::clone(&cfg), index, Arc::clone(&user_targets), &mut input_cache, &job).await;
+        errors.lock().await.append(&mut res_errors);
+        stats.lock().await.push(SourceStats::new(input_stats, target_stats));
+        drop(update_lock);
+    }
+}
+
+async fn process_sources(client: Arc<reqwest::Client>, config: Arc<Config>, user_targets: Arc<ProcessTargets>, job: &Arc<ProcessingJob>) -> (Vec<SourceStats>, Vec<M3uFilterError>) {
     let mut handle_list = vec![];
     let thread_num = config.threads;
     let process_parallel = thread_num > 1 && config.sources.len() > 1;
@@ -385,27 +632,19 @@ async fn process_sources(client: Arc<reqwest::Client>, config: Arc<Config>, user
     }
     let errors = Arc::new(Mutex::<Vec<M3uFilterError>>::new(vec![]));
     let stats = Arc::new(Mutex::<Vec<SourceStats>>::new(vec![]));
-    for (index, _) in config.sources.iter().enumerate() {
-        // We're using the file lock this way on purpose
-        let source_lock_path = PathBuf::from(format!("source_{index}"));
-        let Ok(update_lock) = config.file_locks.try_write_lock(&source_lock_path).await else {
-            warn!("The update operation for the source at index {index} was skipped because an update is already in progress.");
-            continue;
-        };
-
+    // sources sharing an input are processed in order, the parsed input is downloaded once
+    for source_indices in group_sources_by_input(&config) {
         let shared_errors = errors.clone();
         let shared_stats = stats.clone();
         let cfg = config.clone();
         let usr_trgts = user_targets.clone();
+        let source_job = Arc::clone(job);
         if process_parallel {
             let http_client = Arc::clone(&client);
             let handles = &mut handle_list;
             let process = move || {
                 System::new().block_on(async {
-                    let (input_stats, target_stats, mut res_errors) = process_source(Arc::clone(&http_client), cfg, index, usr_trgts).await;
-                    shared_errors.lock().await.append(&mut res_errors);
-                    let process_stats = SourceStats::new(input_stats, target_stats);
-                    shared_stats.lock().await.push(process_stats);
+                    process_source_group(http_client, cfg, &source_indices, usr_trgts, shared_stats, shared_errors, source_job).await;
                 });
             };
             handles.push(thread::spawn(process));
@@ -413,12 +652,8 @@ async fn process_sources(client: Arc<reqwest::Client>, config: Arc<Config>, user
                 handles.drain(..).for_each(|handle| { let _ = handle.join(); });
             }
         } else {
-            let (input_stats, target_stats, mut res_errors) = process_source(Arc::clone(&client), cfg, index, usr_trgts).await;
-            shared_errors.lock().await.append(&mut res_errors);
-            let process_stats = SourceStats::new(input_stats, target_stats);
-            shared_stats.lock().await.push(process_stats);
+            process_source_group(Arc::clone(&client), cfg, &source_indices, usr_trgts, shared_stats, shared_errors, source_job).await;
         }
-        drop(update_lock);
     }
     for handle in handle_list {
         let _ = handle.join();
@@ -426,24 +661,39 @@ async fn process_sources(client: Arc<reqwest::Client>, config: Arc<Config>, user
     (Arc::try_unwrap(stats).unwrap().into_inner(), Arc::try_unwrap(errors).unwrap().into_inner())
 }
 
-pub type ProcessingPipe = Vec<fn(playlist: &mut [PlaylistGroup], target: &ConfigTarget) -> Option<Vec<PlaylistGroup>>>;
+const STEP_FILTER: &str = "filter";
+const STEP_RENAME: &str = "rename";
+const STEP_MAP: &str = "map";
+const STEP_PLUGINS: &str = "plugins";
+
+pub type ProcessingStep = fn(playlist: &mut [PlaylistGroup], target: &ConfigTarget) -> Option<Vec<PlaylistGroup>>;
+/// The steps of the processing order with their names for the step measure.
+pub type ProcessingPipe = Vec<(&'static str, ProcessingStep)>;
 
 fn get_processing_pipe(target: &ConfigTarget) -> ProcessingPipe {
-    match &target.processing_order {
-        ProcessingOrder::Frm => vec![filter_playlist, rename_playlist, map_playlist],
-        ProcessingOrder::Fmr => vec![filter_playlist, map_playlist, rename_playlist],
-        ProcessingOrder::Rfm => vec![rename_playlist, filter_playlist, map_playlist],
-        ProcessingOrder::Rmf => vec![rename_playlist, map_playlist, filter_playlist],
-        ProcessingOrder::Mfr => vec![map_playlist, filter_playlist, rename_playlist],
-        ProcessingOrder::Mrf => vec![map_playlist, rename_playlist, filter_playlist]
+    let filter: (&'static str, ProcessingStep) = (STEP_FILTER, filter_playlist);
+    let rename: (&'static str, ProcessingStep) = (STEP_RENAME, rename_playlist);
+    let map: (&'static str, ProcessingStep) = (STEP_MAP, map_playlist);
+    let mut pipe: ProcessingPipe = match &target.processing_order {
+        ProcessingOrder::Frm => vec![filter, rename, map],
+        ProcessingOrder::Fmr => vec![filter, map, rename],
+        ProcessingOrder::Rfm => vec![rename, filter, map],
+        ProcessingOrder::Rmf => vec![rename, map, filter],
+        ProcessingOrder::Mfr => vec![map, filter, rename],
+        ProcessingOrder::Mrf => vec![map, rename, filter]
+    };
+    if target.t_plugins.is_some() {
+        pipe.push((STEP_PLUGINS, plugin_playlist));
     }
+    pipe
 }
 
 fn duplicate_hash(item: &PlaylistItem) -> UUIDType {
     hash_string(&item.get_provider_url())
 }
 
-fn execute_pipe<'a>(target: &ConfigTarget, pipe: &ProcessingPipe, fpl: &FetchedPlaylist<'a>, duplicates: &mut HashSet<UUIDType>) -> FetchedPlaylist<'a> {
+fn execute_pipe<'a>(target: &ConfigTarget, pipe: &ProcessingPipe, fpl: &FetchedPlaylist<'a>, duplicates: &mut HashSet<UUIDType>,
+                    mut mapping_report: Option<&mut MappingReport>, measure: &mut StepMeasure) -> FetchedPlaylist<'a> {
     let mut new_fpl = FetchedPlaylist {
         input: fpl.input,
         playlistgroups: fpl.playlistgroups.clone(), // we need to clone, because of multiple target definitions, we cant change the initial playlist.
@@ -456,10 +706,22 @@ fn execute_pipe<'a>(target: &ConfigTarget, pipe: &ProcessingPipe, fpl: &FetchedP
         }
     }
 
-    for f in pipe {
+    let input_name = fpl.input.name.as_ref().map_or_else(|| fpl.input.id.to_string(), ToString::to_string);
+    if let Some(feed_tags) = target.options.as_ref().and_then(|opt| opt.feed_tags.as_ref()) {
+        tag_feeds(&mut new_fpl.playlistgroups, feed_tags);
+        measure.tick(&format!("{input_name}: feed tags"));
+    }
+    for (step, f) in pipe {
+        if *step == STEP_MAP {
+            if let Some(report) = mapping_report.as_deref_mut() {
+                report_mapping(report, &input_name, &new_fpl.playlistgroups, target);
+                measure.tick(&format!("{input_name}: mapping report"));
+            }
+        }
         if let Some(groups) = f(&mut new_fpl.playlistgroups, target) {
             new_fpl.playlistgroups = groups;
         }
+        measure.tick(&format!("{input_name}: {step}"));
     }
     new_fpl
 }
@@ -486,40 +748,68 @@ fn flatten_groups(playlistgroups: Vec<PlaylistGroup>) -> Vec<PlaylistGroup> {
     sort_order
 }
 
+/// Returns the truncated channels, the validation violations and the channel count of the written playlist.
+/// A cancelled job stops between the stages, before the playlist is persisted.
+#[allow(clippy::too_many_arguments)]
 async fn process_playlist_for_target(client: Arc<reqwest::Client>,
                                      playlists: &mut [FetchedPlaylist<'_>],
                                      target: &ConfigTarget,
                                      cfg: &Config,
                                      stats: &mut HashMap<u16, InputStats>,
-                                     errors: &mut Vec<M3uFilterError>) -> Result<(), Vec<M3uFilterError>> {
+                                     errors: &mut Vec<M3uFilterError>,
+                                     measure: &mut StepMeasure,
+                                     job: &ProcessingJob) -> Result<(Option<usize>, Option<usize>, usize), Vec<M3uFilterError>> {
     let pipe = get_processing_pipe(target);
     debug_if_enabled!("Processing order is {}", &target.processing_order);
 
     let mut duplicates: HashSet<UUIDType> = HashSet::new();
     let mut processed_fetched_playlists: Vec<FetchedPlaylist> = vec![];
-    for provider_fpl in playlists.iter_mut() {
-        let mut processed_fpl = execute_pipe(target, &pipe, provider_fpl, &mut duplicates);
-        playlist_resolve_series(Arc::clone(&client), cfg, target, errors, &pipe, provider_fpl, &mut processed_fpl).await;
-        playlist_resolve_vod(Arc::clone(&client), cfg, target, errors, &processed_fpl).await;
+    let mut mapping_report = if target.t_mapping.is_some() && target.options.as_ref().is_some_and(|opt| opt.mapping_report) {
+        Some(MappingReport::new(target))
+    } else {
+        None
+    };
+    let input_count = playlists.len();
+    let mut processed_items = 0;
+    for (input_idx, provider_fpl) in playlists.iter_mut().enumerate() {
+        if job.is_cancelled() {
+            return Err(vec![]);
+        }
+        update_progress(&target.name, format!("processing input {}/{input_count}, {processed_items} items", input_idx + 1));
+        let input_name = provider_fpl.input.name.as_ref().map_or_else(|| provider_fpl.input.id.to_string(), ToString::to_string);
+        let mut processed_fpl = execute_pipe(target, &pipe, provider_fpl, &mut duplicates, mapping_report.as_mut(), measure);
+        playlist_resolve_series(Arc::clone(&client), cfg, target, errors, &pipe, provider_fpl, &mut processed_fpl, job).await;
+        measure.tick(&format!("{input_name}: resolve series"));
+        playlist_resolve_vod(Arc::clone(&client), cfg, target, errors, &processed_fpl, job).await;
+        measure.tick(&format!("{input_name}: resolve vod"));
         // stats
+        let channel_count = processed_fpl.playlistgroups.iter().map(|group| group.channels.len()).sum::<usize>();
+        processed_items += channel_count;
         let input_stats = stats.get_mut(&processed_fpl.input.id);
         if let Some(stat) = input_stats {
             stat.processed_stats.group_count = processed_fpl.playlistgroups.len();
-            stat.processed_stats.channel_count = processed_fpl.playlistgroups.iter()
-                .map(|group| group.channels.len())
-                .sum();
+            stat.processed_stats.channel_count = channel_count;
         }
         processed_fetched_playlists.push(processed_fpl);
     }
 
+    if let Some(report) = mapping_report {
+        persist_mapping_report(cfg, target, &report, errors);
+    }
+
     apply_affixes(&mut processed_fetched_playlists);
+    measure.tick("affixes");
 
     let mut new_playlist = vec![];
     let mut new_epg = vec![];
 
     // each fetched playlist can have its own epgl url.
     // we need to process each input epg.
+    let epg_enrich_channels = target.options.as_ref().is_some_and(|opt| opt.epg_enrich_channels);
     for mut fp in processed_fetched_playlists {
+        if let (true, Some(tv_guide)) = (epg_enrich_channels, fp.epg.as_ref()) {
+            enrich_from_epg(&mut fp.playlistgroups, &tv_guide.get_channels());
+        }
         // collect all epg_channel ids
         let epg_channel_ids: HashSet<_> = fp.playlistgroups.iter().flat_map(|g| &g.channels)
             .filter_map(|c| c.header.borrow().epg_channel_id.clone()).collect();
@@ -529,24 +819,162 @@ async fn process_playlist_for_target(client: Arc<reqwest::Client>,
             debug_if_enabled!("channel ids are empty");
         } else if let Some(tv_guide) = fp.epg {
             debug!("found epg information for {}", &target.name);
-            if let Some(epg) = tv_guide.filter(&epg_channel_ids) {
+            if let Some(mut epg) = tv_guide.filter(&epg_channel_ids) {
+                if fp.input.has_quirk(ProviderQuirk::FixNegativeEpgOffset) {
+                    fix_negative_epg_offsets(&mut epg);
+                }
                 new_epg.push(epg);
             }
         }
     }
+    measure.tick("epg filter");
 
     if new_playlist.is_empty() {
         info!("Playlist is empty: {}", &target.name);
-        Ok(())
+        Ok((None, None, 0))
     } else {
         let mut flat_new_playlist = flatten_groups(new_playlist);
+        update_progress(&target.name, format!("writing {processed_items} items"));
+        let merged_episodes = if target.options.as_ref().is_some_and(|opt| opt.merge_series) {
+            let merged_episodes = merge_series(&mut flat_new_playlist);
+            measure.tick("merge series");
+            Some(merged_episodes)
+        } else {
+            None
+        };
         sort_playlist(target, &mut flat_new_playlist);
+        if let Some(feed_tags) = target.options.as_ref().and_then(|opt| opt.feed_tags.as_ref()).filter(|feed_tags| feed_tags.group_variants) {
+            group_feed_variants(&mut flat_new_playlist, feed_tags);
+        }
+        measure.tick("sort");
+        let truncated = apply_max_channels(target, &mut flat_new_playlist).map_err(|err| vec![err])?;
         map_playlist_counter(target, &flat_new_playlist);
         process_watch(target, cfg, &flat_new_playlist);
-        persist_playlist(&mut flat_new_playlist, flatten_tvguide(&new_epg).as_ref(), target, cfg).await
+        measure.tick("counter and watch");
+        let mut target_epg = flatten_tvguide(&new_epg);
+        if let (Some(epg), Some(epg_options)) = (target_epg.as_mut(), target.options.as_ref().and_then(|options| options.epg.as_ref())) {
+            apply_epg_options(epg, epg_options);
+            measure.tick("epg options");
+        }
+        if job.is_cancelled() {
+            return Err(vec![]);
+        }
+        let violations = target.validation.as_ref().map(|rules| {
+            let violations = validate_target(&flat_new_playlist, target_epg.as_ref(), rules);
+            measure.tick("validation");
+            persist_validation_report(cfg, target, violations, errors)
+        });
+        let violated = violations.is_some_and(|count| count > 0);
+        // held outputs are written to the preview target, the published files are kept
+        match target.t_preview.as_deref() {
+            Some(preview) if violated => {
+                persist_playlist(&mut flat_new_playlist, target_epg.as_ref(), merged_episodes.as_ref(), preview, cfg, measure).await?;
+                return Err(vec![notify_err!(format!("Target {} failed validation, the outputs are held for the preview user", target.name))]);
+            }
+            Some(preview) => {
+                persist_playlist(&mut flat_new_playlist, target_epg.as_ref(), merged_episodes.as_ref(), target, cfg, measure).await?;
+                remove_held_outputs(cfg, preview);
+            }
+            None => persist_playlist(&mut flat_new_playlist, target_epg.as_ref(), merged_episodes.as_ref(), target, cfg, measure).await?,
+        }
+        if target.validation.as_ref().is_some_and(|rules| rules.fail) && violated {
+            return Err(vec![notify_err!(format!("Target {} failed validation", target.name))]);
+        }
+        if target.options.as_ref().is_some_and(|opt| opt.cache_prefetch_categories > 0) {
+            prefetch_resources(&client, cfg, target, &flat_new_playlist);
+        }
+        let channels = flat_new_playlist.iter().map(|group| group.channels.len()).sum::<usize>();
+        Ok((truncated, violations, channels))
+    }
+}
+
+/// Applies the `channel_overflow` policy if the playlist has more than `max_channels` channels.
+/// Returns the number of removed channels.
+fn apply_max_channels(target: &ConfigTarget, playlist: &mut Vec<PlaylistGroup>) -> Result<Option<usize>, M3uFilterError> {
+    let Some(max_channels) = target.max_channels else { return Ok(None) };
+    let channel_count = playlist.iter().map(|group| group.channels.len()).sum::<usize>();
+    if channel_count <= max_channels {
+        return Ok(None);
+    }
+    match target.channel_overflow {
+        ChannelOverflowPolicy::Fail => Err(notify_err!(format!("Target {} has {channel_count} channels, max_channels is {max_channels}", target.name))),
+        ChannelOverflowPolicy::Warn => {
+            warn!("Target {} has {channel_count} channels, max_channels is {max_channels}", target.name);
+            Ok(None)
+        }
+        ChannelOverflowPolicy::Truncate => {
+            let mut remaining = max_channels;
+            for group in playlist.iter_mut() {
+                group.channels.truncate(remaining);
+                remaining -= group.channels.len();
+            }
+            playlist.retain(|group| !group.channels.is_empty());
+            let truncated = channel_count - max_channels;
+            info!("Target {} truncated by {truncated} channels to max_channels {max_channels}", target.name);
+            Ok(Some(truncated))
+        }
+    }
+}
+
+fn persist_timing_report(cfg: &Config, target: &ConfigTarget, input_steps: &[StepTiming], measure: StepMeasure, errors: &mut Vec<M3uFilterError>) {
+    let report = TimingReport {
+        target: target.name.clone(),
+        timestamp: chrono::Local::now().timestamp(),
+        took_millis: measure.elapsed_millis(),
+        inputs: input_steps.to_vec(),
+        steps: measure.into_steps(),
+    };
+    debug_if_enabled!("Timing report for {}: {}", &target.name, report);
+    if let Err(err) = write_target_report(cfg, &target.name, REPORT_TIMING, &report) {
+        errors.push(err);
+    }
+}
+
+fn persist_parser_report(cfg: &Config, target: &ConfigTarget, input_reports: &[InputParseReport], errors: &mut Vec<M3uFilterError>) {
+    let report = ParseReport {
+        target: target.name.clone(),
+        timestamp: chrono::Local::now().timestamp(),
+        inputs: input_reports.to_vec(),
+    };
+    if let Err(err) = write_target_report(cfg, &target.name, REPORT_PARSER, &report) {
+        errors.push(err);
+    }
+}
+
+fn persist_mapping_report(cfg: &Config, target: &ConfigTarget, report: &MappingReport, errors: &mut Vec<M3uFilterError>) {
+    info!("Mapping report for {}: {} of {} channels unmatched, {} of {} mappers unused",
+        &target.name, report.unmatched_channels.len(), report.channel_count, report.unused_mapper_count(), report.mappers.len());
+    if let Err(err) = write_target_report(cfg, &target.name, REPORT_MAPPING, report) {
+        errors.push(err);
     }
 }
 
+/// Returns the number of violations, each violation is reported as notification.
+/// The held outputs are outdated after the target is published.
+fn remove_held_outputs(cfg: &Config, preview: &ConfigTarget) {
+    if let Some(path) = get_target_storage_path(cfg, &preview.name).filter(|path| path.exists()) {
+        if let Err(err) = std::fs::remove_dir_all(&path) {
+            error!("Failed to remove held outputs {}: {err}", path.display());
+        }
+    }
+}
+
+fn persist_validation_report(cfg: &Config, target: &ConfigTarget, violations: Vec<ValidationViolation>, errors: &mut Vec<M3uFilterError>) -> usize {
+    let count = violations.len();
+    for violation in &violations {
+        errors.push(notify_err!(format!("Target {} violates {}: {}", target.name, violation.rule, violation.message)));
+    }
+    let report = ValidationReport {
+        target: target.name.clone(),
+        timestamp: chrono::Local::now().timestamp(),
+        violations,
+    };
+    if let Err(err) = write_target_report(cfg, &target.name, REPORT_VALIDATION, &report) {
+        errors.push(err);
+    }
+    count
+}
+
 fn process_watch(target: &ConfigTarget, cfg: &Config, new_playlist: &Vec<PlaylistGroup>) {
     if target.t_watch_re.is_some() {
         if default_as_default().eq_ignore_ascii_case(&target.name) {
@@ -562,14 +990,48 @@ fn process_watch(target: &ConfigTarget, cfg: &Config, new_playlist: &Vec<Playlis
     }
 }
 
-pub async fn exec_processing(client: Arc<reqwest::Client>, cfg: Arc<Config>, targets: Arc<ProcessTargets>) {
+/// Targets changed through the api are applied with the saved sources file,
+/// the targets to process are resolved again by name.
+fn apply_changed_sources(cfg: Arc<Config>, targets: Arc<ProcessTargets>) -> (Arc<Config>, Arc<ProcessTargets>) {
+    if !cfg.t_sources_changed.load(std::sync::atomic::Ordering::Acquire) {
+        return (cfg, targets);
+    }
+    let target_names: Option<Vec<String>> = targets.enabled.then(|| cfg.sources.iter().flat_map(|source| &source.targets)
+        .filter(|target| targets.has_target(target.id)).map(|target| target.name.clone()).collect());
+    match config_reader::reload_sources(&cfg)
+        .and_then(|reloaded| config::validate_targets(target_names.as_ref(), &reloaded.sources).map(|valid_targets| (reloaded, valid_targets))) {
+        Ok((reloaded, valid_targets)) => (Arc::new(reloaded), Arc::new(valid_targets)),
+        Err(err) => {
+            error!("Failed to apply the changed targets, processing with the running config: {err}");
+            (cfg, targets)
+        }
+    }
+}
+
+/// Returns the summary of the run, `None` if the processing was skipped.
+pub async fn exec_processing(client: Arc<reqwest::Client>, cfg: Arc<Config>, targets: Arc<ProcessTargets>) -> Option<ProcessingSummary> {
+    if cfg.t_maintenance.is_enabled() {
+        info!("Processing skipped, maintenance mode is enabled");
+        return None;
+    }
+    let (cfg, targets) = apply_changed_sources(cfg, targets);
     let start_time = Instant::now();
-    let (stats, errors) = process_sources(client, cfg.clone(), targets.clone()).await;
+    let Some(job) = cfg.t_processing_jobs.start(if targets.enabled { targets.targets.clone() } else { vec![] }) else {
+        warn!("Processing skipped, the targets are already processed by a running job");
+        return None;
+    };
+    // corrupt files found since the last update are moved aside, the update recreates them
+    quarantine_corrupt_documents(&cfg).await;
+    let (stats, errors) = process_sources(client, cfg.clone(), targets.clone(), &job).await;
+    cfg.t_processing_jobs.finish(&job);
+    if job.is_cancelled() {
+        warn!("Processing job {} was cancelled, the remaining targets are not updated", job.get_info().id);
+    }
     // log errors
     for err in &errors {
         error!("{}", err.message);
     }
-    if let Ok(stats_msg) = serde_json::to_string(&serde_json::Value::Object(serde_json::map::Map::from_iter([("stats".to_string(), serde_json::to_value(stats).unwrap())]))) {
+    if let Ok(stats_msg) = serde_json::to_string(&serde_json::Value::Object(serde_json::map::Map::from_iter([("stats".to_string(), serde_json::to_value(&stats).unwrap())]))) {
         // print stats
         info!("{}", stats_msg);
         // send stats
@@ -583,4 +1045,126 @@ pub async fn exec_processing(client: Arc<reqwest::Client>, cfg: Arc<Config>, tar
     }
     let elapsed = start_time.elapsed().as_secs();
     info!("Update process finished! Took {elapsed} secs.");
-}
\ No newline at end of file
+    let summary = ProcessingSummary::new(stats, &errors, elapsed);
+    notify_processing(&cfg, &summary).await;
+    metrics::record_processing(&summary);
+    Some(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use std::collections::HashSet;
+
+    use crate::filter::get_filter;
+    use crate::model::config::{ChannelOverflowPolicy, ConfigInput, ConfigRename, ConfigSort, ConfigSortGroup, ConfigTarget, ItemField, ProcessingOrder, SortOrder};
+    use crate::model::mapping::Mapping;
+    use crate::model::playlist::{FetchedPlaylist, PlaylistGroup, PlaylistItem, XtreamCluster};
+    use crate::model::playlist_test_utils::{group as test_group, item as test_item};
+    use crate::processing::mapping_report::MappingReport;
+    use crate::processing::playlist_processor::{apply_max_channels, execute_pipe, get_processing_pipe, sort_playlist};
+    use crate::utils::step_measure::StepMeasure;
+
+    #[test]
+    fn sort_pinned_groups_test() {
+        let mut sort = ConfigSort {
+            match_as_ascii: false,
+            groups: Some(ConfigSortGroup { order: SortOrder::Asc, pinned: Some(vec!["^News".to_string(), "(?i)sport".to_string()]), t_pinned_re: vec![] }),
+            channels: None,
+        };
+        sort.prepare().unwrap();
+        let target = ConfigTarget { sort: Some(sort), ..Default::default() };
+        let mut playlist: Vec<PlaylistGroup> = ["Movies", "Sports 2", "Kids", "News", "Sports 1"].iter().map(|title| PlaylistGroup {
+            id: 0,
+            title: Rc::new((*title).to_string()),
+            channels: vec![],
+            xtream_cluster: XtreamCluster::Live,
+        }).collect();
+        sort_playlist(&target, &mut playlist);
+        let titles: Vec<&str> = playlist.iter().map(|group| group.title.as_str()).collect();
+        assert_eq!(titles, vec!["News", "Sports 1", "Sports 2", "Kids", "Movies"]);
+    }
+
+    #[test]
+    fn mapping_report_test() {
+        let mut mapping: Mapping = serde_yaml::from_str(r#"
+id: report
+mapper:
+  - pattern: 'Name ~ "^ESPN"'
+    attributes:
+      group: Sports
+  - pattern: 'Group ~ "^Sports$"'
+    attributes:
+      logo: http://logo
+  - pattern: 'Name ~ "^BBC"'
+    attributes:
+      group: UK
+"#).unwrap();
+        mapping.prepare(None, None).unwrap();
+        let mut rename = ConfigRename { field: ItemField::Name, pattern: "^Old ".to_string(), new_name: String::new(), re: None };
+        rename.prepare().unwrap();
+        let target = ConfigTarget {
+            processing_order: ProcessingOrder::Rfm,
+            rename: Some(vec![rename]),
+            t_filter: Some(get_filter(r#"Group ~ "News""#, None).unwrap()),
+            t_mapping: Some(vec![mapping]),
+            ..Default::default()
+        };
+        let channel = |name: &str, group: &str| test_item(name).title("").group(group).build();
+        let input = ConfigInput::default();
+        let fpl = FetchedPlaylist {
+            input: &input,
+            playlistgroups: vec![test_group(1, "News", XtreamCluster::Live,
+                                            vec![channel("Old ESPN", "News"), channel("CNN", "News"), channel("Cartoons", "Kids")])],
+            epg: None,
+        };
+        let mut report = MappingReport::new(&target);
+        let mut measure = StepMeasure::new();
+        execute_pipe(&target, &get_processing_pipe(&target), &fpl, &mut HashSet::new(), Some(&mut report), &mut measure);
+        assert_eq!(measure.steps().iter().map(|timing| timing.step.as_str()).collect::<Vec<_>>(),
+                   vec!["0: rename", "0: filter", "0: mapping report", "0: map"]);
+        // the channel is renamed before the mapping, the second mapper matches the output of the first one
+        assert_eq!(report.channel_count, 2);
+        assert_eq!(report.mappers.iter().map(|mapper| mapper.matches).collect::<Vec<_>>(), vec![1, 1, 0]);
+        assert_eq!(report.unmatched_channels.iter().map(|channel| channel.name.as_str()).collect::<Vec<_>>(), vec!["CNN"]);
+    }
+
+    #[test]
+    fn processing_pipe_steps_test() {
+        for processing_order in [ProcessingOrder::Frm, ProcessingOrder::Fmr, ProcessingOrder::Rfm, ProcessingOrder::Rmf, ProcessingOrder::Mfr, ProcessingOrder::Mrf] {
+            let target = ConfigTarget { processing_order, ..Default::default() };
+            let steps: Vec<&str> = get_processing_pipe(&target).iter().map(|(step, _)| *step).collect();
+            assert_eq!(steps.join(", "), target.processing_order.to_string());
+        }
+        let target = ConfigTarget { processing_order: ProcessingOrder::Mrf, t_plugins: Some(vec![]), ..Default::default() };
+        let steps: Vec<&str> = get_processing_pipe(&target).iter().map(|(step, _)| *step).collect();
+        assert_eq!(steps, vec!["map", "rename", "filter", "plugins"]);
+    }
+
+    #[test]
+    fn max_channels_test() {
+        let create_playlist = || -> Vec<PlaylistGroup> {
+            [("News", 2), ("Sports", 3), ("Kids", 1)].iter().map(|(title, count)| PlaylistGroup {
+                id: 0,
+                title: Rc::new((*title).to_string()),
+                channels: (0..*count).map(|_| PlaylistItem { header: RefCell::new(Default::default()) }).collect(),
+                xtream_cluster: XtreamCluster::Live,
+            }).collect()
+        };
+        let mut target = ConfigTarget { max_channels: Some(4), ..Default::default() };
+        let mut playlist = create_playlist();
+        assert_eq!(apply_max_channels(&target, &mut playlist).unwrap(), Some(2));
+        let counts: Vec<(&str, usize)> = playlist.iter().map(|group| (group.title.as_str(), group.channels.len())).collect();
+        assert_eq!(counts, vec![("News", 2), ("Sports", 2)]);
+
+        target.channel_overflow = ChannelOverflowPolicy::Warn;
+        let mut playlist = create_playlist();
+        assert_eq!(apply_max_channels(&target, &mut playlist).unwrap(), None);
+        assert_eq!(playlist.len(), 3);
+
+        target.channel_overflow = ChannelOverflowPolicy::Fail;
+        assert!(apply_max_channels(&target, &mut playlist).is_err());
+    }
+}
@@ -0,0 +1,240 @@
+use std::sync::Arc;
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::model::config::{Config, ConfigInput, InputType};
+use crate::model::playlist::XtreamCluster;
+use crate::processing::{local_parser, m3u_parser};
+use crate::processing::parse_report::InputParseReport;
+use crate::utils::download::{get_skip_cluster, get_xtream_base_url, get_xtream_player_api_info_url, ACTIONS};
+use crate::utils::request_utils;
+use crate::utils::request_utils::mask_sensitive_info;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "status", content = "message", rename_all = "lowercase")]
+pub enum SelftestStatus {
+    Pass,
+    Fail(String),
+    Skip,
+}
+
+impl SelftestStatus {
+    fn label(&self) -> &'static str {
+        match self {
+            Self::Pass => "pass",
+            Self::Fail(_) => "FAIL",
+            Self::Skip => "-",
+        }
+    }
+}
+
+/// Results of the self test of one input, nothing is persisted.
+#[derive(Debug, Clone, Serialize)]
+pub struct InputSelftest {
+    pub input: String,
+    pub input_type: InputType,
+    pub connect: SelftestStatus,
+    pub live: SelftestStatus,
+    pub vod: SelftestStatus,
+    pub series: SelftestStatus,
+    pub info: SelftestStatus,
+}
+
+impl InputSelftest {
+    fn new(input: &ConfigInput) -> Self {
+        Self {
+            input: input.name.as_ref().map_or_else(|| mask_sensitive_info(&input.url), ToString::to_string),
+            input_type: input.input_type.clone(),
+            connect: SelftestStatus::Skip,
+            live: SelftestStatus::Skip,
+            vod: SelftestStatus::Skip,
+            series: SelftestStatus::Skip,
+            info: SelftestStatus::Skip,
+        }
+    }
+
+    fn checks(&self) -> [(&'static str, &SelftestStatus); 5] {
+        [("connect", &self.connect), ("live", &self.live), ("vod", &self.vod), ("series", &self.series), ("info", &self.info)]
+    }
+
+    fn cluster_mut(&mut self, cluster: XtreamCluster) -> &mut SelftestStatus {
+        match cluster {
+            XtreamCluster::Live => &mut self.live,
+            XtreamCluster::Video => &mut self.vod,
+            XtreamCluster::Series => &mut self.series,
+        }
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.checks().iter().all(|(_, status)| !matches!(status, SelftestStatus::Fail(_)))
+    }
+}
+
+fn get_id(value: &Value, field: &str) -> Option<String> {
+    match value.get(field)? {
+        Value::String(id) => Some(id.clone()),
+        Value::Number(id) => Some(id.to_string()),
+        _ => None,
+    }
+}
+
+fn is_authenticated(value: &Value) -> bool {
+    value.get("user_info").and_then(|user_info| user_info.get("auth"))
+        .is_some_and(|auth| auth.as_i64() == Some(1) || auth.as_str() == Some("1"))
+}
+
+async fn get_json_array(client: &Arc<reqwest::Client>, input: &ConfigInput, url: &str) -> Result<Vec<Value>, String> {
+    match request_utils::get_input_json_content(Arc::clone(client), input, url, None).await {
+        Ok(Value::Array(entries)) => Ok(entries),
+        Ok(_) => Err("Unexpected response, no list".to_string()),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+/// Fetches the categories and the streams of the first category of a cluster.
+/// Returns the status and the provider id of the first stream.
+async fn test_xtream_cluster(client: &Arc<reqwest::Client>, input: &ConfigInput, base_url: &str,
+                             category_action: &str, stream_action: &str, id_field: &str) -> (SelftestStatus, Option<String>) {
+    let categories = match get_json_array(client, input, &format!("{base_url}&action={category_action}")).await {
+        Ok(categories) => categories,
+        Err(err) => return (SelftestStatus::Fail(err), None),
+    };
+    let Some(category_id) = categories.first().and_then(|category| get_id(category, "category_id")) else {
+        return (SelftestStatus::Pass, None);
+    };
+    match get_json_array(client, input, &format!("{base_url}&action={stream_action}&category_id={category_id}")).await {
+        Ok(streams) => (SelftestStatus::Pass, streams.first().and_then(|stream| get_id(stream, id_field))),
+        Err(err) => (SelftestStatus::Fail(err), None),
+    }
+}
+
+async fn test_xtream_input(client: &Arc<reqwest::Client>, input: &ConfigInput) -> InputSelftest {
+    let mut result = InputSelftest::new(input);
+    let base_url = get_xtream_base_url(input);
+    result.connect = match request_utils::get_input_json_content(Arc::clone(client), input, &base_url, None).await {
+        Ok(value) if is_authenticated(&value) => SelftestStatus::Pass,
+        Ok(_) => SelftestStatus::Fail("Authentication failed".to_string()),
+        Err(err) => SelftestStatus::Fail(err.to_string()),
+    };
+    if result.connect != SelftestStatus::Pass {
+        return result;
+    }
+    let skip_cluster = get_skip_cluster(input);
+    let mut info_sample = None;
+    for (cluster, category_action, stream_action) in &ACTIONS {
+        if skip_cluster.contains(cluster) {
+            continue;
+        }
+        let id_field = if *cluster == XtreamCluster::Series { "series_id" } else { "stream_id" };
+        let (status, sample_id) = test_xtream_cluster(client, input, &base_url, category_action, stream_action, id_field).await;
+        *result.cluster_mut(*cluster) = status;
+        if *cluster != XtreamCluster::Live && info_sample.is_none() {
+            info_sample = sample_id.and_then(|id| id.parse::<u32>().ok()).map(|id| (*cluster, id));
+        }
+    }
+    if let Some(info_url) = info_sample.and_then(|(cluster, id)| get_xtream_player_api_info_url(input, cluster, id)) {
+        result.info = match request_utils::get_input_json_content(Arc::clone(client), input, &info_url, None).await {
+            Ok(Value::Object(_)) => SelftestStatus::Pass,
+            Ok(_) => SelftestStatus::Fail("Unexpected response, no info document".to_string()),
+            Err(err) => SelftestStatus::Fail(err.to_string()),
+        };
+    }
+    result
+}
+
+async fn test_m3u_input(client: &Arc<reqwest::Client>, cfg: &Config, input: &ConfigInput) -> InputSelftest {
+    let mut result = InputSelftest::new(input);
+    let content = match request_utils::download_text_content(Arc::clone(client), input, &input.url, None).await {
+        Ok(content) => content,
+        Err(err) => {
+            result.connect = SelftestStatus::Fail(mask_sensitive_info(&err.to_string()));
+            return result;
+        }
+    };
+    result.connect = SelftestStatus::Pass;
+    let mut parse_report = InputParseReport::new(input);
+    match m3u_parser::parse_m3u(cfg, input, content.lines(), &mut parse_report) {
+        Ok(playlist) if playlist.is_empty() => result.live = SelftestStatus::Fail("Playlist has no entries".to_string()),
+        Ok(playlist) => {
+            for group in &playlist {
+                *result.cluster_mut(group.xtream_cluster) = SelftestStatus::Pass;
+            }
+        }
+        Err(err) => result.live = SelftestStatus::Fail(err.to_string()),
+    }
+    result
+}
+
+fn test_local_input(input: &ConfigInput) -> InputSelftest {
+    let mut result = InputSelftest::new(input);
+    match local_parser::parse_local_directory(input) {
+        Ok(playlist) => {
+            result.connect = SelftestStatus::Pass;
+            result.vod = if playlist.is_empty() { SelftestStatus::Fail("Directory has no video files".to_string()) } else { SelftestStatus::Pass };
+        }
+        Err(err) => result.connect = SelftestStatus::Fail(err.to_string()),
+    }
+    result
+}
+
+/// Tests the connectivity of all enabled inputs without processing the targets.
+pub async fn run_selftest(client: Arc<reqwest::Client>, cfg: &Config) -> Vec<InputSelftest> {
+    let mut results = vec![];
+    for input in cfg.sources.iter().flat_map(|source| &source.inputs).filter(|input| input.enabled) {
+        let result = match input.input_type {
+            InputType::Xtream => test_xtream_input(&client, input).await,
+            InputType::M3u => test_m3u_input(&client, cfg, input).await,
+            InputType::Local => test_local_input(input),
+        };
+        results.push(result);
+    }
+    results
+}
+
+/// One row per input with the check results, followed by the failure messages.
+pub fn format_selftest_matrix(results: &[InputSelftest]) -> String {
+    let input_width = results.iter().map(|result| result.input.chars().count()).chain(std::iter::once("input".len())).max().unwrap_or_default();
+    let mut lines = vec![];
+    let header = ["connect", "live", "vod", "series", "info"].iter().map(|name| format!("{name:<8}")).collect::<String>();
+    lines.push(format!("{:<input_width$}  {:<7} {}", "input", "type", header.trim_end()));
+    let mut failures = vec![];
+    for result in results {
+        let row = result.checks().iter().map(|(_, status)| format!("{:<8}", status.label())).collect::<String>();
+        lines.push(format!("{:<input_width$}  {:<7} {}", result.input, result.input_type.to_string(), row.trim_end()));
+        for (check, status) in result.checks() {
+            if let SelftestStatus::Fail(message) = status {
+                failures.push(format!("{} {check}: {message}", result.input));
+            }
+        }
+    }
+    if !failures.is_empty() {
+        lines.push(String::new());
+        lines.extend(failures);
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::config::InputType;
+    use crate::processing::selftest::{format_selftest_matrix, InputSelftest, SelftestStatus};
+
+    #[test]
+    fn selftest_matrix_test() {
+        let result = InputSelftest {
+            input: "provider".to_string(),
+            input_type: InputType::Xtream,
+            connect: SelftestStatus::Pass,
+            live: SelftestStatus::Pass,
+            vod: SelftestStatus::Fail("timeout".to_string()),
+            series: SelftestStatus::Skip,
+            info: SelftestStatus::Skip,
+        };
+        assert!(!result.is_success());
+        assert_eq!(format_selftest_matrix(&[result]), "input     type    connect live    vod     series  info\n\
+                                                       provider  xtream  pass    pass    FAIL    -       -\n\
+                                                       \n\
+                                                       provider vod: timeout");
+    }
+}
@@ -0,0 +1,159 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::LazyLock;
+
+use regex::Regex;
+
+use crate::model::playlist::{PlaylistGroup, PlaylistItem, PlaylistItemHeader, PlaylistItemType, UUIDType};
+
+static YEAR_REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"[(\[]?\b(?:19|20)\d{2}\b[)\]]?").unwrap());
+static QUALITY_REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?i)\b(?:(?P<uhd>2160p|4k|uhd)|(?P<fhd>1080p|fhd)|(?P<hd>720p|hd))\b").unwrap());
+
+// (video height, bitrate)
+type EpisodeQuality = (u64, u64);
+
+/// The kept episodes of the other inputs for each kept series, series uuid -> episode uuids.
+/// They are added to the episode listing of the kept series info.
+pub type MergedEpisodes = HashMap<UUIDType, Vec<UUIDType>>;
+
+/// The series name without year, case and non-alphanumeric characters.
+fn normalize_series_name(name: &str) -> String {
+    YEAR_REGEX.replace_all(name, "").chars().filter(|c| c.is_alphanumeric()).flat_map(char::to_lowercase).collect()
+}
+
+fn get_series_name(header: &PlaylistItemHeader) -> &str {
+    if header.name.is_empty() { header.title.as_str() } else { header.name.as_str() }
+}
+
+/// Series are matched by tmdb id, if the provider has one, otherwise by the normalized name.
+fn get_series_key(header: &PlaylistItemHeader) -> String {
+    match header.get_additional_property_as_str("tmdb").filter(|tmdb| !tmdb.is_empty() && tmdb != "0") {
+        Some(tmdb) => format!("tmdb:{tmdb}"),
+        None => normalize_series_name(get_series_name(header)),
+    }
+}
+
+/// Higher is better. The video height of the episode info, otherwise the quality tag of the title, then the bitrate.
+fn get_episode_quality(header: &PlaylistItemHeader) -> EpisodeQuality {
+    let height = header.get_additional_property_as_u64("video_height").unwrap_or_else(|| {
+        QUALITY_REGEX.captures(&header.title).map_or(0, |caps| {
+            if caps.name("uhd").is_some() { 2160 } else if caps.name("fhd").is_some() { 1080 } else { 720 }
+        })
+    });
+    (height, header.get_additional_property_as_u64("bitrate").unwrap_or(0))
+}
+
+/// Merges the series of different inputs into one series entry.
+/// The first series info is kept, the series info of the other inputs are removed.
+/// Episodes with the same season and episode number are deduplicated, the episode with the best quality is kept.
+/// Returns the kept episodes of the other inputs for each kept series.
+pub fn merge_series(playlist: &mut [PlaylistGroup]) -> MergedEpisodes {
+    // (input_id, normalized name) -> series key
+    let mut series_keys: HashMap<(u16, String), String> = HashMap::new();
+    // series key -> (input_id, uuid) of the kept series info
+    let mut series_inputs: HashMap<String, (u16, UUIDType)> = HashMap::new();
+    let mut dropped_series_info: HashSet<(u16, String)> = HashSet::new();
+    for item in playlist.iter().flat_map(|group| &group.channels) {
+        let header = item.header.borrow();
+        if header.item_type == PlaylistItemType::SeriesInfo {
+            let key = get_series_key(&header);
+            let input_key = (header.input_id, normalize_series_name(get_series_name(&header)));
+            let (input_id, _) = *series_inputs.entry(key.clone()).or_insert((header.input_id, **header.get_uuid()));
+            if input_id != header.input_id {
+                dropped_series_info.insert(input_key.clone());
+            }
+            series_keys.insert(input_key, key);
+        }
+    }
+
+    // (series key, season, episode) -> (quality, group index, item index)
+    let mut best_episodes: HashMap<(String, u64, u64), (EpisodeQuality, usize, usize)> = HashMap::new();
+    for (group_idx, group) in playlist.iter().enumerate() {
+        for (item_idx, item) in group.channels.iter().enumerate() {
+            let header = item.header.borrow();
+            if header.item_type != PlaylistItemType::Series {
+                continue;
+            }
+            let (Some(season), Some(episode)) = (header.get_additional_property_as_u64("season"), header.get_additional_property_as_u64("episode")) else { continue; };
+            let name = normalize_series_name(get_series_name(&header));
+            let key = series_keys.get(&(header.input_id, name.clone())).cloned().unwrap_or(name);
+            let quality = get_episode_quality(&header);
+            best_episodes.entry((key, season, episode))
+                .and_modify(|best| if quality > best.0 { *best = (quality, group_idx, item_idx) })
+                .or_insert((quality, group_idx, item_idx));
+        }
+    }
+    let mut merged_episodes = MergedEpisodes::new();
+    let mut kept_episodes: HashSet<(usize, usize)> = HashSet::new();
+    for ((key, _, _), (_, group_idx, item_idx)) in best_episodes {
+        let header = playlist[group_idx].channels[item_idx].header.borrow();
+        if let Some((input_id, uuid)) = series_inputs.get(&key) {
+            if *input_id != header.input_id {
+                merged_episodes.entry(*uuid).or_default().push(**header.get_uuid());
+            }
+        }
+        kept_episodes.insert((group_idx, item_idx));
+    }
+
+    let is_kept = |group_idx: usize, item_idx: usize, item: &PlaylistItem| {
+        let header = item.header.borrow();
+        match header.item_type {
+            PlaylistItemType::SeriesInfo => !dropped_series_info.contains(&(header.input_id, normalize_series_name(get_series_name(&header)))),
+            PlaylistItemType::Series => kept_episodes.contains(&(group_idx, item_idx))
+                || header.get_additional_property_as_u64("season").is_none()
+                || header.get_additional_property_as_u64("episode").is_none(),
+            _ => true,
+        }
+    };
+    for (group_idx, group) in playlist.iter_mut().enumerate() {
+        let mut item_idx = 0;
+        group.channels.retain(|item| {
+            let keep = is_kept(group_idx, item_idx, item);
+            item_idx += 1;
+            keep
+        });
+    }
+    merged_episodes
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::model::playlist::{PlaylistItem, PlaylistItemType, XtreamCluster};
+    use crate::model::playlist_test_utils::{group, item as test_item};
+    use crate::processing::series_merge::merge_series;
+    use crate::repository::storage::hash_string;
+
+    fn item(input_id: u16, item_type: PlaylistItemType, name: &str, title: &str, props: serde_json::Value) -> PlaylistItem {
+        test_item(name).title(title).uuid(hash_string(&format!("{input_id}/{title}"))).item_type(item_type)
+            .cluster(XtreamCluster::Series).props(props).input_id(input_id).build()
+    }
+
+    #[test]
+    fn merge_series_test() {
+        let mut playlist = vec![group(1, "Series", XtreamCluster::Series, vec![
+                item(1, PlaylistItemType::SeriesInfo, "The Show (2020)", "The Show (2020)", json!({})),
+                item(2, PlaylistItemType::SeriesInfo, "The Show", "The Show", json!({})),
+                item(1, PlaylistItemType::Series, "The Show (2020)", "S01E01", json!({"season": 1, "episode": 1})),
+                item(2, PlaylistItemType::Series, "The Show", "S01E01 1080p", json!({"season": 1, "episode": 1})),
+                item(2, PlaylistItemType::Series, "The Show", "S01E02", json!({"season": 1, "episode": 2})),
+        ])];
+        let merged = merge_series(&mut playlist);
+        let titles: Vec<(u16, String)> = playlist[0].channels.iter().map(|item| {
+            let header = item.header.borrow();
+            (header.input_id, header.title.to_string())
+        }).collect();
+        assert_eq!(titles, vec![
+            (1, "The Show (2020)".to_string()),
+            (2, "S01E01 1080p".to_string()),
+            (2, "S01E02".to_string()),
+        ]);
+        // the episodes of the second input are listed in the kept series info of the first input
+        let mut episodes = merged.get(&hash_string("1/The Show (2020)")).cloned().unwrap_or_default();
+        episodes.sort_unstable();
+        let mut expected = vec![hash_string("2/S01E01 1080p"), hash_string("2/S01E02")];
+        expected.sort_unstable();
+        assert_eq!(merged.len(), 1);
+        assert_eq!(episodes, expected);
+    }
+}
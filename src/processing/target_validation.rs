@@ -0,0 +1,135 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::Serialize;
+
+use crate::model::config::TargetValidation;
+use crate::model::playlist::PlaylistGroup;
+use crate::model::xmltv::Epg;
+use crate::repository::m3u_playlist_iterator::is_live_stream;
+
+// the report lists only the first channels of a violation
+const MAX_VIOLATION_ITEMS: usize = 50;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ValidationViolation {
+    pub rule: String,
+    pub message: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub items: Vec<String>,
+}
+
+impl ValidationViolation {
+    fn new(rule: &str, message: String, items: Vec<String>) -> Self {
+        Self { rule: rule.to_string(), message, items: items.into_iter().take(MAX_VIOLATION_ITEMS).collect() }
+    }
+}
+
+/// Violations of the target validation rules after the outputs are written.
+#[derive(Debug, Clone, Serialize)]
+pub struct ValidationReport {
+    pub target: String,
+    pub timestamp: i64,
+    pub violations: Vec<ValidationViolation>,
+}
+
+fn get_programme_channel_ids(epg: Option<&Epg>) -> HashSet<&str> {
+    epg.map_or_else(HashSet::new, |epg| epg.children.iter()
+        .filter(|tag| tag.name == "programme")
+        .filter_map(|tag| tag.get_attribute_value("channel").map(String::as_str))
+        .collect())
+}
+
+/// Checks the live channels of the playlist against the rules.
+pub fn validate_target(playlist: &[PlaylistGroup], epg: Option<&Epg>, rules: &TargetValidation) -> Vec<ValidationViolation> {
+    let mut violations = vec![];
+    if rules.no_empty_groups {
+        let empty_groups: Vec<String> = playlist.iter().filter(|group| group.channels.is_empty()).map(|group| group.title.to_string()).collect();
+        if !empty_groups.is_empty() {
+            violations.push(ValidationViolation::new("no_empty_groups", format!("{} empty groups", empty_groups.len()), empty_groups));
+        }
+    }
+
+    let channels: Vec<_> = playlist.iter().flat_map(|group| &group.channels)
+        .map(|item| item.header.borrow())
+        .filter(|header| is_live_stream(header.item_type))
+        .collect();
+
+    if rules.unique_epg_ids {
+        let mut epg_ids: HashMap<&str, usize> = HashMap::new();
+        for header in &channels {
+            if let Some(epg_id) = header.epg_channel_id.as_ref().filter(|epg_id| !epg_id.is_empty()) {
+                *epg_ids.entry(epg_id.as_str()).or_default() += 1;
+            }
+        }
+        let mut duplicates: Vec<String> = epg_ids.into_iter().filter(|(_, count)| *count > 1).map(|(epg_id, count)| format!("{epg_id} ({count})")).collect();
+        if !duplicates.is_empty() {
+            duplicates.sort();
+            violations.push(ValidationViolation::new("unique_epg_ids", format!("{} duplicate epg ids", duplicates.len()), duplicates));
+        }
+    }
+
+    if rules.require_logos {
+        let without_logo: Vec<String> = channels.iter().filter(|header| header.logo.is_empty()).map(|header| header.name.to_string()).collect();
+        if !without_logo.is_empty() {
+            violations.push(ValidationViolation::new("require_logos", format!("{} channels without logo", without_logo.len()), without_logo));
+        }
+    }
+
+    if let Some(min_coverage) = rules.min_epg_coverage {
+        if !channels.is_empty() {
+            let programme_channel_ids = get_programme_channel_ids(epg);
+            let (covered, uncovered): (Vec<_>, Vec<_>) = channels.iter().partition(|header| header.epg_channel_id.as_ref()
+                .is_some_and(|epg_id| programme_channel_ids.contains(epg_id.as_str())));
+            let coverage = covered.len() * 100 / channels.len();
+            if coverage < usize::from(min_coverage) {
+                violations.push(ValidationViolation::new("min_epg_coverage",
+                    format!("epg coverage {coverage}% is below {min_coverage}%"),
+                    uncovered.iter().map(|header| header.name.to_string()).collect()));
+            }
+        }
+    }
+    violations
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::rc::Rc;
+
+    use crate::model::config::TargetValidation;
+    use crate::model::playlist::{PlaylistItem, XtreamCluster};
+    use crate::model::playlist_test_utils::{group, item as test_item};
+    use crate::model::xmltv::{Epg, XmlTag};
+    use crate::processing::target_validation::validate_target;
+
+    fn item(name: &str, logo: &str, epg_id: &str) -> PlaylistItem {
+        test_item(name).logo(logo).epg_id(Some(epg_id)).build()
+    }
+
+    fn programme(channel: &str) -> XmlTag {
+        XmlTag { name: "programme".to_string(), value: None, attributes: Some(Rc::new(HashMap::from([("channel".to_string(), channel.to_string())]))), children: None }
+    }
+
+    #[test]
+    fn validate_target_test() {
+        let playlist = vec![
+            group(1, "News", XtreamCluster::Live, vec![
+                item("News 1", "http://logo/1.png", "news1"),
+                item("News 2", "", "news1"),
+                item("News 3", "http://logo/3.png", "news3"),
+            ]),
+            group(2, "Empty", XtreamCluster::Live, vec![]),
+        ];
+        let epg = Epg { attributes: None, children: vec![programme("news1")] };
+        let rules = TargetValidation { unique_epg_ids: true, require_logos: true, min_epg_coverage: Some(80), no_empty_groups: true, fail: false, hold: false };
+        let violations = validate_target(&playlist, Some(&epg), &rules);
+        let rules_violated: Vec<&str> = violations.iter().map(|violation| violation.rule.as_str()).collect();
+        assert_eq!(rules_violated, vec!["no_empty_groups", "unique_epg_ids", "require_logos", "min_epg_coverage"]);
+        assert_eq!(violations[1].items, vec!["news1 (2)"]);
+        assert_eq!(violations[3].message, "epg coverage 66% is below 80%");
+        assert_eq!(violations[3].items, vec!["News 3"]);
+
+        let rules = TargetValidation { min_epg_coverage: Some(60), ..TargetValidation::default() };
+        assert!(validate_target(&playlist, Some(&epg), &rules).is_empty());
+    }
+}
@@ -0,0 +1,160 @@
+use serde::Serialize;
+
+use crate::auth::password::generate_random_string;
+use crate::m3u_filter_error::{M3uFilterError, M3uFilterErrorKind};
+use crate::model::api_proxy::{ApiProxyConfig, ProxyUserCredentials, TargetUser, TrialPreset};
+use crate::model::config::Config;
+use crate::utils::config_reader;
+use crate::create_m3u_filter_error_result;
+
+const TRIAL_USERNAME_PREFIX: &str = "trial_";
+
+/// Credentials and links of a provisioned trial user.
+#[derive(Debug, Clone, Serialize)]
+pub struct TrialUser {
+    pub username: String,
+    pub password: String,
+    pub target: String,
+    pub exp_date: i64,
+    pub max_connections: u32,
+    pub server_url: String,
+    pub m3u_url: String,
+    pub epg_url: String,
+}
+
+fn create_trial_credentials(api_proxy: &ApiProxyConfig, preset: &TrialPreset, now: i64) -> ProxyUserCredentials {
+    let username = loop {
+        let username = format!("{TRIAL_USERNAME_PREFIX}{}", generate_random_string(8).to_lowercase());
+        if api_proxy.user.iter().flat_map(|target_user| &target_user.credentials).all(|credentials| credentials.username != username) {
+            break username;
+        }
+    };
+    ProxyUserCredentials {
+        username,
+        password: generate_random_string(12),
+        token: None,
+        proxy: preset.proxy.clone(),
+        server: preset.server.clone(),
+        epg_timeshift: None,
+        provider_weight: None,
+        trace_streams: false,
+        exp_date: Some(now + i64::from(preset.duration_hours) * 3600),
+        max_connections: Some(preset.max_connections),
+        enabled: true,
+        trial: true,
+        no_log: false,
+        locale: None,
+        mac: None,
+        max_catchup_days: None,
+        t_forwarded_origin: None,
+    }
+}
+
+fn add_user(api_proxy: &mut ApiProxyConfig, target: &str, credentials: ProxyUserCredentials) {
+    match api_proxy.user.iter_mut().find(|target_user| target_user.target == target) {
+        Some(target_user) => target_user.credentials.push(credentials),
+        None => api_proxy.user.push(TargetUser { target: target.to_string(), credentials: vec![credentials] }),
+    }
+}
+
+/// Disables the expired trial users, returns the usernames of the disabled users.
+pub fn disable_expired_trial_users(api_proxy: &mut ApiProxyConfig, now: i64) -> Vec<String> {
+    api_proxy.user.iter_mut().flat_map(|target_user| &mut target_user.credentials)
+        .filter(|credentials| credentials.trial && credentials.enabled && credentials.exp_date.is_some_and(|exp_date| exp_date <= now))
+        .map(|credentials| {
+            credentials.enabled = false;
+            credentials.username.clone()
+        })
+        .collect()
+}
+
+fn save_api_proxy(cfg: &Config, api_proxy: &ApiProxyConfig) -> Result<(), M3uFilterError> {
+    let backup_dir = cfg.backup_dir.as_deref().unwrap_or(cfg.working_dir.as_str());
+    config_reader::save_api_proxy(cfg.t_api_proxy_file_path.as_str(), backup_dir, api_proxy)
+}
+
+/// Creates a trial user with the settings of the preset and saves the api-proxy file.
+pub fn provision_trial_user(cfg: &Config, preset_name: &str) -> Result<TrialUser, M3uFilterError> {
+    let mut guard = cfg.t_api_proxy.write().unwrap();
+    let Some(api_proxy) = guard.as_mut() else {
+        return create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "No api-proxy config loaded");
+    };
+    let Some(preset) = api_proxy.trial.iter().flatten().find(|preset| preset.name == preset_name).cloned() else {
+        return create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "Unknown trial preset {preset_name}");
+    };
+    // the user is added to the target and not to a renamed alias of the preset
+    let Some(target) = cfg.get_target_by_name(&preset.target) else {
+        return create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "Unknown target {} of trial preset {preset_name}", preset.target);
+    };
+    let mut changed_config = api_proxy.clone();
+    let credentials = create_trial_credentials(&changed_config, &preset, chrono::Utc::now().timestamp());
+    add_user(&mut changed_config, &target.name, credentials.clone());
+    save_api_proxy(cfg, &changed_config)?;
+    *api_proxy = changed_config;
+    drop(guard);
+
+    let server_url = cfg.get_user_server_info(&credentials).get_base_url();
+    let exp_date = credentials.exp_date.unwrap_or_default();
+    let ProxyUserCredentials { username, password, .. } = credentials;
+    Ok(TrialUser {
+        m3u_url: format!("{server_url}/get.php?username={username}&password={password}&type=m3u_plus"),
+        epg_url: format!("{server_url}/xmltv.php?username={username}&password={password}"),
+        exp_date,
+        max_connections: preset.max_connections,
+        target: target.name.clone(),
+        server_url,
+        username,
+        password,
+    })
+}
+
+/// Disables the expired trial users of the loaded api-proxy config and saves the api-proxy file.
+pub fn exec_trial_user_expiry(cfg: &Config) -> Result<Vec<String>, M3uFilterError> {
+    let mut guard = cfg.t_api_proxy.write().unwrap();
+    let Some(api_proxy) = guard.as_mut() else { return Ok(vec![]); };
+    let mut changed_config = api_proxy.clone();
+    let disabled = disable_expired_trial_users(&mut changed_config, chrono::Utc::now().timestamp());
+    if !disabled.is_empty() {
+        save_api_proxy(cfg, &changed_config)?;
+        *api_proxy = changed_config;
+    }
+    Ok(disabled)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::api_proxy::{ApiProxyConfig, ApiProxyServerInfo, ProxyType, TrialPreset};
+    use crate::processing::trial_user::{add_user, create_trial_credentials, disable_expired_trial_users};
+
+    #[test]
+    fn trial_user_expiry_test() {
+        let mut api_proxy = ApiProxyConfig { server: vec![], user: vec![], trial: None };
+        let preset = TrialPreset { name: "day".to_string(), target: "all".to_string(), duration_hours: 24, max_connections: 1, server: None, proxy: ProxyType::Reverse };
+        let credentials = create_trial_credentials(&api_proxy, &preset, 1000);
+        assert!(credentials.username.starts_with("trial_"));
+        assert_eq!(credentials.exp_date, Some(1000 + 24 * 3600));
+        assert_eq!(credentials.max_connections, Some(1));
+        add_user(&mut api_proxy, &preset.target, credentials);
+        assert!(disable_expired_trial_users(&mut api_proxy, 2000).is_empty());
+        assert_eq!(disable_expired_trial_users(&mut api_proxy, 1000 + 24 * 3600).len(), 1);
+        assert!(!api_proxy.user[0].credentials[0].enabled);
+        // disabled users are not disabled again
+        assert!(disable_expired_trial_users(&mut api_proxy, 1000 + 48 * 3600).is_empty());
+    }
+
+    #[test]
+    fn trial_preset_validation_test() {
+        let server = ApiProxyServerInfo {
+            name: "default".to_string(), protocol: "http".to_string(), host: "localhost".to_string(),
+            http_port: "8901".to_string(), https_port: String::new(), rtmp_port: String::new(),
+            timezone: "UTC".to_string(), message: String::new(),
+        };
+        let preset = TrialPreset { name: "day".to_string(), target: "all".to_string(), duration_hours: 24, max_connections: 1, server: None, proxy: ProxyType::Reverse };
+        let mut api_proxy = ApiProxyConfig { server: vec![server], user: vec![], trial: Some(vec![preset.clone()]) };
+        assert!(api_proxy.prepare(false).is_ok());
+        api_proxy.trial = Some(vec![TrialPreset { max_connections: 0, ..preset.clone() }]);
+        assert!(api_proxy.prepare(false).is_err());
+        api_proxy.trial = Some(vec![TrialPreset { name: " ".to_string(), ..preset }]);
+        assert!(api_proxy.prepare(false).is_err());
+    }
+}
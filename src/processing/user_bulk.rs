@@ -0,0 +1,188 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::m3u_filter_error::{M3uFilterError, M3uFilterErrorKind};
+use crate::model::api_proxy::{ApiProxyConfig, ProxyType, ProxyUserCredentials, TargetUser};
+use crate::model::config::Config;
+use crate::utils::config_reader;
+use crate::{create_m3u_filter_error_result, info_err};
+
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum UserStatus {
+    /// Enabled and not expired.
+    Active,
+    Disabled,
+    Expired,
+    Trial,
+}
+
+/// Selects the users of a bulk update, all conditions have to match.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UserBulkFilter {
+    #[serde(default)]
+    pub target: Option<String>,
+    #[serde(default)]
+    pub status: Option<UserStatus>,
+    /// Regular expression for the username.
+    #[serde(default)]
+    pub username: Option<String>,
+}
+
+/// Changes applied to the selected users.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UserBulkUpdate {
+    /// Moves the users to the target (bouquet).
+    #[serde(default)]
+    pub target: Option<String>,
+    /// `0` removes the limit.
+    #[serde(default)]
+    pub max_connections: Option<u32>,
+    #[serde(default)]
+    pub proxy: Option<ProxyType>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct UserBulkReport {
+    pub dry_run: bool,
+    pub users: Vec<String>,
+}
+
+fn matches_status(credentials: &ProxyUserCredentials, status: UserStatus, now: i64) -> bool {
+    let expired = credentials.exp_date.is_some_and(|exp_date| exp_date <= now);
+    match status {
+        UserStatus::Active => credentials.enabled && !expired,
+        UserStatus::Disabled => !credentials.enabled,
+        UserStatus::Expired => expired,
+        UserStatus::Trial => credentials.trial,
+    }
+}
+
+fn apply_update(credentials: &mut ProxyUserCredentials, update: &UserBulkUpdate) {
+    if let Some(max_connections) = update.max_connections {
+        credentials.max_connections = (max_connections > 0).then_some(max_connections);
+    }
+    if let Some(proxy) = &update.proxy {
+        credentials.proxy = proxy.clone();
+    }
+}
+
+/// Applies the update to the users matching the filter, returns the usernames of the updated users.
+pub fn update_users(api_proxy: &mut ApiProxyConfig, filter: &UserBulkFilter, username_re: Option<&Regex>,
+                    update: &UserBulkUpdate, now: i64) -> Vec<String> {
+    let mut updated = vec![];
+    let mut moved = vec![];
+    for target_user in &mut api_proxy.user {
+        if filter.target.as_ref().is_some_and(|target| !target.eq_ignore_ascii_case(&target_user.target)) {
+            continue;
+        }
+        let move_users = update.target.as_ref().is_some_and(|target| target != &target_user.target);
+        let mut kept = Vec::with_capacity(target_user.credentials.len());
+        for mut credentials in std::mem::take(&mut target_user.credentials) {
+            let selected = filter.status.is_none_or(|status| matches_status(&credentials, status, now))
+                && username_re.is_none_or(|re| re.is_match(&credentials.username));
+            if !selected {
+                kept.push(credentials);
+                continue;
+            }
+            updated.push(credentials.username.clone());
+            apply_update(&mut credentials, update);
+            if move_users {
+                moved.push(credentials);
+            } else {
+                kept.push(credentials);
+            }
+        }
+        target_user.credentials = kept;
+    }
+    if let Some(target) = update.target.as_ref().filter(|_| !moved.is_empty()) {
+        match api_proxy.user.iter_mut().find(|target_user| &target_user.target == target) {
+            Some(target_user) => target_user.credentials.append(&mut moved),
+            None => api_proxy.user.push(TargetUser { target: target.clone(), credentials: moved }),
+        }
+    }
+    api_proxy.user.retain(|target_user| !target_user.credentials.is_empty());
+    updated
+}
+
+/// Updates all users matching the filter and saves the api-proxy file.
+/// With `dry_run` the matching users are returned without changes.
+pub fn bulk_update_users(cfg: &Config, filter: &UserBulkFilter, update: &UserBulkUpdate, dry_run: bool) -> Result<UserBulkReport, M3uFilterError> {
+    if let Some(target) = &update.target {
+        if !cfg.sources.iter().flat_map(|source| &source.targets).any(|config_target| &config_target.name == target) {
+            return create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "Unknown target {target}");
+        }
+    }
+    let username_re = match &filter.username {
+        Some(pattern) => Some(Regex::new(pattern).map_err(|err| info_err!(format!("Invalid username pattern {pattern}: {err}")))?),
+        None => None,
+    };
+    let mut guard = cfg.t_api_proxy.write().unwrap();
+    let Some(api_proxy) = guard.as_mut() else {
+        return create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "No api-proxy config loaded");
+    };
+    let mut changed_config = api_proxy.clone();
+    let users = update_users(&mut changed_config, filter, username_re.as_ref(), update, chrono::Utc::now().timestamp());
+    if !dry_run && !users.is_empty() {
+        let backup_dir = cfg.backup_dir.as_deref().unwrap_or(cfg.working_dir.as_str());
+        config_reader::save_api_proxy(cfg.t_api_proxy_file_path.as_str(), backup_dir, &changed_config)?;
+        *api_proxy = changed_config;
+    }
+    Ok(UserBulkReport { dry_run, users })
+}
+
+#[cfg(test)]
+mod tests {
+    use regex::Regex;
+
+    use crate::model::api_proxy::{ApiProxyConfig, ProxyType, ProxyUserCredentials, TargetUser};
+    use crate::processing::user_bulk::{update_users, UserBulkFilter, UserBulkUpdate, UserStatus};
+
+    fn user(username: &str, enabled: bool, exp_date: Option<i64>) -> ProxyUserCredentials {
+        ProxyUserCredentials {
+            username: username.to_string(),
+            password: "secret".to_string(),
+            token: None,
+            proxy: ProxyType::Redirect,
+            server: None,
+            epg_timeshift: None,
+            provider_weight: None,
+            trace_streams: false,
+            exp_date,
+            max_connections: Some(1),
+            enabled,
+            trial: false,
+            no_log: false,
+            locale: None,
+            mac: None,
+            max_catchup_days: None,
+            t_forwarded_origin: None,
+        }
+    }
+
+    #[test]
+    fn update_users_test() {
+        let mut api_proxy = ApiProxyConfig {
+            server: vec![],
+            user: vec![
+                TargetUser { target: "all".to_string(), credentials: vec![user("hotel_1", true, None), user("hotel_2", true, Some(500)), user("max", true, None)] },
+                TargetUser { target: "sports".to_string(), credentials: vec![user("hotel_3", false, None)] },
+            ],
+            trial: None,
+        };
+        let filter = UserBulkFilter { target: Some("all".to_string()), status: Some(UserStatus::Active), username: None };
+        let re = Regex::new("^hotel_").unwrap();
+        let update = UserBulkUpdate { target: Some("sports".to_string()), max_connections: Some(0), proxy: Some(ProxyType::Reverse) };
+        assert_eq!(update_users(&mut api_proxy, &filter, Some(&re), &update, 1000), vec!["hotel_1"]);
+        assert_eq!(api_proxy.user[0].credentials.len(), 2);
+        let moved = &api_proxy.user[1].credentials[1];
+        assert_eq!((moved.username.as_str(), moved.max_connections, &moved.proxy), ("hotel_1", None, &ProxyType::Reverse));
+
+        let filter = UserBulkFilter { status: Some(UserStatus::Expired), ..UserBulkFilter::default() };
+        let update = UserBulkUpdate { target: Some("sports".to_string()), ..UserBulkUpdate::default() };
+        assert_eq!(update_users(&mut api_proxy, &filter, None, &update, 1000), vec!["hotel_2"]);
+        assert_eq!(api_proxy.user.iter().map(|target_user| target_user.credentials.len()).collect::<Vec<_>>(), vec![1, 3]);
+    }
+}
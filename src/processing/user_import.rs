@@ -0,0 +1,261 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::m3u_filter_error::{M3uFilterError, M3uFilterErrorKind};
+use crate::model::api_proxy::{ApiProxyConfig, ProxyType, ProxyUserCredentials, TargetUser};
+use crate::model::config::Config;
+use crate::utils::config_reader;
+use crate::{create_m3u_filter_error_result, info_err};
+
+/// User of a xtream panel export (xtream-ui, xui.one and similar), csv with header line or json.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PanelUser {
+    pub username: String,
+    pub password: String,
+    pub exp_date: Option<i64>,
+    pub max_connections: Option<u32>,
+    pub bouquets: Vec<String>,
+    pub enabled: bool,
+}
+
+/// `target` is used for users without a mapped bouquet, `bouquets` maps panel bouquet ids to target names.
+#[derive(Debug, Clone, Default)]
+pub struct UserImportOptions {
+    pub target: String,
+    pub bouquets: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct UserImportReport {
+    pub imported: Vec<String>,
+    pub skipped: Vec<(String, String)>,
+}
+
+fn get_string(value: Option<&Value>) -> Option<String> {
+    match value? {
+        Value::String(text) => Some(text.trim().to_string()).filter(|text| !text.is_empty()),
+        Value::Number(number) => Some(number.to_string()),
+        Value::Bool(flag) => Some(if *flag { "1" } else { "0" }.to_string()),
+        _ => None,
+    }
+}
+
+/// The bouquet field is a json array `[1,2]`, also as string, or a comma separated list.
+fn parse_bouquets(value: Option<&Value>) -> Vec<String> {
+    match value {
+        Some(Value::Array(ids)) => ids.iter().filter_map(|id| get_string(Some(id))).collect(),
+        Some(Value::String(text)) => {
+            match serde_json::from_str::<Value>(text) {
+                Ok(Value::Array(ids)) => ids.iter().filter_map(|id| get_string(Some(id))).collect(),
+                _ => text.split(',').map(|id| id.trim().trim_matches(['[', ']', '"']).to_string()).filter(|id| !id.is_empty()).collect(),
+            }
+        }
+        Some(Value::Number(id)) => vec![id.to_string()],
+        _ => vec![],
+    }
+}
+
+/// The expiry is a unix timestamp, `0`, `null` or an empty value is unlimited.
+fn parse_exp_date(value: Option<&Value>) -> Option<i64> {
+    get_string(value).and_then(|text| text.parse::<i64>().ok()).filter(|exp_date| *exp_date > 0)
+}
+
+fn is_enabled(record: &serde_json::Map<String, Value>) -> bool {
+    ["enabled", "admin_enabled"].iter()
+        .all(|field| get_string(record.get(*field)).is_none_or(|value| value != "0" && !value.eq_ignore_ascii_case("false")))
+}
+
+fn to_panel_user(record: &serde_json::Map<String, Value>) -> Option<PanelUser> {
+    let username = get_string(record.get("username"))?;
+    Some(PanelUser {
+        password: get_string(record.get("password")).unwrap_or_default(),
+        exp_date: parse_exp_date(record.get("exp_date")),
+        // 0 is unlimited
+        max_connections: get_string(record.get("max_connections")).and_then(|text| text.parse::<u32>().ok()).filter(|max| *max > 0),
+        bouquets: parse_bouquets(record.get("bouquet").or_else(|| record.get("bouquets"))),
+        enabled: is_enabled(record),
+        username,
+    })
+}
+
+/// Splits a csv line, fields can be quoted with `"` and quotes are escaped as `""`.
+fn split_csv_line(line: &str, delimiter: char) -> Vec<String> {
+    let mut fields = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            c if c == delimiter && !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+fn parse_csv(content: &str) -> Vec<serde_json::Map<String, Value>> {
+    let mut lines = content.lines().filter(|line| !line.trim().is_empty());
+    let Some(header) = lines.next() else { return vec![]; };
+    let delimiter = if header.contains(';') && !header.contains(',') { ';' } else { ',' };
+    let columns: Vec<String> = split_csv_line(header, delimiter).iter().map(|column| column.trim().to_lowercase()).collect();
+    lines.map(|line| columns.iter().cloned()
+        .zip(split_csv_line(line, delimiter).into_iter().map(Value::String))
+        .collect())
+        .collect()
+}
+
+/// A json array of users or an object with the users in `users` or `data`.
+fn parse_json(content: &str) -> Result<Vec<serde_json::Map<String, Value>>, String> {
+    let value = serde_json::from_str::<Value>(content).map_err(|err| format!("Invalid json: {err}"))?;
+    let records = match value {
+        Value::Array(records) => records,
+        Value::Object(mut object) => match object.remove("users").or_else(|| object.remove("data")) {
+            Some(Value::Array(records)) => records,
+            _ => return Err("No user list found".to_string()),
+        },
+        _ => return Err("No user list found".to_string()),
+    };
+    Ok(records.into_iter().filter_map(|record| match record {
+        Value::Object(record) => Some(record),
+        _ => None,
+    }).collect())
+}
+
+pub fn parse_panel_users(content: &str) -> Result<Vec<PanelUser>, String> {
+    let trimmed = content.trim_start_matches('\u{feff}').trim();
+    let records = if trimmed.starts_with('[') || trimmed.starts_with('{') {
+        parse_json(trimmed)?
+    } else {
+        parse_csv(trimmed)
+    };
+    Ok(records.iter().filter_map(to_panel_user).collect())
+}
+
+/// Creates an api-proxy user for each panel user. Existing usernames, disabled and expired users are skipped.
+/// The target of a user is the target of the first mapped bouquet, otherwise the default target.
+pub fn import_panel_users(api_proxy: &mut ApiProxyConfig, users: Vec<PanelUser>, options: &UserImportOptions) -> UserImportReport {
+    let mut report = UserImportReport::default();
+    let mut usernames: HashSet<String> = api_proxy.user.iter().flat_map(|target_user| &target_user.credentials)
+        .map(|credentials| credentials.username.clone()).collect();
+    let now = chrono::Utc::now().timestamp();
+    for user in users {
+        let skip_reason = if usernames.contains(&user.username) {
+            Some("username exists")
+        } else if user.password.is_empty() {
+            Some("no password")
+        } else if !user.enabled {
+            Some("disabled")
+        } else if user.exp_date.is_some_and(|exp_date| exp_date <= now) {
+            Some("expired")
+        } else {
+            None
+        };
+        if let Some(reason) = skip_reason {
+            report.skipped.push((user.username, reason.to_string()));
+            continue;
+        }
+        let target = user.bouquets.iter().find_map(|bouquet| options.bouquets.get(bouquet)).unwrap_or(&options.target);
+        let credentials = ProxyUserCredentials {
+            username: user.username.clone(),
+            password: user.password,
+            token: None,
+            proxy: ProxyType::Reverse,
+            server: None,
+            epg_timeshift: None,
+            provider_weight: None,
+            trace_streams: false,
+            exp_date: user.exp_date,
+            max_connections: user.max_connections,
+            enabled: true,
+            trial: false,
+            no_log: false,
+            locale: None,
+            mac: None,
+            max_catchup_days: None,
+            t_forwarded_origin: None,
+        };
+        match api_proxy.user.iter_mut().find(|target_user| &target_user.target == target) {
+            Some(target_user) => target_user.credentials.push(credentials),
+            None => api_proxy.user.push(TargetUser { target: target.clone(), credentials: vec![credentials] }),
+        }
+        usernames.insert(user.username.clone());
+        report.imported.push(user.username);
+    }
+    report
+}
+
+fn validate_import_targets(cfg: &Config, options: &UserImportOptions) -> Result<(), M3uFilterError> {
+    let target_names: HashSet<&str> = cfg.sources.iter().flat_map(|source| &source.targets).map(|target| target.name.as_str()).collect();
+    match std::iter::once(&options.target).chain(options.bouquets.values()).find(|target| !target_names.contains(target.as_str())) {
+        Some(target) => create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "Unknown target {target}"),
+        None => Ok(()),
+    }
+}
+
+/// Imports the users of the panel export into the api-proxy config and saves the api-proxy file.
+pub fn import_users(cfg: &Config, content: &str, options: &UserImportOptions) -> Result<UserImportReport, M3uFilterError> {
+    validate_import_targets(cfg, options)?;
+    let users = parse_panel_users(content).map_err(|err| info_err!(err))?;
+    let mut guard = cfg.t_api_proxy.write().unwrap();
+    let Some(api_proxy) = guard.as_mut() else {
+        return create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "No api-proxy config loaded");
+    };
+    let mut imported_config = api_proxy.clone();
+    let report = import_panel_users(&mut imported_config, users, options);
+    if !report.imported.is_empty() {
+        let backup_dir = cfg.backup_dir.as_deref().unwrap_or(cfg.working_dir.as_str());
+        config_reader::save_api_proxy(cfg.t_api_proxy_file_path.as_str(), backup_dir, &imported_config)?;
+        *api_proxy = imported_config;
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::model::api_proxy::ApiProxyConfig;
+    use crate::processing::user_import::{import_panel_users, parse_panel_users, UserImportOptions};
+
+    #[test]
+    fn parse_csv_test() {
+        let content = "id,username,password,exp_date,enabled,max_connections,bouquet\n\
+                       1,alice,secret,4102444800,1,2,\"[1,3]\"\n\
+                       2,bob,\"pa,ss\",,0,0,";
+        let users = parse_panel_users(content).unwrap();
+        assert_eq!(users.len(), 2);
+        assert_eq!(users[0].exp_date, Some(4_102_444_800));
+        assert_eq!(users[0].max_connections, Some(2));
+        assert_eq!(users[0].bouquets, vec!["1".to_string(), "3".to_string()]);
+        assert_eq!(users[1].password, "pa,ss");
+        assert_eq!(users[1].max_connections, None);
+        assert!(!users[1].enabled);
+    }
+
+    #[test]
+    fn import_json_test() {
+        let content = r#"{"users": [
+            {"username": "alice", "password": "secret", "exp_date": null, "max_connections": 1, "bouquet": [3]},
+            {"username": "carol", "password": "secret", "exp_date": 1000, "bouquet": "[]"},
+            {"username": "dave", "password": "secret", "admin_enabled": "1"}
+        ]}"#;
+        let users = parse_panel_users(content).unwrap();
+        let mut api_proxy = ApiProxyConfig { server: vec![], user: vec![], trial: None };
+        let options = UserImportOptions { target: "all".to_string(), bouquets: HashMap::from([("3".to_string(), "sports".to_string())]) };
+        let report = import_panel_users(&mut api_proxy, users.clone(), &options);
+        assert_eq!(report.imported, vec!["alice".to_string(), "dave".to_string()]);
+        assert_eq!(report.skipped, vec![("carol".to_string(), "expired".to_string())]);
+        assert_eq!(api_proxy.user.iter().map(|target_user| target_user.target.as_str()).collect::<Vec<_>>(), vec!["sports", "all"]);
+        let report = import_panel_users(&mut api_proxy, users, &options);
+        assert!(report.imported.is_empty());
+        assert_eq!(report.skipped.len(), 3);
+    }
+}
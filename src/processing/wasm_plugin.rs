@@ -0,0 +1,238 @@
+use std::fmt::{Debug, Formatter};
+use std::path::PathBuf;
+
+use log::{debug, error};
+use path_clean::PathClean;
+use serde_json::{json, Map, Value};
+use wasmi::{Caller, Engine, Linker, Memory, Module, Store, StoreLimits, StoreLimitsBuilder, TypedFunc};
+
+use crate::info_err;
+use crate::m3u_filter_error::{M3uFilterError, M3uFilterErrorKind};
+use crate::model::config::PluginConfig;
+use crate::model::playlist::{FieldSetAccessor, PlaylistGroup, PlaylistItem};
+
+const DEFAULT_PLUGIN_FUEL: u64 = 1_000_000;
+const DEFAULT_PLUGIN_MEMORY: usize = 16 * 1024 * 1024;
+const PLUGIN_RESULT_KEEP: i64 = 0;
+const PLUGIN_RESULT_DROP: i64 = -1;
+
+/// Experimental filter/mapper plugin, a wasm module with the exports
+/// `memory`, `alloc(len: i32) -> i32` and `process(ptr: i32, len: i32) -> i64`.
+/// The module runs sandboxed, the only import is `env.log(ptr: i32, len: i32)`.
+pub struct WasmPlugin {
+    pub name: String,
+    engine: Engine,
+    module: Module,
+    fuel: u64,
+    memory_limit: usize,
+}
+
+impl Debug for WasmPlugin {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WasmPlugin").field("name", &self.name).finish_non_exhaustive()
+    }
+}
+
+struct PluginState {
+    name: String,
+    limits: StoreLimits,
+}
+
+/// Result of a plugin call for one item.
+#[derive(Debug, PartialEq, Eq)]
+pub enum PluginResult {
+    Keep,
+    Drop,
+    /// The changed fields of the item
+    Update(Map<String, Value>),
+}
+
+impl WasmPlugin {
+    /// Compiles the module, a relative path is resolved against the config directory.
+    pub fn load(config: &PluginConfig, config_path: &str) -> Result<Self, M3uFilterError> {
+        let mut path = PathBuf::from(&config.path);
+        if path.is_relative() {
+            path = PathBuf::from(config_path).join(path).clean();
+        }
+        let wasm = std::fs::read(&path).map_err(|err| info_err!(format!("Failed to read plugin {} {}: {err}", config.name, path.display())))?;
+        Self::from_bytes(&config.name, &wasm, config.fuel, config.t_memory_limit)
+    }
+
+    fn from_bytes(name: &str, wasm: &[u8], fuel: Option<u64>, memory_limit: Option<usize>) -> Result<Self, M3uFilterError> {
+        let mut engine_config = wasmi::Config::default();
+        engine_config.consume_fuel(true);
+        let engine = Engine::new(&engine_config);
+        let module = Module::new(&engine, wasm).map_err(|err| info_err!(format!("Invalid plugin {name}: {err}")))?;
+        Ok(Self {
+            name: name.to_string(),
+            engine,
+            module,
+            fuel: fuel.unwrap_or(DEFAULT_PLUGIN_FUEL),
+            memory_limit: memory_limit.unwrap_or(DEFAULT_PLUGIN_MEMORY),
+        })
+    }
+
+    pub fn instantiate(&self) -> Result<PluginInstance<'_>, String> {
+        let state = PluginState { name: self.name.clone(), limits: StoreLimitsBuilder::new().memory_size(self.memory_limit).instances(1).build() };
+        let mut store = Store::new(&self.engine, state);
+        store.limiter(|state| &mut state.limits);
+        store.set_fuel(self.fuel).map_err(|err| err.to_string())?;
+        let mut linker = <Linker<PluginState>>::new(&self.engine);
+        linker.func_wrap("env", "log", |caller: Caller<'_, PluginState>, ptr: i32, len: i32| {
+            if let Some(message) = caller.get_export("memory").and_then(|export| export.into_memory())
+                .and_then(|memory| read_memory(&memory, &caller, ptr, len).ok()) {
+                debug!("Plugin {}: {}", caller.data().name, String::from_utf8_lossy(&message));
+            }
+        }).map_err(|err| err.to_string())?;
+        let instance = linker.instantiate_and_start(&mut store, &self.module).map_err(|err| err.to_string())?;
+        let memory = instance.get_memory(&store, "memory").ok_or("missing export memory")?;
+        let alloc = instance.get_typed_func::<i32, i32>(&store, "alloc").map_err(|err| err.to_string())?;
+        let process = instance.get_typed_func::<(i32, i32), i64>(&store, "process").map_err(|err| err.to_string())?;
+        Ok(PluginInstance { plugin: self, store, memory, alloc, process })
+    }
+}
+
+fn read_memory(memory: &Memory, ctx: impl wasmi::AsContext, ptr: i32, len: i32) -> Result<Vec<u8>, String> {
+    let offset = usize::try_from(ptr).map_err(|err| err.to_string())?;
+    let mut buffer = vec![0; usize::try_from(len).map_err(|err| err.to_string())?];
+    memory.read(ctx, offset, &mut buffer).map_err(|err| err.to_string())?;
+    Ok(buffer)
+}
+
+pub struct PluginInstance<'a> {
+    plugin: &'a WasmPlugin,
+    store: Store<PluginState>,
+    memory: Memory,
+    alloc: TypedFunc<i32, i32>,
+    process: TypedFunc<(i32, i32), i64>,
+}
+
+impl PluginInstance<'_> {
+    /// Calls the plugin with the json document of an item. `process` returns `0` to keep the item unchanged,
+    /// `-1` to drop it, otherwise `ptr << 32 | len` of a json object with the changed fields.
+    pub fn process(&mut self, input: &[u8]) -> Result<PluginResult, String> {
+        // each item gets the configured fuel, a plugin which loops forever is stopped
+        self.store.set_fuel(self.plugin.fuel).map_err(|err| err.to_string())?;
+        let len = i32::try_from(input.len()).map_err(|err| err.to_string())?;
+        let ptr = self.alloc.call(&mut self.store, len).map_err(|err| err.to_string())?;
+        let offset = usize::try_from(ptr).map_err(|err| err.to_string())?;
+        self.memory.write(&mut self.store, offset, input).map_err(|err| err.to_string())?;
+        match self.process.call(&mut self.store, (ptr, len)).map_err(|err| err.to_string())? {
+            PLUGIN_RESULT_KEEP => Ok(PluginResult::Keep),
+            PLUGIN_RESULT_DROP => Ok(PluginResult::Drop),
+            packed => {
+                #[allow(clippy::cast_possible_truncation)]
+                let (result_ptr, result_len) = ((packed >> 32) as i32, (packed & 0xFFFF_FFFF) as i32);
+                let content = read_memory(&self.memory, &self.store, result_ptr, result_len)?;
+                match serde_json::from_slice::<Value>(&content).map_err(|err| err.to_string())? {
+                    Value::Object(fields) => Ok(PluginResult::Update(fields)),
+                    _ => Err("result is not a json object".to_string()),
+                }
+            }
+        }
+    }
+}
+
+fn item_to_json(pli: &PlaylistItem) -> Vec<u8> {
+    let header = pli.header.borrow();
+    json!({
+        "id": header.id.as_str(),
+        "name": header.name.as_str(),
+        "title": header.title.as_str(),
+        "group": header.group.as_str(),
+        "chno": header.chno.as_str(),
+        "logo": header.logo.as_str(),
+        "logo_small": header.logo_small.as_str(),
+        "parent_code": header.parent_code.as_str(),
+        "audio_track": header.audio_track.as_str(),
+        "time_shift": header.time_shift.as_str(),
+        "rec": header.rec.as_str(),
+        "url": header.url.as_str(),
+        "epg_channel_id": header.epg_channel_id.as_ref().map(|id| id.as_str()),
+        "item_type": header.item_type.to_string(),
+        "cluster": header.xtream_cluster.as_str(),
+    }).to_string().into_bytes()
+}
+
+fn update_item(plugin: &WasmPlugin, pli: &PlaylistItem, fields: &Map<String, Value>) {
+    let mut header = pli.header.borrow_mut();
+    for (field, value) in fields {
+        let updated = value.as_str().is_some_and(|value| header.set_field(field, value));
+        if !updated {
+            debug!("Plugin {} can't update field {field}", plugin.name);
+        }
+    }
+}
+
+/// Runs the plugin for all items of the playlist. If the plugin fails, the remaining items are kept unchanged.
+pub fn apply_plugin(plugin: &WasmPlugin, playlist: &mut Vec<PlaylistGroup>) {
+    let mut instance = match plugin.instantiate() {
+        Ok(instance) => instance,
+        Err(err) => {
+            error!("Failed to start plugin {}: {err}", plugin.name);
+            return;
+        }
+    };
+    let mut failed = false;
+    for group in playlist.iter_mut() {
+        group.channels.retain(|pli| {
+            if failed {
+                return true;
+            }
+            match instance.process(&item_to_json(pli)) {
+                Ok(PluginResult::Keep) => true,
+                Ok(PluginResult::Drop) => false,
+                Ok(PluginResult::Update(fields)) => {
+                    update_item(plugin, pli, &fields);
+                    true
+                }
+                Err(err) => {
+                    error!("Plugin {} failed: {err}", plugin.name);
+                    failed = true;
+                    true
+                }
+            }
+        });
+    }
+    playlist.retain(|group| !group.channels.is_empty());
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::processing::wasm_plugin::{PluginResult, WasmPlugin};
+
+    // drops inputs shorter than 8 bytes, changes the group of all other items
+    const PLUGIN: &str = r#"(module
+        (import "env" "log" (func $log (param i32 i32)))
+        (memory (export "memory") 1)
+        (data (i32.const 16) "{\"group\":\"Plugin\"}")
+        (func (export "alloc") (param $len i32) (result i32) (i32.const 1024))
+        (func (export "process") (param $ptr i32) (param $len i32) (result i64)
+            (call $log (i32.const 16) (i32.const 18))
+            (if (result i64) (i32.lt_u (local.get $len) (i32.const 8))
+                (then (i64.const -1))
+                (else (i64.or (i64.shl (i64.const 16) (i64.const 32)) (i64.const 18))))))"#;
+
+    const ENDLESS_PLUGIN: &str = r#"(module
+        (memory (export "memory") 1)
+        (func (export "alloc") (param i32) (result i32) (i32.const 0))
+        (func (export "process") (param i32 i32) (result i64) (loop $endless (br $endless)) (i64.const 0)))"#;
+
+    #[test]
+    fn plugin_process_test() {
+        let plugin = WasmPlugin::from_bytes("test", PLUGIN.as_bytes(), None, None).unwrap();
+        let mut instance = plugin.instantiate().unwrap();
+        assert_eq!(instance.process(b"{}").unwrap(), PluginResult::Drop);
+        match instance.process(br#"{"group":"News"}"#).unwrap() {
+            PluginResult::Update(fields) => assert_eq!(fields.get("group").and_then(|value| value.as_str()), Some("Plugin")),
+            result => panic!("unexpected result {result:?}"),
+        }
+    }
+
+    #[test]
+    fn plugin_fuel_test() {
+        let plugin = WasmPlugin::from_bytes("endless", ENDLESS_PLUGIN.as_bytes(), Some(10_000), None).unwrap();
+        let mut instance = plugin.instantiate().unwrap();
+        assert!(instance.process(b"{}").is_err());
+    }
+}
@@ -1,10 +1,13 @@
 use std::collections::{HashMap, HashSet};
 use std::rc::Rc;
 
+use chrono::{DateTime, Utc};
 use quick_xml::events::Event;
 use quick_xml::Reader;
 
-use crate::model::xmltv::{Epg, EPG_ATTRIB_CHANNEL, EPG_ATTRIB_ID, EPG_TAG_TV, EPG_TAG_CHANNEL, EPG_TAG_PROGRAMME, TVGuide, XmlTag};
+use crate::model::config::{EpgGapFillOptions, EpgTargetOptions};
+use crate::model::xmltv::{Epg, EpgChannelInfo, EPG_ATTRIB_CHANNEL, EPG_ATTRIB_ID, EPG_ATTRIB_SRC, EPG_ATTRIB_START, EPG_ATTRIB_STOP, EPG_TAG_TV, EPG_TAG_CATEGORY, EPG_TAG_CHANNEL, EPG_TAG_DESC, EPG_TAG_DISPLAY_NAME, EPG_TAG_ICON, EPG_TAG_PROGRAMME, EPG_TAG_TITLE, TVGuide, XmlTag};
+use crate::repository::epg_repository::parse_epg_timestamp;
 use crate::utils::compressed_file_reader::CompressedFileReader;
 
 impl TVGuide {
@@ -47,6 +50,26 @@ impl TVGuide {
             Err(_) => None
         }
     }
+
+    /// The channels of the guide with their display names and icon.
+    pub fn get_channels(&self) -> Vec<EpgChannelInfo> {
+        let Ok(mut reader) = CompressedFileReader::new(&self.file) else { return vec![] };
+        let mut channels = vec![];
+        let mut collect = |tag: XmlTag| {
+            if tag.name != EPG_TAG_CHANNEL {
+                return;
+            }
+            let Some(id) = tag.get_attribute_value(EPG_ATTRIB_ID).cloned() else { return };
+            let children = tag.children.as_deref().unwrap_or_default();
+            channels.push(EpgChannelInfo {
+                id,
+                display_names: children.iter().filter(|child| child.name == EPG_TAG_DISPLAY_NAME).filter_map(|child| child.value.clone()).collect(),
+                icon: children.iter().find(|child| child.name == EPG_TAG_ICON).and_then(|child| child.get_attribute_value(EPG_ATTRIB_SRC).cloned()),
+            });
+        };
+        parse_tvguide(&mut reader, &mut collect);
+        channels
+    }
 }
 
 pub fn parse_tvguide<R, F>(content: R, callback: &mut F)
@@ -165,6 +188,155 @@ pub fn flatten_tvguide(tv_guides: &[Epg]) -> Option<Epg> {
     }
 }
 
+fn truncate_description(tag: &XmlTag, max_length: usize) -> Option<Rc<XmlTag>> {
+    match tag.value.as_ref() {
+        Some(text) if text.chars().count() > max_length => {
+            let mut truncated = tag.clone();
+            truncated.value = Some(format!("{}…", text.chars().take(max_length).collect::<String>()));
+            Some(Rc::new(truncated))
+        }
+        _ => None,
+    }
+}
+
+/// Reduces the epg size for a target. Programmes with a matching category are dropped,
+/// programme icons are removed and descriptions are truncated (`0` removes them).
+pub fn apply_epg_options(epg: &mut Epg, options: &EpgTargetOptions) {
+    if !options.is_active() {
+        return;
+    }
+    let is_dropped_category = |child: &Rc<XmlTag>| child.name == EPG_TAG_CATEGORY
+        && child.value.as_ref().is_some_and(|category| options.t_drop_categories.iter().any(|re| re.is_match(category)));
+    epg.children.retain(|tag| tag.name != EPG_TAG_PROGRAMME
+        || !tag.children.as_ref().is_some_and(|children| children.iter().any(is_dropped_category)));
+    for tag in &mut epg.children {
+        if tag.name != EPG_TAG_PROGRAMME {
+            continue;
+        }
+        if let Some(children) = tag.children.as_mut() {
+            if options.strip_icons {
+                children.retain(|child| child.name != EPG_TAG_ICON);
+            }
+            if let Some(max_length) = options.max_description_length {
+                let mut processed = Vec::with_capacity(children.len());
+                for child in children.drain(..) {
+                    if child.name == EPG_TAG_DESC {
+                        if max_length > 0 {
+                            processed.push(truncate_description(&child, max_length).unwrap_or(child));
+                        }
+                    } else {
+                        processed.push(child);
+                    }
+                }
+                *children = processed;
+            }
+        }
+    }
+    if let Some(fill_gaps) = options.fill_gaps.as_ref() {
+        fill_epg_gaps(epg, fill_gaps, Utc::now().timestamp());
+    }
+}
+
+fn format_epg_timestamp(timestamp: i64) -> Option<String> {
+    DateTime::from_timestamp(timestamp, 0).map(|dt| dt.format("%Y%m%d%H%M%S +0000").to_string())
+}
+
+fn create_gap_programme(channel: &str, start: i64, stop: i64, title: &str) -> Option<XmlTag> {
+    let attributes = HashMap::from([
+        (EPG_ATTRIB_START.to_string(), format_epg_timestamp(start)?),
+        (EPG_ATTRIB_STOP.to_string(), format_epg_timestamp(stop)?),
+        (EPG_ATTRIB_CHANNEL.to_string(), channel.to_string()),
+    ]);
+    let title_tag = XmlTag { name: EPG_TAG_TITLE.to_string(), value: Some(title.to_string()), attributes: None, children: None };
+    Some(XmlTag {
+        name: EPG_TAG_PROGRAMME.to_string(),
+        value: None,
+        attributes: Some(Rc::new(attributes)),
+        children: Some(vec![Rc::new(title_tag)]),
+    })
+}
+
+/// Fills the gaps in the programme of each channel with synthetic programmes of `block_minutes` length.
+/// The guide range spans from the first programme start to the last programme stop over all channels,
+/// channels without any programme are filled over the whole range.
+/// The range is capped to `max_days` around `now`, a single programme with a broken time would otherwise
+/// create blocks over years.
+fn fill_epg_gaps(epg: &mut Epg, options: &EpgGapFillOptions, now: i64) {
+    let mut channels: Vec<&str> = epg.children.iter().filter(|tag| tag.name == EPG_TAG_CHANNEL)
+        .filter_map(|tag| tag.get_attribute_value(EPG_ATTRIB_ID).map(String::as_str)).collect();
+    let mut programmes: HashMap<&str, Vec<(i64, i64)>> = HashMap::new();
+    for tag in epg.children.iter().filter(|tag| tag.name == EPG_TAG_PROGRAMME) {
+        let Some(channel) = tag.get_attribute_value(EPG_ATTRIB_CHANNEL) else { continue; };
+        let start = tag.get_attribute_value(EPG_ATTRIB_START).and_then(|value| parse_epg_timestamp(value));
+        let stop = tag.get_attribute_value(EPG_ATTRIB_STOP).and_then(|value| parse_epg_timestamp(value));
+        if let (Some(start), Some(stop)) = (start, stop) {
+            if !programmes.contains_key(channel.as_str()) && !channels.contains(&channel.as_str()) {
+                channels.push(channel);
+            }
+            programmes.entry(channel).or_default().push((start, stop));
+        }
+    }
+    let Some(range_start) = programmes.values().flatten().map(|(start, _)| *start).min() else { return; };
+    let Some(range_end) = programmes.values().flatten().map(|(_, stop)| *stop).max() else { return; };
+    let max_range = i64::from(options.max_days) * 86_400;
+    let range_start = range_start.max(now - max_range);
+    let range_end = range_end.min(now + max_range);
+    let block = i64::from(options.block_minutes) * 60;
+    let mut gaps = Vec::new();
+    for channel in channels {
+        let mut channel_programmes = programmes.remove(channel).unwrap_or_default();
+        channel_programmes.sort_unstable();
+        channel_programmes.push((range_end, range_end));
+        let mut cursor = range_start;
+        for (start, stop) in channel_programmes {
+            while cursor < start {
+                let block_end = (cursor + block).min(start);
+                if let Some(programme) = create_gap_programme(channel, cursor, block_end, &options.title) {
+                    gaps.push(programme);
+                }
+                cursor = block_end;
+            }
+            cursor = cursor.max(stop);
+        }
+    }
+    epg.children.extend(gaps);
+}
+
+/// Normalizes a malformed timezone offset of a xmltv time like `20250101120000 +-0100`,
+/// `20250101120000 --01:00` or `20250101120000 -100` to `20250101120000 -0100`.
+fn fix_epg_time_offset(value: &str) -> Option<String> {
+    let (time, offset) = value.trim().split_once(' ')?;
+    let offset = offset.trim();
+    if !offset.contains('-') {
+        return None;
+    }
+    let digits: String = offset.chars().filter(char::is_ascii_digit).collect();
+    if digits.is_empty() || digits.len() > 4 {
+        return None;
+    }
+    let fixed = format!("{time} -{digits:0>4}");
+    if fixed == value { None } else { Some(fixed) }
+}
+
+/// Fixes the negative timezone offsets of the programmes for inputs with the `fix_negative_epg_offset` quirk.
+pub fn fix_negative_epg_offsets(epg: &mut Epg) {
+    for tag in &mut epg.children {
+        if tag.name != EPG_TAG_PROGRAMME {
+            continue;
+        }
+        if let Some(attributes) = tag.attributes.as_mut() {
+            let fixed: Vec<(&str, String)> = [EPG_ATTRIB_START, EPG_ATTRIB_STOP].into_iter()
+                .filter_map(|attrib| attributes.get(attrib).and_then(|value| fix_epg_time_offset(value)).map(|value| (attrib, value)))
+                .collect();
+            if !fixed.is_empty() {
+                let attributes = Rc::make_mut(attributes);
+                for (attrib, value) in fixed {
+                    attributes.insert(attrib.to_string(), value);
+                }
+            }
+        }
+    }
+}
 
 #[cfg(test)]
 mod tests {
@@ -173,7 +345,9 @@ mod tests {
     use std::path::PathBuf;
     use std::rc::Rc;
 
-    use crate::model::xmltv::{TVGuide};
+    use crate::model::config::{EpgGapFillOptions, EpgTargetOptions};
+    use crate::model::xmltv::{Epg, TVGuide, XmlTag};
+    use crate::processing::xmltv_parser::{apply_epg_options, fill_epg_gaps, fix_epg_time_offset, parse_tvguide};
 
     #[test]
     fn parse_test() -> io::Result<()> {
@@ -193,4 +367,80 @@ mod tests {
 
         Ok(())
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn epg_options_test() {
+        let content = r#"<tv><channel id="c1"><display-name>C1</display-name><icon src="c1.png"></icon></channel>
+            <programme channel="c1" start="20250101000000 +0000"><title>News</title><desc>Daily news from around the world</desc><icon src="news.png"></icon><category>News</category></programme>
+            <programme channel="c1" start="20250101010000 +0000"><title>Late Show</title><category>Adult</category></programme></tv>"#;
+        let mut children = vec![];
+        parse_tvguide(content.as_bytes(), &mut |tag: XmlTag| if tag.name != "tv" { children.push(tag) });
+        let mut epg = Epg { attributes: None, children };
+        let options = EpgTargetOptions {
+            drop_categories: None,
+            strip_icons: true,
+            max_description_length: Some(10),
+            fill_gaps: None,
+            t_drop_categories: vec![regex::Regex::new("(?i)adult").unwrap()],
+        };
+        apply_epg_options(&mut epg, &options);
+        assert_eq!(epg.children.len(), 2);
+        let channel_children = epg.children[0].children.as_ref().unwrap();
+        assert!(channel_children.iter().any(|tag| tag.name == "icon"));
+        let programme_children = epg.children[1].children.as_ref().unwrap();
+        assert!(!programme_children.iter().any(|tag| tag.name == "icon"));
+        let desc = programme_children.iter().find(|tag| tag.name == "desc").unwrap();
+        assert_eq!(desc.value.as_deref(), Some("Daily news…"));
+    }
+
+    #[test]
+    fn epg_fill_gaps_test() {
+        let content = r#"<tv><channel id="c1"></channel><channel id="c2"></channel>
+            <programme channel="c1" start="20250101000000 +0000" stop="20250101010000 +0000"><title>News</title></programme>
+            <programme channel="c1" start="20250101033000 +0000" stop="20250101040000 +0000"><title>Movie</title></programme></tv>"#;
+        let mut children = vec![];
+        parse_tvguide(content.as_bytes(), &mut |tag: XmlTag| if tag.name != "tv" { children.push(tag) });
+        let mut epg = Epg { attributes: None, children };
+        let options = EpgTargetOptions {
+            fill_gaps: Some(EpgGapFillOptions { title: "No information".to_string(), block_minutes: 60, max_days: 7 }),
+            ..EpgTargetOptions::default()
+        };
+        // 2025-01-01 00:00 UTC
+        fill_epg_gaps(&mut epg, options.fill_gaps.as_ref().unwrap(), 1_735_689_600);
+        let gaps: Vec<(String, String, String)> = epg.children.iter().skip(4).map(|tag| (
+            tag.get_attribute_value("channel").unwrap().clone(),
+            tag.get_attribute_value("start").unwrap().clone(),
+            tag.get_attribute_value("stop").unwrap().clone())).collect();
+        let gap = |channel: &str, start: &str, stop: &str| (channel.to_string(), format!("20250101{start} +0000"), format!("20250101{stop} +0000"));
+        assert_eq!(gaps, vec![
+            gap("c1", "010000", "020000"), gap("c1", "020000", "030000"), gap("c1", "030000", "033000"),
+            gap("c2", "000000", "010000"), gap("c2", "010000", "020000"), gap("c2", "020000", "030000"), gap("c2", "030000", "040000"),
+        ]);
+        let title = epg.children[4].children.as_ref().unwrap().iter().find(|tag| tag.name == "title").unwrap();
+        assert_eq!(title.value.as_deref(), Some("No information"));
+    }
+
+    #[test]
+    fn epg_fill_gaps_range_test() {
+        // the broken stop of c1 would fill c2 until 2099
+        let content = r#"<tv><channel id="c1"></channel><channel id="c2"></channel>
+            <programme channel="c1" start="20250101000000 +0000" stop="20991231000000 +0000"><title>News</title></programme></tv>"#;
+        let mut children = vec![];
+        parse_tvguide(content.as_bytes(), &mut |tag: XmlTag| if tag.name != "tv" { children.push(tag) });
+        let mut epg = Epg { attributes: None, children };
+        let options = EpgGapFillOptions { title: "No information".to_string(), block_minutes: 60, max_days: 1 };
+        fill_epg_gaps(&mut epg, &options, 1_735_689_600);
+        let gaps: Vec<&XmlTag> = epg.children.iter().skip(3).collect();
+        assert_eq!(gaps.len(), 24);
+        assert_eq!(gaps[23].get_attribute_value("stop").unwrap(), "20250102000000 +0000");
+    }
+
+    #[test]
+    fn fix_epg_time_offset_test() {
+        assert_eq!(fix_epg_time_offset("20250101120000 +-0100").as_deref(), Some("20250101120000 -0100"));
+        assert_eq!(fix_epg_time_offset("20250101120000 --01:00").as_deref(), Some("20250101120000 -0100"));
+        assert_eq!(fix_epg_time_offset("20250101120000 -100").as_deref(), Some("20250101120000 -0100"));
+        assert_eq!(fix_epg_time_offset("20250101120000 -0100"), None);
+        assert_eq!(fix_epg_time_offset("20250101120000 +0100"), None);
+    }
+}
@@ -1,15 +1,23 @@
 use std::cell::RefCell;
 use std::collections::HashMap;
+use std::path::Path;
 use std::rc::Rc;
 
+use log::debug;
+use serde::de::{SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer};
 use serde_json::Value;
 
 use crate::create_m3u_filter_error_result;
 use crate::m3u_filter_error::{M3uFilterError, M3uFilterErrorKind};
 use crate::model::config::ConfigInput;
 use crate::model::playlist::{PlaylistGroup, PlaylistItem, PlaylistItemHeader, PlaylistItemType, XtreamCluster};
+use crate::processing::parse_report::InputParseReport;
 use crate::model::xtream::{XtreamCategory, XtreamSeriesInfo, XtreamSeriesInfoEpisode, XtreamStream};
 use crate::repository::storage::hash_string;
+use crate::utils::compressed_file_reader::CompressedFileReader;
+use crate::utils::progress::update_progress;
+use crate::utils::request_utils::get_progress_name;
 
 fn map_to_xtream_category(categories: &Value) -> Result<Vec<XtreamCategory>, M3uFilterError> {
     match serde_json::from_value::<Vec<XtreamCategory>>(categories.to_owned()) {
@@ -20,15 +28,6 @@ fn map_to_xtream_category(categories: &Value) -> Result<Vec<XtreamCategory>, M3u
     }
 }
 
-fn map_to_xtream_streams(xtream_cluster: XtreamCluster, streams: &Value) -> Result<Vec<XtreamStream>, M3uFilterError> {
-    match serde_json::from_value::<Vec<XtreamStream>>(streams.to_owned()) {
-        Ok(stream_list) => Ok(stream_list),
-        Err(err) => {
-            create_m3u_filter_error_result!(M3uFilterErrorKind::Notify, "Failed to map to xtream streams {:?}: {}", xtream_cluster, &err)
-        }
-    }
-}
-
 fn create_xtream_series_info_url(url: &str, username: &str, password: &str, episode: &XtreamSeriesInfoEpisode) -> Rc<String> {
     if episode.direct_source.is_empty() {
         let ext = episode.container_extension.clone();
@@ -95,73 +94,225 @@ fn create_xtream_url(xtream_cluster: XtreamCluster, url: &str, username: &str, p
     }
 }
 
-pub fn parse_xtream(input: &ConfigInput,
-                    xtream_cluster: XtreamCluster,
-                    categories: &Value,
-                    streams: &Value) -> Result<Option<Vec<PlaylistGroup>>, M3uFilterError> {
-    match map_to_xtream_category(categories) {
-        Ok(xtream_categories) => {
-            let input_id = input.id;
-            let url = input.url.as_str();
-            let username = input.username.as_ref().map_or("", |v| v);
-            let password = input.password.as_ref().map_or("", |v| v);
-
-            match map_to_xtream_streams(xtream_cluster, streams) {
-                Ok(xtream_streams) => {
-                    let mut group_map: HashMap::<Rc<String>, RefCell<XtreamCategory>> =
-                        xtream_categories.into_iter().map(|category|
-                            (Rc::clone(&category.category_id), RefCell::new(category))
-                        ).collect();
-                    let unknown_grp = RefCell::new(XtreamCategory {
-                        category_id: Rc::new("0".to_string()),
-                        category_name: Rc::new("Unknown".to_string()),
-                        channels: vec![],
-                    });
-                    for stream in xtream_streams {
-                        let group = group_map.get(&stream.category_id).unwrap_or(&unknown_grp);
-                        let mut grp = group.borrow_mut();
-                        let category_name = &grp.category_name;
-                        let stream_url = create_xtream_url(xtream_cluster, url, username, password, &stream);
-                        let item = PlaylistItem {
-                            header: RefCell::new(PlaylistItemHeader {
-                                id: Rc::new(stream.get_stream_id().to_string()),
-                                uuid: Rc::new(hash_string(&stream_url)),
-                                name: Rc::clone(&stream.name),
-                                logo: Rc::clone(&stream.stream_icon),
-                                group: Rc::clone(category_name),
-                                title: Rc::clone(&stream.name),
-                                url: stream_url,
-                                epg_channel_id: stream.epg_channel_id.clone(),
-                                item_type: PlaylistItemType::from(xtream_cluster),
-                                xtream_cluster,
-                                additional_properties: stream.get_additional_properties(),
-                                category_id: 0,
-                                input_id,
-                                ..Default::default()
-                            }),
-                        };
-                        grp.add(item);
-                    }
-                    let has_channels = !unknown_grp.borrow().channels.is_empty();
-                    if has_channels {
-                        group_map.insert(Rc::new("0".to_string()), unknown_grp);
-                    }
-
-                    Ok(Some(group_map.values().filter(|category| !category.borrow().channels.is_empty())
-                        .map(|category| {
-                            let cat = category.borrow();
-                            PlaylistGroup {
-                                id: cat.category_id.parse::<u32>().unwrap_or(0),
-                                xtream_cluster,
-                                title: Rc::clone(&cat.category_name),
-                                channels: cat.channels.clone(),
-                            }
-                        }).collect()))
-                }
-                Err(err) => Err(err)
+// the parse progress is reported after this number of streams
+const PARSE_PROGRESS_INTERVAL: usize = 10_000;
+
+/// Groups the streams of a cluster into the playlist groups of their categories, streams with unknown category are in `Unknown`.
+struct XtreamPlaylistBuilder<'a> {
+    input: &'a ConfigInput,
+    xtream_cluster: XtreamCluster,
+    group_map: HashMap<Rc<String>, XtreamCategory>,
+    unknown_grp: XtreamCategory,
+    stream_count: usize,
+}
+
+impl<'a> XtreamPlaylistBuilder<'a> {
+    fn new(input: &'a ConfigInput, xtream_cluster: XtreamCluster, xtream_categories: Vec<XtreamCategory>) -> Self {
+        Self {
+            input,
+            xtream_cluster,
+            group_map: xtream_categories.into_iter().map(|category| (Rc::clone(&category.category_id), category)).collect(),
+            unknown_grp: XtreamCategory {
+                category_id: Rc::new("0".to_string()),
+                category_name: Rc::new("Unknown".to_string()),
+                channels: vec![],
+            },
+            stream_count: 0,
+        }
+    }
+
+    /// Malformed streams are skipped and recorded in the parse report.
+    fn add_stream_value(&mut self, value: &Value, parse_report: &mut InputParseReport) -> Result<(), M3uFilterError> {
+        let xtream_cluster = self.xtream_cluster;
+        match XtreamStream::deserialize(value) {
+            Ok(stream) if stream.get_stream_id() == 0 => parse_report.skip_entry(&format!("Missing {xtream_cluster} stream id"), &value.to_string()),
+            Ok(stream) => {
+                self.add_stream(&stream);
+                Ok(())
+            }
+            Err(err) => parse_report.skip_entry(&format!("Invalid {xtream_cluster} stream: {err}"), &value.to_string()),
+        }
+    }
+
+    fn add_stream(&mut self, stream: &XtreamStream) {
+        let input = self.input;
+        let xtream_cluster = self.xtream_cluster;
+        let url = input.url.as_str();
+        let username = input.username.as_ref().map_or("", |v| v);
+        let password = input.password.as_ref().map_or("", |v| v);
+        let grp = match self.group_map.get_mut(&stream.category_id) {
+            Some(group) => group,
+            None => &mut self.unknown_grp,
+        };
+        let stream_url = create_xtream_url(xtream_cluster, url, username, password, stream);
+        let item = PlaylistItem {
+            header: RefCell::new(PlaylistItemHeader {
+                id: Rc::new(stream.get_stream_id().to_string()),
+                uuid: Rc::new(hash_string(&stream_url)),
+                name: Rc::clone(&stream.name),
+                chno: Rc::new(stream.num.map_or_else(String::new, |num| num.to_string())),
+                logo: Rc::clone(&stream.stream_icon),
+                group: Rc::clone(&grp.category_name),
+                title: Rc::clone(&stream.name),
+                url: stream_url,
+                epg_channel_id: stream.epg_channel_id.clone(),
+                item_type: PlaylistItemType::from(xtream_cluster),
+                xtream_cluster,
+                additional_properties: stream.get_additional_properties(),
+                category_id: 0,
+                input_id: input.id,
+                ..Default::default()
+            }),
+        };
+        grp.add(item);
+        self.stream_count += 1;
+    }
+
+    fn build(mut self) -> Vec<PlaylistGroup> {
+        if !self.unknown_grp.channels.is_empty() {
+            self.group_map.insert(Rc::new("0".to_string()), self.unknown_grp);
+        }
+        let xtream_cluster = self.xtream_cluster;
+        self.group_map.into_values().filter(|category| !category.channels.is_empty())
+            .map(|category| PlaylistGroup {
+                id: category.category_id.parse::<u32>().unwrap_or(0),
+                xtream_cluster,
+                title: category.category_name,
+                channels: category.channels,
+            }).collect()
+    }
+}
+
+/// Visits the streams of the json list one by one, only one stream is held as json value.
+struct XtreamStreamsVisitor<'a, 'b> {
+    builder: &'a mut XtreamPlaylistBuilder<'b>,
+    parse_report: &'a mut InputParseReport,
+    // the error of the parse report, serde errors can only hold a message
+    error: &'a mut Option<M3uFilterError>,
+}
+
+impl<'de> Visitor<'de> for XtreamStreamsVisitor<'_, '_> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("a list of xtream streams")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let progress_name = get_progress_name(self.builder.input);
+        while let Some(value) = seq.next_element::<Value>()? {
+            if let Err(err) = self.builder.add_stream_value(&value, self.parse_report) {
+                *self.error = Some(err);
+                return Err(serde::de::Error::custom("parsing aborted"));
+            }
+            if self.builder.stream_count.is_multiple_of(PARSE_PROGRESS_INTERVAL) {
+                update_progress(&progress_name, format!("parsed {} {} streams", self.builder.stream_count, self.builder.xtream_cluster));
             }
         }
-        Err(err) => Err(err)
+        Ok(())
     }
 }
 
+/// Parses the streams incrementally from the downloaded json file, the response is not loaded into memory.
+/// Gzip and deflate compressed files are decompressed.
+pub fn parse_xtream_file(input: &ConfigInput,
+                         xtream_cluster: XtreamCluster,
+                         categories: &Value,
+                         streams_file: &Path,
+                         parse_report: &mut InputParseReport) -> Result<Option<Vec<PlaylistGroup>>, M3uFilterError> {
+    let mut builder = XtreamPlaylistBuilder::new(input, xtream_cluster, map_to_xtream_category(categories)?);
+    let is_empty = std::fs::metadata(streams_file).map_or(true, |metadata| metadata.len() == 0);
+    if is_empty {
+        return create_m3u_filter_error_result!(M3uFilterErrorKind::Notify, "Failed to map to xtream streams {:?}: empty response", xtream_cluster);
+    }
+    let reader = match CompressedFileReader::new(streams_file) {
+        Ok(reader) => reader,
+        Err(err) => return create_m3u_filter_error_result!(M3uFilterErrorKind::Notify, "Failed to read xtream streams {:?}: {err}", xtream_cluster),
+    };
+    let mut error = None;
+    let mut deserializer = serde_json::Deserializer::from_reader(reader);
+    let result = deserializer.deserialize_seq(XtreamStreamsVisitor { builder: &mut builder, parse_report, error: &mut error })
+        .and_then(|()| deserializer.end());
+    if let Some(err) = error {
+        return Err(err);
+    }
+    if let Err(err) = result {
+        return create_m3u_filter_error_result!(M3uFilterErrorKind::Notify, "Failed to map to xtream streams {:?}: {err}", xtream_cluster);
+    }
+    debug!("Parsed {} {xtream_cluster} streams of input {}", builder.stream_count, get_progress_name(input));
+    Ok(Some(builder.build()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use serde_json::{json, Value};
+    use tempfile::NamedTempFile;
+
+    use crate::m3u_filter_error::M3uFilterError;
+    use crate::model::config::ConfigInput;
+    use crate::model::playlist::{get_output_channel_number, PlaylistGroup, XtreamCluster};
+    use crate::processing::parse_report::InputParseReport;
+    use crate::processing::xtream_parser::parse_xtream_file;
+
+    fn parse_xtream(input: &ConfigInput, categories: &Value, streams: &[u8], parse_report: &mut InputParseReport) -> Result<Option<Vec<PlaylistGroup>>, M3uFilterError> {
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(streams).unwrap();
+        parse_xtream_file(input, XtreamCluster::Live, categories, file.path(), parse_report)
+    }
+
+    #[test]
+    fn channel_number_test() {
+        let input = ConfigInput { id: 1, url: "http://provider.tv".to_string(), ..Default::default() };
+        let categories = json!([{"category_id": "1", "category_name": "News"}]);
+        let streams = json!([
+            {"name": "News 1", "category_id": "1", "stream_id": 10, "num": "101"},
+            {"name": "News 2", "category_id": "1", "stream_id": 11}
+        ]);
+        let groups = parse_xtream(&input, &categories, streams.to_string().as_bytes(), &mut InputParseReport::new(&input)).unwrap().unwrap();
+        let channels = &groups[0].channels;
+        assert_eq!(channels[0].header.borrow().chno.as_str(), "101");
+        assert!(channels[1].header.borrow().chno.is_empty());
+        assert_eq!(channels[0].to_xtream().chno.as_str(), "101");
+        assert_eq!(get_output_channel_number(&channels[0].header.borrow().chno, 5, true), 101);
+        assert_eq!(get_output_channel_number(&channels[1].header.borrow().chno, 6, true), 6);
+        assert_eq!(get_output_channel_number(&channels[0].header.borrow().chno, 5, false), 5);
+    }
+
+    #[test]
+    fn skip_malformed_streams_test() {
+        let input = ConfigInput { id: 1, url: "http://provider.tv".to_string(), ..Default::default() };
+        let categories = json!([{"category_id": "1", "category_name": "News"}]);
+        let streams = json!([
+            {"name": "News 1", "category_id": "1", "stream_id": 10},
+            {"name": "News 2", "category_id": "1"},
+            {"name": "News 3", "category_id": "1", "stream_id": "abc"}
+        ]);
+        let mut parse_report = InputParseReport::new(&input);
+        let groups = parse_xtream(&input, &categories, streams.to_string().as_bytes(), &mut parse_report).unwrap().unwrap();
+        assert_eq!(groups[0].channels.len(), 1);
+        assert_eq!(parse_report.skipped.len(), 2);
+    }
+
+    #[test]
+    fn parse_streams_file_test() {
+        let input = ConfigInput { id: 1, url: "http://provider.tv".to_string(), ..Default::default() };
+        let categories = json!([{"category_id": "1", "category_name": "News"}]);
+        let streams = json!([
+            {"name": "News 1", "category_id": "1", "stream_id": 10},
+            {"name": "Other", "category_id": "2", "stream_id": 11}
+        ]);
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(streams.to_string().as_bytes()).unwrap();
+        let mut groups = parse_xtream(&input, &categories, &encoder.finish().unwrap(), &mut InputParseReport::new(&input)).unwrap().unwrap();
+        groups.sort_by(|a, b| a.title.cmp(&b.title));
+        assert_eq!(groups.iter().map(|group| (group.title.as_str(), group.channels.len())).collect::<Vec<_>>(), vec![("News", 1), ("Unknown", 1)]);
+
+        assert!(parse_xtream(&input, &categories, br#"{"user_info": {}}"#, &mut InputParseReport::new(&input)).is_err());
+        assert!(parse_xtream(&input, &categories, br#"[{"name": "News 1", "category_id": "1", "stream_id": 10}"#, &mut InputParseReport::new(&input)).is_err());
+        assert!(parse_xtream(&input, &categories, b"", &mut InputParseReport::new(&input)).is_err());
+    }
+}
@@ -1,15 +1,20 @@
 use crate::m3u_filter_error::{str_to_io_error, to_io_error, M3uFilterError, M3uFilterErrorKind};
 use crate::model::config::{Config, ConfigInput};
-use crate::model::playlist::{FetchedPlaylist, PlaylistEntry, PlaylistItem, PlaylistItemType, XtreamCluster};
+use crate::model::playlist::{FetchedPlaylist, PlaylistItem, PlaylistItemType, XtreamCluster};
+use crate::model::processing_job::ProcessingJob;
 use crate::repository::storage::get_input_storage_path;
 use crate::utils::download;
 use crate::{info_err, notify_err};
+use futures::stream::FuturesUnordered;
+use futures::StreamExt;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::fs;
 use std::fs::File;
-use std::io::{BufWriter, Write};
-use std::path::PathBuf;
+use std::io::{BufWriter, ErrorKind, Write};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use log::error;
 
 const FILE_SERIES_INFO: &str = "xtream_series_info";
 const FILE_VOD_INFO: &str = "xtream_vod_info";
@@ -42,34 +47,106 @@ use crate::utils::file_utils::append_or_crate_file;
 macro_rules! create_resolve_options_function_for_xtream_target {
     ($cluster:ident) => {
         paste::paste! {
-            fn [<get_resolve_ $cluster _options>](target: &ConfigTarget, fpl: &FetchedPlaylist) -> (bool, u16) {
-                let (resolve, resolve_delay) =
-                    target.options.as_ref().map_or((false, 0), |opt| {
+            fn [<get_resolve_ $cluster _options>](target: &ConfigTarget, fpl: &FetchedPlaylist) -> (bool, u16, u16) {
+                let (resolve, resolve_delay, resolve_concurrency) =
+                    target.options.as_ref().map_or((false, 0, 1), |opt| {
                         (opt.[<xtream_resolve_ $cluster>] && fpl.input.input_type == InputType::Xtream,
-                         opt.[<xtream_resolve_ $cluster _delay>])
+                         opt.[<xtream_resolve_ $cluster _delay>],
+                         opt.xtream_resolve_concurrency)
                     });
-                (resolve, resolve_delay)
+                (resolve, resolve_delay, resolve_concurrency)
             }
         }
     };
 }
 
-pub(in crate::processing) async fn playlist_resolve_download_playlist_item(client: Arc<reqwest::Client>, pli: &PlaylistItem, input: &ConfigInput, errors: &mut Vec<M3uFilterError>, resolve_delay: u16, cluster: XtreamCluster) -> Option<String> {
-    let mut result = None;
-    let provider_id = pli.get_provider_id()?;
-    if let Some(info_url) = download::get_xtream_player_api_info_url(input, cluster, provider_id) {
-        result = match download::get_xtream_stream_info_content(client, &info_url, input).await {
-            Ok(content) => Some(content),
-            Err(err) => {
-                errors.push(info_err!(format!("{err}")));
-                None
-            }
-        };
+// successful requests in a row until one more concurrent request is allowed
+const RESOLVE_CONCURRENCY_INCREASE_AFTER: usize = 20;
+
+/// Number of concurrent info requests. Failed requests or a rate limited provider halve the concurrency,
+/// a series of successful requests increases it again up to the configured maximum.
+#[derive(Debug)]
+pub(in crate::processing) struct AdaptiveConcurrency {
+    max: usize,
+    limit: usize,
+    successes: usize,
+}
+
+impl AdaptiveConcurrency {
+    pub fn new(max: u16) -> Self {
+        let max = usize::from(max.max(1));
+        Self { max, limit: max, successes: 0 }
+    }
+
+    pub fn get_limit(&self) -> usize {
+        self.limit
     }
+
+    pub fn record_success(&mut self) {
+        self.successes += 1;
+        if self.successes >= RESOLVE_CONCURRENCY_INCREASE_AFTER && self.limit < self.max {
+            self.limit += 1;
+            self.successes = 0;
+        }
+    }
+
+    pub fn record_failure(&mut self) {
+        self.limit = (self.limit / 2).max(1);
+        self.successes = 0;
+    }
+}
+
+async fn download_info_content(client: Arc<reqwest::Client>, input: &ConfigInput, provider_id: u32, resolve_delay: u16, cluster: XtreamCluster) -> Option<Result<String, String>> {
+    let info_url = download::get_xtream_player_api_info_url(input, cluster, provider_id)?;
+    let result = download::get_xtream_stream_info_content(client, &info_url, input).await.map_err(|err| err.to_string());
+    // the delay is applied per request slot
     if resolve_delay > 0 {
         actix_web::rt::time::sleep(std::time::Duration::new(u64::from(resolve_delay), 0)).await;
     }
-    result
+    Some(result)
+}
+
+/// Downloads the info of the items with up to `concurrency` parallel requests, see `AdaptiveConcurrency`.
+#[allow(clippy::too_many_arguments)]
+/// The content is passed to `on_content` in the order of arrival with the key of the item.
+/// A cancelled job stops the download, the running requests are dropped.
+pub(in crate::processing) async fn playlist_resolve_download_playlist_items<K, I, F>(client: Arc<reqwest::Client>, input: &ConfigInput, items: I,
+                                                                                  errors: &mut Vec<M3uFilterError>, resolve_delay: u16, concurrency: u16,
+                                                                                  cluster: XtreamCluster, job: &ProcessingJob, mut on_content: F)
+where
+    I: Iterator<Item=(K, u32)>,
+    F: FnMut(K, String, &mut Vec<M3uFilterError>) -> bool,
+{
+    let mut adaptive = AdaptiveConcurrency::new(concurrency);
+    let mut items = items.peekable();
+    let mut in_flight = FuturesUnordered::new();
+    let start_request = |key: K, provider_id: u32| {
+        let client = Arc::clone(&client);
+        async move { (key, download_info_content(client, input, provider_id, resolve_delay, cluster).await) }
+    };
+    loop {
+        if job.is_cancelled() {
+            return;
+        }
+        while in_flight.len() < adaptive.get_limit() {
+            let Some((key, provider_id)) = items.next() else { break; };
+            in_flight.push(start_request(key, provider_id));
+        }
+        let Some((key, result)) = in_flight.next().await else { break; };
+        match result {
+            Some(Ok(content)) => {
+                if input.t_rate_limit.get_state().is_some() { adaptive.record_failure(); } else { adaptive.record_success(); }
+                if !on_content(key, content, errors) {
+                    return;
+                }
+            }
+            Some(Err(err)) => {
+                adaptive.record_failure();
+                errors.push(info_err!(err));
+            }
+            None => {}
+        }
+    }
 }
 
 pub(in crate::processing) fn write_info_content_to_wal_file(writer: &mut BufWriter<&File>, provider_id: u32, content: &str) -> std::io::Result<()> {
@@ -82,6 +159,17 @@ pub(in crate::processing) fn write_info_content_to_wal_file(writer: &mut BufWrit
     Ok(())
 }
 
+/// Removes the wal files of a cancelled resolve, the partial content is not applied.
+pub(in crate::processing) fn remove_resolve_wal_files(paths: &[&Path]) {
+    for path in paths {
+        if let Err(err) = fs::remove_file(path) {
+            if err.kind() != ErrorKind::NotFound {
+                error!("Failed to remove wal file {} {err}", path.display());
+            }
+        }
+    }
+}
+
 pub(in crate::processing) fn create_resolve_episode_wal_files(cfg: &Config, input: &ConfigInput) -> Option<(File, PathBuf)> {
     match get_input_storage_path(input, &cfg.working_dir) {
         Ok(storage_path) => {
@@ -154,3 +242,52 @@ where
     }
     processed_info_ids
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::model::config::ConfigInput;
+    use crate::model::playlist::XtreamCluster;
+    use crate::model::processing_job::ProcessingJobs;
+    use crate::processing::xtream_processor::{playlist_resolve_download_playlist_items, remove_resolve_wal_files, AdaptiveConcurrency, RESOLVE_CONCURRENCY_INCREASE_AFTER};
+
+    #[test]
+    fn adaptive_concurrency_test() {
+        let mut concurrency = AdaptiveConcurrency::new(8);
+        assert_eq!(concurrency.get_limit(), 8);
+        concurrency.record_failure();
+        concurrency.record_failure();
+        assert_eq!(concurrency.get_limit(), 2);
+        concurrency.record_failure();
+        concurrency.record_failure();
+        assert_eq!(concurrency.get_limit(), 1);
+        for _ in 0..RESOLVE_CONCURRENCY_INCREASE_AFTER {
+            concurrency.record_success();
+        }
+        assert_eq!(concurrency.get_limit(), 2);
+        assert_eq!(AdaptiveConcurrency::new(0).get_limit(), 1);
+    }
+
+    #[actix_rt::test]
+    async fn cancelled_resolve_test() {
+        let jobs = ProcessingJobs::default();
+        let job = jobs.start(vec![]).unwrap();
+        assert!(jobs.cancel(job.get_info().id));
+        let input = ConfigInput { id: 1, url: "http://127.0.0.1:1".to_string(), ..Default::default() };
+        let mut errors = vec![];
+        let mut received = 0;
+        playlist_resolve_download_playlist_items(Arc::new(reqwest::Client::new()), &input, (1..=3).map(|id| ((), id)), &mut errors,
+                                                 0, 2, XtreamCluster::Video, &job, |(), _, _| { received += 1; true }).await;
+        assert_eq!(received, 0);
+        assert!(errors.is_empty());
+
+        let dir = tempfile::tempdir().unwrap();
+        let content_path = dir.path().join("vod_info_content.wal");
+        let record_path = dir.path().join("vod_info_record.wal");
+        std::fs::write(&content_path, b"partial").unwrap();
+        // a missing wal file is ignored
+        remove_resolve_wal_files(&[&content_path, &record_path]);
+        assert!(!content_path.exists());
+    }
+}
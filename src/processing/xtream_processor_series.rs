@@ -3,7 +3,8 @@ use crate::model::config::{Config, ConfigTarget, InputType};
 use crate::model::playlist::{FetchedPlaylist, PlaylistGroup, PlaylistItem, PlaylistItemType, XtreamCluster};
 use crate::processing::playlist_processor::ProcessingPipe;
 use crate::processing::xtream_parser::parse_xtream_series_info;
-use crate::processing::xtream_processor::{create_resolve_episode_wal_files, create_resolve_info_wal_files, playlist_resolve_download_playlist_item, read_processed_info_ids, should_update_info, write_info_content_to_wal_file};
+use crate::model::processing_job::ProcessingJob;
+use crate::processing::xtream_processor::{create_resolve_episode_wal_files, create_resolve_info_wal_files, playlist_resolve_download_playlist_items, read_processed_info_ids, remove_resolve_wal_files, should_update_info, write_info_content_to_wal_file};
 use crate::repository::storage::get_input_storage_path;
 use crate::repository::xtream_repository::{xtream_get_info_file_paths, xtream_update_input_info_file, xtream_update_input_series_episodes_record_from_wal_file, xtream_update_input_series_record_from_wal_file};
 use crate::repository::IndexedDocumentReader;
@@ -55,7 +56,7 @@ fn should_update_series_info(pli: &PlaylistItem, processed_provider_ids: &HashMa
 }
 
 async fn playlist_resolve_series_info(client: Arc<reqwest::Client>, cfg: &Config, errors: &mut Vec<M3uFilterError>,
-                                      fpl: &mut FetchedPlaylist<'_>, resolve_delay: u16) -> bool {
+                                      fpl: &mut FetchedPlaylist<'_>, resolve_delay: u16, resolve_concurrency: u16, job: &ProcessingJob) -> bool {
     let mut processed_info_ids = read_processed_series_info_ids(cfg, errors, fpl).await;
     // we cant write to the indexed-document directly because of the write lock and time-consuming operation.
     // All readers would be waiting for the lock and the app would be unresponsive.
@@ -67,28 +68,29 @@ async fn playlist_resolve_series_info(client: Arc<reqwest::Client>, cfg: &Config
     let mut record_writer = file_writer(&wal_record_file);
     let mut content_updated = false;
 
-    let series_info_iter = fpl.playlistgroups.iter()
+    let series_info_ids: Vec<((u32, u64), u32)> = fpl.playlistgroups.iter()
         .filter(|&plg| plg.xtream_cluster == XtreamCluster::Series)
         .flat_map(|plg| &plg.channels)
-        .filter(|&pli| pli.header.borrow().item_type == PlaylistItemType::SeriesInfo);
+        .filter(|&pli| pli.header.borrow().item_type == PlaylistItemType::SeriesInfo)
+        .map(|pli| should_update_series_info(pli, &processed_info_ids))
+        .filter(|(should_update, _, _)| *should_update)
+        .map(|(_, provider_id, ts)| ((provider_id, ts), provider_id))
+        .collect();
 
-    let series_info_count = series_info_iter.clone().count();
+    let series_info_count = series_info_ids.len();
     info!("Found {series_info_count} series info to resolve");
     let start_time = Instant::now();
     let mut processed_series_info_count = 0;
     let mut last_processed_series_info_count = 0;
-    for pli in series_info_iter {
-        let (should_update, provider_id, ts) = should_update_series_info(pli, &processed_info_ids);
-        if should_update {
-            if let Some(content) = playlist_resolve_download_playlist_item(Arc::clone(&client), pli, fpl.input, errors, resolve_delay, XtreamCluster::Series).await {
-                handle_error_and_return!(write_info_content_to_wal_file(&mut content_writer, provider_id, &content),
-                    |err| errors.push(notify_err!(format!("Failed to resolve series, could not write to content wal file {err}"))));
-                processed_info_ids.insert(provider_id, ts);
-                handle_error_and_return!(write_series_info_record_to_wal_file(&mut record_writer, provider_id, ts),
-                    |err| errors.push(notify_err!(format!("Failed to resolve series wal, could not write to record wal file {err}"))));
-                content_updated = true;
-            }
-        }
+    let mut write_failed = false;
+    playlist_resolve_download_playlist_items(Arc::clone(&client), fpl.input, series_info_ids.into_iter(), errors, resolve_delay, resolve_concurrency,
+                                             XtreamCluster::Series, job, |(provider_id, ts), content, errors| {
+        handle_error_and_return!(write_info_content_to_wal_file(&mut content_writer, provider_id, &content),
+            |err| { errors.push(notify_err!(format!("Failed to resolve series, could not write to content wal file {err}"))); write_failed = true; });
+        processed_info_ids.insert(provider_id, ts);
+        handle_error_and_return!(write_series_info_record_to_wal_file(&mut record_writer, provider_id, ts),
+            |err| { errors.push(notify_err!(format!("Failed to resolve series wal, could not write to record wal file {err}"))); write_failed = true; });
+        content_updated = true;
         if log_enabled!(Level::Info) {
             processed_series_info_count += 1;
             let elapsed = start_time.elapsed().as_secs();
@@ -97,6 +99,16 @@ async fn playlist_resolve_series_info(client: Arc<reqwest::Client>, cfg: &Config
                 last_processed_series_info_count = processed_series_info_count;
             }
         }
+        true
+    }).await;
+    if write_failed {
+        return false;
+    }
+    if job.is_cancelled() {
+        drop(content_writer);
+        drop(record_writer);
+        remove_resolve_wal_files(&[&wal_content_path, &wal_record_path]);
+        return false;
     }
     if last_processed_series_info_count != processed_series_info_count {
         info!("resolved {processed_series_info_count}/{series_info_count} series info");
@@ -209,17 +221,18 @@ async fn process_series_info(
     result
 }
 
-
+#[allow(clippy::too_many_arguments)]
 pub async fn playlist_resolve_series(client: Arc<reqwest::Client>, cfg: &Config, target: &ConfigTarget,
                                      errors: &mut Vec<M3uFilterError>,
                                      pipe: &ProcessingPipe,
                                      provider_fpl: &mut FetchedPlaylist<'_>,
                                      processed_fpl: &mut FetchedPlaylist<'_>,
+                                     job: &ProcessingJob,
 ) {
-    let (resolve_series, resolve_delay) = get_resolve_series_options(target, processed_fpl);
+    let (resolve_series, resolve_delay, resolve_concurrency) = get_resolve_series_options(target, processed_fpl);
     if !resolve_series { return; }
 
-    if !playlist_resolve_series_info(client, cfg, errors, processed_fpl, resolve_delay).await { return; }
+    if !playlist_resolve_series_info(client, cfg, errors, processed_fpl, resolve_delay, resolve_concurrency, job).await || job.is_cancelled() { return; }
     let series_playlist = process_series_info(cfg, provider_fpl, errors).await;
     if series_playlist.is_empty() { return; }
     // original content saved into original list
@@ -228,7 +241,7 @@ pub async fn playlist_resolve_series(client: Arc<reqwest::Client>, cfg: &Config,
     }
     // run processing pipe over new items
     let mut new_playlist = series_playlist;
-    for f in pipe {
+    for (_, f) in pipe {
         if let Some(v) = f(&mut new_playlist, target) {
             new_playlist = v;
         }
@@ -1,7 +1,8 @@
 use crate::m3u_filter_error::{M3uFilterError, M3uFilterErrorKind};
 use crate::model::config::{Config, ConfigTarget, InputType};
 use crate::model::playlist::{FetchedPlaylist, PlaylistItem, PlaylistItemType, XtreamCluster};
-use crate::processing::xtream_processor::{create_resolve_info_wal_files, playlist_resolve_download_playlist_item, read_processed_info_ids, should_update_info, write_info_content_to_wal_file};
+use crate::model::processing_job::ProcessingJob;
+use crate::processing::xtream_processor::{create_resolve_info_wal_files, playlist_resolve_download_playlist_items, read_processed_info_ids, remove_resolve_wal_files, should_update_info, write_info_content_to_wal_file};
 use crate::repository::xtream_repository::{xtream_update_input_info_file, xtream_update_input_vod_record_from_wal_file, InputVodInfoRecord};
 use crate::{create_resolve_options_function_for_xtream_target, handle_error, handle_error_and_return, notify_err};
 use crate::utils::json_utils::{get_u32_from_serde_value, get_u64_from_serde_value};
@@ -65,8 +66,9 @@ fn should_update_vod_info(pli: &PlaylistItem, processed_provider_ids: &HashMap<u
     should_update_info(pli, processed_provider_ids, TAG_VOD_INFO_ADDED)
 }
 
-pub async fn playlist_resolve_vod(client: Arc<reqwest::Client>, cfg: &Config, target: &ConfigTarget, errors: &mut Vec<M3uFilterError>, fpl: &FetchedPlaylist<'_>) {
-    let (resolve_movies, resolve_delay) = get_resolve_vod_options(target, fpl);
+pub async fn playlist_resolve_vod(client: Arc<reqwest::Client>, cfg: &Config, target: &ConfigTarget, errors: &mut Vec<M3uFilterError>,
+                                  fpl: &FetchedPlaylist<'_>, job: &ProcessingJob) {
+    let (resolve_movies, resolve_delay, resolve_concurrency) = get_resolve_vod_options(target, fpl);
     if !resolve_movies { return; }
 
     // we cant write to the indexed-document directly because of the write lock and time-consuming operation.
@@ -80,31 +82,32 @@ pub async fn playlist_resolve_vod(client: Arc<reqwest::Client>, cfg: &Config, ta
     let mut record_writer = file_writer(&wal_record_file);
     let mut content_updated = false;
 
-    let vod_info_iter = fpl.playlistgroups.iter()
+    let vod_info_ids: Vec<((), u32)> = fpl.playlistgroups.iter()
         .flat_map(|plg| &plg.channels)
-        .filter(|&pli| pli.header.borrow().xtream_cluster == XtreamCluster::Video);
+        .filter(|&pli| pli.header.borrow().xtream_cluster == XtreamCluster::Video)
+        .map(|pli| should_update_vod_info(pli, &processed_info_ids))
+        .filter(|(should_update, _, _)| *should_update)
+        .map(|(_, provider_id, _)| ((), provider_id))
+        .collect();
 
-    let vod_info_count = vod_info_iter.clone().count();
+    let vod_info_count = vod_info_ids.len();
 
     info!("Found {vod_info_count} vod info to resolve");
     let start_time = Instant::now();
     let mut processed_vod_info_count = 0;
     let mut last_processed_vod_info_count = 0;
-
-    for pli in  vod_info_iter {
-        let (should_update, _provider_id, _ts) = should_update_vod_info(pli, &processed_info_ids);
-        if should_update {
-            if let Some(content) = playlist_resolve_download_playlist_item(Arc::clone(&client), pli, fpl.input, errors, resolve_delay, XtreamCluster::Video).await {
-                if let Some((provider_id, info_record)) = extract_info_record_from_vod_info(&content) {
-                    let ts = info_record.ts;
-                    handle_error_and_return!(write_info_content_to_wal_file(&mut content_writer, provider_id, &content),
-                        |err| errors.push(notify_err!(format!("Failed to resolve vod, could not write to content wal file {err}"))));
-                    processed_info_ids.insert(provider_id, ts);
-                    handle_error_and_return!(write_vod_info_record_to_wal_file(&mut record_writer, provider_id, &info_record),
-                        |err| errors.push(notify_err!(format!("Failed to resolve vod wal, could not write to record wal file {err}"))));
-                    content_updated = true;
-                }
-            }
+    let mut write_failed = false;
+
+    playlist_resolve_download_playlist_items(Arc::clone(&client), fpl.input, vod_info_ids.into_iter(), errors, resolve_delay, resolve_concurrency,
+                                             XtreamCluster::Video, job, |(), content, errors| {
+        if let Some((provider_id, info_record)) = extract_info_record_from_vod_info(&content) {
+            let ts = info_record.ts;
+            handle_error_and_return!(write_info_content_to_wal_file(&mut content_writer, provider_id, &content),
+                |err| { errors.push(notify_err!(format!("Failed to resolve vod, could not write to content wal file {err}"))); write_failed = true; });
+            processed_info_ids.insert(provider_id, ts);
+            handle_error_and_return!(write_vod_info_record_to_wal_file(&mut record_writer, provider_id, &info_record),
+                |err| { errors.push(notify_err!(format!("Failed to resolve vod wal, could not write to record wal file {err}"))); write_failed = true; });
+            content_updated = true;
         }
         if log_enabled!(Level::Info) {
             processed_vod_info_count += 1;
@@ -114,6 +117,16 @@ pub async fn playlist_resolve_vod(client: Arc<reqwest::Client>, cfg: &Config, ta
                 last_processed_vod_info_count = processed_vod_info_count;
             }
         }
+        true
+    }).await;
+    if write_failed {
+        return;
+    }
+    if job.is_cancelled() {
+        drop(content_writer);
+        drop(record_writer);
+        remove_resolve_wal_files(&[&wal_content_path, &wal_record_path]);
+        return;
     }
     if last_processed_vod_info_count != processed_vod_info_count {
         info!("resolved {processed_vod_info_count}/{vod_info_count} vod info");
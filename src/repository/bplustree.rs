@@ -487,6 +487,13 @@ where
         }
     }
 
+    /// Loads the tree completely and writes it back, dropping unreachable blocks.
+    pub fn compact(filepath: &Path) -> io::Result<u64> {
+        let mut tree = Self::load(filepath)?;
+        tree.dirty = true;
+        tree.store(filepath)
+    }
+
     pub fn load(filepath: &Path) -> io::Result<Self> {
         let file = is_file_valid(File::open(filepath)?)?;
         let mut reader = file_reader(file);
@@ -896,4 +903,33 @@ mod tests {
         assert!(entry_set.is_empty());
         Ok(())
     }
+
+    #[test]
+    fn compact_test() -> io::Result<()> {
+        let mut tree = BPlusTree::<u32, Record>::new();
+        for i in 0u32..=500 {
+            tree.insert(i, Record {
+                id: i,
+                data: format!("Entry {i}"),
+            });
+        }
+        let filepath = PathBuf::from("/tmp/tree_compact.bin");
+        tree.store(&filepath)?;
+        let mut tree_update = BPlusTreeUpdate::<u32, Record>::try_new(&filepath)?;
+        for i in 0u32..=500 {
+            tree_update.update(&i, Record {
+                id: i,
+                data: format!("Entry {}", i + 9000),
+            })?;
+        }
+        drop(tree_update);
+        let size_before = std::fs::metadata(&filepath)?.len();
+        BPlusTree::<u32, Record>::compact(&filepath)?;
+        assert!(std::fs::metadata(&filepath)?.len() <= size_before);
+        let tree: BPlusTree<u32, Record> = BPlusTree::load(&filepath)?;
+        for (key, value) in tree.iter() {
+            assert!(format!("Entry {}", key + 9000).eq(&value.data), "Wrong entry");
+        }
+        Ok(())
+    }
 }
@@ -0,0 +1,171 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use log::error;
+use serde::{Deserialize, Serialize};
+
+use crate::repository::user_repository::load_user_file;
+use crate::utils::json_utils::json_write_documents_to_file;
+
+const FILE_CHANNEL_NOTES: &str = "channel_notes.json";
+
+/// Operator note and labels of a channel, e.g. for curation workflows.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChannelNote {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub note: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub labels: Vec<String>,
+    #[serde(default)]
+    pub updated: i64,
+}
+
+impl ChannelNote {
+    /// Trims the note and labels, empty and duplicate labels are removed.
+    /// Commas and quotes are replaced, the labels are emitted as comma separated m3u attribute.
+    pub fn new(note: &str, labels: &[String]) -> Self {
+        let mut cleaned: Vec<String> = vec![];
+        for label in labels.iter().map(|label| label.replace([',', '"'], " ").trim().to_string()).filter(|label| !label.is_empty()) {
+            if !cleaned.iter().any(|existing| existing.eq_ignore_ascii_case(&label)) {
+                cleaned.push(label);
+            }
+        }
+        Self { note: note.trim().to_string(), labels: cleaned, updated: chrono::Utc::now().timestamp() }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.note.is_empty() && self.labels.is_empty()
+    }
+
+    /// Case-insensitive match of the note or one of the labels.
+    pub fn matches(&self, query: &str) -> bool {
+        let query = query.trim().to_lowercase();
+        query.is_empty() || self.note.to_lowercase().contains(&query)
+            || self.labels.iter().any(|label| label.to_lowercase().contains(&query))
+    }
+}
+
+/// Notes of the channels keyed by the hex uuid of the channel, the uuid is stable across playlist updates.
+/// The notes are stored in the working directory, the file is written outside the lock of the notes.
+#[derive(Debug)]
+pub struct ChannelNotes {
+    file: Option<PathBuf>,
+    notes: Mutex<(HashMap<String, ChannelNote>, u64)>,
+    // the revision of the written file, an older snapshot is not written after a newer one
+    persisted: tokio::sync::Mutex<u64>,
+}
+
+impl ChannelNotes {
+    pub fn new(working_dir: &str) -> Self {
+        let file = get_channel_notes_file_path(working_dir);
+        let notes = load_user_file(&file, "channel notes");
+        Self { file: Some(file), notes: Mutex::new((notes, 0)), persisted: tokio::sync::Mutex::new(0) }
+    }
+
+    async fn persist(&self, notes: HashMap<String, ChannelNote>, revision: u64) {
+        let Some(file) = self.file.clone() else { return; };
+        let mut persisted = self.persisted.lock().await;
+        if *persisted >= revision {
+            return;
+        }
+        let path = file.clone();
+        match tokio::task::spawn_blocking(move || json_write_documents_to_file(&path, &notes).map_err(|err| err.to_string())).await
+            .map_err(|err| err.to_string()).and_then(|result| result) {
+            Ok(()) => *persisted = revision,
+            Err(err) => error!("Failed to write channel notes {}: {err}", file.display()),
+        }
+    }
+
+    /// Applies the change and returns a snapshot for the persist, `None` if nothing changed.
+    fn update<F: FnOnce(&mut HashMap<String, ChannelNote>) -> bool>(&self, change: F) -> Option<(HashMap<String, ChannelNote>, u64)> {
+        let mut guard = self.notes.lock().ok()?;
+        let (notes, revision) = &mut *guard;
+        if !change(notes) {
+            return None;
+        }
+        *revision += 1;
+        Some((notes.clone(), *revision))
+    }
+
+    /// The notes matching the query, all notes for an empty query.
+    pub fn search(&self, query: &str) -> HashMap<String, ChannelNote> {
+        self.notes.lock().map(|guard| guard.0.iter()
+            .filter(|(_, note)| note.matches(query))
+            .map(|(uuid, note)| (uuid.clone(), note.clone())).collect()).unwrap_or_default()
+    }
+
+    pub fn get(&self, uuid: &str) -> Option<ChannelNote> {
+        self.notes.lock().ok()?.0.get(&uuid.to_uppercase()).cloned()
+    }
+
+    /// Sets the note of the channel, an empty note removes it.
+    pub async fn set(&self, uuid: &str, note: ChannelNote) {
+        let snapshot = self.update(|notes| {
+            if note.is_empty() {
+                notes.remove(&uuid.to_uppercase());
+            } else {
+                notes.insert(uuid.to_uppercase(), note);
+            }
+            true
+        });
+        if let Some((notes, revision)) = snapshot {
+            self.persist(notes, revision).await;
+        }
+    }
+
+    pub async fn remove(&self, uuid: &str) -> bool {
+        match self.update(|notes| notes.remove(&uuid.to_uppercase()).is_some()) {
+            Some((notes, revision)) => {
+                self.persist(notes, revision).await;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// A channel uuid is the 64 character hex encoded hash of the channel, the keys are upper case.
+pub fn is_valid_channel_uuid(uuid: &str) -> bool {
+    uuid.len() == 64 && uuid.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+fn get_channel_notes_file_path(working_dir: &str) -> PathBuf {
+    PathBuf::from(working_dir).join(FILE_CHANNEL_NOTES)
+}
+
+/// The stored channel notes, read by the playlist update to emit the notes.
+pub fn channel_notes_read(working_dir: &str) -> HashMap<String, ChannelNote> {
+    load_user_file(&get_channel_notes_file_path(working_dir), "channel notes")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    use crate::repository::channel_notes_repository::{is_valid_channel_uuid, ChannelNote, ChannelNotes};
+
+    #[actix_rt::test]
+    async fn channel_notes_test() {
+        let notes = ChannelNotes { file: None, notes: Mutex::new((HashMap::new(), 0)), persisted: tokio::sync::Mutex::new(0) };
+        let uuid = "AB".repeat(32);
+        assert!(is_valid_channel_uuid(&uuid));
+        assert!(!is_valid_channel_uuid("abc"));
+
+        let note = ChannelNote::new(" check audio ", &["Sports".to_string(), " sports".to_string(), "a,\"b\"".to_string(), String::new()]);
+        assert_eq!(note.note, "check audio");
+        assert_eq!(note.labels, vec!["Sports".to_string(), "a  b".to_string()]);
+        notes.set(&uuid, note).await;
+        assert!(notes.get(&uuid.to_lowercase()).is_some());
+        assert_eq!(notes.search("SPORT").len(), 1);
+        assert_eq!(notes.search("audio").len(), 1);
+        assert!(notes.search("news").is_empty());
+
+        notes.set(&uuid, ChannelNote::new("", &[])).await;
+        assert!(notes.get(&uuid).is_none());
+        notes.set(&uuid, ChannelNote::new("x", &[])).await;
+        assert!(notes.remove(&uuid).await);
+        assert!(!notes.remove(&uuid).await);
+    }
+}
@@ -1,27 +1,45 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::fs::File;
+use std::fmt;
 use std::io::{Cursor, Write};
-use std::path::{Path};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use chrono::{DateTime, NaiveDateTime};
+use log::error;
 use quick_xml::{Writer};
+use serde::{Deserialize, Serialize};
 use crate::{debug_if_enabled, notify_err};
 use crate::m3u_filter_error::{M3uFilterError, M3uFilterErrorKind};
 use crate::model::config::{Config, ConfigTarget, TargetOutput};
 use crate::model::config::TargetType;
-use crate::model::xmltv::{Epg};
+use crate::model::playlist::PlaylistGroup;
+use crate::model::xmltv::{Epg, TVGuide, XmlTag, EPG_ATTRIB_CHANNEL, EPG_ATTRIB_SRC, EPG_ATTRIB_START, EPG_ATTRIB_STOP, EPG_TAG_CATEGORY, EPG_TAG_DESC, EPG_TAG_ICON, EPG_TAG_PROGRAMME, EPG_TAG_TITLE};
+use crate::processing::xmltv_parser::parse_tvguide;
+use crate::repository::playlist_repository::load_target_playlist;
+use crate::repository::storage::hash_string_as_hex;
+use crate::utils::file_lock_manager::FileLockManager;
+use crate::utils::file_utils::file_reader;
+use crate::utils::output_encoding::{apply_newline_style, get_bom, get_newline};
 use crate::repository::m3u_repository::{m3u_get_epg_file_path};
+use crate::repository::stalker_repository::stalker_get_epg_file_path;
 use crate::repository::xtream_repository::{xtream_get_epg_file_path, xtream_get_storage_path};
 
 fn epg_write_file(target: &ConfigTarget, epg: &Epg, path: &Path) -> Result<(), M3uFilterError> {
     let mut writer = Writer::new(Cursor::new(vec![]));
     match epg.write_to(&mut writer) {
         Ok(()) => {
-            let result = writer.into_inner().into_inner();
+            let encoding = target.get_output_encoding();
+            let content = writer.into_inner().into_inner();
+            let result = apply_newline_style(&String::from_utf8_lossy(&content), encoding).into_owned();
             match File::create(path) {
                 Ok(mut epg_file) => {
-                    match epg_file.write_all("<?xml version=\"1.0\" encoding=\"utf-8\" ?><!DOCTYPE tv SYSTEM \"xmltv.dtd\">".as_bytes()) {
+                    let header = format!("<?xml version=\"1.0\" encoding=\"utf-8\" ?>{}<!DOCTYPE tv SYSTEM \"xmltv.dtd\">{}", get_newline(encoding), get_newline(encoding));
+                    match epg_file.write_all(&[get_bom(encoding), header.as_bytes()].concat()) {
                         Ok(()) => {}
                         Err(err) => return Err(notify_err!(format!("failed to write epg: {} - {}", path.to_str().unwrap_or("?"), err))),
                     }
-                    match epg_file.write_all(&result) {
+                    match epg_file.write_all(result.as_bytes()) {
                         Ok(()) => {
                            debug_if_enabled!("Epg for target {} written to {}", target.name, path.to_str().unwrap_or("?"));
                         }
@@ -36,26 +54,338 @@ fn epg_write_file(target: &ConfigTarget, epg: &Epg, path: &Path) -> Result<(), M
     Ok(())
 }
 
-pub fn epg_write(target: &ConfigTarget, cfg: &Config, target_path: &Path, epg: Option<&Epg>, output: &TargetOutput) -> Result<(), M3uFilterError> {
+/// The epg variants of a target epg are stored in the directory `<epg file>.variants`.
+fn get_epg_variants_dir(epg_path: &Path) -> PathBuf {
+    let file_name = epg_path.file_name().map_or_else(|| "epg".to_string(), |name| name.to_string_lossy().to_string());
+    epg_path.with_file_name(format!("{file_name}.variants"))
+}
+
+/// The epg variant of a bouquet channel set, named by the hash of the virtual ids.
+fn get_epg_variant_file_path(epg_path: &Path, virtual_ids: &BTreeSet<u32>) -> PathBuf {
+    let ids = virtual_ids.iter().map(u32::to_string).collect::<Vec<_>>().join(",");
+    get_epg_variants_dir(epg_path).join(format!("{}.xml", hash_string_as_hex(&ids)))
+}
+
+/// The epg channel ids of the bouquet channels.
+fn get_bouquet_epg_channel_ids(playlist: &[PlaylistGroup], virtual_ids: &BTreeSet<u32>) -> HashSet<String> {
+    playlist.iter()
+        .flat_map(|group| &group.channels)
+        .filter_map(|channel| {
+            let header = channel.header.borrow();
+            header.epg_channel_id.as_ref().filter(|_| virtual_ids.contains(&header.virtual_id)).map(ToString::to_string)
+        })
+        .collect()
+}
+
+/// Removes the bouquet variants of the epg, they are written again on request with the new epg.
+async fn epg_remove_variants(cfg: &Config, epg_path: &Path) -> Result<(), M3uFilterError> {
+    let variants_dir = get_epg_variants_dir(epg_path);
+    let _file_lock = cfg.file_locks.write_lock(&variants_dir).await.map_err(|err| notify_err!(format!("failed to remove epg variants: {} - {err}", variants_dir.display())))?;
+    if variants_dir.exists() {
+        if let Err(err) = std::fs::remove_dir_all(&variants_dir) {
+            return Err(notify_err!(format!("failed to remove epg variants: {} - {err}", variants_dir.display())));
+        }
+    }
+    Ok(())
+}
+
+/// The epg variant with the channels of the bouquet virtual ids, see `epg_bouquet_variants`.
+/// The variant is written on the first request and shared by all users with the same bouquet channels.
+pub async fn epg_get_bouquet_variant(cfg: &Config, target: &ConfigTarget, epg_path: &Path, virtual_ids: &BTreeSet<u32>) -> Option<PathBuf> {
+    let variants_dir = get_epg_variants_dir(epg_path);
+    let variant_path = get_epg_variant_file_path(epg_path, virtual_ids);
+    // same lock order as epg_write, the epg before its variants
+    let _epg_lock = cfg.file_locks.read_lock(epg_path).await.ok()?;
+    let _file_lock = cfg.file_locks.write_lock(&variants_dir).await.ok()?;
+    if variant_path.exists() {
+        return Some(variant_path);
+    }
+    let channel_ids = get_bouquet_epg_channel_ids(&load_target_playlist(cfg, target).await.ok()?, virtual_ids);
+    let (target, epg_file, path) = (target.clone(), epg_path.to_path_buf(), variant_path.clone());
+    let result = tokio::task::spawn_blocking(move || {
+        let channel_ids: HashSet<Rc<String>> = channel_ids.into_iter().map(Rc::new).collect();
+        let variant = TVGuide { file: epg_file }.filter(&channel_ids).unwrap_or(Epg { attributes: None, children: vec![] });
+        std::fs::create_dir_all(&variants_dir).map_err(|err| notify_err!(format!("failed to create epg variants: {} - {err}", variants_dir.display())))?;
+        epg_write_file(&target, &variant, &path)
+    }).await;
+    match result {
+        Ok(Ok(())) => Some(variant_path),
+        Ok(Err(err)) => {
+            error!("{}", err.message);
+            None
+        }
+        Err(err) => {
+            error!("Failed to write epg variant {}: {err}", variant_path.display());
+            None
+        }
+    }
+}
+
+pub async fn epg_write(target: &ConfigTarget, cfg: &Config, target_path: &Path, epg: Option<&Epg>, output: &TargetOutput) -> Result<(), M3uFilterError> {
     if let Some(epg_data) = epg {
-        match &output.target {
-            TargetType::M3u => {
-                let path = m3u_get_epg_file_path(target_path);
-                debug_if_enabled!("writing m3u epg to {}", path.to_str().unwrap_or("?"));
-                epg_write_file(target, epg_data, &path)?;
-            }
+        let epg_path = match &output.target {
+            TargetType::M3u => m3u_get_epg_file_path(target_path),
             TargetType::Xtream => {
                 match xtream_get_storage_path(cfg, &target.name) {
-                    Some(path) => {
-                        let epg_path = xtream_get_epg_file_path(&path);
-                        debug_if_enabled!("writing xtream epg to {}", epg_path.to_str().unwrap_or("?"));
-                        epg_write_file(target, epg_data, &epg_path)?;
-                    }
+                    Some(path) => xtream_get_epg_file_path(&path),
                     None => return Err(notify_err!(format!("failed to serialize epg for target: {}, storage path not found", target.name))),
                 }
             }
-            TargetType::Strm => {}
-        }
+            TargetType::Stalker => stalker_get_epg_file_path(target_path),
+            TargetType::Strm => return Ok(()),
+        };
+        debug_if_enabled!("writing {} epg to {}", output.target, epg_path.to_str().unwrap_or("?"));
+        let _file_lock = cfg.file_locks.write_lock(&epg_path).await.map_err(|err| notify_err!(format!("failed to write epg: {} - {err}", epg_path.display())))?;
+        cfg.t_epg_now_next.invalidate(&epg_path);
+        epg_write_file(target, epg_data, &epg_path)?;
+        epg_remove_variants(cfg, &epg_path).await?;
     }
     Ok(())
 }
+
+#[derive(Debug, Clone, Default)]
+pub struct EpgNowNext {
+    pub now: Option<String>,
+    pub next: Option<String>,
+    now_stop: Option<i64>,
+    next_start: i64,
+}
+
+impl EpgNowNext {
+    pub fn to_description(&self) -> Option<String> {
+        match (self.now.as_ref(), self.next.as_ref()) {
+            (Some(now), Some(next)) => Some(format!("Now: {now} | Next: {next}")),
+            (Some(now), None) => Some(format!("Now: {now}")),
+            (None, Some(next)) => Some(format!("Next: {next}")),
+            (None, None) => None,
+        }
+    }
+}
+
+pub fn parse_epg_timestamp(value: &str) -> Option<i64> {
+    DateTime::parse_from_str(value, "%Y%m%d%H%M%S %z").map(|dt| dt.timestamp()).ok()
+        .or_else(|| NaiveDateTime::parse_from_str(value, "%Y%m%d%H%M%S").map(|dt| dt.and_utc().timestamp()).ok())
+}
+
+fn get_programme_title(tag: &XmlTag) -> Option<String> {
+    tag.children.as_ref()
+        .and_then(|children| children.iter().find(|child| child.name == EPG_TAG_TITLE))
+        .and_then(|title| title.value.clone())
+}
+
+/// Reads the current and the following programme title for each channel of a stored epg file.
+fn read_now_next(epg_path: &Path, timestamp: i64) -> HashMap<String, EpgNowNext> {
+    let mut result: HashMap<String, EpgNowNext> = HashMap::new();
+    let Ok(file) = File::open(epg_path) else { return result };
+    let mut collect = |tag: XmlTag| {
+        if tag.name != EPG_TAG_PROGRAMME {
+            return;
+        }
+        let (Some(channel), Some(start), Some(stop)) = (
+            tag.get_attribute_value(EPG_ATTRIB_CHANNEL),
+            tag.get_attribute_value(EPG_ATTRIB_START).and_then(|v| parse_epg_timestamp(v)),
+            tag.get_attribute_value(EPG_ATTRIB_STOP).and_then(|v| parse_epg_timestamp(v))) else { return };
+        if stop <= timestamp {
+            return;
+        }
+        let entry = result.entry(channel.to_string()).or_default();
+        if start <= timestamp {
+            entry.now = get_programme_title(&tag);
+            entry.now_stop = Some(stop);
+        } else if entry.next.is_none() || start < entry.next_start {
+            entry.next = get_programme_title(&tag);
+            entry.next_start = start;
+        }
+    };
+    parse_tvguide(file_reader(file), &mut collect);
+    result
+}
+
+/// The now/next titles are valid until the first current programme ends or the first following programme starts.
+fn get_now_next_valid_until(now_next: &HashMap<String, EpgNowNext>) -> i64 {
+    now_next.values()
+        .flat_map(|entry| [entry.now_stop, entry.next.as_ref().map(|_| entry.next_start)])
+        .flatten()
+        .min()
+        .unwrap_or(i64::MAX)
+}
+
+struct CachedNowNext {
+    valid_until: i64,
+    now_next: Arc<HashMap<String, EpgNowNext>>,
+}
+
+/// The now/next titles of the target epg files. The epg is only parsed again after a programme change or an epg update.
+#[derive(Default)]
+pub struct EpgNowNextCache {
+    entries: Mutex<HashMap<PathBuf, CachedNowNext>>,
+}
+
+impl fmt::Debug for EpgNowNextCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EpgNowNextCache").finish_non_exhaustive()
+    }
+}
+
+impl EpgNowNextCache {
+    pub async fn get(&self, file_locks: &FileLockManager, epg_path: &Path, timestamp: i64) -> Arc<HashMap<String, EpgNowNext>> {
+        if let Some(cached) = self.entries.lock().unwrap().get(epg_path) {
+            if timestamp < cached.valid_until {
+                return Arc::clone(&cached.now_next);
+            }
+        }
+        let Ok(_file_lock) = file_locks.read_lock(epg_path).await else { return Arc::default() };
+        let now_next = Arc::new(read_now_next(epg_path, timestamp));
+        let valid_until = get_now_next_valid_until(&now_next);
+        self.entries.lock().unwrap().insert(epg_path.to_path_buf(), CachedNowNext { valid_until, now_next: Arc::clone(&now_next) });
+        now_next
+    }
+
+    pub fn invalidate(&self, epg_path: &Path) {
+        self.entries.lock().unwrap().remove(epg_path);
+    }
+}
+
+/// The current and the following programme title for each channel of a stored epg file.
+pub async fn epg_read_now_next(cfg: &Config, epg_path: &Path, timestamp: i64) -> Arc<HashMap<String, EpgNowNext>> {
+    cfg.t_epg_now_next.get(&cfg.file_locks, epg_path, timestamp).await
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EpgProgramme {
+    pub start: i64,
+    pub stop: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub desc: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub category: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub icon: Option<String>,
+}
+
+impl EpgProgramme {
+    fn from_tag(tag: &XmlTag, start: i64, stop: i64) -> Self {
+        let children = tag.children.as_deref().unwrap_or_default();
+        let child_value = |name: &str| children.iter().find(|child| child.name == name).and_then(|child| child.value.clone());
+        Self {
+            start,
+            stop,
+            title: child_value(EPG_TAG_TITLE),
+            desc: child_value(EPG_TAG_DESC),
+            category: children.iter().filter(|child| child.name == EPG_TAG_CATEGORY).filter_map(|child| child.value.clone()).collect(),
+            icon: children.iter().find(|child| child.name == EPG_TAG_ICON).and_then(|child| child.get_attribute_value(EPG_ATTRIB_SRC).cloned()),
+        }
+    }
+}
+
+/// Reads the programmes of a channel from a stored epg file, sorted by start.
+/// Only programmes overlapping the optional time range `from` - `to` (unix timestamps) are returned.
+pub async fn epg_read_channel_programmes(cfg: &Config, epg_path: &Path, channel_id: &str, from: Option<i64>, to: Option<i64>) -> Vec<EpgProgramme> {
+    let mut result = vec![];
+    let Ok(_file_lock) = cfg.file_locks.read_lock(epg_path).await else { return result };
+    let Ok(file) = File::open(epg_path) else { return result };
+    let mut collect = |tag: XmlTag| {
+        if tag.name != EPG_TAG_PROGRAMME || tag.get_attribute_value(EPG_ATTRIB_CHANNEL).is_none_or(|channel| channel != channel_id) {
+            return;
+        }
+        let (Some(start), Some(stop)) = (
+            tag.get_attribute_value(EPG_ATTRIB_START).and_then(|v| parse_epg_timestamp(v)),
+            tag.get_attribute_value(EPG_ATTRIB_STOP).and_then(|v| parse_epg_timestamp(v))) else { return };
+        if from.is_some_and(|from| stop <= from) || to.is_some_and(|to| start >= to) {
+            return;
+        }
+        result.push(EpgProgramme::from_tag(&tag, start, stop));
+    };
+    parse_tvguide(file_reader(file), &mut collect);
+    result.sort_by_key(|programme| programme.start);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::path::PathBuf;
+
+    use std::collections::{BTreeSet, HashSet};
+    use std::path::Path;
+
+    use crate::model::config::Config;
+    use crate::model::playlist::XtreamCluster;
+    use crate::model::playlist_test_utils::{group, item};
+    use crate::repository::epg_repository::{epg_read_channel_programmes, epg_remove_variants, get_bouquet_epg_channel_ids, get_epg_variant_file_path, EpgNowNextCache};
+    use crate::utils::file_lock_manager::FileLockManager;
+
+    #[actix_rt::test]
+    async fn epg_bouquet_variant_test() {
+        let playlist = vec![
+            group(1, "News", XtreamCluster::Live, vec![item("ARD").virtual_id(1).epg_id(Some("ard.de")).build(), item("BBC").virtual_id(2).epg_id(Some("bbc1.uk")).build()]),
+            group(2, "Sports", XtreamCluster::Live, vec![item("Sport1").virtual_id(3).epg_id(Some("sport1.de")).build(), item("Sport2").virtual_id(4).epg_id(None).build()]),
+        ];
+        let virtual_ids = BTreeSet::from([1, 3, 4]);
+        assert_eq!(get_bouquet_epg_channel_ids(&playlist, &virtual_ids), HashSet::from(["ard.de".to_string(), "sport1.de".to_string()]));
+
+        // users with the same bouquet channels share the variant
+        let dir = tempfile::tempdir().unwrap();
+        let epg_path = dir.path().join("epg.xml");
+        let variant_path = get_epg_variant_file_path(&epg_path, &virtual_ids);
+        assert_eq!(variant_path, get_epg_variant_file_path(&epg_path, &BTreeSet::from([4, 3, 1])));
+        assert_ne!(variant_path, get_epg_variant_file_path(&epg_path, &BTreeSet::from([1, 3])));
+        assert_eq!(variant_path.parent(), Some(Path::new(&dir.path().join("epg.xml.variants"))));
+
+        std::fs::create_dir_all(variant_path.parent().unwrap()).unwrap();
+        std::fs::write(&variant_path, "<tv></tv>").unwrap();
+        epg_remove_variants(&Config::default(), &epg_path).await.unwrap();
+        assert!(!variant_path.exists());
+    }
+
+    #[actix_rt::test]
+    async fn now_next_test() -> io::Result<()> {
+        let epg_path = PathBuf::from("/tmp/epg_now_next.xml");
+        std::fs::write(&epg_path, r#"<?xml version="1.0" encoding="utf-8" ?><tv>
+<programme start="20250101100000 +0000" stop="20250101110000 +0000" channel="ch1"><title>Old</title></programme>
+<programme start="20250101110000 +0000" stop="20250101120000 +0000" channel="ch1"><title>Current</title></programme>
+<programme start="20250101130000 +0000" stop="20250101140000 +0000" channel="ch1"><title>Later</title></programme>
+<programme start="20250101120000 +0000" stop="20250101130000 +0000" channel="ch1"><title>Following</title></programme>
+</tv>"#)?;
+        let file_locks = FileLockManager::new();
+        let cache = EpgNowNextCache::default();
+        // 2025-01-01 11:30:00 UTC
+        let now_next = cache.get(&file_locks, &epg_path, 1_735_731_000).await;
+        let entry = now_next.get("ch1").expect("channel missing");
+        assert_eq!(entry.now.as_deref(), Some("Current"));
+        assert_eq!(entry.next.as_deref(), Some("Following"));
+        assert_eq!(entry.to_description().as_deref(), Some("Now: Current | Next: Following"));
+        // cached until the current programme ends at 12:00:00
+        std::fs::write(&epg_path, "<tv></tv>")?;
+        assert!(std::sync::Arc::ptr_eq(&now_next, &cache.get(&file_locks, &epg_path, 1_735_732_000).await));
+        assert!(cache.get(&file_locks, &epg_path, 1_735_732_800).await.is_empty());
+        std::fs::write(&epg_path, r#"<tv><programme start="20250101110000 +0000" stop="20250101140000 +0000" channel="ch1"><title>Updated</title></programme></tv>"#)?;
+        cache.invalidate(&epg_path);
+        assert_eq!(cache.get(&file_locks, &epg_path, 1_735_732_800).await.get("ch1").and_then(|entry| entry.now.as_deref()), Some("Updated"));
+        Ok(())
+    }
+
+    #[actix_rt::test]
+    async fn channel_programmes_test() -> io::Result<()> {
+        let cfg = Config::default();
+        let epg_path = PathBuf::from("/tmp/epg_channel_programmes.xml");
+        std::fs::write(&epg_path, r#"<?xml version="1.0" encoding="utf-8" ?><tv>
+<programme start="20250101120000 +0000" stop="20250101130000 +0000" channel="ch1"><title>Second</title><category>News</category></programme>
+<programme start="20250101110000 +0000" stop="20250101120000 +0000" channel="ch1"><title>First</title><desc>Description</desc></programme>
+<programme start="20250101110000 +0000" stop="20250101120000 +0000" channel="ch2"><title>Other</title></programme>
+<programme start="20250101130000 +0000" stop="20250101140000 +0000" channel="ch1"><title>Third</title></programme>
+</tv>"#)?;
+        let programmes = epg_read_channel_programmes(&cfg, &epg_path, "ch1", None, None).await;
+        let titles: Vec<&str> = programmes.iter().filter_map(|p| p.title.as_deref()).collect();
+        assert_eq!(titles, vec!["First", "Second", "Third"]);
+        assert_eq!(programmes[0].desc.as_deref(), Some("Description"));
+        assert_eq!(programmes[1].category, vec!["News".to_string()]);
+        // 2025-01-01 11:30:00 UTC - 13:00:00 UTC
+        let programmes = epg_read_channel_programmes(&cfg, &epg_path, "ch1", Some(1_735_731_000), Some(1_735_736_400)).await;
+        let titles: Vec<&str> = programmes.iter().filter_map(|p| p.title.as_deref()).collect();
+        assert_eq!(titles, vec!["First", "Second"]);
+        Ok(())
+    }
+}
@@ -0,0 +1,157 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use log::info;
+use serde::Serialize;
+
+use crate::m3u_filter_error::{M3uFilterError, M3uFilterErrorKind};
+use crate::model::config::Config;
+use crate::repository::memory_storage::{memory_read_json, memory_write_json};
+use crate::repository::storage::{get_target_id_mapping_file, get_target_storage_path};
+use crate::repository::target_id_mapping::TargetIdMapping;
+use crate::utils::file_utils::file_reader;
+use crate::utils::json_utils::json_write_documents_to_file;
+use crate::{create_m3u_filter_error_result, info_err};
+
+const FILE_ID_NAMESPACES: &str = "id_namespaces.json";
+/// Size of the virtual id range of a target, all ids stay below `i32::MAX` for players which parse them as signed.
+pub const ID_NAMESPACE_SIZE: u32 = 1 << 23;
+const MAX_ID_NAMESPACE: u32 = 255;
+
+// the targets are processed in parallel, the namespace file is only changed under this lock
+static ID_NAMESPACES_LOCK: Mutex<()> = Mutex::new(());
+
+/// The first virtual id of the namespace, namespace `0` holds the ids of mappings created before namespaces.
+pub const fn id_namespace_base(namespace: u32) -> u32 {
+    namespace * ID_NAMESPACE_SIZE
+}
+
+fn get_id_namespaces_file(cfg: &Config) -> PathBuf {
+    PathBuf::from(&cfg.working_dir).join(FILE_ID_NAMESPACES)
+}
+
+fn read_id_namespaces(cfg: &Config) -> BTreeMap<String, u32> {
+    if let Some(storage) = cfg.get_memory_storage() {
+        return memory_read_json(storage, &get_id_namespaces_file(cfg)).unwrap_or_default();
+    }
+    File::open(get_id_namespaces_file(cfg)).ok()
+        .and_then(|file| serde_json::from_reader(file_reader(file)).ok())
+        .unwrap_or_default()
+}
+
+fn write_id_namespaces(cfg: &Config, namespaces: &BTreeMap<String, u32>) -> Result<(), M3uFilterError> {
+    let path = get_id_namespaces_file(cfg);
+    match cfg.get_memory_storage() {
+        Some(storage) => memory_write_json(storage, &path, namespaces),
+        None => json_write_documents_to_file(&path, namespaces),
+    }.map_err(|err| info_err!(format!("Failed to write id namespaces {}: {err}", path.display())))
+}
+
+fn assign_id_namespace(namespaces: &mut BTreeMap<String, u32>, target_name: &str) -> Option<u32> {
+    if let Some(namespace) = namespaces.get(target_name) {
+        return Some(*namespace);
+    }
+    let namespace = (1..=MAX_ID_NAMESPACE).find(|namespace| !namespaces.values().any(|assigned| assigned == namespace))?;
+    namespaces.insert(target_name.to_string(), namespace);
+    Some(namespace)
+}
+
+/// Returns the id namespace of the target, a new target gets the next free namespace.
+/// A renamed target is a new target for the namespaces, its ids can't clash with the ids of the old name.
+pub fn get_target_id_namespace(cfg: &Config, target_name: &str) -> Result<u32, M3uFilterError> {
+    let _lock = ID_NAMESPACES_LOCK.lock().map_err(|err| info_err!(err.to_string()))?;
+    let mut namespaces = read_id_namespaces(cfg);
+    let count = namespaces.len();
+    let Some(namespace) = assign_id_namespace(&mut namespaces, target_name) else {
+        return create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "No free id namespace for target {target_name}, run the id migration to release unused namespaces");
+    };
+    if namespaces.len() != count {
+        info!("Assigned id namespace {namespace} to target {target_name}");
+        write_id_namespaces(cfg, &namespaces)?;
+    }
+    Ok(namespace)
+}
+
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct IdMigrationReport {
+    /// target name and number of moved ids
+    pub migrated_targets: BTreeMap<String, usize>,
+    pub released_namespaces: Vec<String>,
+    pub errors: Vec<String>,
+}
+
+/// Moves the ids of all target mappings into the namespace of the target and releases the namespaces
+/// of targets which are no longer configured. The playlists have to be updated after the migration.
+pub async fn migrate_id_namespaces(cfg: &Config) -> IdMigrationReport {
+    let mut report = IdMigrationReport::default();
+    // the preview target of held outputs keeps its namespace
+    let target_names: Vec<&str> = cfg.sources.iter().flat_map(|source| &source.targets)
+        .flat_map(|target| std::iter::once(target.name.as_str()).chain(target.t_preview.as_ref().map(|preview| preview.name.as_str())))
+        .collect();
+    {
+        let Ok(_lock) = ID_NAMESPACES_LOCK.lock() else { return report; };
+        let mut namespaces = read_id_namespaces(cfg);
+        namespaces.retain(|name, _| {
+            let configured = target_names.contains(&name.as_str());
+            if !configured {
+                report.released_namespaces.push(name.clone());
+            }
+            configured
+        });
+        if let Err(err) = write_id_namespaces(cfg, &namespaces) {
+            report.errors.push(err.to_string());
+        }
+    }
+    let bouquets = &cfg.t_user_bouquets;
+    for target_name in target_names {
+        let Some(target_path) = get_target_storage_path(cfg, target_name) else { continue; };
+        let mapping_file = get_target_id_mapping_file(&target_path);
+        if !mapping_file.exists() {
+            continue;
+        }
+        let namespace = match get_target_id_namespace(cfg, target_name) {
+            Ok(namespace) => namespace,
+            Err(err) => {
+                report.errors.push(err.to_string());
+                continue;
+            }
+        };
+        let result = match cfg.file_locks.write_lock(&mapping_file).await {
+            Ok(_file_lock) => {
+                let mut mapping = TargetIdMapping::new(&mapping_file, namespace);
+                mapping.migrate_to_namespace(namespace).and_then(|moved_ids| mapping.persist().map(|()| moved_ids))
+            }
+            Err(err) => Err(err),
+        };
+        match result {
+            Ok(moved_ids) if moved_ids.is_empty() => {}
+            Ok(moved_ids) => {
+                // the favorites and bouquets of the users reference the virtual ids
+                let remapped = bouquets.remap_target_ids(target_name, &moved_ids);
+                info!("Moved {} ids of target {target_name} into namespace {namespace}, {remapped} bouquet entries updated", moved_ids.len());
+                report.migrated_targets.insert(target_name.to_string(), moved_ids.len());
+            }
+            Err(err) => report.errors.push(format!("{}: {err}", mapping_file.display())),
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use crate::repository::id_namespace::assign_id_namespace;
+
+    #[test]
+    fn assign_id_namespace_test() {
+        let mut namespaces = BTreeMap::from([("old".to_string(), 1)]);
+        assert_eq!(assign_id_namespace(&mut namespaces, "old"), Some(1));
+        assert_eq!(assign_id_namespace(&mut namespaces, "new"), Some(2));
+        namespaces.remove("old");
+        assert_eq!(assign_id_namespace(&mut namespaces, "renamed"), Some(1));
+        assert_eq!(assign_id_namespace(&mut namespaces, "new"), Some(2));
+    }
+}
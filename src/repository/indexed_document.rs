@@ -1,11 +1,16 @@
+use std::collections::HashMap;
 use std::fmt::Debug;
 use std::fs::{File};
 use std::io::{BufReader, Error, ErrorKind, Read, Seek, SeekFrom, Write};
 use std::marker::PhantomData;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{LazyLock, Mutex};
+use std::time::SystemTime;
 
 use crate::repository::bplustree::{BPlusTree, BPlusTreeQuery};
 use crate::utils::file_utils;
+use crate::utils::file_lock_manager::FileLockManager;
 use log::error;
 use serde::{Deserialize, Serialize};
 use tempfile::NamedTempFile;
@@ -22,21 +27,139 @@ pub type IndexedDocumentIndex<K> = BPlusTree<K, OffsetPointer>;
 
 pub(in crate::repository) struct IndexedDocument {}
 
+const FLAG_FRAGMENTED: u8 = 0x01;
+// records are stored with a checksum, files without this flag are read in the old format
+const FLAG_CHECKSUM: u8 = 0x02;
+const CHECKSUM_SIZE: usize = 4;
+const FILE_SUFFIX_CORRUPT: &str = "corrupt";
+const FILE_SUFFIX_STAGED: &str = "staged";
+
+// main path -> index path and modification time of the reported corrupt files
+type CorruptDocuments = HashMap<PathBuf, (PathBuf, Option<SystemTime>)>;
+static STAGED_WRITER_ID: AtomicU64 = AtomicU64::new(0);
+static CORRUPT_DOCUMENTS: LazyLock<Mutex<CorruptDocuments>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
 impl IndexedDocument {
-    pub(in crate::repository) fn read_fragmentation<R: Read + Seek>(file: &mut R) -> std::io::Result<bool> {
+    fn read_flags<R: Read + Seek>(file: &mut R) -> std::io::Result<u8> {
         file.seek(SeekFrom::Start(0))?;
-        let mut bool_bytes = [0u8];
-        file.read_exact(&mut bool_bytes)?;
-        Ok(u8::from_le_bytes(bool_bytes) == 1)
+        let mut flag_bytes = [0u8];
+        file.read_exact(&mut flag_bytes)?;
+        Ok(u8::from_le_bytes(flag_bytes))
     }
 
-    pub(in crate::repository) fn write_fragmentation<W: Write + Seek>(file: &mut W, fragmented: bool) -> std::io::Result<()> {
+    fn write_flags<W: Write + Seek>(file: &mut W, fragmented: bool, checksum: bool) -> std::io::Result<()> {
         file.seek(SeekFrom::Start(0))?;
-        let fragmented_byte = if fragmented { 1u8.to_le_bytes() } else { 0u8.to_le_bytes() };
-        file.write_all(&fragmented_byte)
+        let mut flags = 0u8;
+        if fragmented {
+            flags |= FLAG_FRAGMENTED;
+        }
+        if checksum {
+            flags |= FLAG_CHECKSUM;
+        }
+        file.write_all(&flags.to_le_bytes())
+    }
+
+    pub(in crate::repository) fn read_fragmentation<R: Read + Seek>(file: &mut R) -> std::io::Result<bool> {
+        Ok(Self::read_flags(file)? & FLAG_FRAGMENTED != 0)
+    }
+
+    pub(in crate::repository) fn read_checksum_flag<R: Read + Seek>(file: &mut R) -> std::io::Result<bool> {
+        Ok(Self::read_flags(file)? & FLAG_CHECKSUM != 0)
+    }
+
+    #[inline]
+    const fn record_overhead(checksum: bool) -> usize {
+        if checksum { LEN_SIZE + CHECKSUM_SIZE } else { LEN_SIZE }
+    }
+
+    fn checksum(content: &[u8]) -> [u8; CHECKSUM_SIZE] {
+        let hash = blake3::hash(content);
+        let mut checksum = [0u8; CHECKSUM_SIZE];
+        checksum.copy_from_slice(&hash.as_bytes()[0..CHECKSUM_SIZE]);
+        checksum
+    }
+
+    fn write_record<W: Write>(writer: &mut W, content: &[u8], checksum: bool) -> std::io::Result<()> {
+        let content_len = SizeType::try_from(content.len()).map_err(to_io_error)?;
+        writer.write_all(&content_len.to_le_bytes())?;
+        if checksum {
+            writer.write_all(&Self::checksum(content))?;
+        }
+        writer.write_all(content)
+    }
+
+    /// Reads the record at the current position into the buffer.
+    /// Returns an `InvalidData` error if the stored checksum does not match the content.
+    pub(in crate::repository) fn read_record<R: Read>(reader: &mut R, checksum: bool, buffer: &mut Vec<u8>) -> Result<(), Error> {
+        let buf_size = Self::read_content_size(reader)?;
+        let mut checksum_bytes = [0u8; CHECKSUM_SIZE];
+        if checksum {
+            reader.read_exact(&mut checksum_bytes)?;
+        }
+        buffer.clear();
+        buffer.resize(buf_size, 0u8);
+        reader.read_exact(buffer)?;
+        if checksum && Self::checksum(buffer) != checksum_bytes {
+            return Err(Error::new(ErrorKind::InvalidData, "Record checksum mismatch"));
+        }
+        Ok(())
+    }
+
+    /// Each writer gets its own staging file, concurrent writers of the same document don't share a file.
+    fn get_staged_path(path: &Path, writer_id: u64) -> PathBuf {
+        let mut staged_path = path.as_os_str().to_owned();
+        staged_path.push(format!(".{}-{writer_id}.{FILE_SUFFIX_STAGED}", std::process::id()));
+        PathBuf::from(staged_path)
+    }
+
+    /// Removes the staging files of interrupted writes inside the directory and its subdirectories.
+    pub(in crate::repository) fn remove_staged_files(dir: &Path) -> usize {
+        let Ok(entries) = std::fs::read_dir(dir) else { return 0 };
+        let mut removed = 0;
+        for path in entries.filter_map(|entry| entry.ok().map(|entry| entry.path())) {
+            if path.is_dir() {
+                removed += Self::remove_staged_files(&path);
+            } else if path.extension().is_some_and(|ext| ext == FILE_SUFFIX_STAGED) && std::fs::remove_file(&path).is_ok() {
+                removed += 1;
+            }
+        }
+        removed
+    }
+
+    /// Records corrupt files, the readers hold only the read lock and the files are moved aside by `quarantine_corrupt`.
+    pub(in crate::repository) fn report_corrupt(main_path: &Path, index_path: &Path) {
+        let modified = std::fs::metadata(main_path).and_then(|metadata| metadata.modified()).ok();
+        if let Ok(mut corrupt) = CORRUPT_DOCUMENTS.lock() {
+            if corrupt.insert(main_path.to_path_buf(), (index_path.to_path_buf(), modified)).is_none() {
+                error!("Corrupt file detected {}", main_path.to_string_lossy());
+            }
+        }
+    }
+
+    /// Moves the reported corrupt files aside under the write lock, they are recreated on the next refresh.
+    /// Files which were replaced since they were reported are kept. Returns the count of quarantined files.
+    pub(in crate::repository) async fn quarantine_corrupt(file_locks: &FileLockManager) -> usize {
+        let corrupt = CORRUPT_DOCUMENTS.lock().map(|mut corrupt| std::mem::take(&mut *corrupt)).unwrap_or_default();
+        let mut count = 0;
+        for (main_path, (index_path, modified)) in corrupt {
+            let Ok(_file_lock) = file_locks.write_lock(&main_path).await else { continue; };
+            if std::fs::metadata(&main_path).and_then(|metadata| metadata.modified()).ok() != modified {
+                continue;
+            }
+            for path in [&main_path, &index_path] {
+                let mut quarantine_path = path.as_os_str().to_owned();
+                quarantine_path.push(format!(".{FILE_SUFFIX_CORRUPT}"));
+                match std::fs::rename(path, &quarantine_path) {
+                    Ok(()) => error!("Corrupt file quarantined {}", path.to_string_lossy()),
+                    Err(err) => error!("Failed to quarantine corrupt file {}: {err}", path.to_string_lossy()),
+                }
+            }
+            count += 1;
+        }
+        count
     }
 
-    pub(in crate::repository) fn read_content_size<R: Read + Seek>(reader: &mut R) -> Result<usize, Error>
+    pub(in crate::repository) fn read_content_size<R: Read>(reader: &mut R) -> Result<usize, Error>
     {
         let mut size_bytes = [0u8; LEN_SIZE];
         reader.read_exact(&mut size_bytes)?;
@@ -68,10 +191,15 @@ impl IndexedDocument {
  * - content
  * - index
  *
- * Layout of content file record is:
- *   - content-size (u32) + content (deflate)
+ * Layout of content file is:
+ *   - flags (u8, fragmented and checksum)
+ *   - records: content-size (u32) + checksum (4 bytes, blake3 prefix) + content (bincode)
  *
  * index file is a bplustree
+ *
+ * A staged writer writes a complete new document next to the served files.
+ * `commit` replaces the served files by renaming, readers which already opened
+ * the old files continue reading them. Only the commit needs the write lock.
  */
 pub(in crate::repository) struct IndexedDocumentWriter<K>
 where
@@ -84,6 +212,9 @@ where
     index_tree: IndexedDocumentIndex<K>,
     dirty: bool,
     fragmented: bool,
+    checksum: bool,
+    // served (main, index) paths of a staged writer
+    target_paths: Option<(PathBuf, PathBuf)>,
 }
 
 impl<K> IndexedDocumentWriter<K>
@@ -105,11 +236,13 @@ where
             .unwrap_or(0);
 
         let mut fragmented = false;
+        let mut checksum = true;
         if main_offset == 0 {
-            IndexedDocument::write_fragmentation(&mut main_file, false)?;
+            IndexedDocument::write_flags(&mut main_file, false, checksum)?;
             main_offset = 1;
         } else {
             fragmented = IndexedDocument::read_fragmentation(&mut main_file)?;
+            checksum = IndexedDocument::read_checksum_flag(&mut main_file)?;
         }
 
         // Initialize the index tree (BPlusTree) - either by deserializing an existing one or creating a new one
@@ -130,9 +263,12 @@ where
             index_tree,
             dirty: false,
             fragmented,
+            checksum,
+            target_paths: None,
         })
     }
 
+    #[cfg(test)]
     pub fn new(main_path: PathBuf, index_path: PathBuf) -> Result<Self, Error> {
         Self::new_with_mode(main_path, index_path, false)
     }
@@ -141,6 +277,33 @@ where
         Self::new_with_mode(main_path, index_path, true)
     }
 
+    /// Writes a new document into staging files, the served files are replaced with `commit`.
+    pub fn new_staged(main_path: PathBuf, index_path: PathBuf) -> Result<Self, Error> {
+        let writer_id = STAGED_WRITER_ID.fetch_add(1, Ordering::Relaxed);
+        let staged_main_path = IndexedDocument::get_staged_path(&main_path, writer_id);
+        let staged_index_path = IndexedDocument::get_staged_path(&index_path, writer_id);
+        let mut writer = Self::new_with_mode(staged_main_path, staged_index_path, false)?;
+        writer.target_paths = Some((main_path, index_path));
+        Ok(writer)
+    }
+
+    /// Replaces the served document with the staged one. The caller has to hold the write lock of the main file.
+    pub fn commit(mut self) -> Result<(), Error> {
+        self.store()?;
+        self.main_file.flush()?;
+        let Some((main_path, index_path)) = self.target_paths.take() else { return Ok(()) };
+        let staged_main_path = self.main_path.clone();
+        let staged_index_path = self.index_path.clone();
+        drop(self);
+        if staged_index_path.exists() {
+            std::fs::rename(&staged_index_path, &index_path)?;
+        } else if index_path.exists() {
+            // empty document, there is no index
+            std::fs::remove_file(&index_path)?;
+        }
+        std::fs::rename(&staged_main_path, &main_path)
+    }
+
     pub fn store(&mut self) -> std::io::Result<()> {
         if self.dirty {
             self.dirty = false;
@@ -170,6 +333,9 @@ where
             let size = IndexedDocument::read_content_size(&mut self.main_file)?;
             if size == encoded_bytes.len() {
                 // check if it is equal
+                if self.checksum {
+                    self.main_file.seek(SeekFrom::Current(CHECKSUM_SIZE as i64))?;
+                }
                 let mut record_buffer = vec![0; size];
                 // record_buffer.resize(size, 0);
 
@@ -183,7 +349,7 @@ where
                 // does not fit we need to append, file is fragmented
                 if !self.fragmented {
                     self.fragmented = true;
-                    IndexedDocument::write_fragmentation(&mut self.main_file, true)?;
+                    IndexedDocument::write_flags(&mut self.main_file, true, self.checksum)?;
                 }
                 self.main_file.seek(SeekFrom::End(0))?;
                 new_record_appended = true;
@@ -197,13 +363,11 @@ where
 
         self.dirty = true;
 
-        let encoded_bytes_len = SizeType::try_from(encoded_bytes.len()).map_err(to_io_error)?;
-        self.main_file.write_all(&encoded_bytes_len.to_le_bytes())?;
-        match file_utils::check_write(&self.main_file.write_all(&encoded_bytes)) {
+        match file_utils::check_write(&IndexedDocument::write_record(&mut self.main_file, &encoded_bytes, self.checksum)) {
             Ok(()) => {
                 if new_record_appended {
                     self.index_tree.insert(doc_id, self.main_offset);
-                    let written_bytes = SizeType::try_from(encoded_bytes.len() + LEN_SIZE).map_err(to_io_error)?;
+                    let written_bytes = SizeType::try_from(encoded_bytes.len() + IndexedDocument::record_overhead(self.checksum)).map_err(to_io_error)?;
                     self.main_offset += written_bytes;
                 }
             }
@@ -237,6 +401,8 @@ where
 {
     main_file: BufReader<File>,
     index_tree: IndexedDocumentIndex<K>,
+    checksum: bool,
+    t_buffer: Vec<u8>,
     t_type: PhantomData<T>,
 }
 
@@ -248,12 +414,15 @@ where
 {
     pub fn new(main_path: &Path, index_path: &Path) -> Result<Self, Error> {
         if main_path.exists() && index_path.exists() {
-            let main_file = open_readonly_file(main_path)?;
+            let mut main_file = open_readonly_file(main_path)?;
+            let checksum = IndexedDocument::read_checksum_flag(&mut main_file)?;
             let index_tree = IndexedDocumentIndex::<K>::load(index_path)?;
 
             Ok(Self {
                 main_file: file_reader(main_file),
                 index_tree,
+                checksum,
+                t_buffer: Vec::with_capacity(BLOCK_SIZE),
                 t_type: PhantomData,
             })
         } else {
@@ -263,10 +432,8 @@ where
     pub fn get(&mut self, doc_id: &K) -> Result<T, Error> {
         if let Some(offset) = self.index_tree.query(doc_id) {
             self.main_file.seek(SeekFrom::Start(u64::from(*offset)))?;
-            let buf_size = IndexedDocument::read_content_size(&mut self.main_file)?;
-            let mut buffer: Vec<u8> = vec![0; buf_size];
-            self.main_file.read_exact(&mut buffer)?;
-            if let Ok(item) = bincode::deserialize::<T>(&buffer) {
+            IndexedDocument::read_record(&mut self.main_file, self.checksum, &mut self.t_buffer)?;
+            if let Ok(item) = bincode::deserialize::<T>(&self.t_buffer) {
                 return Ok(item);
             }
         }
@@ -282,7 +449,9 @@ where
 ////////////////////////////////////////////////////////
 pub(in crate::repository) struct IndexedDocumentIterator<K, T> {
     main_path: PathBuf,
+    index_path: PathBuf,
     main_file: BufReader<File>,
+    checksum: bool,
     offsets: Vec<OffsetPointer>,
     index: usize,
     failed: bool,
@@ -305,10 +474,13 @@ where
                 offsets.sort_unstable();
             }
             match File::open(main_path) {
-                Ok(file) => {
+                Ok(mut file) => {
+                    let checksum = IndexedDocument::read_checksum_flag(&mut file)?;
                     Ok(Self {
                         main_path: main_path.to_path_buf(),
+                        index_path: index_path.to_path_buf(),
                         main_file: file_reader(file),
+                        checksum,
                         offsets,
                         index: 0,
                         failed: false,
@@ -339,19 +511,18 @@ where
         if !self.has_next() {
             return Ok(None);
         }
-        // read content-size
         self.main_file.seek(SeekFrom::Start(u64::from(self.offsets[self.index])))?;
         self.index += 1;
-        let buf_size: usize = IndexedDocument::read_content_size(&mut self.main_file)?;
-        // resize buffer if necessary
-        if self.t_buffer.capacity() < buf_size {
-            self.t_buffer.reserve(buf_size - self.t_buffer.capacity());
-        }
-        self.t_buffer.resize(buf_size, 0u8);
         // read content
-        self.main_file.read_exact(&mut self.t_buffer[0..buf_size])?;
+        if let Err(err) = IndexedDocument::read_record(&mut self.main_file, self.checksum, &mut self.t_buffer) {
+            self.failed = true;
+            if err.kind() == ErrorKind::InvalidData {
+                IndexedDocument::report_corrupt(&self.main_path, &self.index_path);
+            }
+            return Err(err);
+        }
         // deserialize buffer
-        match bincode::deserialize::<T>(&self.t_buffer[0..buf_size]) {
+        match bincode::deserialize::<T>(&self.t_buffer) {
             Ok(value) => Ok(Some(value)),
             Err(err) => {
                 self.failed = true;
@@ -395,10 +566,15 @@ impl IndexedDocumentDirectAccess {
             // get the offset from index
             let offset = IndexedDocument::get_offset(index_path, doc_id)?;
             let mut main_file = File::open(main_path)?;
+            let checksum = IndexedDocument::read_checksum_flag(&mut main_file)?;
             main_file.seek(SeekFrom::Start(offset))?;
-            let buf_size = IndexedDocument::read_content_size(&mut main_file)?;
-            let mut buffer: Vec<u8> = vec![0; buf_size];
-            main_file.read_exact(&mut buffer)?;
+            let mut buffer: Vec<u8> = Vec::new();
+            if let Err(err) = IndexedDocument::read_record(&mut main_file, checksum, &mut buffer) {
+                if err.kind() == ErrorKind::InvalidData {
+                    IndexedDocument::report_corrupt(main_path, index_path);
+                }
+                return Err(err);
+            }
             if let Ok(item) = bincode::deserialize::<T>(&buffer) {
                 return Ok(item);
             }
@@ -458,8 +634,19 @@ where
         if !fragmented {
             return Ok(());
         }
+        self.compact()
+    }
 
-        let gc_file = NamedTempFile::new()?;
+    /// Rewrites the main file and the index even if the document is not marked as fragmented.
+    /// Every indexed record is checked against the main file bounds before it is copied.
+    pub fn compact(&mut self) -> Result<(), Error> {
+        let main_size = self.main_file.metadata()?.len();
+        let checksum = IndexedDocument::read_checksum_flag(&mut self.main_file)?;
+        // same directory, the compacted file replaces the main file atomically
+        let gc_file = match self.main_path.parent() {
+            Some(dir) => NamedTempFile::new_in(dir)?,
+            None => NamedTempFile::new()?,
+        };
         let gc_path = gc_file.path();
         {
             let mut key_offset = Vec::<(K, OffsetPointer)>::new();
@@ -468,30 +655,27 @@ where
             });
             let mut gc_writer = file_writer(&gc_file);
 
-            let fragmented_byte = 0u8.to_le_bytes();
-            gc_writer.write_all(&fragmented_byte)?;
+            // compacted files are always written with checksums
+            let flags = FLAG_CHECKSUM.to_le_bytes();
+            gc_writer.write_all(&flags)?;
 
-            let mut gc_offset = 1usize; // offset is 1 because of fragment bit
+            let mut gc_offset = 1usize; // offset is 1 because of the flags byte
             let mut buffer: Vec<u8> = Vec::with_capacity(BLOCK_SIZE);
-            let mut size_bytes = [0u8; LEN_SIZE];
             for (key, offset) in key_offset {
-                // read old content
+                // read old content, fails on checksum mismatch or if the record exceeds the file
                 self.main_file.seek(SeekFrom::Start(u64::from(offset)))?;
-                self.main_file.read_exact(&mut size_bytes)?;
-                let buf_size = SizeType::from_le_bytes(size_bytes) as usize;
-                // ensure buffer capacity
-                if buffer.capacity() < buf_size {
-                    buffer.reserve(buf_size - buffer.capacity());
+                let record_size = IndexedDocument::read_content_size(&mut self.main_file)? + IndexedDocument::record_overhead(checksum);
+                if u64::from(offset) + record_size as u64 > main_size {
+                    return Err(Error::new(ErrorKind::InvalidData, format!("Corrupt record at offset {offset} in {:?}", self.main_path)));
                 }
-                buffer.resize(buf_size, 0u8);
-                self.main_file.read_exact(&mut buffer[0..buf_size])?;
-
-                gc_writer.write_all(&size_bytes)?;
-                gc_writer.write_all(&buffer[0..buf_size])?;
+                self.main_file.seek(SeekFrom::Start(u64::from(offset)))?;
+                IndexedDocument::read_record(&mut self.main_file, checksum, &mut buffer)
+                    .map_err(|err| Error::new(ErrorKind::InvalidData, format!("Corrupt record at offset {offset} in {:?}: {err}", self.main_path)))?;
+                IndexedDocument::write_record(&mut gc_writer, &buffer, true)?;
 
                 let pointer = OffsetPointer::try_from(gc_offset).map_err(to_io_error)?;
                 self.index_tree.insert(key, pointer);
-                gc_offset += size_bytes.len() + buf_size; // gc_file.stream_position();
+                gc_offset += IndexedDocument::record_overhead(true) + buffer.len();
             }
 
             gc_writer.flush()?;
@@ -511,7 +695,8 @@ mod tests {
 
     use serde::{Deserialize, Serialize};
 
-    use crate::repository::indexed_document::{IndexedDocumentGarbageCollector, IndexedDocumentIterator, IndexedDocumentWriter};
+    use crate::utils::file_lock_manager::FileLockManager;
+    use crate::repository::indexed_document::{IndexedDocument, IndexedDocumentDirectAccess, IndexedDocumentGarbageCollector, IndexedDocumentIterator, IndexedDocumentWriter};
 
     // Example usage with a simple struct
     #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
@@ -582,4 +767,75 @@ mod tests {
 
         Ok(())
     }
+
+    #[actix_rt::test]
+    async fn checksum_test() -> io::Result<()> {
+        let main_path = PathBuf::from("/tmp/checksum.iw");
+        let index_path = PathBuf::from("/tmp/checksum.iw.idx");
+        {
+            let mut idw = IndexedDocumentWriter::new(main_path.clone(), index_path.clone())?;
+            for i in 0u32..=10 {
+                idw.write_doc(i, &Record { id: i, data: format!("Entry {i}") })?;
+            }
+            idw.store()?;
+        }
+        let record = IndexedDocumentDirectAccess::read_indexed_item::<u32, Record>(&main_path, &index_path, &5)?;
+        assert_eq!(record.data, "Entry 5");
+
+        // corrupt the last byte of the last record
+        let mut content = std::fs::read(&main_path)?;
+        let last = content.len() - 1;
+        content[last] ^= 0xFF;
+        std::fs::write(&main_path, content)?;
+
+        let result = IndexedDocumentDirectAccess::read_indexed_item::<u32, Record>(&main_path, &index_path, &10);
+        assert_eq!(result.err().map(|err| err.kind()), Some(io::ErrorKind::InvalidData));
+        // the reader holds the read lock, the file is moved aside with the write lock
+        assert!(main_path.exists());
+        assert!(IndexedDocument::quarantine_corrupt(&FileLockManager::new()).await >= 1);
+        assert!(!main_path.exists(), "Corrupt file should be quarantined");
+        assert!(PathBuf::from("/tmp/checksum.iw.corrupt").exists(), "Corrupt file should be quarantined");
+        Ok(())
+    }
+
+    #[test]
+    fn staged_commit_test() -> io::Result<()> {
+        let main_path = PathBuf::from("/tmp/staged.iw");
+        let index_path = PathBuf::from("/tmp/staged.iw.idx");
+        let write = |data: &str| -> io::Result<()> {
+            let mut writer = IndexedDocumentWriter::new_staged(main_path.clone(), index_path.clone())?;
+            for i in 0u32..10 {
+                writer.write_doc(i, &Record { id: i, data: format!("{data} {i}") })?;
+            }
+            writer.commit()
+        };
+        write("Old")?;
+        let old_reader = IndexedDocumentIterator::<u32, Record>::new(&main_path, &index_path)?;
+        write("New")?;
+        // readers keep the document they opened
+        assert!(old_reader.map(|doc| doc.data).all(|data| data.starts_with("Old")));
+        let new_reader = IndexedDocumentIterator::<u32, Record>::new(&main_path, &index_path)?;
+        assert_eq!(new_reader.map(|doc| doc.data).filter(|data| data.starts_with("New")).count(), 10);
+        Ok(())
+    }
+
+    #[test]
+    fn concurrent_staged_writers_test() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let main_path = dir.path().join("concurrent.iw");
+        let index_path = dir.path().join("concurrent.iw.idx");
+        let mut first = IndexedDocumentWriter::new_staged(main_path.clone(), index_path.clone())?;
+        let mut second = IndexedDocumentWriter::new_staged(main_path.clone(), index_path.clone())?;
+        for i in 0u32..10 {
+            first.write_doc(i, &Record { id: i, data: format!("First {i}") })?;
+            second.write_doc(i, &Record { id: i, data: format!("Second {i}") })?;
+        }
+        first.commit()?;
+        second.commit()?;
+        // the last commit is served complete, the writers didn't share a staging file
+        let reader = IndexedDocumentIterator::<u32, Record>::new(&main_path, &index_path)?;
+        assert_eq!(reader.map(|doc| doc.data).filter(|data| data.starts_with("Second")).count(), 10);
+        assert_eq!(std::fs::read_dir(dir.path())?.count(), 2);
+        Ok(())
+    }
 }
@@ -0,0 +1,119 @@
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+
+use crate::m3u_filter_error::{M3uFilterError, M3uFilterErrorKind};
+use crate::model::config::ConfigInput;
+use crate::model::playlist::{PlaylistGroup, PlaylistItem, PlaylistItemHeader, PlaylistItemType, XtreamCluster};
+use crate::repository::storage::get_input_storage_path;
+use crate::utils::compressed_file_reader::CompressedFileReader;
+use crate::utils::file_utils::file_writer;
+use crate::{create_m3u_filter_error, create_m3u_filter_error_result};
+
+const FILE_INPUT_FALLBACK: &str = "fallback_playlist.json.gz";
+
+// the item type and group cluster are not serialized with the playlist
+#[derive(Serialize, Deserialize)]
+struct FallbackItem {
+    header: PlaylistItemHeader,
+    item_type: PlaylistItemType,
+}
+
+#[derive(Serialize, Deserialize)]
+struct FallbackGroup {
+    id: u32,
+    title: String,
+    xtream_cluster: XtreamCluster,
+    items: Vec<FallbackItem>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct FallbackPlaylist {
+    timestamp: i64,
+    groups: Vec<FallbackGroup>,
+}
+
+fn get_fallback_path(input: &ConfigInput, working_dir: &str) -> Option<PathBuf> {
+    get_input_storage_path(input, working_dir).ok().map(|path| path.join(FILE_INPUT_FALLBACK))
+}
+
+fn write_fallback(path: &Path, fallback: &FallbackPlaylist) -> std::io::Result<()> {
+    let tmp_path = path.with_extension("tmp");
+    {
+        let mut encoder = GzEncoder::new(file_writer(File::create(&tmp_path)?), Compression::fast());
+        serde_json::to_writer(&mut encoder, fallback)?;
+        encoder.finish()?.flush()?;
+    }
+    std::fs::rename(&tmp_path, path)
+}
+
+/// Stores the playlist of a successful update, it replaces the playlist of a later failed update.
+pub fn input_write_fallback(input: &ConfigInput, working_dir: &str, playlist: &[PlaylistGroup]) -> Result<(), M3uFilterError> {
+    let Some(path) = get_fallback_path(input, working_dir) else {
+        return create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "failed to create storage path for input {}", input.id);
+    };
+    let fallback = FallbackPlaylist {
+        timestamp: chrono::Utc::now().timestamp(),
+        groups: playlist.iter().map(|group| FallbackGroup {
+            id: group.id,
+            title: group.title.to_string(),
+            xtream_cluster: group.xtream_cluster,
+            items: group.channels.iter().map(|item| {
+                let header = item.header.borrow();
+                FallbackItem { header: header.clone(), item_type: header.item_type }
+            }).collect(),
+        }).collect(),
+    };
+    write_fallback(&path, &fallback)
+        .map_err(|err| create_m3u_filter_error!(M3uFilterErrorKind::Info, "failed to write input fallback {}: {err}", path.display()))
+}
+
+/// The playlist and timestamp of the last successful update of the input.
+pub fn input_read_fallback(input: &ConfigInput, working_dir: &str) -> Option<(i64, Vec<PlaylistGroup>)> {
+    let path = get_fallback_path(input, working_dir).filter(|path| path.exists())?;
+    let fallback: FallbackPlaylist = serde_json::from_reader(CompressedFileReader::new(&path).ok()?).ok()?;
+    let groups = fallback.groups.into_iter().map(|group| PlaylistGroup {
+        id: group.id,
+        title: Rc::new(group.title),
+        xtream_cluster: group.xtream_cluster,
+        channels: group.items.into_iter().map(|item| {
+            let mut header = item.header;
+            header.item_type = item.item_type;
+            PlaylistItem { header: RefCell::new(header) }
+        }).collect(),
+    }).collect();
+    Some((fallback.timestamp, groups))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::config::ConfigInput;
+    use crate::model::playlist::{PlaylistItemType, XtreamCluster};
+    use crate::model::playlist_test_utils::{group, item};
+    use crate::repository::input_fallback_repository::{input_read_fallback, input_write_fallback};
+
+    #[test]
+    fn input_fallback_test() {
+        let working_dir = "/tmp/m3u_filter_input_fallback_test";
+        let _ = std::fs::remove_dir_all(working_dir);
+        let input = ConfigInput { id: 1, name: Some("provider".to_string()), ..ConfigInput::default() };
+        assert!(input_read_fallback(&input, working_dir).is_none());
+
+        let channel = item("News HD").url("http://provider.tv/live/1.m3u8").item_type(PlaylistItemType::LiveHls).build();
+        let playlist = vec![group(3, "News", XtreamCluster::Live, vec![channel])];
+        input_write_fallback(&input, working_dir, &playlist).unwrap();
+
+        let (timestamp, groups) = input_read_fallback(&input, working_dir).unwrap();
+        assert!(timestamp > 0);
+        assert_eq!((groups[0].id, groups[0].title.as_str(), groups[0].xtream_cluster), (3, "News", XtreamCluster::Live));
+        let header = groups[0].channels[0].header.borrow();
+        assert_eq!((header.title.as_str(), header.item_type), ("News HD", PlaylistItemType::LiveHls));
+        let _ = std::fs::remove_dir_all(working_dir);
+    }
+}
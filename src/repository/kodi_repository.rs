@@ -3,6 +3,7 @@ use crate::model::api_proxy::{ApiProxyServerInfo, ProxyType, ProxyUserCredential
 use crate::model::config::{Config, ConfigTarget, TargetOutput};
 use crate::model::playlist::{FieldGetAccessor, PlaylistGroup, PlaylistItem, PlaylistItemType, XtreamCluster};
 use crate::model::xtream::XtreamSeriesEpisode;
+use crate::processing::movie_parts::{extract_movie_part, PROP_MOVIE_PART};
 use crate::repository::bplustree::BPlusTree;
 use crate::repository::storage::get_input_storage_path;
 use crate::repository::xtream_repository::{xtream_get_record_file_path, InputVodInfoRecord};
@@ -13,12 +14,14 @@ use chrono::Datelike;
 use log::error;
 use regex::Regex;
 use serde::Serialize;
+use std::fmt::Write as _;
 use std::collections::HashMap;
 use std::fs::File;
 use std::io::Write;
 use std::path::PathBuf;
 use std::rc::Rc;
 use std::sync::LazyLock;
+use crate::utils::output_encoding::{apply_newline_style, get_bom, sanitize_line};
 use crate::utils::request_utils::extract_extension_from_url;
 
 struct KodiStyle {
@@ -183,6 +186,10 @@ async fn kodi_style_rename(cfg: &Config, strm_item_info: &StrmItemInfo, style: &
         }
     }
 
+    if let Some(part) = strm_item_info.movie_part {
+        filename.push(format!("{separator}cd{part}"));
+    }
+
     if tmdb_id > 0 {
         filename.push(format!("{separator}{{tmdb={tmdb_id}}}"));
     }
@@ -195,6 +202,35 @@ async fn kodi_style_rename(cfg: &Config, strm_item_info: &StrmItemInfo, style: &
     (path, kodi_filename)
 }
 
+/// Kodi style directory and filename (without extension) for a file name like `Show.S01E02.2020`,
+/// e.g. `Show (2020)/Season 1` and `Show (2020) S01E02`. Used for downloaded files, there is no tmdb lookup.
+pub fn kodi_style_rename_file(name: &str, underscore_whitespace: bool) -> (PathBuf, String) {
+    let style = &*KODI_STYLE;
+    let separator = if underscore_whitespace { "_" } else { " " };
+    let cleaned_name = name.replace(['.', '_'], " ");
+    let (name_1, year) = kodi_style_rename_year(&cleaned_name, style, None);
+    let (name_2, season) = kodi_style_rename_season(name_1, style, None);
+    let (name_3, episode) = kodi_style_rename_episode(&name_2, style, None);
+    let name_4 = trim_whitespace(&style.whitespace, &style.alphanumeric.replace_all(&name_3, ""));
+    let mut dir_name = sanitize_for_filename(&name_4, underscore_whitespace);
+    if let Some(value) = year {
+        dir_name = format!("{dir_name}{separator}({value})");
+    }
+    let mut filename = dir_name.clone();
+    let mut path = PathBuf::from(&dir_name);
+    if let Some(value) = season {
+        filename.push_str(&format!("{separator}S{value:02}"));
+        path.push(format!("Season{separator}{value}"));
+    }
+    if let Some(value) = episode {
+        if season.is_none() {
+            filename.push_str(separator);
+        }
+        filename.push_str(&format!("E{value:02}"));
+    }
+    (path, filename)
+}
+
 #[derive(Clone)]
 enum InputTmdbIndexTree {
     Video(BPlusTree<u32, InputVodInfoRecord>),
@@ -272,6 +308,7 @@ struct StrmItemInfo {
     release_date: Option<String>,
     season: Option<String>,
     episode: Option<String>,
+    movie_part: Option<u32>,
 }
 
 fn extract_item_info(pli: &PlaylistItem) -> StrmItemInfo {
@@ -294,8 +331,14 @@ fn extract_item_info(pli: &PlaylistItem) -> StrmItemInfo {
         let episode = header.get_additional_property_as_str("episode");
         (series_name, release_date, season, episode)
     } else { (None, None, None, None) };
+    // the title of a multi-part movie is stored without part to place all parts into one folder
+    let (title, movie_part) = match header.get_additional_property_as_u64(PROP_MOVIE_PART) {
+        Some(part) if item_type == PlaylistItemType::Video => extract_movie_part(&title)
+            .map_or((title, None), |(movie_title, _)| (Rc::new(movie_title), u32::try_from(part).ok())),
+        _ => (title, None),
+    };
 
-    StrmItemInfo { group, title, item_type, provider_id, virtual_id, input_id, url, series_name, release_date, season, episode }
+    StrmItemInfo { group, title, item_type, provider_id, virtual_id, input_id, url, series_name, release_date, season, episode, movie_part }
 }
 
 fn prepare_strm_output_directory(cleanup: bool, path: &PathBuf) -> Result<(), M3uFilterError> {
@@ -337,6 +380,7 @@ pub async fn kodi_write_strm_playlist(target: &ConfigTarget, cfg: &Config, new_p
             });
 
         let (underscore_whitespace, cleanup, kodi_style) = get_strm_output_options(target);
+        let encoding = target.get_output_encoding();
         let Some(path) = file_utils::get_file_path(&cfg.working_dir, Some(std::path::PathBuf::from(&output.filename.as_ref().unwrap()))) else {
             return create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "Failed to get file path for {}", output.filename.as_deref().unwrap_or(""));
         };
@@ -355,8 +399,12 @@ pub async fn kodi_write_strm_playlist(target: &ConfigTarget, cfg: &Config, new_p
                 let (dir_path, strm_file_name) = if kodi_style {
                     kodi_style_rename(cfg, &str_item_info, &KODI_STYLE, &mut input_tmdb_indexes, underscore_whitespace).await
                 } else {
-                    let dir_path = path.join(sanitize_for_filename(&str_item_info.group, underscore_whitespace));
-                    let strm_file_name = sanitize_for_filename(&str_item_info.title, underscore_whitespace);
+                    let mut dir_path = path.join(sanitize_for_filename(&str_item_info.group, underscore_whitespace));
+                    let mut strm_file_name = sanitize_for_filename(&str_item_info.title, underscore_whitespace);
+                    if let Some(part) = str_item_info.movie_part {
+                        dir_path = dir_path.join(&strm_file_name);
+                        strm_file_name = format!("{strm_file_name}{}cd{part}", if underscore_whitespace { "_" } else { " " });
+                    }
                     (dir_path, strm_file_name)
                 };
                 let output_path = path.join(dir_path);
@@ -366,13 +414,17 @@ pub async fn kodi_write_strm_playlist(target: &ConfigTarget, cfg: &Config, new_p
                 };
 
                 let url = get_strm_url(credentials_and_server_info.as_ref(), &str_item_info);
-                let seekable = pli.header.borrow().xtream_cluster != XtreamCluster::Live;
+                let (seekable, kodi_props) = {
+                    let header = pli.header.borrow();
+                    (header.xtream_cluster != XtreamCluster::Live, header.kodi_props.clone())
+                };
 
                 let file_path = output_path.join(format!("{strm_file_name}.strm"));
                 match File::create(&file_path) {
                     Ok(mut strm_file) => {
-                        let content = format!("#KODIPROP:seekable={seekable}\n#KODIPROP:inputstream=inputstream.ffmpeg\n#KODIPROP:http-reconnect=true\n{url}");
-                        match file_utils::check_write(&strm_file.write_all(content.as_bytes())) {
+                        let content = get_strm_content(seekable, kodi_props.as_deref().map(Vec::as_slice), &url);
+                        let content = [get_bom(encoding), apply_newline_style(&content, encoding).as_bytes()].concat();
+                        match file_utils::check_write(&strm_file.write_all(&content)) {
                             Ok(()) => {}
                             Err(err) => {
                                 error!("failed to write strm playlist: {err}");
@@ -391,6 +443,27 @@ pub async fn kodi_write_strm_playlist(target: &ConfigTarget, cfg: &Config, new_p
     result
 }
 
+/// The props of the source entry replace the default props with the same key,
+/// e.g. `inputstream=inputstream.adaptive` of a drm stream replaces `inputstream.ffmpeg`.
+fn get_strm_content(seekable: bool, kodi_props: Option<&[String]>, url: &str) -> String {
+    let kodi_props = kodi_props.unwrap_or_default();
+    let get_key = |prop: &str| prop.split_once('=').map_or_else(|| prop.trim().to_string(), |(key, _)| key.trim().to_string());
+    let source_keys: Vec<String> = kodi_props.iter().map(|prop| get_key(prop)).collect();
+    let mut content = String::new();
+    for prop in [format!("seekable={seekable}"), "inputstream=inputstream.ffmpeg".to_string(), "http-reconnect=true".to_string()] {
+        let key = get_key(&prop);
+        // inputstreamaddon is the key of older kodi versions
+        if !source_keys.iter().any(|source_key| *source_key == key || (key == "inputstream" && source_key == "inputstreamaddon")) {
+            let _ = writeln!(content, "#KODIPROP:{prop}");
+        }
+    }
+    for prop in kodi_props {
+        let _ = writeln!(content, "#KODIPROP:{}", sanitize_line(prop));
+    }
+    content.push_str(&sanitize_line(url));
+    content
+}
+
 fn get_strm_url(credentials_and_server_info: Option<&(ProxyUserCredentials, ApiProxyServerInfo)>, str_item_info: &StrmItemInfo) -> String {
     credentials_and_server_info.as_ref()
         .map_or_else(|| str_item_info.url.to_string(),
@@ -413,3 +486,28 @@ fn get_strm_url(credentials_and_server_info: Option<&(ProxyUserCredentials, ApiP
                          },
         )
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::repository::kodi_repository::{get_strm_content, kodi_style_rename_file};
+
+    #[test]
+    fn kodi_style_rename_file_test() {
+        assert_eq!(kodi_style_rename_file("The.Show.2020.S01E02", false),
+                   (PathBuf::from("The Show (2020)").join("Season 1"), "The Show (2020) S01E02".to_string()));
+        assert_eq!(kodi_style_rename_file("Movie_2010", true),
+                   (PathBuf::from("Movie_(2010)"), "Movie_(2010)".to_string()));
+    }
+
+    #[test]
+    fn strm_content_test() {
+        assert_eq!(get_strm_content(true, None, "http://provider.tv/movie/1.mp4"),
+                   "#KODIPROP:seekable=true\n#KODIPROP:inputstream=inputstream.ffmpeg\n#KODIPROP:http-reconnect=true\nhttp://provider.tv/movie/1.mp4");
+        let props = vec!["inputstream.adaptive.license_type=clearkey".to_string(), "inputstreamaddon=inputstream.adaptive".to_string()];
+        assert_eq!(get_strm_content(false, Some(&props), "http://provider.tv/live/1.mpd"),
+                   "#KODIPROP:seekable=false\n#KODIPROP:http-reconnect=true\n#KODIPROP:inputstream.adaptive.license_type=clearkey\n\
+                   #KODIPROP:inputstreamaddon=inputstream.adaptive\nhttp://provider.tv/live/1.mpd");
+    }
+}
@@ -1,18 +1,150 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::rc::Rc;
+
+use chrono::Local;
+
 use crate::info_err;
 use crate::m3u_filter_error::{M3uFilterError, M3uFilterErrorKind};
 use crate::model::api_proxy::{ProxyType, ProxyUserCredentials};
+use crate::model::channel_viewers::get_channel_viewer_key;
 use crate::model::config::{Config, ConfigTarget, ConfigTargetOptions};
-use crate::model::playlist::{M3uPlaylistItem, PlaylistItemType};
-use crate::repository::indexed_document::IndexedDocumentIterator;
-use crate::repository::m3u_repository::m3u_get_file_paths;
+use crate::model::playlist::{M3uPlaylistItem, PlaylistItemType, UUIDType, XtreamCluster};
+use crate::repository::memory_storage::DocumentIterator;
+use crate::repository::epg_repository::{epg_read_now_next, EpgNowNext};
+use crate::repository::m3u_repository::{m3u_get_epg_file_path, m3u_get_file_paths};
 use crate::repository::storage::ensure_target_storage_path;
-use crate::utils::file_lock_manager::FileReadGuard;
+use crate::utils::request_utils::{is_local_url, replace_stream_extension};
 
 pub const M3U_STREAM_PATH: &str = "m3u-stream";
 pub const M3U_RESOURCE_PATH: &str = "resource/m3u";
 
+/// Stream format requested with the `output` parameter, applied to live streams.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum M3uStreamOutput {
+    #[default]
+    Default,
+    Ts,
+    Hls,
+}
+
+impl M3uStreamOutput {
+    pub fn from_request_param(output: &str) -> Self {
+        match output.trim().to_lowercase().as_str() {
+            "ts" | "mpegts" => Self::Ts,
+            "m3u8" | "hls" => Self::Hls,
+            _ => Self::Default,
+        }
+    }
+
+    pub const fn get_extension(self) -> Option<&'static str> {
+        match self {
+            Self::Default => None,
+            Self::Ts => Some(".ts"),
+            Self::Hls => Some(".m3u8"),
+        }
+    }
+}
+
+/// Split playlists, only the entries of a cluster and/or with a group prefix.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct M3uPlaylistFilter {
+    pub cluster: Option<XtreamCluster>,
+    pub group_prefix: Option<String>,
+}
+
+impl M3uPlaylistFilter {
+    /// `cluster` is one of `live`, `vod` or `series`, `group` is a case-insensitive group title prefix.
+    pub fn from_request_params(cluster: &str, group: &str) -> Self {
+        let cluster = match cluster.trim().to_lowercase().as_str() {
+            "live" => Some(XtreamCluster::Live),
+            "vod" | "movie" | "video" => Some(XtreamCluster::Video),
+            "series" => Some(XtreamCluster::Series),
+            _ => None,
+        };
+        let group = group.trim();
+        Self {
+            cluster,
+            group_prefix: if group.is_empty() { None } else { Some(group.to_lowercase()) },
+        }
+    }
+
+    pub fn matches(&self, m3u_pli: &M3uPlaylistItem) -> bool {
+        self.cluster.is_none_or(|cluster| get_item_cluster(m3u_pli.item_type) == cluster)
+            && self.group_prefix.as_ref().is_none_or(|prefix| m3u_pli.group.to_lowercase().starts_with(prefix.as_str()))
+    }
+}
+
+pub const fn get_item_cluster(item_type: PlaylistItemType) -> XtreamCluster {
+    match item_type {
+        PlaylistItemType::Live
+        | PlaylistItemType::Catchup
+        | PlaylistItemType::LiveUnknown
+        | PlaylistItemType::LiveHls => XtreamCluster::Live,
+        PlaylistItemType::Video => XtreamCluster::Video,
+        PlaylistItemType::Series
+        | PlaylistItemType::SeriesInfo => XtreamCluster::Series,
+    }
+}
+
+/// Playlist parameters of the `get.php` request. `type=m3u` creates a plain playlist without attributes,
+/// `type=m3u_plus` (default) the extended playlist.
+#[derive(Debug, Clone, Default)]
+pub struct M3uPlaylistParams {
+    pub plain: bool,
+    pub output: M3uStreamOutput,
+    pub filter: M3uPlaylistFilter,
+    /// Translated group titles, see `category_translations`.
+    pub category_translations: Option<HashMap<String, String>>,
+    /// Current viewers of the live channels for `sort=trending`, the most watched channels come first.
+    pub trending: Option<HashMap<UUIDType, u32>>,
+}
+
+impl M3uPlaylistParams {
+    pub fn from_request_params(playlist_type: &str, output: &str) -> Self {
+        Self {
+            plain: playlist_type.trim().eq_ignore_ascii_case("m3u"),
+            output: M3uStreamOutput::from_request_param(output),
+            filter: M3uPlaylistFilter::default(),
+            category_translations: None,
+            trending: None,
+        }
+    }
+
+    pub fn with_filter(mut self, filter: M3uPlaylistFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    pub fn with_trending(mut self, trending: Option<HashMap<UUIDType, u32>>) -> Self {
+        self.trending = trending;
+        self
+    }
+
+    pub fn with_category_translations(mut self, category_translations: Option<&HashMap<String, String>>) -> Self {
+        self.category_translations = category_translations.cloned();
+        self
+    }
+}
+
+pub const fn is_live_stream(item_type: PlaylistItemType) -> bool {
+    matches!(item_type, PlaylistItemType::Live | PlaylistItemType::LiveUnknown)
+}
+
+/// The live channels ordered by their viewers, followed by the other items.
+/// The order of channels with the same number of viewers is kept.
+fn sort_trending(mut items: Vec<M3uPlaylistItem>, viewers: &HashMap<UUIDType, u32>) -> Vec<M3uPlaylistItem> {
+    items.sort_by_cached_key(|m3u_pli| if is_live_stream(m3u_pli.item_type) {
+        (false, Reverse(viewers.get(&get_channel_viewer_key(&m3u_pli.url)).copied().unwrap_or(0)))
+    } else {
+        (true, Reverse(0))
+    });
+    items
+}
+
 pub struct M3uPlaylistIterator {
-    reader: IndexedDocumentIterator<u32, M3uPlaylistItem>,
+    reader: DocumentIterator<M3uPlaylistItem>,
     base_url: String,
     username: String,
     password: String,
@@ -20,7 +152,10 @@ pub struct M3uPlaylistIterator {
     mask_redirect_url: bool,
     include_type_in_url: bool,
     proxy_type: ProxyType,
-    _file_lock: FileReadGuard,
+    params: M3uPlaylistParams,
+    epg_now_next: Option<Arc<HashMap<String, EpgNowNext>>>,
+    // the matching items in trending order, the playlist is read at once
+    trending_items: Option<std::vec::IntoIter<M3uPlaylistItem>>,
     started: bool,
 }
 
@@ -29,20 +164,27 @@ impl M3uPlaylistIterator {
         cfg: &Config,
         target: &ConfigTarget,
         user: &ProxyUserCredentials,
+        params: M3uPlaylistParams,
     ) -> Result<Self, M3uFilterError> {
         let target_path = ensure_target_storage_path(cfg, target.name.as_str())?;
         let (m3u_path, idx_path) = m3u_get_file_paths(&target_path);
 
-        let file_lock = cfg.file_locks.read_lock(&m3u_path).await
-            .map_err(|err| info_err!(format!("Could not lock document {m3u_path:?}: {err}")))?;
-
-        let reader =
-            IndexedDocumentIterator::<u32, M3uPlaylistItem>::new(&m3u_path, &idx_path)
-                .map_err(|err| info_err!(format!("Could not deserialize file {m3u_path:?} - {err}")))?;
+        let mut reader = DocumentIterator::<M3uPlaylistItem>::open_target_documents(cfg, &m3u_path, &idx_path).await
+            .map_err(|err| info_err!(format!("Could not deserialize file {m3u_path:?} - {err}")))?;
 
         let target_options = target.options.as_ref();
         let include_type_in_url = target_options.is_some_and(|opts| opts.m3u_include_type_in_url);
         let mask_redirect_url = target_options.is_some_and(|opts| opts.m3u_mask_redirect_url);
+        let epg_now_next = if target_options.is_some_and(|opts| opts.m3u_epg_now_next) {
+            Some(epg_read_now_next(cfg, &m3u_get_epg_file_path(&target_path), Local::now().timestamp()).await)
+        } else {
+            None
+        };
+
+        let trending_items = params.trending.as_ref().map(|viewers| {
+            let items: Vec<M3uPlaylistItem> = reader.by_ref().filter(|m3u_pli| params.filter.matches(m3u_pli)).collect();
+            sort_trending(items, viewers).into_iter()
+        });
 
         let server_info = cfg.get_user_server_info(user);
         Ok(Self {
@@ -54,12 +196,14 @@ impl M3uPlaylistIterator {
             include_type_in_url,
             mask_redirect_url,
             proxy_type: user.proxy.clone(),
-            _file_lock: file_lock, // Save lock inside struct
+            params,
+            epg_now_next,
+            trending_items,
             started: false,
         })
     }
 
-    fn get_rewritten_url(&self, m3u_pli: &M3uPlaylistItem, typed: bool, prefix_path: &str) -> String {
+    fn get_rewritten_url(&self, m3u_pli: &M3uPlaylistItem, typed: bool, prefix_path: &str, extension: &str) -> String {
         if typed {
             let stream_type = match m3u_pli.item_type {
                 PlaylistItemType::Live
@@ -70,52 +214,137 @@ impl M3uPlaylistIterator {
                 PlaylistItemType::Series
                 | PlaylistItemType::SeriesInfo => "series",
             };
-            format!("{}/{prefix_path}/{stream_type}/{}/{}/{}",
+            format!("{}/{prefix_path}/{stream_type}/{}/{}/{}{extension}",
                     &self.base_url,
                     &self.username,
                     &self.password,
                     m3u_pli.virtual_id
             )
         } else {
-            format!("{}/{prefix_path}/{}/{}/{}",
+            format!("{}/{prefix_path}/{}/{}/{}{extension}",
                     &self.base_url, &self.username, &self.password, m3u_pli.virtual_id
             )
         }
     }
 
+    fn get_stream_extension(&self, m3u_pli: &M3uPlaylistItem) -> Option<&'static str> {
+        if is_live_stream(m3u_pli.item_type) { self.params.output.get_extension() } else { None }
+    }
+
     fn get_stream_url(&self, m3u_pli: &M3uPlaylistItem, typed: bool) -> String {
-        self.get_rewritten_url(m3u_pli, typed, M3U_STREAM_PATH)
+        self.get_rewritten_url(m3u_pli, typed, M3U_STREAM_PATH, self.get_stream_extension(m3u_pli).unwrap_or_default())
     }
     fn get_resource_url(&self, m3u_pli: &M3uPlaylistItem) -> String {
-        self.get_rewritten_url(m3u_pli, false, M3U_RESOURCE_PATH)
+        self.get_rewritten_url(m3u_pli, false, M3U_RESOURCE_PATH, "")
     }
 
-}
-
-impl Iterator for M3uPlaylistIterator {
-    type Item = String;
-
-    fn next(&mut self) -> Option<Self::Item> {
-        if !self.started {
-            self.started = true;
-            return Some("#EXTM3U".to_string());
-        }
-
+    /// The next item matching the filter with the rewritten stream and resource urls of the user.
+    fn next_rewritten(&mut self) -> Option<(M3uPlaylistItem, Option<(String, String)>)> {
         // TODO hls and unknown reverse proxy
-        self.reader.next().map(|m3u_pli| {
+        let filter = &self.params.filter;
+        let next_item = match self.trending_items.as_mut() {
+            Some(items) => items.next(),
+            None => self.reader.by_ref().find(|m3u_pli| filter.matches(m3u_pli)),
+        };
+        next_item.map(|mut m3u_pli| {
+            if let Some(group) = self.params.category_translations.as_ref().and_then(|translations| translations.get(m3u_pli.group.as_str())) {
+                m3u_pli.group = Rc::new(group.clone());
+            }
             let rewrite_urls = match m3u_pli.item_type {
                 PlaylistItemType::LiveHls => None,
                 _ => if match &self.proxy_type {
                     ProxyType::Reverse => true,
-                    ProxyType::Redirect => self.mask_redirect_url,
+                    // files of local inputs are always served by the proxy
+                    ProxyType::Redirect => self.mask_redirect_url || is_local_url(&m3u_pli.url),
                 } {
                     Some((self.get_stream_url(&m3u_pli, self.include_type_in_url), self.get_resource_url(&m3u_pli)))
                 } else {
                     None
                 }
             };
+            if rewrite_urls.is_none() {
+                // redirected provider urls, the extension is changed for xtream like live urls
+                if let Some(ext) = self.get_stream_extension(&m3u_pli) {
+                    m3u_pli.url = Rc::new(replace_stream_extension(&m3u_pli.url, ext));
+                }
+            }
+            (m3u_pli, rewrite_urls)
+        })
+    }
+
+    /// The next item with the stream url of the user as `url`.
+    pub fn next_user_item(&mut self) -> Option<M3uPlaylistItem> {
+        self.next_rewritten().map(|(mut m3u_pli, rewrite_urls)| {
+            if let Some((stream_url, _)) = rewrite_urls {
+                m3u_pli.url = Rc::new(stream_url);
+            }
+            m3u_pli
+        })
+    }
+}
+
+impl Iterator for M3uPlaylistIterator {
+    type Item = String;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if !self.started {
+            self.started = true;
+            return Some("#EXTM3U".to_string());
+        }
+
+        self.next_rewritten().map(|(m3u_pli, rewrite_urls)| {
+            if self.params.plain {
+                return m3u_pli.to_plain_m3u(rewrite_urls.as_ref());
+            }
             let target_options = self.target_options.as_ref();
-            m3u_pli.to_m3u(target_options, rewrite_urls.as_ref())
+            let description = self.epg_now_next.as_ref()
+                .and_then(|epg| m3u_pli.epg_channel_id.as_ref().and_then(|id| epg.get(id.as_str())))
+                .and_then(EpgNowNext::to_description);
+            m3u_pli.to_m3u(target_options, rewrite_urls.as_ref(), description.as_deref())
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use std::collections::HashMap;
+
+    use crate::model::channel_viewers::get_channel_viewer_key;
+    use crate::model::playlist::{PlaylistItemType, XtreamCluster};
+    use crate::model::playlist_test_utils::item as test_item;
+    use crate::repository::m3u_playlist_iterator::{sort_trending, M3uPlaylistFilter};
+
+    #[test]
+    fn playlist_filter_test() {
+        let item = |group: &str, item_type: PlaylistItemType| test_item("").group(group).item_type(item_type).virtual_id(1).input_id(1).build_m3u();
+        let filter = M3uPlaylistFilter::from_request_params("vod", "");
+        assert_eq!(filter.cluster, Some(XtreamCluster::Video));
+        assert!(filter.matches(&item("Movies", PlaylistItemType::Video)));
+        assert!(!filter.matches(&item("News", PlaylistItemType::Live)));
+        let filter = M3uPlaylistFilter::from_request_params("", "DE ");
+        assert!(filter.matches(&item("de | Sport", PlaylistItemType::LiveHls)));
+        assert!(!filter.matches(&item("UK | Sport", PlaylistItemType::Live)));
+        assert_eq!(M3uPlaylistFilter::from_request_params("unknown", ""), M3uPlaylistFilter::default());
+
+        let mut item = item("Sport", PlaylistItemType::Live);
+        item.url = Rc::new("http://provider.tv/live/1.ts".to_string());
+        item.user_agent = Rc::new("Special Agent/1.0".to_string());
+        item.referrer = Rc::new("http://provider.tv/".to_string());
+        assert!(item.to_plain_m3u(None).ends_with("\nhttp://provider.tv/live/1.ts|User-Agent=Special%20Agent%2F1.0&Referer=http%3A%2F%2Fprovider.tv%2F"));
+        // proxied urls are requested with the headers by the proxy
+        let rewrite_urls = ("http://proxy.tv/live/u/p/1.ts".to_string(), String::new());
+        assert!(item.to_plain_m3u(Some(&rewrite_urls)).ends_with("\nhttp://proxy.tv/live/u/p/1.ts"));
+    }
+
+    #[test]
+    fn sort_trending_test() {
+        let item = |id: u32, item_type: PlaylistItemType| test_item("").url(&format!("http://provider.tv/live/{id}.ts"))
+            .item_type(item_type).virtual_id(id).input_id(1).build_m3u();
+        let items = vec![item(1, PlaylistItemType::Video), item(2, PlaylistItemType::Live), item(3, PlaylistItemType::Live), item(4, PlaylistItemType::Live)];
+        let viewers = HashMap::from([(get_channel_viewer_key("http://provider.tv/live/4.m3u8"), 3), (get_channel_viewer_key("http://provider.tv/live/3"), 1)]);
+        let ids: Vec<u32> = sort_trending(items, &viewers).iter().map(|m3u_pli| m3u_pli.virtual_id).collect();
+        assert_eq!(ids, vec![4, 3, 2, 1]);
+    }
+}
@@ -1,20 +1,28 @@
 use std::fs::File;
 use std::io::{Error, Write};
 use std::path::{Path, PathBuf};
-use log::error;
+use std::rc::Rc;
+use std::time::SystemTime;
+use log::{error, info};
+use serde::{Deserialize, Serialize};
 
 use crate::{create_m3u_filter_error, info_err};
+use crate::auth::password::generate_random_string;
 use crate::m3u_filter_error::{str_to_io_error, M3uFilterError, M3uFilterErrorKind};
 use crate::model::api_proxy::{ProxyUserCredentials};
-use crate::model::config::{Config, ConfigTarget};
-use crate::model::playlist::{M3uPlaylistItem, PlaylistGroup, PlaylistItem, PlaylistItemType};
-use crate::repository::indexed_document::{IndexedDocumentDirectAccess, IndexedDocumentWriter};
-use crate::repository::m3u_playlist_iterator::M3uPlaylistIterator;
-use crate::repository::storage::{FILE_SUFFIX_DB, FILE_SUFFIX_INDEX};
+use crate::model::config::{Config, ConfigTarget, TargetType};
+use crate::model::playlist::{get_output_channel_number, M3uPlaylistItem, PlaylistGroup, PlaylistItemType};
+use crate::repository::channel_notes_repository::channel_notes_read;
+use crate::repository::indexed_document::{IndexedDocumentDirectAccess, IndexedDocumentIterator, IndexedDocumentWriter};
+use crate::repository::m3u_playlist_iterator::{M3uPlaylistFilter, M3uPlaylistIterator, M3uPlaylistParams};
+use crate::repository::storage::{get_target_storage_path, uuid_as_hex, FILE_SUFFIX_DB, FILE_SUFFIX_INDEX};
 use crate::utils::file_utils;
-use crate::utils::file_utils::file_writer;
+use crate::utils::file_utils::{file_writer, sanitize_filename};
+use crate::utils::output_encoding::{apply_newline_style, get_bom, get_newline};
 
 const FILE_M3U: &str = "m3u";
+const DIR_M3U_GZIP_CACHE: &str = "m3u_gzip";
+const MAX_M3U_GZIP_RENDITIONS: usize = 32;
 macro_rules! cant_write_result {
     ($path:expr, $err:expr) => {
         create_m3u_filter_error!(M3uFilterErrorKind::Notify, "failed to write m3u playlist: {} - {}", $path.to_str().unwrap() ,$err)
@@ -27,54 +35,155 @@ pub fn m3u_get_file_paths(target_path: &Path) -> (PathBuf, PathBuf) {
     (m3u_path, index_path)
 }
 
+/// The gzipped rendition of a playlist response, the etag identifies the response.
+pub fn m3u_get_gzip_cache_path(target_path: &Path, etag: &str) -> PathBuf {
+    target_path.join(DIR_M3U_GZIP_CACHE).join(format!("{etag}.m3u.gz"))
+}
+
+/// The oldest renditions are removed if the cache has more than `MAX_M3U_GZIP_RENDITIONS` entries.
+pub fn m3u_write_gzip_cache(path: &Path, content: &[u8]) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+        m3u_prune_gzip_cache(parent, MAX_M3U_GZIP_RENDITIONS - 1);
+    }
+    // written to a unique temp file, a concurrent request never reads a partial file
+    let tmp_path = path.with_extension(format!("{}.tmp", generate_random_string(8)));
+    std::fs::write(&tmp_path, content)?;
+    std::fs::rename(&tmp_path, path)
+}
+
+fn m3u_prune_gzip_cache(cache_dir: &Path, max_entries: usize) {
+    let Ok(entries) = std::fs::read_dir(cache_dir) else { return; };
+    let mut renditions: Vec<(SystemTime, PathBuf)> = entries.filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "gz"))
+        .filter_map(|path| std::fs::metadata(&path).and_then(|metadata| metadata.modified()).ok().map(|modified| (modified, path)))
+        .collect();
+    if renditions.len() > max_entries {
+        renditions.sort();
+        for (_, path) in renditions.drain(..renditions.len() - max_entries) {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+/// The renditions of the previous playlist are outdated after an update.
+fn m3u_clear_gzip_cache(target_path: &Path) {
+    let cache_dir = target_path.join(DIR_M3U_GZIP_CACHE);
+    if cache_dir.exists() {
+        if let Err(err) = std::fs::remove_dir_all(&cache_dir) {
+            error!("Failed to clear m3u gzip cache {}: {err}", cache_dir.display());
+        }
+    }
+}
+
 pub fn m3u_get_epg_file_path(target_path: &Path) -> PathBuf {
     let path = target_path.join(PathBuf::from(format!("{FILE_M3U}.{FILE_SUFFIX_DB}")));
     file_utils::add_prefix_to_filename(&path, "epg_", Some("xml"))
 }
 
-fn persist_m3u_playlist_as_text(target: &ConfigTarget, cfg: &Config, m3u_playlist: &Vec<M3uPlaylistItem>) {
+fn write_m3u_playlist_file<'a, I>(target: &ConfigTarget, m3u_filename: &Path, m3u_playlist: I)
+where
+    I: Iterator<Item=&'a M3uPlaylistItem>,
+{
+    match File::create(m3u_filename) {
+        Ok(file) => {
+            let mut buf_writer = file_writer(&file);
+            let encoding = target.get_output_encoding();
+            let newline = get_newline(encoding);
+            let _ = buf_writer.write(get_bom(encoding));
+            let _ = buf_writer.write(format!("#EXTM3U{newline}").as_bytes());
+            for m3u in m3u_playlist {
+                let _ = buf_writer.write(apply_newline_style(&m3u.to_m3u(target.options.as_ref(), None, None), encoding).as_bytes());
+                let _ = buf_writer.write(newline.as_bytes());
+            }
+        }
+        Err(_) => {
+            error!("Can't write m3u plain playlist {}", &m3u_filename.to_str().unwrap());
+        }
+    }
+}
+
+/// `playlist.m3u` is split into `playlist_live.m3u` or `playlist_<prefix>.m3u`.
+fn get_split_file_path(m3u_filename: &Path, name: &str) -> PathBuf {
+    let stem = m3u_filename.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = m3u_filename.extension().map_or_else(|| FILE_M3U.to_string(), |ext| ext.to_string_lossy().to_string());
+    m3u_filename.with_file_name(format!("{stem}_{}.{extension}", sanitize_filename(&name.trim().to_lowercase())))
+}
+
+fn get_split_playlists(target: &ConfigTarget) -> Vec<(String, M3uPlaylistFilter)> {
+    let Some(options) = target.options.as_ref() else { return vec![]; };
+    let mut splits = vec![];
+    if options.m3u_split_clusters {
+        for cluster in ["live", "vod", "series"] {
+            splits.push((cluster.to_string(), M3uPlaylistFilter::from_request_params(cluster, "")));
+        }
+    }
+    for prefix in options.m3u_split_group_prefixes.iter().filter(|prefix| !prefix.trim().is_empty()) {
+        splits.push((prefix.to_string(), M3uPlaylistFilter::from_request_params("", prefix)));
+    }
+    splits
+}
+
+fn persist_m3u_playlist_as_text(target: &ConfigTarget, cfg: &Config, m3u_playlist: &[M3uPlaylistItem]) {
     if let Some(filename) = target.get_m3u_filename() {
         if let Some(m3u_filename) = file_utils::get_file_path(&cfg.working_dir, Some(PathBuf::from(filename))) {
-            match File::create(&m3u_filename) {
-                Ok(file) => {
-                    let mut buf_writer = file_writer(&file);
-                    let _ = buf_writer.write(b"#EXTM3U\n");
-                    for m3u in m3u_playlist {
-                        let _ = buf_writer.write(m3u.to_m3u(target.options.as_ref(), None).as_bytes());
-                        let _ = buf_writer.write(b"\n");
-                    }
-                }
-                Err(_) => {
-                    error!("Can't write m3u plain playlist {}", &m3u_filename.to_str().unwrap());
-                }
+            write_m3u_playlist_file(target, &m3u_filename, m3u_playlist.iter());
+            for (name, filter) in get_split_playlists(target) {
+                let split_filename = get_split_file_path(&m3u_filename, &name);
+                write_m3u_playlist_file(target, &split_filename, m3u_playlist.iter().filter(|m3u| filter.matches(m3u)));
             }
         }
     }
 }
 
+/// The source `tvg-chno` is kept, numbering with the virtual ids is applied only with `preserve_channel_numbers`.
+fn get_m3u_channel_number(m3u: &M3uPlaylistItem, preserve_channel_numbers: bool) -> Rc<String> {
+    if preserve_channel_numbers {
+        Rc::new(get_output_channel_number(&m3u.chno, m3u.virtual_id, true).to_string())
+    } else {
+        Rc::clone(&m3u.chno)
+    }
+}
+
 pub async fn m3u_write_playlist(target: &ConfigTarget, cfg: &Config, target_path: &Path, new_playlist: &[PlaylistGroup]) -> Result<(), M3uFilterError> {
     if !new_playlist.is_empty() {
         let (m3u_path, idx_path) = m3u_get_file_paths(target_path);
+        let preserve_channel_numbers = target.options.as_ref().is_some_and(|opts| opts.preserve_channel_numbers);
+        let channel_notes = target.options.as_ref().is_some_and(|opts| opts.m3u_channel_notes)
+            .then(|| channel_notes_read(&cfg.working_dir));
         let m3u_playlist = new_playlist.iter()
             .flat_map(|pg| &pg.channels)
             .filter(|&pli| pli.header.borrow().item_type != PlaylistItemType::SeriesInfo)
-            .map(PlaylistItem::to_m3u).collect::<Vec<M3uPlaylistItem>>();
+            .map(|pli| {
+                let mut m3u = pli.to_m3u();
+                m3u.chno = get_m3u_channel_number(&m3u, preserve_channel_numbers);
+                if let Some(note) = channel_notes.as_ref().and_then(|notes| notes.get(&uuid_as_hex(&pli.header.borrow().uuid))) {
+                    m3u.note = Rc::new(note.note.clone());
+                    m3u.labels = Rc::new(note.labels.join(","));
+                }
+                m3u
+            }).collect::<Vec<M3uPlaylistItem>>();
 
         persist_m3u_playlist_as_text(target, cfg, &m3u_playlist);
-        {
-            let _file_lock = cfg.file_locks.write_lock(&m3u_path).await.map_err(|err| info_err!(format!("{err}")))?;
-            match IndexedDocumentWriter::new(m3u_path.clone(), idx_path) {
-                Ok(mut writer) => {
-                    for m3u in m3u_playlist {
-                        match writer.write_doc(m3u.virtual_id, &m3u) {
-                            Ok(()) => {}
-                            Err(err) => return Err(cant_write_result!(&m3u_path, err))
-                        }
+        m3u_clear_gzip_cache(target_path);
+        if let Some(storage) = cfg.get_memory_storage() {
+            return storage.write_documents(&m3u_path, m3u_playlist.iter().map(|m3u| (m3u.virtual_id, m3u)))
+                .map_err(|err| cant_write_result!(&m3u_path, err));
+        }
+        match IndexedDocumentWriter::new_staged(m3u_path.clone(), idx_path) {
+            Ok(mut writer) => {
+                for m3u in m3u_playlist {
+                    match writer.write_doc(m3u.virtual_id, &m3u) {
+                        Ok(()) => {}
+                        Err(err) => return Err(cant_write_result!(&m3u_path, err))
                     }
-                    writer.store().map_err(|err| cant_write_result!(&m3u_path, err))?;
                 }
-                Err(err) => return Err(cant_write_result!(&m3u_path, err))
+                // the lock is only held to replace the served files
+                let _file_lock = cfg.file_locks.write_lock(&m3u_path).await.map_err(|err| info_err!(format!("{err}")))?;
+                writer.commit().map_err(|err| cant_write_result!(&m3u_path, err))?;
             }
+            Err(err) => return Err(cant_write_result!(&m3u_path, err))
         }
     }
     Ok(())
@@ -84,8 +193,9 @@ pub async fn m3u_load_rewrite_playlist(
     cfg: &Config,
     target: &ConfigTarget,
     user: &ProxyUserCredentials,
+    params: M3uPlaylistParams,
 ) -> Result<Box<dyn Iterator<Item = String>>, M3uFilterError> {
-    Ok(Box::new(M3uPlaylistIterator::new(cfg, target, user).await?))
+    Ok(Box::new(M3uPlaylistIterator::new(cfg, target, user, params).await?))
 }
 
 
@@ -93,8 +203,177 @@ pub async  fn m3u_get_item_for_stream_id(cfg: &Config, stream_id: u32, m3u_path:
     if stream_id < 1 {
         return Err(str_to_io_error("id should start with 1"));
     }
+    if let Some(storage) = cfg.get_memory_storage() {
+        return storage.read_document(m3u_path, stream_id);
+    }
     {
         let _file_lock = cfg.file_locks.read_lock(m3u_path).await?;
         IndexedDocumentDirectAccess::read_indexed_item::<u32, M3uPlaylistItem>(m3u_path, idx_path, &stream_id)
     }
-}
\ No newline at end of file
+}
+
+// Item layout before `user_agent`, `referrer`, `note`, `labels` and `kodi_props` were introduced, only used to migrate existing playlists.
+#[derive(Serialize, Deserialize)]
+struct LegacyM3uPlaylistItem {
+    virtual_id: u32,
+    provider_id: Rc<String>,
+    name: Rc<String>,
+    chno: Rc<String>,
+    logo: Rc<String>,
+    logo_small: Rc<String>,
+    group: Rc<String>,
+    title: Rc<String>,
+    parent_code: Rc<String>,
+    audio_track: Rc<String>,
+    time_shift: Rc<String>,
+    rec: Rc<String>,
+    url: Rc<String>,
+    epg_channel_id: Option<Rc<String>>,
+    input_id: u16,
+    item_type: PlaylistItemType,
+}
+
+impl From<LegacyM3uPlaylistItem> for M3uPlaylistItem {
+    fn from(item: LegacyM3uPlaylistItem) -> Self {
+        let empty = Rc::new(String::new());
+        Self {
+            virtual_id: item.virtual_id,
+            provider_id: item.provider_id,
+            name: item.name,
+            chno: item.chno,
+            logo: item.logo,
+            logo_small: item.logo_small,
+            group: item.group,
+            title: item.title,
+            parent_code: item.parent_code,
+            audio_track: item.audio_track,
+            time_shift: item.time_shift,
+            rec: item.rec,
+            url: item.url,
+            user_agent: Rc::clone(&empty),
+            referrer: Rc::clone(&empty),
+            epg_channel_id: item.epg_channel_id,
+            input_id: item.input_id,
+            item_type: item.item_type,
+            note: Rc::clone(&empty),
+            labels: empty,
+            kodi_props: None,
+        }
+    }
+}
+
+/// Returns `true` if the playlist had the legacy layout and was rewritten.
+fn migrate_m3u_playlist_layout(m3u_path: &Path, idx_path: &Path) -> Result<bool, Error> {
+    let mut current = IndexedDocumentIterator::<u32, M3uPlaylistItem>::new(m3u_path, idx_path)?;
+    if current.read_next().is_ok() {
+        return Ok(false);
+    }
+    drop(current);
+    let mut legacy = IndexedDocumentIterator::<u32, LegacyM3uPlaylistItem>::new(m3u_path, idx_path)?;
+    let items: Vec<M3uPlaylistItem> = legacy.by_ref().map(M3uPlaylistItem::from).collect();
+    if legacy.has_error() {
+        return Err(str_to_io_error(&format!("Unknown playlist layout {}", m3u_path.display())));
+    }
+    drop(legacy);
+    let mut writer = IndexedDocumentWriter::new_staged(m3u_path.to_path_buf(), idx_path.to_path_buf())?;
+    for item in &items {
+        writer.write_doc(item.virtual_id, item)?;
+    }
+    writer.commit()?;
+    Ok(true)
+}
+
+/// Converts the m3u playlists with a legacy item layout at startup,
+/// the playlist readers only decode the current layout.
+pub fn migrate_m3u_playlist_layouts(cfg: &Config) {
+    if cfg.get_memory_storage().is_some() {
+        return;
+    }
+    for target in cfg.sources.iter().flat_map(|source| &source.targets).filter(|target| target.has_output(&TargetType::M3u)) {
+        let Some(target_path) = get_target_storage_path(cfg, &target.name) else { continue; };
+        let (m3u_path, idx_path) = m3u_get_file_paths(&target_path);
+        if !m3u_path.exists() || !idx_path.exists() {
+            continue;
+        }
+        match migrate_m3u_playlist_layout(&m3u_path, &idx_path) {
+            Ok(true) => info!("Migrated m3u playlist {}", m3u_path.display()),
+            Ok(false) => {}
+            Err(err) => error!("Failed to migrate m3u playlist {}: {err}", m3u_path.display()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+    use std::rc::Rc;
+
+    use crate::model::playlist::{M3uPlaylistItem, PlaylistItemType};
+    use crate::model::playlist_test_utils::item;
+    use crate::repository::indexed_document::{IndexedDocumentIterator, IndexedDocumentWriter};
+    use crate::repository::m3u_repository::{get_m3u_channel_number, get_split_file_path, m3u_prune_gzip_cache, migrate_m3u_playlist_layout, LegacyM3uPlaylistItem};
+
+    #[test]
+    fn prune_gzip_cache_test() {
+        let cache_dir = tempfile::tempdir().unwrap();
+        for (index, name) in ["a", "b", "c"].iter().enumerate() {
+            let path = cache_dir.path().join(format!("{name}.m3u.gz"));
+            std::fs::write(&path, name).unwrap();
+            let modified = std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1000 + index as u64);
+            std::fs::File::options().write(true).open(&path).unwrap().set_modified(modified).unwrap();
+        }
+        m3u_prune_gzip_cache(cache_dir.path(), 2);
+        assert!(!cache_dir.path().join("a.m3u.gz").exists());
+        assert!(cache_dir.path().join("b.m3u.gz").exists());
+        assert!(cache_dir.path().join("c.m3u.gz").exists());
+    }
+
+    #[test]
+    fn split_file_path_test() {
+        assert_eq!(get_split_file_path(Path::new("/data/playlist.m3u"), "live"), PathBuf::from("/data/playlist_live.m3u"));
+        assert_eq!(get_split_file_path(Path::new("/data/playlist"), "DE |"), PathBuf::from("/data/playlist_de__.m3u"));
+    }
+
+    #[test]
+    fn m3u_channel_number_test() {
+        let numbered = item("News").chno("101").virtual_id(5).build_m3u();
+        let named = item("Sport").chno("A1").virtual_id(6).build_m3u();
+        let missing = item("Kids").virtual_id(7).build_m3u();
+        // the source tvg-chno survives with default options
+        assert_eq!(get_m3u_channel_number(&numbered, false).as_str(), "101");
+        assert_eq!(get_m3u_channel_number(&named, false).as_str(), "A1");
+        assert_eq!(get_m3u_channel_number(&missing, false).as_str(), "");
+        assert_eq!(get_m3u_channel_number(&numbered, true).as_str(), "101");
+        assert_eq!(get_m3u_channel_number(&named, true).as_str(), "6");
+        assert_eq!(get_m3u_channel_number(&missing, true).as_str(), "7");
+    }
+
+    #[test]
+    fn migrate_m3u_playlist_layout_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let (m3u_path, idx_path) = (dir.path().join("m3u.db"), dir.path().join("m3u.idx"));
+        let text = |value: &str| Rc::new(value.to_string());
+        let mut writer = IndexedDocumentWriter::new(m3u_path.clone(), idx_path.clone()).unwrap();
+        for virtual_id in 1..=2 {
+            let legacy = LegacyM3uPlaylistItem {
+                virtual_id, provider_id: text("11"), name: text("News"), chno: text("7"), logo: text("http://logo.png"), logo_small: text(""),
+                group: text("DE"), title: text("News"), parent_code: text(""), audio_track: text(""), time_shift: text(""), rec: text(""),
+                url: text("http://provider.tv/1.ts"), epg_channel_id: Some(text("news.de")), input_id: 1, item_type: PlaylistItemType::Live,
+            };
+            writer.write_doc(virtual_id, &legacy).unwrap();
+        }
+        writer.store().unwrap();
+        drop(writer);
+
+        assert!(migrate_m3u_playlist_layout(&m3u_path, &idx_path).unwrap());
+        let items: Vec<M3uPlaylistItem> = IndexedDocumentIterator::<u32, M3uPlaylistItem>::new(&m3u_path, &idx_path).unwrap().collect();
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[1].virtual_id, 2);
+        assert_eq!(items[1].chno.as_str(), "7");
+        assert_eq!(items[1].url.as_str(), "http://provider.tv/1.ts");
+        assert!(items[1].user_agent.is_empty() && items[1].note.is_empty() && items[1].kodi_props.is_none());
+        assert_eq!(items[1].epg_channel_id.as_deref().map(String::as_str), Some("news.de"));
+        // the current layout is kept
+        assert!(!migrate_m3u_playlist_layout(&m3u_path, &idx_path).unwrap());
+    }
+}
@@ -0,0 +1,269 @@
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind};
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::m3u_filter_error::{str_to_io_error, to_io_error};
+use crate::model::config::Config;
+use crate::repository::bplustree::BPlusTree;
+use crate::repository::indexed_document::IndexedDocumentIterator;
+use crate::repository::target_id_mapping::VirtualIdRecord;
+
+/// Documents of a collection in write order, the index maps the document id to the position.
+#[derive(Debug, Default, Clone)]
+struct MemoryDocuments {
+    docs: Vec<Vec<u8>>,
+    index: HashMap<u32, usize>,
+}
+
+impl MemoryDocuments {
+    fn insert(&mut self, doc_id: u32, content: Vec<u8>) {
+        match self.index.get(&doc_id) {
+            Some(&pos) => self.docs[pos] = content,
+            None => {
+                self.index.insert(doc_id, self.docs.len());
+                self.docs.push(content);
+            }
+        }
+    }
+}
+
+fn not_found(path: &Path) -> Error {
+    Error::new(ErrorKind::NotFound, format!("Not found in memory storage {}", path.to_string_lossy()))
+}
+
+/// Target storage of the `memory` storage mode.
+/// Playlists, categories and id mappings are kept under their file path instead of writing the files.
+/// A collection is swapped as a whole, readers keep the collection they started with.
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    documents: RwLock<HashMap<PathBuf, Arc<MemoryDocuments>>>,
+    files: RwLock<HashMap<PathBuf, Arc<Vec<u8>>>>,
+    id_mappings: Mutex<HashMap<PathBuf, BPlusTree<u32, VirtualIdRecord>>>,
+}
+
+impl MemoryStorage {
+    fn get_documents(&self, path: &Path) -> Option<Arc<MemoryDocuments>> {
+        self.documents.read().ok()?.get(path).cloned()
+    }
+
+    /// Replaces the collection with the given documents.
+    pub fn write_documents<'a, T, I>(&self, path: &Path, docs: I) -> Result<(), Error>
+    where
+        T: Serialize + 'a,
+        I: IntoIterator<Item=(u32, &'a T)>,
+    {
+        let mut documents = MemoryDocuments::default();
+        for (doc_id, doc) in docs {
+            documents.insert(doc_id, bincode::serialize(doc).map_err(to_io_error)?);
+        }
+        self.documents.write().map_err(|_| str_to_io_error("Memory storage lock poisoned"))?
+            .insert(path.to_path_buf(), Arc::new(documents));
+        Ok(())
+    }
+
+    /// Inserts or replaces a single document of the collection.
+    pub fn write_document<T: Serialize + ?Sized>(&self, path: &Path, doc_id: u32, doc: &T) -> Result<(), Error> {
+        let content = bincode::serialize(doc).map_err(to_io_error)?;
+        let mut documents = self.documents.write().map_err(|_| str_to_io_error("Memory storage lock poisoned"))?;
+        Arc::make_mut(documents.entry(path.to_path_buf()).or_default()).insert(doc_id, content);
+        Ok(())
+    }
+
+    /// Removes the documents which are not accepted by `keep`, a collection without documents is removed.
+    pub fn retain_documents<F: Fn(u32) -> bool>(&self, path: &Path, keep: F) -> Result<(), Error> {
+        let mut documents = self.documents.write().map_err(|_| str_to_io_error("Memory storage lock poisoned"))?;
+        let Some(current) = documents.get(path) else {
+            return Ok(());
+        };
+        let mut retained = MemoryDocuments::default();
+        let mut entries: Vec<(u32, usize)> = current.index.iter().filter(|(doc_id, _)| keep(**doc_id)).map(|(doc_id, pos)| (*doc_id, *pos)).collect();
+        if entries.len() == current.index.len() {
+            return Ok(());
+        }
+        entries.sort_by_key(|(_, pos)| *pos);
+        for (doc_id, pos) in entries {
+            retained.insert(doc_id, current.docs[pos].clone());
+        }
+        if retained.docs.is_empty() {
+            documents.remove(path);
+        } else {
+            documents.insert(path.to_path_buf(), Arc::new(retained));
+        }
+        Ok(())
+    }
+
+    pub fn read_document<T: DeserializeOwned>(&self, path: &Path, doc_id: u32) -> Result<T, Error> {
+        let documents = self.get_documents(path).ok_or_else(|| not_found(path))?;
+        let pos = documents.index.get(&doc_id).ok_or_else(|| not_found(path))?;
+        bincode::deserialize(&documents.docs[*pos]).map_err(to_io_error)
+    }
+
+    pub fn iter_documents<T: DeserializeOwned>(&self, path: &Path) -> Result<MemoryDocumentIterator<T>, Error> {
+        let documents = self.get_documents(path).ok_or_else(|| not_found(path))?;
+        Ok(MemoryDocumentIterator { documents, path: path.to_path_buf(), index: 0, failed: false, t_type: PhantomData })
+    }
+
+    pub fn write_file(&self, path: &Path, content: Vec<u8>) -> Result<(), Error> {
+        self.files.write().map_err(|_| str_to_io_error("Memory storage lock poisoned"))?
+            .insert(path.to_path_buf(), Arc::new(content));
+        Ok(())
+    }
+
+    pub fn read_file(&self, path: &Path) -> Option<Arc<Vec<u8>>> {
+        self.files.read().ok()?.get(path).cloned()
+    }
+
+    pub fn load_id_mapping(&self, path: &Path) -> Option<BPlusTree<u32, VirtualIdRecord>> {
+        self.id_mappings.lock().ok()?.get(path).cloned()
+    }
+
+    pub fn store_id_mapping(&self, path: &Path, tree: &BPlusTree<u32, VirtualIdRecord>) -> Result<(), Error> {
+        self.id_mappings.lock().map_err(|_| str_to_io_error("Memory storage lock poisoned"))?
+            .insert(path.to_path_buf(), tree.clone());
+        Ok(())
+    }
+
+    pub fn query_id_mapping(&self, path: &Path, virtual_id: u32) -> Option<VirtualIdRecord> {
+        self.id_mappings.lock().ok()?.get(path)?.query(&virtual_id).cloned()
+    }
+
+    pub fn update_id_mapping(&self, path: &Path, virtual_id: u32, record: VirtualIdRecord) -> Result<(), Error> {
+        let mut id_mappings = self.id_mappings.lock().map_err(|_| str_to_io_error("Memory storage lock poisoned"))?;
+        let tree = id_mappings.get_mut(path).ok_or_else(|| not_found(path))?;
+        tree.insert(virtual_id, record);
+        Ok(())
+    }
+}
+
+pub struct MemoryDocumentIterator<T> {
+    documents: Arc<MemoryDocuments>,
+    path: PathBuf,
+    index: usize,
+    failed: bool,
+    t_type: PhantomData<T>,
+}
+
+impl<T: DeserializeOwned> Iterator for MemoryDocumentIterator<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let content = self.documents.docs.get(self.index)?;
+        self.index += 1;
+        match bincode::deserialize::<T>(content) {
+            Ok(value) => Some(value),
+            Err(_) => {
+                self.failed = true;
+                None
+            }
+        }
+    }
+}
+
+/// Reads the documents of a target collection from the file or from the memory storage.
+pub(in crate::repository) enum DocumentIterator<T> {
+    File(IndexedDocumentIterator<u32, T>),
+    Memory(MemoryDocumentIterator<T>),
+}
+
+impl<T: DeserializeOwned> DocumentIterator<T> {
+    pub fn open(storage: Option<&Arc<MemoryStorage>>, main_path: &Path, index_path: &Path) -> Result<Self, Error> {
+        match storage {
+            Some(storage) => storage.iter_documents(main_path).map(DocumentIterator::Memory),
+            None => IndexedDocumentIterator::new(main_path, index_path).map(DocumentIterator::File),
+        }
+    }
+
+    /// Opens the documents of the target storage. A file is read locked while it is opened, it is replaced by
+    /// renaming and not modified in place. The memory storage replaces the documents as a whole and needs no lock.
+    pub async fn open_target_documents(cfg: &Config, main_path: &Path, index_path: &Path) -> Result<Self, Error> {
+        let storage = cfg.get_memory_storage();
+        if storage.is_some() {
+            return Self::open(storage, main_path, index_path);
+        }
+        let _file_lock = cfg.file_locks.read_lock(main_path).await?;
+        Self::open(storage, main_path, index_path)
+    }
+
+    pub fn get_path(&self) -> &Path {
+        match self {
+            Self::File(reader) => reader.get_path(),
+            Self::Memory(reader) => &reader.path,
+        }
+    }
+
+    pub const fn has_error(&self) -> bool {
+        match self {
+            Self::File(reader) => reader.has_error(),
+            Self::Memory(reader) => reader.failed,
+        }
+    }
+}
+
+impl<T: DeserializeOwned> Iterator for DocumentIterator<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Self::File(reader) => reader.next(),
+            Self::Memory(reader) => reader.next(),
+        }
+    }
+}
+
+/// Json collections (categories) are stored as file content.
+pub fn memory_write_json<T: Serialize + ?Sized>(storage: &MemoryStorage, path: &Path, value: &T) -> Result<(), Error> {
+    storage.write_file(path, serde_json::to_vec(value).map_err(to_io_error)?)
+}
+
+pub fn memory_read_json<T: for<'de> Deserialize<'de>>(storage: &MemoryStorage, path: &Path) -> Option<T> {
+    storage.read_file(path).and_then(|content| serde_json::from_slice(&content).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::repository::memory_storage::MemoryStorage;
+
+    #[test]
+    fn memory_documents_test() {
+        let storage = MemoryStorage::default();
+        let path = Path::new("/target/live.db");
+        let docs = [(3, "three".to_string()), (1, "one".to_string())];
+        storage.write_documents(path, docs.iter().map(|(id, doc)| (*id, doc))).unwrap();
+        let reader = storage.iter_documents::<String>(path).unwrap();
+        storage.write_document(path, 2, &"two".to_string()).unwrap();
+        storage.write_document(path, 3, &"drei".to_string()).unwrap();
+        // the reader keeps the collection it started with
+        assert_eq!(reader.collect::<Vec<_>>(), vec!["three", "one"]);
+        assert_eq!(storage.iter_documents::<String>(path).unwrap().collect::<Vec<_>>(), vec!["drei", "one", "two"]);
+        assert_eq!(storage.read_document::<String>(path, 1).unwrap(), "one");
+        assert!(storage.read_document::<String>(path, 4).is_err());
+        assert!(storage.iter_documents::<String>(Path::new("/target/vod.db")).is_err());
+    }
+
+    #[test]
+    fn retain_documents_test() {
+        let storage = MemoryStorage::default();
+        let path = Path::new("/target/vod_info.db");
+        for (doc_id, doc) in [(5, "five"), (2, "two"), (7, "seven")] {
+            storage.write_document(path, doc_id, &doc.to_string()).unwrap();
+        }
+        storage.retain_documents(path, |doc_id| doc_id != 2).unwrap();
+        assert_eq!(storage.iter_documents::<String>(path).unwrap().collect::<Vec<_>>(), vec!["five", "seven"]);
+        assert_eq!(storage.read_document::<String>(path, 7).unwrap(), "seven");
+        assert!(storage.read_document::<String>(path, 2).is_err());
+        storage.retain_documents(path, |_| false).unwrap();
+        assert!(storage.iter_documents::<String>(path).is_err());
+        // a missing collection is no error
+        storage.retain_documents(Path::new("/target/series_info.db"), |_| false).unwrap();
+    }
+}
@@ -1,5 +1,7 @@
 pub mod storage;
 pub mod target_id_mapping;
+pub mod id_namespace;
+pub mod memory_storage;
 pub mod bplustree;
 mod indexed_document;
 pub use indexed_document::IndexedDocumentReader;
@@ -10,3 +12,9 @@ pub mod epg_repository;
 pub mod kodi_repository;
 pub mod m3u_playlist_iterator;
 pub mod xtream_playlist_iterator;
+pub mod report_repository;
+pub mod storage_compaction;
+pub mod user_repository;
+pub mod stalker_repository;
+pub mod input_fallback_repository;
+pub mod channel_notes_repository;
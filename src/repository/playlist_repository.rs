@@ -1,74 +1,241 @@
-use crate::info_err;
-use crate::m3u_filter_error::{M3uFilterError, M3uFilterErrorKind};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::Error;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use log::info;
+
+use crate::{info_err, notify_err};
+use crate::m3u_filter_error::{str_to_io_error, M3uFilterError, M3uFilterErrorKind};
 use crate::model::config::{Config, ConfigTarget, TargetType};
 use crate::model::playlist::PlaylistItemType::LiveUnknown;
-use crate::model::playlist::{PlaylistGroup, PlaylistItemType};
+use crate::model::playlist::{M3uPlaylistItem, PlaylistGroup, PlaylistItem, PlaylistItemHeader, PlaylistItemType, XtreamCluster, XtreamPlaylistItem};
 use crate::model::xmltv::Epg;
+use crate::processing::epg_enrich::normalize_name;
+use crate::processing::movie_parts::assign_movie_parts;
+use crate::processing::series_merge::MergedEpisodes;
 use crate::repository::epg_repository::epg_write;
 use crate::repository::kodi_repository::kodi_write_strm_playlist;
-use crate::repository::m3u_repository::m3u_write_playlist;
-use crate::repository::storage::{ensure_target_storage_path, get_target_id_mapping_file};
-use crate::repository::target_id_mapping::TargetIdMapping;
-use crate::repository::xtream_repository::xtream_write_playlist;
+use crate::repository::m3u_repository::{m3u_get_epg_file_path, m3u_get_file_paths, m3u_get_item_for_stream_id, m3u_write_playlist};
+use crate::repository::memory_storage::DocumentIterator;
+use crate::repository::stalker_repository::stalker_write_playlist;
+use crate::repository::storage::{ensure_target_storage_path, get_target_id_mapping_file, get_target_storage_path, hash_string};
+use crate::repository::id_namespace::get_target_id_namespace;
+use crate::repository::target_id_mapping::{IdMappingEntry, TargetIdMapping};
+use crate::repository::xtream_repository::{xtream_get_epg_file_path, xtream_get_file_paths, xtream_get_item_for_stream_id, xtream_get_storage_path, xtream_write_playlist};
+use crate::utils::disk_space::check_disk_space;
+use crate::utils::step_measure::StepMeasure;
+
+/// The playlist and epg documents of the target, they are staged next to the served files on an update.
+/// Caches like the gzip renditions or the epg variants in the target directory don't grow the update.
+fn get_output_document_paths(cfg: &Config, target_path: &Path, target_name: &str) -> Vec<PathBuf> {
+    let (m3u_path, m3u_index_path) = m3u_get_file_paths(target_path);
+    let mut documents = vec![m3u_path, m3u_index_path, m3u_get_epg_file_path(target_path)];
+    if let Some(xtream_path) = xtream_get_storage_path(cfg, target_name) {
+        for cluster in [XtreamCluster::Live, XtreamCluster::Video, XtreamCluster::Series] {
+            let (main_path, index_path) = xtream_get_file_paths(&xtream_path, cluster);
+            documents.push(main_path);
+            documents.push(index_path);
+        }
+        documents.push(xtream_get_epg_file_path(&xtream_path));
+    }
+    documents
+}
 
-pub async fn persist_playlist(playlist: &mut [PlaylistGroup], epg: Option<&Epg>,
-                              target: &ConfigTarget, cfg: &Config) -> Result<(), Vec<M3uFilterError>> {
+fn get_documents_size(documents: &[PathBuf]) -> u64 {
+    documents.iter().filter_map(|path| std::fs::metadata(path).ok()).map(|meta| meta.len()).sum()
+}
+
+/// The previous output size is the estimate of the update, the file system is queried on a blocking thread.
+async fn check_target_disk_space(cfg: &Config, target_path: &Path, target_name: &str) -> Result<(), String> {
+    let documents = get_output_document_paths(cfg, target_path, target_name);
+    let disk_space = cfg.t_disk_space.clone();
+    let target_path = target_path.to_path_buf();
+    tokio::task::spawn_blocking(move || check_disk_space(&disk_space, &target_path, get_documents_size(&documents)))
+        .await.map_err(|err| err.to_string())?
+}
+
+pub async fn persist_playlist(playlist: &mut [PlaylistGroup], epg: Option<&Epg>, merged_episodes: Option<&MergedEpisodes>,
+                              target: &ConfigTarget, cfg: &Config, measure: &mut StepMeasure) -> Result<(), Vec<M3uFilterError>> {
     let mut errors = vec![];
     let target_path = match ensure_target_storage_path(cfg, &target.name) {
         Ok(path) => path,
         Err(err) => return Err(vec![err]),
     };
+    if let Err(err) = check_target_disk_space(cfg, &target_path, &target.name).await {
+        return Err(vec![notify_err!(format!("Target {} is not updated: {err}", target.name))]);
+    }
 
     let target_id_mapping_file = get_target_id_mapping_file(&target_path);
 
-    let _file_lock = match cfg.file_locks.write_lock(&target_id_mapping_file).await {
-        Ok(lock) => lock,
-        Err(err) => {
-            errors.push(info_err!(err.to_string()));
-            return Err(errors);
+    {
+        // The id mapping lock is released before the outputs are written, the playlist files have their own locks.
+        let _file_lock = match cfg.file_locks.write_lock(&target_id_mapping_file).await {
+            Ok(lock) => lock,
+            Err(err) => {
+                errors.push(info_err!(err.to_string()));
+                return Err(errors);
+            }
+        };
+
+        let namespace = match get_target_id_namespace(cfg, &target.name) {
+            Ok(namespace) => namespace,
+            Err(err) => {
+                errors.push(err);
+                return Err(errors);
+            }
+        };
+        let mut target_id_mapping = TargetIdMapping::open(cfg, &target_id_mapping_file, namespace);
+        let retention = target.options.as_ref().map_or(0, |o| o.id_mapping_retention);
+        let rematch = target.options.as_ref().is_some_and(|o| o.id_mapping_rematch);
+        // the refresh counter marks the seen entries, only needed for the retention and the re-match
+        if retention > 0 || rematch {
+            target_id_mapping.start_refresh();
         }
-    };
 
-    let mut target_id_mapping = TargetIdMapping::new(&target_id_mapping_file);
+        // Virtual IDs assignment
+        let mut entries = vec![];
+        for group in playlist.iter_mut() {
+            for channel in &group.channels {
+                let mut header = channel.header.borrow_mut();
+                let provider_id = header.get_provider_id().unwrap_or_default();
+                if provider_id == 0 {
+                    header.item_type = if header.url.ends_with(".m3u8") { PlaylistItemType::LiveHls } else { LiveUnknown };
+                }
+                let uuid = **header.get_uuid();
+                let name = if header.title.is_empty() { &header.name } else { &header.title };
+                let fingerprint = hash_string(&format!("{}|{}|{}|{}", header.input_id, header.item_type,
+                                                       normalize_name(name), normalize_name(&header.group)));
+                entries.push(IdMappingEntry { uuid, provider_id, item_type: header.item_type, fingerprint });
+            }
+        }
+        let (virtual_ids, rematched) = match target_id_mapping.assign_entries(&entries, rematch) {
+            Ok(assigned) => assigned,
+            Err(err) => {
+                errors.push(notify_err!(format!("Target {} is not updated: {err}", target.name)));
+                return Err(errors);
+            }
+        };
+        for (channel, virtual_id) in playlist.iter().flat_map(|group| &group.channels).zip(virtual_ids) {
+            channel.header.borrow_mut().virtual_id = virtual_id;
+        }
+        if rematched > 0 {
+            info!("Re-matched {rematched} virtual ids for target {}", target.name);
+        }
 
-    // Virtual IDs assignment
-    for group in playlist.iter_mut() {
-        for channel in &group.channels {
-            let mut header = channel.header.borrow_mut();
-            let provider_id = header.get_provider_id().unwrap_or_default();
-            if provider_id == 0 {
-                header.item_type = if header.url.ends_with(".m3u8") { PlaylistItemType::LiveHls } else { LiveUnknown };
+        // an empty playlist is most likely a provider failure, keep the ids in this case
+        if retention > 0 && playlist.iter().any(|group| !group.channels.is_empty()) {
+            let protected = cfg.t_user_bouquets.get_target_ids(&target.name);
+            let dropped = target_id_mapping.garbage_collect(u32::from(retention), &protected);
+            if dropped > 0 {
+                info!("Dropped {dropped} stale virtual ids for target {}", target.name);
             }
-            let uuid = header.get_uuid();
-            let item_type = header.item_type;
-            header.virtual_id = target_id_mapping.insert_entry(**uuid, provider_id, item_type, 0);
+        }
+        if let Err(err) = target_id_mapping.persist() {
+            errors.push(info_err!(err.to_string()));
         }
     }
+    assign_movie_parts(playlist);
+    measure.tick("virtual ids");
 
     for output in &target.output {
         let result = match output.target {
             TargetType::M3u => m3u_write_playlist(target, cfg, &target_path, playlist).await,
-            TargetType::Xtream => xtream_write_playlist(target, cfg, playlist).await,
+            TargetType::Xtream => xtream_write_playlist(target, cfg, playlist, merged_episodes).await,
             TargetType::Strm => kodi_write_strm_playlist(target, cfg, playlist, output).await,
+            TargetType::Stalker => stalker_write_playlist(cfg, &target_path, playlist).await,
         };
 
         if let Err(err) = result {
             errors.push(err);
-        } else {
-            if let Err(err) = target_id_mapping.persist() {
-                errors.push(info_err!(err.to_string()));
+        } else if !playlist.is_empty() {
+            if let Err(err) = epg_write(target, cfg, &target_path, epg, output).await {
+                errors.push(err);
             }
-            if !playlist.is_empty() {
-                if let Err(err) = epg_write(target, cfg, &target_path, epg, output) {
-                    errors.push(err);
-                }
+        }
+        measure.tick(&format!("write {}", output.target));
+    }
+
+    if errors.is_empty() { Ok(()) } else { Err(errors) }
+}
+
+/// The stored playlist of a target for the web ui, read from the m3u output or else from the xtream live and vod output.
+/// The channels carry their virtual ids, the web player requests the playback tokens with them.
+pub async fn load_target_playlist(cfg: &Config, target: &ConfigTarget) -> Result<Vec<PlaylistGroup>, Error> {
+    let target_path = get_target_storage_path(cfg, &target.name).ok_or_else(|| str_to_io_error(&format!("Could not find path for target {}", target.name)))?;
+    let mut groups: Vec<PlaylistGroup> = vec![];
+    let mut group_index: HashMap<(XtreamCluster, Rc<String>), usize> = HashMap::new();
+    let mut add_item = |header: PlaylistItemHeader| {
+        let idx = *group_index.entry((header.xtream_cluster, Rc::clone(&header.group))).or_insert_with(|| {
+            groups.push(PlaylistGroup { id: u32::try_from(groups.len() + 1).unwrap_or(u32::MAX), title: Rc::clone(&header.group), channels: vec![], xtream_cluster: header.xtream_cluster });
+            groups.len() - 1
+        });
+        groups[idx].channels.push(PlaylistItem { header: RefCell::new(header) });
+    };
+    if target.has_output(&TargetType::M3u) {
+        let (m3u_path, idx_path) = m3u_get_file_paths(&target_path);
+        for pli in DocumentIterator::<M3uPlaylistItem>::open_target_documents(cfg, &m3u_path, &idx_path).await? {
+            add_item(PlaylistItemHeader {
+                id: pli.provider_id, name: pli.name, chno: pli.chno, logo: pli.logo, group: pli.group, title: pli.title, url: pli.url,
+                epg_channel_id: pli.epg_channel_id, item_type: pli.item_type, xtream_cluster: XtreamCluster::try_from(pli.item_type).unwrap_or(XtreamCluster::Live),
+                virtual_id: pli.virtual_id, input_id: pli.input_id, ..PlaylistItemHeader::default()
+            });
+        }
+    } else if target.has_output(&TargetType::Xtream) {
+        let storage_path = xtream_get_storage_path(cfg, &target.name).ok_or_else(|| str_to_io_error(&format!("Could not find path for target {} xtream output", target.name)))?;
+        for cluster in [XtreamCluster::Live, XtreamCluster::Video] {
+            let (xtream_path, idx_path) = xtream_get_file_paths(&storage_path, cluster);
+            if cfg.get_memory_storage().is_none() && !xtream_path.exists() {
+                continue;
+            }
+            for pli in DocumentIterator::<XtreamPlaylistItem>::open_target_documents(cfg, &xtream_path, &idx_path).await? {
+                add_item(PlaylistItemHeader {
+                    id: Rc::new(pli.provider_id.to_string()), name: pli.name, chno: pli.chno, logo: pli.logo, group: pli.group, title: pli.title, url: pli.url,
+                    epg_channel_id: pli.epg_channel_id, item_type: pli.item_type, xtream_cluster: pli.xtream_cluster,
+                    virtual_id: pli.virtual_id, input_id: pli.input_id, ..PlaylistItemHeader::default()
+                });
             }
         }
     }
+    Ok(groups)
+}
 
-    if let Err(err) = target_id_mapping.persist() {
-        errors.push(info_err!(err.to_string()));
+/// The provider url, input and type of a stored channel of the target.
+pub async fn get_target_stream(cfg: &Config, target: &ConfigTarget, stream_id: u32) -> Result<(String, u16, PlaylistItemType), Error> {
+    if target.has_output(&TargetType::M3u) {
+        let target_path = get_target_storage_path(cfg, &target.name).ok_or_else(|| str_to_io_error(&format!("Could not find path for target {}", target.name)))?;
+        let (m3u_path, idx_path) = m3u_get_file_paths(&target_path);
+        let pli = m3u_get_item_for_stream_id(cfg, stream_id, &m3u_path, &idx_path).await?;
+        Ok((pli.url.to_string(), pli.input_id, pli.item_type))
+    } else if target.has_output(&TargetType::Xtream) {
+        let pli = xtream_get_item_for_stream_id(stream_id, cfg, target, None).await?;
+        Ok((pli.url.to_string(), pli.input_id, pli.item_type))
+    } else {
+        Err(str_to_io_error(&format!("Target {} has no m3u or xtream output", target.name)))
     }
+}
 
-    if errors.is_empty() { Ok(()) } else { Err(errors) }
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::model::config::Config;
+    use crate::repository::playlist_repository::{get_documents_size, get_output_document_paths};
+
+    #[test]
+    fn output_documents_size_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let cfg = Config { working_dir: dir.path().to_string_lossy().to_string(), ..Config::default() };
+        let target_path = dir.path().join("all");
+        fs::create_dir_all(target_path.join("xtream")).unwrap();
+        fs::create_dir_all(target_path.join("m3u_gzip")).unwrap();
+        fs::write(target_path.join("m3u.db"), [0u8; 100]).unwrap();
+        fs::write(target_path.join("xtream").join("live.db"), [0u8; 50]).unwrap();
+        // the caches of the target are not part of the update
+        fs::write(target_path.join("m3u_gzip").join("user.m3u.gz"), [0u8; 1000]).unwrap();
+        fs::write(target_path.join("xtream").join("epg.xml.variants"), [0u8; 1000]).unwrap();
+        let documents = get_output_document_paths(&cfg, &target_path, "all");
+        assert_eq!(get_documents_size(&documents), 150);
+    }
 }
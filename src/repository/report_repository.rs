@@ -0,0 +1,51 @@
+use std::fs::File;
+use std::path::PathBuf;
+
+use log::error;
+use serde::Serialize;
+
+use crate::m3u_filter_error::{M3uFilterError, M3uFilterErrorKind};
+use crate::model::config::Config;
+use crate::repository::storage::{ensure_target_storage_path, get_target_storage_path};
+use crate::utils::file_utils::file_writer;
+use crate::create_m3u_filter_error_result;
+
+pub const REPORT_MAPPING: &str = "mapping_report.json";
+pub const REPORT_TIMING: &str = "timing_report.json";
+pub const REPORT_PARSER: &str = "parser_report.json";
+pub const REPORT_VALIDATION: &str = "validation_report.json";
+
+fn get_report_file_path(target_path: &std::path::Path, report_name: &str) -> PathBuf {
+    target_path.join(report_name)
+}
+
+/// Writes the report as json into the target storage directory.
+pub fn write_target_report<T>(cfg: &Config, target_name: &str, report_name: &str, report: &T) -> Result<(), M3uFilterError>
+where
+    T: ?Sized + Serialize,
+{
+    let target_path = ensure_target_storage_path(cfg, target_name)?;
+    let report_path = get_report_file_path(&target_path, report_name);
+    match File::create(&report_path) {
+        Ok(file) => {
+            if let Err(err) = serde_json::to_writer(file_writer(file), report) {
+                return create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "failed to write report {}: {err}", report_path.to_string_lossy());
+            }
+            Ok(())
+        }
+        Err(err) => create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "failed to create report {}: {err}", report_path.to_string_lossy())
+    }
+}
+
+/// Returns the raw json content of a stored target report.
+pub fn read_target_report(cfg: &Config, target_name: &str, report_name: &str) -> Option<String> {
+    let target_path = get_target_storage_path(cfg, target_name)?;
+    let report_path = get_report_file_path(&target_path, report_name);
+    if report_path.exists() {
+        match std::fs::read_to_string(&report_path) {
+            Ok(content) => return Some(content),
+            Err(err) => error!("failed to read report {}: {err}", report_path.to_string_lossy()),
+        }
+    }
+    None
+}
@@ -0,0 +1,159 @@
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::m3u_filter_error::{M3uFilterError, M3uFilterErrorKind};
+use crate::model::config::Config;
+use crate::model::playlist::{PlaylistGroup, PlaylistItemType, XtreamCluster};
+use crate::repository::memory_storage::{memory_read_json, memory_write_json};
+use crate::repository::storage::get_target_storage_path;
+use crate::utils::file_utils::file_reader;
+use crate::utils::json_utils::json_write_documents_to_file;
+use crate::{create_m3u_filter_error, info_err};
+
+const FILE_STALKER_PLAYLIST: &str = "stalker_playlist.json";
+const FILE_STALKER_EPG: &str = "epg_stalker.xml";
+
+/// Genre of the stalker portal, a live group of the target.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StalkerGenre {
+    pub id: u32,
+    pub title: String,
+}
+
+/// Live channel of the stalker portal. The stalker channel id is the virtual id of the channel,
+/// the favorites of the boxes stay valid between the updates.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StalkerChannel {
+    pub id: u32,
+    pub number: u32,
+    pub name: String,
+    pub logo: String,
+    pub genre_id: u32,
+    pub epg_channel_id: String,
+    pub url: String,
+    pub user_agent: String,
+    pub referrer: String,
+    pub input_id: u16,
+    pub item_type: PlaylistItemType,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StalkerPlaylist {
+    pub genres: Vec<StalkerGenre>,
+    pub channels: Vec<StalkerChannel>,
+}
+
+impl StalkerPlaylist {
+    pub fn get_channel(&self, channel_id: u32) -> Option<&StalkerChannel> {
+        self.channels.iter().find(|channel| channel.id == channel_id)
+    }
+
+    /// The channels of the genre, `*` or an empty genre are all channels.
+    pub fn get_genre_channels(&self, genre: &str) -> Vec<&StalkerChannel> {
+        let genre = genre.trim();
+        match genre.parse::<u32>() {
+            Ok(genre_id) => self.channels.iter().filter(|channel| channel.genre_id == genre_id).collect(),
+            Err(_) if genre.is_empty() || genre == "*" => self.channels.iter().collect(),
+            Err(_) => vec![],
+        }
+    }
+}
+
+fn get_stalker_file_path(target_path: &Path) -> PathBuf {
+    target_path.join(FILE_STALKER_PLAYLIST)
+}
+
+pub fn stalker_get_epg_file_path(target_path: &Path) -> PathBuf {
+    target_path.join(FILE_STALKER_EPG)
+}
+
+/// Creates the genres and channels of the live groups, the channel numbers are the channel numbers
+/// of the playlist or their position.
+pub fn create_stalker_playlist(playlist: &[PlaylistGroup]) -> StalkerPlaylist {
+    let mut stalker_playlist = StalkerPlaylist::default();
+    let mut position = 0;
+    for group in playlist.iter().filter(|group| group.xtream_cluster == XtreamCluster::Live) {
+        let genre_id = u32::try_from(stalker_playlist.genres.len() + 1).unwrap_or(u32::MAX);
+        let mut has_channels = false;
+        for channel in &group.channels {
+            let header = channel.header.borrow();
+            if !matches!(header.item_type, PlaylistItemType::Live | PlaylistItemType::LiveHls | PlaylistItemType::LiveUnknown) {
+                continue;
+            }
+            position += 1;
+            has_channels = true;
+            stalker_playlist.channels.push(StalkerChannel {
+                id: header.virtual_id,
+                number: header.chno.trim().parse::<u32>().unwrap_or(position),
+                name: header.title.to_string(),
+                logo: header.logo.to_string(),
+                genre_id,
+                epg_channel_id: header.epg_channel_id.as_ref().map(ToString::to_string).unwrap_or_default(),
+                url: header.url.to_string(),
+                user_agent: header.user_agent.to_string(),
+                referrer: header.referrer.to_string(),
+                input_id: header.input_id,
+                item_type: header.item_type,
+            });
+        }
+        if has_channels {
+            stalker_playlist.genres.push(StalkerGenre { id: genre_id, title: group.title.to_string() });
+        }
+    }
+    stalker_playlist
+}
+
+pub async fn stalker_write_playlist(cfg: &Config, target_path: &Path, playlist: &[PlaylistGroup]) -> Result<(), M3uFilterError> {
+    if playlist.is_empty() {
+        return Ok(());
+    }
+    let stalker_playlist = create_stalker_playlist(playlist);
+    let path = get_stalker_file_path(target_path);
+    let result = match cfg.get_memory_storage() {
+        Some(storage) => memory_write_json(storage, &path, &stalker_playlist),
+        None => {
+            let _file_lock = cfg.file_locks.write_lock(&path).await.map_err(|err| info_err!(format!("{err}")))?;
+            json_write_documents_to_file(&path, &stalker_playlist)
+        }
+    };
+    result.map_err(|err| create_m3u_filter_error!(M3uFilterErrorKind::Notify, "failed to write stalker playlist: {} - {err}", path.display()))
+}
+
+pub async fn stalker_read_playlist(cfg: &Config, target_name: &str) -> Option<StalkerPlaylist> {
+    let path = get_stalker_file_path(&get_target_storage_path(cfg, target_name)?);
+    if let Some(storage) = cfg.get_memory_storage() {
+        return memory_read_json(storage, &path);
+    }
+    let _file_lock = cfg.file_locks.read_lock(&path).await.ok()?;
+    File::open(&path).ok().and_then(|file| serde_json::from_reader(file_reader(file)).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::playlist::{PlaylistItem, PlaylistItemType, XtreamCluster};
+    use crate::model::playlist_test_utils::{group, item as test_item};
+    use crate::repository::stalker_repository::create_stalker_playlist;
+
+    fn item(virtual_id: u32, chno: &str, item_type: PlaylistItemType) -> PlaylistItem {
+        test_item(&format!("Channel {virtual_id}")).virtual_id(virtual_id).chno(chno).item_type(item_type).build()
+    }
+
+    #[test]
+    fn stalker_playlist_test() {
+        let playlist = vec![
+            group(1, "News", XtreamCluster::Live, vec![item(10, "", PlaylistItemType::Live), item(11, "7", PlaylistItemType::LiveHls)]),
+            group(2, "Movies", XtreamCluster::Video, vec![item(20, "", PlaylistItemType::Video)]),
+            group(3, "Sports", XtreamCluster::Live, vec![item(30, "", PlaylistItemType::Live)]),
+        ];
+        let stalker_playlist = create_stalker_playlist(&playlist);
+        assert_eq!(stalker_playlist.genres.iter().map(|genre| (genre.id, genre.title.as_str())).collect::<Vec<_>>(), vec![(1, "News"), (2, "Sports")]);
+        assert_eq!(stalker_playlist.channels.iter().map(|channel| (channel.id, channel.number, channel.genre_id)).collect::<Vec<_>>(),
+                   vec![(10, 1, 1), (11, 7, 1), (30, 3, 2)]);
+        assert_eq!(stalker_playlist.get_channel(30).map(|channel| channel.name.as_str()), Some("Channel 30"));
+        assert_eq!(stalker_playlist.get_genre_channels("2").len(), 1);
+        assert_eq!(stalker_playlist.get_genre_channels("*").len(), 3);
+        assert!(stalker_playlist.get_genre_channels("unknown").is_empty());
+    }
+}
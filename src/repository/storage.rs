@@ -4,6 +4,7 @@ use crate::m3u_filter_error::{M3uFilterError, M3uFilterErrorKind};
 use crate::model::config::{Config, ConfigInput};
 use crate::model::playlist::UUIDType;
 use crate::notify_err;
+use crate::repository::indexed_document::IndexedDocument;
 use crate::utils::file_utils;
 
 pub(in crate::repository) const FILE_SUFFIX_DB: &str = "db";
@@ -29,7 +30,12 @@ pub fn hash_string_as_hex(url: &str) -> String {
     hex_encode(&hash_string(url))
 }
 
-pub(in crate::repository) fn get_target_id_mapping_file(target_path: &Path) -> PathBuf {
+/// The hex encoded uuid of a playlist item, the key of the channel notes.
+pub fn uuid_as_hex(uuid: &UUIDType) -> String {
+    hex_encode(uuid)
+}
+
+pub fn get_target_id_mapping_file(target_path: &Path) -> PathBuf {
     target_path.join(PathBuf::from(FILE_ID_MAPPING))
 }
 
@@ -50,6 +56,16 @@ pub fn get_target_storage_path(cfg: &Config, target_name: &str) -> Option<PathBu
     file_utils::get_file_path(&cfg.working_dir, Some(std::path::PathBuf::from(target_name.replace(' ', "_"))))
 }
 
+/// Quarantines the corrupt files found by the readers since the last update.
+pub async fn quarantine_corrupt_documents(cfg: &Config) -> usize {
+    IndexedDocument::quarantine_corrupt(&cfg.file_locks).await
+}
+
+/// Removes the staging files left by an interrupted update of the target, the served files are kept.
+pub fn target_remove_staged_files(cfg: &Config, target_name: &str) -> usize {
+    get_target_storage_path(cfg, target_name).map_or(0, |path| IndexedDocument::remove_staged_files(&path))
+}
+
 pub fn get_input_storage_path(input: &ConfigInput, working_dir: &str) -> std::io::Result<PathBuf> {
     let name =  format!("input_{}", input.name.clone().unwrap_or_else(|| format!("{}", input.id)));
     let path = Path::new(working_dir).join(name);
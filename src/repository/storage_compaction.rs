@@ -0,0 +1,173 @@
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use log::{debug, info};
+use serde::Serialize;
+
+use crate::model::config::{Config, ConfigInput, InputType};
+use crate::model::playlist::{PlaylistItemType, XtreamCluster};
+use crate::model::xtream::XtreamSeriesEpisode;
+use crate::repository::bplustree::BPlusTree;
+use crate::repository::indexed_document::IndexedDocumentGarbageCollector;
+use crate::repository::m3u_repository::m3u_get_file_paths;
+use crate::repository::storage::{get_input_storage_path, get_target_id_mapping_file, get_target_storage_path};
+use crate::repository::target_id_mapping::VirtualIdRecord;
+use crate::repository::xtream_repository::{xtream_get_file_paths, xtream_get_info_file_paths, xtream_get_record_file_path, xtream_get_storage_path, InputVodInfoRecord};
+
+const FILE_SUFFIX_WAL: &str = "wal";
+// wal files which were not touched for this duration are leftovers of an aborted processing
+const WAL_REMNANT_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct CompactionReport {
+    pub compacted_files: usize,
+    pub removed_wal_files: usize,
+    pub size_before: u64,
+    pub size_after: u64,
+    pub reclaimed_bytes: u64,
+    pub errors: Vec<String>,
+}
+
+impl CompactionReport {
+    fn add(&mut self, size_before: u64, size_after: u64) {
+        self.compacted_files += 1;
+        self.size_before += size_before;
+        self.size_after += size_after;
+        self.reclaimed_bytes = self.size_before.saturating_sub(self.size_after);
+    }
+
+    fn add_error(&mut self, path: &Path, err: &Error) {
+        self.errors.push(format!("{}: {err}", path.to_string_lossy()));
+    }
+}
+
+fn file_size(path: &Path) -> u64 {
+    fs::metadata(path).map_or(0, |meta| meta.len())
+}
+
+async fn compact_indexed_document(cfg: &Config, main_path: PathBuf, index_path: PathBuf, report: &mut CompactionReport) {
+    if !main_path.exists() || !index_path.exists() {
+        return;
+    }
+    let size_before = file_size(&main_path) + file_size(&index_path);
+    let result = match cfg.file_locks.write_lock(&main_path).await {
+        Ok(_file_lock) => IndexedDocumentGarbageCollector::<u32>::new(main_path.clone(), index_path.clone())
+            .and_then(|mut gc| gc.compact()),
+        Err(err) => Err(err),
+    };
+    match result {
+        Ok(()) => report.add(size_before, file_size(&main_path) + file_size(&index_path)),
+        Err(err) => report.add_error(&main_path, &err),
+    }
+}
+
+async fn compact_tree<V>(cfg: &Config, path: &Path, report: &mut CompactionReport)
+where
+    V: Serialize + for<'de> serde::Deserialize<'de> + Clone,
+{
+    if !path.exists() {
+        return;
+    }
+    let size_before = file_size(path);
+    let result = match cfg.file_locks.write_lock(path).await {
+        Ok(_file_lock) => BPlusTree::<u32, V>::compact(path),
+        Err(err) => Err(err),
+    };
+    match result {
+        Ok(_) => report.add(size_before, file_size(path)),
+        Err(err) => report.add_error(path, &err),
+    }
+}
+
+fn remove_wal_remnants(storage_path: &Path, report: &mut CompactionReport) {
+    let Ok(entries) = fs::read_dir(storage_path) else { return };
+    let now = SystemTime::now();
+    for path in entries.filter_map(Result::ok).map(|entry| entry.path()) {
+        if path.extension().is_none_or(|ext| ext != FILE_SUFFIX_WAL) {
+            continue;
+        }
+        let outdated = fs::metadata(&path).and_then(|meta| meta.modified())
+            .is_ok_and(|modified| now.duration_since(modified).unwrap_or_default() > WAL_REMNANT_AGE);
+        if outdated {
+            let size = file_size(&path);
+            match fs::remove_file(&path) {
+                Ok(()) => {
+                    debug!("Removed wal remnant {}", path.to_string_lossy());
+                    report.removed_wal_files += 1;
+                    report.size_before += size;
+                    report.reclaimed_bytes = report.size_before.saturating_sub(report.size_after);
+                }
+                Err(err) => report.add_error(&path, &err),
+            }
+        }
+    }
+}
+
+async fn compact_input(cfg: &Config, input: &ConfigInput, report: &mut CompactionReport) {
+    if input.input_type != InputType::Xtream {
+        return;
+    }
+    let storage_path = match get_input_storage_path(input, &cfg.working_dir) {
+        Ok(path) => path,
+        Err(err) => {
+            report.errors.push(format!("Could not access storage for input {}: {err}", input.name.as_ref().map_or_else(|| input.id.to_string(), ToString::to_string)));
+            return;
+        }
+    };
+    remove_wal_remnants(&storage_path, report);
+    for cluster in [XtreamCluster::Video, XtreamCluster::Series] {
+        if let Some((info_path, idx_path)) = xtream_get_info_file_paths(&storage_path, cluster) {
+            compact_indexed_document(cfg, info_path, idx_path, report).await;
+        }
+    }
+    if let Some(path) = xtream_get_record_file_path(&storage_path, PlaylistItemType::Video) {
+        compact_tree::<InputVodInfoRecord>(cfg, &path, report).await;
+    }
+    if let Some(path) = xtream_get_record_file_path(&storage_path, PlaylistItemType::SeriesInfo) {
+        compact_tree::<u64>(cfg, &path, report).await;
+    }
+    if let Some(path) = xtream_get_record_file_path(&storage_path, PlaylistItemType::Series) {
+        compact_tree::<XtreamSeriesEpisode>(cfg, &path, report).await;
+    }
+}
+
+async fn compact_target(cfg: &Config, target_name: &str, report: &mut CompactionReport) {
+    let Some(target_path) = get_target_storage_path(cfg, target_name) else { return };
+    if !target_path.exists() {
+        return;
+    }
+    let (m3u_path, m3u_idx_path) = m3u_get_file_paths(&target_path);
+    compact_indexed_document(cfg, m3u_path, m3u_idx_path, report).await;
+    compact_tree::<VirtualIdRecord>(cfg, &get_target_id_mapping_file(&target_path), report).await;
+    if let Some(xtream_path) = xtream_get_storage_path(cfg, target_name) {
+        for cluster in [XtreamCluster::Live, XtreamCluster::Video, XtreamCluster::Series] {
+            let (main_path, idx_path) = xtream_get_file_paths(&xtream_path, cluster);
+            compact_indexed_document(cfg, main_path, idx_path, report).await;
+            if let Some((info_path, idx_path)) = xtream_get_info_file_paths(&xtream_path, cluster) {
+                compact_indexed_document(cfg, info_path, idx_path, report).await;
+            }
+        }
+    }
+}
+
+/// Compacts all indexed documents and trees of all inputs and targets.
+/// Corrupt files are reported and left untouched.
+pub async fn compact_storage(cfg: &Config) -> Result<CompactionReport, Error> {
+    if !Path::new(&cfg.working_dir).exists() {
+        return Err(Error::new(ErrorKind::NotFound, format!("Working dir not found {}", cfg.working_dir)));
+    }
+    let mut report = CompactionReport::default();
+    for source in &cfg.sources {
+        for input in &source.inputs {
+            compact_input(cfg, input, &mut report).await;
+        }
+        for target in &source.targets {
+            compact_target(cfg, &target.name, &mut report).await;
+        }
+    }
+    info!("Storage compaction finished: {} files compacted, {} wal remnants removed, {} bytes reclaimed, {} errors",
+        report.compacted_files, report.removed_wal_files, report.reclaimed_bytes, report.errors.len());
+    Ok(report)
+}
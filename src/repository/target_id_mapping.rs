@@ -1,14 +1,20 @@
 use std::cmp::max;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::io::Error;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 use chrono::Local;
-use log::error;
+use log::{error, info};
 use serde::{Deserialize, Serialize};
 
+use crate::m3u_filter_error::str_to_io_error;
+use crate::model::config::Config;
 use crate::model::playlist::{PlaylistItemType, UUIDType};
 use crate::repository::bplustree::BPlusTree;
+use crate::repository::id_namespace::{id_namespace_base, ID_NAMESPACE_SIZE};
+use crate::repository::memory_storage::MemoryStorage;
+use crate::repository::storage::{get_target_id_mapping_file, get_target_storage_path};
 
 // TODO make configurable
 const EXPIRATION_DURATION: i64 = 86400;
@@ -21,12 +27,37 @@ pub struct VirtualIdRecord {
     pub item_type: PlaylistItemType,
     pub parent_virtual_id: u32, // only for series to hold series info id.
     pub last_updated: i64,
+    pub last_seen: u32, // refresh counter when the entry was last part of the target
+    pub fingerprint: Option<UUIDType>, // name based identity to re-match entries with changed urls
+}
+
+// Record layout before `fingerprint` was introduced, only used to migrate existing id mappings.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct LegacyVirtualIdRecordV2 {
+    virtual_id: u32,
+    provider_id: u32,
+    uuid: UUIDType,
+    item_type: PlaylistItemType,
+    parent_virtual_id: u32,
+    last_updated: i64,
+    last_seen: u32,
+}
+
+// Record layout before `last_seen` was introduced, only used to migrate existing id mappings.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct LegacyVirtualIdRecord {
+    virtual_id: u32,
+    provider_id: u32,
+    uuid: UUIDType,
+    item_type: PlaylistItemType,
+    parent_virtual_id: u32,
+    last_updated: i64,
 }
 
 impl VirtualIdRecord {
-    fn new(provider_id: u32, virtual_id: u32, item_type: PlaylistItemType, parent_virtual_id: u32, uuid: UUIDType) -> Self {
+    fn new(provider_id: u32, virtual_id: u32, item_type: PlaylistItemType, parent_virtual_id: u32, uuid: UUIDType, last_seen: u32) -> Self {
         let last_updated = Local::now().timestamp();
-        Self { virtual_id, provider_id, uuid, item_type, parent_virtual_id, last_updated }
+        Self { virtual_id, provider_id, uuid, item_type, parent_virtual_id, last_updated, last_seen, fingerprint: None }
     }
 
     pub fn is_expired(&self) -> bool {
@@ -34,23 +65,126 @@ impl VirtualIdRecord {
     }
 
     pub fn copy_update_timestamp(&self) -> Self {
-        Self::new(self.provider_id, self.virtual_id, self.item_type, self.parent_virtual_id, self.uuid)
+        Self { last_updated: Local::now().timestamp(), ..self.clone() }
     }
 }
 
+impl From<LegacyVirtualIdRecordV2> for VirtualIdRecord {
+    fn from(record: LegacyVirtualIdRecordV2) -> Self {
+        Self {
+            virtual_id: record.virtual_id,
+            provider_id: record.provider_id,
+            uuid: record.uuid,
+            item_type: record.item_type,
+            parent_virtual_id: record.parent_virtual_id,
+            last_updated: record.last_updated,
+            last_seen: record.last_seen,
+            fingerprint: None,
+        }
+    }
+}
+
+impl From<LegacyVirtualIdRecord> for VirtualIdRecord {
+    fn from(record: LegacyVirtualIdRecord) -> Self {
+        Self {
+            virtual_id: record.virtual_id,
+            provider_id: record.provider_id,
+            uuid: record.uuid,
+            item_type: record.item_type,
+            parent_virtual_id: record.parent_virtual_id,
+            last_updated: record.last_updated,
+            last_seen: 0,
+            fingerprint: None,
+        }
+    }
+}
+
+fn migrate_virtual_id_tree<R>(path: &Path, legacy_tree: &BPlusTree<u32, R>) -> BPlusTree<u32, VirtualIdRecord>
+where
+    R: Serialize + for<'de> Deserialize<'de> + Clone + Into<VirtualIdRecord>,
+{
+    info!("Migrating id mapping {}", path.to_string_lossy());
+    let mut tree = BPlusTree::<u32, VirtualIdRecord>::new();
+    for (virtual_id, record) in legacy_tree.iter() {
+        tree.insert(*virtual_id, record.clone().into());
+    }
+    tree
+}
+
+fn load_virtual_id_tree(path: &Path) -> (BPlusTree<u32, VirtualIdRecord>, bool) {
+    if let Ok(tree) = BPlusTree::<u32, VirtualIdRecord>::load(path) {
+        return (tree, false);
+    }
+    if let Ok(legacy_tree) = BPlusTree::<u32, LegacyVirtualIdRecordV2>::load(path) {
+        return (migrate_virtual_id_tree(path, &legacy_tree), true);
+    }
+    match BPlusTree::<u32, LegacyVirtualIdRecord>::load(path) {
+        Ok(legacy_tree) => (migrate_virtual_id_tree(path, &legacy_tree), true),
+        Err(_) => (BPlusTree::<u32, VirtualIdRecord>::new(), false),
+    }
+}
+
+/// Converts the id mappings with a legacy record layout at startup,
+/// the readers of the xtream info requests only decode the current layout.
+pub fn migrate_id_mapping_layouts(cfg: &Config) {
+    if cfg.get_memory_storage().is_some() {
+        return;
+    }
+    for target in cfg.sources.iter().flat_map(|source| &source.targets) {
+        let Some(path) = get_target_storage_path(cfg, &target.name).map(|target_path| get_target_id_mapping_file(&target_path)) else { continue; };
+        if !path.exists() {
+            continue;
+        }
+        let (mut tree, migrated) = load_virtual_id_tree(&path);
+        if migrated {
+            if let Err(err) = tree.store(&path) {
+                error!("Failed to migrate id mapping {}: {err}", path.to_string_lossy());
+            }
+        }
+    }
+}
+
+/// Playlist entry for the virtual id assignment of a target refresh.
+pub struct IdMappingEntry {
+    pub uuid: UUIDType,
+    pub provider_id: u32,
+    pub item_type: PlaylistItemType,
+    pub fingerprint: UUIDType,
+}
+
 pub struct TargetIdMapping {
     dirty: bool,
     virtual_id_counter: u32,
+    refresh_counter: u32,
     by_virtual_id: BPlusTree<u32, VirtualIdRecord>,
     by_uuid: BTreeMap<UUIDType, u32>,
     path: PathBuf,
+    memory_storage: Option<Arc<MemoryStorage>>,
 }
 
 impl TargetIdMapping {
-    pub fn new(path: &Path) -> Self {
-        let tree_virtual_id: BPlusTree<u32, VirtualIdRecord> = BPlusTree::<u32, VirtualIdRecord>::load(path).unwrap_or_else(|_| BPlusTree::<u32, VirtualIdRecord>::new());
+    /// A new mapping assigns the ids from the start of the namespace, existing mappings keep their ids until migrated.
+    pub fn new(path: &Path, namespace: u32) -> Self {
+        let (tree_virtual_id, migrated) = load_virtual_id_tree(path);
+        Self::from_tree(tree_virtual_id, migrated, path, namespace, None)
+    }
+
+    /// Opens the mapping from the memory storage if configured, otherwise from the file.
+    pub fn open(cfg: &Config, path: &Path, namespace: u32) -> Self {
+        match cfg.get_memory_storage() {
+            Some(storage) => {
+                let tree_virtual_id = storage.load_id_mapping(path).unwrap_or_else(BPlusTree::new);
+                Self::from_tree(tree_virtual_id, false, path, namespace, Some(Arc::clone(storage)))
+            }
+            None => Self::new(path, namespace),
+        }
+    }
+
+    fn from_tree(tree_virtual_id: BPlusTree<u32, VirtualIdRecord>, migrated: bool, path: &Path, namespace: u32,
+                 memory_storage: Option<Arc<MemoryStorage>>) -> Self {
         let mut tree_uuid = BTreeMap::new();
         let mut virtual_id_counter: u32 = 0;
+        let mut refresh_counter: u32 = 0;
         tree_virtual_id.traverse(|keys, values| {
             match keys.iter().max() {
                 None => {}
@@ -60,33 +194,204 @@ impl TargetIdMapping {
             }
             for v in values {
                 tree_uuid.insert(v.uuid, v.virtual_id);
+                refresh_counter = max(refresh_counter, v.last_seen);
             }
         });
+        if virtual_id_counter == 0 {
+            virtual_id_counter = id_namespace_base(namespace);
+        } else if namespace > 0 && virtual_id_counter < id_namespace_base(namespace) {
+            info!("Id mapping {} has ids outside of the target namespace, run the id migration", path.to_string_lossy());
+        }
         Self {
-            dirty: false,
+            dirty: migrated,
             virtual_id_counter,
+            refresh_counter,
             by_virtual_id: tree_virtual_id,
             by_uuid: tree_uuid,
             path: path.to_path_buf(),
+            memory_storage,
         }
     }
 
-    pub fn insert_entry(&mut self, uuid: UUIDType, provider_id: u32, item_type: PlaylistItemType, parent_virtual_id: u32) -> u32 {
+    /// Starts a new target refresh, entries not inserted during the refresh count as unseen.
+    pub fn start_refresh(&mut self) {
+        self.refresh_counter += 1;
+    }
+
+    /// Returns the virtual id of the entry, `None` if the entry is new and the id namespace is exhausted.
+    pub fn insert_entry(&mut self, uuid: UUIDType, provider_id: u32, item_type: PlaylistItemType, parent_virtual_id: u32) -> Option<u32> {
+        self.insert_record(uuid, provider_id, item_type, parent_virtual_id, None)
+    }
+
+    fn exhausted_error(&self) -> Error {
+        str_to_io_error(&format!("The id namespace of the id mapping {} is exhausted, run the id migration", self.path.to_string_lossy()))
+    }
+
+    fn insert_record(&mut self, uuid: UUIDType, provider_id: u32, item_type: PlaylistItemType, parent_virtual_id: u32,
+                     fingerprint: Option<UUIDType>) -> Option<u32> {
         match self.by_uuid.get(&uuid) {
             None => {
+                // the next id would be the first id of the following namespace
+                if self.virtual_id_counter % ID_NAMESPACE_SIZE == ID_NAMESPACE_SIZE - 1 {
+                    error!("{}", self.exhausted_error());
+                    return None;
+                }
                 self.dirty = true;
                 self.virtual_id_counter += 1;
-                let record = VirtualIdRecord::new(provider_id, self.virtual_id_counter, item_type, parent_virtual_id, uuid);
+                let mut record = VirtualIdRecord::new(provider_id, self.virtual_id_counter, item_type, parent_virtual_id, uuid, self.refresh_counter);
+                record.fingerprint = fingerprint;
                 self.by_virtual_id.insert(self.virtual_id_counter, record);
-                self.virtual_id_counter
+                self.by_uuid.insert(uuid, self.virtual_id_counter);
+                Some(self.virtual_id_counter)
+            }
+            Some(&virtual_id) => {
+                if let Some(record) = self.by_virtual_id.query(&virtual_id) {
+                    let fingerprint = fingerprint.or(record.fingerprint);
+                    if record.last_seen != self.refresh_counter || record.fingerprint != fingerprint {
+                        let mut record = record.clone();
+                        record.last_seen = self.refresh_counter;
+                        record.fingerprint = fingerprint;
+                        self.by_virtual_id.insert(virtual_id, record);
+                        self.dirty = true;
+                    }
+                }
+                Some(virtual_id)
             }
-            Some(record) => *record
         }
     }
 
+    /// Assigns the virtual ids of the playlist entries of a refresh, the ids are returned in the order of the entries.
+    /// With `rematch` an entry with unknown uuid (the provider changed the stream id or url) takes over the id of an entry
+    /// which is missing in this refresh and has the same fingerprint. Only unambiguous fingerprints are re-matched.
+    /// Returns the ids and the number of re-matched entries, fails if the id namespace is exhausted.
+    pub fn assign_entries(&mut self, entries: &[IdMappingEntry], rematch: bool) -> Result<(Vec<u32>, usize), Error> {
+        let mut virtual_ids = vec![0; entries.len()];
+        let mut pending = vec![];
+        for (index, entry) in entries.iter().enumerate() {
+            if self.by_uuid.contains_key(&entry.uuid) {
+                virtual_ids[index] = self.insert_record(entry.uuid, entry.provider_id, entry.item_type, 0, Some(entry.fingerprint))
+                    .ok_or_else(|| self.exhausted_error())?;
+            } else {
+                pending.push(index);
+            }
+        }
+
+        let mut rematched = 0;
+        if rematch && !pending.is_empty() {
+            // fingerprint of the missing entries, None if the fingerprint is ambiguous
+            let mut missing: HashMap<UUIDType, Option<u32>> = HashMap::new();
+            for (virtual_id, record) in self.by_virtual_id.iter() {
+                if let Some(fingerprint) = record.fingerprint {
+                    if record.last_seen != self.refresh_counter && record.parent_virtual_id == 0 {
+                        missing.entry(fingerprint).and_modify(|id| *id = None).or_insert(Some(*virtual_id));
+                    }
+                }
+            }
+            let mut pending_count: HashMap<UUIDType, usize> = HashMap::new();
+            for index in &pending {
+                *pending_count.entry(entries[*index].fingerprint).or_default() += 1;
+            }
+            pending.retain(|index| {
+                let entry = &entries[*index];
+                let Some(Some(virtual_id)) = missing.get(&entry.fingerprint).copied() else { return true };
+                if pending_count.get(&entry.fingerprint).copied().unwrap_or_default() != 1 {
+                    return true;
+                }
+                let Some(record) = self.by_virtual_id.query(&virtual_id) else { return true };
+                let mut record = record.clone();
+                self.by_uuid.remove(&record.uuid);
+                self.by_uuid.insert(entry.uuid, virtual_id);
+                record.uuid = entry.uuid;
+                record.provider_id = entry.provider_id;
+                record.last_seen = self.refresh_counter;
+                record.last_updated = Local::now().timestamp();
+                self.by_virtual_id.insert(virtual_id, record);
+                self.dirty = true;
+                virtual_ids[*index] = virtual_id;
+                rematched += 1;
+                false
+            });
+        }
+
+        for index in pending {
+            let entry = &entries[index];
+            virtual_ids[index] = self.insert_record(entry.uuid, entry.provider_id, entry.item_type, 0, Some(entry.fingerprint))
+                .ok_or_else(|| self.exhausted_error())?;
+        }
+        Ok((virtual_ids, rematched))
+    }
+
+    /// Drops all entries which were not seen for more than `retention` refreshes, the `protected` ids
+    /// (favorites and bouquets of the users) are never dropped.
+    /// Entries of a kept parent (series episodes) are kept too. Returns the number of dropped entries.
+    pub fn garbage_collect(&mut self, retention: u32, protected: &HashSet<u32>) -> usize {
+        let min_seen = self.refresh_counter.saturating_sub(retention);
+        let is_alive = |record: &VirtualIdRecord| record.last_seen >= min_seen || protected.contains(&record.virtual_id);
+        let alive_parents: HashSet<u32> = self.by_virtual_id.iter()
+            .filter(|(_, record)| is_alive(record))
+            .map(|(virtual_id, _)| *virtual_id)
+            .collect();
+        let mut tree = BPlusTree::<u32, VirtualIdRecord>::new();
+        let mut dropped = 0;
+        for (virtual_id, record) in self.by_virtual_id.iter() {
+            // the entry with the highest virtual id is kept, the counter is restored from it and dropped ids are never reassigned
+            if is_alive(record) || *virtual_id == self.virtual_id_counter
+                || (record.parent_virtual_id != 0 && alive_parents.contains(&record.parent_virtual_id)) {
+                tree.insert(*virtual_id, record.clone());
+            } else {
+                self.by_uuid.remove(&record.uuid);
+                dropped += 1;
+            }
+        }
+        if dropped > 0 {
+            self.by_virtual_id = tree;
+            self.dirty = true;
+        }
+        dropped
+    }
+
+    /// Moves all ids outside of the namespace into the namespace, the parent ids of episodes are moved too.
+    /// Returns the moved ids (old id to new id), fails if the ids don't fit into the namespace.
+    pub fn migrate_to_namespace(&mut self, namespace: u32) -> Result<BTreeMap<u32, u32>, Error> {
+        let base = id_namespace_base(namespace);
+        let in_namespace = |virtual_id: u32| virtual_id > base && virtual_id - base < ID_NAMESPACE_SIZE;
+        let mut counter = self.by_virtual_id.iter().map(|(virtual_id, _)| *virtual_id).filter(|virtual_id| in_namespace(*virtual_id)).max().unwrap_or(base);
+        let moved_ids: BTreeMap<u32, u32> = self.by_virtual_id.iter()
+            .filter(|(virtual_id, _)| !in_namespace(**virtual_id))
+            .map(|(virtual_id, _)| {
+                counter += 1;
+                (*virtual_id, counter)
+            }).collect();
+        if moved_ids.is_empty() {
+            return Ok(moved_ids);
+        }
+        if counter - base >= ID_NAMESPACE_SIZE {
+            return Err(self.exhausted_error());
+        }
+        let mut tree = BPlusTree::<u32, VirtualIdRecord>::new();
+        self.by_uuid.clear();
+        for (virtual_id, record) in self.by_virtual_id.iter() {
+            let mut record = record.clone();
+            record.virtual_id = moved_ids.get(virtual_id).copied().unwrap_or(*virtual_id);
+            // catchup entries hold the provider id as parent
+            if record.item_type == PlaylistItemType::Series {
+                record.parent_virtual_id = moved_ids.get(&record.parent_virtual_id).copied().unwrap_or(record.parent_virtual_id);
+            }
+            self.by_uuid.insert(record.uuid, record.virtual_id);
+            tree.insert(record.virtual_id, record);
+        }
+        self.by_virtual_id = tree;
+        self.virtual_id_counter = counter;
+        self.dirty = true;
+        Ok(moved_ids)
+    }
+
     pub fn persist(&mut self) -> Result<(), Error> {
         if self.dirty {
-            self.by_virtual_id.store(&self.path)?;
+            match &self.memory_storage {
+                Some(storage) => storage.store_id_mapping(&self.path, &self.by_virtual_id)?,
+                None => { self.by_virtual_id.store(&self.path)?; }
+            }
         }
         self.dirty = false;
         Ok(())
@@ -99,4 +404,94 @@ impl Drop for TargetIdMapping {
             error!("Failed to persist target id mapping {:?} err:{err}", &self.path);
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use crate::model::playlist::PlaylistItemType;
+    use crate::repository::id_namespace::id_namespace_base;
+    use crate::repository::storage::hash_string;
+    use crate::repository::target_id_mapping::{IdMappingEntry, TargetIdMapping};
+
+    #[test]
+    fn garbage_collect_test() {
+        let path = std::path::PathBuf::from("/tmp/id_mapping_gc.db");
+        let _ = std::fs::remove_file(&path);
+        let mut mapping = TargetIdMapping::new(&path, 0);
+        mapping.start_refresh();
+        let stale_id = mapping.insert_entry(hash_string("stale"), 1, PlaylistItemType::Live, 0).unwrap();
+        let series_id = mapping.insert_entry(hash_string("series"), 2, PlaylistItemType::SeriesInfo, 0).unwrap();
+        let episode_id = mapping.insert_entry(hash_string("episode"), 3, PlaylistItemType::Series, series_id).unwrap();
+        let favorite_id = mapping.insert_entry(hash_string("favorite"), 5, PlaylistItemType::Live, 0).unwrap();
+        for _ in 0..3 {
+            mapping.start_refresh();
+            mapping.insert_entry(hash_string("series"), 2, PlaylistItemType::SeriesInfo, 0);
+        }
+        let last_id = mapping.insert_entry(hash_string("new"), 4, PlaylistItemType::Live, 0).unwrap();
+        assert_eq!(mapping.garbage_collect(2, &HashSet::from([favorite_id])), 1);
+        assert_eq!(mapping.insert_entry(hash_string("favorite"), 5, PlaylistItemType::Live, 0), Some(favorite_id));
+        assert_eq!(mapping.insert_entry(hash_string("episode"), 3, PlaylistItemType::Series, series_id), Some(episode_id));
+        // dropped ids are not reused
+        assert!(mapping.insert_entry(hash_string("stale"), 1, PlaylistItemType::Live, 0).unwrap() > last_id.max(stale_id));
+    }
+
+    #[test]
+    fn namespace_test() {
+        let path = std::path::PathBuf::from("/tmp/id_mapping_namespace.db");
+        let _ = std::fs::remove_file(&path);
+        let mut mapping = TargetIdMapping::new(&path, 0);
+        let series_id = mapping.insert_entry(hash_string("series"), 2, PlaylistItemType::SeriesInfo, 0).unwrap();
+        mapping.insert_entry(hash_string("episode"), 3, PlaylistItemType::Series, series_id);
+        assert_eq!(mapping.migrate_to_namespace(2).unwrap().len(), 2);
+        assert!(mapping.migrate_to_namespace(2).unwrap().is_empty());
+        let base = id_namespace_base(2);
+        let series_id = mapping.insert_entry(hash_string("series"), 2, PlaylistItemType::SeriesInfo, 0).unwrap();
+        assert_eq!(series_id, base + 1);
+        assert_eq!(mapping.by_virtual_id.query(&(base + 2)).map(|record| record.parent_virtual_id), Some(series_id));
+        assert_eq!(mapping.insert_entry(hash_string("new"), 4, PlaylistItemType::Live, 0), Some(base + 3));
+
+        let _ = std::fs::remove_file(&path);
+        let mut mapping = TargetIdMapping::new(&path, 3);
+        assert_eq!(mapping.insert_entry(hash_string("live"), 1, PlaylistItemType::Live, 0), Some(id_namespace_base(3) + 1));
+        // the last id of the namespace is assigned, then the namespace is exhausted
+        mapping.virtual_id_counter = id_namespace_base(4) - 2;
+        assert_eq!(mapping.insert_entry(hash_string("last"), 2, PlaylistItemType::Live, 0), Some(id_namespace_base(4) - 1));
+        assert!(mapping.insert_entry(hash_string("overflow"), 3, PlaylistItemType::Live, 0).is_none());
+        assert_eq!(mapping.insert_entry(hash_string("live"), 1, PlaylistItemType::Live, 0), Some(id_namespace_base(3) + 1));
+        let entry = IdMappingEntry { uuid: hash_string("overflow"), provider_id: 3, item_type: PlaylistItemType::Live, fingerprint: hash_string("overflow") };
+        assert!(mapping.assign_entries(&[entry], false).is_err());
+    }
+
+    #[test]
+    fn rematch_test() {
+        let path = std::path::PathBuf::from("/tmp/id_mapping_rematch.db");
+        let _ = std::fs::remove_file(&path);
+        let entry = |url: &str, provider_id: u32, name: &str| IdMappingEntry {
+            uuid: hash_string(url),
+            provider_id,
+            item_type: PlaylistItemType::Live,
+            fingerprint: hash_string(name),
+        };
+        let mut mapping = TargetIdMapping::new(&path, 0);
+        mapping.start_refresh();
+        let (ids, rematched) = mapping.assign_entries(&[entry("a1", 1, "A"), entry("b1", 2, "B"), entry("c1", 3, "C"), entry("d1", 4, "D")], true).unwrap();
+        assert_eq!(rematched, 0);
+
+        // the provider migrated, A and B have new urls, the new entries E1 and E2 share the fingerprint of the missing C
+        mapping.start_refresh();
+        let (new_ids, rematched) = mapping.assign_entries(&[entry("a2", 11, "A"), entry("b2", 12, "B"), entry("d1", 4, "D"),
+            entry("e1", 13, "C"), entry("e2", 14, "C")], true).unwrap();
+        assert_eq!(rematched, 2);
+        assert_eq!(&new_ids[..3], &[ids[0], ids[1], ids[3]]);
+        assert!(new_ids[3] > ids[3] && new_ids[4] > new_ids[3]);
+        assert_eq!(mapping.by_virtual_id.query(&ids[0]).map(|record| record.provider_id), Some(11));
+        assert_eq!(mapping.insert_entry(hash_string("a2"), 11, PlaylistItemType::Live, 0), Some(ids[0]));
+
+        // without rematch new uuids get new ids
+        mapping.start_refresh();
+        let (new_ids, rematched) = mapping.assign_entries(&[entry("a3", 21, "A")], false).unwrap();
+        assert_eq!(rematched, 0);
+        assert!(new_ids[0] > ids[3]);
+    }
+}
@@ -0,0 +1,397 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use log::error;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::model::api_proxy::ProxyUserCredentials;
+use crate::utils::file_utils::file_reader;
+use crate::utils::json_utils::json_write_documents_to_file;
+
+const FILE_USER_EPG_OVERRIDES: &str = "user_epg_overrides.json";
+const FILE_USER_ACTIVITY: &str = "user_activity.json";
+const FILE_USER_BOUQUETS: &str = "user_bouquets.json";
+// the activity changes with each request, it is written at most once per interval
+const USER_ACTIVITY_PERSIST_INTERVAL_SECS: i64 = 60;
+
+type EpgOverrides = HashMap<String, String>;
+
+/// Epg channel ids which are replaced for a user, e.g. a regional guide instead of the national guide.
+/// The overrides are stored per username in the working directory.
+#[derive(Debug)]
+pub struct UserEpgOverrides {
+    file: Option<PathBuf>,
+    users: Mutex<HashMap<String, EpgOverrides>>,
+}
+
+impl UserEpgOverrides {
+    pub fn new(working_dir: &str) -> Self {
+        let file = PathBuf::from(working_dir).join(FILE_USER_EPG_OVERRIDES);
+        let users = load_user_file(&file, "user epg overrides");
+        Self { file: Some(file), users: Mutex::new(users) }
+    }
+
+    fn persist(&self, users: &HashMap<String, EpgOverrides>) {
+        if let Some(file) = &self.file {
+            if let Err(err) = json_write_documents_to_file(file, users) {
+                error!("Failed to write user epg overrides {}: {err}", file.display());
+            }
+        }
+    }
+
+    /// The overrides of the user, the key is the epg channel id of the channel, the value the replacement.
+    pub fn get(&self, username: &str) -> Option<EpgOverrides> {
+        self.users.lock().ok()?.get(username).filter(|overrides| !overrides.is_empty()).cloned()
+    }
+
+    pub fn get_override(&self, username: &str, epg_channel_id: &str) -> Option<String> {
+        self.users.lock().ok()?.get(username).and_then(|overrides| overrides.get(epg_channel_id)).cloned()
+    }
+
+    pub fn set(&self, username: &str, epg_channel_id: &str, epg_override: &str) {
+        let Ok(mut users) = self.users.lock() else { return; };
+        users.entry(username.to_string()).or_default().insert(epg_channel_id.to_string(), epg_override.to_string());
+        self.persist(&users);
+    }
+
+    pub fn remove(&self, username: &str, epg_channel_id: &str) -> bool {
+        let Ok(mut users) = self.users.lock() else { return false; };
+        let Some(overrides) = users.get_mut(username) else { return false; };
+        let removed = overrides.remove(epg_channel_id).is_some();
+        if overrides.is_empty() {
+            users.remove(username);
+        }
+        if removed {
+            self.persist(&users);
+        }
+        removed
+    }
+
+    /// Removes all overrides of the user, returns the count of removed overrides.
+    pub fn purge_user(&self, username: &str) -> usize {
+        let Ok(mut users) = self.users.lock() else { return 0; };
+        let removed = users.remove(username).map_or(0, |overrides| overrides.len());
+        if removed > 0 {
+            self.persist(&users);
+        }
+        removed
+    }
+}
+
+/// Favorites and bouquets of a user, named lists of virtual ids of the target of the user.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UserBouquetList {
+    pub target: String,
+    #[serde(default)]
+    pub bouquets: BTreeMap<String, BTreeSet<u32>>,
+}
+
+/// Favorites and bouquets of the users, stored per username in the working directory.
+/// The ids are protected from the id mapping retention of the target.
+/// The bouquets are loaded once with the config, see `Config::t_user_bouquets`.
+#[derive(Debug, Default)]
+pub struct UserBouquets {
+    file: Option<PathBuf>,
+    users: Mutex<HashMap<String, UserBouquetList>>,
+}
+
+impl UserBouquets {
+    pub fn new(working_dir: &str) -> Self {
+        let file = PathBuf::from(working_dir).join(FILE_USER_BOUQUETS);
+        let users = load_user_file(&file, "user bouquets");
+        Self { file: Some(file), users: Mutex::new(users) }
+    }
+
+    fn persist(&self, users: &HashMap<String, UserBouquetList>) {
+        if let Some(file) = &self.file {
+            if let Err(err) = json_write_documents_to_file(file, users) {
+                error!("Failed to write user bouquets {}: {err}", file.display());
+            }
+        }
+    }
+
+    pub fn get(&self, username: &str) -> Option<UserBouquetList> {
+        self.users.lock().ok()?.get(username).cloned()
+    }
+
+    /// Replaces the bouquet, an empty list removes it. The bouquets of a user moved to another target are dropped,
+    /// the ids belong to the old target.
+    pub fn set(&self, username: &str, target: &str, name: &str, virtual_ids: BTreeSet<u32>) {
+        let Ok(mut users) = self.users.lock() else { return; };
+        let list = users.entry(username.to_string()).or_default();
+        if list.target != target {
+            list.target = target.to_string();
+            list.bouquets.clear();
+        }
+        if virtual_ids.is_empty() {
+            list.bouquets.remove(name);
+        } else {
+            list.bouquets.insert(name.to_string(), virtual_ids);
+        }
+        if list.bouquets.is_empty() {
+            users.remove(username);
+        }
+        self.persist(&users);
+    }
+
+    pub fn remove(&self, username: &str, name: &str) -> bool {
+        let Ok(mut users) = self.users.lock() else { return false; };
+        let Some(list) = users.get_mut(username) else { return false; };
+        let removed = list.bouquets.remove(name).is_some();
+        if list.bouquets.is_empty() {
+            users.remove(username);
+        }
+        if removed {
+            self.persist(&users);
+        }
+        removed
+    }
+
+    /// Removes all bouquets of the user, returns the count of removed bouquets.
+    pub fn purge_user(&self, username: &str) -> usize {
+        let Ok(mut users) = self.users.lock() else { return 0; };
+        let removed = users.remove(username).map_or(0, |list| list.bouquets.len());
+        if removed > 0 {
+            self.persist(&users);
+        }
+        removed
+    }
+
+    /// The virtual ids of the target which are part of a bouquet of any user.
+    pub fn get_target_ids(&self, target: &str) -> HashSet<u32> {
+        self.users.lock().map(|users| users.values()
+            .filter(|list| list.target == target)
+            .flat_map(|list| list.bouquets.values().flatten().copied())
+            .collect()).unwrap_or_default()
+    }
+
+    /// Replaces the moved virtual ids of the target (old id to new id), returns the count of replaced ids.
+    pub fn remap_target_ids(&self, target: &str, moved_ids: &BTreeMap<u32, u32>) -> usize {
+        let Ok(mut users) = self.users.lock() else { return 0; };
+        let mut remapped = 0;
+        for list in users.values_mut().filter(|list| list.target == target) {
+            for virtual_ids in list.bouquets.values_mut() {
+                *virtual_ids = virtual_ids.iter().map(|virtual_id| match moved_ids.get(virtual_id) {
+                    Some(moved_id) => {
+                        remapped += 1;
+                        *moved_id
+                    }
+                    None => *virtual_id,
+                }).collect();
+            }
+        }
+        if remapped > 0 {
+            self.persist(&users);
+        }
+        remapped
+    }
+}
+
+/// Last activity of a user, the timestamps are unix seconds.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UserActivity {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_login: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_stream: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_ip: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_agent: Option<String>,
+}
+
+impl UserActivity {
+    pub fn get_last_activity(&self) -> Option<i64> {
+        self.last_login.max(self.last_stream)
+    }
+
+    /// A user without activity since `dormant_days` days is dormant.
+    pub fn is_dormant(&self, dormant_days: u32, now: i64) -> bool {
+        self.get_last_activity().is_none_or(|last_activity| now - last_activity >= i64::from(dormant_days) * 86_400)
+    }
+}
+
+/// Last login, stream, ip and user agent of the users, stored per username in the working directory.
+#[derive(Debug)]
+pub struct UserActivities {
+    file: Option<PathBuf>,
+    users: Mutex<(HashMap<String, UserActivity>, i64)>,
+}
+
+impl UserActivities {
+    pub fn new(working_dir: &str) -> Self {
+        let file = PathBuf::from(working_dir).join(FILE_USER_ACTIVITY);
+        let users = load_user_file(&file, "user activity");
+        Self { file: Some(file), users: Mutex::new((users, 0)) }
+    }
+
+    fn record(&self, user: &ProxyUserCredentials, ip: Option<String>, user_agent: Option<&str>, stream: bool, now: i64) {
+        // the activity of users without logging is not tracked
+        if user.no_log {
+            return;
+        }
+        let snapshot = {
+            let Ok(mut guard) = self.users.lock() else { return; };
+            let (users, persisted) = &mut *guard;
+            let activity = users.entry(user.username.clone()).or_default();
+            activity.last_login = Some(now);
+            if stream {
+                activity.last_stream = Some(now);
+            }
+            if ip.is_some() {
+                activity.last_ip = ip;
+            }
+            if let Some(user_agent) = user_agent.filter(|user_agent| !user_agent.is_empty()) {
+                activity.user_agent = Some(user_agent.to_string());
+            }
+            (now - *persisted >= USER_ACTIVITY_PERSIST_INTERVAL_SECS).then(|| {
+                *persisted = now;
+                users.clone()
+            })
+        };
+        if let Some(users) = snapshot {
+            self.persist_in_background(users);
+        }
+    }
+
+    /// The file is written outside of the lock, within the runtime on a blocking thread.
+    fn persist_in_background(&self, users: HashMap<String, UserActivity>) {
+        let Some(file) = self.file.clone() else { return; };
+        let write = move || if let Err(err) = json_write_documents_to_file(&file, &users) {
+            error!("Failed to write user activity {}: {err}", file.display());
+        };
+        match tokio::runtime::Handle::try_current() {
+            Ok(handle) => drop(handle.spawn_blocking(write)),
+            Err(_) => write(),
+        }
+    }
+
+    fn persist(&self, users: &HashMap<String, UserActivity>) {
+        if let Some(file) = &self.file {
+            if let Err(err) = json_write_documents_to_file(file, users) {
+                error!("Failed to write user activity {}: {err}", file.display());
+            }
+        }
+    }
+
+    /// Records an authenticated api request of the user.
+    pub fn record_login(&self, user: &ProxyUserCredentials, ip: Option<String>, user_agent: Option<&str>) {
+        self.record(user, ip, user_agent, false, chrono::Utc::now().timestamp());
+    }
+
+    /// Records a stream request of the user.
+    pub fn record_stream(&self, user: &ProxyUserCredentials, ip: Option<String>, user_agent: Option<&str>) {
+        self.record(user, ip, user_agent, true, chrono::Utc::now().timestamp());
+    }
+
+    pub fn get(&self, username: &str) -> Option<UserActivity> {
+        self.users.lock().ok()?.0.get(username).cloned()
+    }
+
+    /// Writes the activity, e.g. before shutdown.
+    pub fn flush(&self) {
+        if let Ok(guard) = self.users.lock() {
+            self.persist(&guard.0);
+        }
+    }
+
+    pub fn purge_user(&self, username: &str) -> bool {
+        let Ok(mut guard) = self.users.lock() else { return false; };
+        let removed = guard.0.remove(username).is_some();
+        if removed {
+            self.persist(&guard.0);
+        }
+        removed
+    }
+}
+
+pub(crate) fn load_user_file<T: DeserializeOwned + Default>(file: &Path, name: &str) -> T {
+    if !file.exists() {
+        return T::default();
+    }
+    match File::open(file).map(file_reader).map_err(|err| err.to_string())
+        .and_then(|reader| serde_json::from_reader::<_, T>(reader).map_err(|err| err.to_string())) {
+        Ok(users) => users,
+        Err(err) => {
+            error!("Failed to read {name} {}: {err}", file.display());
+            T::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+    use std::sync::Mutex;
+
+    use serde_json::json;
+
+    use crate::model::api_proxy::ProxyUserCredentials;
+    use crate::repository::user_repository::{UserActivities, UserBouquets, UserEpgOverrides};
+
+    #[test]
+    fn user_epg_overrides_test() {
+        let overrides = UserEpgOverrides { file: None, users: Mutex::new(HashMap::new()) };
+        assert!(overrides.get("max").is_none());
+        overrides.set("max", "bbc1.uk", "bbc1.london");
+        overrides.set("max", "itv1.uk", "itv1.london");
+        assert_eq!(overrides.get_override("max", "bbc1.uk").as_deref(), Some("bbc1.london"));
+        assert!(overrides.get_override("anna", "bbc1.uk").is_none());
+        assert!(overrides.remove("max", "bbc1.uk"));
+        assert!(!overrides.remove("max", "bbc1.uk"));
+        assert_eq!(overrides.get("max").map(|o| o.len()), Some(1));
+        assert_eq!(overrides.purge_user("max"), 1);
+        assert!(overrides.get("max").is_none());
+    }
+
+    #[test]
+    fn user_bouquets_test() {
+        let bouquets = UserBouquets { file: None, users: Mutex::new(HashMap::new()) };
+        bouquets.set("max", "all", "favorites", BTreeSet::from([1, 2]));
+        bouquets.set("max", "all", "sports", BTreeSet::from([2, 3]));
+        bouquets.set("anna", "movies", "favorites", BTreeSet::from([7]));
+        assert_eq!(bouquets.get_target_ids("all"), HashSet::from([1, 2, 3]));
+        assert_eq!(bouquets.get_target_ids("movies"), HashSet::from([7]));
+        assert_eq!(bouquets.remap_target_ids("movies", &BTreeMap::from([(7, 107), (3, 103)])), 1);
+        assert_eq!(bouquets.get_target_ids("movies"), HashSet::from([107]));
+        assert_eq!(bouquets.remap_target_ids("movies", &BTreeMap::from([(107, 7)])), 1);
+        assert!(bouquets.remove("max", "sports"));
+        assert!(!bouquets.remove("max", "sports"));
+        assert_eq!(bouquets.get_target_ids("all"), HashSet::from([1, 2]));
+        // the user moved to another target
+        bouquets.set("max", "movies", "favorites", BTreeSet::from([8]));
+        assert!(bouquets.get_target_ids("all").is_empty());
+        assert_eq!(bouquets.get_target_ids("movies"), HashSet::from([7, 8]));
+        bouquets.set("anna", "movies", "favorites", BTreeSet::new());
+        assert!(bouquets.get("anna").is_none());
+        assert_eq!(bouquets.purge_user("max"), 1);
+        assert!(bouquets.get_target_ids("movies").is_empty());
+    }
+
+    #[test]
+    fn user_activity_test() {
+        let activities = UserActivities { file: None, users: Mutex::new((HashMap::new(), 0)) };
+        let user = |username: &str, no_log: bool| {
+            let mut user: ProxyUserCredentials = serde_json::from_value(json!({"username": username, "password": "secret", "token": null, "server": null, "epg_timeshift": null})).unwrap();
+            user.no_log = no_log;
+            user
+        };
+        activities.record(&user("max", false), Some("10.0.0.1".to_string()), Some("VLC/3.0"), false, 1000);
+        activities.record(&user("max", false), None, None, true, 2000);
+        activities.record(&user("anna", true), Some("10.0.0.2".to_string()), None, true, 2000);
+        assert!(activities.get("anna").is_none());
+        let activity = activities.get("max").unwrap();
+        assert_eq!(activity.last_login, Some(2000));
+        assert_eq!(activity.last_stream, Some(2000));
+        assert_eq!(activity.last_ip.as_deref(), Some("10.0.0.1"));
+        assert_eq!(activity.user_agent.as_deref(), Some("VLC/3.0"));
+        assert!(!activity.is_dormant(1, 2000 + 86_399));
+        assert!(activity.is_dormant(1, 2000 + 86_400));
+        assert!(activities.get("anna").unwrap_or_default().is_dormant(30, 2000));
+        assert!(activities.purge_user("max"));
+        assert!(activities.get("max").is_none());
+    }
+}
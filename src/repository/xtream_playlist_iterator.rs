@@ -5,15 +5,13 @@ use crate::model::api_proxy::{ProxyUserCredentials};
 use crate::model::config::{Config, ConfigTarget};
 use crate::model::playlist::{XtreamCluster, XtreamPlaylistItem};
 use crate::model::xtream::XtreamMappingOptions;
-use crate::repository::indexed_document::{IndexedDocumentIterator};
+use crate::repository::memory_storage::DocumentIterator;
 use crate::repository::xtream_repository::{xtream_get_file_paths, xtream_get_storage_path};
-use crate::utils::file_lock_manager::FileReadGuard;
 
 pub struct XtreamPlaylistIterator {
-    reader: IndexedDocumentIterator<u32, XtreamPlaylistItem>,
+    reader: DocumentIterator<XtreamPlaylistItem>,
     options: XtreamMappingOptions,
     category_id: u32,
-    _file_lock: FileReadGuard,
     base_url: String,
     user: ProxyUserCredentials,
 }
@@ -28,19 +26,15 @@ impl XtreamPlaylistIterator {
     ) -> Result<Self, M3uFilterError> {
         if let Some(storage_path) = xtream_get_storage_path(config, target.name.as_str()) {
             let (xtream_path, idx_path) = xtream_get_file_paths(&storage_path, cluster);
-            let file_lock = config.file_locks.read_lock(&xtream_path).await
-                .map_err(|err| info_err!(format!("Could not lock document {xtream_path:?}: {err}")))?;
-
-            let reader = IndexedDocumentIterator::<u32, XtreamPlaylistItem>::new(&xtream_path, &idx_path)
+            let reader = DocumentIterator::<XtreamPlaylistItem>::open_target_documents(config, &xtream_path, &idx_path).await
                 .map_err(|err| info_err!(format!("Could not deserialize file {} - {}", &xtream_path.to_str().unwrap(), err)))?;
 
-            let options = XtreamMappingOptions::from_target_options(target.options.as_ref());
+            let options = XtreamMappingOptions::from_target(target);
             let server_info = config.get_user_server_info(user);
             Ok(Self {
                 reader,
                 options,
                 category_id,
-                _file_lock: file_lock,
                 base_url: server_info.get_base_url(),
                 user: user.clone(),
             })
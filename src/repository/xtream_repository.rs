@@ -1,22 +1,28 @@
 use crate::m3u_filter_error::str_to_io_error;
 use crate::file_utils::file_reader;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::fs::File;
 use std::io::{BufReader, Error, ErrorKind, Read};
 use std::path::{Path, PathBuf};
+use std::rc::Rc;
 
-use log::error;
+use log::{error, info};
+use serde::{Deserialize, Serialize};
 use serde_json::{json, Map, Value};
 
 use crate::m3u_filter_error::{M3uFilterError, M3uFilterErrorKind};
 use crate::model::api_proxy::{ProxyType, ProxyUserCredentials};
-use crate::model::config::{Config, ConfigInput, ConfigTarget};
-use crate::model::playlist::{PlaylistEntry, PlaylistGroup, PlaylistItem, PlaylistItemType, XtreamCluster, XtreamPlaylistItem};
+use crate::model::config::{Config, ConfigInput, ConfigTarget, TargetType};
+use crate::model::playlist::{PlaylistEntry, PlaylistGroup, PlaylistItem, PlaylistItemType, UUIDType, XtreamCluster, XtreamPlaylistItem};
+use crate::processing::movie_parts::PROP_MOVIE_PARTS;
+use crate::processing::series_merge::MergedEpisodes;
 use crate::model::xtream::{rewrite_doc_urls, XtreamMappingOptions, XtreamSeriesEpisode, INFO_RESOURCE_PREFIX, INFO_RESOURCE_PREFIX_EPISODE, SEASON_RESOURCE_PREFIX};
 use crate::repository::bplustree::{BPlusTree, BPlusTreeQuery, BPlusTreeUpdate};
-use crate::repository::indexed_document::{IndexedDocumentDirectAccess, IndexedDocumentGarbageCollector, IndexedDocumentWriter};
+use crate::repository::memory_storage::{memory_read_json, memory_write_json, DocumentIterator};
+use crate::repository::indexed_document::{IndexedDocumentDirectAccess, IndexedDocumentGarbageCollector, IndexedDocumentIterator, IndexedDocumentWriter};
 use crate::repository::storage::{get_input_storage_path, get_target_id_mapping_file, get_target_storage_path, hash_string, FILE_SUFFIX_DB, FILE_SUFFIX_INDEX};
+use crate::repository::id_namespace::get_target_id_namespace;
 use crate::repository::target_id_mapping::{TargetIdMapping, VirtualIdRecord};
 use crate::repository::xtream_playlist_iterator::XtreamPlaylistIterator;
 use crate::utils::file_utils::open_readonly_file;
@@ -32,14 +38,18 @@ const FILE_VOD_INFO_RECORD: &str = "vod_info_record";
 const FILE_SERIES_INFO_RECORD: &str = "series_info_record";
 const FILE_SERIES_EPISODE_RECORD: &str = "series_episode_record";
 const FILE_SERIES: &str = "series";
+const COL_SERIES_MERGE: &str = "series_merge";
 pub const FILE_EPG: &str = "epg.xml";
 const PATH_XTREAM: &str = "xtream";
 const TAG_CATEGORY_ID: &str = "category_id";
 const TAG_CATEGORY_IDS: &str = "category_ids";
 const TAG_CATEGORY_NAME: &str = "category_name";
+pub const TAG_CATEGORY_ICON: &str = "category_icon";
+const TAG_CATEGORY_DESCRIPTION: &str = "category_description";
 const TAG_DIRECT_SOURCE: &str = "direct_source";
 const TAG_PARENT_ID: &str = "parent_id";
 const TAG_MOVIE_DATA: &str = "movie_data";
+const TAG_MOVIE_PARTS: &str = "parts";
 pub const TAG_INFO_DATA: &str = "info";
 pub const TAG_SEASONS_DATA: &str = "seasons";
 const TAG_STREAM_ID: &str = "stream_id";
@@ -127,21 +137,32 @@ async fn write_playlists_to_file(
 ) -> Result<(), M3uFilterError> {
     for (cluster, playlist) in collections {
         let (xtream_path, idx_path) = xtream_get_file_paths(storage_path, cluster);
-        {
-            let _file_lock = cfg.file_locks.write_lock(&xtream_path).await.map_err(|err| info_err!(format!("{err}")))?;
-            match IndexedDocumentWriter::new(xtream_path.clone(), idx_path) {
-                Ok(mut writer) => {
-                    for item in playlist {
-                        let xtream = item.to_xtream();
-                        match writer.write_doc(item.header.borrow().virtual_id, &xtream) {
-                            Ok(()) => {}
-                            Err(err) => return Err(cant_write_result!(&xtream_path, err)),
-                        }
+        if let Some(storage) = cfg.get_memory_storage() {
+            let docs: Vec<(u32, XtreamPlaylistItem)> = playlist.iter().map(|item| (item.header.borrow().virtual_id, item.to_xtream())).collect();
+            storage.write_documents(&xtream_path, docs.iter().map(|(virtual_id, xtream)| (*virtual_id, xtream)))
+                .map_err(|err| cant_write_result!(&xtream_path, err))?;
+            // the info documents of removed vod and series would otherwise stay in memory until restart
+            if let Some((info_path, _)) = xtream_get_info_file_paths(storage_path, cluster) {
+                let virtual_ids: HashSet<u32> = docs.iter().map(|(virtual_id, _)| *virtual_id).collect();
+                storage.retain_documents(&info_path, |virtual_id| virtual_ids.contains(&virtual_id))
+                    .map_err(|err| cant_write_result!(&info_path, err))?;
+            }
+            continue;
+        }
+        match IndexedDocumentWriter::new_staged(xtream_path.clone(), idx_path) {
+            Ok(mut writer) => {
+                for item in playlist {
+                    let xtream = item.to_xtream();
+                    match writer.write_doc(item.header.borrow().virtual_id, &xtream) {
+                        Ok(()) => {}
+                        Err(err) => return Err(cant_write_result!(&xtream_path, err)),
                     }
-                    writer.store().map_err(|err| cant_write_result!(&xtream_path, err))?;
                 }
-                Err(err) => return Err(cant_write_result!(&xtream_path, err)),
+                // the lock is only held to replace the served files
+                let _file_lock = cfg.file_locks.write_lock(&xtream_path).await.map_err(|err| info_err!(format!("{err}")))?;
+                writer.commit().map_err(|err| cant_write_result!(&xtream_path, err))?;
             }
+            Err(err) => return Err(cant_write_result!(&xtream_path, err)),
         }
     }
     Ok(())
@@ -156,22 +177,28 @@ fn get_map_item_as_str(map: &serde_json::Map<String, Value>, key: &str) -> Optio
     None
 }
 
-fn load_old_category_ids(path: &Path) -> (u32, HashMap<String, u32>) {
+/// Reads the category documents of the collection from the memory storage or the file.
+fn read_category_documents(cfg: &Config, col_path: &Path) -> Vec<Value> {
+    if let Some(storage) = cfg.get_memory_storage() {
+        return memory_read_json(storage, col_path).unwrap_or_default();
+    }
+    match File::open(col_path) {
+        Ok(file) => json_iter_array::<Value, BufReader<File>>(file_reader(file)).flatten().collect(),
+        Err(_) => vec![],
+    }
+}
+
+fn load_old_category_ids(cfg: &Config, path: &Path) -> (u32, HashMap<String, u32>) {
     let mut result: HashMap<String, u32> = HashMap::new();
     let mut max_id: u32 = 0;
     for cat in [COL_CAT_LIVE, COL_CAT_VOD, COL_CAT_SERIES] {
         let col_path = get_collection_path(path, cat);
-        if col_path.exists() {
-            if let Ok(file) = File::open(col_path) {
-                let reader = file_reader(file);
-                for entry in json_iter_array::<Value, BufReader<File>>(reader).flatten() {
-                    if let Some(category_id) = entry.get(TAG_CATEGORY_ID).and_then(get_u32_from_serde_value) {
-                        if let Value::Object(item) = entry {
-                            if let Some(category_name) = get_map_item_as_str(&item, TAG_CATEGORY_NAME) {
-                                result.insert(category_name, category_id);
-                                max_id = max_id.max(category_id);
-                            }
-                        }
+        for entry in read_category_documents(cfg, &col_path) {
+            if let Some(category_id) = entry.get(TAG_CATEGORY_ID).and_then(get_u32_from_serde_value) {
+                if let Value::Object(item) = entry {
+                    if let Some(category_name) = get_map_item_as_str(&item, TAG_CATEGORY_NAME) {
+                        result.insert(category_name, category_id);
+                        max_id = max_id.max(category_id);
                     }
                 }
             }
@@ -180,6 +207,59 @@ fn load_old_category_ids(path: &Path) -> (u32, HashMap<String, u32>) {
     (max_id, result)
 }
 
+fn create_category_document(target: &ConfigTarget, cat_id: u32, title: &str) -> Value {
+    let mut doc = json!({
+      TAG_CATEGORY_ID: format!("{cat_id}"),
+      TAG_CATEGORY_NAME: title,
+      TAG_PARENT_ID: 0
+    });
+    if let (Some(info), Value::Object(map)) = (target.get_category_info(title), &mut doc) {
+        if let Some(icon) = info.icon.as_ref() {
+            map.insert(TAG_CATEGORY_ICON.to_string(), Value::String(icon.clone()));
+        }
+        if let Some(description) = info.description.as_ref() {
+            map.insert(TAG_CATEGORY_DESCRIPTION.to_string(), Value::String(description.clone()));
+        }
+    }
+    doc
+}
+
+/// Category icons are served through the resource endpoint for reverse proxy users.
+pub fn xtream_rewrite_category_icons(categories: &mut [Value], url: &str, user: &ProxyUserCredentials) {
+    for category in categories.iter_mut().filter_map(Value::as_object_mut) {
+        let has_icon = category.get(TAG_CATEGORY_ICON).and_then(Value::as_str).is_some_and(|icon| !icon.is_empty());
+        if has_icon {
+            if let Some(category_id) = category.get(TAG_CATEGORY_ID).and_then(get_u32_from_serde_value) {
+                category.insert(TAG_CATEGORY_ICON.to_string(),
+                                Value::String(format!("{url}/resource/category/{}/{}/{category_id}/icon", user.username, user.password)));
+            }
+        }
+    }
+}
+
+/// Replaces the category names which have a translation.
+pub fn xtream_translate_categories(categories: &mut [Value], translations: &HashMap<String, String>) {
+    for category in categories.iter_mut().filter_map(Value::as_object_mut) {
+        if let Some(translation) = category.get(TAG_CATEGORY_NAME).and_then(Value::as_str).and_then(|name| translations.get(name)) {
+            category.insert(TAG_CATEGORY_NAME.to_string(), Value::String(translation.clone()));
+        }
+    }
+}
+
+/// Returns the original category icon url, category ids are unique over all clusters.
+pub fn xtream_get_category_icon(cfg: &Config, target_name: &str, category_id: u32) -> Option<String> {
+    let path = xtream_get_storage_path(cfg, target_name)?;
+    for cat in [COL_CAT_LIVE, COL_CAT_VOD, COL_CAT_SERIES] {
+        let col_path = get_collection_path(&path, cat);
+        for entry in read_category_documents(cfg, &col_path) {
+            if entry.get(TAG_CATEGORY_ID).and_then(get_u32_from_serde_value) == Some(category_id) {
+                return entry.get(TAG_CATEGORY_ICON).and_then(Value::as_str).map(ToString::to_string);
+            }
+        }
+    }
+    None
+}
+
 pub fn xtream_get_storage_path(cfg: &Config, target_name: &str) -> Option<PathBuf> {
     get_target_storage_path(cfg, target_name).map(|target_path| target_path.join(PathBuf::from(PATH_XTREAM)))
 }
@@ -202,7 +282,101 @@ pub fn xtream_get_file_paths_for_series(storage_path: &Path) -> (PathBuf, PathBu
     xtream_get_file_paths_for_name(storage_path, FILE_SERIES)
 }
 
+// Item layout before `chno`, `user_agent` and `referrer` were introduced, only used to migrate existing playlists.
+#[derive(Serialize, Deserialize)]
+struct LegacyXtreamPlaylistItem {
+    virtual_id: u32,
+    provider_id: u32,
+    name: Rc<String>,
+    logo: Rc<String>,
+    logo_small: Rc<String>,
+    group: Rc<String>,
+    title: Rc<String>,
+    parent_code: Rc<String>,
+    rec: Rc<String>,
+    url: Rc<String>,
+    epg_channel_id: Option<Rc<String>>,
+    xtream_cluster: XtreamCluster,
+    additional_properties: Option<String>,
+    item_type: PlaylistItemType,
+    category_id: u32,
+    input_id: u16,
+}
+
+impl From<LegacyXtreamPlaylistItem> for XtreamPlaylistItem {
+    fn from(item: LegacyXtreamPlaylistItem) -> Self {
+        let empty = Rc::new(String::new());
+        Self {
+            virtual_id: item.virtual_id,
+            provider_id: item.provider_id,
+            name: item.name,
+            chno: Rc::clone(&empty),
+            logo: item.logo,
+            logo_small: item.logo_small,
+            group: item.group,
+            title: item.title,
+            parent_code: item.parent_code,
+            rec: item.rec,
+            url: item.url,
+            user_agent: Rc::clone(&empty),
+            referrer: empty,
+            epg_channel_id: item.epg_channel_id,
+            xtream_cluster: item.xtream_cluster,
+            additional_properties: item.additional_properties,
+            item_type: item.item_type,
+            category_id: item.category_id,
+            input_id: item.input_id,
+        }
+    }
+}
+
+/// Returns `true` if the playlist had the legacy layout and was rewritten.
+fn migrate_xtream_playlist_layout(xtream_path: &Path, idx_path: &Path) -> Result<bool, Error> {
+    let mut current = IndexedDocumentIterator::<u32, XtreamPlaylistItem>::new(xtream_path, idx_path)?;
+    if current.read_next().is_ok() {
+        return Ok(false);
+    }
+    drop(current);
+    let mut legacy = IndexedDocumentIterator::<u32, LegacyXtreamPlaylistItem>::new(xtream_path, idx_path)?;
+    let items: Vec<XtreamPlaylistItem> = legacy.by_ref().map(XtreamPlaylistItem::from).collect();
+    if legacy.has_error() {
+        return Err(str_to_io_error(&format!("Unknown playlist layout {}", xtream_path.display())));
+    }
+    drop(legacy);
+    let mut writer = IndexedDocumentWriter::new_staged(xtream_path.to_path_buf(), idx_path.to_path_buf())?;
+    for item in &items {
+        writer.write_doc(item.virtual_id, item)?;
+    }
+    writer.commit()?;
+    Ok(true)
+}
+
+/// Converts the xtream playlists with a legacy item layout at startup,
+/// the playlist readers only decode the current layout.
+pub fn migrate_xtream_playlist_layouts(cfg: &Config) {
+    if cfg.get_memory_storage().is_some() {
+        return;
+    }
+    for target in cfg.sources.iter().flat_map(|source| &source.targets).filter(|target| target.has_output(&TargetType::Xtream)) {
+        let Some(storage_path) = xtream_get_storage_path(cfg, &target.name) else { continue; };
+        for cluster in [XtreamCluster::Live, XtreamCluster::Video, XtreamCluster::Series] {
+            let (xtream_path, idx_path) = xtream_get_file_paths(&storage_path, cluster);
+            if !xtream_path.exists() || !idx_path.exists() {
+                continue;
+            }
+            match migrate_xtream_playlist_layout(&xtream_path, &idx_path) {
+                Ok(true) => info!("Migrated xtream playlist {}", xtream_path.display()),
+                Ok(false) => {}
+                Err(err) => error!("Failed to migrate xtream playlist {}: {err}", xtream_path.display()),
+            }
+        }
+    }
+}
+
 async fn xtream_garbage_collect(config: &Config, target_name: &str) -> std::io::Result<()> {
+    if config.get_memory_storage().is_some() {
+        return Ok(());
+    }
     // Garbage collect series
     let storage_path = try_option_ok!(xtream_get_storage_path(config, target_name));
     let (info_path, idx_path) = try_option_ok!(xtream_get_info_file_paths(
@@ -216,10 +390,69 @@ async fn xtream_garbage_collect(config: &Config, target_name: &str) -> std::io::
     Ok(())
 }
 
+/// The episode documents of the merged episodes for each kept series, series virtual id -> episodes.
+/// The episodes have their own virtual id, they are streamed from their own input.
+fn create_merged_episode_documents(playlist: &[PlaylistGroup], merged_episodes: &MergedEpisodes) -> HashMap<u32, Vec<Value>> {
+    let items: HashMap<UUIDType, &PlaylistItem> = playlist.iter().flat_map(|group| &group.channels)
+        .map(|pli| (**pli.header.borrow().get_uuid(), pli)).collect();
+    let mut result = HashMap::new();
+    for (series_uuid, episode_uuids) in merged_episodes {
+        let Some(series) = items.get(series_uuid) else { continue; };
+        let episodes: Vec<Value> = episode_uuids.iter().filter_map(|uuid| items.get(uuid)).map(|pli| {
+            let header = pli.header.borrow();
+            let prop = |field: &str| header.get_additional_property(field).cloned().unwrap_or(Value::Null);
+            json!({
+                TAG_ID: header.virtual_id.to_string(),
+                "episode_num": prop("episode"),
+                "season": prop("season"),
+                "title": header.title.as_str(),
+                "container_extension": prop("container_extension"),
+                "added": prop("added"),
+                TAG_DIRECT_SOURCE: header.url.as_str(),
+                TAG_INFO_DATA: {
+                    "movie_image": prop("cover"),
+                    "plot": prop("plot"),
+                    "releasedate": prop("release_date"),
+                },
+            })
+        }).collect();
+        if !episodes.is_empty() {
+            result.insert(series.header.borrow().virtual_id, episodes);
+        }
+    }
+    result
+}
+
+/// Adds the merged episodes to the episode listing of the series info,
+/// an episode of the series info with the same season and episode number is replaced.
+fn merge_episode_documents(episodes: &mut Map<String, Value>, merged: Vec<Value>) {
+    let get_episode_num = |episode: &Value| episode.get("episode_num").and_then(get_u32_from_serde_value);
+    for episode in merged {
+        let Some(season) = episode.get("season").and_then(get_u32_from_serde_value) else { continue; };
+        if let Value::Array(episode_list) = episodes.entry(season.to_string()).or_insert_with(|| Value::Array(vec![])) {
+            let episode_num = get_episode_num(&episode);
+            episode_list.retain(|existing| get_episode_num(existing) != episode_num);
+            episode_list.push(episode);
+            episode_list.sort_by_key(|existing| get_episode_num(existing).unwrap_or(0));
+        }
+    }
+}
+
+fn xtream_read_merged_episodes(cfg: &Config, target_name: &str, virtual_id: u32) -> Vec<Value> {
+    let Some(storage_path) = xtream_get_storage_path(cfg, target_name) else { return vec![] };
+    let merge_path = get_collection_path(&storage_path, COL_SERIES_MERGE);
+    let merged: Option<HashMap<u32, Vec<Value>>> = match cfg.get_memory_storage() {
+        Some(storage) => memory_read_json(storage, &merge_path),
+        None => File::open(&merge_path).ok().and_then(|file| serde_json::from_reader(file_reader(file)).ok()),
+    };
+    merged.and_then(|mut merged| merged.remove(&virtual_id)).unwrap_or_default()
+}
+
 pub async fn xtream_write_playlist(
     target: &ConfigTarget,
     cfg: &Config,
     playlist: &mut [PlaylistGroup],
+    merged_episodes: Option<&MergedEpisodes>,
 ) -> Result<(), M3uFilterError> {
     let path = ensure_xtream_storage_path(cfg, target.name.as_str())?;
     let mut errors = Vec::new();
@@ -230,8 +463,22 @@ pub async fn xtream_write_playlist(
     let mut series_col = vec![];
     let mut vod_col = vec![];
 
+    let merge_path = get_collection_path(&path, COL_SERIES_MERGE);
+    let merged_docs = merged_episodes.map(|merged| create_merged_episode_documents(playlist, merged)).unwrap_or_default();
+    let result = match cfg.get_memory_storage() {
+        Some(storage) => memory_write_json(storage, &merge_path, &merged_docs),
+        None if merged_episodes.is_some() => json_write_documents_to_file(&merge_path, &merged_docs),
+        None if merge_path.exists() => fs::remove_file(&merge_path),
+        None => Ok(()),
+    };
+    if let Err(err) = result {
+        errors.push(format!("Persisting merged episodes failed: {}: {err}", merge_path.display()));
+    }
+
+    let remove_empty_categories = target.options.as_ref().is_some_and(|opts| opts.xtream_remove_empty_categories);
+
     // preserve category_ids
-    let (max_cat_id, existing_cat_ids) = load_old_category_ids(&path);
+    let (max_cat_id, existing_cat_ids) = load_old_category_ids(cfg, &path);
     let mut cat_id_counter = max_cat_id;
     for plg in playlist.iter_mut() {
         if !&plg.channels.is_empty() {
@@ -241,16 +488,7 @@ pub async fn xtream_write_playlist(
             });
             plg.id = *cat_id;
 
-            match &plg.xtream_cluster {
-                XtreamCluster::Live => &mut cat_live_col,
-                XtreamCluster::Series => &mut cat_series_col,
-                XtreamCluster::Video => &mut cat_vod_col,
-            }.push(json!({
-              TAG_CATEGORY_ID: format!("{}", &cat_id),
-              TAG_CATEGORY_NAME: plg.title.clone(),
-              TAG_PARENT_ID: 0
-            }));
-
+            let mut channel_count = 0;
             for pli in &plg.channels {
                 let mut header = pli.header.borrow_mut();
                 let col = match header.item_type {
@@ -276,8 +514,18 @@ pub async fn xtream_write_playlist(
                 drop(header);
                 if let Some(pl) = col {
                     pl.push(pli);
+                    channel_count += 1;
                 }
             }
+
+            // categories without any written channel are confusing empty folders for some players
+            if channel_count > 0 || !remove_empty_categories {
+                match &plg.xtream_cluster {
+                    XtreamCluster::Live => &mut cat_live_col,
+                    XtreamCluster::Series => &mut cat_series_col,
+                    XtreamCluster::Video => &mut cat_vod_col,
+                }.push(create_category_document(target, *cat_id, plg.title.as_str()));
+            }
         }
     }
 
@@ -286,7 +534,11 @@ pub async fn xtream_write_playlist(
         (get_collection_path(&path, COL_CAT_VOD), &cat_vod_col),
         (get_collection_path(&path, COL_CAT_SERIES), &cat_series_col),
     ] {
-        match json_write_documents_to_file(&col_path, data) {
+        let result = match cfg.get_memory_storage() {
+            Some(storage) => memory_write_json(storage, &col_path, data),
+            None => json_write_documents_to_file(&col_path, data),
+        };
+        match result {
             Ok(()) => {}
             Err(err) => {
                 errors.push(format!("Persisting collection failed: {}: {}", &col_path.to_str().unwrap(), err));
@@ -333,7 +585,11 @@ pub fn xtream_get_collection_path(
 ) -> Result<(Option<PathBuf>, Option<String>), Error> {
     if let Some(path) = xtream_get_storage_path(cfg, target_name) {
         let col_path = get_collection_path(&path, collection_name);
-        if col_path.exists() {
+        if let Some(storage) = cfg.get_memory_storage() {
+            if let Some(content) = storage.read_file(&col_path) {
+                return Ok((None, Some(String::from_utf8_lossy(&content).to_string())));
+            }
+        } else if col_path.exists() {
             return Ok((Some(col_path), None));
         }
     }
@@ -347,6 +603,9 @@ async fn xtream_read_item_for_stream_id(
     cluster: XtreamCluster,
 ) -> Result<XtreamPlaylistItem, Error> {
     let (xtream_path, idx_path) = xtream_get_file_paths(storage_path, cluster);
+    if let Some(storage) = cfg.get_memory_storage() {
+        return storage.read_document(&xtream_path, stream_id);
+    }
     {
         let _file_lock = cfg.file_locks.read_lock(&xtream_path).await?;
         IndexedDocumentDirectAccess::read_indexed_item::<u32, XtreamPlaylistItem>(&xtream_path, &idx_path, &stream_id)
@@ -359,6 +618,9 @@ async fn xtream_read_series_item_for_stream_id(
     storage_path: &Path,
 ) -> Result<XtreamPlaylistItem, Error> {
     let (xtream_path, idx_path) = xtream_get_file_paths_for_series(storage_path);
+    if let Some(storage) = cfg.get_memory_storage() {
+        return storage.read_document(&xtream_path, stream_id);
+    }
     {
         let _file_lock = cfg.file_locks.read_lock(&xtream_path).await?;
         IndexedDocumentDirectAccess::read_indexed_item::<u32, XtreamPlaylistItem>(&xtream_path, &idx_path, &stream_id)
@@ -373,6 +635,16 @@ macro_rules! try_cluster {
     };
 }
 
+async fn xtream_query_id_mapping(config: &Config, target_name: &str, target_path: &Path, virtual_id: u32) -> Result<Option<VirtualIdRecord>, Error> {
+    let target_id_mapping_file = get_target_id_mapping_file(target_path);
+    if let Some(storage) = config.get_memory_storage() {
+        return Ok(storage.query_id_mapping(&target_id_mapping_file, virtual_id));
+    }
+    let _file_lock = config.file_locks.read_lock(&target_id_mapping_file).await.map_err(|err| str_to_io_error(&format!("Could not get lock for id mapping for target {target_name} err:{err}")))?;
+    let mut target_id_mapping = BPlusTreeQuery::<u32, VirtualIdRecord>::try_new(&target_id_mapping_file).map_err(|err| str_to_io_error(&format!("Could not load id mapping for target {target_name} err:{err}")))?;
+    Ok(target_id_mapping.query(&virtual_id))
+}
+
 pub async fn xtream_get_item_for_stream_id(
     virtual_id: u32,
     config: &Config,
@@ -382,11 +654,7 @@ pub async fn xtream_get_item_for_stream_id(
     let target_path = get_target_storage_path(config, target.name.as_str()).ok_or_else(|| str_to_io_error(&format!("Could not find path for target {}", &target.name)))?;
     let storage_path = xtream_get_storage_path(config, target.name.as_str()).ok_or_else(|| str_to_io_error(&format!("Could not find path for target {} xtream output", &target.name)))?;
     {
-        let target_id_mapping_file = get_target_id_mapping_file(&target_path);
-        let _file_lock = config.file_locks.read_lock(&target_id_mapping_file).await.map_err(|err|str_to_io_error(&format!("Could not get lock for id mapping for target {} err:{err}", target.name)))?;
-
-        let mut target_id_mapping = BPlusTreeQuery::<u32, VirtualIdRecord>::try_new(&target_id_mapping_file).map_err(|err| str_to_io_error(&format!("Could not load id mapping for target {} err:{err}", target.name)))?;
-        let mapping = target_id_mapping.query(&virtual_id).ok_or_else(|| str_to_io_error(&format!("Could not find mapping for target {} and id {}", target.name, virtual_id)))?;
+        let mapping = xtream_query_id_mapping(config, &target.name, &target_path, virtual_id).await?.ok_or_else(|| str_to_io_error(&format!("Could not find mapping for target {} and id {}", target.name, virtual_id)))?;
         match mapping.item_type {
             PlaylistItemType::SeriesInfo => {
                 xtream_read_series_item_for_stream_id(config, virtual_id, &storage_path).await
@@ -413,6 +681,20 @@ pub async fn xtream_get_item_for_stream_id(
     }
 }
 
+/// The first live item of the target for each of the epg channel ids.
+pub async fn xtream_get_live_items_by_epg_id(config: &Config, target: &ConfigTarget, epg_ids: &[String]) -> Result<HashMap<String, XtreamPlaylistItem>, Error> {
+    let storage_path = xtream_get_storage_path(config, target.name.as_str()).ok_or_else(|| str_to_io_error(&format!("Could not find path for target {} xtream output", &target.name)))?;
+    let (xtream_path, idx_path) = xtream_get_file_paths(&storage_path, XtreamCluster::Live);
+    let reader = DocumentIterator::<XtreamPlaylistItem>::open_target_documents(config, &xtream_path, &idx_path).await?;
+    let mut items = HashMap::new();
+    for pli in reader {
+        if let Some(epg_id) = pli.epg_channel_id.as_ref().filter(|epg_id| epg_ids.contains(epg_id.as_ref())) {
+            items.entry(epg_id.to_string()).or_insert(pli);
+        }
+    }
+    Ok(items)
+}
+
 pub async fn xtream_load_rewrite_playlist(
     cluster: XtreamCluster,
     config: &Config,
@@ -436,6 +718,14 @@ pub async fn xtream_write_series_info(
         XtreamCluster::Series
     ));
 
+    if let Some(storage) = config.get_memory_storage() {
+        storage.write_document(&info_path, series_info_id, content)?;
+        let target_id_mapping_file = get_target_id_mapping_file(&target_path);
+        if let Some(record) = storage.query_id_mapping(&target_id_mapping_file, series_info_id) {
+            storage.update_id_mapping(&target_id_mapping_file, series_info_id, record.copy_update_timestamp())?;
+        }
+        return Ok(());
+    }
     {
         let _file_lock = config.file_locks.write_lock(&info_path).await?;
         let mut writer = IndexedDocumentWriter::new_append(info_path, idx_path)?;
@@ -464,6 +754,9 @@ pub async fn xtream_write_vod_info(
 ) -> Result<(), Error> {
     let storage_path = try_option_ok!(xtream_get_storage_path(config, target_name));
     let (info_path, idx_path) = try_option_ok!(xtream_get_info_file_paths(&storage_path, XtreamCluster::Video));
+    if let Some(storage) = config.get_memory_storage() {
+        return storage.write_document(&info_path, virtual_id, content);
+    }
     {
         let _file_lock = config.file_locks.write_lock(&info_path).await?;
         let mut writer = IndexedDocumentWriter::new_append(info_path, idx_path)?;
@@ -483,16 +776,8 @@ async fn xtream_get_series_info_mapping(
 
 async fn xtream_get_info_mapping(config: &Config, target_name: &str, info_id: u32) -> Option<VirtualIdRecord> {
     let target_path = get_target_storage_path(config, target_name)?;
-
-    let target_id_mapping_file = get_target_id_mapping_file(&target_path);
-    let _file_lock = config.file_locks.read_lock(&target_id_mapping_file).await.map_err(|err| {
-        error!("Could not lock id mapping for target {target_name}: {}", err);
-        str_to_io_error(&format!("ID mapping load error for target {target_name}"))
-    }).ok()?;
-    BPlusTreeQuery::<u32, VirtualIdRecord>::try_new(&target_id_mapping_file).map_err(|err| {
-        error!("Could not load id mapping for target {target_name}: {}", err);
-        str_to_io_error(&format!("ID mapping load error for target {target_name}"))
-    }).ok().map(|mut tree| tree.query(&info_id))?
+    xtream_query_id_mapping(config, target_name, &target_path, info_id).await
+        .map_err(|err| error!("{err}")).ok()?
 }
 
 // Reads the series info entry if exists
@@ -507,6 +792,9 @@ pub async fn xtream_load_series_info(
 
     let (info_path, idx_path) = xtream_get_info_file_paths(&storage_path, XtreamCluster::Series)?;
 
+    if let Some(storage) = config.get_memory_storage() {
+        return storage.read_document(&info_path, series_id).ok();
+    }
     if info_path.exists() && idx_path.exists() {
         {
             let _file_lock = config.file_locks.read_lock(&info_path).await.map_err(|err| {
@@ -548,6 +836,9 @@ pub async fn xtream_load_vod_info(
 
     let (info_path, idx_path) = xtream_get_info_file_paths(&target_storage_path, XtreamCluster::Video)?;
 
+    if let Some(storage) = config.get_memory_storage() {
+        return storage.read_document(&info_path, vod_id).ok();
+    }
     if info_path.exists() && idx_path.exists() {
         {
             let _file_lock = config.file_locks.read_lock(&info_path).await.map_err(|err| {
@@ -597,7 +888,7 @@ fn rewrite_xtream_vod_info<P>(
         movie_data.insert(TAG_STREAM_ID.to_string(), Value::Number(serde_json::value::Number::from(stream_id)));
         movie_data.insert(TAG_CATEGORY_ID.to_string(), Value::Number(serde_json::value::Number::from(category_id)));
         movie_data.insert(TAG_CATEGORY_IDS.to_string(), Value::Array(vec![Value::Number(serde_json::value::Number::from(category_id))]));
-        let options = XtreamMappingOptions::from_target_options(target.options.as_ref());
+        let options = XtreamMappingOptions::from_target(target);
         if options.skip_video_direct_source {
             movie_data.insert(TAG_DIRECT_SOURCE.to_string(), Value::String(String::new()));
         } else {
@@ -606,6 +897,10 @@ fn rewrite_xtream_vod_info<P>(
                 Value::String(pli.get_provider_url().to_string()),
             );
         }
+        // all parts of a multi-part movie with their stream ids
+        if let Some(movie_parts) = pli.get_additional_property(PROP_MOVIE_PARTS) {
+            movie_data.insert(TAG_MOVIE_PARTS.to_string(), movie_parts);
+        }
     }
     let result = serde_json::to_string(&doc).map_err(|_| str_to_io_error("Failed to serialize vod info"))?;
 
@@ -682,8 +977,9 @@ async fn rewrite_xtream_series_info<P>(
     {
         let target_id_mapping_file = get_target_id_mapping_file(&target_path);
         let _file_lock = config.file_locks.write_lock(&target_id_mapping_file).await.map_err(|err| str_to_io_error(&format!("Could not load id mapping for target {} err:{err}", target.name)))?;
-        let mut target_id_mapping = TargetIdMapping::new(&target_id_mapping_file);
-        let options = XtreamMappingOptions::from_target_options(target.options.as_ref());
+        let namespace = get_target_id_namespace(config, &target.name).map_err(|err| str_to_io_error(&err.to_string()))?;
+        let mut target_id_mapping = TargetIdMapping::open(config, &target_id_mapping_file, namespace);
+        let options = XtreamMappingOptions::from_target(target);
 
         let provider_url = pli.get_provider_url();
         for episode_list in episodes.values_mut().filter_map(Value::as_array_mut) {
@@ -696,7 +992,7 @@ async fn rewrite_xtream_series_info<P>(
                         episode_provider_id,
                         PlaylistItemType::Series,
                         virtual_id,
-                    );
+                    ).ok_or_else(|| str_to_io_error(&format!("No free virtual id for the episodes of target {}", target.name)))?;
                     episode.insert(TAG_ID.to_string(), Value::String(episode_virtual_id.to_string()));
                     if resource_url.is_some() {
                         // we need to update the info data.
@@ -713,6 +1009,16 @@ async fn rewrite_xtream_series_info<P>(
         }
 
         drop(target_id_mapping);
+
+        if target.options.as_ref().is_some_and(|opt| opt.merge_series) {
+            let mut merged = xtream_read_merged_episodes(config, &target.name, virtual_id);
+            if options.skip_series_direct_source {
+                for episode in merged.iter_mut().filter_map(Value::as_object_mut) {
+                    episode.insert(TAG_DIRECT_SOURCE.to_string(), Value::String(String::new()));
+                }
+            }
+            merge_episode_documents(episodes, merged);
+        }
     }
     let result = serde_json::to_string(&doc).map_err(|_| str_to_io_error("Failed to serialize updated series info"))?;
 
@@ -753,11 +1059,26 @@ pub async fn xtream_get_input_info(
     provider_id: u32,
     cluster: XtreamCluster,
 ) -> Option<String> {
-    if let Ok(Some((info_path, idx_path))) = get_input_storage_path(input, &cfg.working_dir).map(|storage_path| xtream_get_info_file_paths(&storage_path, cluster))
-    {
-        if let Ok(_file_lock) = cfg.file_locks.read_lock(&info_path).await {
-            if let Ok(content) = IndexedDocumentDirectAccess::read_indexed_item::<u32, String>(&info_path, &idx_path, &provider_id) {
-                return Some(content);
+    if let Ok(storage_path) = get_input_storage_path(input, &cfg.working_dir) {
+        if let Some((info_path, idx_path)) = xtream_get_info_file_paths(&storage_path, cluster) {
+            let result = match cfg.file_locks.read_lock(&info_path).await {
+                Ok(_file_lock) => IndexedDocumentDirectAccess::read_indexed_item::<u32, String>(&info_path, &idx_path, &provider_id),
+                Err(err) => Err(err),
+            };
+            match result {
+                Ok(content) => return Some(content),
+                Err(err) if err.kind() == ErrorKind::InvalidData => {
+                    // the info file was quarantined, drop the records to resolve the info again on next refresh
+                    let item_type = if cluster == XtreamCluster::Series { PlaylistItemType::SeriesInfo } else { PlaylistItemType::Video };
+                    if let Some(record_path) = xtream_get_record_file_path(&storage_path, item_type) {
+                        if let Ok(_file_lock) = cfg.file_locks.write_lock(&record_path).await {
+                            if let Err(err) = fs::remove_file(&record_path) {
+                                error!("Failed to delete record file for corrupt {cluster} info {err}");
+                            }
+                        }
+                    }
+                }
+                Err(_) => {}
             }
         }
     }
@@ -946,9 +1267,135 @@ pub async fn xtream_update_input_series_episodes_record_from_wal_file(
 #[cfg(test)]
 mod tests {
     use std::io;
+    use std::rc::Rc;
+    use std::sync::Arc;
+
+    use serde_json::{json, Map, Value};
+
+    use crate::model::api_proxy::{ProxyType, ProxyUserCredentials};
+    use crate::model::config::{Config, ConfigCategoryInfo, ConfigTarget, StorageMode};
+    use crate::model::playlist::{PlaylistItemType, XtreamCluster, XtreamPlaylistItem};
+    use crate::model::playlist_test_utils::{group, item};
+    use crate::repository::indexed_document::{IndexedDocumentIterator, IndexedDocumentWriter};
+    use crate::repository::memory_storage::MemoryStorage;
+    use crate::repository::xtream_repository::{create_category_document, merge_episode_documents, migrate_xtream_playlist_layout, xtream_get_file_paths,
+                                               xtream_get_info_file_paths, xtream_get_storage_path, xtream_rewrite_category_icons, xtream_write_playlist,
+                                               xtream_write_vod_info, LegacyXtreamPlaylistItem, TAG_CATEGORY_DESCRIPTION, TAG_CATEGORY_ICON};
 
     #[test]
     fn test() -> io::Result<()> {
         Ok(())
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn migrate_xtream_playlist_layout_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let (xtream_path, idx_path) = (dir.path().join("live.db"), dir.path().join("live.idx"));
+        let text = |value: &str| Rc::new(value.to_string());
+        let mut writer = IndexedDocumentWriter::new(xtream_path.clone(), idx_path.clone()).unwrap();
+        for virtual_id in 1..=2 {
+            let legacy = LegacyXtreamPlaylistItem {
+                virtual_id, provider_id: virtual_id + 10, name: text("News"), logo: text("http://logo.png"), logo_small: text(""),
+                group: text("DE"), title: text("News"), parent_code: text(""), rec: text(""), url: text("http://provider.tv/1.ts"),
+                epg_channel_id: Some(text("news.de")), xtream_cluster: XtreamCluster::Live, additional_properties: None,
+                item_type: PlaylistItemType::Live, category_id: 1, input_id: 1,
+            };
+            writer.write_doc(virtual_id, &legacy).unwrap();
+        }
+        writer.store().unwrap();
+        drop(writer);
+
+        assert!(migrate_xtream_playlist_layout(&xtream_path, &idx_path).unwrap());
+        let items: Vec<XtreamPlaylistItem> = IndexedDocumentIterator::<u32, XtreamPlaylistItem>::new(&xtream_path, &idx_path).unwrap().collect();
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[1].provider_id, 12);
+        assert_eq!(items[1].logo.as_str(), "http://logo.png");
+        assert!(items[1].chno.is_empty());
+        assert_eq!(items[1].epg_channel_id.as_deref().map(String::as_str), Some("news.de"));
+        // the current layout is kept
+        assert!(!migrate_xtream_playlist_layout(&xtream_path, &idx_path).unwrap());
+    }
+
+    #[test]
+    fn merge_episode_documents_test() {
+        let mut episodes: Map<String, Value> = serde_json::from_value(json!({
+            "1": [{"id": "10", "episode_num": 1, "season": 1}, {"id": "11", "episode_num": "2", "season": 1}],
+        })).unwrap();
+        merge_episode_documents(&mut episodes, vec![
+            json!({"id": "20", "episode_num": 2, "season": 1}),
+            json!({"id": "21", "episode_num": 3, "season": 1}),
+            json!({"id": "22", "episode_num": 1, "season": 2}),
+        ]);
+        let ids = |season: &str| episodes[season].as_array().unwrap().iter().map(|episode| episode["id"].as_str().unwrap().to_string()).collect::<Vec<_>>();
+        assert_eq!(ids("1"), vec!["10", "20", "21"]);
+        assert_eq!(ids("2"), vec!["22"]);
+    }
+
+    #[test]
+    fn category_info_test() {
+        let mut info = ConfigCategoryInfo {
+            pattern: "(?i)^sports".to_string(),
+            icon: Some("http://images/sports.png".to_string()),
+            description: Some("All sports".to_string()),
+            re: None,
+        };
+        info.prepare().unwrap();
+        let target = ConfigTarget { category_info: Some(vec![info]), ..Default::default() };
+        let mut categories = vec![create_category_document(&target, 3, "Sports HD"), create_category_document(&target, 4, "News")];
+        assert_eq!(categories[0][TAG_CATEGORY_ICON], "http://images/sports.png");
+        assert_eq!(categories[0][TAG_CATEGORY_DESCRIPTION], "All sports");
+        assert!(categories[1].get(TAG_CATEGORY_ICON).is_none());
+
+        let user = ProxyUserCredentials {
+            username: "user".to_string(),
+            password: "pass".to_string(),
+            token: None,
+            proxy: ProxyType::Reverse,
+            server: None,
+            epg_timeshift: None,
+            provider_weight: None,
+            trace_streams: false,
+            exp_date: None,
+            max_connections: None,
+            enabled: true,
+            trial: false,
+            no_log: false,
+            locale: None,
+            mac: None,
+            max_catchup_days: None,
+            t_forwarded_origin: None,
+        };
+        xtream_rewrite_category_icons(&mut categories, "http://localhost", &user);
+        assert_eq!(categories[0][TAG_CATEGORY_ICON], Value::String("http://localhost/resource/category/user/pass/3/icon".to_string()));
+    }
+
+    #[actix_rt::test]
+    async fn memory_info_garbage_collect_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let cfg = Config {
+            working_dir: dir.path().to_string_lossy().to_string(),
+            storage_mode: StorageMode::Memory,
+            t_memory_storage: Some(Arc::new(MemoryStorage::default())),
+            ..Config::default()
+        };
+        let target = ConfigTarget { name: "memory".to_string(), ..Default::default() };
+        let movie = |virtual_id: u32| item(&format!("movie {virtual_id}")).item_type(PlaylistItemType::Video).cluster(XtreamCluster::Video)
+            .url(&format!("http://provider/movie/user/pass/{virtual_id}.mp4")).virtual_id(virtual_id).build();
+
+        let mut playlist = vec![group(0, "Movies", XtreamCluster::Video, vec![movie(10), movie(11)])];
+        xtream_write_playlist(&target, &cfg, &mut playlist, None).await.unwrap();
+        xtream_write_vod_info(&cfg, &target.name, 10, "{\"info\":10}").await.unwrap();
+        xtream_write_vod_info(&cfg, &target.name, 11, "{\"info\":11}").await.unwrap();
+
+        let mut playlist = vec![group(0, "Movies", XtreamCluster::Video, vec![movie(10)])];
+        xtream_write_playlist(&target, &cfg, &mut playlist, None).await.unwrap();
+        let storage = cfg.get_memory_storage().unwrap();
+        let storage_path = xtream_get_storage_path(&cfg, &target.name).unwrap();
+        let (info_path, _) = xtream_get_info_file_paths(&storage_path, XtreamCluster::Video).unwrap();
+        assert_eq!(storage.read_document::<String>(&info_path, 10).unwrap(), "{\"info\":10}");
+        assert!(storage.read_document::<String>(&info_path, 11).is_err());
+        // nothing is written to the target directory
+        assert!(!info_path.exists());
+        assert!(!xtream_get_file_paths(&storage_path, XtreamCluster::Video).0.exists());
+    }
+}
@@ -0,0 +1,51 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Limits the download rate of an input, shared by all concurrent downloads of the input.
+/// Each received chunk reserves its transfer time, the download waits until the reserved time has passed.
+#[derive(Debug)]
+pub struct BandwidthLimiter {
+    bytes_per_second: u64,
+    next_free: Mutex<Option<Instant>>,
+}
+
+impl BandwidthLimiter {
+    pub fn new(bytes_per_second: u64) -> Self {
+        Self { bytes_per_second: bytes_per_second.max(1), next_free: Mutex::new(None) }
+    }
+
+    /// Reserves the transfer time of the chunk and returns the time to wait.
+    fn reserve(&self, bytes: usize, now: Instant) -> Duration {
+        let Ok(mut next_free) = self.next_free.lock() else { return Duration::ZERO; };
+        let transfer_time = Duration::from_secs_f64(bytes as f64 / self.bytes_per_second as f64);
+        let start = next_free.filter(|next| *next > now).unwrap_or(now);
+        let end = start + transfer_time;
+        *next_free = Some(end);
+        end - now
+    }
+
+    pub async fn consume(&self, bytes: usize) {
+        let wait = self.reserve(bytes, Instant::now());
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::utils::bandwidth_limiter::BandwidthLimiter;
+
+    #[test]
+    fn reserve_test() {
+        let limiter = BandwidthLimiter::new(1000);
+        let now = Instant::now();
+        assert_eq!(limiter.reserve(500, now), Duration::from_millis(500));
+        // a concurrent download waits for the previous reservation
+        assert_eq!(limiter.reserve(1000, now), Duration::from_millis(1500));
+        // after an idle period the reservation starts again
+        assert_eq!(limiter.reserve(100, now + Duration::from_secs(5)), Duration::from_millis(100));
+    }
+}
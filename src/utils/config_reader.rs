@@ -1,6 +1,9 @@
+use std::collections::HashMap;
 use std::env;
 use std::fs::File;
+use std::io::Read;
 use std::path::PathBuf;
+use std::sync::atomic::Ordering;
 use std::sync::LazyLock;
 use chrono::Local;
 use log::{debug, error, info, warn};
@@ -9,12 +12,13 @@ use serde::Serialize;
 use crate::{create_m3u_filter_error, create_m3u_filter_error_result, handle_m3u_filter_error_result, info_err};
 use crate::m3u_filter_error::{to_io_error, M3uFilterError, M3uFilterErrorKind};
 use crate::model::api_proxy::ApiProxyConfig;
-use crate::model::config::{Config, ConfigDto};
+use crate::model::config::{Config, ConfigDto, ConfigTarget};
 use crate::model::mapping::Mappings;
 use crate::utils::{file_utils, multi_file_reader};
 
 pub fn read_mappings(args_mapping: Option<String>, cfg: &mut Config) -> Result<Option<String>, M3uFilterError> {
     let mappings_file: String = args_mapping.unwrap_or_else(|| file_utils::get_default_mappings_path(cfg.t_config_path.as_str()));
+    mappings_file.clone_into(&mut cfg.t_mapping_file_path);
 
     match read_mapping(mappings_file.as_str()) {
         Ok(mappings) => {
@@ -43,6 +47,9 @@ pub fn read_api_proxy_config(args_api_proxy_config: Option<String>, cfg: &mut Co
             None
         }
         Some(config) => {
+            for preset in config.trial.iter().flatten().filter(|preset| cfg.get_target_by_name(&preset.target).is_none()) {
+                warn!("Unknown target {} of trial preset {}", preset.target, preset.name);
+            }
             cfg.set_api_proxy(Some(config));
             Some(api_proxy_config_file)
         }
@@ -52,8 +59,10 @@ pub fn read_api_proxy_config(args_api_proxy_config: Option<String>, cfg: &mut Co
 pub fn read_config(config_path: &str, config_file: &str, sources_file: &str) -> Result<Config, M3uFilterError> {
     let files = vec![std::path::PathBuf::from(config_file), std::path::PathBuf::from(sources_file)];
     match multi_file_reader::MultiFileReader::new(&files) {
-        Ok(file) => {
-            match serde_yaml::from_reader::<_, Config>(file) {
+        Ok(mut file) => {
+            let mut content = String::new();
+            match file.read_to_string(&mut content).map_err(|err| err.to_string())
+                .and_then(|_| parse_config(&content)) {
                 Ok(mut result) => {
                     result.t_config_path = config_path.to_string();
                     result.t_config_file_path = config_file.to_string();
@@ -72,6 +81,148 @@ pub fn read_config(config_path: &str, config_file: &str, sources_file: &str) ->
     }
 }
 
+const TARGET_TEMPLATES: &str = "target_templates";
+const TARGET_TEMPLATE: &str = "template";
+
+/// A config without target templates is deserialized from the text to keep the line and column of errors,
+/// the targets of a config with templates are validated one by one to name the target and its template.
+fn parse_config(content: &str) -> Result<Config, String> {
+    let mut value: serde_yaml::Value = serde_yaml::from_str(content).map_err(|err| err.to_string())?;
+    if !uses_target_templates(&value) {
+        return serde_yaml::from_str::<Config>(content).map_err(|err| err.to_string());
+    }
+    expand_target_templates(&mut value)?;
+    serde_yaml::from_value::<Config>(value).map_err(|err| err.to_string())
+}
+
+fn get_targets_mut(content: &mut serde_yaml::Value) -> impl Iterator<Item=&mut serde_yaml::Value> {
+    content.get_mut("sources").and_then(serde_yaml::Value::as_sequence_mut).into_iter().flatten()
+        .filter_map(|source| source.get_mut("targets")).filter_map(serde_yaml::Value::as_sequence_mut).flatten()
+}
+
+fn uses_target_templates(content: &serde_yaml::Value) -> bool {
+    content.get(TARGET_TEMPLATES).is_some()
+        || content.get("sources").and_then(serde_yaml::Value::as_sequence).into_iter().flatten()
+        .filter_map(|source| source.get("targets")).filter_map(serde_yaml::Value::as_sequence).flatten()
+        .any(|target| target.get(TARGET_TEMPLATE).is_some())
+}
+
+/// The `target_templates` of the config content by name.
+fn get_target_templates(content: &serde_yaml::Value) -> Result<HashMap<String, serde_yaml::Value>, String> {
+    let mut templates = HashMap::new();
+    if let Some(template_list) = content.get(TARGET_TEMPLATES) {
+        for template in template_list.as_sequence().ok_or("target_templates should be a list")? {
+            let name = template.get("name").and_then(serde_yaml::Value::as_str).ok_or("target template name required")?;
+            if templates.insert(name.to_string(), template.clone()).is_some() {
+                return Err(format!("target template names should be unique: {name}"));
+            }
+        }
+    }
+    Ok(templates)
+}
+
+/// Merges the overlay into the base, mappings are merged recursively, all other values are replaced.
+fn merge_yaml(base: &mut serde_yaml::Value, overlay: serde_yaml::Value) {
+    match (base, overlay) {
+        (serde_yaml::Value::Mapping(base_map), serde_yaml::Value::Mapping(overlay_map)) => {
+            for (key, value) in overlay_map {
+                match base_map.get_mut(&key) {
+                    Some(base_value) => merge_yaml(base_value, value),
+                    None => { base_map.insert(key, value); }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+/// Replaces the `template` field of the target with the fields of the (inherited) template, the target fields take precedence.
+/// `resolving` holds the template chain to detect cycles.
+fn apply_target_template(templates: &HashMap<String, serde_yaml::Value>, target: &mut serde_yaml::Value, resolving: &mut Vec<String>) -> Result<(), String> {
+    let Some(template_name) = target.as_mapping_mut().and_then(|fields| fields.remove(TARGET_TEMPLATE)) else { return Ok(()); };
+    let template_name = template_name.as_str().ok_or("target template name should be a string")?.to_string();
+    if resolving.contains(&template_name) {
+        return Err(format!("target template cycle: {} -> {template_name}", resolving.join(" -> ")));
+    }
+    let mut template = templates.get(&template_name).cloned().ok_or_else(|| format!("unknown target template: {template_name}"))?;
+    if let Some(fields) = template.as_mapping_mut() {
+        fields.remove("name");
+    }
+    resolving.push(template_name);
+    apply_target_template(templates, &mut template, resolving)?;
+    resolving.pop();
+    merge_yaml(&mut template, std::mem::take(target));
+    *target = template;
+    Ok(())
+}
+
+/// Expands the targets which inherit from a template of `target_templates`.
+/// Templates are targets without name, they can inherit from another template.
+fn expand_target_templates(content: &mut serde_yaml::Value) -> Result<(), String> {
+    let templates = get_target_templates(content)?;
+    if let Some(config) = content.as_mapping_mut() {
+        config.remove(TARGET_TEMPLATES);
+    }
+    for target in get_targets_mut(content) {
+        let template = target.get(TARGET_TEMPLATE).and_then(serde_yaml::Value::as_str).map(ToString::to_string);
+        apply_target_template(&templates, target, &mut Vec::new())?;
+        if let Err(err) = serde_yaml::from_value::<ConfigTarget>(target.clone()) {
+            let name = target.get("name").and_then(serde_yaml::Value::as_str).unwrap_or("?");
+            return Err(match template {
+                Some(template) => format!("target {name} with template {template}: {err}"),
+                None => format!("target {name}: {err}"),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// The fields of `value` which differ from `base`, fields of `base` missing in `value` are set to null.
+fn diff_yaml(value: serde_yaml::Value, base: &serde_yaml::Value) -> Option<serde_yaml::Value> {
+    if &value == base {
+        return None;
+    }
+    let (serde_yaml::Value::Mapping(value_map), serde_yaml::Value::Mapping(base_map)) = (&value, base) else {
+        return Some(value);
+    };
+    let mut result = serde_yaml::Mapping::new();
+    for (key, field) in value_map {
+        match base_map.get(key) {
+            Some(base_field) => if let Some(diff) = diff_yaml(field.clone(), base_field) {
+                result.insert(key.clone(), diff);
+            },
+            None => { result.insert(key.clone(), field.clone()); }
+        }
+    }
+    for key in base_map.keys().filter(|key| !value_map.contains_key(*key)) {
+        result.insert(key.clone(), serde_yaml::Value::Null);
+    }
+    Some(serde_yaml::Value::Mapping(result))
+}
+
+/// Keeps the template of a target, only the fields which differ from the expanded template are written.
+fn reduce_to_template(templates: &HashMap<String, serde_yaml::Value>, template: serde_yaml::Value, target: serde_yaml::Value) -> Result<serde_yaml::Value, String> {
+    let name = target.get("name").cloned().unwrap_or_default();
+    let mut expanded = serde_yaml::Mapping::new();
+    expanded.insert(TARGET_TEMPLATE.into(), template.clone());
+    let mut expanded = serde_yaml::Value::Mapping(expanded);
+    apply_target_template(templates, &mut expanded, &mut Vec::new())?;
+    // the base is normalized like the target to compare the defaults, the mandatory fields may be missing in the template
+    let mut base: serde_yaml::Value = serde_yaml::from_str("{filter: '', output: []}").map_err(|err| err.to_string())?;
+    merge_yaml(&mut base, expanded);
+    if let Some(fields) = base.as_mapping_mut() {
+        fields.insert("name".into(), name.clone());
+    }
+    let base = serde_yaml::from_value::<ConfigTarget>(base).and_then(|base| serde_yaml::to_value(&base)).map_err(|err| err.to_string())?;
+    let mut result = serde_yaml::Mapping::new();
+    result.insert("name".into(), name);
+    result.insert(TARGET_TEMPLATE.into(), template);
+    if let Some(serde_yaml::Value::Mapping(fields)) = diff_yaml(target, &base) {
+        result.extend(fields.into_iter().filter(|(key, _)| key.as_str() != Some("name")));
+    }
+    Ok(serde_yaml::Value::Mapping(result))
+}
+
 pub fn read_mapping(mapping_file: &str) -> Result<Option<Mappings>, M3uFilterError> {
     let mapping_file = std::path::PathBuf::from(mapping_file);
     if let Ok(file) = file_utils::open_file(&mapping_file) {
@@ -138,6 +289,113 @@ pub fn save_api_proxy(file_path: &str, backup_dir: &str, config: &ApiProxyConfig
     write_config_file(file_path, backup_dir, config, "api-proxy.yml")
 }
 
+/// Change of a target through the api.
+pub enum TargetChange {
+    Create { source: usize, target: ConfigTarget },
+    Update { name: String, target: ConfigTarget },
+    Delete { name: String },
+}
+
+fn find_target_position(sources: &[serde_yaml::Value], name: &str) -> Option<(usize, usize)> {
+    sources.iter().enumerate().find_map(|(source_idx, source)| {
+        source.get("targets").and_then(serde_yaml::Value::as_sequence)
+            .and_then(|targets| targets.iter().position(|target| target.get("name").and_then(serde_yaml::Value::as_str) == Some(name)))
+            .map(|target_idx| (source_idx, target_idx))
+    })
+}
+
+fn get_source_targets(sources: &mut [serde_yaml::Value], source_idx: usize) -> Option<&mut Vec<serde_yaml::Value>> {
+    sources.get_mut(source_idx).and_then(|source| source.get_mut("targets")).and_then(serde_yaml::Value::as_sequence_mut)
+}
+
+/// Applies the change to the content of the sources file, the inputs and the other targets are kept as they are.
+/// An updated target keeps its template, only the fields which differ from the template are written.
+fn apply_target_change(sources_file: &mut serde_yaml::Value, templates: &HashMap<String, serde_yaml::Value>, change: &TargetChange) -> Result<(), M3uFilterError> {
+    let Some(sources) = sources_file.get_mut("sources").and_then(serde_yaml::Value::as_sequence_mut) else {
+        return create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "No sources defined");
+    };
+    let to_value = |target: &ConfigTarget| serde_yaml::to_value(target).map_err(|err| info_err!(format!("Invalid target: {err}")));
+    match change {
+        TargetChange::Create { source, target } => {
+            if find_target_position(sources, &target.name).is_some() {
+                return create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "Target {} already exists", target.name);
+            }
+            let value = to_value(target)?;
+            let Some(targets) = get_source_targets(sources, *source) else {
+                return create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "Source {} not found", source);
+            };
+            targets.push(value);
+        }
+        TargetChange::Update { name, target } => {
+            let Some((source_idx, target_idx)) = find_target_position(sources, name) else {
+                return create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "Target {} not found", name);
+            };
+            if name != &target.name && find_target_position(sources, &target.name).is_some() {
+                return create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "Target {} already exists", target.name);
+            }
+            let mut target = target.clone();
+            // the previous name keeps the playlist urls of the users working
+            if name != &target.name && !target.aliases.contains(name) {
+                target.aliases.push(name.clone());
+            }
+            let mut value = to_value(&target)?;
+            if let Some(targets) = get_source_targets(sources, source_idx) {
+                if let Some(template) = targets[target_idx].get(TARGET_TEMPLATE).cloned() {
+                    value = reduce_to_template(templates, template, value).map_err(|err| info_err!(format!("Invalid target: {err}")))?;
+                }
+                targets[target_idx] = value;
+            }
+        }
+        TargetChange::Delete { name } => {
+            let Some((source_idx, target_idx)) = find_target_position(sources, name) else {
+                return create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "Target {} not found", name);
+            };
+            if let Some(targets) = get_source_targets(sources, source_idx) {
+                targets.remove(target_idx);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Validates the changed sources with the main config and saves the sources file.
+/// The running config is not changed, the targets are processed with the saved sources on the next refresh.
+pub fn save_target_change(cfg: &Config, change: &TargetChange) -> Result<(), M3uFilterError> {
+    let sources_path = cfg.t_sources_file_path.as_str();
+    let mut sources_file: serde_yaml::Value = File::open(sources_path).map_err(to_io_error)
+        .and_then(|file| serde_yaml::from_reader(file).map_err(to_io_error))
+        .map_err(|err| info_err!(format!("Could not read file {sources_path}: {err}")))?;
+    // the templates can be defined in the main config or in the sources file
+    let config_file: serde_yaml::Value = File::open(cfg.t_config_file_path.as_str()).ok()
+        .and_then(|file| serde_yaml::from_reader(file).ok()).unwrap_or_default();
+    let mut templates = get_target_templates(&config_file).map_err(|err| info_err!(err))?;
+    templates.extend(get_target_templates(&sources_file).map_err(|err| info_err!(err))?);
+    apply_target_change(&mut sources_file, &templates, change)?;
+
+    let candidate = tempfile::NamedTempFile::new_in(&cfg.working_dir)
+        .and_then(|file| serde_yaml::to_writer(file.as_file(), &sources_file).map_err(to_io_error).map(|()| file))
+        .map_err(|err| info_err!(format!("Could not validate sources: {err}")))?;
+    let candidate_path = candidate.path().to_string_lossy().to_string();
+    read_config(cfg.t_config_path.as_str(), cfg.t_config_file_path.as_str(), candidate_path.as_str())?;
+
+    let backup_dir = cfg.backup_dir.as_deref().unwrap_or(cfg.working_dir.as_str());
+    write_config_file(sources_path, backup_dir, &sources_file, "source.yml")?;
+    cfg.t_sources_changed.store(true, Ordering::Release);
+    Ok(())
+}
+
+/// The config with the sources and templates of the sources file, the runtime state is shared with `cfg`.
+pub fn reload_sources(cfg: &Config) -> Result<Config, M3uFilterError> {
+    let reloaded = read_config(cfg.t_config_path.as_str(), cfg.t_config_file_path.as_str(), cfg.t_sources_file_path.as_str())?;
+    let mut result = cfg.clone();
+    result.sources = reloaded.sources;
+    result.templates = reloaded.templates;
+    if let Some(mappings) = read_mapping(cfg.t_mapping_file_path.as_str())? {
+        result.set_mappings(&mappings);
+    }
+    Ok(result)
+}
+
 pub fn save_main_config(file_path: &str, backup_dir: &str, config: &ConfigDto) -> Result<(), M3uFilterError> {
     write_config_file(file_path, backup_dir, config, "config.yml")
 }
@@ -153,11 +411,133 @@ pub fn resolve_env_var(value: &str) -> String {
 
 #[cfg(test)]
 mod tests {
-    use crate::utils::config_reader::resolve_env_var;
+    use std::collections::HashMap;
+
+    use crate::model::config::ConfigTarget;
+    use crate::utils::config_reader::{apply_target_change, expand_target_templates, get_target_templates, parse_config, resolve_env_var, TargetChange};
 
     #[test]
     fn test_resolve() {
        let resolved =  resolve_env_var("${env:HOME}");
         assert_eq!(resolved, std::env::var("HOME").unwrap());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_apply_target_change() {
+        let mut sources: serde_yaml::Value = serde_yaml::from_str(r"
+sources:
+  - inputs:
+      - url: http://provider.tv/get.php
+    targets:
+      - name: all
+        filter: '!ALL_CHAN!'
+        output:
+          - type: m3u
+").unwrap();
+        let target: ConfigTarget = serde_yaml::from_str("{name: sports, filter: 'Group ~ \"Sports\"', output: [{type: xtream}]}").unwrap();
+        apply_target_change(&mut sources, &HashMap::new(), &TargetChange::Create { source: 0, target: target.clone() }).unwrap();
+        assert!(apply_target_change(&mut sources, &HashMap::new(), &TargetChange::Create { source: 0, target: target.clone() }).is_err());
+        assert!(apply_target_change(&mut sources, &HashMap::new(), &TargetChange::Create { source: 1, target: ConfigTarget { name: "other".to_string(), ..target.clone() } }).is_err());
+        apply_target_change(&mut sources, &HashMap::new(), &TargetChange::Update { name: "sports".to_string(), target: ConfigTarget { name: "football".to_string(), ..target } }).unwrap();
+        apply_target_change(&mut sources, &HashMap::new(), &TargetChange::Delete { name: "all".to_string() }).unwrap();
+        let targets = sources["sources"][0]["targets"].as_sequence().unwrap();
+        assert_eq!(targets.len(), 1);
+        assert_eq!(targets[0]["name"].as_str(), Some("football"));
+        assert_eq!(targets[0]["aliases"][0].as_str(), Some("sports"));
+        assert_eq!(sources["sources"][0]["inputs"][0]["url"].as_str(), Some("http://provider.tv/get.php"));
+    }
+
+    #[test]
+    fn test_expand_target_templates() {
+        let mut content: serde_yaml::Value = serde_yaml::from_str(r#"
+target_templates:
+  - name: base
+    sort:
+      match_as_ascii: true
+    output:
+      - type: xtream
+    options:
+      ignore_logo: true
+  - name: country
+    template: base
+    options:
+      xtream_skip_live_direct_source: true
+sources:
+  - inputs:
+      - url: http://provider.tv/get.php
+    targets:
+      - name: germany
+        template: country
+        filter: 'Group ~ "^DE"'
+        options:
+          ignore_logo: false
+"#).unwrap();
+        expand_target_templates(&mut content).unwrap();
+        assert!(content.get("target_templates").is_none());
+        let target = &content["sources"][0]["targets"][0];
+        assert!(target.get("template").is_none());
+        assert_eq!(target["name"].as_str(), Some("germany"));
+        assert_eq!(target["output"][0]["type"].as_str(), Some("xtream"));
+        assert_eq!(target["sort"]["match_as_ascii"].as_bool(), Some(true));
+        assert_eq!(target["options"]["ignore_logo"].as_bool(), Some(false));
+        assert_eq!(target["options"]["xtream_skip_live_direct_source"].as_bool(), Some(true));
+
+        let mut content: serde_yaml::Value = serde_yaml::from_str(r"
+target_templates:
+  - {name: first, template: second}
+  - {name: second, template: first}
+sources:
+  - targets:
+      - {name: cycle, template: first}
+").unwrap();
+        assert!(expand_target_templates(&mut content).is_err());
+    }
+
+    #[test]
+    fn test_update_template_target() {
+        let mut sources: serde_yaml::Value = serde_yaml::from_str(r#"
+target_templates:
+  - name: country
+    output:
+      - type: xtream
+    options:
+      ignore_logo: true
+sources:
+  - inputs:
+      - url: http://provider.tv/get.php
+    targets:
+      - name: germany
+        template: country
+        filter: 'Group ~ "^DE"'
+"#).unwrap();
+        let templates = get_target_templates(&sources).unwrap();
+        let mut expanded = sources.clone();
+        expand_target_templates(&mut expanded).unwrap();
+        let mut target: ConfigTarget = serde_yaml::from_value(expanded["sources"][0]["targets"][0].clone()).unwrap();
+        target.filter = "Group ~ \"^AT\"".to_string();
+        apply_target_change(&mut sources, &templates, &TargetChange::Update { name: "germany".to_string(), target }).unwrap();
+        let target = sources["sources"][0]["targets"][0].as_mapping().unwrap();
+        let keys: Vec<&str> = target.keys().filter_map(serde_yaml::Value::as_str).collect();
+        assert_eq!(keys, vec!["name", "template", "filter"]);
+        assert_eq!(target["filter"].as_str(), Some("Group ~ \"^AT\""));
+    }
+
+    #[test]
+    fn test_parse_config_errors() {
+        let err = parse_config("api: {host: localhost, port: 8901, web_root: ./web}\nworking_dir: ./data\nthreads: many\n").err().unwrap();
+        assert!(err.contains("line 3"), "{err}");
+
+        let err = parse_config(r"
+api: {host: localhost, port: 8901, web_root: ./web}
+working_dir: ./data
+target_templates:
+  - {name: country, options: {ignore_logo: maybe}}
+sources:
+  - inputs:
+      - url: http://provider.tv/get.php
+    targets:
+      - {name: germany, template: country, filter: '!ALL_CHAN!', output: [{type: m3u}]}
+").err().unwrap();
+        assert!(err.starts_with("target germany with template country:"), "{err}");
+    }
+}
@@ -2,4 +2,6 @@ pub const fn default_as_true() -> bool { true }
 
 pub fn default_as_default() -> String { String::from("default") }
 
+pub const fn default_as_one_u16() -> u16 { 1 }
+
 pub const fn default_as_two_u16() -> u16 { 2 }
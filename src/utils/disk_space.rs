@@ -0,0 +1,78 @@
+use std::path::Path;
+
+use crate::model::config::DiskSpaceConfig;
+use crate::utils::size_utils::human_readable_byte_size;
+
+#[cfg(unix)]
+fn get_available_space_unix(path: &Path) -> Option<u64> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = CString::new(path.as_os_str().as_bytes()).ok()?;
+    unsafe {
+        let mut stat: libc::statvfs = std::mem::zeroed();
+        if libc::statvfs(c_path.as_ptr(), &mut stat) == 0 {
+            #[allow(clippy::unnecessary_cast)]
+            Some(stat.f_bavail as u64 * stat.f_frsize as u64)
+        } else {
+            None
+        }
+    }
+}
+
+/// The space available for the process on the filesystem of the path, `None` if it can't be determined.
+/// A path which does not exist yet is checked on its nearest existing parent.
+pub fn get_available_space(path: &Path) -> Option<u64> {
+    let existing = path.ancestors().find(|p| p.exists())?;
+    #[cfg(unix)]
+    return get_available_space_unix(existing);
+
+    #[cfg(not(unix))]
+    {
+        let _ = existing;
+        None
+    }
+}
+
+/// The estimated space for a write of `size` bytes, with the configured margin and the space which has to be kept free.
+pub fn estimate_required_space(disk_space: &DiskSpaceConfig, size: u64) -> u64 {
+    size.saturating_add(size / 100 * u64::from(disk_space.margin))
+        .saturating_add(disk_space.t_min_free)
+}
+
+/// Fails if less than the estimated space for a write of `size` bytes is available at the path.
+/// The check passes if the available space can't be determined.
+pub fn check_disk_space(disk_space: &DiskSpaceConfig, path: &Path, size: u64) -> Result<(), String> {
+    if !disk_space.enabled {
+        return Ok(());
+    }
+    let required = estimate_required_space(disk_space, size);
+    match get_available_space(path) {
+        Some(available) if available < required => Err(format!("Not enough disk space at {}, {} available, {} required",
+                                                                 path.display(), human_readable_byte_size(available), human_readable_byte_size(required))),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::model::config::DiskSpaceConfig;
+    use crate::utils::disk_space::{check_disk_space, estimate_required_space, get_available_space};
+
+    #[test]
+    fn disk_space_test() {
+        let mut disk_space = DiskSpaceConfig { enabled: true, min_free: None, margin: 20, t_min_free: 1_000 };
+        assert_eq!(estimate_required_space(&disk_space, 10_000), 13_000);
+        assert_eq!(estimate_required_space(&disk_space, u64::MAX), u64::MAX);
+        let tmp = std::env::temp_dir();
+        if get_available_space(&tmp).is_some() {
+            assert!(get_available_space(&tmp.join("not/existing")).is_some());
+            assert!(check_disk_space(&disk_space, &tmp, 0).is_ok());
+            assert!(check_disk_space(&disk_space, &tmp, u64::MAX / 2).is_err());
+        }
+        disk_space.enabled = false;
+        assert!(check_disk_space(&disk_space, Path::new("/"), u64::MAX / 2).is_ok());
+    }
+}
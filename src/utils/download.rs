@@ -1,23 +1,34 @@
 use crate::Arc;
 use std::borrow::Cow;
-use crate::m3u_filter_error::{str_to_io_error, M3uFilterError};
-use crate::model::config::{Config, ConfigInput, ConfigTarget};
+use crate::m3u_filter_error::{str_to_io_error, M3uFilterError, M3uFilterErrorKind};
+use crate::model::config::{Config, ConfigInput, ConfigTarget, InputType};
 use crate::model::playlist::{PlaylistEntry, PlaylistGroup, XtreamCluster};
 use crate::model::xmltv::TVGuide;
-use crate::processing::{m3u_parser, xtream_parser};
+use crate::processing::{local_parser, m3u_parser, xtream_parser};
+use crate::processing::parse_report::InputParseReport;
 use crate::repository::xtream_repository::{rewrite_xtream_series_info_content, rewrite_xtream_vod_info_content, xtream_get_input_info};
 use crate::repository::xtream_repository;
 use crate::utils::{file_utils, request_utils};
-use log::{debug, info};
+use crate::utils::compressed_file_reader::CompressedFileReader;
+use log::{debug, info, warn};
 use std::cmp::Ordering;
 use std::io::{Error};
-use std::path::PathBuf;
-use crate::debug_if_enabled;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use chrono::{DateTime, Local};
+use cron::Schedule;
+use url::Url;
+use crate::{debug_if_enabled, notify_err};
+use crate::utils::preflight::guard_provider_request;
+use crate::utils::request_utils::mask_sensitive_info;
+use crate::repository::storage::get_input_storage_path;
 use crate::model::api_proxy::{ProxyUserCredentials};
 
 const ACTION_GET_SERIES_INFO: &str = "get_series_info";
 const ACTION_GET_VOD_INFO: &str = "get_vod_info";
 const ACTION_GET_LIVE_INFO: &str = "get_live_info";
+const SNAPSHOT_PREFIX: &str = "snapshot_";
+const FILE_M3U_DOWNLOAD: &str = "download.m3u";
 
 fn prepare_file_path(persist: Option<&str>, working_dir: &str, action: &str) -> Option<PathBuf> {
     let persist_file: Option<PathBuf> =
@@ -31,17 +42,187 @@ fn prepare_file_path(persist: Option<&str>, working_dir: &str, action: &str) ->
     }
 }
 
-pub async fn get_m3u_playlist(client: Arc<reqwest::Client>, cfg: &Config, input: &ConfigInput, working_dir: &str) -> (Vec<PlaylistGroup>, Vec<M3uFilterError>) {
-    let url = input.url.clone();
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum InputSnapshotKind {
+    Playlist,
+    Epg,
+}
+
+fn get_snapshot_schedule(input: &ConfigInput, kind: InputSnapshotKind) -> Option<&str> {
+    input.schedule.as_ref().and_then(|schedule| match kind {
+        InputSnapshotKind::Playlist => schedule.playlist.as_deref(),
+        InputSnapshotKind::Epg => schedule.epg.as_deref(),
+    })
+}
+
+fn get_snapshot_path(input: &ConfigInput, working_dir: &str, file_name: &str) -> Option<PathBuf> {
+    get_input_storage_path(input, working_dir).ok().map(|path| path.join(format!("{SNAPSHOT_PREFIX}{file_name}")))
+}
+
+pub fn get_xtream_base_url(input: &ConfigInput) -> String {
+    let username = input.username.as_ref().map_or("", |v| v);
+    let password = input.password.as_ref().map_or("", |v| v);
+    format!("{}/player_api.php?username={}&password={}", input.url, username, password)
+}
+
+/// The urls and snapshot file names of an input, the urls of one snapshot are tried in failover order.
+fn get_snapshot_sources(cfg: &Config, input: &ConfigInput, kind: InputSnapshotKind) -> Vec<(Vec<String>, String)> {
+    match (kind, &input.input_type) {
+        (InputSnapshotKind::Epg, _) => {
+            let urls = cfg.t_input_health.get_epg_failover_order(input, &input.get_epg_urls());
+            if urls.is_empty() { vec![] } else { vec![(urls.into_iter().cloned().collect(), "epg.xml".to_string())] }
+        }
+        (InputSnapshotKind::Playlist, InputType::M3u) => vec![(vec![input.url.clone()], "playlist.m3u".to_string())],
+        (InputSnapshotKind::Playlist, InputType::Local) => vec![],
+        (InputSnapshotKind::Playlist, InputType::Xtream) => {
+            let base_url = get_xtream_base_url(input);
+            let skip_cluster = get_skip_cluster(input);
+            ACTIONS.iter().filter(|(xtream_cluster, _, _)| !skip_cluster.contains(xtream_cluster))
+                .flat_map(|(_, category, stream)| [category, stream])
+                .map(|action| (vec![format!("{base_url}&action={action}")], format!("{action}.json")))
+                .collect()
+        }
+    }
+}
+
+/// A snapshot is current if no scheduled refresh was missed since it was written.
+fn is_snapshot_current(path: &Path, expression: &str) -> bool {
+    let Ok(schedule) = Schedule::from_str(expression) else { return false; };
+    path.metadata().and_then(|meta| meta.modified()).is_ok_and(|modified| {
+        let modified: DateTime<Local> = modified.into();
+        schedule.after(&modified).next().is_none_or(|next| next > Local::now())
+    })
+}
+
+async fn download_snapshot(client: Arc<reqwest::Client>, input: &ConfigInput, working_dir: &str, url: &str, path: &Path, kind: InputSnapshotKind) -> Result<(), M3uFilterError> {
+    let mut staged_name = path.as_os_str().to_owned();
+    staged_name.push(".staged");
+    let staged_path = PathBuf::from(staged_name);
+    match kind {
+        InputSnapshotKind::Playlist => request_utils::get_input_text_content(client, input, working_dir, url, Some(staged_path.clone())).await.map(|_| ())?,
+        InputSnapshotKind::Epg => request_utils::get_input_text_content_as_file(client, input, working_dir, url, Some(staged_path.clone())).await.map(|_| ())?,
+    }
+    // local files are not copied, they are read directly
+    if staged_path.exists() {
+        std::fs::rename(&staged_path, path).map_err(|err| notify_err!(format!("Failed to write input snapshot {}: {err}", path.to_string_lossy())))?;
+    }
+    Ok(())
+}
+
+/// Downloads the playlist or epg of the input into the snapshot files, which are used by the target processing.
+pub async fn refresh_input_snapshot(client: Arc<reqwest::Client>, cfg: &Config, input: &ConfigInput, kind: InputSnapshotKind) -> Result<(), M3uFilterError> {
+    for (urls, file_name) in get_snapshot_sources(cfg, input, kind) {
+        let Some(path) = get_snapshot_path(input, &cfg.working_dir, &file_name) else { continue; };
+        let _file_lock = cfg.file_locks.write_lock(&path).await.map_err(|err| notify_err!(format!("{err}")))?;
+        let mut last_error = None;
+        for url in &urls {
+            // scheduled downloads pass the same rate limit and preflight checks as the api requests
+            let result = match guard_provider_request(cfg, input, url).await {
+                Ok((request_input, request_url)) => download_snapshot(Arc::clone(&client), request_input, &cfg.working_dir, &request_url, &path, kind).await,
+                Err(err) => Err(notify_err!(format!("Failed to download {}: {err}", mask_sensitive_info(url)))),
+            };
+            if kind == InputSnapshotKind::Epg {
+                cfg.t_input_health.record_epg_result(input, url, result.is_ok());
+            }
+            match result {
+                Ok(()) => {
+                    last_error = None;
+                    break;
+                }
+                Err(err) => {
+                    if urls.len() > 1 {
+                        warn!("Failed to download epg {}, trying next url: {}", mask_sensitive_info(url), mask_sensitive_info(&err.message));
+                    }
+                    last_error = Some(err);
+                }
+            }
+        }
+        if let Some(err) = last_error {
+            return Err(err);
+        }
+    }
+    Ok(())
+}
+
+/// Inputs with a refresh schedule are read from the latest snapshot instead of the provider.
+/// If the snapshot is missing or a scheduled refresh was missed, it is downloaded first.
+/// Returns the url and persist path to read the content from.
+async fn resolve_input_source(client: Arc<reqwest::Client>, cfg: &Config, input: &ConfigInput, kind: InputSnapshotKind,
+                              url: &str, file_name: &str, persist_file_path: Option<PathBuf>) -> Result<(String, Option<PathBuf>), M3uFilterError> {
+    if let Some(expression) = get_snapshot_schedule(input, kind) {
+        if let Some(path) = get_snapshot_path(input, &cfg.working_dir, file_name) {
+            {
+                let _file_lock = cfg.file_locks.write_lock(&path).await.map_err(|err| notify_err!(format!("{err}")))?;
+                if !is_snapshot_current(&path, expression) {
+                    download_snapshot(client, input, &cfg.working_dir, url, &path, kind).await?;
+                }
+            }
+            if let Ok(file_url) = Url::from_file_path(&path) {
+                if path.exists() {
+                    return Ok((file_url.to_string(), None));
+                }
+            }
+        }
+    }
+    Ok((url.to_string(), persist_file_path))
+}
+
+pub async fn get_m3u_playlist(client: Arc<reqwest::Client>, cfg: &Config, input: &ConfigInput, working_dir: &str, parse_report: &mut InputParseReport) -> (Vec<PlaylistGroup>, Vec<M3uFilterError>) {
     let persist_file_path = prepare_file_path(input.persist.as_deref(), working_dir, "");
+    let (url, persist_file_path) = match resolve_input_source(Arc::clone(&client), cfg, input, InputSnapshotKind::Playlist, &input.url, "playlist.m3u", persist_file_path).await {
+        Ok(source) => source,
+        Err(err) => return (vec![], vec![err]),
+    };
+    if cfg.low_memory {
+        return get_m3u_playlist_from_file(client, cfg, input, working_dir, &url, persist_file_path, parse_report).await;
+    }
     match request_utils::get_input_text_content(client, input, working_dir, &url, persist_file_path).await {
         Ok(text) => {
-            (m3u_parser::parse_m3u(cfg, input, text.lines()), vec![])
+            match m3u_parser::parse_m3u(cfg, input, text.lines(), parse_report) {
+                Ok(playlist) => (playlist, vec![]),
+                Err(err) => (vec![], vec![err]),
+            }
         }
         Err(err) => (vec![], vec![err])
     }
 }
 
+/// The playlist is downloaded into a file and parsed line by line, the download is not held in memory as a whole.
+async fn get_m3u_playlist_from_file(client: Arc<reqwest::Client>, cfg: &Config, input: &ConfigInput, working_dir: &str, url: &str,
+                                    persist_file_path: Option<PathBuf>, parse_report: &mut InputParseReport) -> (Vec<PlaylistGroup>, Vec<M3uFilterError>) {
+    // without persist path the download is a temporary file of the input storage
+    let download_path = match persist_file_path {
+        Some(path) => Some(path),
+        None => match get_input_storage_path(input, working_dir) {
+            Ok(path) => Some(path.join(FILE_M3U_DOWNLOAD)),
+            Err(err) => return (vec![], vec![notify_err!(format!("Failed to create input storage: {err}"))]),
+        },
+    };
+    let is_temporary = download_path.as_ref().is_some_and(|path| path.ends_with(FILE_M3U_DOWNLOAD));
+    let file_path = match request_utils::get_input_text_content_as_file(client, input, working_dir, url, download_path).await {
+        Ok(file_path) => file_path,
+        Err(err) => return (vec![], vec![err]),
+    };
+    let result = match CompressedFileReader::new(&file_path) {
+        Ok(reader) => m3u_parser::parse_m3u(cfg, input, reader.map_while(Result::ok), parse_report),
+        Err(err) => Err(notify_err!(format!("Failed to read playlist {}: {err}", file_path.display()))),
+    };
+    if is_temporary && file_path.ends_with(FILE_M3U_DOWNLOAD) {
+        let _ = std::fs::remove_file(&file_path);
+    }
+    match result {
+        Ok(playlist) => (playlist, vec![]),
+        Err(err) => (vec![], vec![err]),
+    }
+}
+
+pub fn get_local_playlist(input: &ConfigInput) -> (Vec<PlaylistGroup>, Vec<M3uFilterError>) {
+    match local_parser::parse_local_directory(input) {
+        Ok(playlist) => (playlist, vec![]),
+        Err(err) => (vec![], vec![err]),
+    }
+}
+
 pub fn get_xtream_player_api_action_url(input: &ConfigInput, action: &str) -> Option<String> {
     if let Some(user_info) = input.get_user_info() {
         Some(format!("{}/player_api.php?username={}&password={}&action={}",
@@ -69,6 +250,20 @@ pub async fn get_xtream_stream_info_content(client: Arc<reqwest::Client>, info_u
     request_utils::download_text_content(client, input, info_url, None).await
 }
 
+/// Info, catchup and short epg requests of the api pass the rate limit and preflight checks, see `guard_provider_request`.
+pub async fn get_xtream_api_content(client: Arc<reqwest::Client>, cfg: &Config, url: &str, input: &ConfigInput) -> Result<String, Error> {
+    let (input, url) = guard_provider_request(cfg, input, url).await.map_err(|err| str_to_io_error(&err.to_string()))?;
+    request_utils::download_text_content(client, input, &url, None).await
+}
+
+/// Identical info requests of concurrent clients (e.g. after a refresh) share one provider request.
+async fn get_xtream_stream_info_content_coalesced(client: Arc<reqwest::Client>, cfg: &Config, info_url: &str, input: &ConfigInput) -> Result<String, Error> {
+    let request = async {
+        get_xtream_api_content(client, cfg, info_url, input).await.map_err(|err| err.to_string())
+    };
+    input.t_info_requests.request(info_url, request).await.map_err(|err| str_to_io_error(&err))
+}
+
 #[allow(clippy::too_many_arguments)]
 pub async fn get_xtream_stream_info<P>(client: Arc<reqwest::Client>,
                                        config: &Config,
@@ -112,7 +307,7 @@ where
         }
     }
 
-    if let Ok(content) = get_xtream_stream_info_content(client, info_url, input).await {
+    if let Ok(content) = get_xtream_stream_info_content_coalesced(client, config, info_url, input).await {
         return match cluster {
             XtreamCluster::Live => Ok(content),
             XtreamCluster::Video => xtream_repository::write_and_get_xtream_vod_info(config, target, pli, user, &content).await,
@@ -124,7 +319,7 @@ where
                                              target.name.replace(' ', "_").as_str(), &cluster, pli.get_virtual_id())))
 }
 
-fn get_skip_cluster(input: &ConfigInput) -> Vec<XtreamCluster> {
+pub fn get_skip_cluster(input: &ConfigInput) -> Vec<XtreamCluster> {
     let mut skip_cluster = vec![];
     if let Some(input_options) = &input.options {
         if input_options.xtream_skip_live {
@@ -144,16 +339,14 @@ fn get_skip_cluster(input: &ConfigInput) -> Vec<XtreamCluster> {
     skip_cluster
 }
 
-const ACTIONS: [(XtreamCluster, &str, &str); 3] = [
+pub const ACTIONS: [(XtreamCluster, &str, &str); 3] = [
     (XtreamCluster::Live, "get_live_categories", "get_live_streams"),
     (XtreamCluster::Video, "get_vod_categories", "get_vod_streams"),
     (XtreamCluster::Series, "get_series_categories", "get_series")];
 
-pub async fn get_xtream_playlist(client: Arc<reqwest::Client>, input: &ConfigInput, working_dir: &str) -> (Vec<PlaylistGroup>, Vec<M3uFilterError>) {
+pub async fn get_xtream_playlist(client: Arc<reqwest::Client>, cfg: &Config, input: &ConfigInput, working_dir: &str, parse_report: &mut InputParseReport) -> (Vec<PlaylistGroup>, Vec<M3uFilterError>) {
     let mut playlist_groups: Vec<PlaylistGroup> = Vec::with_capacity(128);
-    let username = input.username.as_ref().map_or("", |v| v);
-    let password = input.password.as_ref().map_or("", |v| v);
-    let base_url = format!("{}/player_api.php?username={}&password={}", input.url, username, password);
+    let base_url = get_xtream_base_url(input);
 
     let skip_cluster = get_skip_cluster(input);
 
@@ -164,22 +357,38 @@ pub async fn get_xtream_playlist(client: Arc<reqwest::Client>, input: &ConfigInp
             let stream_url = format!("{base_url}&action={stream}");
             let category_file_path = prepare_file_path(input.persist.as_deref(), working_dir, format!("{category}_").as_str());
             let stream_file_path = prepare_file_path(input.persist.as_deref(), working_dir, format!("{stream}_").as_str());
+            let category_source = resolve_input_source(Arc::clone(&client), cfg, input, InputSnapshotKind::Playlist, &category_url, &format!("{category}.json"), category_file_path).await;
+            let stream_source = resolve_input_source(Arc::clone(&client), cfg, input, InputSnapshotKind::Playlist, &stream_url, &format!("{stream}.json"), stream_file_path).await;
+            let ((category_url, category_file_path), (stream_url, stream_file_path)) = match (category_source, stream_source) {
+                (Ok(category_source), Ok(stream_source)) => (category_source, stream_source),
+                (Err(err), _) | (_, Err(err)) => {
+                    errors.push(err);
+                    continue;
+                }
+            };
 
             match futures::join!(
                 request_utils::get_input_json_content(Arc::clone(&client), input, category_url.as_str(), category_file_path),
-                request_utils::get_input_json_content(Arc::clone(&client), input, stream_url.as_str(), stream_file_path)
+                // the stream lists can be huge, they are parsed from the downloaded file
+                request_utils::get_input_json_content_as_file(Arc::clone(&client), input, stream_url.as_str(), stream_file_path)
             ) {
-                (Ok(category_content), Ok(stream_content)) => {
-                    match xtream_parser::parse_xtream(input,
-                                                      *xtream_cluster,
-                                                      &category_content,
-                                                      &stream_content) {
+                (Ok(category_content), Ok(stream_file)) => {
+                    match xtream_parser::parse_xtream_file(input,
+                                                           *xtream_cluster,
+                                                           &category_content,
+                                                           &stream_file.path,
+                                                           parse_report) {
                         Ok(sub_playlist_parsed) => {
                             if let Some(mut xtream_sub_playlist) = sub_playlist_parsed {
                                 playlist_groups.append(&mut xtream_sub_playlist);
                             }
                         }
-                        Err(err) => errors.push(err)
+                        Err(err) => {
+                            errors.push(err);
+                            if parse_report.aborted {
+                                break;
+                            }
+                        }
                     }
                 }
                 (Err(err1), Err(err2)) => {
@@ -197,20 +406,106 @@ pub async fn get_xtream_playlist(client: Arc<reqwest::Client>, input: &ConfigInp
     (playlist_groups, errors)
 }
 
-pub async fn get_xmltv(client: Arc<reqwest::Client>, _cfg: &Config, input: &ConfigInput, working_dir: &str) -> (Option<TVGuide>, Vec<M3uFilterError>) {
-    match &input.epg_url {
-        None => (None, vec![]),
-        Some(url) => {
-            debug!("Getting epg file path for url: {}", url);
-            let persist_file_path = prepare_file_path(input.persist.as_deref(), working_dir, "")
-                .map(|path| file_utils::add_prefix_to_filename(&path, "epg_", Some("xml")));
+async fn get_xmltv_for_url(client: Arc<reqwest::Client>, cfg: &Config, input: &ConfigInput, working_dir: &str, url: &str) -> Result<TVGuide, M3uFilterError> {
+    debug!("Getting epg file path for url: {}", url);
+    let persist_file_path = prepare_file_path(input.persist.as_deref(), working_dir, "")
+        .map(|path| file_utils::add_prefix_to_filename(&path, "epg_", Some("xml")));
+    let (url, persist_file_path) = resolve_input_source(Arc::clone(&client), cfg, input, InputSnapshotKind::Epg, url, "epg.xml", persist_file_path).await?;
+    request_utils::get_input_text_content_as_file(client, input, working_dir, &url, persist_file_path).await.map(|file| TVGuide { file })
+}
 
-            match request_utils::get_input_text_content_as_file(client, input, working_dir, url, persist_file_path).await {
-                Ok(file) => {
-                    (Some(TVGuide { file }), vec![])
+/// The epg urls are tried in failover order, failed urls are moved to the end for the next updates.
+pub async fn get_xmltv(client: Arc<reqwest::Client>, cfg: &Config, input: &ConfigInput, working_dir: &str) -> (Option<TVGuide>, Vec<M3uFilterError>) {
+    let urls = cfg.t_input_health.get_epg_failover_order(input, &input.get_epg_urls());
+    let mut errors = vec![];
+    for url in &urls {
+        match get_xmltv_for_url(Arc::clone(&client), cfg, input, working_dir, url).await {
+            Ok(tv_guide) => {
+                cfg.t_input_health.record_epg_result(input, url, true);
+                return (Some(tv_guide), vec![]);
+            }
+            Err(err) => {
+                cfg.t_input_health.record_epg_result(input, url, false);
+                if urls.len() > 1 {
+                    warn!("Failed to get epg {}, trying next url: {}", mask_sensitive_info(url), mask_sensitive_info(&err.message));
                 }
-                Err(err) => (None, vec![err])
+                errors.push(err);
             }
         }
     }
-}
\ No newline at end of file
+    (None, errors)
+}
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::sync::Arc;
+    use std::time::{Duration, SystemTime};
+
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+
+    use crate::model::config::{Config, ConfigInput, InputType, VideoConfig};
+    use crate::processing::parse_report::InputParseReport;
+    use crate::utils::download::{get_m3u_playlist, get_snapshot_sources, is_snapshot_current, InputSnapshotKind};
+
+    #[test]
+    fn is_snapshot_current_test() {
+        let snapshot = tempfile::NamedTempFile::new().unwrap();
+        // daily at midnight
+        assert!(is_snapshot_current(snapshot.path(), "0 0 0 * * * *"));
+        snapshot.as_file().set_modified(SystemTime::now() - Duration::from_secs(2 * 24 * 3600)).unwrap();
+        assert!(!is_snapshot_current(snapshot.path(), "0 0 0 * * * *"));
+        assert!(!is_snapshot_current(&snapshot.path().with_extension("missing"), "0 0 0 * * * *"));
+    }
+
+    #[test]
+    fn get_snapshot_sources_test() {
+        let input = ConfigInput {
+            input_type: InputType::Xtream,
+            url: "http://provider.tv".to_string(),
+            username: Some("user".to_string()),
+            password: Some("secret".to_string()),
+            ..Default::default()
+        };
+        let cfg = Config::default();
+        let sources = get_snapshot_sources(&cfg, &input, InputSnapshotKind::Playlist);
+        assert_eq!(sources.len(), 6);
+        assert_eq!(sources[0], (vec!["http://provider.tv/player_api.php?username=user&password=secret&action=get_live_categories".to_string()], "get_live_categories.json".to_string()));
+        assert!(get_snapshot_sources(&cfg, &input, InputSnapshotKind::Epg).is_empty());
+
+        let input = ConfigInput {
+            epg_url: Some("http://provider.tv/epg.xml".to_string()),
+            epg_failover_urls: Some(vec!["http://backup.tv/epg.xml".to_string()]),
+            ..input
+        };
+        let sources = get_snapshot_sources(&cfg, &input, InputSnapshotKind::Epg);
+        assert_eq!(sources, vec![(vec!["http://provider.tv/epg.xml".to_string(), "http://backup.tv/epg.xml".to_string()], "epg.xml".to_string())]);
+    }
+
+    #[actix_rt::test]
+    async fn low_memory_m3u_playlist_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let working_dir = dir.path().to_string_lossy().to_string();
+        let content = "#EXTM3U\n#EXTINF:-1 group-title=\"News\",News 1\nhttp://provider.tv/live/1.ts\n#EXTINF:-1 group-title=\"Sports\",Sports 1\nhttp://provider.tv/live/2.ts\n";
+        let plain_path = dir.path().join("playlist.m3u");
+        std::fs::write(&plain_path, content).unwrap();
+        let gzip_path = dir.path().join("playlist.m3u.gz");
+        let mut encoder = GzEncoder::new(std::fs::File::create(&gzip_path).unwrap(), Compression::default());
+        encoder.write_all(content.as_bytes()).unwrap();
+        encoder.finish().unwrap();
+
+        let cfg = Config { low_memory: true, working_dir: working_dir.clone(), video: Some(VideoConfig::default()), ..Config::default() };
+        let client = Arc::new(reqwest::Client::new());
+        // the playlist is parsed from the file, gzip compressed files are decoded
+        for path in [&plain_path, &gzip_path] {
+            let input = ConfigInput { id: 1, name: Some("provider".to_string()), url: path.to_string_lossy().to_string(), ..Default::default() };
+            let mut parse_report = InputParseReport::new(&input);
+            let (playlist, errors) = get_m3u_playlist(Arc::clone(&client), &cfg, &input, &working_dir, &mut parse_report).await;
+            assert!(errors.is_empty());
+            let titles: Vec<&str> = playlist.iter().map(|group| group.title.as_str()).collect();
+            assert_eq!(titles, vec!["News", "Sports"]);
+            assert_eq!(playlist[1].channels[0].header.borrow().url.as_str(), "http://provider.tv/live/2.ts");
+            assert!(path.exists());
+        }
+    }
+}
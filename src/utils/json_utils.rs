@@ -75,23 +75,25 @@ pub fn json_filter_file(file_path: &Path, filter: &HashMap<&str, &str>) -> Vec<s
     };
 
     let reader = file_reader(file);
-    for entry in json_iter_array::<serde_json::Value, BufReader<File>>(reader).flatten() {
-        if let Some(item) = entry.as_object() {
-            if filter.iter().all(|(&key, &value)| {
-                item.get(key).is_some_and(|field_value| match field_value {
-                    Value::String(s) => s == value,
-                    Value::Number(n) => value.parse::<i64>().ok() == n.as_i64(),
-                    _ => false,
-                })
-            }) {
-                filtered.push(entry);
-            }
-        }
-    }
-
+    filtered.extend(json_iter_array::<serde_json::Value, BufReader<File>>(reader).flatten().filter(|entry| json_filter_matches(entry, filter)));
     filtered
 }
 
+/// Same as `json_filter_file` for documents which are already loaded.
+pub fn json_filter_documents(documents: Vec<Value>, filter: &HashMap<&str, &str>) -> Vec<Value> {
+    documents.into_iter().filter(|entry| json_filter_matches(entry, filter)).collect()
+}
+
+fn json_filter_matches(entry: &Value, filter: &HashMap<&str, &str>) -> bool {
+    entry.as_object().is_some_and(|item| filter.iter().all(|(&key, &value)| {
+        item.get(key).is_some_and(|field_value| match field_value {
+            Value::String(s) => s == value,
+            Value::Number(n) => value.parse::<i64>().ok() == n.as_i64(),
+            _ => false,
+        })
+    }))
+}
+
 pub fn json_write_documents_to_file<T>(file: &Path, value: &T) -> Result<(), Error>
 where
     T: ?Sized + Serialize,
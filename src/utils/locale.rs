@@ -0,0 +1,54 @@
+use std::collections::HashMap;
+
+/// Locales are compared lowercase with `-` as separator, `pt_BR` and `pt-br` are the same locale.
+pub fn normalize_locale(locale: &str) -> String {
+    locale.trim().replace('_', "-").to_lowercase()
+}
+
+/// The languages of an `Accept-Language` header ordered by their quality, `*` and languages with `q=0` are skipped.
+pub fn parse_accept_language(header: &str) -> Vec<String> {
+    let mut languages: Vec<(String, f32)> = header.split(',')
+        .filter_map(|entry| {
+            let mut parts = entry.split(';');
+            let language = normalize_locale(parts.next()?);
+            let quality = parts.find_map(|param| param.trim().strip_prefix("q="))
+                .map_or(1.0, |quality| quality.trim().parse::<f32>().unwrap_or(0.0));
+            (!language.is_empty() && language != "*" && quality > 0.0).then_some((language, quality))
+        })
+        .collect();
+    // stable sort, languages with the same quality keep their order
+    languages.sort_by(|(_, q1), (_, q2)| q2.total_cmp(q1));
+    languages.into_iter().map(|(language, _)| language).collect()
+}
+
+/// The value of the first preferred locale, a locale with region falls back to the language (`de-at` to `de`).
+/// The keys of `available` are normalized locales.
+pub fn select_locale<'a, T>(available: &'a HashMap<String, T>, preferred: &[String]) -> Option<&'a T> {
+    preferred.iter().find_map(|locale| {
+        available.get(locale.as_str())
+            .or_else(|| locale.split_once('-').and_then(|(language, _)| available.get(language)))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::utils::locale::{normalize_locale, parse_accept_language, select_locale};
+
+    #[test]
+    fn accept_language_test() {
+        assert_eq!(parse_accept_language("fr-CH, fr;q=0.9, en;q=0.8, de;q=0.95, *;q=0.5"), vec!["fr-ch", "de", "fr", "en"]);
+        assert_eq!(parse_accept_language("en;q=0, pt_BR"), vec!["pt-br"]);
+        assert!(parse_accept_language("").is_empty());
+        assert_eq!(normalize_locale(" pt_BR "), "pt-br");
+    }
+
+    #[test]
+    fn select_locale_test() {
+        let available = HashMap::from([("de".to_string(), 1), ("pt-br".to_string(), 2)]);
+        assert_eq!(select_locale(&available, &["de-at".to_string()]), Some(&1));
+        assert_eq!(select_locale(&available, &["fr".to_string(), "pt-br".to_string()]), Some(&2));
+        assert_eq!(select_locale(&available, &["pt".to_string()]), None);
+    }
+}
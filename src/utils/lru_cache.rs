@@ -1,11 +1,33 @@
 use crate::repository::storage::hash_string_as_hex;
-use crate::utils::file_utils::traverse_dir;
 use crate::utils::size_utils::human_readable_byte_size;
 use async_std::sync::RwLock;
 use log::{debug, error, info, trace};
+use serde::Serialize;
 use std::collections::{HashMap, VecDeque};
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+const PART_FILE_SUFFIX: &str = ".part";
+
+/// Partial files not modified for this duration belong to aborted downloads.
+pub const STALE_PART_FILE_AGE: Duration = Duration::from_secs(600);
+
+/// Result of a cache scan.
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct CacheScanReport {
+    pub files: usize,
+    pub size: u64,
+    pub removed_partial: usize,
+    pub removed_orphaned: usize,
+    pub evicted: usize,
+    pub freed_bytes: u64,
+}
+
+/// Cache files are named by the hex hash of the resource url.
+fn is_cache_key(name: &str) -> bool {
+    name.len() == 64 && name.bytes().all(|c| c.is_ascii_hexdigit())
+}
 
 /// `LRUResourceCache`
 ///
@@ -20,6 +42,7 @@ use std::path::{Path, PathBuf};
 /// - `cache`: A `HashMap` that maps a unique key to a tuple containing the file path and its size.
 /// - `usage_order`: A `VecDeque` that tracks the access order of keys, with the oldest at the front.
 /// - `lock`: An `RwLock` to ensure thread-safe access to the cache during read and write operations.
+#[derive(Debug)]
 pub struct LRUResourceCache {
     capacity: usize,  // Maximum size in bytes
     cache_dir: PathBuf,
@@ -46,33 +69,68 @@ impl LRUResourceCache {
         }
     }
 
-    /// - Scans the cache directory and populates the internal data structures with existing files and their sizes.
-    /// - Updates the `current_size` and `usage_order` fields based on the scanned files.
-    ///   The use/access order is not restored!!!
-    pub async fn scan(&mut self) -> std::io::Result<()> {
-        let _write_lock = self.lock.write().await;
-        let mut visit = |entry: &std::fs::DirEntry, metadata: &std::fs::Metadata| {
-            let path = entry.path();
-            if let Some(file_name) = path.file_name() {
-                let key = String::from(file_name.to_string_lossy());
-                let file_size = usize::try_from(metadata.len()).unwrap_or(0);
-                // we need to duplicate because of closure we cant call insert_to_cache
-                {  // insert_to_cache
-                    let mut path = self.cache_dir.clone();
-                    path.push(&key);
-                    trace!("Added file to cache: {}", &path.to_string_lossy());
-                    self.cache.insert(key.clone(), (path.clone(), file_size));
-                    self.usage_order.push_back(key);
-                    self.current_size += file_size;
+    /// - Scans the cache directory and rebuilds the internal data structures from the existing files and their sizes.
+    /// - Partial files of downloads (`.part`) not modified within `stale_part_age`, files which are no cache entries
+    ///   and empty files are removed.
+    /// - The use/access order is restored from the modification time of the files, the oldest at the front.
+    /// - Evicts the least recently used files if the cache size exceeds the capacity.
+    pub async fn scan(&mut self, stale_part_age: Duration) -> std::io::Result<CacheScanReport> {
+        let mut report = CacheScanReport::default();
+        {
+            let _write_lock = self.lock.write().await;
+            self.cache.clear();
+            self.usage_order.clear();
+            self.current_size = 0;
+            let mut entries = vec![];
+            for entry in fs::read_dir(&self.cache_dir)?.flatten() {
+                let Ok(metadata) = entry.metadata() else { continue; };
+                if !metadata.is_file() {
+                    continue;
+                }
+                let path = entry.path();
+                let file_name = entry.file_name().to_string_lossy().to_string();
+                let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                if let Some(key) = file_name.strip_suffix(PART_FILE_SUFFIX) {
+                    let stale = modified.elapsed().map_or(true, |age| age >= stale_part_age);
+                    if stale || !is_cache_key(key) {
+                        Self::remove_file(&path, metadata.len(), &mut report.removed_partial, &mut report.freed_bytes);
+                    }
+                } else if !is_cache_key(&file_name) || metadata.len() == 0 {
+                    Self::remove_file(&path, metadata.len(), &mut report.removed_orphaned, &mut report.freed_bytes);
+                } else {
+                    entries.push((file_name, path, usize::try_from(metadata.len()).unwrap_or(0), modified));
                 }
             }
-        };
-        let result = traverse_dir(&self.cache_dir, &mut visit);
-        info!("Cache scanned, current size {} / {}", human_readable_byte_size(self.current_size as u64), human_readable_byte_size(self.capacity as u64));
-        result
+            entries.sort_by_key(|(_, _, _, modified)| *modified);
+            for (key, path, file_size, _) in entries {
+                trace!("Added file to cache: {}", &path.to_string_lossy());
+                self.cache.insert(key.clone(), (path, file_size));
+                self.usage_order.push_back(key);
+                self.current_size += file_size;
+            }
+        }
+        let (evicted, evicted_bytes) = self.evict_if_needed().await;
+        report.evicted = evicted;
+        report.freed_bytes += evicted_bytes;
+        report.files = self.cache.len();
+        report.size = self.current_size as u64;
+        info!("Cache scanned, current size {} / {}, removed {} partial and {} orphaned files, evicted {} files",
+            human_readable_byte_size(self.current_size as u64), human_readable_byte_size(self.capacity as u64),
+            report.removed_partial, report.removed_orphaned, report.evicted);
+        Ok(report)
     }
 
-    ///   - Adds a new file to the cache.
+    fn remove_file(path: &Path, size: u64, counter: &mut usize, freed_bytes: &mut u64) {
+        if let Err(err) = fs::remove_file(path) {
+            error!("Failed to delete cache file {} {err}", path.to_string_lossy());
+        } else {
+            debug!("Removed file from cache dir: {}", path.to_string_lossy());
+            *counter += 1;
+            *freed_bytes += size;
+        }
+    }
+
+    ///   - Adds a new file to the cache, the downloaded partial file is renamed to the cache file.
     ///   - Evicts the least recently used files if the cache size exceeds the capacity after the addition.
     ///   - Arguments:
     ///     - `url`: The unique identifier for the file.
@@ -81,6 +139,10 @@ impl LRUResourceCache {
     ///     - The `PathBuf` where the file is stored.
     pub async fn add_content(&mut self, url: &str, file_size: usize) -> std::io::Result<PathBuf> {
         let key = hash_string_as_hex(url);
+        let part_path = self.part_path(url);
+        if part_path.exists() {
+            fs::rename(&part_path, self.cache_dir.join(&key))?;
+        }
         let path = {
             self.insert_to_cache(key, file_size).await
         };
@@ -95,17 +157,22 @@ impl LRUResourceCache {
         let mut path = self.cache_dir.clone();
         path.push(&key);
         debug!("Added file to cache: {}", &path.to_string_lossy());
+        // a refetched resource replaces the entry
+        if let Some((_, size)) = self.cache.remove(&key) {
+            self.current_size -= size;
+            self.usage_order.retain(|k| k != &key);
+        }
         self.cache.insert(key.clone(), (path.clone(), file_size));
         self.usage_order.push_back(key);
         self.current_size += file_size;
         path
     }
 
-    pub fn store_path(&self, url: &str) -> PathBuf {
+    /// The path of the partial file a resource is downloaded to, `add_content` moves it into the cache.
+    /// Partial files of aborted downloads are removed by `scan`.
+    pub fn part_path(&self, url: &str) -> PathBuf {
         let key = hash_string_as_hex(url);
-        let mut path = self.cache_dir.clone();
-        path.push(&key);
-        path
+        self.cache_dir.join(format!("{key}{PART_FILE_SUFFIX}"))
     }
 
     ///   - Retrieves a file from the cache if it exists.
@@ -116,42 +183,82 @@ impl LRUResourceCache {
     ///     - The `PathBuf` of the file if it exists; `None` otherwise.
     pub async fn get_content(&mut self, url: &str) -> Option<PathBuf> {
         let key = hash_string_as_hex(url);
-        {
-            let _read_lock = self.lock.read().await;
-            if let Some((path, size)) = self.cache.get(&key) {
-                if path.exists() {
-                    // Move to the end of the queue
-                    self.usage_order.retain(|k| k != &key);   // remove from queue
-                    self.usage_order.push_back(key);  // add to the to end
-                    return Some(path.clone());
-                }
-                {
-                    // this should not happen, someone deleted the file manually and the cache is not in sync
-                    let _write_lock = self.lock.write().await;
-                    self.current_size -= size;
-                    self.cache.remove(&key);
-                    self.usage_order.retain(|k| k != &key);
-                }
+        let _write_lock = self.lock.write().await;
+        if let Some((path, size)) = self.cache.get(&key) {
+            if path.exists() {
+                let path = path.clone();
+                // Move to the end of the queue
+                self.usage_order.retain(|k| k != &key);   // remove from queue
+                self.usage_order.push_back(key);  // add to the to end
+                return Some(path);
             }
+            // this should not happen, someone deleted the file manually and the cache is not in sync
+            self.current_size -= size;
+            self.cache.remove(&key);
+            self.usage_order.retain(|k| k != &key);
         }
         None
     }
 
-    async fn evict_if_needed(&mut self) {
+    /// Returns the count and the size of the evicted files.
+    async fn evict_if_needed(&mut self) -> (usize, u64) {
         let _write_lock = self.lock.write().await;
+        let mut evicted = 0;
+        let mut evicted_bytes = 0;
         // if the cache size is to small and one element exceeds the size than the cache won't work, we ignore this
         while self.current_size > self.capacity {
-            if let Some(oldest_file) = self.usage_order.pop_front() {
-                if let Some((file, size)) = self.cache.remove(&oldest_file) {
-                    self.current_size -= size;
-                    if let Err(err) = fs::remove_file(&file) {
-                        error!("Failed to delete cached file {} {err}", file.to_string_lossy());
-                    } else {
-                        debug!("Removed file from cache: {}", file.to_string_lossy());
-                    }
+            let Some(oldest_file) = self.usage_order.pop_front() else { break; };
+            if let Some((file, size)) = self.cache.remove(&oldest_file) {
+                self.current_size -= size;
+                if let Err(err) = fs::remove_file(&file) {
+                    error!("Failed to delete cached file {} {err}", file.to_string_lossy());
+                } else {
+                    debug!("Removed file from cache: {}", file.to_string_lossy());
+                    evicted += 1;
+                    evicted_bytes += size as u64;
                 }
             }
         }
+        (evicted, evicted_bytes)
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::time::Duration;
+
+    use crate::repository::storage::hash_string_as_hex;
+    use crate::utils::lru_cache::LRUResourceCache;
+
+    #[test]
+    fn cache_scan_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let key_a = hash_string_as_hex("http://a");
+        let key_b = hash_string_as_hex("http://b");
+        fs::write(dir.path().join(&key_a), [0u8; 10]).unwrap();
+        fs::write(dir.path().join(&key_b), [0u8; 10]).unwrap();
+        fs::write(dir.path().join(format!("{}.part", hash_string_as_hex("http://c"))), [0u8; 5]).unwrap();
+        fs::write(dir.path().join("unknown.tmp"), [0u8; 3]).unwrap();
+        fs::write(dir.path().join(hash_string_as_hex("http://d")), []).unwrap();
+
+        let mut cache = LRUResourceCache::new(15, dir.path());
+        let (report, rescan_report) = async_std::task::block_on(async {
+            // a second scan must not count the files twice
+            (cache.scan(Duration::ZERO).await.unwrap(), cache.scan(Duration::ZERO).await.unwrap())
+        });
+        assert_eq!((report.removed_partial, report.removed_orphaned, report.evicted), (1, 2, 1));
+        assert_eq!((report.files, report.size), (1, 10));
+        assert_eq!((rescan_report.files, rescan_report.size, rescan_report.evicted), (1, 10, 0));
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+
+        let report = async_std::task::block_on(async {
+            fs::write(cache.part_path("http://e"), [0u8; 4]).unwrap();
+            cache.add_content("http://e", 4).await.unwrap();
+            assert!(cache.get_content("http://e").await.is_some());
+            cache.scan(Duration::from_secs(600)).await.unwrap()
+        });
+        assert_eq!((report.files, report.size, report.removed_partial), (2, 14, 0));
+    }
+}
@@ -0,0 +1,124 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{LazyLock, RwLock};
+
+use crate::model::stats::ProcessingSummary;
+
+const METRICS_PREFIX: &str = "m3u_filter";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetricType {
+    Counter,
+    Gauge,
+}
+
+impl MetricType {
+    const fn as_str(self) -> &'static str {
+        match self {
+            Self::Counter => "counter",
+            Self::Gauge => "gauge",
+        }
+    }
+}
+
+/// Duration and result of the last processing of a target.
+#[derive(Debug, Clone, Copy)]
+struct TargetProcessing {
+    secs_took: u64,
+    success: bool,
+}
+
+/// Counters which are updated by the streams, the resource cache and the processing.
+/// The gauges of the active connections are read from the server state when the metrics are requested.
+#[derive(Debug, Default)]
+struct Metrics {
+    stream_reconnects: AtomicU64,
+    cache_hits: AtomicU64,
+    cache_misses: AtomicU64,
+    processing_runs: AtomicU64,
+    targets: RwLock<BTreeMap<String, TargetProcessing>>,
+}
+
+static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::default);
+
+pub fn record_stream_reconnect() {
+    METRICS.stream_reconnects.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn record_cache_lookup(hit: bool) {
+    let counter = if hit { &METRICS.cache_hits } else { &METRICS.cache_misses };
+    counter.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn record_processing(summary: &ProcessingSummary) {
+    METRICS.processing_runs.fetch_add(1, Ordering::Relaxed);
+    if let Ok(mut targets) = METRICS.targets.write() {
+        for target in summary.sources.iter().flat_map(|source| &source.targets) {
+            targets.insert(target.name.clone(), TargetProcessing { secs_took: target.secs_took, success: target.success });
+        }
+    }
+}
+
+fn escape_label_value(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// Writes metrics in the prometheus text format.
+#[derive(Debug, Default)]
+pub struct MetricsWriter {
+    content: String,
+}
+
+impl MetricsWriter {
+    /// Writes a metric, the values are given as label value and value, an empty label writes the value without labels.
+    pub fn write<V: std::fmt::Display>(&mut self, name: &str, help: &str, metric_type: MetricType, label: &str, values: impl IntoIterator<Item=(String, V)>) {
+        let _ = writeln!(self.content, "# HELP {METRICS_PREFIX}_{name} {help}");
+        let _ = writeln!(self.content, "# TYPE {METRICS_PREFIX}_{name} {}", metric_type.as_str());
+        for (label_value, value) in values {
+            if label.is_empty() {
+                let _ = writeln!(self.content, "{METRICS_PREFIX}_{name} {value}");
+            } else {
+                let _ = writeln!(self.content, "{METRICS_PREFIX}_{name}{{{label}=\"{}\"}} {value}", escape_label_value(&label_value));
+            }
+        }
+    }
+
+    /// Writes the counters of the streams, the cache and the processing.
+    pub fn write_counters(&mut self) {
+        let counter = |value: &AtomicU64| [(String::new(), value.load(Ordering::Relaxed))];
+        self.write("stream_reconnects_total", "Reconnects of provider streams.", MetricType::Counter, "", counter(&METRICS.stream_reconnects));
+        self.write("cache_hits_total", "Resources served from the resource cache.", MetricType::Counter, "", counter(&METRICS.cache_hits));
+        self.write("cache_misses_total", "Resources not found in the resource cache.", MetricType::Counter, "", counter(&METRICS.cache_misses));
+        self.write("processing_runs_total", "Finished playlist processing runs.", MetricType::Counter, "", counter(&METRICS.processing_runs));
+        let targets = METRICS.targets.read().map(|targets| targets.clone()).unwrap_or_default();
+        self.write("processing_duration_seconds", "Duration of the last processing of the target.", MetricType::Gauge, "target",
+                   targets.iter().map(|(name, processing)| (name.clone(), processing.secs_took)));
+        self.write("processing_success", "Result of the last processing of the target, 1 for success.", MetricType::Gauge, "target",
+                   targets.iter().map(|(name, processing)| (name.clone(), u8::from(processing.success))));
+    }
+
+    pub fn finish(self) -> String {
+        self.content
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::metrics::{MetricType, MetricsWriter};
+
+    #[test]
+    fn metrics_writer_test() {
+        let mut writer = MetricsWriter::default();
+        writer.write("client_connections", "Active client streams per user.", MetricType::Gauge, "user",
+                     [("max".to_string(), 2), ("a\"b".to_string(), 1)]);
+        writer.write("stream_reconnects_total", "Reconnects.", MetricType::Counter, "", [(String::new(), 5)]);
+        assert_eq!(writer.finish(), "# HELP m3u_filter_client_connections Active client streams per user.\n\
+            # TYPE m3u_filter_client_connections gauge\n\
+            m3u_filter_client_connections{user=\"max\"} 2\n\
+            m3u_filter_client_connections{user=\"a\\\"b\"} 1\n\
+            # HELP m3u_filter_stream_reconnects_total Reconnects.\n\
+            # TYPE m3u_filter_stream_reconnects_total counter\n\
+            m3u_filter_stream_reconnects_total 5\n");
+    }
+}
@@ -14,6 +14,20 @@ pub mod lru_cache;
 pub mod size_utils;
 pub mod sys;
 pub mod atomic_once_flag;
+pub mod step_measure;
+pub mod progress;
+pub mod sanitize;
+pub mod secret_resolver;
+pub mod server_name_resolver;
+pub mod bandwidth_limiter;
+pub mod rate_limit;
+pub mod request_coalescer;
+pub mod request_limiter;
+pub mod preflight;
+pub mod disk_space;
+pub mod output_encoding;
+pub mod metrics;
+pub mod locale;
 
 #[macro_export]
 macro_rules! debug_if_enabled {
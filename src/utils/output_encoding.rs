@@ -0,0 +1,71 @@
+use std::borrow::Cow;
+
+use crate::model::config::{NewlineStyle, OutputEncoding};
+
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+fn is_invalid_control(c: char) -> bool {
+    c.is_control() && !matches!(c, '\t' | '\n' | '\r')
+}
+
+/// Removes the control characters of provider texts, tabs and line breaks are kept.
+pub fn sanitize_control_chars(text: &str) -> Cow<'_, str> {
+    if text.chars().any(is_invalid_control) {
+        Cow::Owned(text.chars().filter(|c| !is_invalid_control(*c)).collect())
+    } else {
+        Cow::Borrowed(text)
+    }
+}
+
+/// Removes the control characters of a single line value, tabs and line breaks are replaced with a space.
+pub fn sanitize_line(text: &str) -> Cow<'_, str> {
+    if text.chars().any(char::is_control) {
+        Cow::Owned(text.chars().filter(|c| !is_invalid_control(*c))
+            .map(|c| if c.is_control() { ' ' } else { c }).collect())
+    } else {
+        Cow::Borrowed(text)
+    }
+}
+
+pub fn get_bom(encoding: Option<&OutputEncoding>) -> &'static [u8] {
+    if encoding.is_some_and(|encoding| encoding.bom) { UTF8_BOM } else { b"" }
+}
+
+pub fn get_newline(encoding: Option<&OutputEncoding>) -> &'static str {
+    match encoding.map(|encoding| encoding.newline).unwrap_or_default() {
+        NewlineStyle::Lf => "\n",
+        NewlineStyle::Crlf => "\r\n",
+    }
+}
+
+/// Normalizes the line breaks of the text to the configured newline style.
+pub fn apply_newline_style<'a>(text: &'a str, encoding: Option<&OutputEncoding>) -> Cow<'a, str> {
+    let newline = get_newline(encoding);
+    let has_cr = text.contains('\r');
+    if !has_cr && (newline == "\n" || !text.contains('\n')) {
+        return Cow::Borrowed(text);
+    }
+    let normalized = if has_cr { text.replace("\r\n", "\n").replace('\r', "\n") } else { text.to_string() };
+    if newline == "\n" {
+        Cow::Owned(normalized)
+    } else {
+        Cow::Owned(normalized.replace('\n', newline))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::config::{NewlineStyle, OutputEncoding};
+    use crate::utils::output_encoding::{apply_newline_style, get_bom, sanitize_control_chars, sanitize_line};
+
+    #[test]
+    fn output_encoding_test() {
+        assert_eq!(sanitize_control_chars("News\u{0}\u{1b}[1m\tDaily\n\u{85}"), "News[1m\tDaily\n");
+        assert_eq!(sanitize_line("Sports\r\nHD\u{7}"), "Sports  HD");
+        let crlf = OutputEncoding { bom: true, newline: NewlineStyle::Crlf };
+        assert_eq!(apply_newline_style("a\nb\r\nc\rd", Some(&crlf)), "a\r\nb\r\nc\r\nd");
+        assert_eq!(apply_newline_style("a\r\nb", None), "a\nb");
+        assert_eq!(get_bom(Some(&crlf)), b"\xEF\xBB\xBF");
+        assert!(get_bom(None).is_empty());
+    }
+}
@@ -0,0 +1,317 @@
+use std::fmt::{Display, Formatter};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use log::{info, warn};
+use tokio::net::{lookup_host, TcpStream};
+use tokio::time::timeout;
+use url::Url;
+
+use crate::model::config::{Config, ConfigInput, ConfigInputPreflight, PreflightFailureAction};
+use crate::utils::request_utils::mask_sensitive_info;
+
+/// Last result of the preflight checks of an input, `None` if the checks passed.
+/// A simulated outage fails the checks until it ends, to test the failover without breaking the provider.
+#[derive(Debug, Default)]
+pub struct PreflightState {
+    last_check: Mutex<Option<(Instant, Option<String>)>>,
+    simulated_outage: Mutex<Option<i64>>,
+}
+
+impl PreflightState {
+    /// Starts or extends a simulated outage until the timestamp.
+    pub fn start_simulated_outage(&self, until: i64) {
+        if let Ok(mut outage) = self.simulated_outage.lock() {
+            *outage = Some(until);
+        }
+    }
+
+    /// Ends the simulated outage, returns `true` if the input was down.
+    pub fn end_simulated_outage(&self) -> bool {
+        self.simulated_outage.lock().ok().and_then(|mut outage| outage.take()).is_some()
+    }
+
+    /// Ends the simulated outage if it is expired at `now`, returns `true` if it ended.
+    pub fn end_expired_simulated_outage(&self, now: i64) -> bool {
+        let Ok(mut outage) = self.simulated_outage.lock() else { return false; };
+        if outage.is_some_and(|until| until <= now) {
+            *outage = None;
+            return true;
+        }
+        false
+    }
+
+    /// The end of the simulated outage if the input is down at `now`.
+    pub fn get_simulated_outage(&self, now: i64) -> Option<i64> {
+        self.simulated_outage.lock().ok()?.filter(|until| *until > now)
+    }
+
+    fn get_result(&self, max_age: Duration) -> Option<Result<(), String>> {
+        let last_check = self.last_check.lock().ok()?;
+        last_check.as_ref()
+            .filter(|(checked, _)| checked.elapsed() < max_age)
+            .map(|(_, failure)| failure.clone().map_or(Ok(()), Err))
+    }
+
+    /// Stores the result, returns `true` if the result changed.
+    fn set_result(&self, result: &Result<(), String>) -> bool {
+        let Ok(mut last_check) = self.last_check.lock() else { return false; };
+        let failure = result.as_ref().err().cloned();
+        let changed = last_check.as_ref().is_none_or(|(_, last_failure)| last_failure.is_some() != failure.is_some());
+        *last_check = Some((Instant::now(), failure));
+        changed
+    }
+}
+
+async fn check_dns(host: &str, duration: Duration) -> Result<(), String> {
+    match timeout(duration, lookup_host((host, 0))).await {
+        Ok(Ok(mut addresses)) => addresses.next().map(|_| ()).ok_or_else(|| format!("dns lookup of {host} returned no address")),
+        Ok(Err(err)) => Err(format!("dns lookup of {host} failed: {err}")),
+        Err(_) => Err(format!("dns lookup of {host} timed out")),
+    }
+}
+
+async fn check_host(host: &str, duration: Duration) -> Result<(), String> {
+    match timeout(duration, TcpStream::connect(host)).await {
+        Ok(Ok(_)) => Ok(()),
+        Ok(Err(err)) => Err(format!("{host} is not reachable: {err}")),
+        Err(_) => Err(format!("connect to {host} timed out")),
+    }
+}
+
+async fn run_preflight(preflight: &ConfigInputPreflight) -> Result<(), String> {
+    let duration = Duration::from_secs(preflight.timeout_secs);
+    for host in &preflight.dns {
+        check_dns(host, duration).await?;
+    }
+    for host in &preflight.hosts {
+        check_host(host, duration).await?;
+    }
+    Ok(())
+}
+
+/// Runs the preflight checks of the input. With `cached` the last result is used within the `interval_secs`.
+pub async fn check_input_preflight(input: &ConfigInput, cached: bool) -> Result<(), String> {
+    if let Some(until) = input.t_preflight.get_simulated_outage(chrono::Utc::now().timestamp()) {
+        return Err(format!("simulated outage until {}", chrono::DateTime::from_timestamp(until, 0).unwrap_or_default().to_rfc3339()));
+    }
+    let Some(preflight) = input.preflight.as_ref() else { return Ok(()); };
+    if cached {
+        if let Some(result) = input.t_preflight.get_result(Duration::from_secs(preflight.interval_secs)) {
+            return result;
+        }
+    }
+    let result = run_preflight(preflight).await;
+    if input.t_preflight.set_result(&result) {
+        let input_name = input.name.as_ref().map_or_else(|| mask_sensitive_info(&input.url), ToString::to_string);
+        match &result {
+            Ok(()) => info!("Preflight checks of input {input_name} passed"),
+            Err(err) => warn!("Preflight checks of input {input_name} failed: {err}"),
+        }
+    }
+    result
+}
+
+/// The input to request after the preflight checks, see `PreflightFailureAction`.
+pub enum PreflightOutcome<'a> {
+    Use(&'a ConfigInput),
+    /// The checks of the input failed and the checks of the fallback input passed.
+    Fallback(&'a ConfigInput, String),
+    Skip(String),
+    Fail(String),
+}
+
+/// Inputs with failed preflight checks are not requested, no request leaves e.g. a vpn which is down.
+/// The fallback input is only used if its own checks pass.
+/// Inputs without preflight config fail only during a simulated outage and are skipped.
+pub async fn apply_input_preflight<'a>(cfg: &'a Config, input: &'a ConfigInput, cached: bool) -> PreflightOutcome<'a> {
+    let Err(err) = check_input_preflight(input, cached).await else { return PreflightOutcome::Use(input); };
+    let Some(preflight) = input.preflight.as_ref() else { return PreflightOutcome::Skip(err); };
+    match preflight.on_failure {
+        PreflightFailureAction::Skip => PreflightOutcome::Skip(err),
+        PreflightFailureAction::Fail => PreflightOutcome::Fail(err),
+        PreflightFailureAction::Fallback => match preflight.fallback.as_ref().and_then(|fallback| cfg.get_input_by_name(fallback)) {
+            None => PreflightOutcome::Skip(err),
+            Some(fallback) => match check_input_preflight(fallback, cached).await {
+                Ok(()) => PreflightOutcome::Fallback(fallback, err),
+                Err(fallback_err) => PreflightOutcome::Skip(format!("{err}, fallback input {}: {fallback_err}", fallback.name.as_deref().unwrap_or_default())),
+            },
+        },
+    }
+}
+
+/// Rewrites a provider url of the input to the fallback input, the host and the credentials are replaced.
+/// Urls of other hosts, e.g. logos, are kept.
+pub fn get_fallback_url(url: &str, input: &ConfigInput, fallback: &ConfigInput) -> Option<String> {
+    let mut url = Url::parse(url).ok()?;
+    let input_url = Url::parse(&input.url).ok()?;
+    if url.origin() != input_url.origin() {
+        return Some(url.to_string());
+    }
+    let fallback_url = Url::parse(&fallback.url).ok()?;
+    url.set_scheme(fallback_url.scheme()).ok()?;
+    url.set_host(fallback_url.host_str()).ok()?;
+    url.set_port(fallback_url.port()).ok()?;
+    if let (Some(username), Some(password), Some(fallback_username), Some(fallback_password))
+        = (input.username.as_deref(), input.password.as_deref(), fallback.username.as_deref(), fallback.password.as_deref()) {
+        let mut segments: Vec<String> = url.path_segments()?.map(ToString::to_string).collect();
+        if let Some(pos) = segments.windows(2).position(|pair| pair[0] == username && pair[1] == password) {
+            segments[pos] = fallback_username.to_string();
+            segments[pos + 1] = fallback_password.to_string();
+            url.path_segments_mut().ok()?.clear().extend(&segments);
+        }
+        if url.query().is_some() {
+            let pairs: Vec<(String, String)> = url.query_pairs().map(|(key, value)| {
+                let value = match key.as_ref() {
+                    "username" if value == username => fallback_username.to_string(),
+                    "password" if value == password => fallback_password.to_string(),
+                    _ => value.to_string(),
+                };
+                (key.to_string(), value)
+            }).collect();
+            url.query_pairs_mut().clear().extend_pairs(pairs);
+        }
+    }
+    Some(url.to_string())
+}
+
+/// Why no request leaves for an input.
+#[derive(Debug)]
+pub enum ProviderRequestDenied {
+    RateLimit(Duration),
+    Unreachable(String),
+}
+
+impl Display for ProviderRequestDenied {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::RateLimit(wait) => write!(f, "Provider rate limit, retry in {}s", wait.as_secs() + 1),
+            Self::Unreachable(err) => write!(f, "Provider unreachable: {err}"),
+        }
+    }
+}
+
+/// Every provider request of the api (streams, HEAD probes, info, epg and resources) is checked here.
+/// Returns the input and url to request, which are the fallback input and the rewritten url
+/// if the preflight checks failed and the input is configured with `on_failure: fallback`.
+pub async fn guard_provider_request<'a>(cfg: &'a Config, input: &'a ConfigInput, url: &str) -> Result<(&'a ConfigInput, String), ProviderRequestDenied> {
+    let (request_input, request_url) = match apply_input_preflight(cfg, input, true).await {
+        PreflightOutcome::Use(input) => (input, url.to_string()),
+        PreflightOutcome::Fallback(fallback, err) => {
+            let fallback_url = get_fallback_url(url, input, fallback).ok_or(ProviderRequestDenied::Unreachable(err))?;
+            (fallback, fallback_url)
+        }
+        PreflightOutcome::Skip(err) | PreflightOutcome::Fail(err) => return Err(ProviderRequestDenied::Unreachable(err)),
+    };
+    // the provider is not requested until the rate limit delay is over
+    let wait = request_input.t_rate_limit.get_remaining_wait();
+    if !wait.is_zero() {
+        return Err(ProviderRequestDenied::RateLimit(wait));
+    }
+    Ok((request_input, request_url))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::model::config::{Config, ConfigInput, ConfigInputPreflight, ConfigSource, PreflightFailureAction};
+    use crate::utils::preflight::{apply_input_preflight, get_fallback_url, guard_provider_request, run_preflight, PreflightOutcome, PreflightState, ProviderRequestDenied};
+
+    fn input(id: u16, name: &str, url: &str, on_failure: PreflightFailureAction) -> ConfigInput {
+        ConfigInput {
+            id,
+            name: Some(name.to_string()),
+            url: url.to_string(),
+            username: Some(format!("{name}_user")),
+            password: Some(format!("{name}_pass")),
+            preflight: Some(ConfigInputPreflight {
+                dns: vec![],
+                hosts: vec![],
+                timeout_secs: 1,
+                interval_secs: 60,
+                on_failure,
+                fallback: Some("backup".to_string()),
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[actix_rt::test]
+    async fn preflight_test() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let mut preflight = ConfigInputPreflight {
+            dns: vec!["localhost".to_string()],
+            hosts: vec![address],
+            timeout_secs: 2,
+            interval_secs: 60,
+            on_failure: PreflightFailureAction::Skip,
+            fallback: None,
+        };
+        assert!(run_preflight(&preflight).await.is_ok());
+        drop(listener);
+        preflight.hosts = vec!["127.0.0.1:1".to_string()];
+        assert!(run_preflight(&preflight).await.is_err());
+
+        let state = PreflightState::default();
+        assert!(state.get_result(Duration::from_secs(60)).is_none());
+        assert!(state.set_result(&Err("down".to_string())));
+        assert!(!state.set_result(&Err("still down".to_string())));
+        assert_eq!(state.get_result(Duration::from_secs(60)), Some(Err("still down".to_string())));
+        assert!(state.get_result(Duration::ZERO).is_none());
+
+        state.start_simulated_outage(1000);
+        assert_eq!(state.get_simulated_outage(999), Some(1000));
+        assert!(state.get_simulated_outage(1000).is_none());
+        assert!(!state.end_expired_simulated_outage(999));
+        assert!(state.end_expired_simulated_outage(1000));
+        assert!(!state.end_simulated_outage());
+    }
+
+    #[test]
+    fn get_fallback_url_test() {
+        let primary = input(1, "primary", "http://primary.tv:8080", PreflightFailureAction::Fallback);
+        let backup = input(2, "backup", "https://backup.tv", PreflightFailureAction::Skip);
+        assert_eq!(get_fallback_url("http://primary.tv:8080/live/primary_user/primary_pass/12.ts", &primary, &backup).as_deref(),
+                   Some("https://backup.tv/live/backup_user/backup_pass/12.ts"));
+        assert_eq!(get_fallback_url("http://primary.tv:8080/player_api.php?username=primary_user&password=primary_pass&action=get_vod_info", &primary, &backup).as_deref(),
+                   Some("https://backup.tv/player_api.php?username=backup_user&password=backup_pass&action=get_vod_info"));
+        assert_eq!(get_fallback_url("http://logos.tv/primary_user/primary_pass/1.png", &primary, &backup).as_deref(),
+                   Some("http://logos.tv/primary_user/primary_pass/1.png"));
+    }
+
+    #[actix_rt::test]
+    async fn guard_provider_request_test() {
+        let primary = input(1, "primary", "http://primary.tv", PreflightFailureAction::Fallback);
+        let backup = input(2, "backup", "http://backup.tv", PreflightFailureAction::Skip);
+        let cfg = Config {
+            sources: vec![ConfigSource { inputs: vec![primary, backup], targets: vec![] }],
+            ..Config::default()
+        };
+        let primary = &cfg.sources[0].inputs[0];
+        let backup = &cfg.sources[0].inputs[1];
+        let url = "http://primary.tv/live/primary_user/primary_pass/1.ts";
+
+        let (request_input, request_url) = guard_provider_request(&cfg, primary, url).await.unwrap();
+        assert_eq!(request_input.id, 1);
+        assert_eq!(request_url, url);
+
+        let until = chrono::Utc::now().timestamp() + 60;
+        primary.t_preflight.start_simulated_outage(until);
+        assert!(matches!(apply_input_preflight(&cfg, primary, true).await, PreflightOutcome::Fallback(fallback, _) if fallback.id == 2));
+        let (request_input, request_url) = guard_provider_request(&cfg, primary, url).await.unwrap();
+        assert_eq!(request_input.id, 2);
+        assert_eq!(request_url, "http://backup.tv/live/backup_user/backup_pass/1.ts");
+
+        // the fallback input is checked too
+        backup.t_preflight.start_simulated_outage(until);
+        assert!(matches!(apply_input_preflight(&cfg, primary, true).await, PreflightOutcome::Skip(_)));
+        assert!(matches!(guard_provider_request(&cfg, primary, url).await, Err(ProviderRequestDenied::Unreachable(_))));
+        primary.t_preflight.end_simulated_outage();
+        backup.t_preflight.end_simulated_outage();
+
+        primary.t_rate_limit.record_rate_limited(Some(30));
+        assert!(matches!(guard_provider_request(&cfg, primary, url).await, Err(ProviderRequestDenied::RateLimit(_))));
+    }
+}
@@ -0,0 +1,84 @@
+use std::io::Write;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+// the progress line is redrawn at most every interval
+const RENDER_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Progress of the cli processing, rendered as one status line on stderr.
+/// Each input download and target has an entry, finished entries are printed as own line.
+#[derive(Default)]
+struct CliProgress {
+    // (key, status) in order of appearance
+    entries: Vec<(String, String)>,
+    last_render: Option<Instant>,
+}
+
+static PROGRESS: OnceLock<Mutex<CliProgress>> = OnceLock::new();
+
+pub fn enable_progress() {
+    let _ = PROGRESS.set(Mutex::new(CliProgress::default()));
+}
+
+pub fn is_progress_enabled() -> bool {
+    PROGRESS.get().is_some()
+}
+
+fn format_line(entries: &[(String, String)]) -> String {
+    entries.iter().map(|(key, status)| format!("{key}: {status}")).collect::<Vec<_>>().join(" | ")
+}
+
+fn render(entries: &[(String, String)]) {
+    let mut stderr = std::io::stderr().lock();
+    let _ = write!(stderr, "\r\x1b[2K{}", format_line(entries));
+    let _ = stderr.flush();
+}
+
+pub fn update_progress(key: &str, status: String) {
+    let Some(progress) = PROGRESS.get() else { return; };
+    let Ok(mut guard) = progress.lock() else { return; };
+    let CliProgress { entries, last_render } = &mut *guard;
+    match entries.iter_mut().find(|(entry_key, _)| entry_key == key) {
+        Some(entry) => entry.1 = status,
+        None => entries.push((key.to_string(), status)),
+    }
+    if last_render.is_none_or(|ts| ts.elapsed() >= RENDER_INTERVAL) {
+        *last_render = Some(Instant::now());
+        render(entries);
+    }
+}
+
+pub fn finish_progress(key: &str, status: &str) {
+    let Some(progress) = PROGRESS.get() else { return; };
+    let Ok(mut guard) = progress.lock() else { return; };
+    let entries = &mut guard.entries;
+    entries.retain(|(entry_key, _)| entry_key != key);
+    let mut stderr = std::io::stderr().lock();
+    let _ = writeln!(stderr, "\r\x1b[2K{key}: {status}");
+    if !entries.is_empty() {
+        let _ = write!(stderr, "{}", format_line(entries));
+    }
+    let _ = stderr.flush();
+}
+
+pub fn update_download_progress(name: &str, received: u64, total: Option<u64>) {
+    if !is_progress_enabled() {
+        return;
+    }
+    let status = match total.filter(|total| *total > 0) {
+        Some(total) => format!("downloading {}%", received.saturating_mul(100) / total),
+        None => format!("downloading {} KB", received / 1024),
+    };
+    update_progress(name, status);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::progress::format_line;
+
+    #[test]
+    fn format_line_test() {
+        let entries = vec![("provider".to_string(), "downloading 50%".to_string()), ("target".to_string(), "1000 items".to_string())];
+        assert_eq!(format_line(&entries), "provider: downloading 50% | target: 1000 items");
+    }
+}
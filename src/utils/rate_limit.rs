@@ -0,0 +1,155 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use reqwest::StatusCode;
+use serde::Serialize;
+
+// delay after the first rate limit response
+const MIN_RATE_LIMIT_DELAY_SECS: u64 = 2;
+const MAX_RATE_LIMIT_DELAY_SECS: u64 = 600;
+
+/// Rate limit state of a provider, reported in the input health.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RateLimitState {
+    /// Seconds between two provider requests.
+    pub delay_secs: u64,
+    /// Rate limit responses since the input was last without delay.
+    pub hits: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_hit: Option<i64>,
+    /// The provider asked to retry after this time (`Retry-After`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry_after: Option<i64>,
+}
+
+#[derive(Debug, Default)]
+struct RateLimit {
+    state: RateLimitState,
+    next_request: Option<Instant>,
+}
+
+/// Adaptive delay between the requests of an input after the provider responded with `429 Too Many Requests`.
+/// Each rate limit response doubles the delay, each successful request halves it until the delay is gone.
+#[derive(Debug, Default)]
+pub struct ProviderRateLimit {
+    inner: Mutex<RateLimit>,
+}
+
+/// `429` or `503` with `Retry-After` are rate limit responses, some providers use `503` for temporary bans.
+pub fn is_rate_limit_response(status: StatusCode, retry_after: Option<u64>) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || (status == StatusCode::SERVICE_UNAVAILABLE && retry_after.is_some())
+}
+
+/// Parses the `Retry-After` header, seconds or a http date.
+pub fn parse_retry_after(value: &str) -> Option<u64> {
+    let value = value.trim();
+    value.parse::<u64>().ok().or_else(|| chrono::DateTime::parse_from_rfc2822(value).ok()
+        .map(|date| u64::try_from(date.timestamp() - chrono::Utc::now().timestamp()).unwrap_or(0)))
+}
+
+impl ProviderRateLimit {
+    /// Records a rate limit response and returns the new delay, `true` if the input was not rate limited before.
+    pub fn record_rate_limited(&self, retry_after: Option<u64>) -> (Duration, bool) {
+        self.record_rate_limited_at(retry_after, Instant::now(), chrono::Utc::now().timestamp())
+    }
+
+    fn record_rate_limited_at(&self, retry_after: Option<u64>, now: Instant, timestamp: i64) -> (Duration, bool) {
+        let Ok(mut rate_limit) = self.inner.lock() else { return (Duration::ZERO, false) };
+        let started = rate_limit.state.delay_secs == 0;
+        let backoff = if started { MIN_RATE_LIMIT_DELAY_SECS } else { rate_limit.state.delay_secs * 2 };
+        let delay_secs = backoff.max(retry_after.unwrap_or(0)).min(MAX_RATE_LIMIT_DELAY_SECS);
+        rate_limit.state.delay_secs = delay_secs;
+        rate_limit.state.hits += 1;
+        rate_limit.state.last_hit = Some(timestamp);
+        rate_limit.state.retry_after = retry_after.map(|secs| timestamp + i64::try_from(secs).unwrap_or(i64::MAX - timestamp));
+        rate_limit.next_request = Some(now + Duration::from_secs(delay_secs));
+        (Duration::from_secs(delay_secs), started)
+    }
+
+    /// Halves the delay after a successful request, returns `true` if the input is no longer rate limited.
+    pub fn record_success(&self) -> bool {
+        let Ok(mut rate_limit) = self.inner.lock() else { return false };
+        if rate_limit.state.delay_secs == 0 {
+            return false;
+        }
+        rate_limit.state.delay_secs /= 2;
+        if rate_limit.state.delay_secs < MIN_RATE_LIMIT_DELAY_SECS {
+            *rate_limit = RateLimit::default();
+            return true;
+        }
+        false
+    }
+
+    /// Reserves the next request slot and returns the time to wait.
+    fn reserve(&self, now: Instant) -> Duration {
+        let Ok(mut rate_limit) = self.inner.lock() else { return Duration::ZERO };
+        if rate_limit.state.delay_secs == 0 {
+            return Duration::ZERO;
+        }
+        let start = rate_limit.next_request.filter(|next| *next > now).unwrap_or(now);
+        rate_limit.next_request = Some(start + Duration::from_secs(rate_limit.state.delay_secs));
+        start - now
+    }
+
+    /// Waits for the next request slot if the input is rate limited.
+    pub async fn wait(&self) {
+        let wait = self.reserve(Instant::now());
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Time until the provider accepts requests again, without reserving a request slot.
+    pub fn get_remaining_wait(&self) -> Duration {
+        let now = Instant::now();
+        self.inner.lock().ok()
+            .and_then(|rate_limit| rate_limit.next_request)
+            .filter(|next| *next > now)
+            .map_or(Duration::ZERO, |next| next - now)
+    }
+
+    pub fn get_state(&self) -> Option<RateLimitState> {
+        self.inner.lock().ok().map(|rate_limit| rate_limit.state.clone()).filter(|state| state.delay_secs > 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use reqwest::StatusCode;
+
+    use crate::utils::rate_limit::{is_rate_limit_response, parse_retry_after, ProviderRateLimit};
+
+    #[test]
+    fn rate_limit_test() {
+        let rate_limit = ProviderRateLimit::default();
+        let now = Instant::now();
+        assert_eq!(rate_limit.reserve(now), Duration::ZERO);
+        assert_eq!(rate_limit.record_rate_limited_at(None, now, 1000), (Duration::from_secs(2), true));
+        assert_eq!(rate_limit.record_rate_limited_at(None, now, 1000), (Duration::from_secs(4), false));
+        // the next request waits for the delay, the following ones are spaced by the delay
+        assert_eq!(rate_limit.reserve(now), Duration::from_secs(4));
+        assert_eq!(rate_limit.reserve(now), Duration::from_secs(8));
+        // retry after is respected
+        assert_eq!(rate_limit.record_rate_limited_at(Some(30), now, 1000), (Duration::from_secs(30), false));
+        assert_eq!(rate_limit.get_state().unwrap().retry_after, Some(1030));
+        assert_eq!(rate_limit.get_state().unwrap().hits, 3);
+        assert!(!rate_limit.record_success());
+        assert!(!rate_limit.record_success());
+        assert!(!rate_limit.record_success());
+        assert!(rate_limit.record_success());
+        assert!(rate_limit.get_state().is_none());
+        assert_eq!(rate_limit.reserve(now), Duration::ZERO);
+    }
+
+    #[test]
+    fn rate_limit_response_test() {
+        assert_eq!(parse_retry_after(" 120 "), Some(120));
+        assert_eq!(parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"), Some(0));
+        assert_eq!(parse_retry_after("soon"), None);
+        assert!(is_rate_limit_response(StatusCode::TOO_MANY_REQUESTS, None));
+        assert!(is_rate_limit_response(StatusCode::SERVICE_UNAVAILABLE, Some(10)));
+        assert!(!is_rate_limit_response(StatusCode::SERVICE_UNAVAILABLE, None));
+    }
+}
@@ -0,0 +1,68 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::OnceCell;
+
+type InFlightRequest = Arc<OnceCell<Result<String, String>>>;
+
+/// Concurrent requests with the same key share one provider request and its response.
+/// The response is only shared while the request is in flight, following requests start a new request.
+#[derive(Debug, Default)]
+pub struct RequestCoalescer {
+    in_flight: Mutex<HashMap<String, InFlightRequest>>,
+}
+
+impl RequestCoalescer {
+    pub async fn request<F>(&self, key: &str, request: F) -> Result<String, String>
+    where
+        F: Future<Output=Result<String, String>>,
+    {
+        let cell = self.in_flight.lock().ok().map(|mut in_flight| Arc::clone(in_flight.entry(key.to_string()).or_default()));
+        let Some(cell) = cell else { return request.await; };
+        // if the requesting client disconnects, one of the waiting requests takes over
+        let result = cell.get_or_init(|| request).await.clone();
+        if let Ok(mut in_flight) = self.in_flight.lock() {
+            if in_flight.get(key).is_some_and(|current| Arc::ptr_eq(current, &cell)) {
+                in_flight.remove(key);
+            }
+        }
+        result
+    }
+
+    #[cfg(test)]
+    fn in_flight_count(&self) -> usize {
+        self.in_flight.lock().map_or(0, |in_flight| in_flight.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    use crate::utils::request_coalescer::RequestCoalescer;
+
+    #[actix_rt::test]
+    async fn request_coalescer_test() {
+        let coalescer = RequestCoalescer::default();
+        let requests = AtomicUsize::new(0);
+        let request = || async {
+            requests.fetch_add(1, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            Ok("info".to_string())
+        };
+        let (first, second, other) = futures::join!(
+            coalescer.request("vod_1", request()),
+            coalescer.request("vod_1", request()),
+            coalescer.request("vod_2", request()));
+        assert_eq!(first.as_deref(), Ok("info"));
+        assert_eq!(second.as_deref(), Ok("info"));
+        assert!(other.is_ok());
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+        assert_eq!(coalescer.in_flight_count(), 0);
+        // finished requests are not cached
+        assert!(coalescer.request("vod_1", request()).await.is_ok());
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+    }
+}
@@ -0,0 +1,64 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::model::config::PlaylistLimitConfig;
+
+/// Limits the concurrent playlist generations. Requests beyond the limit wait in a small queue,
+/// requests which don't fit into the queue or wait too long are rejected.
+#[derive(Debug)]
+pub struct RequestLimiter {
+    semaphore: Arc<Semaphore>,
+    queued: AtomicUsize,
+    queue_size: usize,
+    queue_timeout: Duration,
+}
+
+impl RequestLimiter {
+    pub fn new(config: &PlaylistLimitConfig) -> Self {
+        Self {
+            semaphore: Arc::new(Semaphore::new(usize::from(config.max_concurrent.max(1)))),
+            queued: AtomicUsize::new(0),
+            queue_size: usize::from(config.queue),
+            queue_timeout: Duration::from_secs(config.queue_timeout_secs),
+        }
+    }
+
+    /// The permit is held until the playlist is sent, `None` if the request is rejected.
+    pub async fn acquire(&self) -> Option<OwnedSemaphorePermit> {
+        if let Ok(permit) = Arc::clone(&self.semaphore).try_acquire_owned() {
+            return Some(permit);
+        }
+        if self.queued.fetch_add(1, Ordering::SeqCst) >= self.queue_size {
+            self.queued.fetch_sub(1, Ordering::SeqCst);
+            return None;
+        }
+        let permit = tokio::time::timeout(self.queue_timeout, Arc::clone(&self.semaphore).acquire_owned()).await;
+        self.queued.fetch_sub(1, Ordering::SeqCst);
+        permit.ok().and_then(Result::ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::config::PlaylistLimitConfig;
+    use crate::utils::request_limiter::RequestLimiter;
+
+    #[actix_rt::test]
+    async fn request_limiter_test() {
+        let limiter = RequestLimiter::new(&PlaylistLimitConfig { max_concurrent: 1, queue: 1, queue_timeout_secs: 0, retry_after_secs: 1 });
+        let first = limiter.acquire().await;
+        assert!(first.is_some());
+        // the queued request times out
+        assert!(limiter.acquire().await.is_none());
+        drop(first);
+        assert!(limiter.acquire().await.is_some());
+
+        let limiter = RequestLimiter::new(&PlaylistLimitConfig { max_concurrent: 1, queue: 0, queue_timeout_secs: 10, retry_after_secs: 1 });
+        let _first = limiter.acquire().await;
+        // no queue, rejected at once
+        assert!(limiter.acquire().await.is_none());
+    }
+}
@@ -1,29 +1,38 @@
 use crate::Arc;
 use std::collections::{HashMap, HashSet};
 use std::fs;
-use std::fs::File;
+use std::fs::OpenOptions;
 use std::io::{BufWriter, Error, ErrorKind, Read, Write};
 use std::path::{Path, PathBuf};
-use std::sync::LazyLock;
-use std::time::Instant;
+use std::net::IpAddr;
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
 
 use flate2::read::{GzDecoder, ZlibDecoder};
 use futures::StreamExt;
-use log::{debug, error, log_enabled, trace, Level};
-use regex::Regex;
-use reqwest::header::CONTENT_ENCODING;
+use log::{debug, error, info, log_enabled, trace, warn, Level};
+use reqwest::header::{CONTENT_ENCODING, CONTENT_RANGE, ETAG, IF_RANGE, LAST_MODIFIED, RANGE, RETRY_AFTER};
+use reqwest::StatusCode;
+use tokio::sync::OwnedMutexGuard;
 use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
 use url::Url;
 
 use crate::m3u_filter_error::{str_to_io_error, M3uFilterError, M3uFilterErrorKind};
-use crate::model::config::ConfigInput;
+use crate::model::config::{ConfigInput, ProviderQuirk};
 use crate::model::stats::format_elapsed_time;
 use crate::repository::storage::get_input_storage_path;
 use crate::repository::xtream_repository::FILE_EPG;
 use crate::utils::compression_utils::{is_deflate, is_gzip, ENCODING_DEFLATE, ENCODING_GZIP};
-use crate::utils::file_utils::{get_file_path, persist_file};
+use crate::utils::file_utils::{get_file_path, persist_file, rename_or_copy};
+use crate::utils::progress::update_download_progress;
+use crate::utils::rate_limit::{is_rate_limit_response, parse_retry_after};
+pub use crate::utils::sanitize::mask_sensitive_info;
 use crate::{create_m3u_filter_error_result, debug_if_enabled};
 
+const EMPTY_BODY_RETRIES: u8 = 2;
+const EMPTY_BODY_RETRY_DELAY: Duration = Duration::from_secs(2);
+const DOWNLOAD_RESUME_DELAY: Duration = Duration::from_secs(2);
+
 pub const fn bytes_to_megabytes(bytes: u64) -> u64 {
     bytes / 1_048_576
 }
@@ -120,12 +129,16 @@ pub async fn get_input_text_content(client: Arc<reqwest::Client>, input: &Config
     }
 }
 
+/// The request uses the headers and the tls settings of the input.
 pub fn get_client_request(client: &Arc<reqwest::Client>,
-                          headers: Option<&HashMap<String, String>>,
+                          input: Option<&ConfigInput>,
                           url: &Url,
                           custom_headers: Option<&HashMap<String, Vec<u8>>>) -> reqwest::RequestBuilder {
-    let request = client.get(url.clone());
-    let headers = get_request_headers(headers, custom_headers);
+    let request = match input {
+        Some(input) => input.get_http_client(client).get(input.get_request_url(url)),
+        None => client.get(url.clone()),
+    };
+    let headers = get_request_headers(input.map(|i| &i.headers), custom_headers);
     request.headers(headers)
 }
 
@@ -180,99 +193,335 @@ fn get_local_file_content(file_path: &PathBuf) -> Result<String, Error> {
 }
 
 
-async fn get_remote_content_as_file(client: Arc<reqwest::Client>, input: &ConfigInput, url: &Url, file_path: &Path) -> Result<PathBuf, std::io::Error> {
-    let start_time = Instant::now();
-    let request = get_client_request(&client, Some(&input.headers), url, None);
-    match request.send().await {
-        Ok(response) => {
-            if response.status().is_success() {
-                // Open a file in write mode
-                let mut file = BufWriter::with_capacity(8192, File::create(file_path)?);
-                // Stream the response body in chunks
-                let mut stream = response.bytes_stream();
-                while let Some(chunk) = stream.next().await {
-                    match chunk {
-                        Ok(bytes) => {
-                            file.write_all(&bytes)?;
-                        }
-                        Err(err) => {
-                            return Err(str_to_io_error(&format!("Failed to read chunk: {err}")));
-                        }
+pub fn get_progress_name(input: &ConfigInput) -> String {
+    input.name.as_ref().map_or_else(|| mask_sensitive_info(input.url.as_str()), ToString::to_string)
+}
+
+fn get_content_length(input: &ConfigInput, response: &reqwest::Response) -> Option<u64> {
+    if input.has_quirk(ProviderQuirk::IgnoreContentLength) {
+        None
+    } else {
+        response.content_length()
+    }
+}
+
+/// The partial files of the running downloads, a download of the same url waits until the partial file is released.
+static PARTIAL_DOWNLOAD_LOCKS: LazyLock<Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+enum PartialContent {
+    Memory(Vec<u8>),
+    File {
+        name: String,
+        path: PathBuf,
+        validator_path: PathBuf,
+        lock: Option<OwnedMutexGuard<()>>,
+    },
+}
+
+/// Download with resume, an interrupted download is resumed with a `Range` request.
+/// Text content is kept in memory and only resumed within the update.
+/// File content is downloaded into a partial file in the temp directory, the validator (`ETag` or `Last-Modified`)
+/// of the provider response is stored next to the partial file and a partial file of a previous update
+/// is only resumed if the content is unchanged (`If-Range`).
+struct PartialDownload {
+    content: PartialContent,
+    validator: Option<String>,
+    encoding: Option<String>,
+}
+
+impl PartialDownload {
+    fn in_memory() -> Self {
+        Self {
+            content: PartialContent::Memory(Vec::new()),
+            validator: None,
+            encoding: None,
+        }
+    }
+
+    /// The partial file is shared by all downloads of the url, the lock is held until the download is dropped.
+    async fn on_disk(url: &Url) -> Self {
+        let name = blake3::hash(url.as_str().as_bytes()).to_hex().to_string();
+        let file_lock = Arc::clone(PARTIAL_DOWNLOAD_LOCKS.lock().unwrap().entry(name.clone()).or_default());
+        let lock = file_lock.lock_owned().await;
+        let temp_dir = tempfile::env::temp_dir();
+        Self {
+            content: PartialContent::File {
+                path: temp_dir.join(format!("download_{name}.part")),
+                validator_path: temp_dir.join(format!("download_{name}.validator")),
+                name,
+                lock: Some(lock),
+            },
+            validator: None,
+            encoding: None,
+        }
+    }
+
+    fn len(&self) -> u64 {
+        match &self.content {
+            PartialContent::Memory(buffer) => buffer.len() as u64,
+            PartialContent::File { path, .. } => fs::metadata(path).map_or(0, |metadata| metadata.len()),
+        }
+    }
+
+    fn remove(&mut self) {
+        match &mut self.content {
+            PartialContent::Memory(buffer) => buffer.clear(),
+            PartialContent::File { path, validator_path, .. } => {
+                let _ = fs::remove_file(path);
+                let _ = fs::remove_file(validator_path);
+            }
+        }
+    }
+
+    fn set_validator(&mut self, validator: Option<String>) {
+        if let PartialContent::File { validator_path, .. } = &self.content {
+            match &validator {
+                Some(value) => {
+                    if let Err(err) = fs::write(validator_path, value) {
+                        debug!("Could not write download validator {}: {err}", validator_path.display());
                     }
                 }
+                None => { let _ = fs::remove_file(validator_path); }
+            }
+        }
+        self.validator = validator;
+    }
+
+    fn persist(&mut self, file_path: &Path) -> Result<(), Error> {
+        match &mut self.content {
+            PartialContent::Memory(buffer) => fs::write(file_path, std::mem::take(buffer)),
+            PartialContent::File { path, .. } => rename_or_copy(path, file_path, true),
+        }
+    }
+
+    fn take_content(&mut self) -> Result<Vec<u8>, Error> {
+        match &mut self.content {
+            PartialContent::Memory(buffer) => Ok(std::mem::take(buffer)),
+            PartialContent::File { path, .. } => fs::read(path),
+        }
+    }
 
+    fn writer(&mut self, append: bool) -> Result<Box<dyn Write + '_>, Error> {
+        match &mut self.content {
+            PartialContent::Memory(buffer) => {
+                if !append {
+                    buffer.clear();
+                }
+                Ok(Box::new(buffer))
+            }
+            PartialContent::File { path, .. } => {
+                let file = OpenOptions::new().create(true).write(true).append(append).truncate(!append).open(path)?;
+                Ok(Box::new(BufWriter::with_capacity(8192, file)))
+            }
+        }
+    }
+}
+
+impl Drop for PartialDownload {
+    fn drop(&mut self) {
+        if let PartialContent::File { name, lock, .. } = &mut self.content {
+            drop(lock.take());
+            let mut locks = PARTIAL_DOWNLOAD_LOCKS.lock().unwrap();
+            // no other download waits for the partial file
+            if locks.get(name).is_some_and(|file_lock| Arc::strong_count(file_lock) == 1) {
+                locks.remove(name);
+            }
+        }
+    }
+}
+
+/// Returns the start offset of a `Content-Range: bytes start-end/total` header.
+fn parse_content_range_start(value: &str) -> Option<u64> {
+    value.trim().strip_prefix("bytes")?.trim_start().split('-').next()?.trim().parse().ok()
+}
+
+fn get_response_validator(response: &reqwest::Response) -> Option<String> {
+    let header_value = |name| response.headers().get(name).and_then(|value: &HeaderValue| value.to_str().ok()).map(ToString::to_string);
+    // weak etags are not allowed for If-Range
+    header_value(ETAG).filter(|etag| !etag.starts_with("W/")).or_else(|| header_value(LAST_MODIFIED))
+}
+
+/// Records a rate limit response of the provider, the following requests of the input are delayed.
+/// Returns `true` for rate limit responses.
+pub fn record_provider_rate_limit(input: &ConfigInput, status: StatusCode, retry_after: Option<&str>) -> bool {
+    let retry_after = retry_after.and_then(parse_retry_after);
+    if !is_rate_limit_response(status, retry_after) {
+        return false;
+    }
+    let (delay, started) = input.t_rate_limit.record_rate_limited(retry_after);
+    if started {
+        warn!("Input {} is rate limited by the provider (status {status}), requests are delayed by {} secs", get_progress_name(input), delay.as_secs());
+    } else {
+        debug_if_enabled!("Input {} is still rate limited, requests are delayed by {} secs", get_progress_name(input), delay.as_secs());
+    }
+    true
+}
+
+pub fn record_provider_success(input: &ConfigInput) {
+    if input.t_rate_limit.record_success() {
+        info!("Input {} is no longer rate limited", get_progress_name(input));
+    }
+}
+
+fn interrupted_error(msg: &str) -> Error {
+    Error::new(ErrorKind::Interrupted, msg)
+}
+
+async fn download_part(client: &Arc<reqwest::Client>, input: &ConfigInput, url: &Url, part: &mut PartialDownload, resume: bool) -> Result<(), Error> {
+    let offset = if resume { part.len() } else { 0 };
+    let mut request = get_client_request(client, Some(input), url, None);
+    if offset > 0 {
+        request = request.header(RANGE, format!("bytes={offset}-"));
+        if let Some(validator) = &part.validator {
+            request = request.header(IF_RANGE, validator.as_str());
+        }
+    }
+    input.t_rate_limit.wait().await;
+    let response = request.send().await
+        .map_err(|err| interrupted_error(&format!("Request failed: {} {err}", mask_sensitive_info(url.as_str()))))?;
+    let status = response.status();
+    let retry_after = response.headers().get(RETRY_AFTER).and_then(|value| value.to_str().ok());
+    if record_provider_rate_limit(input, status, retry_after) {
+        return Err(str_to_io_error(&format!("Provider rate limit, request failed with status {status} {}", mask_sensitive_info(url.as_str()))));
+    }
+    if offset > 0 && status == StatusCode::RANGE_NOT_SATISFIABLE {
+        part.remove();
+        return Err(interrupted_error("Partial download is not satisfiable, restarting"));
+    }
+    if !status.is_success() {
+        return Err(str_to_io_error(&format!("Request failed with status {status} {}", mask_sensitive_info(url.as_str()))));
+    }
+    record_provider_success(input);
+    let content_range_start = response.headers().get(CONTENT_RANGE).and_then(|value| value.to_str().ok()).and_then(parse_content_range_start);
+    let resumed = offset > 0 && status == StatusCode::PARTIAL_CONTENT && content_range_start == Some(offset);
+    if status == StatusCode::PARTIAL_CONTENT && !resumed {
+        part.remove();
+        return Err(interrupted_error("Unexpected partial content, restarting"));
+    }
+    if resumed {
+        debug_if_enabled!("Resuming download at {offset} bytes {}", mask_sensitive_info(url.as_str()));
+    } else {
+        part.set_validator(get_response_validator(&response));
+        part.encoding = response.headers().get(CONTENT_ENCODING).and_then(|value| value.to_str().ok()).map(ToString::to_string);
+    }
+
+    let mut file = part.writer(resumed)?;
+    let progress_name = get_progress_name(input);
+    let total = get_content_length(input, &response).map(|length| if resumed { length + offset } else { length });
+    let mut received = if resumed { offset } else { 0 };
+    let mut stream = response.bytes_stream();
+    while let Some(chunk) = stream.next().await {
+        match chunk {
+            Ok(bytes) => {
+                if let Some(limiter) = input.t_download_limiter.as_ref() {
+                    limiter.consume(bytes.len()).await;
+                }
+                file.write_all(&bytes)?;
+                received += bytes.len() as u64;
+                update_download_progress(&progress_name, received, total);
+            }
+            Err(err) => {
                 file.flush()?;
-                let elapsed = start_time.elapsed().as_secs();
-                debug!("File downloaded successfully to {file_path:?}, took:{}", format_elapsed_time(elapsed));
-                Ok(file_path.to_path_buf())
-            } else {
-                Err(str_to_io_error(&format!("Request failed with status {} {}", response.status(), mask_sensitive_info(url.as_str()))))
+                return Err(interrupted_error(&format!("Failed to read chunk: {err}")));
             }
         }
-        Err(err) => Err(str_to_io_error(&format!("Request failed: {} {err}", mask_sensitive_info(url.as_str())))),
     }
+    file.flush()?;
+    if total.is_some_and(|total| received < total) {
+        return Err(interrupted_error(&format!("Download incomplete, received {received} bytes")));
+    }
+    Ok(())
 }
 
-async fn get_remote_content(client: Arc<reqwest::Client>, input: &ConfigInput, url: &Url) -> Result<String, Error> {
+async fn download_with_resume(client: &Arc<reqwest::Client>, input: &ConfigInput, url: &Url, mut part: PartialDownload) -> Result<PartialDownload, Error> {
     let start_time = Instant::now();
-    let request = get_client_request(&client, Some(&input.headers), url, None);
-    match request.send().await {
-        Ok(response) => {
-            let is_success = response.status().is_success();
-            if is_success {
-                let header_value = response.headers().get(CONTENT_ENCODING);
-                let mut encoding = header_value.and_then(|encoding_header| encoding_header.to_str().map_or(None, |value| Some(value.to_string())));
-                match response.bytes().await {
-                    Ok(bytes) => {
-                        if bytes.len() >= 2 {
-                            if is_gzip(&bytes[0..2]) {
-                                encoding = Some(ENCODING_GZIP.to_string());
-                            } else if is_deflate(&bytes[0..2]) {
-                                encoding = Some(ENCODING_DEFLATE.to_string());
-                            }
-                        }
+    let attempts = input.get_download_resume_attempts();
+    if let PartialContent::File { validator_path, .. } = &part.content {
+        part.validator = if attempts > 0 { fs::read_to_string(validator_path).ok() } else { None };
+        if part.validator.is_none() {
+            // without validator the partial file of a previous update cant be resumed
+            part.remove();
+        }
+    }
+    let mut attempt = 0;
+    loop {
+        match download_part(client, input, url, &mut part, attempts > 0).await {
+            Ok(()) => {
+                debug_if_enabled!("Download took:{} {}", format_elapsed_time(start_time.elapsed().as_secs()), mask_sensitive_info(url.as_str()));
+                return Ok(part);
+            }
+            Err(err) if err.kind() == ErrorKind::Interrupted && attempt < attempts => {
+                attempt += 1;
+                debug_if_enabled!("Download interrupted, resume {attempt}/{attempts} {}: {err}", mask_sensitive_info(url.as_str()));
+                tokio::time::sleep(DOWNLOAD_RESUME_DELAY).await;
+            }
+            Err(err) => {
+                // the partial file is kept to resume the download with the next update
+                if attempts == 0 || part.validator.is_none() {
+                    part.remove();
+                }
+                return Err(err);
+            }
+        }
+    }
+}
 
-                        let mut decode_buffer = String::new();
-                        if let Some(encoding_type) = encoding {
-                            match encoding_type.as_str() {
-                                ENCODING_GZIP => {
-                                    let mut decoder = GzDecoder::new(&bytes[..]);
-                                    match decoder.read_to_string(&mut decode_buffer) {
-                                        Ok(_) => {}
-                                        Err(err) => return Err(str_to_io_error(&format!("failed to decode gzip content {err}")))
-                                    };
-                                }
-                                ENCODING_DEFLATE => {
-                                    let mut decoder = ZlibDecoder::new(&bytes[..]);
-                                    match decoder.read_to_string(&mut decode_buffer) {
-                                        Ok(_) => {}
-                                        Err(err) => return Err(str_to_io_error(&format!("failed to decode zlib content {err}")))
-                                    }
-                                }
-                                _ => {}
-                            };
-                        }
+async fn get_remote_content_as_file(client: Arc<reqwest::Client>, input: &ConfigInput, url: &Url, file_path: &Path) -> Result<PathBuf, std::io::Error> {
+    let mut part = download_with_resume(&client, input, url, PartialDownload::on_disk(url).await).await?;
+    let result = part.persist(file_path);
+    part.remove();
+    result?;
+    debug!("File downloaded successfully to {file_path:?}");
+    Ok(file_path.to_path_buf())
+}
 
-                        if decode_buffer.is_empty() {
-                            match String::from_utf8(bytes.to_vec()) {
-                                Ok(decoded_content) => {
-                                    debug_if_enabled!("Request took:{} {}", format_elapsed_time(start_time.elapsed().as_secs()), mask_sensitive_info(url.as_str()));
-                                    Ok(decoded_content)
-                                }
-                                Err(err) => Err(str_to_io_error(&format!("failed to plain text content {err}")))
-                            }
-                        } else {
-                            debug_if_enabled!("Request took:{},  {}", format_elapsed_time(start_time.elapsed().as_secs()), mask_sensitive_info(url.as_str()));
-                            Ok(decode_buffer)
-                        }
-                    }
-                    Err(err) => Err(str_to_io_error(&format!("failed to read response {} {err}", mask_sensitive_info(url.as_str()))))
+async fn get_remote_content(client: Arc<reqwest::Client>, input: &ConfigInput, url: &Url) -> Result<String, Error> {
+    let start_time = Instant::now();
+    let mut part = download_with_resume(&client, input, url, PartialDownload::in_memory()).await?;
+    let mut encoding = part.encoding.take();
+    let content = part.take_content();
+    part.remove();
+    let bytes = content.map_err(|err| str_to_io_error(&format!("failed to read response {} {err}", mask_sensitive_info(url.as_str()))))?;
+    if bytes.len() >= 2 {
+        if is_gzip(&bytes[0..2]) {
+            encoding = Some(ENCODING_GZIP.to_string());
+        } else if is_deflate(&bytes[0..2]) {
+            encoding = Some(ENCODING_DEFLATE.to_string());
+        }
+    }
+
+    let mut decode_buffer = String::new();
+    if let Some(encoding_type) = encoding {
+        match encoding_type.as_str() {
+            ENCODING_GZIP => {
+                let mut decoder = GzDecoder::new(&bytes[..]);
+                match decoder.read_to_string(&mut decode_buffer) {
+                    Ok(_) => {}
+                    Err(err) => return Err(str_to_io_error(&format!("failed to decode gzip content {err}")))
+                };
+            }
+            ENCODING_DEFLATE => {
+                let mut decoder = ZlibDecoder::new(&bytes[..]);
+                match decoder.read_to_string(&mut decode_buffer) {
+                    Ok(_) => {}
+                    Err(err) => return Err(str_to_io_error(&format!("failed to decode zlib content {err}")))
                 }
-            } else {
-                Err(str_to_io_error(&format!("Request failed with status {} {}", response.status(), mask_sensitive_info(url.as_str()))))
             }
+            _ => {}
+        };
+    }
+
+    if decode_buffer.is_empty() {
+        match String::from_utf8(bytes) {
+            Ok(decoded_content) => {
+                debug_if_enabled!("Request took:{} {}", format_elapsed_time(start_time.elapsed().as_secs()), mask_sensitive_info(url.as_str()));
+                Ok(decoded_content)
+            }
+            Err(err) => Err(str_to_io_error(&format!("failed to plain text content {err}")))
         }
-        Err(err) => Err(str_to_io_error(&format!("Request failed {} {err}", mask_sensitive_info(url.as_str()))))
+    } else {
+        debug_if_enabled!("Request took:{},  {}", format_elapsed_time(start_time.elapsed().as_secs()), mask_sensitive_info(url.as_str()));
+        Ok(decode_buffer)
     }
 }
 
@@ -292,7 +541,7 @@ pub async fn download_text_content_as_file(client: Arc<reqwest::Client>, input:
                 Err(err) => Err(err)
             }, Ok);
             match file_path {
-                Ok(persist_path) => get_remote_content_as_file(client, input, &url, &persist_path).await,
+                Ok(persist_path) => get_remote_content_as_file_with_quirks(client, input, &url, &persist_path).await,
                 Err(err) => Err(err)
             }
         }
@@ -302,12 +551,43 @@ pub async fn download_text_content_as_file(client: Arc<reqwest::Client>, input:
 }
 
 
+/// Retries empty downloads for inputs with the `retry_empty_body` quirk.
+async fn get_remote_content_as_file_with_quirks(client: Arc<reqwest::Client>, input: &ConfigInput, url: &Url, file_path: &Path) -> Result<PathBuf, Error> {
+    let retries = if input.has_quirk(ProviderQuirk::RetryEmptyBody) { EMPTY_BODY_RETRIES } else { 0 };
+    let mut attempt = 0;
+    loop {
+        let result = get_remote_content_as_file(Arc::clone(&client), input, url, file_path).await;
+        let is_empty = matches!(&result, Ok(path) if fs::metadata(path).is_ok_and(|metadata| metadata.len() == 0));
+        if attempt >= retries || !is_empty {
+            return result;
+        }
+        attempt += 1;
+        debug_if_enabled!("Empty response, retry {attempt}/{retries} {}", mask_sensitive_info(url.as_str()));
+        tokio::time::sleep(EMPTY_BODY_RETRY_DELAY).await;
+    }
+}
+
+/// Retries empty responses for inputs with the `retry_empty_body` quirk.
+async fn get_remote_content_with_quirks(client: Arc<reqwest::Client>, input: &ConfigInput, url: &Url) -> Result<String, Error> {
+    let retries = if input.has_quirk(ProviderQuirk::RetryEmptyBody) { EMPTY_BODY_RETRIES } else { 0 };
+    let mut attempt = 0;
+    loop {
+        let result = get_remote_content(Arc::clone(&client), input, url).await;
+        if attempt >= retries || !matches!(&result, Ok(content) if content.trim().is_empty()) {
+            return result;
+        }
+        attempt += 1;
+        debug_if_enabled!("Empty response, retry {attempt}/{retries} {}", mask_sensitive_info(url.as_str()));
+        tokio::time::sleep(EMPTY_BODY_RETRY_DELAY).await;
+    }
+}
+
 pub async fn download_text_content(client: Arc<reqwest::Client>, input: &ConfigInput, url_str: &str, persist_filepath: Option<PathBuf>) -> Result<String, Error> {
     if let Ok(url) = url_str.parse::<url::Url>() {
         let result = if url.scheme() == "file" {
             url.to_file_path().map_or_else(|()| Err(str_to_io_error(&format!("Unknown file {}", mask_sensitive_info(url_str)))), |file_path| get_local_file_content(&file_path))
         } else {
-            get_remote_content(client, input, &url).await
+            get_remote_content_with_quirks(client, input, &url).await
         };
         match result {
             Ok(content) => {
@@ -336,6 +616,41 @@ async fn download_json_content(client: Arc<reqwest::Client>, input: &ConfigInput
     }
 }
 
+/// A json document downloaded into a file, a temporary file is removed on drop.
+pub struct JsonContentFile {
+    pub path: PathBuf,
+    _temp_file: Option<tempfile::TempPath>,
+}
+
+async fn download_json_content_as_file(client: Arc<reqwest::Client>, input: &ConfigInput, url_str: &str, persist_filepath: Option<PathBuf>) -> Result<JsonContentFile, Error> {
+    let url = url_str.parse::<Url>().map_err(|_| str_to_io_error(&format!("Malformed URL {}", mask_sensitive_info(url_str))))?;
+    if url.scheme() == "file" {
+        return match url.to_file_path() {
+            Ok(path) if path.exists() => Ok(JsonContentFile { path, _temp_file: None }),
+            _ => Err(Error::new(ErrorKind::NotFound, format!("Unknown file {}", mask_sensitive_info(url_str)))),
+        };
+    }
+    let (file_path, temp_file) = match persist_filepath {
+        Some(path) => (path, None),
+        None => {
+            let temp_file = tempfile::NamedTempFile::new()?.into_temp_path();
+            (temp_file.to_path_buf(), Some(temp_file))
+        }
+    };
+    let path = get_remote_content_as_file_with_quirks(client, input, &url, &file_path).await?;
+    Ok(JsonContentFile { path, _temp_file: temp_file })
+}
+
+/// Downloads the json document into the `persist_filepath` or a temporary file instead of loading it into memory,
+/// for large documents which are parsed incrementally.
+pub async fn get_input_json_content_as_file(client: Arc<reqwest::Client>, input: &ConfigInput, url: &str, persist_filepath: Option<PathBuf>) -> Result<JsonContentFile, M3uFilterError> {
+    debug_if_enabled!("downloading json content from {}", mask_sensitive_info(url));
+    match download_json_content_as_file(client, input, url, persist_filepath).await {
+        Ok(content) => Ok(content),
+        Err(e) => create_m3u_filter_error_result!(M3uFilterErrorKind::Notify, "cant download input url: {}  => {}", mask_sensitive_info(url), mask_sensitive_info(e.to_string().as_str()))
+    }
+}
+
 pub async fn get_input_json_content(client: Arc<reqwest::Client>, input: &ConfigInput, url: &str, persist_filepath: Option<PathBuf>) -> Result<serde_json::Value, M3uFilterError> {
     match download_json_content(client, input, url, persist_filepath).await {
         Ok(content) => Ok(content),
@@ -355,20 +670,6 @@ pub async fn get_input_json_content(client: Arc<reqwest::Client>, input: &Config
 //     None
 // }
 
-static USERNAME_REGEX: LazyLock<regex::Regex> = LazyLock::new(|| Regex::new(r"(username=)[^&]*").unwrap());
-static PASSWORD_REGEX: LazyLock<regex::Regex> = LazyLock::new(|| Regex::new(r"(password=)[^&]*").unwrap());
-static TOKEN_REGEX: LazyLock<regex::Regex> = LazyLock::new(|| Regex::new(r"(token=)[^&]*").unwrap());
-static STREAM_URL: LazyLock<regex::Regex> = LazyLock::new(|| Regex::new(r"(.*://).*/(live|video|movie|series|m3u-stream)/\w+/\w+").unwrap());
-
-pub fn mask_sensitive_info(query: &str) -> String {
-    // Replace with "***"
-    let masked_query = USERNAME_REGEX.replace_all(query, "$1***");
-    let masked_query = PASSWORD_REGEX.replace_all(&masked_query, "$1***");
-    let masked_query = TOKEN_REGEX.replace_all(&masked_query, "$1***");
-    let masked_query =STREAM_URL.replace_all(&masked_query, "$1***/$2/***");
-    masked_query.to_string()
-}
-
 pub fn extract_extension_from_url(url: &str) -> Option<&str> {
     if let Some(protocol_pos) = url.find("://") {
         if let Some(last_slash_pos) = url[protocol_pos + 3..].rfind('/') {
@@ -386,15 +687,117 @@ pub fn extract_extension_from_url(url: &str) -> Option<&str> {
 }
 
 
+const LIVE_STREAM_EXTENSIONS: &[&str] = &[".ts", ".m3u8"];
+
+/// Replaces the `.ts` or `.m3u8` extension of a live stream url, other urls are returned unchanged.
+pub fn replace_stream_extension(url: &str, extension: &str) -> String {
+    match extract_extension_from_url(url) {
+        Some(ext) if LIVE_STREAM_EXTENSIONS.contains(&ext) && url.ends_with(ext) => format!("{}{extension}", &url[..url.len() - ext.len()]),
+        _ => url.to_string(),
+    }
+}
+
+/// The requested extension of a stream is used in the provider url and the redirect,
+/// only the live stream and the configured video extensions are accepted.
+pub fn is_valid_stream_extension(extension: &str, video_extensions: &[String]) -> bool {
+    (1..=5).contains(&extension.len())
+        && extension.chars().all(|c| c.is_ascii_alphanumeric())
+        && (LIVE_STREAM_EXTENSIONS.iter().any(|ext| ext[1..].eq_ignore_ascii_case(extension))
+        || video_extensions.iter().any(|ext| ext.trim_start_matches('.').eq_ignore_ascii_case(extension)))
+}
+
+/// Files of local inputs have `file://` urls, they can't be redirected to the client.
+pub fn is_local_url(url: &str) -> bool {
+    url.starts_with("file://")
+}
+
+/// Trusted proxies are ip addresses or networks in cidr notation like `10.0.0.0/8`.
+pub fn is_trusted_proxy(addr: &IpAddr, trusted_proxies: &[String]) -> bool {
+    trusted_proxies.iter().any(|trusted| {
+        let (network, prefix) = trusted.split_once('/').map_or((trusted.as_str(), None), |(network, prefix)| (network, Some(prefix)));
+        let Ok(network) = network.trim().parse::<IpAddr>() else { return false; };
+        match (addr, network) {
+            (IpAddr::V4(addr), IpAddr::V4(network)) => {
+                let Some(prefix) = prefix.map_or(Some(32), |p| p.trim().parse::<u32>().ok().filter(|p| *p <= 32)) else { return false; };
+                let mask = u32::MAX.checked_shl(32 - prefix).unwrap_or(0);
+                u32::from(*addr) & mask == u32::from(network) & mask
+            }
+            (IpAddr::V6(addr), IpAddr::V6(network)) => {
+                let Some(prefix) = prefix.map_or(Some(128), |p| p.trim().parse::<u32>().ok().filter(|p| *p <= 128)) else { return false; };
+                let mask = u128::MAX.checked_shl(128 - prefix).unwrap_or(0);
+                u128::from(*addr) & mask == u128::from(network) & mask
+            }
+            _ => false,
+        }
+    })
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::utils::request_utils::STREAM_URL;
+    use std::net::IpAddr;
+
+    use crate::utils::request_utils::{is_trusted_proxy, is_valid_stream_extension, mask_sensitive_info, parse_content_range_start, replace_stream_extension, PartialDownload, PARTIAL_DOWNLOAD_LOCKS};
 
     #[test]
     fn test_url_mask() {
         // Replace with "***"
         let masked_query = "https://bubblegum.tv/live/username/password/2344.ts";
-        let masked_query = STREAM_URL.replace_all(&masked_query, "$1***/$2/***");
-        println!("{masked_query}")
+        let masked_query = mask_sensitive_info(masked_query);
+        assert_eq!(masked_query, "https://***/live/***/2344.ts");
+    }
+
+    #[test]
+    fn test_valid_stream_extension() {
+        let video_extensions = vec!["mkv".to_string(), ".mp4".to_string()];
+        assert!(is_valid_stream_extension("ts", &video_extensions));
+        assert!(is_valid_stream_extension("M3U8", &video_extensions));
+        assert!(is_valid_stream_extension("mp4", &video_extensions));
+        assert!(!is_valid_stream_extension("avi", &video_extensions));
+        assert!(!is_valid_stream_extension("", &video_extensions));
+        assert!(!is_valid_stream_extension("ts?x=1", &video_extensions));
+        assert!(!is_valid_stream_extension("ts/../../admin", &video_extensions));
+    }
+
+    #[test]
+    fn test_replace_stream_extension() {
+        assert_eq!(replace_stream_extension("http://provider.tv/live/user/pass/1.ts", ".m3u8"), "http://provider.tv/live/user/pass/1.m3u8");
+        assert_eq!(replace_stream_extension("http://provider.tv/live/user/pass/1.m3u8", ".ts"), "http://provider.tv/live/user/pass/1.ts");
+        assert_eq!(replace_stream_extension("http://provider.tv/movie/user/pass/1.mkv", ".ts"), "http://provider.tv/movie/user/pass/1.mkv");
+        assert_eq!(replace_stream_extension("http://provider.tv/stream?id=1", ".ts"), "http://provider.tv/stream?id=1");
+    }
+
+    #[test]
+    fn test_is_trusted_proxy() {
+        let trusted = vec!["172.16.0.0/12".to_string(), "192.168.1.10".to_string(), "fd00::/8".to_string()];
+        assert!(is_trusted_proxy(&"172.17.0.2".parse::<IpAddr>().unwrap(), &trusted));
+        assert!(is_trusted_proxy(&"192.168.1.10".parse::<IpAddr>().unwrap(), &trusted));
+        assert!(!is_trusted_proxy(&"192.168.1.11".parse::<IpAddr>().unwrap(), &trusted));
+        assert!(is_trusted_proxy(&"fd12::1".parse::<IpAddr>().unwrap(), &trusted));
+        assert!(!is_trusted_proxy(&"2001:db8::1".parse::<IpAddr>().unwrap(), &trusted));
+        assert!(is_trusted_proxy(&"8.8.8.8".parse::<IpAddr>().unwrap(), &["0.0.0.0/0".to_string()]));
+    }
+
+    #[test]
+    fn test_parse_content_range_start() {
+        assert_eq!(parse_content_range_start("bytes 1024-2047/2048"), Some(1024));
+        assert_eq!(parse_content_range_start("bytes 0-99/*"), Some(0));
+        assert_eq!(parse_content_range_start("bytes */2048"), None);
+        assert_eq!(parse_content_range_start("items 1-2/3"), None);
     }
-}
\ No newline at end of file
+
+    #[actix_rt::test]
+    async fn partial_download_lock_test() {
+        let url = url::Url::parse("http://provider.tv/xmltv.php?username=user&password=pass").unwrap();
+        let name = blake3::hash(url.as_str().as_bytes()).to_hex().to_string();
+        let first = PartialDownload::on_disk(&url).await;
+        // the second download waits for the partial file
+        let waiting = tokio::time::timeout(std::time::Duration::from_millis(50), PartialDownload::on_disk(&url)).await;
+        assert!(waiting.is_err());
+        drop(first);
+        assert!(!PARTIAL_DOWNLOAD_LOCKS.lock().unwrap().contains_key(&name));
+        let second = tokio::time::timeout(std::time::Duration::from_millis(50), PartialDownload::on_disk(&url)).await;
+        assert!(second.is_ok());
+        drop(second);
+        assert!(!PARTIAL_DOWNLOAD_LOCKS.lock().unwrap().contains_key(&name));
+    }
+}
@@ -0,0 +1,160 @@
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::sync::{LazyLock, OnceLock, RwLock};
+
+use regex::{Captures, Regex};
+
+use crate::model::config::LogSanitizeConfig;
+use crate::repository::storage::hash_string_as_hex;
+
+const MASK: &str = "***";
+// the hash is shortened, it only needs to distinguish the values of one log
+const HASH_LEN: usize = 8;
+
+static USERNAME_REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(username=)([^&]*)").unwrap());
+static PASSWORD_REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(password=)([^&]*)").unwrap());
+static TOKEN_REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(token=)([^&]*)").unwrap());
+static STREAM_URL: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(.*://)(.*)/(live|video|movie|series|m3u-stream)/(\w+/\w+)").unwrap());
+
+static DEFAULT_CONFIG: LazyLock<LogSanitizeConfig> = LazyLock::new(LogSanitizeConfig::default);
+static SANITIZE_CONFIG: OnceLock<LogSanitizeConfig> = OnceLock::new();
+static LOG_EXCLUDED_USERS: LazyLock<RwLock<HashSet<String>>> = LazyLock::new(|| RwLock::new(HashSet::new()));
+
+/// Sets the sanitize rules, they can only be set once at startup.
+pub fn set_sanitize_config(config: LogSanitizeConfig) {
+    let _ = SANITIZE_CONFIG.set(config);
+}
+
+/// Sets the usernames and tokens of the users with `no_log`, the log lines with their requests are not written.
+pub fn set_log_excluded_users(identifiers: HashSet<String>) {
+    if let Ok(mut users) = LOG_EXCLUDED_USERS.write() {
+        *users = identifiers;
+    }
+}
+
+/// Returns true if the text contains the username as `username=` query value or as url path segment.
+pub fn contains_username(text: &str, username: &str) -> bool {
+    if username.is_empty() {
+        return false;
+    }
+    USERNAME_REGEX.captures_iter(text).any(|caps| &caps[2] == username)
+        || text.split_whitespace()
+        .filter(|token| token.contains("://") || token.starts_with('/'))
+        .any(|url| url.split(['?', '#']).next().unwrap_or_default().split('/').any(|segment| segment == username))
+}
+
+/// Returns true if the text contains the token as `token=` query value or as url path segment.
+pub fn contains_token(text: &str, token: &str) -> bool {
+    !token.is_empty() && (TOKEN_REGEX.captures_iter(text).any(|caps| &caps[2] == token) || contains_username(text, token))
+}
+
+/// Log lines with requests of excluded users are not written.
+pub fn is_log_excluded(message: &str) -> bool {
+    LOG_EXCLUDED_USERS.read().is_ok_and(|users| !users.is_empty() && users.iter().any(|value| contains_token(message, value)))
+}
+
+fn get_sanitize_config() -> &'static LogSanitizeConfig {
+    SANITIZE_CONFIG.get().unwrap_or(&DEFAULT_CONFIG)
+}
+
+fn redact(value: &str, hash: bool) -> String {
+    if hash && !value.is_empty() {
+        let mut hashed = hash_string_as_hex(value);
+        hashed.truncate(HASH_LEN);
+        format!("#{hashed}")
+    } else {
+        MASK.to_string()
+    }
+}
+
+/// Keeps the first capture group and redacts the rest of the match.
+fn redact_pattern<'a>(text: &'a str, re: &Regex, hash: bool) -> Cow<'a, str> {
+    re.replace_all(text, |caps: &Captures| {
+        let matched = caps.get(0).map_or("", |m| m.as_str());
+        match caps.get(1) {
+            Some(prefix) => {
+                let start = caps.get(0).map_or(0, |m| m.start());
+                let (kept, rest) = matched.split_at(prefix.end() - start);
+                format!("{kept}{}", redact(rest, hash))
+            }
+            None => redact(matched, hash),
+        }
+    })
+}
+
+fn sanitize(text: &str, config: &LogSanitizeConfig) -> String {
+    if !config.enabled {
+        return text.to_string();
+    }
+    let hash = config.hash;
+    let mut sanitized = text.to_string();
+    for re in [&*USERNAME_REGEX, &*PASSWORD_REGEX, &*TOKEN_REGEX] {
+        sanitized = redact_pattern(&sanitized, re, hash).to_string();
+    }
+    sanitized = STREAM_URL.replace_all(&sanitized, |caps: &Captures| {
+        format!("{}{}/{}/{}", &caps[1], redact(&caps[2], hash), &caps[3], redact(&caps[4], hash))
+    }).to_string();
+    for re in &config.t_patterns {
+        sanitized = redact_pattern(&sanitized, re, hash).to_string();
+    }
+    sanitized
+}
+
+pub fn mask_sensitive_info(query: &str) -> String {
+    sanitize(query, get_sanitize_config())
+}
+
+/// Complete log lines are only redacted for the configured log targets.
+pub fn sanitize_log_message<'a>(target: &str, message: &'a str) -> Cow<'a, str> {
+    let config = get_sanitize_config();
+    if config.enabled && config.is_target_redacted(target) {
+        Cow::Owned(sanitize(message, config))
+    } else {
+        Cow::Borrowed(message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::model::config::LogSanitizeConfig;
+    use crate::utils::sanitize::{contains_token, contains_username, sanitize};
+
+    #[test]
+    fn sanitize_test() {
+        let mut config = LogSanitizeConfig::default();
+        assert_eq!(sanitize("http://provider.tv/player_api.php?username=user&password=secret", &config),
+                   "http://provider.tv/player_api.php?username=***&password=***");
+        assert_eq!(sanitize("http://provider.tv/live/user/secret/1.ts", &config), "http://***/live/***/1.ts");
+
+        config.hash = true;
+        config.patterns = vec!["(mac=)[0-9A-F:]+".to_string(), r"\d+\.\d+\.\d+\.\d+".to_string()];
+        config.prepare().unwrap();
+        let first = sanitize("username=user&mac=00:1A:79:00:00:01 from 10.0.0.1", &config);
+        let second = sanitize("username=user&mac=00:1A:79:00:00:02 from 10.0.0.1", &config);
+        assert!(!first.contains("user&") && !first.contains("00:1A") && !first.contains("10.0.0.1"));
+        assert_eq!(first.split('&').next(), second.split('&').next());
+        assert_ne!(first, second);
+
+        config.enabled = false;
+        assert_eq!(sanitize("username=user", &config), "username=user");
+
+        config.targets = HashMap::from([("m3u_filter".to_string(), true), ("m3u_filter::processing".to_string(), false)]);
+        assert!(config.is_target_redacted("m3u_filter::api::xtream_api"));
+        assert!(!config.is_target_redacted("m3u_filter::processing::playlist_processor"));
+        assert!(!config.is_target_redacted("m3u_filterx"));
+    }
+
+    #[test]
+    fn contains_username_test() {
+        assert!(contains_username("GET /get.php?username=alice&password=secret HTTP/1.1", "alice"));
+        assert!(contains_username("GET /live/alice/secret/12.ts HTTP/1.1", "alice"));
+        assert!(contains_username("http://localhost/xmltv.php?username=alice", "alice"));
+        assert!(!contains_username("GET /get.php?username=alice2&password=secret", "alice"));
+        assert!(!contains_username("user alice connected", "alice"));
+        assert!(!contains_username("GET /live/bob/secret/12.ts?alice", "alice"));
+        assert!(contains_token("http://localhost/get.php?token=abc&type=m3u", "abc"));
+        assert!(!contains_token("http://localhost/get.php?token=abcd", "abc"));
+    }
+}
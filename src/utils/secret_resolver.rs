@@ -0,0 +1,104 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+
+use regex::Regex;
+
+use crate::m3u_filter_error::{M3uFilterError, M3uFilterErrorKind};
+use crate::utils::file_utils;
+use crate::{create_m3u_filter_error_result, info_err};
+
+static SECRET_REF_REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\$\{(?P<kind>env|file|secret):(?P<name>[^}]+)}").unwrap());
+
+/// Resolves secret references in config values:
+/// - `${env:NAME}` environment variable
+/// - `${file:/run/secrets/name}` content of a file, e.g. docker secrets
+/// - `${secret:name}` entry of the `secrets_file`
+///
+/// Unresolvable references are reported as error, the secret values are never part of the error.
+pub struct SecretResolver {
+    config_path: PathBuf,
+    secrets: HashMap<String, String>,
+}
+
+fn get_path(config_path: &Path, file_name: &str) -> PathBuf {
+    let path = PathBuf::from(file_name);
+    if path.is_relative() && !file_utils::path_exists(&path) {
+        config_path.join(path)
+    } else {
+        path
+    }
+}
+
+impl SecretResolver {
+    pub fn new(config_path: &str, secrets_file: Option<&str>) -> Result<Self, M3uFilterError> {
+        let config_path = PathBuf::from(config_path);
+        let secrets = match secrets_file {
+            None => HashMap::new(),
+            Some(file_name) => {
+                let secrets_path = get_path(&config_path, file_name);
+                match File::open(&secrets_path) {
+                    Ok(file) => match serde_yaml::from_reader::<_, HashMap<String, String>>(file) {
+                        Ok(secrets) => secrets,
+                        Err(err) => return create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "Could not parse secrets file {}: {}", file_name, err),
+                    },
+                    Err(err) => return create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "Could not read secrets file {}: {}", file_name, err),
+                }
+            }
+        };
+        Ok(Self { config_path, secrets })
+    }
+
+    pub fn resolve(&self, value: &str) -> Result<String, M3uFilterError> {
+        let mut unresolved = vec![];
+        let resolved = SECRET_REF_REGEX.replace_all(value, |caps: &regex::Captures| {
+            let name = caps["name"].trim();
+            let secret = match &caps["kind"] {
+                "env" => env::var(name).ok(),
+                "file" => std::fs::read_to_string(get_path(&self.config_path, name)).ok()
+                    .map(|content| content.trim_end_matches(['\r', '\n']).to_string()),
+                _ => self.secrets.get(name).cloned(),
+            };
+            secret.unwrap_or_else(|| {
+                unresolved.push(caps[0].to_string());
+                caps[0].to_string()
+            })
+        }).to_string();
+        if unresolved.is_empty() {
+            Ok(resolved)
+        } else {
+            Err(info_err!(format!("Could not resolve secret reference {}", unresolved.join(", "))))
+        }
+    }
+
+    pub fn is_reference(value: &str) -> bool {
+        SECRET_REF_REGEX.is_match(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::io::Write;
+    use std::path::PathBuf;
+
+    use crate::utils::secret_resolver::SecretResolver;
+
+    #[test]
+    fn resolve_test() {
+        let mut secret_file = tempfile::NamedTempFile::new().unwrap();
+        writeln!(secret_file, "file_secret").unwrap();
+        let resolver = SecretResolver {
+            config_path: PathBuf::from("."),
+            secrets: HashMap::from([("provider_password".to_string(), "vault_secret".to_string())]),
+        };
+        assert_eq!(resolver.resolve("plain").unwrap(), "plain");
+        assert_eq!(resolver.resolve("${env:HOME}").unwrap(), std::env::var("HOME").unwrap());
+        assert_eq!(resolver.resolve("${secret:provider_password}").unwrap(), "vault_secret");
+        assert_eq!(resolver.resolve(&format!("${{file:{}}}", secret_file.path().to_string_lossy())).unwrap(), "file_secret");
+        let err = resolver.resolve("${secret:unknown}").unwrap_err();
+        assert!(err.message.contains("${secret:unknown}"));
+    }
+}
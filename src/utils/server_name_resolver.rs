@@ -0,0 +1,50 @@
+use std::net::SocketAddr;
+
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+
+/// Resolves the tls `server_name` of an input to the addresses of the input host,
+/// the other names are resolved as usual. The lookup runs when the connection is established.
+pub struct ServerNameResolver {
+    server_name: String,
+    input_host: String,
+}
+
+impl ServerNameResolver {
+    pub fn new(server_name: &str, input_host: &str) -> Self {
+        Self { server_name: server_name.to_lowercase(), input_host: input_host.to_string() }
+    }
+
+    fn get_lookup_host(&self, name: &str) -> String {
+        if name.eq_ignore_ascii_case(&self.server_name) { self.input_host.clone() } else { name.to_string() }
+    }
+}
+
+impl Resolve for ServerNameResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let host = self.get_lookup_host(name.as_str());
+        Box::pin(async move {
+            // the port of the request url is used, 0 is only a placeholder
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host.as_str(), 0)).await?.collect();
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::str::FromStr;
+
+    use reqwest::dns::{Name, Resolve};
+
+    use crate::utils::server_name_resolver::ServerNameResolver;
+
+    #[actix_rt::test]
+    async fn server_name_resolver_test() {
+        let resolver = ServerNameResolver::new("Provider.TV", "127.0.0.2");
+        let addrs: Vec<IpAddr> = resolver.resolve(Name::from_str("provider.tv").unwrap()).await.unwrap().map(|addr| addr.ip()).collect();
+        assert_eq!(addrs, vec![IpAddr::V4(Ipv4Addr::new(127, 0, 0, 2))]);
+        let addrs: Vec<IpAddr> = resolver.resolve(Name::from_str("127.0.0.3").unwrap()).await.unwrap().map(|addr| addr.ip()).collect();
+        assert_eq!(addrs, vec![IpAddr::V4(Ipv4Addr::new(127, 0, 0, 3))]);
+    }
+}
@@ -0,0 +1,50 @@
+use std::time::Instant;
+
+use serde::Serialize;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StepTiming {
+    pub step: String,
+    pub millis: u128,
+}
+
+/// Measures consecutive processing steps, each `tick` closes the step started with the previous tick.
+pub struct StepMeasure {
+    start: Instant,
+    step_start: Instant,
+    steps: Vec<StepTiming>,
+}
+
+impl StepMeasure {
+    pub fn new() -> Self {
+        let now = Instant::now();
+        Self {
+            start: now,
+            step_start: now,
+            steps: vec![],
+        }
+    }
+
+    /// Restarts the current step without recording it.
+    pub fn restart(&mut self) {
+        self.step_start = Instant::now();
+    }
+
+    pub fn tick(&mut self, step: &str) {
+        let now = Instant::now();
+        self.steps.push(StepTiming { step: step.to_string(), millis: now.duration_since(self.step_start).as_millis() });
+        self.step_start = now;
+    }
+
+    pub fn elapsed_millis(&self) -> u128 {
+        self.start.elapsed().as_millis()
+    }
+
+    pub fn steps(&self) -> &[StepTiming] {
+        &self.steps
+    }
+
+    pub fn into_steps(self) -> Vec<StepTiming> {
+        self.steps
+    }
+}